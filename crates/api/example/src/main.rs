@@ -1,6 +1,6 @@
 use ggcat_api::{
-    ColoredQueryOutputFormat, ExtraElaboration, GGCATConfig, GGCATInstance,
-    GeneralSequenceBlockData,
+    BuildOptions, ColoredQueryOutputFormat, ExtraElaboration, GGCATConfig, GGCATInstance,
+    GeneralSequenceBlockData, LogFormat, QueryOptions, ReadsSizeEncoding,
 };
 use itertools::Itertools;
 use std::{path::PathBuf, sync::Mutex};
@@ -13,6 +13,12 @@ fn main() {
         total_threads_count: 16,
         intermediate_compression_level: None,
         stats_file: None,
+        io_threads_count: None,
+        compute_threads_count: None,
+        log_format: LogFormat::Human,
+        verbose_phases_log: false,
+        reads_size_encoding: ReadsSizeEncoding::Varint,
+        max_read_length: None,
     });
 
     let graph_file = PathBuf::from("/tmp/sal-dbg.fa");
@@ -45,6 +51,7 @@ fn main() {
         true,
         1,
         ExtraElaboration::UnitigLinks,
+        BuildOptions::default(),
     );
 
     let input_query = PathBuf::from("../../../example-inputs/query.fa");
@@ -59,6 +66,7 @@ fn main() {
         None,
         true,
         ColoredQueryOutputFormat::JsonLinesWithNames,
+        QueryOptions::default(),
     );
 
     println!("Output query file: {:?}", output_query.display());
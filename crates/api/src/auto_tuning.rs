@@ -0,0 +1,281 @@
+use ::utils::hyperloglog::HyperLogLog;
+use ::utils::random_seed::{derive_seed, RandomComponent};
+use config::{MAXIMUM_SECOND_BUCKETS_LOG, MAX_BUCKETS_COUNT_LOG, MIN_BUCKETS_COUNT_LOG};
+use hashes::rolling::minqueue::RollingMinQueue;
+use hashes::{
+    ExtendableHashTraitType, HashFunction, HashFunctionFactory, MinimizerHashFunctionFactory,
+};
+use io::sequences_stream::general::{GeneralSequenceBlockData, GeneralSequencesStream};
+use io::sequences_stream::GenericSequencesStream;
+use std::fs::File;
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+// Bases read from the start of the input to estimate its parameters
+const AUTO_TUNING_SAMPLE_BASES: u64 = 64 * 1024 * 1024;
+// Buckets used to measure the skew of the minimizers distribution in the sample
+const SKEW_SAMPLE_BUCKETS_LOG: usize = 10;
+// Estimated memory used by each distinct kmer of a bucket in the kmers merge phase
+const BYTES_PER_DISTINCT_KMER: f64 = 32.0;
+// Each bucket should be split in its second buckets by distinct minimizers, with this margin
+const MINIMIZERS_PER_SECOND_BUCKET_LOG: usize = 4;
+// The minimizers are too few to fill the buckets if they are less than the buckets over this ratio
+const MINIMIZERS_DIVERSITY_WARNING_RATIO: u64 = 4;
+
+/// The statistics of the sampled start of the input
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InputSample {
+    pub sampled_bases: u64,
+    pub distinct_kmers: u64,
+    pub distinct_minimizers: u64,
+    /// Largest bucket over the average one, when the kmers are split by minimizer
+    pub buckets_skew: f64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TunedParameters {
+    pub buckets_count_log: usize,
+    pub minimizer_length: usize,
+    pub estimated_distinct_kmers: u64,
+    pub buckets_skew: f64,
+}
+
+/// Reads the start of the input, up to a fixed amount of bases, estimating its distinct kmers
+/// and the skew of the minimizer buckets
+pub fn sample_input<H: MinimizerHashFunctionFactory>(
+    input_streams: &[GeneralSequenceBlockData],
+    k: usize,
+    m: usize,
+    random_seed: Option<u64>,
+) -> InputSample {
+    H::initialize(k);
+
+    let mut hll = HyperLogLog::new(
+        HyperLogLog::DEFAULT_PRECISION,
+        derive_seed(random_seed, RandomComponent::CardinalitySketch, 0),
+    );
+    let mut minimizers_hll = HyperLogLog::new(
+        HyperLogLog::DEFAULT_PRECISION,
+        derive_seed(random_seed, RandomComponent::CardinalitySketch, 1),
+    );
+    let mut minimizer_queue = RollingMinQueue::<H>::new(k - m);
+    let mut buckets_counts = vec![0u64; 1 << SKEW_SAMPLE_BUCKETS_LOG];
+    let mut sampled_bases = 0;
+
+    // The invalid records are reported by the build, that reads the whole input
+    let mut stream = GeneralSequencesStream::new(false);
+    for block in input_streams {
+        if sampled_bases >= AUTO_TUNING_SAMPLE_BASES {
+            break;
+        }
+
+        stream.read_block_until(block, false, Some(k - 1), |seq, _info| {
+            sampled_bases += seq.seq.len() as u64;
+
+            for part in seq.seq.split(|b| *b == b'N') {
+                if part.len() < k {
+                    continue;
+                }
+                for hash in H::new(part, k).iter() {
+                    hll.add_hash(H::get_u64(hash.to_unextendable()));
+                }
+
+                let minimizers = H::new(part, m).iter().map(|x| x.to_unextendable());
+                for minimizer in minimizer_queue.make_iter(minimizers) {
+                    minimizers_hll.add_hash(H::get_u64(minimizer));
+                    buckets_counts
+                        [H::get_bucket(0, SKEW_SAMPLE_BUCKETS_LOG, minimizer) as usize] += 1;
+                }
+            }
+
+            // Stop reading the input as soon as the sample is complete
+            if sampled_bases >= AUTO_TUNING_SAMPLE_BASES {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+    }
+
+    let total_count: u64 = buckets_counts.iter().sum();
+    let buckets_skew = if total_count > 0 {
+        *buckets_counts.iter().max().unwrap() as f64 * buckets_counts.len() as f64
+            / total_count as f64
+    } else {
+        1.0
+    };
+
+    InputSample {
+        sampled_bases,
+        distinct_kmers: hll.estimate(),
+        distinct_minimizers: minimizers_hll.estimate(),
+        buckets_skew,
+    }
+}
+
+/// Returns a warning if the distinct minimizers of the sample are far fewer than the buckets,
+/// so that most of the buckets are left empty and the kmers collapse in the few remaining ones.
+/// A longer minimizers length gives more distinct minimizers
+pub fn check_minimizers_diversity(
+    sample: &InputSample,
+    minimizer_length: usize,
+    buckets_count: usize,
+) -> Option<String> {
+    if sample.distinct_minimizers * MINIMIZERS_DIVERSITY_WARNING_RATIO >= buckets_count as u64 {
+        return None;
+    }
+
+    Some(format!(
+        "WARNING: only {} distinct minimizers of length {} were found in the first {} bases of the input, too few for {} buckets. Consider a longer minimizers length",
+        sample.distinct_minimizers, minimizer_length, sample.sampled_bases, buckets_count
+    ))
+}
+
+/// Chooses the buckets count so that the largest bucket of each thread fits in the available memory
+/// in the kmers merge phase, extrapolating the distinct kmers of the sample to the whole input.
+/// The minimizers length is increased if needed so that each second bucket gets multiple minimizers
+pub fn choose_parameters(
+    sample: &InputSample,
+    total_bases: u64,
+    k: usize,
+    memory_bytes: u64,
+    threads_count: usize,
+) -> TunedParameters {
+    let scale = total_bases.max(sample.sampled_bases) as f64 / sample.sampled_bases.max(1) as f64;
+    let estimated_distinct_kmers = (sample.distinct_kmers as f64 * scale) as u64;
+
+    let largest_buckets_memory = estimated_distinct_kmers as f64
+        * BYTES_PER_DISTINCT_KMER
+        * sample.buckets_skew.max(1.0)
+        * threads_count.max(1) as f64;
+    let buckets_count = (largest_buckets_memory / memory_bytes.max(1) as f64).ceil() as u64;
+    let buckets_count_log = (buckets_count.max(1).next_power_of_two().ilog2() as usize)
+        .clamp(MIN_BUCKETS_COUNT_LOG, MAX_BUCKETS_COUNT_LOG);
+
+    let min_minimizer_length =
+        (buckets_count_log + MAXIMUM_SECOND_BUCKETS_LOG + MINIMIZERS_PER_SECOND_BUCKET_LOG + 1) / 2;
+    let minimizer_length = ::utils::compute_best_m(k)
+        .max(min_minimizer_length)
+        .min(k - 1);
+
+    TunedParameters {
+        buckets_count_log,
+        minimizer_length,
+        estimated_distinct_kmers,
+        buckets_skew: sample.buckets_skew,
+    }
+}
+
+/// The path of the run manifest of a graph
+pub fn get_run_manifest_file(graph_file: impl AsRef<Path>) -> PathBuf {
+    graph_file.as_ref().with_extension("manifest.json")
+}
+
+/// Writes the parameters chosen for a build, to be able to run it again with the same setup
+pub(crate) fn write_run_manifest(graph_file: &Path, k: usize, parameters: &TunedParameters) {
+    let manifest_file = get_run_manifest_file(graph_file);
+    let mut file = File::create(&manifest_file).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create run manifest {}: {}",
+            manifest_file.display(),
+            err
+        )
+    });
+
+    writeln!(
+        file,
+        "{{\"kmer_length\":{}, \"minimizer_length\":{}, \"buckets_count_log\":{}, \"estimated_distinct_kmers\":{}, \"buckets_skew\":{:.3}}}",
+        k,
+        parameters.minimizer_length,
+        parameters.buckets_count_log,
+        parameters.estimated_distinct_kmers,
+        parameters.buckets_skew
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_minimizers_diversity, choose_parameters, sample_input};
+    use crate::GeneralSequenceBlockData;
+    use hashes::cn_nthash::CanonicalNtHashIteratorFactory;
+    use utils::test_dir::TestDir;
+
+    const K: usize = 31;
+    const M: usize = 12;
+
+    fn sample_sequence(name: &str, sequence: &str) -> super::InputSample {
+        sample_sequence_with_m(name, sequence, M)
+    }
+
+    fn sample_sequence_with_m(name: &str, sequence: &str, m: usize) -> super::InputSample {
+        let temp_dir = TestDir::new(name);
+        let input_file = temp_dir.path("input.fa");
+        std::fs::write(&input_file, format!(">0\n{}\n", sequence)).unwrap();
+        let sample = sample_input::<CanonicalNtHashIteratorFactory>(
+            &[GeneralSequenceBlockData::FASTA((input_file.clone(), None))],
+            K,
+            m,
+            None,
+        );
+        sample
+    }
+
+    #[test]
+    fn higher_cardinality_gets_more_buckets() {
+        let mut state: u64 = 0xbb67_ae85_84ca_a73b;
+        let random_sequence: String = (0..200_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                ['A', 'C', 'G', 'T'][(state >> 32) as usize % 4]
+            })
+            .collect();
+
+        // The same number of bases, with the first 2000 repeated
+        let repeated_sequence = random_sequence[..2000].repeat(100);
+
+        let low_cardinality = sample_sequence("auto-tuning-low", &repeated_sequence);
+        let high_cardinality = sample_sequence("auto-tuning-high", &random_sequence);
+        assert!(high_cardinality.distinct_kmers > 20 * low_cardinality.distinct_kmers);
+
+        // Parameters for a 10G bases input sampled at its start, with 1GB of memory
+        let choose = |sample| choose_parameters(sample, 10_000_000_000, K, 1 << 30, 16);
+
+        let low_parameters = choose(&low_cardinality);
+        let high_parameters = choose(&high_cardinality);
+        assert!(high_parameters.estimated_distinct_kmers > low_parameters.estimated_distinct_kmers);
+        assert!(high_parameters.buckets_count_log > low_parameters.buckets_count_log);
+        assert!(high_parameters.minimizer_length >= low_parameters.minimizer_length);
+    }
+
+    #[test]
+    fn too_short_minimizers_on_low_diversity_input_warn() {
+        let mut state: u64 = 0x3c6e_f372_fe94_f82b;
+        let random_sequence: String = (0..200_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                ['A', 'C', 'G', 'T'][(state >> 32) as usize % 4]
+            })
+            .collect();
+        let low_diversity_sequence = random_sequence[..2000].repeat(100);
+        let buckets_count = 1 << config::MIN_BUCKETS_COUNT_LOG;
+
+        let short_m = 5;
+        let low_diversity =
+            sample_sequence_with_m("minimizers-check-low", &low_diversity_sequence, short_m);
+        let warning = check_minimizers_diversity(&low_diversity, short_m, buckets_count);
+        assert!(warning.unwrap().contains("too few"));
+
+        let high_diversity = sample_sequence("minimizers-check-high", &random_sequence);
+        assert!(high_diversity.distinct_minimizers > low_diversity.distinct_minimizers);
+        assert_eq!(
+            check_minimizers_diversity(&high_diversity, M, buckets_count),
+            None
+        );
+    }
+}
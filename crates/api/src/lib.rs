@@ -1,47 +1,108 @@
+mod auto_tuning;
+mod online_builder;
+mod options;
+mod query_run_record;
 mod utils;
 
+use crate::options::RunSettings;
+use crate::query_run_record::QueryRunRecord;
+use ::utils::hyperloglog::HyperLogLog;
+use ::utils::random_seed::{derive_seed, RandomComponent};
 use colors::bundles::graph_querying::ColorBundleGraphQuerying;
 use colors::colors_manager::ColorsManager;
 use colors::{
     bundles::multifile_building::ColorBundleMultifileBuilding, non_colored::NonColoredManager,
 };
-use hashes::MinimizerHashFunctionFactory;
+use config::PipelineSettings;
 use hashes::{cn_nthash::CanonicalNtHashIteratorFactory, fw_nthash::ForwardNtHashIteratorFactory};
+use hashes::{ExtendableHashTraitType, HashFunction, HashFunctionFactory};
+use io::concurrent::structured_sequences::kmer_counts_sidecar::get_kmer_counts_file;
+use io::open_files_limit::max_buckets_count_log_for_open_files;
+use io::sequences_reader::SequencesReader;
 use io::sequences_stream::fasta::FastaFileSequencesStream;
+use io::sequences_stream::general::GeneralSequencesStream;
 use io::sequences_stream::GenericSequencesStream;
 use parallel_processor::enable_counters_logging;
 use parallel_processor::memory_data_size::MemoryDataSize;
 use parallel_processor::memory_fs::MemoryFs;
 use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use parking_lot::Mutex;
+use querier::QueryResultCallback;
+use rayon::prelude::*;
 use std::cmp::max;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
 use std::fs::create_dir_all;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::Ordering;
 use std::time::Duration;
 
+pub use crate::auto_tuning::{get_run_manifest_file, TunedParameters};
+pub use crate::online_builder::OnlineGraphBuilder;
+pub use crate::options::{BuildOptions, QueryOptions};
 pub use crate::utils::HashType;
+pub use ::utils::phase_log::{
+    get_phases_sub_times, get_phases_utilization, log_info, log_warning, LogFormat, PhaseSubTimes,
+    PhaseUtilization, SubPhase,
+};
+pub use assembler::{
+    find_orphan_links, ComponentsOutputMode, KmerCountingMode, OrphanLink, OrphanLinksCheck,
+    SingleThreadedMode,
+};
+pub use colors::managers::multiple::{
+    SharedSequencePolicy, APPROXIMATE_COLOR_NAME, SHARED_COLOR_NAME, UBIQUITOUS_COLOR_NAME,
+};
+pub use colors::storage::diff::ColormapDiff;
+pub use colors::storage::serializer::rebuild_colormap_from_temp;
 pub use config::ColorIndexType;
+pub use hashes::canonical_compatibility::CanonicalCompatibility;
+pub use hashes::minimizer_ordering::MinimizerOrdering;
+pub use io::circular_inputs::CircularInputs;
+pub use io::concurrent::structured_sequences::coverage::CoverageStatistic;
+pub use io::concurrent::structured_sequences::low_coverage_bed::get_low_coverage_bed_file;
+pub use io::concurrent::structured_sequences::unitig_anchors::get_unitig_anchors_file;
+pub use io::concurrent::temp_reads::creads_utils::ReadsSizeEncoding;
+pub use io::input_validation::QueriesValidation;
+pub use io::mmap_sink::{MmapSinkReader, MmapSinkWriter};
+pub use io::n_gaps::{find_n_gaps, write_n_gaps_bed};
 pub use io::sequences_reader::{DnaSequence, DnaSequencesFileType};
 pub use io::sequences_stream::{
+    circular_fasta::CircularFastaStream,
     general::{DynamicSequencesStream, GeneralSequenceBlockData},
+    kmer_counts_dump::KmerCountsDumpStream,
+    stranded_inputs::{InputStrandMode, StrandedInputsStream},
     SequenceInfo,
 };
+pub use io::unitig_links::{LinkStrand, UnitigLink};
+pub use querier::abundance_profile::AbundanceProfile;
+pub use querier::kmer_counts::KmerCountsTable;
+pub use querier::kmer_spectrum::SpectraComparison;
+pub use querier::query_results_merge::{
+    merge_query_results, write_merged_query_results, GraphColorHit, MergedQueryResult, QueryResult,
+};
+pub use querier::regions_query::{QueryRegion, RegionQueryResult};
+pub use querier::scaffolding::ScaffoldingResult;
+pub use querier::unitigs_index::{UnitigPosition, UnitigsIndex};
 pub use querier::ColoredQueryOutputFormat;
 
 pub mod debug {
     use crate::utils::HashType;
     use assembler::AssemblerStartingStep;
+    pub use assembler::{DEBUG_MISMATCHED_UNITIG_JOINS, DEBUG_ORPHAN_LINKS_MISSING_UNITIGS};
     pub use config::KEEP_FILES as DEBUG_KEEP_FILES;
     use parking_lot::Mutex;
     use querier::QuerierStartingStep;
+    use std::path::PathBuf;
     use std::sync::atomic::{AtomicBool, AtomicUsize};
 
     pub static DEBUG_ASSEMBLER_FIRST_STEP: Mutex<AssemblerStartingStep> =
         Mutex::new(AssemblerStartingStep::MinimizerBucketing);
     pub static DEBUG_ASSEMBLER_LAST_STEP: Mutex<AssemblerStartingStep> =
         Mutex::new(AssemblerStartingStep::MaximalUnitigsLinks);
+    /// The temp dir of a build run kept with KEEP_FILES, reused by a build resumed from a later step.
+    /// The directory is not removed by the resumed build, that removes only the files it created
+    pub static DEBUG_ASSEMBLER_RESUME_TEMP_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
 
     pub static DEBUG_QUERIER_FIRST_STEP: Mutex<QuerierStartingStep> =
         Mutex::new(QuerierStartingStep::MinimizerBucketing);
@@ -76,6 +137,28 @@ pub struct GGCATConfig {
 
     /// The path to an optional json-formatted real time stats file
     pub stats_file: Option<PathBuf>,
+
+    /// The threads used to prefetch and flush the files, derived from the total threads if None.
+    /// The builds and the queries can override the prefetch threads with their options
+    pub io_threads_count: Option<usize>,
+
+    /// The threads of the global compute pool, derived from the total threads if None.
+    /// The builds and the queries can override the processing threads with their options
+    pub compute_threads_count: Option<usize>,
+
+    /// The format of the log messages
+    pub log_format: LogFormat,
+
+    /// Logs the start and the end of every phase, with its elapsed and cpu times
+    pub verbose_phases_log: bool,
+
+    /// The encoding of the reads sizes in the temporary buckets
+    pub reads_size_encoding: ReadsSizeEncoding,
+
+    /// The maximum bases count of a read in the temporary buckets, a longer read is reported as a
+    /// corrupted bucket and fails the build or the query. With None the longest read of the
+    /// fixed width encoding is allowed
+    pub max_read_length: Option<u64>,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -89,23 +172,146 @@ pub enum ExtraElaboration {
     Eulertigs,
     /// Generate pathtigs
     Pathtigs,
+    /// Generate maximal unitigs links and group the unitigs by connected component
+    ConnectedComponents(ComponentsOutputMode),
 }
 
 static INSTANCE: Mutex<Option<&'static GGCATInstance>> = Mutex::new(None);
 
+fn estimate_distinct_kmers_with_hash<H: HashFunctionFactory>(
+    input_streams: &[GeneralSequenceBlockData],
+    k: usize,
+    random_seed: Option<u64>,
+) -> u64 {
+    H::initialize(k);
+
+    let seed = derive_seed(random_seed, RandomComponent::CardinalitySketch, 0);
+
+    let hll = input_streams
+        .par_iter()
+        .map(|block| {
+            let mut hll = HyperLogLog::new(HyperLogLog::DEFAULT_PRECISION, seed);
+            GeneralSequencesStream::new(false).read_block(
+                block,
+                false,
+                Some(k - 1),
+                |seq, _info| {
+                    for part in seq.seq.split(|b| *b == b'N') {
+                        if part.len() < k {
+                            continue;
+                        }
+                        for hash in H::new(part, k).iter() {
+                            hll.add_hash(H::get_u64(hash.to_unextendable()));
+                        }
+                    }
+                },
+            );
+            hll
+        })
+        .reduce(
+            || HyperLogLog::new(HyperLogLog::DEFAULT_PRECISION, seed),
+            |mut a, b| {
+                a.merge(&b);
+                a
+            },
+        );
+
+    hll.estimate()
+}
+
 pub struct GGCATInstance(GGCATConfig);
 
-fn create_tempdir(base_path: Option<PathBuf>) -> Option<PathBuf> {
-    base_path.map(|t| {
-        let temp_dir = t.join(&format!("build_graph_{}", uuid::Uuid::new_v4()));
-        let _ = create_dir_all(&temp_dir);
-        temp_dir
-    })
+/// Temporary directory of a single run, removed when dropped.
+/// It is never removed with KEEP_FILES, while with keep_until_success
+/// it is retained unless the run is marked as succeeded, for post-mortem analysis.
+/// A directory supplied by the user, as the one of a resumed run, is never removed:
+/// only the files created in it by this run are
+struct RunTempDir {
+    path: Option<PathBuf>,
+    keep_until_success: bool,
+    succeeded: bool,
+    // The entries found in a supplied directory when the run started
+    supplied_entries: Option<HashSet<OsString>>,
 }
 
-fn remove_tempdir(temp_dir: Option<PathBuf>) {
-    if let Some(temp_dir) = temp_dir {
-        let _ = std::fs::remove_dir_all(temp_dir);
+impl RunTempDir {
+    fn create(base_path: Option<PathBuf>, keep_until_success: bool) -> Self {
+        Self::existing(
+            base_path.map(|t| {
+                let temp_dir = t.join(&format!("build_graph_{}", uuid::Uuid::new_v4()));
+                let _ = create_dir_all(&temp_dir);
+                temp_dir
+            }),
+            keep_until_success,
+        )
+    }
+
+    fn existing(path: Option<PathBuf>, keep_until_success: bool) -> Self {
+        Self {
+            path,
+            keep_until_success,
+            succeeded: false,
+            supplied_entries: None,
+        }
+    }
+
+    fn supplied(path: PathBuf, keep_until_success: bool) -> Self {
+        let supplied_entries = std::fs::read_dir(&path)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.file_name())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            supplied_entries: Some(supplied_entries),
+            ..Self::existing(Some(path), keep_until_success)
+        }
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        self.path.clone()
+    }
+
+    /// Marks the run as succeeded and removes the directory, unless KEEP_FILES is set.
+    /// Dropping the directory without calling this keeps it with keep_until_success
+    fn succeed(mut self) {
+        self.succeeded = true;
+    }
+}
+
+impl Drop for RunTempDir {
+    fn drop(&mut self) {
+        let keep_files = config::KEEP_FILES.load(Ordering::Relaxed)
+            || (!self.succeeded && self.keep_until_success);
+
+        if keep_files {
+            return;
+        }
+
+        let Some(temp_dir) = &self.path else {
+            return;
+        };
+
+        match &self.supplied_entries {
+            None => {
+                let _ = std::fs::remove_dir_all(temp_dir);
+            }
+            Some(supplied_entries) => {
+                for entry in std::fs::read_dir(temp_dir).into_iter().flatten().flatten() {
+                    if supplied_entries.contains(&entry.file_name()) {
+                        continue;
+                    }
+                    let _ = if entry.path().is_dir() {
+                        std::fs::remove_dir_all(entry.path())
+                    } else {
+                        std::fs::remove_file(entry.path())
+                    };
+                }
+            }
+        }
     }
 }
 
@@ -124,9 +330,21 @@ impl GGCATInstance {
         fdlimit::raise_fd_limit();
 
         config::PREFER_MEMORY.store(config.prefer_memory, Ordering::Relaxed);
+        ::utils::phase_log::set_log_format(config.log_format);
+        ::utils::phase_log::set_verbose_phases_log(config.verbose_phases_log);
+        io::concurrent::temp_reads::creads_utils::set_reads_size_encoding(
+            config.reads_size_encoding,
+        );
+        if let Some(max_read_length) = config.max_read_length {
+            io::concurrent::temp_reads::creads_utils::set_max_read_length(max_read_length);
+        }
 
         rayon::ThreadPoolBuilder::new()
-            .num_threads(config.total_threads_count)
+            .num_threads(
+                config
+                    .compute_threads_count
+                    .unwrap_or(config.total_threads_count),
+            )
             .thread_name(|i| format!("rayon-thread-{}", i))
             .build_global()
             .unwrap();
@@ -148,15 +366,52 @@ impl GGCATInstance {
                 (config.memory * (MemoryDataSize::OCTET_GIBIOCTET_FACTOR as f64)) as usize,
             ),
             config::FLUSH_QUEUE_FACTOR * config.total_threads_count,
-            max(1, config.total_threads_count / 4),
+            config::flush_threads_count(
+                config.io_threads_count,
+                max(1, config.total_threads_count / 4),
+            ),
             8192,
         );
         *instance = Some(Box::leak(Box::new(GGCATInstance(config))));
         return instance.unwrap();
     }
 
-    /// Builds a new graph from the given input streams, with the specified parameters
+    /// Builds a new graph from the given input streams, with the specified parameters.
+    /// Panics if a check of the built graph fails, see try_build_graph
     pub fn build_graph(
+        &self,
+        input_streams: Vec<GeneralSequenceBlockData>,
+        output_file: PathBuf,
+        color_names: Option<&[String]>,
+        kmer_length: usize,
+        threads_count: usize,
+        forward_only: bool,
+        minimizer_length: Option<usize>,
+        colors: bool,
+        min_multiplicity: usize,
+        extra_elab: ExtraElaboration,
+        options: BuildOptions,
+    ) -> PathBuf {
+        self.try_build_graph(
+            input_streams,
+            output_file,
+            color_names,
+            kmer_length,
+            threads_count,
+            forward_only,
+            minimizer_length,
+            colors,
+            min_multiplicity,
+            extra_elab,
+            options,
+        )
+        .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Builds a new graph from the given input streams, with the specified parameters, returning an error
+    /// if a check of the built graph fails, as the orphan links check in fail mode. The graph is written
+    /// even if a check fails. The builds and the queries run one at a time, each with its own options
+    pub fn try_build_graph(
         &self,
         // The input streams
         input_streams: Vec<GeneralSequenceBlockData>,
@@ -171,7 +426,7 @@ impl GGCATInstance {
         kmer_length: usize,
         // The threads to be used
         threads_count: usize,
-        // Treats reverse complementary kmers as different
+        // Treats reverse complementary kmers as different, for the inputs without their own strand mode
         forward_only: bool,
         // Overrides the default m-mers (minimizers) length
         minimizer_length: Option<usize>,
@@ -183,18 +438,84 @@ impl GGCATInstance {
         min_multiplicity: usize,
 
         extra_elab: ExtraElaboration,
-    ) -> PathBuf {
-        let bucketing_hash_dispatch = if forward_only {
-            <ForwardNtHashIteratorFactory as MinimizerHashFunctionFactory>::dynamic_dispatch_id()
+
+        // The options of this build
+        mut options: BuildOptions,
+    ) -> Result<PathBuf, String> {
+        if options.deterministic_output {
+            if colors
+                || !matches!(
+                    extra_elab,
+                    ExtraElaboration::None | ExtraElaboration::UnitigLinks
+                )
+            {
+                return Err(
+                    "the deterministic output can only be written for the uncolored unitigs"
+                        .to_string(),
+                );
+            }
+            if options.output_sink.is_some()
+                || options.kmer_counts_output
+                || options.low_coverage_bed.is_some()
+                || options.unitig_anchors_output
+            {
+                return Err(
+                    "the deterministic output cannot be combined with the outputs that refer to the unitig ids"
+                        .to_string(),
+                );
+            }
+        }
+
+        let _settings = RunSettings::for_build(&mut options);
+        let pipeline_settings = options.pipeline_settings();
+
+        let strand_specific = options.strand_specific;
+        if strand_specific {
+            if matches!(
+                extra_elab,
+                ExtraElaboration::GreedyMatchtigs
+                    | ExtraElaboration::Eulertigs
+                    | ExtraElaboration::Pathtigs
+            ) {
+                return Err("the matchtigs cannot be built in the strand specific mode".to_string());
+            }
+            if input_streams.iter().any(|block| {
+                block.strand_mode(InputStrandMode::ForwardOnly) == InputStrandMode::Canonical
+            }) {
+                return Err(
+                    "the canonical inputs cannot be used in the strand specific mode".to_string(),
+                );
+            }
+        }
+        let forward_only = forward_only || strand_specific;
+
+        let strand_mode = if forward_only {
+            InputStrandMode::ForwardOnly
         } else {
-            <CanonicalNtHashIteratorFactory as MinimizerHashFunctionFactory>::dynamic_dispatch_id()
+            InputStrandMode::Canonical
         };
 
+        // With some forward-only inputs the reverse complementary kmers are hashed as different kmers,
+        // while the canonical inputs are added on both strands, bucketed by their canonical minimizers
+        let forward_only_kmers =
+            GeneralSequenceBlockData::has_forward_only_kmers(&input_streams, strand_mode);
+        let canonical_minimizers = input_streams
+            .iter()
+            .any(|block| block.strand_mode(strand_mode) == InputStrandMode::Canonical);
+
+        // The skewed buckets retry can switch to the random ordering after the first bucketing
+        let bucketing_hash_dispatch = utils::get_minimizer_hash_static_id(
+            canonical_minimizers,
+            options.minimizer_ordering != MinimizerOrdering::Hash
+                || options.skewed_buckets_auto_retry,
+        );
+
         let merging_hash_dispatch = utils::get_hash_static_id(
             debug::DEBUG_HASH_TYPE.lock().clone(),
             kmer_length,
-            forward_only,
-        );
+            forward_only_kmers,
+            options.canonical_compatibility,
+        )?;
 
         let colors_hash = if colors {
             ColorBundleMultifileBuilding::dynamic_dispatch_id()
@@ -202,36 +523,285 @@ impl GGCATInstance {
             NonColoredManager::dynamic_dispatch_id()
         };
 
-        let temp_dir = create_tempdir(self.0.temp_dir.clone());
+        hashes::minimizer_ordering::set_random_order_key(derive_seed(
+            pipeline_settings.random_seed,
+            RandomComponent::MinimizerOrdering,
+            0,
+        ) as u32);
+
+        let sample_input = |sample_minimizer_length| {
+            if canonical_minimizers {
+                auto_tuning::sample_input::<CanonicalNtHashIteratorFactory>(
+                    &input_streams,
+                    kmer_length,
+                    sample_minimizer_length,
+                    pipeline_settings.random_seed,
+                )
+            } else {
+                auto_tuning::sample_input::<ForwardNtHashIteratorFactory>(
+                    &input_streams,
+                    kmer_length,
+                    sample_minimizer_length,
+                    pipeline_settings.random_seed,
+                )
+            }
+        };
+
+        let mut buckets_count_log = *debug::BUCKETS_COUNT_LOG_FORCE.lock();
+        let tuned_parameters = if options.auto_tuning && buckets_count_log.is_none() {
+            let sample =
+                sample_input(minimizer_length.unwrap_or(::utils::compute_best_m(kmer_length)));
+            let total_bases = input_streams
+                .iter()
+                .map(|block| block.estimated_bases_count())
+                .sum();
+
+            let parameters = auto_tuning::choose_parameters(
+                &sample,
+                total_bases,
+                kmer_length,
+                (self.0.memory * (MemoryDataSize::OCTET_GIBIOCTET_FACTOR as f64)) as u64,
+                threads_count,
+            );
+            buckets_count_log = Some(parameters.buckets_count_log);
+            Some(parameters)
+        } else {
+            None
+        };
+
+        let minimizer_length = minimizer_length
+            .or(tuned_parameters.map(|parameters| parameters.minimizer_length))
+            .unwrap_or(::utils::compute_best_m(kmer_length));
+
+        if options.minimizers_check {
+            let sample = sample_input(minimizer_length);
+            let buckets_count = 1
+                << buckets_count_log
+                    .unwrap_or_else(|| {
+                        io::compute_stats_from_input_blocks(&input_streams).best_buckets_count_log
+                    })
+                    .min(
+                        max_buckets_count_log_for_open_files(pipeline_settings.max_open_files)
+                            .unwrap_or(usize::MAX),
+                    );
+            log_info(format!(
+                "Found {} distinct minimizers of length {} in the first {} bases of the input, for {} buckets",
+                sample.distinct_minimizers, minimizer_length, sample.sampled_bases, buckets_count
+            ));
+            if let Some(warning) =
+                auto_tuning::check_minimizers_diversity(&sample, minimizer_length, buckets_count)
+            {
+                log_warning(warning);
+            }
+        }
+
+        let temp_dir = match debug::DEBUG_ASSEMBLER_RESUME_TEMP_DIR.lock().clone() {
+            Some(resume_temp_dir) => {
+                RunTempDir::supplied(resume_temp_dir, options.keep_temp_files_until_success)
+            }
+            None => RunTempDir::create(
+                self.0.temp_dir.clone(),
+                options.keep_temp_files_until_success,
+            ),
+        };
 
         let output_file = assembler::dynamic_dispatch::run_assembler(
             (bucketing_hash_dispatch, merging_hash_dispatch, colors_hash),
             kmer_length,
-            minimizer_length.unwrap_or(::utils::compute_best_m(kmer_length)),
+            minimizer_length,
             debug::DEBUG_ASSEMBLER_FIRST_STEP.lock().clone(),
             debug::DEBUG_ASSEMBLER_LAST_STEP.lock().clone(),
             input_streams,
+            strand_mode,
             color_names.unwrap_or(&[]),
             output_file,
-            temp_dir.clone(),
+            temp_dir.path(),
             threads_count,
             min_multiplicity,
-            *debug::BUCKETS_COUNT_LOG_FORCE.lock(),
+            options.target_sequences.clone(),
+            buckets_count_log,
             Some(debug::DEBUG_LINK_PHASE_ITERATION_START_STEP.load(Ordering::Relaxed)),
             self.0.intermediate_compression_level,
-            extra_elab == ExtraElaboration::UnitigLinks,
+            matches!(
+                extra_elab,
+                ExtraElaboration::UnitigLinks | ExtraElaboration::ConnectedComponents(_)
+            ),
             match extra_elab {
                 ExtraElaboration::GreedyMatchtigs => Some(assembler::MatchtigMode::GreedyTigs),
                 ExtraElaboration::Eulertigs => Some(assembler::MatchtigMode::EulerTigs),
                 ExtraElaboration::Pathtigs => Some(assembler::MatchtigMode::PathTigs),
                 _ => None,
             },
+            match extra_elab {
+                ExtraElaboration::ConnectedComponents(mode) => Some(mode),
+                _ => None,
+            },
             debug::DEBUG_ONLY_BSTATS.load(Ordering::Relaxed),
+            pipeline_settings,
         );
+        let output_file = output_file?;
 
-        remove_tempdir(temp_dir);
+        // The order, the ids and the strand of the unitigs depend on the buckets scheduling
+        if options.deterministic_output && output_file.exists() {
+            let sorted_file = output_file.with_file_name(format!(
+                "sorted-{}",
+                output_file.file_name().unwrap().to_string_lossy()
+            ));
+            assembler::sort_unitigs(&output_file, &sorted_file, !forward_only_kmers);
+            std::fs::rename(&sorted_file, &output_file).map_err(|err| {
+                format!(
+                    "cannot replace {} with the sorted graph: {}",
+                    output_file.display(),
+                    err
+                )
+            })?;
+        }
 
-        output_file
+        if let Some(parameters) = tuned_parameters {
+            auto_tuning::write_run_manifest(
+                &output_file,
+                kmer_length,
+                &TunedParameters {
+                    minimizer_length,
+                    ..parameters
+                },
+            );
+        }
+
+        temp_dir.succeed();
+
+        Ok(output_file)
+    }
+
+    /// Checks that all the fasta input files are well formed, returning an error describing
+    /// the first malformed record found. Other input types are not checked
+    pub fn validate_inputs(input_streams: &[GeneralSequenceBlockData]) -> Result<(), String> {
+        for block in input_streams {
+            if let GeneralSequenceBlockData::FASTA((file, _)) = block {
+                if matches!(
+                    SequencesReader::get_file_type(file),
+                    Some(DnaSequencesFileType::FASTA)
+                ) {
+                    io::input_validation::validate_fasta_file(file)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps the colored inputs of a graph built again in append mode to the colors of the existing graph,
+    /// so that each color keeps its index in the existing colormap and the new colors are appended after
+    /// them. The input files without a color are assigned to the existing color with the given name, so that
+    /// their kmers are counted in that color instead of creating a new one, as with more sequencing of the
+    /// same library. Returns the color names of the appended graph, ordered by color index
+    pub fn append_inputs_to_color(
+        // The existing graph, with its colormap in the same folder
+        existing_graph: impl AsRef<Path>,
+        kmer_length: usize,
+        // The input files, with their color index in color_names if already assigned
+        inputs: &mut [(PathBuf, Option<ColorIndexType>)],
+        // The names of the colors of the inputs, ordered by color index
+        color_names: &[String],
+        // The existing color of the input files without a color
+        same_color_as: &str,
+    ) -> Result<Vec<String>, String> {
+        use colors::colors_manager::ColorMapReader;
+        use colors::storage::deserializer::ColorsDeserializer;
+        use colors::DefaultColorsSerializer;
+
+        let colormap_file = Self::get_colormap_file(existing_graph);
+        if !colormap_file.exists() {
+            return Err(format!(
+                "the colormap {} of the existing graph does not exist",
+                colormap_file.display()
+            ));
+        }
+        let colormap = ColorsDeserializer::<DefaultColorsSerializer>::new(&colormap_file, true);
+        colormap.check_kmer_length(kmer_length)?;
+
+        let mut appended_names: Vec<_> = (0..colormap.input_colors_count())
+            .map(|color| {
+                colormap
+                    .get_color_name(color as ColorIndexType, false)
+                    .to_string()
+            })
+            .collect();
+
+        let mut existing_index = |name: &str| match appended_names.iter().position(|n| n == name) {
+            Some(index) => index as ColorIndexType,
+            None => {
+                appended_names.push(name.to_string());
+                (appended_names.len() - 1) as ColorIndexType
+            }
+        };
+
+        let remapped_colors: Vec<_> = color_names
+            .iter()
+            .map(|name| existing_index(name))
+            .collect();
+        let same_color = appended_names
+            .iter()
+            .take(colormap.input_colors_count())
+            .position(|name| name == same_color_as)
+            .ok_or_else(|| {
+                format!(
+                    "the color '{}' is not one of the colors of the existing graph",
+                    same_color_as
+                )
+            })? as ColorIndexType;
+
+        for (file, input_color) in inputs.iter_mut() {
+            *input_color = Some(match *input_color {
+                Some(color) => *remapped_colors.get(color as usize).ok_or_else(|| {
+                    format!(
+                        "the color index {} of {} has no name",
+                        color,
+                        file.display()
+                    )
+                })?,
+                None => same_color,
+            });
+        }
+        Ok(appended_names)
+    }
+
+    /// Checks that the fasta query files are well formed before a long query run, without querying the graph,
+    /// returning the number of valid, too short, empty and malformed queries. With the strict input enabled
+    /// an error is returned if any query is not valid, otherwise a warning is logged
+    pub fn validate_queries(
+        query_files: &[PathBuf],
+        kmer_length: usize,
+        strict_input: bool,
+    ) -> Result<QueriesValidation, String> {
+        io::input_validation::validate_queries(query_files, kmer_length, strict_input)
+    }
+
+    /// Estimates the number of distinct kmers in the given input streams with a single streaming pass,
+    /// using an HyperLogLog sketch. The estimate has a standard error of about 1%
+    pub fn estimate_distinct_kmers(
+        &self,
+        // The input streams
+        input_streams: &[GeneralSequenceBlockData],
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // Treats reverse complementary kmers as different
+        forward_only: bool,
+        // The seed of the sketch, to get the same estimate from the same inputs
+        random_seed: Option<u64>,
+    ) -> u64 {
+        if forward_only {
+            estimate_distinct_kmers_with_hash::<ForwardNtHashIteratorFactory>(
+                input_streams,
+                kmer_length,
+                random_seed,
+            )
+        } else {
+            estimate_distinct_kmers_with_hash::<CanonicalNtHashIteratorFactory>(
+                input_streams,
+                kmer_length,
+                random_seed,
+            )
+        }
     }
 
     /// Queries a (optionally) colored graph with a specific set of sequences as queries
@@ -259,44 +829,707 @@ impl GGCATInstance {
 
         // Query output format
         color_output_format: ColoredQueryOutputFormat,
+
+        // The options of this query
+        options: QueryOptions,
     ) -> PathBuf {
-        let bucketing_hash_dispatch = if forward_only {
-            <ForwardNtHashIteratorFactory as MinimizerHashFunctionFactory>::dynamic_dispatch_id()
-        } else {
-            <CanonicalNtHashIteratorFactory as MinimizerHashFunctionFactory>::dynamic_dispatch_id()
-        };
+        self.run_query(
+            input_graph,
+            input_query,
+            output_file_prefix,
+            kmer_length,
+            threads_count,
+            forward_only,
+            minimizer_length,
+            colors,
+            color_output_format,
+            options,
+            None,
+        )
+    }
 
-        let merging_hash_dispatch = utils::get_hash_static_id(
-            debug::DEBUG_HASH_TYPE.lock().clone(),
+    /// Queries a colored graph like query_graph, additionally reporting the results of each query
+    /// to the given callback as soon as they are available, to allow showing incremental progress.
+    /// The callback receives the query index and its matches as (color index, matched kmers count) pairs.
+    /// WARNING: the callback is called concurrently from multiple threads, so it must be thread-safe.
+    pub fn query_graph_with_callback(
+        &self,
+        // The input graph
+        input_graph: PathBuf,
+        // The input query as a .fasta file
+        input_query: PathBuf,
+
+        // The output file
+        output_file_prefix: PathBuf,
+
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // The threads to be used
+        threads_count: usize,
+        // Treats reverse complementary kmers as different
+        forward_only: bool,
+        // Overrides the default m-mers (minimizers) length
+        minimizer_length: Option<usize>,
+
+        // Query output format
+        color_output_format: ColoredQueryOutputFormat,
+
+        // The options of this query
+        options: QueryOptions,
+
+        result_callback: impl Fn(u64, &[(ColorIndexType, u64)]) + Send + Sync,
+    ) -> PathBuf {
+        self.run_query(
+            input_graph,
+            input_query,
+            output_file_prefix,
+            kmer_length,
+            threads_count,
+            forward_only,
+            minimizer_length,
+            true,
+            color_output_format,
+            options,
+            Some(&result_callback),
+        )
+    }
+
+    /// Queries a colored graph with the regions of a reference listed in a BED file, or in a GFF/GTF
+    /// file if it has a .gff, .gff3 or .gtf extension, attributing the matches to the feature of each region.
+    /// The extracted regions are written to <output_file_prefix>.regions.fa and queried as in query_graph.
+    /// Returns the matches of each region, as (color index, matched kmers count) pairs, in the regions order
+    pub fn query_regions(
+        &self,
+        // The input graph
+        input_graph: PathBuf,
+        // The reference containing the regions, as a .fasta file
+        reference_file: PathBuf,
+        // The regions to be queried
+        regions_file: PathBuf,
+
+        // The output file
+        output_file_prefix: PathBuf,
+
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // The threads to be used
+        threads_count: usize,
+        // Treats reverse complementary kmers as different
+        forward_only: bool,
+        // Overrides the default m-mers (minimizers) length
+        minimizer_length: Option<usize>,
+
+        // Query output format
+        color_output_format: ColoredQueryOutputFormat,
+
+        // The options of this query
+        options: QueryOptions,
+    ) -> Vec<RegionQueryResult> {
+        let regions = querier::regions_query::read_query_regions(&regions_file)
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let queries_file = PathBuf::from(format!("{}.regions.fa", output_file_prefix.display()));
+        querier::regions_query::write_regions_queries(reference_file, &regions, &queries_file)
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let results = Mutex::new(HashMap::new());
+        self.query_graph_with_callback(
+            input_graph,
+            queries_file,
+            output_file_prefix,
             kmer_length,
+            threads_count,
             forward_only,
+            minimizer_length,
+            color_output_format,
+            options,
+            |query_index, matches| {
+                results.lock().insert(query_index, matches.to_vec());
+            },
         );
 
-        let colors_hash = if colors {
+        querier::regions_query::attribute_regions_results(&regions, &results.into_inner())
+    }
+
+    fn run_query(
+        &self,
+        input_graph: PathBuf,
+        input_query: PathBuf,
+        output_file_prefix: PathBuf,
+        kmer_length: usize,
+        threads_count: usize,
+        forward_only: bool,
+        minimizer_length: Option<usize>,
+        colors: bool,
+        color_output_format: ColoredQueryOutputFormat,
+        options: QueryOptions,
+        result_callback: Option<&QueryResultCallback>,
+    ) -> PathBuf {
+        let _settings = RunSettings::for_query(&options);
+        let pipeline_settings = options.pipeline_settings();
+        let temp_dir = RunTempDir::create(
+            self.0.temp_dir.clone(),
+            options.keep_temp_files_until_success,
+        );
+
+        let record = QueryRunRecord {
+            input_graph,
+            input_query,
+            output_file_prefix,
+            kmer_length,
+            minimizer_length: minimizer_length.unwrap_or(::utils::compute_best_m(kmer_length)),
+            forward_only,
+            colors,
+            color_output_format,
+            buckets_count_log: *debug::BUCKETS_COUNT_LOG_FORCE.lock(),
+            unitigs_subset: options.unitigs_subset,
+        };
+        if let Some(temp_dir) = temp_dir.path() {
+            record.write(&temp_dir);
+        }
+
+        let output_file = self.run_recorded_query(
+            &record,
+            debug::DEBUG_QUERIER_FIRST_STEP.lock().clone(),
+            temp_dir.path(),
+            threads_count,
+            options.canonical_compatibility,
+            options.minimizer_ordering,
+            pipeline_settings,
+            result_callback,
+        );
+
+        temp_dir.succeed();
+
+        output_file
+    }
+
+    fn run_recorded_query(
+        &self,
+        record: &QueryRunRecord,
+        step: querier::QuerierStartingStep,
+        temp_dir: Option<PathBuf>,
+        threads_count: usize,
+        canonical_compatibility: CanonicalCompatibility,
+        minimizer_ordering: MinimizerOrdering,
+        settings: PipelineSettings,
+        result_callback: Option<&QueryResultCallback>,
+    ) -> PathBuf {
+        let bucketing_hash_dispatch = utils::get_minimizer_hash_static_id(
+            !record.forward_only,
+            minimizer_ordering != MinimizerOrdering::Hash,
+        );
+
+        let merging_hash_dispatch = utils::get_hash_static_id(
+            debug::DEBUG_HASH_TYPE.lock().clone(),
+            record.kmer_length,
+            record.forward_only,
+            canonical_compatibility,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+
+        let colors_hash = if record.colors {
             ColorBundleGraphQuerying::dynamic_dispatch_id()
         } else {
             NonColoredManager::dynamic_dispatch_id()
         };
 
-        let temp_dir = create_tempdir(self.0.temp_dir.clone());
+        hashes::minimizer_ordering::set_random_order_key(derive_seed(
+            settings.random_seed,
+            RandomComponent::MinimizerOrdering,
+            0,
+        ) as u32);
 
-        let output_file = querier::dynamic_dispatch::run_query(
+        querier::dynamic_dispatch::run_query(
             (bucketing_hash_dispatch, merging_hash_dispatch, colors_hash),
-            kmer_length,
-            minimizer_length.unwrap_or(::utils::compute_best_m(kmer_length)),
-            debug::DEBUG_QUERIER_FIRST_STEP.lock().clone(),
-            input_graph,
-            input_query,
-            output_file_prefix,
-            temp_dir.clone(),
-            *debug::BUCKETS_COUNT_LOG_FORCE.lock(),
+            record.kmer_length,
+            record.minimizer_length,
+            step,
+            record.input_graph.clone(),
+            record.input_query.clone(),
+            record.output_file_prefix.clone(),
+            temp_dir,
+            record.buckets_count_log,
             threads_count,
             self.0.intermediate_compression_level,
-            color_output_format,
+            record.color_output_format,
+            result_callback,
+            record.unitigs_subset.clone(),
+            settings,
+        )
+    }
+
+    /// Runs again a query starting from the given phase (e.g. "colormap reading"), reading the
+    /// intermediate files of the previous phases from the temp dir of a query run kept with
+    /// KEEP_FILES. The query is run with the same parameters and fails if the intermediate files
+    /// needed by the phase are missing. The unitigs subset of the options is ignored, as the one
+    /// of the query run is used
+    pub fn resume_from_phase(
+        &self,
+        // The temp dir of the query run
+        temp_dir: impl AsRef<Path>,
+        // The name of the first phase to run
+        phase_name: &str,
+        // The threads to be used
+        threads_count: usize,
+        // The options of the resumed query
+        options: QueryOptions,
+    ) -> Result<PathBuf, String> {
+        let _settings = RunSettings::for_query(&options);
+        let temp_dir = temp_dir.as_ref();
+        let step = querier::QuerierStartingStep::from_phase_name(phase_name)?;
+        let mut record = QueryRunRecord::read(temp_dir)?;
+
+        // The buckets count chosen by the query run from its inputs and the open files limit
+        let buckets_count_log = record.buckets_count_log.unwrap_or_else(|| {
+            let searched_graph = match &record.unitigs_subset {
+                Some(_) => temp_dir.join("unitigs-subset.fa"),
+                None => record.input_graph.clone(),
+            };
+            io::compute_stats_from_input_blocks(&[
+                GeneralSequenceBlockData::FASTA((searched_graph, None)),
+                GeneralSequenceBlockData::FASTA((record.input_query.clone(), None)),
+            ])
+            .best_buckets_count_log
+        });
+        let buckets_count_log = buckets_count_log.min(
+            max_buckets_count_log_for_open_files(options.max_open_files).unwrap_or(usize::MAX),
         );
+        record.buckets_count_log = Some(buckets_count_log);
+
+        querier::phase_resume::check_phase_prerequisites(
+            temp_dir,
+            step,
+            1 << buckets_count_log,
+            record.colors,
+        )?;
+
+        Ok(self.run_recorded_query(
+            &record,
+            step,
+            Some(temp_dir.to_path_buf()),
+            threads_count,
+            options.canonical_compatibility,
+            options.minimizer_ordering,
+            options.pipeline_settings(),
+            None,
+        ))
+    }
+
+    /// Builds an on-disk index mapping each kmer of the graph to its unitig, offset and strand.
+    /// The index can be opened with UnitigsIndex::open to lookup kmers positions
+    pub fn build_unitigs_index(
+        &self,
+        // The input graph
+        input_graph: PathBuf,
+        // Specifies the k-mers length
+        kmer_length: usize,
+    ) -> PathBuf {
+        let index_file = querier::unitigs_index::get_unitigs_index_file(&input_graph);
+        querier::unitigs_index::build_unitigs_index(input_graph, &index_file, kmer_length);
+        index_file
+    }
+
+    /// Queries the graph reporting, for each query, the matched unitigs and the intervals
+    /// of consecutive kmers matches, instead of the matches counts.
+    /// The unitig and offset of each query kmer are read from the graph kmers index,
+    /// see [`Self::get_or_build_unitigs_index`]
+    pub fn query_graph_positions(
+        &self,
+        // The input graph
+        input_graph: PathBuf,
+        // The input query as a .fasta file
+        input_query: PathBuf,
+
+        // The output file
+        output_file_prefix: PathBuf,
+
+        // Specifies the k-mers length
+        kmer_length: usize,
+    ) -> PathBuf {
+        let index_file = self.get_or_build_unitigs_index(input_graph, kmer_length);
+        querier::positions_query::query_positions(index_file, input_query, output_file_prefix)
+    }
 
-        remove_tempdir(temp_dir);
+    /// Queries the graph reporting, for each query, the ids of the unitigs matched by its kmers and optionally
+    /// their sequences, for the local re-alignment of the queries. The unitig ids are found with the graph
+    /// kmers index ([`Self::get_or_build_unitigs_index`]), while loading the sequences needs an additional
+    /// pass on the graph
+    pub fn query_graph_unitigs(
+        &self,
+        // The input graph
+        input_graph: PathBuf,
+        // The input query as a .fasta file
+        input_query: PathBuf,
+
+        // The output file
+        output_file_prefix: PathBuf,
+
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // Also report the sequences of the matched unitigs
+        with_sequences: bool,
+    ) -> PathBuf {
+        let index_file = self.get_or_build_unitigs_index(input_graph.clone(), kmer_length);
+        querier::unitigs_query::query_unitigs(
+            index_file,
+            input_graph,
+            input_query,
+            output_file_prefix,
+            with_sequences,
+        )
+    }
+
+    /// Queries the graph counting, for each query, only the matched kmers that belong to a run of at least
+    /// min_run_length consecutive matching kmers along the query, dropping the scattered single matches.
+    /// The output has the same columns of the uncolored queries. The runs are found by looking up each
+    /// query kmer in the graph kmers index, see [`Self::get_or_build_unitigs_index`]
+    pub fn query_graph_min_run_length(
+        &self,
+        // The input graph
+        input_graph: PathBuf,
+        // The input query as a .fasta file
+        input_query: PathBuf,
+
+        // The output file
+        output_file_prefix: PathBuf,
 
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // The minimum number of consecutive matching kmers of a counted match
+        min_run_length: usize,
+    ) -> PathBuf {
+        let index_file = self.get_or_build_unitigs_index(input_graph, kmer_length);
+        querier::run_length_query::query_min_run_length(
+            index_file,
+            input_query,
+            output_file_prefix,
+            min_run_length,
+        )
+    }
+
+    /// Writes the kmers coverage of the graph projected on a reference as a bedGraph track, giving each
+    /// position the count of the kmer starting there as tracked while building the graph, or 0 if the kmer
+    /// is not in the graph. The graph must have been built with the kmer_counts_output build option, in a build
+    /// with the kmer counters support. The reference kmers are located in the unitigs with the graph kmers index
+    /// ([`Self::get_or_build_unitigs_index`]). Returns the number of written intervals
+    pub fn write_coverage_bedgraph(
+        &self,
+        // The built graph
+        input_graph: PathBuf,
+        // The reference the coverage is projected on
+        reference: impl AsRef<Path>,
+        // The output bedGraph file
+        output_file: impl AsRef<Path>,
+        // Specifies the k-mers length
+        kmer_length: usize,
+    ) -> Result<usize, String> {
+        let kmer_counts_file = get_kmer_counts_file(&input_graph);
+        if !kmer_counts_file.exists() {
+            return Err(format!(
+                "The kmer counts of the graph were not found at {}, build the graph with the kmer counts output enabled",
+                kmer_counts_file.display()
+            ));
+        }
+        let index_file = self.get_or_build_unitigs_index(input_graph, kmer_length);
+        querier::coverage_track::write_coverage_bedgraph(
+            index_file,
+            kmer_counts_file,
+            reference,
+            output_file,
+        )
+    }
+
+    /// Queries the graph expanding the IUPAC degenerate bases of the query kmers into all their
+    /// concrete variants, and reporting for each query the unitigs matched by any of the variants.
+    /// Queries with a kmer expanding to more than max_expansions variants are reported as too degenerate.
+    /// Each variant is looked up in the graph kmers index, see [`Self::get_or_build_unitigs_index`]
+    pub fn query_graph_degenerate(
+        &self,
+        // The input graph
+        input_graph: PathBuf,
+        // The input query as a .fasta file
+        input_query: PathBuf,
+
+        // The output file
+        output_file_prefix: PathBuf,
+
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // The maximum number of variants of a single query kmer
+        max_expansions: usize,
+    ) -> PathBuf {
+        let index_file = self.get_or_build_unitigs_index(input_graph, kmer_length);
+        querier::degenerate_query::query_degenerate(
+            index_file,
+            input_query,
+            output_file_prefix,
+            max_expansions,
+        )
+    }
+
+    /// Colors an existing uncolored graph by matching the kmers of each sample against it, without
+    /// building the graph again. Each sample file is assigned the color equal to its index, and
+    /// a unitig receives the colors of all the samples sharing at least one kmer with it, as found
+    /// with the graph kmers index ([`Self::get_or_build_unitigs_index`]).
+    /// Returns the sorted colors of each colored unitig, by unitig id
+    pub fn color_existing_graph(
+        &self,
+        // The input graph
+        input_graph: PathBuf,
+        // The samples to be used as colors
+        sample_files: Vec<PathBuf>,
+
+        // Specifies the k-mers length
+        kmer_length: usize,
+    ) -> BTreeMap<u64, Vec<ColorIndexType>> {
+        let index_file = self.get_or_build_unitigs_index(input_graph, kmer_length);
+        querier::graph_coloring::color_existing_graph(index_file, &sample_files)
+    }
+
+    /// Writes the graph with each unitig oriented as the reference strand where its kmers are found,
+    /// keeping the original orientation of the unitigs not found in the reference. The strand of each
+    /// reference kmer is read from the graph kmers index, see [`Self::get_or_build_unitigs_index`].
+    /// Returns the number of reversed unitigs
+    pub fn orient_unitigs_to_reference(
+        &self,
+        // The input graph
+        input_graph: PathBuf,
+        // The reference as a .fasta file
+        reference_file: PathBuf,
+        // The output file
+        output_file: PathBuf,
+
+        // Specifies the k-mers length
+        kmer_length: usize,
+    ) -> usize {
+        let index_file = self.get_or_build_unitigs_index(input_graph.clone(), kmer_length);
+        querier::reference_orientation::orient_unitigs_to_reference(
+            index_file,
+            input_graph,
+            reference_file,
+            output_file,
+        )
+    }
+
+    /// Orders and orients the unitigs of the graph into scaffolds using the read pairs linking them,
+    /// writing the scaffolds to a fasta file with N gaps of the estimated length between the unitigs.
+    /// The reads are placed on the unitigs with the graph kmers index ([`Self::get_or_build_unitigs_index`]).
+    /// See querier::scaffolding::scaffold_unitigs for the details
+    pub fn scaffold_unitigs(
+        &self,
+        // The input graph
+        input_graph: PathBuf,
+        // The first and second mates of the read pairs, in the same order
+        reads_1: PathBuf,
+        reads_2: PathBuf,
+        // The output scaffolds file
+        output_file: PathBuf,
+
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // Minimum number of pairs supporting a join between two unitigs
+        min_pair_links: usize,
+        // The fragments insert size, estimated from the pairs if not given
+        insert_size: Option<usize>,
+    ) -> Result<ScaffoldingResult, String> {
+        let index_file = self.get_or_build_unitigs_index(input_graph.clone(), kmer_length);
+        querier::scaffolding::scaffold_unitigs(
+            index_file,
+            input_graph,
+            reads_1,
+            reads_2,
+            output_file,
+            min_pair_links,
+            insert_size,
+        )
+    }
+
+    /// Returns the kmers index of the graph, stored next to it, building it only if needed.
+    /// An existing index is reused if it was built with the same kmer_length from the current graph file,
+    /// recognized by the size and the modification time of the graph recorded in the index header.
+    /// It is built again if the graph changed since, if the kmer length differs, or if it was written
+    /// by an older version of the index format and cannot be opened
+    pub fn get_or_build_unitigs_index(&self, input_graph: PathBuf, kmer_length: usize) -> PathBuf {
+        let index_file = querier::unitigs_index::get_unitigs_index_file(&input_graph);
+
+        let index_valid = UnitigsIndex::open(&index_file)
+            .map(|index| index.kmer_length() == kmer_length && index.matches_graph(&input_graph))
+            .unwrap_or(false);
+
+        if !index_valid {
+            self.build_unitigs_index(input_graph, kmer_length);
+        }
+
+        index_file
+    }
+
+    /// Builds an on-disk table with the exact count of each canonical kmer of the input files.
+    /// The table can be opened with KmerCountsTable::open to stream the counts of query kmers
+    pub fn build_kmer_counts_table(
+        &self,
+        // The input files
+        input_files: Vec<PathBuf>,
+        // The output file
+        output_file: PathBuf,
+        // Specifies the k-mers length
+        kmer_length: usize,
+    ) -> PathBuf {
+        querier::kmer_counts::build_kmer_counts_table(&input_files, &output_file, kmer_length);
+        output_file
+    }
+
+    /// Compares the kmer spectra of two sets of input files, writing the number of distinct kmers
+    /// of each set with each abundance side by side in a tab-separated file, with the L1 distance
+    /// and the cosine similarity of the spectra. All the distinct kmers of a set are kept in memory
+    /// while counting, so this is intended as a lightweight QC of moderately sized inputs
+    pub fn compare_kmer_spectra(
+        first_input_files: Vec<PathBuf>,
+        second_input_files: Vec<PathBuf>,
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // The output file
+        output_file: impl AsRef<Path>,
+    ) -> SpectraComparison {
+        let first = querier::kmer_spectrum::compute_kmer_spectrum(&first_input_files, kmer_length);
+        let second =
+            querier::kmer_spectrum::compute_kmer_spectrum(&second_input_files, kmer_length);
+        let comparison = querier::kmer_spectrum::compare_spectra(&first, &second);
+        querier::kmer_spectrum::write_spectra_comparison(
+            &first,
+            &second,
+            &comparison,
+            &output_file,
+        )
+        .unwrap_or_else(|err| {
+            panic!(
+                "Cannot write kmer spectra comparison {}: {}",
+                output_file.as_ref().display(),
+                err
+            )
+        });
+        comparison
+    }
+
+    /// Suggests the minimum multiplicity for building a graph from the input files, as the valley between
+    /// the peak of the erroneous kmers and the peak of the true coverage in their kmer spectrum.
+    /// If the spectrum has no clear valley a warning is logged and the default of 2 is returned.
+    /// As compare_kmer_spectra, all the distinct kmers are kept in memory while counting
+    pub fn estimate_min_multiplicity(
+        input_files: Vec<PathBuf>,
+        // Specifies the k-mers length
+        kmer_length: usize,
+    ) -> usize {
+        let spectrum = querier::kmer_spectrum::compute_kmer_spectrum(&input_files, kmer_length);
+        querier::kmer_spectrum::estimate_min_multiplicity(&spectrum).unwrap_or_else(|| {
+            log_warning(format!(
+                "WARNING: the kmer spectrum has no clear valley between the errors and the coverage peak, using the minimum multiplicity {}",
+                querier::kmer_spectrum::DEFAULT_MIN_MULTIPLICITY
+            ));
+            querier::kmer_spectrum::DEFAULT_MIN_MULTIPLICITY
+        })
+    }
+
+    /// Writes the abundance profile of each color of a graph, as a tab-separated matrix with the
+    /// number of distinct kmers of each color in each abundance bin. The abundances are read from a
+    /// kmer counts table for each color, as built by build_kmer_counts_table from the inputs of that color
+    pub fn write_color_abundance_profile(
+        // The input graph, with its colormap in the same folder
+        input_graph: impl AsRef<Path>,
+        // The kmer counts tables, indexed by color
+        color_tables: &[PathBuf],
+        // Lower bounds of the abundance bins, querier::abundance_profile::DEFAULT_ABUNDANCE_BINS by default
+        bins: &[u64],
+        // The output file
+        output_file: impl AsRef<Path>,
+    ) -> Result<AbundanceProfile, String> {
+        let (profile, color_names) =
+            querier::abundance_profile::compute_abundance_profile(input_graph, color_tables, bins)?;
+        querier::abundance_profile::write_abundance_profile(&profile, &color_names, &output_file)
+            .map_err(|err| {
+            format!(
+                "Cannot write abundance profile {}: {}",
+                output_file.as_ref().display(),
+                err
+            )
+        })?;
+        Ok(profile)
+    }
+
+    /// Concatenates the unitigs of multiple graphs in a single graph, renumbering the unitig ids
+    /// and the links so that they are unique across all the inputs. The graphs are not assembled again
+    pub fn concat_graphs(
+        // The input graphs
+        input_graphs: Vec<PathBuf>,
+        // The output file
+        output_file: PathBuf,
+    ) -> PathBuf {
+        assembler::concat_graphs(&input_graphs, output_file)
+    }
+
+    /// Computes a checksum of the unitigs of a graph, that does not depend on their order,
+    /// strand or ids. Two runs producing the same set of unitigs have the same hash
+    pub fn compute_reproducibility_hash(graph_file: impl AsRef<Path>) -> u64 {
+        assembler::compute_reproducibility_hash(graph_file)
+    }
+
+    /// Computes the reproducibility hash of a graph and writes it next to the graph,
+    /// returning the path of the written hash file
+    pub fn write_reproducibility_hash(graph_file: impl AsRef<Path>) -> PathBuf {
+        assembler::write_reproducibility_hash(graph_file)
+    }
+
+    /// Splits the unitigs longer than max_unitig_length into pieces overlapping by k - 1 bases,
+    /// linked together so that the graph topology is preserved. The unitigs are renumbered.
+    /// Returns the number of unitigs in the output graph
+    pub fn split_long_unitigs(
+        // The input graph
+        input_graph: impl AsRef<Path>,
+        // The output file
+        output_file: impl AsRef<Path>,
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // The maximum length of the output unitigs
+        max_unitig_length: usize,
+    ) -> usize {
+        assembler::split_long_unitigs(input_graph, output_file, kmer_length, max_unitig_length)
+    }
+
+    /// Splits the graph into files of at most max_records_per_file unitigs each, named as the graph
+    /// file with the part number before its extensions (e.g. unitigs.part001.fa), writing to index_file
+    /// the ranges of unitig ids found in each part. Returns the written part files
+    pub fn write_output_parts(
+        // The input graph
+        input_graph: impl AsRef<Path>,
+        // The maximum number of unitigs in each part
+        max_records_per_file: usize,
+        // The output index file
+        index_file: impl AsRef<Path>,
+    ) -> Vec<PathBuf> {
+        querier::output_parts::write_output_parts(input_graph, max_records_per_file, index_file)
+    }
+
+    /// Converts an existing fasta assembly into an uncolored unitigs graph that can be queried as the
+    /// ones built with build_graph, with each record (split at the non-ACGT bases) written as a unitig.
+    /// With rebuild_links the links between the unitigs overlapping by k - 1 bases are added to the headers.
+    /// Returns the output graph
+    pub fn import_fasta_as_unitigs(
+        &self,
+        // The input fasta assembly
+        input_fasta: impl AsRef<Path>,
+        // The output graph
+        output_file: PathBuf,
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // Rebuild the links between the unitigs
+        rebuild_links: bool,
+    ) -> PathBuf {
+        querier::fasta_import::import_fasta_as_unitigs(
+            input_fasta,
+            &output_file,
+            kmer_length,
+            rebuild_links,
+        );
         output_file
     }
 
@@ -326,6 +1559,134 @@ impl GGCATInstance {
         })
     }
 
+    /// Compares the colormap of a graph with the one of its updated version, as after an append
+    /// or a rebuild, returning the added and removed color names and the color subsets added,
+    /// removed or changed at each index
+    pub fn diff_colormaps(
+        // The colormap before the update
+        old_colormap: impl AsRef<Path>,
+        // The colormap after the update
+        new_colormap: impl AsRef<Path>,
+    ) -> ColormapDiff {
+        colors::storage::diff::diff_colormaps(old_colormap, new_colormap)
+    }
+
+    /// Returns an iterator over the links between the unitigs of a graph built with the unitigs links,
+    /// with the orientation of both the unitigs. Each link is returned once from each of its unitigs
+    pub fn iter_links(
+        // The input graph
+        input_graph: impl AsRef<Path>,
+    ) -> impl Iterator<Item = UnitigLink> {
+        io::unitig_links::iter_links(input_graph)
+    }
+
+    /// Writes the unitigs of a colored graph to a separate fasta file for each color, named
+    /// <output_prefix>.color<index>.fa. The unitigs shared by multiple colors are written to each
+    /// of their files. Returns the written files, indexed by color
+    pub fn write_unitigs_by_color(
+        // The input graph, with its colormap in the same folder
+        input_graph: impl AsRef<Path>,
+        // The prefix of the output files
+        output_prefix: impl AsRef<Path>,
+        // The maximum number of output files open at the same time, None for the default limit
+        max_open_files: Option<usize>,
+    ) -> Vec<PathBuf> {
+        querier::color_unitigs::write_unitigs_by_color(input_graph, output_prefix, max_open_files)
+    }
+
+    /// Writes the jaccard similarity of the kmers of each pair of colors of a graph, as a symmetric matrix
+    /// in tsv format, or as the list of the pairs sharing kmers with many colors. The sentinel colors are
+    /// not written. Returns the number of input colors
+    pub fn write_color_jaccard_matrix(
+        // The input graph, with its colormap in the same folder
+        input_graph: impl AsRef<Path>,
+        // The output tsv file
+        output_file: impl AsRef<Path>,
+    ) -> usize {
+        querier::color_jaccard::write_color_jaccard_matrix(input_graph, output_file)
+    }
+
+    /// Writes the kmers of a graph found in exactly one input color, grouped by color, as the stretches of
+    /// consecutive unique kmers of the unitigs with their sequence, to design color specific markers.
+    /// Fails if the graph was built with SharedSequencePolicy::FirstColor.
+    /// Returns the number of unique kmers of each input color
+    pub fn write_unique_kmers_report(
+        &self,
+        // The input graph, with its colormap in the same folder
+        input_graph: impl AsRef<Path>,
+        // The output tsv file
+        output_file: impl AsRef<Path>,
+    ) -> Result<Vec<u64>, String> {
+        let temp_dir = RunTempDir::create(self.0.temp_dir.clone(), false);
+        let unique_kmers = querier::unique_kmers::write_unique_kmers_report(
+            input_graph,
+            output_file,
+            temp_dir.path().unwrap_or_default(),
+        );
+        if unique_kmers.is_ok() {
+            temp_dir.succeed();
+        }
+        unique_kmers
+    }
+
+    /// Writes the unitigs of a graph as a parquet table, one row per unitig with its id, length, coverage,
+    /// GC content and, for colored graphs, the list of its colors. Returns the number of written rows.
+    /// Requires the parquet feature
+    #[cfg(feature = "parquet")]
+    pub fn write_unitigs_parquet(
+        // The input graph, with its colormap in the same folder if colored
+        input_graph: impl AsRef<Path>,
+        // The output parquet file
+        output_file: impl AsRef<Path>,
+        // Add the colors column, decoded from the colormap
+        colored: bool,
+    ) -> u64 {
+        querier::unitigs_parquet::write_unitigs_parquet(input_graph, output_file, colored)
+    }
+
+    /// Writes a small graph in the GraphViz DOT format for visualization, with a node for each unitig and an edge
+    /// for each of its links. Graphs with more than max_nodes unitigs are refused with an error.
+    /// Returns the number of written nodes
+    pub fn write_unitigs_dot(
+        // The input graph, with the unitigs links for the edges
+        input_graph: impl AsRef<Path>,
+        // The output dot file
+        output_file: impl AsRef<Path>,
+        // querier::unitigs_dot::DEFAULT_DOT_MAX_NODES by default
+        max_nodes: usize,
+    ) -> Result<usize, String> {
+        querier::unitigs_dot::write_unitigs_dot(input_graph, output_file, max_nodes)
+    }
+
+    /// Converts a colored graph to GFA, tagging each segment with its colors for visualization
+    /// in Bandage and similar tools. At most max_listed_colors colors are listed for each segment,
+    /// followed by the count of the remaining ones. Returns the number of written segments
+    pub fn write_colored_gfa(
+        // The input graph, with its colormap in the same folder
+        input_graph: impl AsRef<Path>,
+        // The output gfa file
+        output_file: impl AsRef<Path>,
+        kmer_length: usize,
+        // The name of the colors tag, querier::colored_gfa::DEFAULT_GFA_COLOR_TAG by default
+        color_tag: &str,
+        max_listed_colors: usize,
+        // List the color indices instead of the color names
+        color_indices: bool,
+        // Add the coverage of each color of the segment, as a sparse CV:B:i tag.
+        // The graph must have been built with the color_coverage_output build option
+        color_coverage: bool,
+    ) -> usize {
+        querier::colored_gfa::write_colored_gfa(
+            input_graph,
+            output_file,
+            kmer_length,
+            color_tag,
+            max_listed_colors,
+            color_indices,
+            color_coverage,
+        )
+    }
+
     /// Dumps the unitigs of the given graph, optionally with colors
     /// It's not guaranteed that maximal unitigs are returned, as only kmers with the same colors subset
     /// are returned as whole unitigs to speedup colormap reading times
@@ -345,22 +1706,23 @@ impl GGCATInstance {
         single_thread_output_function: bool,
         output_function: impl Fn(&[u8], &[ColorIndexType], bool) + Send + Sync,
     ) {
-        let temp_dir = create_tempdir(self.0.temp_dir.clone());
+        let temp_dir = RunTempDir::create(self.0.temp_dir.clone(), false);
 
         if colors {
             dumper::dump_unitigs(
                 kmer_length,
                 minimizer_length.unwrap_or(::utils::compute_best_m(kmer_length)),
                 graph_input,
-                temp_dir.clone(),
+                temp_dir.path(),
                 *debug::BUCKETS_COUNT_LOG_FORCE.lock(),
                 threads_count,
                 single_thread_output_function,
                 self.0.intermediate_compression_level,
+                PipelineSettings::default(),
                 output_function,
             );
         } else {
-            FastaFileSequencesStream::new().read_block(
+            FastaFileSequencesStream::new(false).read_block(
                 &(graph_input, None),
                 false,
                 Some(kmer_length - 1),
@@ -370,6 +1732,6 @@ impl GGCATInstance {
             );
         }
 
-        remove_tempdir(temp_dir);
+        temp_dir.succeed();
     }
 }
@@ -0,0 +1,217 @@
+use crate::{BuildOptions, ExtraElaboration, GGCATInstance, GeneralSequenceBlockData};
+use io::concurrent::structured_sequences::kmer_counts_sidecar::get_kmer_counts_file;
+use io::sequences_stream::unitigs_kmer_counts::UnitigsKmerCountsStream;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Builds a graph from reads that arrive over time, as the ones basecalled during a sequencing run.
+/// The batches of reads are written to disk as soon as they are added, so the reads are never held
+/// in memory, and they are incorporated into the graph only when the current graph is requested,
+/// by appending the new batches to the unitigs of the previous graph. With a minimum multiplicity,
+/// the counts of all the kmers are kept in a second graph next to the output one, built with the
+/// kmer counts and read back with them, so that the kmers below the minimum multiplicity can reach
+/// it in the next batches. The graph is uncolored and it is the same as the one built at once from
+/// all the reads
+pub struct OnlineGraphBuilder<'a> {
+    instance: &'a GGCATInstance,
+    output_file: PathBuf,
+    kmer_length: usize,
+    threads_count: usize,
+    forward_only: bool,
+    minimizer_length: Option<usize>,
+    min_multiplicity: usize,
+    pending_batches: Vec<PathBuf>,
+    batches_count: usize,
+    has_graph: bool,
+}
+
+impl<'a> OnlineGraphBuilder<'a> {
+    pub fn new(
+        instance: &'a GGCATInstance,
+        // The output graph, updated each time the current graph is requested
+        output_file: PathBuf,
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // The threads to be used
+        threads_count: usize,
+        // Treats reverse complementary kmers as different
+        forward_only: bool,
+        // Overrides the default m-mers (minimizers) length
+        minimizer_length: Option<usize>,
+        // Minimum multiplicity required to keep a kmer, counted over all the batches
+        min_multiplicity: usize,
+    ) -> Result<Self, String> {
+        if min_multiplicity > 1 && !cfg!(feature = "kmer-counters") {
+            return Err(
+                "The online build with a minimum multiplicity requires the kmer-counters feature"
+                    .to_string(),
+            );
+        }
+
+        Ok(Self {
+            instance,
+            output_file,
+            kmer_length,
+            threads_count,
+            forward_only,
+            minimizer_length,
+            min_multiplicity,
+            pending_batches: vec![],
+            batches_count: 0,
+            has_graph: false,
+        })
+    }
+
+    // The graph with all the kmers of the batches, with their counts written next to it
+    fn counts_graph(&self) -> PathBuf {
+        self.sibling_file("counts-", "")
+    }
+
+    fn counted_kmers(&self, counts_graph: &Path) -> Vec<GeneralSequenceBlockData> {
+        UnitigsKmerCountsStream::new(vec![counts_graph.to_path_buf()], self.kmer_length)
+            .unwrap_or_else(|err| panic!("{}", err))
+            .into_input_blocks()
+    }
+
+    fn build(
+        &self,
+        inputs: Vec<GeneralSequenceBlockData>,
+        output_file: PathBuf,
+        min_multiplicity: usize,
+        kmer_counts_output: bool,
+    ) -> PathBuf {
+        self.instance.build_graph(
+            inputs,
+            output_file,
+            None,
+            self.kmer_length,
+            self.threads_count,
+            self.forward_only,
+            self.minimizer_length,
+            false,
+            min_multiplicity,
+            ExtraElaboration::None,
+            BuildOptions {
+                kmer_counts_output,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn replace_graph(next_file: impl AsRef<Path>, file: impl AsRef<Path>) {
+        std::fs::rename(next_file.as_ref(), file.as_ref()).unwrap_or_else(|err| {
+            panic!(
+                "Cannot update the online graph {}: {}",
+                file.as_ref().display(),
+                err
+            )
+        });
+    }
+
+    fn sibling_file(&self, prefix: &str, suffix: &str) -> PathBuf {
+        let file_name = self
+            .output_file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.output_file
+            .with_file_name(format!("{}{}{}", prefix, file_name, suffix))
+    }
+
+    /// Adds a batch of reads, written to a fasta file next to the output graph
+    /// until the next update of the graph
+    pub fn add_batch<S: AsRef<[u8]>>(&mut self, reads: impl IntoIterator<Item = S>) {
+        self.batches_count += 1;
+        let batch_file = self.sibling_file("", &format!(".batch{}.fa", self.batches_count));
+
+        let mut writer = BufWriter::new(File::create(&batch_file).unwrap_or_else(|err| {
+            panic!(
+                "Cannot create reads batch {}: {}",
+                batch_file.display(),
+                err
+            )
+        }));
+        for read in reads {
+            writer.write_all(b">read\n").unwrap();
+            writer.write_all(read.as_ref()).unwrap();
+            writer.write_all(b"\n").unwrap();
+        }
+        writer.flush().unwrap();
+
+        self.pending_batches.push(batch_file);
+    }
+
+    /// Incorporates the batches added since the last call into the graph, returning the updated graph.
+    /// Returns None if no batch was added yet
+    pub fn current_graph(&mut self) -> Option<PathBuf> {
+        if self.pending_batches.is_empty() {
+            return self.has_graph.then(|| self.output_file.clone());
+        }
+
+        let batches = self
+            .pending_batches
+            .iter()
+            .map(|batch| GeneralSequenceBlockData::FASTA((batch.clone(), None)));
+
+        if self.min_multiplicity <= 1 {
+            let mut inputs = vec![];
+            if self.has_graph {
+                inputs.push(GeneralSequenceBlockData::FASTA((
+                    self.output_file.clone(),
+                    None,
+                )));
+            }
+            inputs.extend(batches);
+
+            // The previous graph is an input of the build, so the new one is written to another file
+            let next_graph = self.build(inputs, self.sibling_file("next-", ""), 1, false);
+            Self::replace_graph(&next_graph, &self.output_file);
+        } else {
+            let counts_graph = self.counts_graph();
+            let mut inputs = vec![];
+            if self.has_graph {
+                inputs.extend(self.counted_kmers(&counts_graph));
+            }
+            inputs.extend(batches);
+
+            let next_counts_graph =
+                self.build(inputs, self.sibling_file("next-counts-", ""), 1, true);
+
+            Self::replace_graph(&next_counts_graph, &counts_graph);
+            Self::replace_graph(
+                get_kmer_counts_file(&next_counts_graph),
+                get_kmer_counts_file(&counts_graph),
+            );
+
+            let inputs = self.counted_kmers(&counts_graph);
+            self.build(
+                inputs,
+                self.output_file.clone(),
+                self.min_multiplicity,
+                false,
+            );
+        }
+
+        for batch in self.pending_batches.drain(..) {
+            let _ = std::fs::remove_file(batch);
+        }
+        self.has_graph = true;
+
+        Some(self.output_file.clone())
+    }
+}
+
+impl Drop for OnlineGraphBuilder<'_> {
+    fn drop(&mut self) {
+        for batch in self.pending_batches.drain(..) {
+            let _ = std::fs::remove_file(batch);
+        }
+        // The counts of the kmers are needed only to add more batches
+        if self.min_multiplicity > 1 {
+            let counts_graph = self.counts_graph();
+            let _ = std::fs::remove_file(get_kmer_counts_file(&counts_graph));
+            let _ = std::fs::remove_file(counts_graph);
+        }
+    }
+}
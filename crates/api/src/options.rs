@@ -0,0 +1,320 @@
+use assembler::{KmerCountingMode, OrphanLinksCheck, SingleThreadedMode};
+use colors::managers::multiple::SharedSequencePolicy;
+use config::PipelineSettings;
+use hashes::canonical_compatibility::CanonicalCompatibility;
+use hashes::minimizer_ordering::MinimizerOrdering;
+use io::concurrent::structured_sequences::coverage::CoverageStatistic;
+use parking_lot::{Mutex, MutexGuard};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// The options of a single graph construction, passed to GGCATInstance::build_graph.
+/// The default options build the same graph as the build command without optional flags
+pub struct BuildOptions {
+    /// Restricts the construction to the kmers of the given target sequences, plus the ones adjacent to them.
+    /// With None all the input kmers are assembled
+    pub target_sequences: Option<PathBuf>,
+    /// The maximum memory (in bytes) used by the kmers of the target sequences, that are kept in a
+    /// sorted index file in the temp dir if they do not fit. With None the default of 1GB is used
+    pub target_kmers_memory: Option<usize>,
+    /// Stranded RNA-seq mode, keeping the orientation of the reads in the whole build: the kmers are hashed
+    /// forward-only whatever the forward_only argument, so that the unitigs and their links follow the 5'->3'
+    /// direction of the transcripts. The builds with canonical inputs or matchtigs, that merge or reorient
+    /// the two strands, are refused
+    pub strand_specific: bool,
+    /// A sink that receives the final unitigs in plain fasta format, in place of the output file.
+    /// See assembler::set_output_sink for when the unitigs are streamed
+    pub output_sink: Option<Box<dyn Write + Send>>,
+    /// Also write the count of each kmer of the unitigs, as tracked while building the graph, to a file next
+    /// to the output graph. The counts are available only in the builds with the kmer counters support
+    pub kmer_counts_output: bool,
+    /// Also write the stretches of the unitigs where the coverage of the bases is below this value as a BED
+    /// file next to the output graph. The coverage is available only in the builds with the kmer counters support
+    pub low_coverage_bed: Option<u32>,
+    /// Also write the hashes of the first and the last kmer of each unitig to a file next to the output graph
+    pub unitig_anchors_output: bool,
+    /// Also write the input reads that do not contribute any kmer to the graph to this fasta file,
+    /// each with a `reason:` tag added to its header
+    pub unused_reads_output: Option<PathBuf>,
+    /// The statistic of the kmers counts of each unitig reported as its coverage (km tag)
+    pub coverage_statistic: CoverageStatistic,
+    /// The minimum number of occurrences of a kmer in a color required to assign that color to the kmer
+    pub min_color_coverage: usize,
+    /// Also write the occurrences of the kmers in each of their colors next to the colormap, to report
+    /// the per-color coverage of the unitigs, as in the CV tag of the colored GFA. The colormap stores
+    /// a subset for each distinct coverage of the same colors, so it can grow considerably
+    pub color_coverage_output: bool,
+    /// Caps the number of colors of each kmer, replacing the colors of the kmers found in more colors
+    /// with UBIQUITOUS_COLOR_NAME. With None the colors are not capped
+    pub max_kmer_colors: Option<usize>,
+    /// Bounds the size of the colors subsets stored in the colormap, marking the subsampled ones with
+    /// APPROXIMATE_COLOR_NAME. With None all the subsets are exact
+    pub max_exact_color_set: Option<usize>,
+    /// How the kmers shared by multiple colors are assigned to the colors
+    pub shared_sequence_policy: SharedSequencePolicy,
+    /// Selects the intermediate files compression level from the measured compression speed
+    /// and disk write speed, when no explicit level is given
+    pub adaptive_compression_level: bool,
+    /// The value at which the kmers counters saturate, instead of wrapping around.
+    /// None uses all the counter bits
+    pub kmer_counters_saturation: Option<usize>,
+    /// Compares the kmers sharing the same hash by their full sequence, for the non invertible hashes
+    pub exact_kmers: bool,
+    /// How the kmers repeated in a read are counted
+    pub kmer_counting_mode: KmerCountingMode,
+    /// The minimum number of occurrences in the reads of the (k+1)-mer joining two kmers
+    /// required to extend a unitig from one kmer to the other
+    pub min_link_coverage: usize,
+    /// The load factor of the kmers merge hash maps, sized for the average number of kmers of the buckets.
+    /// Lower values allocate larger tables, trading memory for speed
+    pub merge_hashmap_load_factor: f64,
+    /// When the graph is built in single-threaded mode
+    pub single_threaded_mode: SingleThreadedMode,
+    /// Rewrites the output graph with the unitigs sorted by their canonical sequence and renumbered in that
+    /// order, so that the same inputs give a byte-identical file with any threads count or single-threaded mode.
+    /// The colored builds, the matchtigs, the connected components and the outputs that refer to the unitig
+    /// ids (the sink and the sidecar files) are refused, as they are not rewritten
+    pub deterministic_output: bool,
+    /// Repeats the minimizer bucketing with a perturbed random minimizer ordering when a single
+    /// bucket is much larger than the others. Without it the skewed buckets are only reported
+    pub skewed_buckets_auto_retry: bool,
+    /// If the links are checked for references to unitigs that are not in the output graph
+    pub orphan_links_check: OrphanLinksCheck,
+    /// Chooses the buckets count and the minimizers length not explicitly given by sampling the start
+    /// of the input, writing the chosen parameters in a run manifest next to the output graph
+    pub auto_tuning: bool,
+    /// Reports the distinct minimizers found in the start of the input with the chosen minimizers length,
+    /// warning if they are too few for the buckets
+    pub minimizers_check: bool,
+    /// The order used to choose the minimizer among the m-mers of a kmer
+    pub minimizer_ordering: MinimizerOrdering,
+    /// The canonicalization semantics of the kmers
+    pub canonical_compatibility: CanonicalCompatibility,
+    /// The threads used to prefetch the input and intermediate files of each step, so that I/O and compute
+    /// can overlap on slow disks. With None they are derived from the threads count of the run
+    pub io_threads_count: Option<usize>,
+    /// The threads used to process the reads and the buckets of each step.
+    /// With None they are derived from the threads count of the run
+    pub compute_threads_count: Option<usize>,
+    /// The maximum number of files kept open at the same time, for systems with a low descriptors limit.
+    /// The buckets count is reduced to fit the limit, down to the minimum buckets count, then the reads
+    /// bucketing writes the buckets in waves, reading the inputs once for each wave. None for no limit
+    pub max_open_files: Option<usize>,
+    /// The seed of all the randomized components, to get the same output from the same inputs.
+    /// With None each run uses different random values
+    pub random_seed: Option<u64>,
+    /// Fails on the input records with characters that are not nucleotides or IUPAC codes,
+    /// instead of skipping them with a warning
+    pub strict_input: bool,
+    /// Enables the diagnostic timers of the sub-phases of the reads bucketing, see get_phases_sub_times
+    pub subphase_timing: bool,
+    /// Keeps the temporary files until the end of the run, removing them only if it completes successfully,
+    /// so that they can be inspected after a failure
+    pub keep_temp_files_until_success: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            target_sequences: None,
+            target_kmers_memory: None,
+            strand_specific: false,
+            output_sink: None,
+            kmer_counts_output: false,
+            low_coverage_bed: None,
+            unitig_anchors_output: false,
+            unused_reads_output: None,
+            coverage_statistic: CoverageStatistic::Mean,
+            min_color_coverage: 1,
+            color_coverage_output: false,
+            max_kmer_colors: None,
+            max_exact_color_set: None,
+            shared_sequence_policy: SharedSequencePolicy::AllColors,
+            adaptive_compression_level: false,
+            kmer_counters_saturation: None,
+            exact_kmers: false,
+            kmer_counting_mode: KmerCountingMode::CountPerOccurrence,
+            min_link_coverage: 1,
+            merge_hashmap_load_factor: assembler::DEFAULT_MERGE_HASHMAP_LOAD_FACTOR,
+            single_threaded_mode: SingleThreadedMode::Auto,
+            deterministic_output: false,
+            skewed_buckets_auto_retry: false,
+            orphan_links_check: OrphanLinksCheck::Disabled,
+            auto_tuning: false,
+            minimizers_check: false,
+            minimizer_ordering: MinimizerOrdering::Hash,
+            canonical_compatibility: CanonicalCompatibility::Ggcat,
+            io_threads_count: None,
+            compute_threads_count: None,
+            max_open_files: None,
+            random_seed: None,
+            strict_input: false,
+            subphase_timing: false,
+            keep_temp_files_until_success: false,
+        }
+    }
+}
+
+impl BuildOptions {
+    pub(crate) fn pipeline_settings(&self) -> PipelineSettings {
+        PipelineSettings {
+            io_threads_count: self.io_threads_count,
+            compute_threads_count: self.compute_threads_count,
+            max_open_files: self.max_open_files,
+            random_seed: self.random_seed,
+            strict_input: self.strict_input,
+            subphase_timing: self.subphase_timing,
+        }
+    }
+}
+
+/// The options of a single query, passed to GGCATInstance::query_graph and the other colored queries.
+/// The default options run the same query as the query command without optional flags
+#[derive(Clone, Debug)]
+pub struct QueryOptions {
+    /// Restricts the query to the kmers of the graph unitigs with the given ids,
+    /// for example the ones of a single connected component. With None the whole graph is queried
+    pub unitigs_subset: Option<Vec<u64>>,
+    /// Reports for each (query, color) the strand of the graph where the majority of the query kmers matched
+    pub matched_strand_report: bool,
+    /// Drops from the colored query output the (query, color) pairs where the color covers less than this
+    /// fraction of the query kmers. With 0 all the matched colors are reported
+    pub min_color_abundance: f64,
+    /// The maximum memory (in bytes) shared by the buckets whose query counters are sorted by color
+    /// when reading the colormap. With None the default of 1GB is used
+    pub counters_sort_memory: Option<usize>,
+    /// The order used to choose the minimizer among the m-mers of a kmer
+    pub minimizer_ordering: MinimizerOrdering,
+    /// The canonicalization semantics of the kmers, that must be the same of the queried graph
+    pub canonical_compatibility: CanonicalCompatibility,
+    /// The threads used to prefetch the input and intermediate files of each step, so that I/O and compute
+    /// can overlap on slow disks. With None they are derived from the threads count of the run
+    pub io_threads_count: Option<usize>,
+    /// The threads used to process the reads and the buckets of each step.
+    /// With None they are derived from the threads count of the run
+    pub compute_threads_count: Option<usize>,
+    /// The maximum number of files kept open at the same time, for systems with a low descriptors limit.
+    /// The buckets count is reduced to fit the limit, down to the minimum buckets count, then the reads
+    /// bucketing writes the buckets in waves, reading the inputs once for each wave. None for no limit
+    pub max_open_files: Option<usize>,
+    /// The seed of all the randomized components, to get the same output from the same inputs.
+    /// With None each run uses different random values
+    pub random_seed: Option<u64>,
+    /// Fails on the input records with characters that are not nucleotides or IUPAC codes,
+    /// instead of skipping them with a warning
+    pub strict_input: bool,
+    /// Enables the diagnostic timers of the sub-phases of the reads bucketing, see get_phases_sub_times
+    pub subphase_timing: bool,
+    /// Keeps the temporary files until the end of the run, removing them only if it completes successfully,
+    /// so that they can be inspected after a failure
+    pub keep_temp_files_until_success: bool,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        Self {
+            unitigs_subset: None,
+            matched_strand_report: false,
+            min_color_abundance: 0.0,
+            counters_sort_memory: None,
+            minimizer_ordering: MinimizerOrdering::Hash,
+            canonical_compatibility: CanonicalCompatibility::Ggcat,
+            io_threads_count: None,
+            compute_threads_count: None,
+            max_open_files: None,
+            random_seed: None,
+            strict_input: false,
+            subphase_timing: false,
+            keep_temp_files_until_success: false,
+        }
+    }
+}
+
+impl QueryOptions {
+    pub(crate) fn pipeline_settings(&self) -> PipelineSettings {
+        PipelineSettings {
+            io_threads_count: self.io_threads_count,
+            compute_threads_count: self.compute_threads_count,
+            max_open_files: self.max_open_files,
+            random_seed: self.random_seed,
+            strict_input: self.strict_input,
+            subphase_timing: self.subphase_timing,
+        }
+    }
+}
+
+// The pipelines read the options from process-wide settings, so only one run at a time can use them
+static RUN_LOCK: Mutex<()> = Mutex::new(());
+
+/// The settings of the running build or query, restored to the defaults when dropped,
+/// so that the options of a run never leak into the next ones
+pub(crate) struct RunSettings {
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl RunSettings {
+    /// Waits for the running build or query to complete, then applies the given build options
+    pub fn for_build(options: &mut BuildOptions) -> Self {
+        let lock = RUN_LOCK.lock();
+        apply_build_options(options);
+        Self { _lock: lock }
+    }
+
+    /// Waits for the running build or query to complete, then applies the given query options
+    pub fn for_query(options: &QueryOptions) -> Self {
+        let lock = RUN_LOCK.lock();
+        apply_query_options(options);
+        Self { _lock: lock }
+    }
+}
+
+impl Drop for RunSettings {
+    fn drop(&mut self) {
+        apply_build_options(&mut BuildOptions::default());
+        apply_query_options(&QueryOptions::default());
+        // The retry key of skewed buckets and the sampled frequent minimizers are set during the run
+        hashes::minimizer_ordering::reset_minimizer_ordering();
+    }
+}
+
+fn apply_build_options(options: &mut BuildOptions) {
+    config::set_keep_files_until_success(options.keep_temp_files_until_success);
+    assembler::set_target_kmers_memory(options.target_kmers_memory);
+    assembler::set_output_sink(options.output_sink.take());
+    io::concurrent::structured_sequences::kmer_counts_sidecar::set_kmer_counts_output(
+        options.kmer_counts_output,
+    );
+    io::concurrent::structured_sequences::low_coverage_bed::set_low_coverage_bed(
+        options.low_coverage_bed,
+    );
+    io::concurrent::structured_sequences::unitig_anchors::set_unitig_anchors_output(
+        options.unitig_anchors_output,
+    );
+    io::unused_reads::set_unused_reads_output(options.unused_reads_output.clone());
+    io::concurrent::structured_sequences::coverage::set_coverage_statistic(
+        options.coverage_statistic,
+    );
+    colors::managers::multiple::set_min_color_coverage(options.min_color_coverage);
+    colors::managers::multiple::set_color_coverage_output(options.color_coverage_output);
+    colors::managers::multiple::set_max_kmer_colors(options.max_kmer_colors);
+    colors::managers::multiple::set_max_exact_color_set(options.max_exact_color_set);
+    colors::managers::multiple::set_shared_sequence_policy(options.shared_sequence_policy);
+    io::compression_tuning::set_adaptive_compression_level(options.adaptive_compression_level);
+    structs::map_entry::set_kmer_counters_saturation(options.kmer_counters_saturation);
+    assembler::set_exact_kmers_mode(options.exact_kmers);
+    assembler::set_kmer_counting_mode(options.kmer_counting_mode);
+    assembler::set_min_link_coverage(options.min_link_coverage);
+    assembler::set_merge_hashmap_load_factor(options.merge_hashmap_load_factor);
+    assembler::set_single_threaded_mode(options.single_threaded_mode);
+    assembler::set_skewed_buckets_auto_retry(options.skewed_buckets_auto_retry);
+    assembler::set_orphan_links_check(options.orphan_links_check);
+    hashes::minimizer_ordering::set_minimizer_ordering(options.minimizer_ordering);
+}
+
+fn apply_query_options(options: &QueryOptions) {
+    config::set_keep_files_until_success(options.keep_temp_files_until_success);
+    querier::matched_strand::set_matched_strand_report(options.matched_strand_report);
+    querier::color_abundance::set_min_color_abundance(options.min_color_abundance);
+    querier::set_counters_sort_memory(options.counters_sort_memory);
+    hashes::minimizer_ordering::set_minimizer_ordering(options.minimizer_ordering);
+}
@@ -0,0 +1,153 @@
+use querier::ColoredQueryOutputFormat;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+// Saved in the temp dir of each query run, to be able to resume it from one of its phases
+const QUERY_RUN_RECORD_FILE: &str = "query-run.txt";
+
+/// The parameters of a query run, written as tab-separated key and value lines
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct QueryRunRecord {
+    pub input_graph: PathBuf,
+    pub input_query: PathBuf,
+    pub output_file_prefix: PathBuf,
+    pub kmer_length: usize,
+    pub minimizer_length: usize,
+    pub forward_only: bool,
+    pub colors: bool,
+    pub color_output_format: ColoredQueryOutputFormat,
+    pub buckets_count_log: Option<usize>,
+    pub unitigs_subset: Option<Vec<u64>>,
+}
+
+fn format_name(format: ColoredQueryOutputFormat) -> &'static str {
+    match format {
+        ColoredQueryOutputFormat::JsonLinesWithNumbers => "json-lines-with-numbers",
+        ColoredQueryOutputFormat::JsonLinesWithNames => "json-lines-with-names",
+        ColoredQueryOutputFormat::Classification => "classification",
+        ColoredQueryOutputFormat::CountMatrix => "count-matrix",
+    }
+}
+
+impl QueryRunRecord {
+    pub fn write(&self, temp_dir: &Path) {
+        let record_file = temp_dir.join(QUERY_RUN_RECORD_FILE);
+        let mut file = File::create(&record_file).unwrap_or_else(|err| {
+            panic!(
+                "Cannot create query run record {}: {}",
+                record_file.display(),
+                err
+            )
+        });
+
+        let mut fields = vec![
+            ("input_graph", self.input_graph.display().to_string()),
+            ("input_query", self.input_query.display().to_string()),
+            (
+                "output_file_prefix",
+                self.output_file_prefix.display().to_string(),
+            ),
+            ("kmer_length", self.kmer_length.to_string()),
+            ("minimizer_length", self.minimizer_length.to_string()),
+            ("forward_only", self.forward_only.to_string()),
+            ("colors", self.colors.to_string()),
+            (
+                "color_output_format",
+                format_name(self.color_output_format).to_string(),
+            ),
+        ];
+        if let Some(buckets_count_log) = self.buckets_count_log {
+            fields.push(("buckets_count_log", buckets_count_log.to_string()));
+        }
+        if let Some(unitigs_subset) = &self.unitigs_subset {
+            fields.push((
+                "unitigs_subset",
+                unitigs_subset
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ));
+        }
+
+        for (key, value) in fields {
+            writeln!(file, "{}\t{}", key, value).unwrap();
+        }
+    }
+
+    pub fn read(temp_dir: &Path) -> Result<Self, String> {
+        let record_file = temp_dir.join(QUERY_RUN_RECORD_FILE);
+        let file = File::open(&record_file).map_err(|err| {
+            format!(
+                "Cannot open query run record {}: {}, the temp dir must be the one of a query run",
+                record_file.display(),
+                err
+            )
+        })?;
+
+        let mut fields = std::collections::HashMap::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|err| err.to_string())?;
+            if let Some((key, value)) = line.split_once('\t') {
+                fields.insert(key.to_string(), value.to_string());
+            }
+        }
+
+        let field = |key: &str| {
+            fields.get(key).cloned().ok_or_else(|| {
+                format!(
+                    "Missing {} in query run record {}",
+                    key,
+                    record_file.display()
+                )
+            })
+        };
+        let parse_error = |key: &str| format!("Invalid {} in query run record", key);
+
+        let color_output_format = match field("color_output_format")?.as_str() {
+            "json-lines-with-numbers" => ColoredQueryOutputFormat::JsonLinesWithNumbers,
+            "json-lines-with-names" => ColoredQueryOutputFormat::JsonLinesWithNames,
+            "classification" => ColoredQueryOutputFormat::Classification,
+            "count-matrix" => ColoredQueryOutputFormat::CountMatrix,
+            _ => return Err(parse_error("color_output_format")),
+        };
+
+        Ok(Self {
+            input_graph: PathBuf::from(field("input_graph")?),
+            input_query: PathBuf::from(field("input_query")?),
+            output_file_prefix: PathBuf::from(field("output_file_prefix")?),
+            kmer_length: field("kmer_length")?
+                .parse()
+                .map_err(|_| parse_error("kmer_length"))?,
+            minimizer_length: field("minimizer_length")?
+                .parse()
+                .map_err(|_| parse_error("minimizer_length"))?,
+            forward_only: field("forward_only")?
+                .parse()
+                .map_err(|_| parse_error("forward_only"))?,
+            colors: field("colors")?
+                .parse()
+                .map_err(|_| parse_error("colors"))?,
+            color_output_format,
+            buckets_count_log: match fields.get("buckets_count_log") {
+                Some(value) => Some(
+                    value
+                        .parse()
+                        .map_err(|_| parse_error("buckets_count_log"))?,
+                ),
+                None => None,
+            },
+            unitigs_subset: match fields.get("unitigs_subset") {
+                Some(value) => Some(
+                    value
+                        .split(',')
+                        .filter(|id| !id.is_empty())
+                        .map(|id| id.parse().map_err(|_| parse_error("unitigs_subset")))
+                        .collect::<Result<_, _>>()?,
+                ),
+                None => None,
+            },
+        })
+    }
+}
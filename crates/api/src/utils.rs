@@ -1,4 +1,5 @@
 use dynamic_dispatch::DynamicDispatch;
+use hashes::canonical_compatibility::CanonicalCompatibility;
 
 #[derive(Copy, Clone)]
 pub enum HashType {
@@ -13,7 +14,8 @@ pub(crate) fn get_hash_static_id(
     hash_type: HashType,
     k: usize,
     forward_only: bool,
-) -> DynamicDispatch<()> {
+    canonical_compatibility: CanonicalCompatibility,
+) -> Result<DynamicDispatch<()>, String> {
     use hashes::*;
 
     let hash_type = match hash_type {
@@ -27,34 +29,51 @@ pub(crate) fn get_hash_static_id(
         x => x,
     };
 
-    match hash_type {
+    // The canonicalization semantics are chosen once here, as a separate hash factory
+    let lexicographic = canonical_compatibility.is_lexicographic();
+    if !forward_only && lexicographic && (!matches!(hash_type, HashType::SeqHash) || k > 64) {
+        return Err(format!(
+            "The {:?} canonical compatibility requires the sequence hash, with k <= 64!",
+            canonical_compatibility
+        ));
+    }
+
+    Ok(match hash_type {
         HashType::SeqHash => {
             if k <= 8 {
                 if forward_only {
                     fw_seqhash::u16::ForwardSeqHashFactory::dynamic_dispatch_id()
+                } else if lexicographic {
+                    cn_lex_seqhash::u16::CanonicalSeqHashFactory::dynamic_dispatch_id()
                 } else {
                     cn_seqhash::u16::CanonicalSeqHashFactory::dynamic_dispatch_id()
                 }
             } else if k <= 16 {
                 if forward_only {
                     fw_seqhash::u32::ForwardSeqHashFactory::dynamic_dispatch_id()
+                } else if lexicographic {
+                    cn_lex_seqhash::u32::CanonicalSeqHashFactory::dynamic_dispatch_id()
                 } else {
                     cn_seqhash::u32::CanonicalSeqHashFactory::dynamic_dispatch_id()
                 }
             } else if k <= 32 {
                 if forward_only {
                     fw_seqhash::u64::ForwardSeqHashFactory::dynamic_dispatch_id()
+                } else if lexicographic {
+                    cn_lex_seqhash::u64::CanonicalSeqHashFactory::dynamic_dispatch_id()
                 } else {
                     cn_seqhash::u64::CanonicalSeqHashFactory::dynamic_dispatch_id()
                 }
             } else if k <= 64 {
                 if forward_only {
                     fw_seqhash::u128::ForwardSeqHashFactory::dynamic_dispatch_id()
+                } else if lexicographic {
+                    cn_lex_seqhash::u128::CanonicalSeqHashFactory::dynamic_dispatch_id()
                 } else {
                     cn_seqhash::u128::CanonicalSeqHashFactory::dynamic_dispatch_id()
                 }
             } else {
-                panic!("Cannot use sequence hash for k > 64!");
+                return Err("Cannot use sequence hash for k > 64!".to_string());
             }
         }
         HashType::RabinKarp32 => {
@@ -81,5 +100,46 @@ pub(crate) fn get_hash_static_id(
         HashType::Auto => {
             unreachable!()
         }
+    })
+}
+
+/// The hash used to choose the minimizers. With the default ordering the minimizers are compared by
+/// their hash values, the other orderings are applied by a separate hash factory
+pub(crate) fn get_minimizer_hash_static_id(canonical: bool, ordered: bool) -> DynamicDispatch<()> {
+    use hashes::*;
+
+    match (canonical, ordered) {
+        (true, false) => {
+            <cn_nthash::CanonicalNtHashIteratorFactory as MinimizerHashFunctionFactory>::dynamic_dispatch_id()
+        }
+        (false, false) => {
+            <fw_nthash::ForwardNtHashIteratorFactory as MinimizerHashFunctionFactory>::dynamic_dispatch_id()
+        }
+        (true, true) => {
+            <cn_nthash::ordered::CanonicalNtHashIteratorFactory as MinimizerHashFunctionFactory>::dynamic_dispatch_id()
+        }
+        (false, true) => {
+            <fw_nthash::ordered::ForwardNtHashIteratorFactory as MinimizerHashFunctionFactory>::dynamic_dispatch_id()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_hash_settings_are_reported_as_errors() {
+        let ggcat = CanonicalCompatibility::Ggcat;
+        assert!(get_hash_static_id(HashType::SeqHash, 65, false, ggcat).is_err());
+        assert!(get_hash_static_id(HashType::Auto, 65, false, ggcat).is_ok());
+        let bifrost = CanonicalCompatibility::Bifrost;
+        let lexicographic_seqhash = get_hash_static_id(HashType::Auto, 31, false, bifrost);
+        let lexicographic_rkhash = get_hash_static_id(HashType::RabinKarp64, 31, false, bifrost);
+        let forward_rkhash = get_hash_static_id(HashType::RabinKarp64, 31, true, bifrost);
+        assert!(lexicographic_seqhash.is_ok());
+        assert!(lexicographic_rkhash.is_err());
+        // The forward-only kmers are not canonicalized
+        assert!(forward_rkhash.is_ok());
     }
 }
@@ -0,0 +1,1113 @@
+mod common;
+
+use common::{
+    build_single_input_graph, build_single_input_graph_with_options, canonical, example_input,
+    random_sequences, read_canonical_unitigs, read_fasta_sequences, reverse_complement,
+    test_instance, TestDir, K, PIPELINE_LOCK, THREADS_COUNT,
+};
+use ggcat_api::{
+    BuildOptions, CircularFastaStream, CircularInputs, ExtraElaboration, GGCATInstance,
+    GeneralSequenceBlockData, InputStrandMode, StrandedInputsStream,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+
+#[test]
+fn compressed_inputs_give_identical_unitigs() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("compressed_inputs_give_identical_unitigs");
+
+    let plain_input = example_input("sal1.fa");
+    let plain_data = std::fs::read(&plain_input).unwrap();
+
+    let xz_input = dir.path("sal1.fa.xz");
+    let mut xz_encoder = xz2::write::XzEncoder::new(File::create(&xz_input).unwrap(), 6);
+    xz_encoder.write_all(&plain_data).unwrap();
+    xz_encoder.finish().unwrap();
+
+    let bz2_input = dir.path("sal1.fa.bz2");
+    let mut bz2_encoder = bzip2::write::BzEncoder::new(
+        File::create(&bz2_input).unwrap(),
+        bzip2::Compression::default(),
+    );
+    bz2_encoder.write_all(&plain_data).unwrap();
+    bz2_encoder.finish().unwrap();
+
+    let plain_unitigs = read_canonical_unitigs(build_single_input_graph(
+        instance,
+        plain_input,
+        dir.path("plain-graph.fa"),
+    ));
+    assert!(!plain_unitigs.is_empty());
+
+    for (input, output) in [(xz_input, "xz-graph.fa"), (bz2_input, "bz2-graph.fa")] {
+        let unitigs =
+            read_canonical_unitigs(build_single_input_graph(instance, input, dir.path(output)));
+        assert_eq!(unitigs, plain_unitigs, "Mismatch for {}", output);
+    }
+}
+
+// Serves the data over http on a local port, interrupting the first download halfway
+// and answering the following requests from the requested range start
+fn spawn_flaky_http_server(data: Vec<u8>) -> std::net::SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+        let mut interrupted = false;
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = BufReader::new(stream.try_clone().unwrap());
+
+            let mut is_head = false;
+            let mut range_start = 0;
+            loop {
+                let mut line = String::new();
+                request.read_line(&mut line).unwrap();
+                let line = line.trim().to_ascii_lowercase();
+                if line.is_empty() {
+                    break;
+                }
+                is_head |= line.starts_with("head ");
+                if let Some(range) = line.strip_prefix("range: bytes=") {
+                    range_start = range.trim_end_matches('-').parse().unwrap();
+                }
+            }
+
+            let body = &data[range_start..];
+            let status = if range_start > 0 {
+                "206 Partial Content"
+            } else {
+                "200 OK"
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+
+            if !is_head {
+                let sent_size = if interrupted {
+                    body.len()
+                } else {
+                    body.len() / 2
+                };
+                interrupted = true;
+                let _ = stream.write_all(&body[..sent_size]);
+            }
+        }
+    });
+
+    address
+}
+
+#[test]
+fn url_input_gives_identical_unitigs() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("url_input_gives_identical_unitigs");
+
+    let plain_input = example_input("sal1.fa");
+    let plain_data = std::fs::read(&plain_input).unwrap();
+
+    // Compress in two gzip members, as in bgzf files
+    let mut gzip_data = vec![];
+    for part in plain_data.chunks(plain_data.len() / 2 + 1) {
+        let mut encoder =
+            flate2::write::GzEncoder::new(&mut gzip_data, flate2::Compression::default());
+        encoder.write_all(part).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    let address = spawn_flaky_http_server(gzip_data);
+    let url_input = PathBuf::from(format!("http://{}/sal1.fa.gz", address));
+
+    let plain_unitigs = read_canonical_unitigs(build_single_input_graph(
+        instance,
+        plain_input,
+        dir.path("plain-url-graph.fa"),
+    ));
+    let url_unitigs = read_canonical_unitigs(build_single_input_graph(
+        instance,
+        url_input,
+        dir.path("url-graph.fa"),
+    ));
+
+    assert!(!plain_unitigs.is_empty());
+    assert_eq!(url_unitigs, plain_unitigs);
+}
+
+#[test]
+fn target_assembly_excludes_unrelated_input() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("target_assembly_excludes_unrelated_input");
+
+    let mut random_sequence = random_sequences(0x2545F4914F6CDD1D);
+
+    let target_region = random_sequence(300);
+    let unrelated = random_sequence(300);
+
+    let input = dir.path("target-input.fa");
+    writeln!(
+        File::create(&input).unwrap(),
+        ">target_region\n{}\n>unrelated\n{}",
+        target_region,
+        unrelated
+    )
+    .unwrap();
+
+    let target = dir.path("target.fa");
+    writeln!(
+        File::create(&target).unwrap(),
+        ">target\n{}",
+        &target_region[100..160]
+    )
+    .unwrap();
+
+    let unitigs = read_canonical_unitigs(build_single_input_graph_with_options(
+        instance,
+        input.clone(),
+        dir.path("target-graph.fa"),
+        BuildOptions {
+            target_sequences: Some(target.clone()),
+            ..Default::default()
+        },
+    ));
+
+    // The target kmers do not fit in the memory and are searched in a sorted index file
+    let on_disk_unitigs = read_canonical_unitigs(build_single_input_graph_with_options(
+        instance,
+        input,
+        dir.path("target-graph-on-disk.fa"),
+        BuildOptions {
+            target_sequences: Some(target),
+            target_kmers_memory: Some(1024),
+            ..Default::default()
+        },
+    ));
+
+    // The target kmers are extended by exactly one base on each side
+    assert_eq!(unitigs, vec![canonical(&target_region[99..161])]);
+    assert_eq!(on_disk_unitigs, unitigs);
+}
+
+#[test]
+fn distinct_kmers_estimate_is_accurate() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("distinct_kmers_estimate_is_accurate");
+
+    let inputs: Vec<_> = ["sal1.fa", "sal3.fa"]
+        .iter()
+        .map(|f| GeneralSequenceBlockData::FASTA((example_input(f), None)))
+        .collect();
+
+    let estimate = instance.estimate_distinct_kmers(&inputs, K, false, None);
+
+    let graph_file = instance.build_graph(
+        inputs,
+        dir.path("distinct-kmers-graph.fa"),
+        None,
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        false,
+        1,
+        ExtraElaboration::None,
+        BuildOptions::default(),
+    );
+    let exact: usize = read_canonical_unitigs(graph_file)
+        .iter()
+        .map(|u| u.len() - K + 1)
+        .sum();
+
+    let error = (estimate as f64 - exact as f64).abs() / exact as f64;
+    assert!(
+        error < 0.03,
+        "Estimate: {} exact: {} error: {:.2}%",
+        estimate,
+        exact,
+        error * 100.0
+    );
+}
+
+#[test]
+fn single_threaded_build_gives_identical_graph() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("single_threaded_build_gives_identical_graph");
+
+    let graphs: Vec<_> = [
+        (ggcat_api::SingleThreadedMode::Forced, "single-threaded.fa"),
+        (ggcat_api::SingleThreadedMode::Disabled, "multi-threaded.fa"),
+    ]
+    .iter()
+    .map(|(mode, name)| {
+        instance.build_graph(
+            vec![GeneralSequenceBlockData::FASTA((
+                example_input("sal1.fa"),
+                None,
+            ))],
+            dir.path(name),
+            None,
+            K,
+            THREADS_COUNT,
+            false,
+            None,
+            false,
+            1,
+            ExtraElaboration::UnitigLinks,
+            BuildOptions {
+                single_threaded_mode: *mode,
+                deterministic_output: true,
+                random_seed: Some(42),
+                ..Default::default()
+            },
+        )
+    })
+    .collect();
+
+    assert_eq!(
+        std::fs::read(&graphs[0]).unwrap(),
+        std::fs::read(&graphs[1]).unwrap()
+    );
+}
+
+#[test]
+fn unitig_spanning_minimizer_buckets_is_not_fragmented() {
+    use hashes::{cn_nthash::CanonicalNtHashIteratorFactory, MinimizerHashFunctionFactory};
+    use hashes::{ExtendableHashTraitType, HashFunction, HashFunctionFactory};
+
+    const BUCKETS_COUNT_LOG: usize = 8;
+
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("unitig_spanning_minimizer_buckets_is_not_fragmented");
+
+    // A random sequence has no repeated (k-1)-mers, so all its kmers form a single unitig
+    let mut random_sequence = random_sequences(0x9e37_79b9_7f4a_7c15);
+    let sequence = random_sequence(1000);
+
+    // Check that the kmers of the sequence are split between multiple buckets
+    let m = ::utils::compute_best_m(K);
+    let hashes: Vec<_> = CanonicalNtHashIteratorFactory::new(sequence.as_bytes(), m)
+        .iter()
+        .map(|hash| hash.to_unextendable())
+        .collect();
+    let kmers_buckets: BTreeSet<_> = hashes
+        .windows(K - m + 1)
+        .map(|window| {
+            let minimizer = window
+                .iter()
+                .min_by_key(|hash| CanonicalNtHashIteratorFactory::get_full_minimizer(**hash))
+                .unwrap();
+            CanonicalNtHashIteratorFactory::get_bucket(0, BUCKETS_COUNT_LOG, *minimizer)
+        })
+        .collect();
+    assert!(kmers_buckets.len() > 1);
+
+    let input_file = dir.path("minimizer-change-input.fa");
+    std::fs::write(&input_file, format!(">0\n{}\n", sequence)).unwrap();
+
+    *ggcat_api::debug::BUCKETS_COUNT_LOG_FORCE.lock() = Some(BUCKETS_COUNT_LOG);
+    let graph =
+        build_single_input_graph(instance, input_file, dir.path("minimizer-change-graph.fa"));
+    *ggcat_api::debug::BUCKETS_COUNT_LOG_FORCE.lock() = None;
+
+    assert_eq!(read_canonical_unitigs(graph), vec![canonical(&sequence)]);
+}
+
+#[test]
+fn mismatching_join_across_buckets_truncates_the_unitig() {
+    // Restores the debug settings even if the test fails
+    struct DebugReset;
+    impl Drop for DebugReset {
+        fn drop(&mut self) {
+            *ggcat_api::debug::BUCKETS_COUNT_LOG_FORCE.lock() = None;
+            ggcat_api::debug::DEBUG_MISMATCHED_UNITIG_JOINS.store(false, Ordering::Relaxed);
+        }
+    }
+
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("mismatching_join_across_buckets_truncates_the_unitig");
+
+    // The same single unitig spanning multiple buckets of the previous test
+    let mut random_sequence = random_sequences(0x9e37_79b9_7f4a_7c15);
+    let sequence = random_sequence(1000);
+    let input_file = dir.path("mismatching-join-input.fa");
+    std::fs::write(&input_file, format!(">0\n{}\n", sequence)).unwrap();
+
+    let _reset = DebugReset;
+    *ggcat_api::debug::BUCKETS_COUNT_LOG_FORCE.lock() = Some(8);
+    ggcat_api::debug::DEBUG_MISMATCHED_UNITIG_JOINS.store(true, Ordering::Relaxed);
+    let graph =
+        build_single_input_graph(instance, input_file, dir.path("mismatching-join-graph.fa"));
+
+    // The build completes, ending the unitig before the first part that does not match
+    let unitigs = read_canonical_unitigs(graph);
+    assert_eq!(unitigs.len(), 1);
+    assert!(unitigs[0].len() >= K && unitigs[0].len() < sequence.len());
+    assert!(sequence.contains(&unitigs[0]) || reverse_complement(&sequence).contains(&unitigs[0]));
+}
+
+#[test]
+fn kmer_counts_dump_builds_the_same_unitigs_as_the_reads() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("kmer_counts_dump_builds_the_same_unitigs_as_the_reads");
+
+    let mut random_sequence = random_sequences(0x3c6e_f372_fe94_f82b);
+
+    // The first sequence is read twice, so only its kmers pass the multiplicity filter
+    let repeated = random_sequence(300);
+    let single = random_sequence(300);
+    let reads = [&repeated, &repeated, &single];
+
+    let reads_input = dir.path("kmer-counts-dump-reads.fa");
+    std::fs::write(
+        &reads_input,
+        reads
+            .iter()
+            .map(|read| format!(">read\n{}\n", read))
+            .collect::<String>(),
+    )
+    .unwrap();
+
+    let mut kmer_counts: BTreeMap<String, u64> = BTreeMap::new();
+    for read in reads {
+        for start in 0..=read.len() - K {
+            *kmer_counts
+                .entry(canonical(&read[start..start + K]))
+                .or_default() += 1;
+        }
+    }
+    let dump_input = dir.path("kmer-counts-dump.txt");
+    std::fs::write(
+        &dump_input,
+        kmer_counts
+            .iter()
+            .map(|(kmer, count)| format!("{}\t{}\n", kmer, count))
+            .collect::<String>(),
+    )
+    .unwrap();
+
+    let build = |inputs, output_file| {
+        instance.build_graph(
+            inputs,
+            dir.path(output_file),
+            None,
+            K,
+            THREADS_COUNT,
+            false,
+            None,
+            false,
+            2,
+            ExtraElaboration::None,
+            BuildOptions::default(),
+        )
+    };
+
+    let reads_graph = build(
+        vec![GeneralSequenceBlockData::FASTA((reads_input, None))],
+        "kmer-counts-dump-reads-graph.fa",
+    );
+    let dump_graph = build(
+        ggcat_api::KmerCountsDumpStream::new(vec![dump_input.clone()], K)
+            .unwrap()
+            .into_input_blocks(),
+        "kmer-counts-dump-graph.fa",
+    );
+
+    assert!(ggcat_api::KmerCountsDumpStream::new(vec![dump_input], K - 2).is_err());
+
+    assert_eq!(
+        read_canonical_unitigs(&dump_graph),
+        vec![canonical(&repeated)]
+    );
+    assert_eq!(
+        read_canonical_unitigs(&dump_graph),
+        read_canonical_unitigs(&reads_graph)
+    );
+}
+
+#[test]
+fn kmer_counts_dump_keeps_the_kmers_counted_past_the_threshold_with_long_k() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir =
+        TestDir::new("kmer_counts_dump_keeps_the_kmers_counted_past_the_threshold_with_long_k");
+
+    // A k larger than 64 uses a non invertible hash, that rebuilds the kmers from the saved reads
+    const LONG_K: usize = 71;
+
+    let mut random_sequence = random_sequences(0x510e_527f_ade6_82d1);
+    let repeated = random_sequence(400);
+    let single = random_sequence(400);
+
+    // The counts of the repeated kmers reach the threshold with a single record
+    let mut kmer_counts: BTreeMap<String, u64> = BTreeMap::new();
+    for (read, count) in [(&repeated, 5), (&single, 1)] {
+        for start in 0..=read.len() - LONG_K {
+            *kmer_counts
+                .entry(canonical(&read[start..start + LONG_K]))
+                .or_default() += count;
+        }
+    }
+    let dump_input = dir.path("kmer-counts-dump.txt");
+    std::fs::write(
+        &dump_input,
+        kmer_counts
+            .iter()
+            .map(|(kmer, count)| format!("{}\t{}\n", kmer, count))
+            .collect::<String>(),
+    )
+    .unwrap();
+
+    let dump_graph = instance.build_graph(
+        ggcat_api::KmerCountsDumpStream::new(vec![dump_input], LONG_K)
+            .unwrap()
+            .into_input_blocks(),
+        dir.path("kmer-counts-dump-graph.fa"),
+        None,
+        LONG_K,
+        THREADS_COUNT,
+        false,
+        None,
+        false,
+        2,
+        ExtraElaboration::None,
+        BuildOptions::default(),
+    );
+
+    assert_eq!(
+        read_canonical_unitigs(&dump_graph),
+        vec![canonical(&repeated)]
+    );
+}
+
+#[test]
+fn stranded_inputs_honor_the_strand_mode_of_each_file() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("stranded_inputs_honor_the_strand_mode_of_each_file");
+
+    let mut random_sequence = random_sequences(0xa54f_f53a_5f1d_36f1);
+
+    let reference = random_sequence(200);
+    let sample = random_sequence(200);
+
+    let reference_input = dir.path("stranded-inputs-reference.fa");
+    std::fs::write(&reference_input, format!(">reference\n{}\n", reference)).unwrap();
+    let sample_input = dir.path("stranded-inputs-sample.fa");
+    std::fs::write(&sample_input, format!(">sample\n{}\n", sample)).unwrap();
+
+    let other_sample = random_sequence(200);
+    let other_sample_input = dir.path("stranded-inputs-other-sample.fa");
+    std::fs::write(
+        &other_sample_input,
+        format!(">other-sample\n{}\n", other_sample),
+    )
+    .unwrap();
+
+    // The forward-only reference makes the reverse complementary kmers different, while the inputs
+    // without a strand mode are canonical as in the requested build
+    let mut inputs = StrandedInputsStream::new(vec![
+        (reference_input, None, InputStrandMode::ForwardOnly),
+        (sample_input, None, InputStrandMode::Canonical),
+    ])
+    .into_input_blocks();
+    inputs.push(GeneralSequenceBlockData::FASTA((other_sample_input, None)));
+
+    let graph_file = instance.build_graph(
+        inputs,
+        dir.path("stranded-inputs-graph.fa"),
+        None,
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        false,
+        1,
+        ExtraElaboration::None,
+        BuildOptions::default(),
+    );
+
+    // The reference is kept only on its strand, the samples on both of them
+    let mut unitigs = read_fasta_sequences(&graph_file);
+    unitigs.sort_unstable();
+    let mut expected = vec![
+        reference,
+        reverse_complement(&sample),
+        sample,
+        reverse_complement(&other_sample),
+        other_sample,
+    ];
+    expected.sort_unstable();
+    assert_eq!(unitigs, expected);
+}
+
+#[test]
+fn tandem_repeat_kmers_are_counted_once_per_read() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("tandem_repeat_kmers_are_counted_once_per_read");
+
+    let mut random_sequence = random_sequences(0x510e_527f_ade6_82d1);
+
+    // The kmers of the tandem repeat appear multiple times in the same read,
+    // while the ones of the shared sequence appear once in two reads
+    let tandem_repeat = random_sequence(12).repeat(6);
+    let repeat_read = format!(
+        "{}{}{}",
+        random_sequence(100),
+        tandem_repeat,
+        random_sequence(100)
+    );
+    let shared = random_sequence(100);
+    let input = dir.path("tandem-repeat-input.fa");
+    std::fs::write(
+        &input,
+        format!(">0\n{}\n>1\n{}\n>2\n{}\n", repeat_read, shared, shared),
+    )
+    .unwrap();
+
+    let build = |mode, name| {
+        let graph = instance.build_graph(
+            vec![GeneralSequenceBlockData::FASTA((input.clone(), None))],
+            dir.path(name),
+            None,
+            K,
+            THREADS_COUNT,
+            false,
+            None,
+            false,
+            2,
+            ExtraElaboration::None,
+            BuildOptions {
+                kmer_counting_mode: mode,
+                ..Default::default()
+            },
+        );
+        read_canonical_unitigs(graph)
+    };
+
+    let per_occurrence = build(
+        ggcat_api::KmerCountingMode::CountPerOccurrence,
+        "tandem-repeat-per-occurrence.fa",
+    );
+    let per_read = build(
+        ggcat_api::KmerCountingMode::CountPerRead,
+        "tandem-repeat-per-read.fa",
+    );
+
+    assert_eq!(per_read, vec![canonical(&shared)]);
+    assert!(per_occurrence.contains(&canonical(&shared)));
+    assert!(per_occurrence.len() > 1);
+
+    // Only the kmers of the repeat reach the minimum multiplicity counting each occurrence
+    let repeat_kmers: BTreeSet<_> = (0..=(tandem_repeat.len() - K))
+        .map(|i| canonical(&tandem_repeat[i..i + K]))
+        .collect();
+    for unitig in per_occurrence.iter().filter(|u| **u != canonical(&shared)) {
+        for i in 0..=(unitig.len() - K) {
+            assert!(repeat_kmers.contains(&canonical(&unitig[i..i + K])));
+        }
+    }
+}
+
+#[test]
+fn open_files_stay_under_the_limit_with_many_buckets() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("open_files_stay_under_the_limit_with_many_buckets");
+
+    let unlimited_graph = build_single_input_graph(
+        instance,
+        example_input("sal1.fa"),
+        dir.path("open-files-unlimited.fa"),
+    );
+
+    // Even the minimum buckets count does not fit the limit, the bucketing is split in waves
+    const MAX_OPEN_FILES: usize = 256;
+    *ggcat_api::debug::BUCKETS_COUNT_LOG_FORCE.lock() = Some(12);
+    config::PREFER_MEMORY.store(false, Ordering::Relaxed);
+    assert_eq!(
+        io::open_files_limit::max_buckets_count_log_for_open_files(Some(MAX_OPEN_FILES)),
+        Some(config::MIN_BUCKETS_COUNT_LOG)
+    );
+    let waves_count = io::open_files_limit::buckets_waves(
+        1 << config::MIN_BUCKETS_COUNT_LOG,
+        Some(MAX_OPEN_FILES),
+    )
+    .len();
+    io::open_files_limit::BucketingOpenFiles::reset_peak();
+
+    let graph = build_single_input_graph_with_options(
+        instance,
+        example_input("sal1.fa"),
+        dir.path("open-files-limit.fa"),
+        BuildOptions {
+            max_open_files: Some(MAX_OPEN_FILES),
+            ..Default::default()
+        },
+    );
+    let peak_open_files = io::open_files_limit::BucketingOpenFiles::peak();
+
+    config::PREFER_MEMORY.store(true, Ordering::Relaxed);
+    *ggcat_api::debug::BUCKETS_COUNT_LOG_FORCE.lock() = None;
+
+    assert!(waves_count > 1);
+    assert!(
+        peak_open_files > 0 && peak_open_files <= MAX_OPEN_FILES,
+        "{} bucket files open with a limit of {}",
+        peak_open_files,
+        MAX_OPEN_FILES
+    );
+    assert_eq!(
+        read_canonical_unitigs(graph),
+        read_canonical_unitigs(unlimited_graph)
+    );
+}
+
+#[test]
+fn online_build_matches_the_build_of_all_the_reads() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("online_build_matches_the_build_of_all_the_reads");
+
+    let mut state: u64 = 0xcbbb_9d5d_c105_9ed8;
+    let mut next_random = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state >> 32
+    };
+
+    // Overlapping reads sampled from a genome, split in two batches
+    let genome: String = (0..3000)
+        .map(|_| ['A', 'C', 'G', 'T'][next_random() as usize % 4])
+        .collect();
+    let reads: Vec<_> = (0..120)
+        .map(|_| {
+            let start = next_random() as usize % (genome.len() - 150);
+            genome[start..start + 150].to_string()
+        })
+        .collect();
+    let (first_batch, second_batch) = reads.split_at(reads.len() / 2);
+
+    let mut online_builder = ggcat_api::OnlineGraphBuilder::new(
+        instance,
+        dir.path("online-graph.fa"),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        1,
+    )
+    .unwrap();
+    assert_eq!(online_builder.current_graph(), None);
+
+    online_builder.add_batch(first_batch);
+    let first_graph = online_builder.current_graph().unwrap();
+    let first_unitigs = read_canonical_unitigs(&first_graph);
+
+    online_builder.add_batch(second_batch);
+    let online_graph = online_builder.current_graph().unwrap();
+
+    let all_reads = dir.path("online-all-reads.fa");
+    std::fs::write(
+        &all_reads,
+        reads
+            .iter()
+            .map(|read| format!(">read\n{}\n", read))
+            .collect::<String>(),
+    )
+    .unwrap();
+    let single_graph =
+        build_single_input_graph(instance, all_reads, dir.path("online-single-graph.fa"));
+
+    assert_ne!(first_unitigs, read_canonical_unitigs(&online_graph));
+    assert_eq!(
+        read_canonical_unitigs(&online_graph),
+        read_canonical_unitigs(&single_graph)
+    );
+}
+
+#[test]
+#[cfg(feature = "kmer-counters")]
+fn online_build_counts_the_kmers_over_all_the_batches() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("online_build_counts_the_kmers_over_all_the_batches");
+
+    let mut random_sequence = random_sequences(0x1f83_d9ab_fb41_bd6b);
+
+    // The repeated read reaches the minimum multiplicity in the first batch,
+    // the split one only when its second copy arrives with the second batch
+    let repeated = random_sequence(200);
+    let split = random_sequence(200);
+    let singleton = random_sequence(200);
+
+    let mut online_builder = ggcat_api::OnlineGraphBuilder::new(
+        instance,
+        dir.path("online-counted-graph.fa"),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        2,
+    )
+    .unwrap();
+
+    online_builder.add_batch([&repeated, &repeated, &split]);
+    let first_graph = online_builder.current_graph().unwrap();
+    assert_eq!(
+        read_canonical_unitigs(&first_graph),
+        vec![canonical(&repeated)]
+    );
+
+    online_builder.add_batch([&split, &singleton]);
+    let online_graph = online_builder.current_graph().unwrap();
+    let mut expected = vec![canonical(&repeated), canonical(&split)];
+    expected.sort();
+    assert_eq!(read_canonical_unitigs(&online_graph), expected);
+}
+
+#[test]
+fn single_read_links_are_pruned_by_the_min_link_coverage() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("single_read_links_are_pruned_by_the_min_link_coverage");
+
+    let mut random_sequence = random_sequences(0x3c6e_f372_fe94_f82b);
+
+    // The second sequence starts with the last k - 1 bases of the first one, each sequence is
+    // read twice and a single spurious read spans the junction between them
+    let first = random_sequence(200);
+    let second = format!(
+        "{}{}",
+        &first[first.len() - (K - 1)..],
+        random_sequence(170)
+    );
+    let junction = format!("{}{}", &first[first.len() - K..], &second[K - 1..K]);
+
+    let input = dir.path("link-coverage-input.fa");
+    std::fs::write(
+        &input,
+        format!(
+            ">a\n{0}\n>a\n{0}\n>b\n{1}\n>b\n{1}\n>junction\n{2}\n",
+            first, second, junction
+        ),
+    )
+    .unwrap();
+
+    let pruned_graph = build_single_input_graph_with_options(
+        instance,
+        input.clone(),
+        dir.path("link-coverage-2.fa"),
+        BuildOptions {
+            min_link_coverage: 2,
+            ..Default::default()
+        },
+    );
+    let joined_graph = build_single_input_graph(instance, input, dir.path("link-coverage-1.fa"));
+
+    let mut separate_unitigs = vec![canonical(&first), canonical(&second)];
+    separate_unitigs.sort_unstable();
+    assert_eq!(read_canonical_unitigs(&pruned_graph), separate_unitigs);
+    assert_eq!(
+        read_canonical_unitigs(&joined_graph),
+        vec![canonical(&format!("{}{}", first, &second[K - 1..]))]
+    );
+}
+
+#[test]
+fn links_read_on_both_strands_across_the_buckets_reach_the_min_link_coverage() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir =
+        TestDir::new("links_read_on_both_strands_across_the_buckets_reach_the_min_link_coverage");
+
+    let mut random_sequence = random_sequences(0x510e_527f_ade6_82d1);
+
+    // Each link of the sequence is covered by two reads on opposite strands, the links between
+    // its super-kmers cross the buckets
+    let sequence = random_sequence(2000);
+    let input = dir.path("link-coverage-strands-input.fa");
+    std::fs::write(
+        &input,
+        format!(
+            ">forward\n{}\n>reverse\n{}\n",
+            sequence,
+            reverse_complement(&sequence)
+        ),
+    )
+    .unwrap();
+
+    let graph = build_single_input_graph_with_options(
+        instance,
+        input,
+        dir.path("link-coverage-strands.fa"),
+        BuildOptions {
+            min_link_coverage: 2,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(read_canonical_unitigs(&graph), vec![canonical(&sequence)]);
+}
+
+#[test]
+fn circular_inputs_include_the_kmers_spanning_the_origin() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("circular_inputs_include_the_kmers_spanning_the_origin");
+
+    let mut random_sequence = random_sequences(0xa54f_f53a_5f1d_36f1);
+    let sequence = random_sequence(300);
+    let origin_kmer = canonical(&format!(
+        "{}{}",
+        &sequence[sequence.len() - K / 2..],
+        &sequence[..K - K / 2]
+    ));
+
+    let input = dir.path("circular-input.fa");
+    std::fs::write(&input, format!(">plasmid circular=true\n{}\n", sequence)).unwrap();
+
+    let graph_kmers = |graph: PathBuf| -> BTreeSet<String> {
+        read_canonical_unitigs(graph)
+            .iter()
+            .flat_map(|unitig| {
+                (0..=unitig.len() - K)
+                    .map(|i| canonical(&unitig[i..i + K]))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    };
+
+    let circular_graph = instance.build_graph(
+        CircularFastaStream::new(vec![(input.clone(), None)], CircularInputs::HeaderTag)
+            .into_input_blocks(),
+        dir.path("circular-graph.fa"),
+        None,
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        false,
+        1,
+        ExtraElaboration::None,
+        BuildOptions::default(),
+    );
+    let linear_graph =
+        build_single_input_graph(instance, input.clone(), dir.path("linear-graph.fa"));
+
+    // A circular sequence has a kmer starting at each of its bases
+    let circular_kmers = graph_kmers(circular_graph);
+    assert_eq!(circular_kmers.len(), sequence.len());
+    assert!(circular_kmers.contains(&origin_kmer));
+
+    let linear_kmers = graph_kmers(linear_graph);
+    assert_eq!(linear_kmers.len(), sequence.len() - K + 1);
+    assert!(!linear_kmers.contains(&origin_kmer));
+
+    // The other readers of the same file, as the queries, read the record as linear
+    let mut query_kmers = 0;
+    io::sequences_reader::SequencesReader::new().process_file_extended(
+        &input,
+        |record| query_kmers += record.seq.len() - K + 1,
+        Some(K - 1),
+        false,
+        false,
+    );
+    assert_eq!(query_kmers, sequence.len() - K + 1);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn io_and_compute_threads_counts_are_honored() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("io_and_compute_threads_counts_are_honored");
+
+    // The reading and compute pools of the reads bucketing and of the kmers merge, by thread name
+    const POOLS: [&str; 4] = ["mm_disk", "mm_comp", "km_disk", "km_comp"];
+
+    // Samples the threads of the process during the build, keeping the largest size of each pool
+    let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let observer = std::thread::spawn({
+        let done = done.clone();
+        move || {
+            let mut pools_sizes = [0; POOLS.len()];
+            while !done.load(Ordering::Relaxed) {
+                let mut threads = [0; POOLS.len()];
+                for task in std::fs::read_dir("/proc/self/task").unwrap().flatten() {
+                    let Ok(name) = std::fs::read_to_string(task.path().join("comm")) else {
+                        continue;
+                    };
+                    if let Some(pool) = POOLS.iter().position(|pool| name.starts_with(pool)) {
+                        threads[pool] += 1;
+                    }
+                }
+                for (pool_size, threads) in pools_sizes.iter_mut().zip(threads) {
+                    *pool_size = (*pool_size).max(threads);
+                }
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            pools_sizes
+        }
+    });
+
+    let graph = build_single_input_graph_with_options(
+        instance,
+        example_input("sal1.fa"),
+        dir.path("threads-split.fa"),
+        BuildOptions {
+            io_threads_count: Some(4),
+            compute_threads_count: Some(3),
+            ..Default::default()
+        },
+    );
+
+    done.store(true, Ordering::Relaxed);
+    let pools_sizes = observer.join().unwrap();
+
+    assert!(!read_canonical_unitigs(graph).is_empty());
+    // One of the 4 I/O threads flushes the buckets, the other 3 read the files of each step
+    assert_eq!(pools_sizes, [3, 3, 3, 3]);
+}
+
+#[test]
+fn merge_hashmap_load_factor_does_not_change_the_unitigs() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("merge_hashmap_load_factor_does_not_change_the_unitigs");
+
+    let default_graph = build_single_input_graph(
+        instance,
+        example_input("sal1.fa"),
+        dir.path("load-factor-default.fa"),
+    );
+
+    let sparse_graph = build_single_input_graph_with_options(
+        instance,
+        example_input("sal1.fa"),
+        dir.path("load-factor-sparse.fa"),
+        BuildOptions {
+            merge_hashmap_load_factor: 0.2,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        read_canonical_unitigs(default_graph),
+        read_canonical_unitigs(sparse_graph)
+    );
+}
+
+#[test]
+fn strand_specific_transcripts_keep_their_orientation() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("strand_specific_transcripts_keep_their_orientation");
+
+    let mut random_sequence = random_sequences(0x3c6e_f372_fe94_f82b);
+    let sense = random_sequence(300);
+    let antisense = reverse_complement(&sense);
+
+    let input = dir.path("strand-specific-transcripts.fa");
+    std::fs::write(
+        &input,
+        format!(">sense\n{}\n>antisense\n{}\n", sense, antisense),
+    )
+    .unwrap();
+
+    let build = |strand_specific, output_file| {
+        instance.build_graph(
+            vec![GeneralSequenceBlockData::FASTA((input.clone(), None))],
+            dir.path(output_file),
+            None,
+            K,
+            THREADS_COUNT,
+            false,
+            None,
+            false,
+            1,
+            ExtraElaboration::None,
+            BuildOptions {
+                strand_specific,
+                ..Default::default()
+            },
+        )
+    };
+
+    // The two transcripts are distinct unitigs, each one in the orientation of its read,
+    // even if the build is not requested as forward-only
+    let mut unitigs = read_fasta_sequences(build(true, "strand-specific-graph.fa"));
+    let matchtigs = instance.try_build_graph(
+        vec![GeneralSequenceBlockData::FASTA((input.clone(), None))],
+        dir.path("strand-specific-matchtigs.fa"),
+        None,
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        false,
+        1,
+        ExtraElaboration::GreedyMatchtigs,
+        BuildOptions {
+            strand_specific: true,
+            ..Default::default()
+        },
+    );
+
+    unitigs.sort_unstable();
+    let mut expected = vec![sense.clone(), antisense];
+    expected.sort_unstable();
+    assert_eq!(unitigs, expected);
+    assert!(matchtigs.is_err());
+
+    // Without the strand the transcripts are merged
+    let canonical_unitigs = read_canonical_unitigs(build(false, "strand-merged-graph.fa"));
+    assert_eq!(canonical_unitigs, vec![canonical(&sense)]);
+}
+
+#[test]
+fn minimizer_bucketing_sub_phases_fit_in_the_phase_time() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("minimizer_bucketing_sub_phases_fit_in_the_phase_time");
+
+    // With a single compute thread the sub-phases of the bucketing run one after the other
+    let graph = build_single_input_graph_with_options(
+        instance,
+        example_input("sal1.fa"),
+        dir.path("subphase-timing.fa"),
+        BuildOptions {
+            compute_threads_count: Some(1),
+            subphase_timing: true,
+            ..Default::default()
+        },
+    );
+
+    assert!(!read_canonical_unitigs(graph).is_empty());
+
+    let sub_times = ggcat_api::get_phases_sub_times();
+    let bucketing = sub_times
+        .iter()
+        .find(|sub_times| sub_times.phase == "phase: reads bucketing")
+        .unwrap_or_else(|| panic!("{:?}", sub_times));
+
+    for (sub_phase, time) in &bucketing.sub_phases {
+        assert!(!time.is_zero(), "{:?}: {:?}", sub_phase, bucketing);
+    }
+    assert!(bucketing.total() <= bucketing.wall_time, "{:?}", bucketing);
+}
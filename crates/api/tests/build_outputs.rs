@@ -0,0 +1,510 @@
+mod common;
+
+use common::{
+    build_single_input_graph, build_single_input_graph_with_options, canonical, example_input,
+    random_sequences, read_canonical_unitigs, read_fasta_sequences, test_instance, TestDir, K,
+    PIPELINE_LOCK, THREADS_COUNT,
+};
+use ggcat_api::{BuildOptions, ExtraElaboration, GGCATInstance, GeneralSequenceBlockData};
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+
+#[test]
+fn identical_runs_have_identical_reproducibility_hash() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("identical_runs_have_identical_reproducibility_hash");
+
+    let hashes: Vec<_> = ["repro-first.fa", "repro-second.fa"]
+        .iter()
+        .map(|name| {
+            let graph =
+                build_single_input_graph(instance, example_input("sal1.fa"), dir.path(name));
+            let hash_file = GGCATInstance::write_reproducibility_hash(&graph);
+            std::fs::read_to_string(hash_file).unwrap()
+        })
+        .collect();
+
+    assert_eq!(hashes[0], hashes[1]);
+    assert_eq!(
+        u64::from_str_radix(hashes[0].trim(), 16).unwrap(),
+        GGCATInstance::compute_reproducibility_hash(dir.path("repro-first.fa"))
+    );
+}
+
+// Writer that records the arrival time of each chunk of output
+#[derive(Clone)]
+struct TimestampingWriter(Arc<Mutex<Vec<(Instant, Vec<u8>)>>>);
+
+impl Write for TimestampingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().push((Instant::now(), buf.to_vec()));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn streamed_unitigs_are_received_during_build() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("streamed_unitigs_are_received_during_build");
+
+    let file_unitigs = read_canonical_unitigs(build_single_input_graph(
+        instance,
+        example_input("sal1.fa"),
+        dir.path("streaming-file.fa"),
+    ));
+
+    let sink = TimestampingWriter(Arc::new(Mutex::new(vec![])));
+    build_single_input_graph_with_options(
+        instance,
+        example_input("sal1.fa"),
+        dir.path("streaming-sink.fa"),
+        BuildOptions {
+            output_sink: Some(Box::new(sink.clone())),
+            ..Default::default()
+        },
+    );
+    let build_end = Instant::now();
+
+    let chunks = std::mem::take(&mut *sink.0.lock());
+
+    // The unitigs are received in multiple batches, each one made of complete records
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|(_, chunk)| chunk.ends_with(b"\n")));
+    assert!(chunks.windows(2).all(|w| w[0].0 <= w[1].0));
+    assert!(chunks[0].0 < build_end);
+
+    let output: Vec<u8> = chunks.into_iter().flat_map(|(_, chunk)| chunk).collect();
+    let mut streamed_unitigs: Vec<_> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .filter(|line| !line.starts_with('>') && !line.is_empty())
+        .map(canonical)
+        .collect();
+    streamed_unitigs.sort_unstable();
+
+    assert_eq!(streamed_unitigs, file_unitigs);
+}
+
+#[test]
+fn streamed_unitigs_with_links_are_checked_before_the_sink() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("streamed_unitigs_with_links_are_checked_before_the_sink");
+
+    let output_file = dir.path("streaming-links-sink.fa");
+
+    let sink = TimestampingWriter(Arc::new(Mutex::new(vec![])));
+    instance.build_graph(
+        vec![GeneralSequenceBlockData::FASTA((
+            example_input("sal1.fa"),
+            None,
+        ))],
+        output_file.clone(),
+        None,
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        false,
+        1,
+        ExtraElaboration::ConnectedComponents(ggcat_api::ComponentsOutputMode::HeaderTag),
+        BuildOptions {
+            output_sink: Some(Box::new(sink.clone())),
+            orphan_links_check: ggcat_api::OrphanLinksCheck::Report,
+            ..Default::default()
+        },
+    );
+
+    // Only the graph with links is written, to the sink
+    assert!(!output_file.exists());
+
+    let output: Vec<u8> = std::mem::take(&mut *sink.0.lock())
+        .into_iter()
+        .flat_map(|(_, chunk)| chunk)
+        .collect();
+    let output = String::from_utf8(output).unwrap();
+    let headers: Vec<_> = output.lines().filter(|l| l.starts_with('>')).collect();
+    assert!(!headers.is_empty());
+    assert!(headers.iter().any(|h| h.contains(" L:")));
+    assert!(headers.iter().all(|h| h.contains(" component=")));
+
+    // The links received by the sink are the ones checked by the orphan links check
+    let sink_graph = dir.path("streaming-links-sink-output.fa");
+    std::fs::write(&sink_graph, &output).unwrap();
+    assert!(ggcat_api::find_orphan_links(&sink_graph).is_empty());
+}
+
+#[test]
+fn build_options_do_not_leak_into_the_next_build() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("build_options_do_not_leak_into_the_next_build");
+
+    let anchors_graph = build_single_input_graph_with_options(
+        instance,
+        example_input("sal1.fa"),
+        dir.path("options-anchors.fa"),
+        BuildOptions {
+            unitig_anchors_output: true,
+            ..Default::default()
+        },
+    );
+    assert!(ggcat_api::get_unitig_anchors_file(&anchors_graph).exists());
+
+    let default_graph = build_single_input_graph(
+        instance,
+        example_input("sal1.fa"),
+        dir.path("options-default.fa"),
+    );
+    assert!(!ggcat_api::get_unitig_anchors_file(&default_graph).exists());
+}
+
+#[test]
+fn mmap_output_is_read_while_it_is_written() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("mmap_output_is_read_while_it_is_written");
+
+    let file_unitigs = read_canonical_unitigs(build_single_input_graph(
+        instance,
+        example_input("sal1.fa"),
+        dir.path("mmap-file.fa"),
+    ));
+
+    let mmap_file = dir.path("mmap-output.bin");
+    let mmap_writer = ggcat_api::MmapSinkWriter::create(&mmap_file).unwrap();
+
+    let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let reader_thread = {
+        let mmap_file = mmap_file.clone();
+        let done = done.clone();
+        std::thread::spawn(move || {
+            let mut reader = ggcat_api::MmapSinkReader::open(&mmap_file).unwrap();
+            let mut output = vec![];
+            loop {
+                let finished = done.load(Ordering::Acquire);
+                let data = reader.read_committed().unwrap();
+                // Only complete records are committed
+                assert!(data.is_empty() || data.ends_with(b"\n"));
+                output.extend_from_slice(data);
+                if finished {
+                    break output;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        })
+    };
+
+    build_single_input_graph_with_options(
+        instance,
+        example_input("sal1.fa"),
+        dir.path("mmap-sink.fa"),
+        BuildOptions {
+            output_sink: Some(Box::new(mmap_writer)),
+            ..Default::default()
+        },
+    );
+    done.store(true, Ordering::Release);
+
+    let output = reader_thread.join().unwrap();
+    let mut mmap_unitigs: Vec<_> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .filter(|line| !line.starts_with('>') && !line.is_empty())
+        .map(canonical)
+        .collect();
+    mmap_unitigs.sort_unstable();
+
+    assert_eq!(mmap_unitigs, file_unitigs);
+}
+
+#[test]
+fn unused_reads_are_written_with_their_reason() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("unused_reads_are_written_with_their_reason");
+
+    let mut random_sequence = random_sequences(0x6a09_e667_f3bc_c908);
+
+    // The repeated read passes the minimum multiplicity, the singleton one does not
+    let repeated = random_sequence(200);
+    let input = dir.path("unused-reads-input.fa");
+    std::fs::write(
+        &input,
+        format!(
+            ">repeated1\n{}\n>short\nACGTACGTAC\n>repeated2\n{}\n>singleton\n{}\n>ambiguous\n{}\n",
+            repeated,
+            repeated,
+            random_sequence(200),
+            "N".repeat(100)
+        ),
+    )
+    .unwrap();
+
+    let unused_file = dir.path("unused-reads.fa");
+    instance.build_graph(
+        vec![GeneralSequenceBlockData::FASTA((input, None))],
+        dir.path("unused-reads-graph.fa"),
+        None,
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        false,
+        2,
+        ExtraElaboration::None,
+        BuildOptions {
+            unused_reads_output: Some(unused_file.clone()),
+            ..Default::default()
+        },
+    );
+
+    let reasons: BTreeMap<String, String> = BufReader::new(File::open(unused_file).unwrap())
+        .lines()
+        .map(|line| line.unwrap())
+        .filter_map(|line| {
+            let header = line.strip_prefix('>')?.to_string();
+            let (name, reason) = header.split_once(" reason:")?;
+            Some((name.to_string(), reason.to_string()))
+        })
+        .collect();
+
+    assert_eq!(
+        reasons,
+        BTreeMap::from([
+            ("ambiguous".to_string(), "all_n".to_string()),
+            ("short".to_string(), "too_short".to_string()),
+            ("singleton".to_string(), "low_coverage".to_string()),
+        ])
+    );
+}
+
+#[test]
+#[cfg(feature = "kmer-counters")]
+fn low_coverage_window_is_written_to_the_bed() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("low_coverage_window_is_written_to_the_bed");
+
+    let mut random_sequence = random_sequences(0xa54f_f53a_5f1d_36f1);
+    let sequence = random_sequence(300);
+
+    // The whole sequence is read once, and its sides twice more, leaving the bases 150..200
+    // covered only by the kmers of the single full read
+    let input = dir.path("low-coverage-input.fa");
+    std::fs::write(
+        &input,
+        format!(
+            ">full\n{}\n>left1\n{}\n>left2\n{}\n>right1\n{}\n>right2\n{}\n",
+            sequence,
+            &sequence[..150],
+            &sequence[..150],
+            &sequence[200..],
+            &sequence[200..]
+        ),
+    )
+    .unwrap();
+
+    // The low coverage stretches are written while building the graph, from its kmer counts
+    let graph = build_single_input_graph_with_options(
+        instance,
+        input,
+        dir.path("low-coverage.fa"),
+        BuildOptions {
+            low_coverage_bed: Some(2),
+            ..Default::default()
+        },
+    );
+    assert_eq!(read_canonical_unitigs(&graph), vec![canonical(&sequence)]);
+    let unitig_id = BufReader::new(File::open(&graph).unwrap())
+        .lines()
+        .next()
+        .unwrap()
+        .unwrap()[1..]
+        .split(' ')
+        .next()
+        .unwrap()
+        .to_string();
+
+    let bed_file = ggcat_api::get_low_coverage_bed_file(&graph);
+
+    // The unitig can be written on any strand
+    let unitig = read_fasta_sequences(&graph).remove(0);
+    let (start, end) = if unitig == sequence {
+        (150, 200)
+    } else {
+        (100, 150)
+    };
+    assert_eq!(
+        std::fs::read_to_string(&bed_file).unwrap(),
+        format!("{}\t{}\t{}\n", unitig_id, start, end)
+    );
+}
+
+#[test]
+fn unitig_anchors_are_hashed_with_the_kmers_hash_of_the_build() {
+    use hashes::cn_seqhash::u64::CanonicalSeqHashFactory;
+    use hashes::fw_seqhash::u64::ForwardSeqHashFactory;
+    use io::concurrent::structured_sequences::unitig_anchors::unitig_anchor_hashes;
+
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("unitig_anchors_are_hashed_with_the_kmers_hash_of_the_build");
+
+    let build = |forward_only, output_file| {
+        let graph = instance.build_graph(
+            vec![GeneralSequenceBlockData::FASTA((
+                example_input("sal1.fa"),
+                None,
+            ))],
+            dir.path(output_file),
+            None,
+            K,
+            THREADS_COUNT,
+            forward_only,
+            None,
+            false,
+            1,
+            ExtraElaboration::None,
+            BuildOptions {
+                unitig_anchors_output: true,
+                ..Default::default()
+            },
+        );
+
+        // The unitig ids of the graph, in the order of the anchors
+        let unitig_ids: Vec<String> = BufReader::new(File::open(&graph).unwrap())
+            .lines()
+            .map(|line| line.unwrap())
+            .filter(|line| line.starts_with('>'))
+            .map(|line| line[1..].split(' ').next().unwrap().to_string())
+            .collect();
+        let anchors = std::fs::read_to_string(ggcat_api::get_unitig_anchors_file(&graph)).unwrap();
+        (
+            read_fasta_sequences(&graph),
+            unitig_ids,
+            anchors
+                .lines()
+                .skip(1)
+                .map(|line| line.to_string())
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    let (unitigs, unitig_ids, anchors) = build(false, "anchors-canonical.fa");
+    assert_eq!(anchors.len(), unitigs.len());
+    for ((unitig, unitig_id), anchor) in unitigs.iter().zip(&unitig_ids).zip(&anchors) {
+        let (first, last) =
+            unitig_anchor_hashes::<CanonicalSeqHashFactory>(unitig.as_bytes(), K).unwrap();
+        assert_eq!(*anchor, format!("{}\t{}\t{}", unitig_id, first, last));
+    }
+
+    let (unitigs, unitig_ids, anchors) = build(true, "anchors-forward.fa");
+    assert_eq!(anchors.len(), unitigs.len());
+    for ((unitig, unitig_id), anchor) in unitigs.iter().zip(&unitig_ids).zip(&anchors) {
+        let (first, last) =
+            unitig_anchor_hashes::<ForwardSeqHashFactory>(unitig.as_bytes(), K).unwrap();
+        assert_eq!(*anchor, format!("{}\t{}\t{}", unitig_id, first, last));
+    }
+}
+
+#[test]
+#[cfg(feature = "kmer-counters")]
+fn coverage_track_follows_the_reference_kmers_counts() {
+    use io::concurrent::structured_sequences::kmer_counts_sidecar::get_kmer_counts_file;
+
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("coverage_track_follows_the_reference_kmers_counts");
+
+    let mut random_sequence = random_sequences(0x9b05_688c_2b3e_6c1f);
+    let genome = random_sequence(300);
+    let novel = random_sequence(40);
+
+    // The genome is read twice, and the bases 50..150 once more
+    let input = dir.path("coverage-track-input.fa");
+    std::fs::write(
+        &input,
+        format!(
+            ">read0\n{0}\n>read1\n{0}\n>read2\n{1}\n",
+            genome,
+            &genome[50..150]
+        ),
+    )
+    .unwrap();
+
+    let graph = build_single_input_graph_with_options(
+        instance,
+        input,
+        dir.path("coverage-track.fa"),
+        BuildOptions {
+            kmer_counts_output: true,
+            ..Default::default()
+        },
+    );
+
+    // The reference ends with bases that are not in the graph
+    let reference = dir.path("coverage-track-reference.fa");
+    std::fs::write(
+        &reference,
+        format!(">chr1 test\n{}{}\n", &genome[..200], novel),
+    )
+    .unwrap();
+
+    let track_file = dir.path("coverage-track.bedgraph");
+    let intervals_count = instance
+        .write_coverage_bedgraph(graph.clone(), &reference, &track_file, K)
+        .unwrap();
+
+    let track = std::fs::read_to_string(&track_file).unwrap();
+    let mut lines = track.lines();
+    assert!(lines.next().unwrap().starts_with("track type=bedGraph"));
+    let intervals: Vec<(u64, u64, u64)> = lines
+        .map(|line| {
+            let fields: Vec<_> = line.split('\t').collect();
+            assert_eq!(fields[0], "chr1");
+            (
+                fields[1].parse().unwrap(),
+                fields[2].parse().unwrap(),
+                fields[3].parse().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(intervals.len(), intervals_count);
+    assert_eq!(intervals.first().unwrap().0, 0);
+    assert_eq!(intervals.last().unwrap().1, 240);
+
+    let coverage_at = |position: u64| {
+        intervals
+            .iter()
+            .find(|(start, end, _)| (*start..*end).contains(&position))
+            .unwrap()
+            .2
+    };
+    assert_eq!(coverage_at(0), 2);
+    assert_eq!(coverage_at(49), 2);
+    assert_eq!(coverage_at(50), 3);
+    assert_eq!(coverage_at(119), 3);
+    assert_eq!(coverage_at(120), 2);
+    assert_eq!(coverage_at(169), 2);
+    // The kmers reaching the novel bases and the last positions without a kmer
+    assert_eq!(coverage_at(170), 0);
+    assert_eq!(coverage_at(239), 0);
+
+    // Without the kmer counts of the build there is no coverage to project
+    std::fs::remove_file(get_kmer_counts_file(&graph)).unwrap();
+    assert!(instance
+        .write_coverage_bedgraph(graph, &reference, &track_file, K)
+        .is_err());
+}
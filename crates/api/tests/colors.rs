@@ -0,0 +1,968 @@
+mod common;
+
+use common::{
+    canonical, example_input, random_sequences, read_canonical_unitigs, read_fasta_sequences,
+    test_instance, TestDir, K, PIPELINE_LOCK, THREADS_COUNT,
+};
+use ggcat_api::{
+    BuildOptions, ColorIndexType, ExtraElaboration, GGCATInstance, GeneralSequenceBlockData,
+};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::Write;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+
+#[test]
+fn unitigs_by_color_contain_exactly_the_color_unitigs() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("unitigs_by_color_contain_exactly_the_color_unitigs");
+
+    // The second sample shares a part of the first one, so that some unitigs have both colors
+    let first_sample = example_input("sal1.fa");
+    let second_sample = dir.path("unitigs-by-color-second-sample.fa");
+    {
+        let mut second = File::create(&second_sample).unwrap();
+        second
+            .write_all(&std::fs::read(example_input("sal3.fa")).unwrap())
+            .unwrap();
+        let first_sequence = read_fasta_sequences(&first_sample)
+            .into_iter()
+            .max_by_key(|seq| seq.len())
+            .unwrap();
+        writeln!(second, ">shared\n{}", &first_sequence[1000..3000]).unwrap();
+    }
+
+    let graph = instance.build_graph(
+        vec![
+            GeneralSequenceBlockData::FASTA((first_sample.clone(), None)),
+            GeneralSequenceBlockData::FASTA((second_sample.clone(), None)),
+        ],
+        dir.path("unitigs-by-color.fa"),
+        Some(&["first".to_string(), "second".to_string()]),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        1,
+        ExtraElaboration::None,
+        BuildOptions::default(),
+    );
+
+    let color_files =
+        GGCATInstance::write_unitigs_by_color(&graph, dir.path("unitigs-by-color-output"), None);
+    assert_eq!(color_files.len(), 2);
+
+    let graph_unitigs = read_fasta_sequences(&graph);
+    let mut written_unitigs = 0;
+
+    for (sample, color_file) in [first_sample, second_sample].iter().zip(&color_files) {
+        let sample_kmers: BTreeSet<String> = read_fasta_sequences(sample)
+            .iter()
+            .filter(|seq| seq.len() >= K)
+            .flat_map(|seq| (0..=seq.len() - K).map(move |i| canonical(&seq[i..i + K])))
+            .collect();
+
+        // A unitig has a color if any of its kmers is in the sample of that color
+        let mut expected_unitigs: Vec<_> = graph_unitigs
+            .iter()
+            .filter(|unitig| {
+                (0..=unitig.len() - K).any(|i| sample_kmers.contains(&canonical(&unitig[i..i + K])))
+            })
+            .map(|unitig| canonical(unitig))
+            .collect();
+        expected_unitigs.sort_unstable();
+
+        let color_unitigs = read_canonical_unitigs(color_file);
+        assert!(!color_unitigs.is_empty());
+        assert!(color_unitigs.len() < graph_unitigs.len());
+        assert_eq!(color_unitigs, expected_unitigs);
+        written_unitigs += color_unitigs.len();
+    }
+
+    // The unitigs of the shared region are written to both files
+    assert!(written_unitigs > graph_unitigs.len());
+
+    // With a single open file the colors are written one at a time, with the same unitigs
+    let limited_files = GGCATInstance::write_unitigs_by_color(
+        &graph,
+        dir.path("unitigs-by-color-limited-output"),
+        Some(1),
+    );
+    for (limited_file, color_file) in limited_files.iter().zip(&color_files) {
+        assert_eq!(
+            std::fs::read(limited_file).unwrap(),
+            std::fs::read(color_file).unwrap()
+        );
+    }
+}
+
+#[test]
+#[cfg(feature = "parquet")]
+fn unitigs_parquet_table_lists_the_colors_of_each_unitig() {
+    use crate::common::build_example_graph;
+    use arrow_array::{Array, ListArray, UInt32Array, UInt64Array};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::io::{BufRead, BufReader};
+
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("unitigs_parquet_table_lists_the_colors_of_each_unitig");
+    let graph = build_example_graph(instance, dir.path("unitigs-table.fa"));
+
+    let table_file = dir.path("unitigs-table.unitigs.parquet");
+    let rows_count = GGCATInstance::write_unitigs_parquet(&graph, &table_file, true);
+    assert_eq!(rows_count as usize, read_fasta_sequences(&graph).len());
+
+    // The unitigs of each color in the table, by id
+    let mut table_colors_unitigs = vec![BTreeSet::new(); 3];
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&table_file).unwrap())
+        .unwrap()
+        .build()
+        .unwrap();
+    for batch in reader {
+        let batch = batch.unwrap();
+        let ids = batch.column_by_name("id").unwrap();
+        let ids = ids.as_any().downcast_ref::<UInt64Array>().unwrap();
+        let colors = batch.column_by_name("colors").unwrap();
+        let colors = colors.as_any().downcast_ref::<ListArray>().unwrap();
+        for row in 0..batch.num_rows() {
+            let unitig_colors = colors.value(row);
+            let unitig_colors = unitig_colors
+                .as_any()
+                .downcast_ref::<UInt32Array>()
+                .unwrap();
+            assert!(!unitig_colors.is_empty());
+            for color in unitig_colors.values() {
+                table_colors_unitigs[*color as usize].insert(ids.value(row));
+            }
+        }
+    }
+
+    // The same unitigs are written to the file of each color
+    let color_files =
+        GGCATInstance::write_unitigs_by_color(&graph, dir.path("unitigs-table-colors"), None);
+    for (color_file, table_unitigs) in color_files.iter().zip(&table_colors_unitigs) {
+        let file_unitigs: BTreeSet<u64> = BufReader::new(File::open(color_file).unwrap())
+            .lines()
+            .map(|line| line.unwrap())
+            .filter_map(|line| {
+                line.strip_prefix('>')?
+                    .split_whitespace()
+                    .next()?
+                    .parse()
+                    .ok()
+            })
+            .collect();
+        assert!(!file_unitigs.is_empty());
+        assert_eq!(&file_unitigs, table_unitigs);
+    }
+}
+
+#[test]
+fn kmers_in_all_colors_become_ubiquitous() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("kmers_in_all_colors_become_ubiquitous");
+
+    let mut random_sequence = random_sequences(0x6a09_e667_f3bc_c908);
+
+    // Each sample has its own sequence, and all of them share a common one
+    let shared = random_sequence(200);
+    let inputs: Vec<_> = (0..3)
+        .map(|i| {
+            let own = random_sequence(200);
+            let input = dir.path(&format!("ubiquitous-input-{}.fa", i));
+            std::fs::write(&input, format!(">own\n{}\n>shared\n{}\n", own, shared)).unwrap();
+            (input, own)
+        })
+        .collect();
+
+    let graph = instance.build_graph(
+        inputs
+            .iter()
+            .map(|(input, _)| GeneralSequenceBlockData::FASTA((input.clone(), None)))
+            .collect(),
+        dir.path("ubiquitous-graph.fa"),
+        Some(&["s0".to_string(), "s1".to_string(), "s2".to_string()]),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        1,
+        ExtraElaboration::None,
+        BuildOptions {
+            max_kmer_colors: Some(2),
+            ..Default::default()
+        },
+    );
+
+    let color_names: Vec<_> =
+        GGCATInstance::dump_colors(GGCATInstance::get_colormap_file(&graph)).collect();
+    assert_eq!(
+        color_names,
+        vec!["s0", "s1", "s2", ggcat_api::UBIQUITOUS_COLOR_NAME]
+    );
+
+    let color_files =
+        GGCATInstance::write_unitigs_by_color(&graph, dir.path("ubiquitous-output"), None);
+    for (color_file, (_, own)) in color_files.iter().zip(&inputs) {
+        assert_eq!(read_canonical_unitigs(color_file), vec![canonical(own)]);
+    }
+    assert_eq!(
+        read_canonical_unitigs(&color_files[3]),
+        vec![canonical(&shared)]
+    );
+}
+
+#[test]
+fn exact_kmers_mode_keeps_the_unitigs_and_their_colors() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("exact_kmers_mode_keeps_the_unitigs_and_their_colors");
+
+    let mut random_sequence = random_sequences(0xbb67_ae85_84ca_a73b);
+
+    let shared = random_sequence(2000);
+    let inputs: Vec<_> = (0..2)
+        .map(|i| {
+            let input = dir.path(&format!("exact-kmers-input-{}.fa", i));
+            std::fs::write(
+                &input,
+                format!(">own\n{}\n>shared\n{}\n", random_sequence(2000), shared),
+            )
+            .unwrap();
+            input
+        })
+        .collect();
+
+    let build = |output_name: &str, exact_kmers: bool| {
+        let graph = instance.build_graph(
+            inputs
+                .iter()
+                .map(|input| GeneralSequenceBlockData::FASTA((input.clone(), None)))
+                .collect(),
+            dir.path(output_name),
+            Some(&["s0".to_string(), "s1".to_string()]),
+            K,
+            THREADS_COUNT,
+            false,
+            None,
+            true,
+            1,
+            ExtraElaboration::None,
+            BuildOptions {
+                exact_kmers,
+                ..Default::default()
+            },
+        );
+        GGCATInstance::write_unitigs_by_color(
+            &graph,
+            dir.path(&format!("{}-colors", output_name)),
+            None,
+        )
+        .iter()
+        .map(read_canonical_unitigs)
+        .collect::<Vec<_>>()
+    };
+
+    let expected = build("exact-kmers-reference.fa", false);
+
+    // The colliding kmers of the non invertible hash are told apart by their sequence
+    *ggcat_api::debug::DEBUG_HASH_TYPE.lock() = ggcat_api::HashType::RabinKarp32;
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| build("exact-kmers-graph.fa", true)));
+    *ggcat_api::debug::DEBUG_HASH_TYPE.lock() = ggcat_api::HashType::Auto;
+
+    assert_eq!(result.unwrap(), expected);
+}
+
+#[test]
+fn colored_gfa_segments_carry_their_colors() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("colored_gfa_segments_carry_their_colors");
+
+    let mut random_sequence = random_sequences(0x510e_527f_ade6_82d1);
+
+    // The shared sequence is a unitig of both colors
+    let shared = random_sequence(300);
+    let first_input = dir.path("colored-gfa-first.fa");
+    let second_input = dir.path("colored-gfa-second.fa");
+    std::fs::write(
+        &first_input,
+        format!(">a\n{}\n>shared\n{}\n", random_sequence(300), shared),
+    )
+    .unwrap();
+    std::fs::write(
+        &second_input,
+        format!(">b\n{}\n>shared\n{}\n", random_sequence(300), shared),
+    )
+    .unwrap();
+
+    let graph = instance.build_graph(
+        vec![
+            GeneralSequenceBlockData::FASTA((first_input, None)),
+            GeneralSequenceBlockData::FASTA((second_input, None)),
+        ],
+        dir.path("colored-gfa.fa"),
+        Some(&["first".to_string(), "second".to_string()]),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        1,
+        ExtraElaboration::None,
+        BuildOptions::default(),
+    );
+
+    let gfa_file = dir.path("colored-gfa.gfa");
+    let segments_count = GGCATInstance::write_colored_gfa(
+        &graph,
+        &gfa_file,
+        K,
+        querier::colored_gfa::DEFAULT_GFA_COLOR_TAG,
+        querier::colored_gfa::DEFAULT_GFA_MAX_LISTED_COLORS,
+        false,
+        false,
+    );
+    assert_eq!(segments_count, 3);
+
+    let mut segment_colors: Vec<(String, String)> = std::fs::read_to_string(&gfa_file)
+        .unwrap()
+        .lines()
+        .filter(|line| line.starts_with("S\t"))
+        .map(|line| {
+            let fields: Vec<_> = line.split('\t').collect();
+            (canonical(fields[2]), fields[4].to_string())
+        })
+        .collect();
+    segment_colors.retain(|(seq, _)| *seq == canonical(&shared));
+
+    assert_eq!(
+        segment_colors,
+        vec![(canonical(&shared), "CL:Z:first,second".to_string())]
+    );
+}
+
+#[test]
+fn color_abundance_profile_bins_the_kmers_of_each_color() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("color_abundance_profile_bins_the_kmers_of_each_color");
+
+    let mut random_sequence = random_sequences(0x9b05_688c_2b3e_6c1f);
+
+    // The first color has its own sequence 3 times and the shared one once,
+    // the second color the shared sequence twice and its own sequence once
+    let first_only = random_sequence(100);
+    let shared = random_sequence(100);
+    let second_only = random_sequence(100);
+    let kmers_count = (100 - K + 1) as u64;
+
+    let write_input = |name: &str, sequences: &[&String]| {
+        let input = dir.path(name);
+        std::fs::write(
+            &input,
+            sequences
+                .iter()
+                .map(|seq| format!(">read\n{}\n", seq))
+                .collect::<String>(),
+        )
+        .unwrap();
+        input
+    };
+    let first_input = write_input(
+        "abundance-profile-first.fa",
+        &[&first_only, &first_only, &first_only, &shared],
+    );
+    let second_input = write_input(
+        "abundance-profile-second.fa",
+        &[&shared, &shared, &second_only],
+    );
+
+    let graph = instance.build_graph(
+        vec![
+            GeneralSequenceBlockData::FASTA((first_input.clone(), None)),
+            GeneralSequenceBlockData::FASTA((second_input.clone(), None)),
+        ],
+        dir.path("abundance-profile.fa"),
+        Some(&["first".to_string(), "second".to_string()]),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        1,
+        ExtraElaboration::None,
+        BuildOptions::default(),
+    );
+
+    let color_tables: Vec<_> = [
+        (first_input, "abundance-profile-first.counts.tbl"),
+        (second_input, "abundance-profile-second.counts.tbl"),
+    ]
+    .into_iter()
+    .map(|(input, table)| instance.build_kmer_counts_table(vec![input], dir.path(table), K))
+    .collect();
+
+    let profile_file = dir.path("abundance-profile.tsv");
+    let profile = GGCATInstance::write_color_abundance_profile(
+        &graph,
+        &color_tables,
+        &[1, 2, 3],
+        &profile_file,
+    )
+    .unwrap();
+
+    assert_eq!(
+        profile.counts,
+        vec![
+            vec![kmers_count, 0, kmers_count],
+            vec![kmers_count, kmers_count, 0]
+        ]
+    );
+    assert_eq!(
+        std::fs::read_to_string(&profile_file).unwrap(),
+        format!(
+            "#color\t1\t2\t3+\nfirst\t{0}\t0\t{0}\nsecond\t{0}\t{0}\t0\n",
+            kmers_count
+        )
+    );
+}
+
+#[test]
+fn colored_gfa_color_coverage_lists_only_the_covered_colors() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("colored_gfa_color_coverage_lists_only_the_covered_colors");
+
+    let mut random_sequence = random_sequences(0x9b05_688c_2b3e_6c1f);
+
+    // The shared sequence is a unitig of the second and the third colors only, twice in the third one
+    let shared = random_sequence(300);
+    let inputs: Vec<_> = (0..3)
+        .map(|i| {
+            let input = dir.path(&format!("gfa-color-coverage-input-{}.fa", i));
+            let mut content = format!(">own\n{}\n", random_sequence(300));
+            for copy in 0..i {
+                content.push_str(&format!(">shared-{}\n{}\n", copy, shared));
+            }
+            std::fs::write(&input, content).unwrap();
+            GeneralSequenceBlockData::FASTA((input, None))
+        })
+        .collect();
+
+    let graph = instance.build_graph(
+        inputs,
+        dir.path("gfa-color-coverage.fa"),
+        Some(&["s0".to_string(), "s1".to_string(), "s2".to_string()]),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        1,
+        ExtraElaboration::None,
+        BuildOptions {
+            color_coverage_output: true,
+            ..Default::default()
+        },
+    );
+
+    let gfa_file = dir.path("gfa-color-coverage.gfa");
+    GGCATInstance::write_colored_gfa(
+        &graph,
+        &gfa_file,
+        K,
+        querier::colored_gfa::DEFAULT_GFA_COLOR_TAG,
+        querier::colored_gfa::DEFAULT_GFA_MAX_LISTED_COLORS,
+        true,
+        true,
+    );
+
+    let shared_kmers = shared.len() - K + 1;
+    let shared_segments: Vec<_> = std::fs::read_to_string(&gfa_file)
+        .unwrap()
+        .lines()
+        .filter(|line| line.starts_with("S\t"))
+        .map(|line| line.split('\t').map(|f| f.to_string()).collect::<Vec<_>>())
+        .filter(|fields| canonical(&fields[2]) == canonical(&shared))
+        .collect();
+
+    assert_eq!(shared_segments.len(), 1);
+    assert_eq!(shared_segments[0][4], "CL:Z:1,2");
+    assert_eq!(
+        shared_segments[0][5],
+        format!("CV:B:i,1,{},2,{}", shared_kmers, 2 * shared_kmers)
+    );
+}
+
+#[test]
+fn shared_sequences_are_assigned_by_the_policy() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("shared_sequences_are_assigned_by_the_policy");
+
+    let mut random_sequence = random_sequences(0xbb67_ae85_84ca_a73b);
+
+    // Each sample has its own sequence, and both share a common one
+    let shared = random_sequence(200);
+    let inputs: Vec<_> = (0..2)
+        .map(|i| {
+            let own = random_sequence(200);
+            let input = dir.path(&format!("shared-policy-input-{}.fa", i));
+            std::fs::write(&input, format!(">own\n{}\n>shared\n{}\n", own, shared)).unwrap();
+            (input, canonical(&own))
+        })
+        .collect();
+    let (own0, own1) = (inputs[0].1.clone(), inputs[1].1.clone());
+    let shared = canonical(&shared);
+    let with_shared = |own: &String| {
+        let mut unitigs = vec![own.clone(), shared.clone()];
+        unitigs.sort_unstable();
+        unitigs
+    };
+
+    for (policy, expected_names, expected_unitigs) in [
+        (
+            ggcat_api::SharedSequencePolicy::AllColors,
+            vec!["s0", "s1"],
+            vec![with_shared(&own0), with_shared(&own1)],
+        ),
+        (
+            ggcat_api::SharedSequencePolicy::FirstColor,
+            vec!["s0", "s1"],
+            vec![with_shared(&own0), vec![own1.clone()]],
+        ),
+        (
+            ggcat_api::SharedSequencePolicy::FlagShared,
+            vec!["s0", "s1", ggcat_api::SHARED_COLOR_NAME],
+            vec![vec![own0.clone()], vec![own1.clone()], vec![shared.clone()]],
+        ),
+    ] {
+        let graph = instance.build_graph(
+            inputs
+                .iter()
+                .map(|(input, _)| GeneralSequenceBlockData::FASTA((input.clone(), None)))
+                .collect(),
+            dir.path("shared-policy-graph.fa"),
+            Some(&["s0".to_string(), "s1".to_string()]),
+            K,
+            THREADS_COUNT,
+            false,
+            None,
+            true,
+            1,
+            ExtraElaboration::None,
+            BuildOptions {
+                shared_sequence_policy: policy,
+                ..Default::default()
+            },
+        );
+
+        let color_names: Vec<_> =
+            GGCATInstance::dump_colors(GGCATInstance::get_colormap_file(&graph)).collect();
+        assert_eq!(color_names, expected_names, "{:?}", policy);
+
+        let color_files =
+            GGCATInstance::write_unitigs_by_color(&graph, dir.path("shared-policy-output"), None);
+        let color_unitigs: Vec<_> = color_files.iter().map(read_canonical_unitigs).collect();
+        assert_eq!(color_unitigs, expected_unitigs, "{:?}", policy);
+    }
+}
+
+#[test]
+fn reads_appended_to_an_existing_color_increase_its_coverage() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("reads_appended_to_an_existing_color_increase_its_coverage");
+
+    let first_sample = random_sequences(0x9b05_688c_2b3e_6c1f)(2000);
+    let second_sample = random_sequences(0x1f83_d9ab_fb41_bd6b)(2000);
+
+    // The kmers of the first sample are seen once, below the minimum multiplicity, until more reads
+    // of the same library are appended to its color
+    let first_reads = dir.path("same-color-first.fa");
+    let more_first_reads = dir.path("same-color-first-more.fa");
+    let second_reads = dir.path("same-color-second.fa");
+    std::fs::write(&first_reads, format!(">r\n{}\n", first_sample)).unwrap();
+    std::fs::write(&more_first_reads, format!(">r\n{}\n", first_sample)).unwrap();
+    std::fs::write(
+        &second_reads,
+        format!(">r1\n{}\n>r2\n{}\n", second_sample, second_sample),
+    )
+    .unwrap();
+
+    let build =
+        |inputs: Vec<(PathBuf, Option<ColorIndexType>)>, color_names: &[String], name: &str| {
+            instance.build_graph(
+                inputs
+                    .into_iter()
+                    .map(GeneralSequenceBlockData::FASTA)
+                    .collect(),
+                dir.path(&format!("{}.fa", name)),
+                Some(color_names),
+                K,
+                THREADS_COUNT,
+                false,
+                None,
+                true,
+                2,
+                ExtraElaboration::None,
+                BuildOptions::default(),
+            )
+        };
+
+    let existing_graph = build(
+        vec![
+            (first_reads.clone(), Some(0)),
+            (second_reads.clone(), Some(1)),
+        ],
+        &["first".to_string(), "second".to_string()],
+        "same-color-before",
+    );
+    let color_files_before =
+        GGCATInstance::write_unitigs_by_color(&existing_graph, dir.path("same-color-before"), None);
+    assert!(read_canonical_unitigs(&color_files_before[0]).is_empty());
+    assert!(!read_canonical_unitigs(&color_files_before[1]).is_empty());
+
+    // The colored input lists name the colors in another order than the existing colormap
+    let listed_names = ["second".to_string(), "first".to_string()];
+    let listed_inputs = vec![
+        (second_reads, Some(0)),
+        (first_reads, Some(1)),
+        (more_first_reads.clone(), None),
+    ];
+
+    let mut inputs = listed_inputs.clone();
+    assert!(GGCATInstance::append_inputs_to_color(
+        &existing_graph,
+        K,
+        &mut inputs,
+        &listed_names,
+        "third"
+    )
+    .is_err());
+    assert!(GGCATInstance::append_inputs_to_color(
+        &existing_graph,
+        27,
+        &mut inputs,
+        &listed_names,
+        "first"
+    )
+    .is_err());
+
+    let mut inputs = listed_inputs;
+    let appended_names = GGCATInstance::append_inputs_to_color(
+        &existing_graph,
+        K,
+        &mut inputs,
+        &listed_names,
+        "first",
+    )
+    .unwrap();
+    assert_eq!(appended_names, ["first", "second"]);
+    assert_eq!(inputs[0].1, Some(1));
+    assert_eq!(inputs[1].1, Some(0));
+    assert_eq!(inputs[2], (more_first_reads, Some(0)));
+
+    let appended_graph = build(inputs, &appended_names, "same-color-after");
+
+    // No color is added, and the kmers of the first sample reach the minimum multiplicity in its color
+    let colormaps_diff = GGCATInstance::diff_colormaps(
+        GGCATInstance::get_colormap_file(&existing_graph),
+        GGCATInstance::get_colormap_file(&appended_graph),
+    );
+    assert!(colormaps_diff.added_colors.is_empty());
+    assert!(colormaps_diff.removed_colors.is_empty());
+
+    let color_files_after =
+        GGCATInstance::write_unitigs_by_color(&appended_graph, dir.path("same-color-after"), None);
+    assert_eq!(color_files_after.len(), 2);
+    let first_unitigs = read_canonical_unitigs(&color_files_after[0]);
+    assert!(!first_unitigs.is_empty());
+    assert_eq!(
+        first_unitigs
+            .iter()
+            .map(|unitig| unitig.len())
+            .sum::<usize>(),
+        first_sample.len() + (first_unitigs.len() - 1) * (K - 1)
+    );
+    assert_eq!(
+        read_canonical_unitigs(&color_files_after[1]),
+        read_canonical_unitigs(&color_files_before[1])
+    );
+}
+
+#[test]
+fn color_jaccard_matrix_matches_the_shared_kmers() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("color_jaccard_matrix_matches_the_shared_kmers");
+
+    let mut random_bases = random_sequences(0x1f83_d9ab_fb41_bd6b);
+    let mut random_sequence = |kmers: usize| random_bases(kmers + K - 1);
+
+    // Separate records, so that the samples share exactly the kmers of their common sequences
+    let first = random_sequence(100);
+    let shared_first_second = random_sequence(50);
+    let shared_second_third = random_sequence(200);
+    let third = random_sequence(50);
+
+    let samples = [
+        vec![&first, &shared_first_second],
+        vec![&shared_first_second, &shared_second_third],
+        vec![&shared_second_third, &third],
+    ];
+    let inputs = samples
+        .iter()
+        .enumerate()
+        .map(|(color, sequences)| {
+            let input = dir.path(&format!("jaccard-sample{}.fa", color));
+            let records: String = sequences
+                .iter()
+                .enumerate()
+                .map(|(i, seq)| format!(">seq{}\n{}\n", i, seq))
+                .collect();
+            std::fs::write(&input, records).unwrap();
+            GeneralSequenceBlockData::FASTA((input, Some(color as ColorIndexType)))
+        })
+        .collect();
+
+    let graph = instance.build_graph(
+        inputs,
+        dir.path("jaccard-graph.fa"),
+        Some(&["s0".to_string(), "s1".to_string(), "s2".to_string()]),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        1,
+        ExtraElaboration::None,
+        BuildOptions::default(),
+    );
+
+    let jaccard_file = dir.path("jaccard-graph.jaccard.tsv");
+    assert_eq!(
+        GGCATInstance::write_color_jaccard_matrix(&graph, &jaccard_file),
+        3
+    );
+
+    let matrix = std::fs::read_to_string(&jaccard_file).unwrap();
+    let mut lines = matrix.lines();
+    assert_eq!(lines.next(), Some("color\ts0\ts1\ts2"));
+    let rows: Vec<Vec<f64>> = lines
+        .map(|line| {
+            line.split('\t')
+                .skip(1)
+                .map(|value| value.parse().unwrap())
+                .collect()
+        })
+        .collect();
+
+    // s0 has 150 kmers, s1 250 and s2 250, sharing 50 (s0, s1) and 200 (s1, s2) kmers
+    let expected = [
+        [1.0, 50.0 / 350.0, 0.0],
+        [50.0 / 350.0, 1.0, 200.0 / 300.0],
+        [0.0, 200.0 / 300.0, 1.0],
+    ];
+    assert_eq!(rows.len(), 3);
+    for (row, expected_row) in rows.iter().zip(expected.iter()) {
+        assert_eq!(row.len(), 3);
+        for (value, expected_value) in row.iter().zip(expected_row.iter()) {
+            assert!((value - expected_value).abs() < 1e-5);
+        }
+    }
+}
+
+#[test]
+fn near_universal_color_sets_are_stored_approximately() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("near_universal_color_sets_are_stored_approximately");
+
+    let mut random_sequence = random_sequences(0xbb67_ae85_84ca_a73b);
+
+    // A sequence shared by all the five samples, and one shared only by the first two
+    let universal = random_sequence(200);
+    let pair = random_sequence(200);
+    let inputs: Vec<_> = (0..5)
+        .map(|i| {
+            let input = dir.path(&format!("approximate-input-{}.fa", i));
+            let mut records = format!(">universal\n{}\n", universal);
+            if i < 2 {
+                records.push_str(&format!(">pair\n{}\n", pair));
+            }
+            std::fs::write(&input, records).unwrap();
+            GeneralSequenceBlockData::FASTA((input, None))
+        })
+        .collect();
+    let color_names: Vec<_> = (0..5).map(|i| format!("s{}", i)).collect();
+
+    let graph = instance.build_graph(
+        inputs,
+        dir.path("approximate-graph.fa"),
+        Some(&color_names),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        1,
+        ExtraElaboration::None,
+        BuildOptions {
+            max_exact_color_set: Some(3),
+            ..Default::default()
+        },
+    );
+
+    let graph_colors: Vec<_> =
+        GGCATInstance::dump_colors(GGCATInstance::get_colormap_file(&graph)).collect();
+    assert_eq!(
+        graph_colors,
+        vec![
+            "s0",
+            "s1",
+            "s2",
+            "s3",
+            "s4",
+            ggcat_api::APPROXIMATE_COLOR_NAME
+        ]
+    );
+
+    let color_files =
+        GGCATInstance::write_unitigs_by_color(&graph, dir.path("approximate-output"), None);
+    let color_unitigs: Vec<_> = color_files.iter().map(read_canonical_unitigs).collect();
+
+    // Above the threshold the set is a sample of 3 colors marked as approximate
+    assert_eq!(color_unitigs[5], vec![canonical(&universal)]);
+    assert_eq!(
+        color_unitigs[..5]
+            .iter()
+            .filter(|unitigs| unitigs.contains(&canonical(&universal)))
+            .count(),
+        3
+    );
+
+    // Below the threshold the set is exact
+    for (color, unitigs) in color_unitigs[..5].iter().enumerate() {
+        assert_eq!(unitigs.contains(&canonical(&pair)), color < 2);
+    }
+    assert!(!color_unitigs[5].contains(&canonical(&pair)));
+}
+
+#[test]
+fn unique_kmers_are_reported_under_their_only_color() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("unique_kmers_are_reported_under_their_only_color");
+
+    let mut random_bases = random_sequences(0x6a09_e667_bb67_ae85);
+    let mut random_sequence = |kmers: usize| random_bases(kmers + K - 1);
+
+    // Separate records, so that only the kmers of the private region are unique to the first sample
+    let shared = random_sequence(150);
+    let private = random_sequence(60);
+
+    let samples = [vec![&shared, &private], vec![&shared]];
+    let inputs = samples
+        .iter()
+        .enumerate()
+        .map(|(color, sequences)| {
+            let input = dir.path(&format!("unique-kmers-sample{}.fa", color));
+            let records: String = sequences
+                .iter()
+                .enumerate()
+                .map(|(i, seq)| format!(">seq{}\n{}\n", i, seq))
+                .collect();
+            std::fs::write(&input, records).unwrap();
+            GeneralSequenceBlockData::FASTA((input, Some(color as ColorIndexType)))
+        })
+        .collect::<Vec<_>>();
+
+    let graph = instance.build_graph(
+        inputs.clone(),
+        dir.path("unique-kmers-graph.fa"),
+        Some(&["s0".to_string(), "s1".to_string()]),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        1,
+        ExtraElaboration::None,
+        BuildOptions::default(),
+    );
+
+    let report_file = dir.path("unique-kmers-graph.unique_kmers.tsv");
+    assert_eq!(
+        instance.write_unique_kmers_report(&graph, &report_file),
+        Ok(vec![60, 0])
+    );
+
+    let report = std::fs::read_to_string(&report_file).unwrap();
+    let stretches: Vec<Vec<&str>> = report
+        .lines()
+        .skip(1)
+        .map(|line| line.split('\t').collect())
+        .collect();
+    assert_eq!(stretches.len(), 1);
+    assert_eq!(stretches[0][0], "s0");
+    assert_eq!(stretches[0][3], "60");
+    assert_eq!(canonical(stretches[0][4]), canonical(&private));
+
+    // None of the reported kmers is shared with the second sample
+    let shared_kmers: BTreeSet<_> = (0..=shared.len() - K)
+        .map(|i| canonical(&shared[i..i + K]))
+        .collect();
+    let reported = stretches[0][4];
+    assert!(
+        (0..=reported.len() - K).all(|i| !shared_kmers.contains(&canonical(&reported[i..i + K])))
+    );
+
+    let build_with_options = |name: &str, options| {
+        instance.build_graph(
+            inputs.clone(),
+            dir.path(name),
+            Some(&["s0".to_string(), "s1".to_string()]),
+            K,
+            THREADS_COUNT,
+            false,
+            None,
+            true,
+            1,
+            ExtraElaboration::None,
+            options,
+        )
+    };
+
+    // The shared kmers have only the ubiquitous sentinel color, that is not an input color
+    let capped_graph = build_with_options(
+        "unique-kmers-capped.fa",
+        BuildOptions {
+            max_kmer_colors: Some(1),
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        instance.write_unique_kmers_report(&capped_graph, &report_file),
+        Ok(vec![60, 0])
+    );
+
+    // Each shared kmer has a single color, the report cannot tell it from the unique ones
+    let first_color_graph = build_with_options(
+        "unique-kmers-first-color.fa",
+        BuildOptions {
+            shared_sequence_policy: ggcat_api::SharedSequencePolicy::FirstColor,
+            ..Default::default()
+        },
+    );
+    assert!(instance
+        .write_unique_kmers_report(&first_color_graph, &report_file)
+        .is_err());
+}
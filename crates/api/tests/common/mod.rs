@@ -0,0 +1,147 @@
+// Each test binary uses only a part of the helpers
+#![allow(dead_code)]
+
+use ggcat_api::{
+    BuildOptions, ExtraElaboration, GGCATConfig, GGCATInstance, GeneralSequenceBlockData,
+    LogFormat, ReadsSizeEncoding,
+};
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+pub use utils::test_dir::TestDir;
+
+pub const K: usize = 31;
+pub const THREADS_COUNT: usize = 4;
+
+// The pipelines share global state, so run them one at a time
+pub static PIPELINE_LOCK: Mutex<()> = Mutex::new(());
+
+/// The temp dir of the instance, where each build or query run creates its own directory
+pub fn instance_temp_dir() -> PathBuf {
+    std::env::temp_dir().join("ggcat-api-tests")
+}
+
+pub fn test_instance() -> &'static GGCATInstance {
+    GGCATInstance::create(GGCATConfig {
+        temp_dir: Some(instance_temp_dir()),
+        memory: 2.0,
+        prefer_memory: true,
+        total_threads_count: THREADS_COUNT,
+        intermediate_compression_level: None,
+        stats_file: None,
+        io_threads_count: None,
+        compute_threads_count: None,
+        log_format: LogFormat::Human,
+        verbose_phases_log: false,
+        reads_size_encoding: ReadsSizeEncoding::Varint,
+        max_read_length: None,
+    })
+}
+
+pub fn example_input(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../example-inputs")
+        .join(name)
+}
+
+/// Returns a generator of random sequences of the given lengths, that are the same for the same seed
+pub fn random_sequences(mut state: u64) -> impl FnMut(usize) -> String {
+    move |length| {
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                ['A', 'C', 'G', 'T'][(state >> 32) as usize % 4]
+            })
+            .collect()
+    }
+}
+
+pub fn reverse_complement(seq: &str) -> String {
+    seq.chars()
+        .rev()
+        .map(|c| match c {
+            'A' => 'T',
+            'C' => 'G',
+            'G' => 'C',
+            'T' => 'A',
+            _ => c,
+        })
+        .collect()
+}
+
+pub fn canonical(seq: &str) -> String {
+    seq.to_string().min(reverse_complement(seq))
+}
+
+pub fn read_canonical_unitigs(graph_file: impl AsRef<Path>) -> Vec<String> {
+    let mut unitigs = vec![];
+    for line in BufReader::new(File::open(graph_file).unwrap()).lines() {
+        let line = line.unwrap();
+        if line.starts_with('>') || line.is_empty() {
+            continue;
+        }
+        unitigs.push(canonical(&line));
+    }
+    unitigs.sort_unstable();
+    unitigs
+}
+
+pub fn read_fasta_sequences(fasta_file: impl AsRef<Path>) -> Vec<String> {
+    BufReader::new(File::open(fasta_file).unwrap())
+        .lines()
+        .map(|l| l.unwrap())
+        .filter(|l| !l.starts_with('>') && !l.is_empty())
+        .collect()
+}
+
+pub fn build_example_graph(instance: &GGCATInstance, output_file: PathBuf) -> PathBuf {
+    instance.build_graph(
+        ["sal1.fa", "sal2.fa", "sal3.fa"]
+            .iter()
+            .map(|f| GeneralSequenceBlockData::FASTA((example_input(f), None)))
+            .collect(),
+        output_file,
+        Some(&["sal1".to_string(), "sal2".to_string(), "sal3".to_string()]),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        1,
+        ExtraElaboration::None,
+        BuildOptions::default(),
+    )
+}
+
+pub fn build_single_input_graph(
+    instance: &GGCATInstance,
+    input: PathBuf,
+    output_file: PathBuf,
+) -> PathBuf {
+    build_single_input_graph_with_options(instance, input, output_file, Default::default())
+}
+
+pub fn build_single_input_graph_with_options(
+    instance: &GGCATInstance,
+    input: PathBuf,
+    output_file: PathBuf,
+    options: BuildOptions,
+) -> PathBuf {
+    instance.build_graph(
+        vec![GeneralSequenceBlockData::FASTA((input, None))],
+        output_file,
+        None,
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        false,
+        1,
+        ExtraElaboration::None,
+        options,
+    )
+}
@@ -0,0 +1,540 @@
+mod common;
+
+use common::{
+    build_example_graph, build_single_input_graph, canonical, example_input, random_sequences,
+    read_canonical_unitigs, read_fasta_sequences, reverse_complement, test_instance, TestDir, K,
+    PIPELINE_LOCK, THREADS_COUNT,
+};
+use ggcat_api::{
+    BuildOptions, ColorIndexType, ColoredQueryOutputFormat, ExtraElaboration,
+    GeneralSequenceBlockData, QueryOptions,
+};
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+#[test]
+fn query_results_callback_matches_output() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("query_results_callback_matches_output");
+    let graph_file = build_example_graph(instance, dir.path("callback-graph.fa"));
+
+    let callback_results = Mutex::new(BTreeMap::new());
+    let output_file = instance.query_graph_with_callback(
+        graph_file,
+        example_input("query.fa"),
+        dir.path("callback-query"),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        ColoredQueryOutputFormat::JsonLinesWithNumbers,
+        QueryOptions::default(),
+        |query, matches| {
+            let colors: Vec<_> = matches.iter().map(|(color, _)| *color).collect();
+            assert!(callback_results.lock().insert(query, colors).is_none());
+        },
+    );
+
+    let mut output_results = BTreeMap::new();
+    for line in BufReader::new(File::open(output_file).unwrap()).lines() {
+        let value: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+        let mut colors: Vec<ColorIndexType> = value["matches"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|c| c.parse().unwrap())
+            .collect();
+        colors.sort_unstable();
+        output_results.insert(value["query_index"].as_u64().unwrap(), colors);
+    }
+
+    assert!(!output_results.is_empty());
+    assert_eq!(callback_results.into_inner(), output_results);
+}
+
+#[test]
+fn restricted_query_ignores_other_unitigs() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("restricted_query_ignores_other_unitigs");
+
+    let graph_file = build_example_graph(instance, dir.path("subset-graph.fa"));
+
+    let mut unitigs = vec![];
+    let mut current_id = None;
+    for line in BufReader::new(File::open(&graph_file).unwrap()).lines() {
+        let line = line.unwrap();
+        if let Some(header) = line.strip_prefix('>') {
+            current_id = Some(header.split(' ').next().unwrap().parse::<u64>().unwrap());
+        } else if line.len() >= K + 10 {
+            unitigs.push((current_id.unwrap(), line));
+        }
+    }
+    assert!(unitigs.len() >= 2);
+
+    let query_file = dir.path("subset-query.fa");
+    {
+        let mut query = File::create(&query_file).unwrap();
+        writeln!(query, ">inside\n{}", unitigs[0].1).unwrap();
+        writeln!(query, ">outside\n{}", unitigs[1].1).unwrap();
+    }
+
+    let output_file = instance.query_graph(
+        graph_file,
+        query_file,
+        dir.path("subset-query"),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        ColoredQueryOutputFormat::JsonLinesWithNumbers,
+        QueryOptions {
+            unitigs_subset: Some(vec![unitigs[0].0]),
+            ..Default::default()
+        },
+    );
+
+    let mut matched_queries = BTreeSet::new();
+    for line in BufReader::new(File::open(output_file).unwrap()).lines() {
+        let value: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+        if !value["matches"].as_object().unwrap().is_empty() {
+            matched_queries.insert(value["query_index"].as_u64().unwrap());
+        }
+    }
+
+    assert_eq!(matched_queries, BTreeSet::from([0]));
+}
+
+#[test]
+fn regions_query_results_are_attributed_to_features() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("regions_query_results_are_attributed_to_features");
+    let graph_file = build_example_graph(instance, dir.path("regions-graph.fa"));
+
+    // A reference made of a part of the first sample followed by a part of the third one
+    let reference_file = dir.path("regions-reference.fa");
+    let regions_file = dir.path("regions.bed");
+    {
+        let mut reference = File::create(&reference_file).unwrap();
+        writeln!(reference, ">chr").unwrap();
+        for sample in ["sal1.fa", "sal3.fa"] {
+            let sequence = read_fasta_sequences(example_input(sample))
+                .into_iter()
+                .max_by_key(|seq| seq.len())
+                .unwrap();
+            write!(reference, "{}", &sequence[2000..3000]).unwrap();
+        }
+        writeln!(reference).unwrap();
+
+        let mut regions = File::create(&regions_file).unwrap();
+        writeln!(regions, "chr\t1000\t2000\tfrom_sal3").unwrap();
+        writeln!(regions, "chr\t0\t1000\tfrom_sal1").unwrap();
+    }
+
+    let results = instance.query_regions(
+        graph_file,
+        reference_file,
+        regions_file,
+        dir.path("regions-query"),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        ColoredQueryOutputFormat::JsonLinesWithNumbers,
+        QueryOptions::default(),
+    );
+
+    let features: Vec<_> = results
+        .iter()
+        .map(|result| result.region.feature_id.as_str())
+        .collect();
+    assert_eq!(features, vec!["from_sal3", "from_sal1"]);
+
+    // All the kmers of each region are found in the color of its sample
+    let region_kmers = (1000 - K + 1) as u64;
+    for (result, sample_color) in results.iter().zip([2, 0]) {
+        assert!(
+            result.matches.contains(&(sample_color, region_kmers)),
+            "{:?}",
+            result
+        );
+    }
+}
+
+#[test]
+fn reverse_complement_query_matches_the_reverse_strand() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("reverse_complement_query_matches_the_reverse_strand");
+
+    let mut random_sequence = random_sequences(0x9b05_688c_2b3e_6c1f);
+    let sequence = random_sequence(400);
+    let input = dir.path("matched-strand-input.fa");
+    std::fs::write(&input, format!(">0\n{}\n", sequence)).unwrap();
+
+    let graph_file = instance.build_graph(
+        vec![GeneralSequenceBlockData::FASTA((input, None))],
+        dir.path("matched-strand-graph.fa"),
+        Some(&["strand".to_string()]),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        1,
+        ExtraElaboration::None,
+        BuildOptions::default(),
+    );
+
+    // The strand is relative to the unitig as written in the graph
+    let unitig = BufReader::new(File::open(&graph_file).unwrap())
+        .lines()
+        .map(|line| line.unwrap())
+        .filter(|line| !line.starts_with('>'))
+        .max_by_key(|line| line.len())
+        .unwrap();
+    let query_input = dir.path("matched-strand-query.fa");
+    std::fs::write(
+        &query_input,
+        format!(">fw\n{}\n>rc\n{}\n", unitig, reverse_complement(&unitig)),
+    )
+    .unwrap();
+
+    let output_file = instance.query_graph(
+        graph_file,
+        query_input,
+        dir.path("matched-strand-query"),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        ColoredQueryOutputFormat::JsonLinesWithNumbers,
+        QueryOptions {
+            matched_strand_report: true,
+            ..Default::default()
+        },
+    );
+
+    let mut strands = BTreeMap::new();
+    for line in BufReader::new(File::open(output_file).unwrap()).lines() {
+        let value: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+        strands.insert(
+            value["query_index"].as_u64().unwrap(),
+            value["strands"]["0"].as_str().unwrap().to_string(),
+        );
+    }
+
+    assert_eq!(strands.get(&0).map(|s| s.as_str()), Some("+"));
+    assert_eq!(strands.get(&1).map(|s| s.as_str()), Some("-"));
+}
+
+#[test]
+fn imported_fasta_assembly_can_be_queried() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("imported_fasta_assembly_can_be_queried");
+
+    let mut random_sequence = random_sequences(0xa54f_f53a_5f1d_36f1);
+
+    let records: Vec<_> = (0..3).map(|_| random_sequence(300)).collect();
+    let unrelated = random_sequence(300);
+
+    let assembly = dir.path("imported-assembly.fa");
+    std::fs::write(
+        &assembly,
+        records
+            .iter()
+            .enumerate()
+            .map(|(i, record)| format!(">contig{}\n{}\n", i, record))
+            .collect::<String>(),
+    )
+    .unwrap();
+
+    let graph_file = instance.import_fasta_as_unitigs(
+        &assembly,
+        dir.path("imported-assembly-graph.fa"),
+        K,
+        true,
+    );
+    assert_eq!(
+        read_canonical_unitigs(&graph_file),
+        read_canonical_unitigs(&assembly)
+    );
+
+    let query_input = dir.path("imported-assembly-query.fa");
+    std::fs::write(
+        &query_input,
+        format!(">contig1\n{}\n>unrelated\n{}\n", records[1], unrelated),
+    )
+    .unwrap();
+
+    let output_file = instance.query_graph(
+        graph_file,
+        query_input,
+        dir.path("imported-assembly-query"),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        false,
+        ColoredQueryOutputFormat::JsonLinesWithNumbers,
+        QueryOptions::default(),
+    );
+
+    let matched_kmers: Vec<_> = BufReader::new(File::open(output_file).unwrap())
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let line = line.unwrap();
+            let fields: Vec<_> = line.split(',').collect();
+            (
+                fields[1].parse::<u64>().unwrap(),
+                fields[2].parse::<u64>().unwrap(),
+            )
+        })
+        .collect();
+
+    let query_kmers = (300 - K + 1) as u64;
+    assert_eq!(
+        matched_kmers,
+        vec![(query_kmers, query_kmers), (0, query_kmers)]
+    );
+}
+
+#[test]
+fn low_coverage_colors_are_dropped_from_the_query_output() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("low_coverage_colors_are_dropped_from_the_query_output");
+
+    let mut random_sequence = random_sequences(0x1f83_d9ab_fb41_bd6b);
+
+    let long_sample = random_sequence(1000);
+    let short_sample = random_sequence(60);
+    let inputs: Vec<_> = [&long_sample, &short_sample]
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| {
+            let input = dir.path(&format!("color-abundance-input-{}.fa", i));
+            std::fs::write(&input, format!(">sample\n{}\n", sample)).unwrap();
+            GeneralSequenceBlockData::FASTA((input, None))
+        })
+        .collect();
+
+    let graph_file = instance.build_graph(
+        inputs,
+        dir.path("color-abundance-graph.fa"),
+        Some(&["long".to_string(), "short".to_string()]),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        1,
+        ExtraElaboration::None,
+        BuildOptions::default(),
+    );
+
+    // The first query matches many kmers of the first color, but only a small fraction of its kmers,
+    // the second one matches fewer kmers of the second color, covering all the query
+    let query_input = dir.path("color-abundance-query.fa");
+    std::fs::write(
+        &query_input,
+        format!(
+            ">high-count\n{}{}\n>high-coverage\n{}\n",
+            &long_sample[..100],
+            random_sequence(1000),
+            short_sample
+        ),
+    )
+    .unwrap();
+
+    let output_file = instance.query_graph(
+        graph_file,
+        query_input,
+        dir.path("color-abundance-query"),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        ColoredQueryOutputFormat::JsonLinesWithNames,
+        QueryOptions {
+            min_color_abundance: 0.5,
+            ..Default::default()
+        },
+    );
+
+    let mut matched_colors = BTreeMap::new();
+    for line in BufReader::new(File::open(output_file).unwrap()).lines() {
+        let value: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+        matched_colors.insert(
+            value["query_index"].as_u64().unwrap(),
+            value["matches"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    assert_eq!(matched_colors.get(&0), Some(&vec![]));
+    assert_eq!(matched_colors.get(&1), Some(&vec!["short".to_string()]));
+}
+
+#[test]
+fn scattered_matches_are_dropped_by_the_min_run_length() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("scattered_matches_are_dropped_by_the_min_run_length");
+
+    let mut random_sequence = random_sequences(0x510e_527f_ade6_82d1);
+
+    let reference = random_sequence(300);
+    let input = dir.path("min-run-input.fa");
+    std::fs::write(&input, format!(">reference\n{}\n", reference)).unwrap();
+    let graph = build_single_input_graph(instance, input, dir.path("min-run-graph.fa"));
+
+    // Single kmers of the reference separated by unrelated bases, and a solid block of 20 kmers
+    let scattered: String = (0..4)
+        .map(|i| format!("{}{}", &reference[i * 60..i * 60 + K], random_sequence(K)))
+        .collect();
+    let solid = format!(
+        "{}{}{}",
+        random_sequence(K),
+        &reference[100..100 + K + 19],
+        random_sequence(K)
+    );
+
+    let query = dir.path("min-run-query.fa");
+    std::fs::write(
+        &query,
+        format!(">scattered\n{}\n>solid\n{}\n", scattered, solid),
+    )
+    .unwrap();
+
+    let matched_kmers = |min_run_length: usize| -> Vec<u64> {
+        let output = instance.query_graph_min_run_length(
+            graph.clone(),
+            query.clone(),
+            dir.path(&format!("min-run-output-{}", min_run_length)),
+            K,
+            min_run_length,
+        );
+        BufReader::new(File::open(output).unwrap())
+            .lines()
+            .skip(1)
+            .map(|line| line.unwrap().split(',').nth(1).unwrap().parse().unwrap())
+            .collect()
+    };
+
+    assert_eq!(matched_kmers(1), vec![4, 20]);
+    assert_eq!(matched_kmers(5), vec![0, 20]);
+}
+
+#[test]
+fn matched_unitig_sequences_contain_the_query_kmers() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("matched_unitig_sequences_contain_the_query_kmers");
+
+    let input = example_input("sal1.fa");
+    let graph = build_single_input_graph(instance, input.clone(), dir.path("unitigs-query.fa"));
+
+    let fragment = read_fasta_sequences(&input)
+        .into_iter()
+        .max_by_key(|seq| seq.len())
+        .unwrap()[5000..5200]
+        .to_string();
+    let query = dir.path("unitigs-query-input.fa");
+    std::fs::write(&query, format!(">fragment\n{}\n", fragment)).unwrap();
+
+    let output = instance.query_graph_unitigs(
+        graph.clone(),
+        query.clone(),
+        dir.path("unitigs-query-output"),
+        K,
+        true,
+    );
+    let result: serde_json::Value =
+        serde_json::from_str(std::fs::read_to_string(&output).unwrap().trim()).unwrap();
+    assert_eq!(result["query_index"], 0);
+
+    let unitigs = result["unitigs"].as_array().unwrap();
+    let sequences: Vec<_> = result["sequences"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|seq| seq.as_str().unwrap().to_string())
+        .collect();
+    assert!(!unitigs.is_empty());
+    assert_eq!(unitigs.len(), sequences.len());
+
+    // Each kmer of the query is in one of the returned unitigs, on any strand
+    let unitig_kmers: BTreeSet<String> = sequences
+        .iter()
+        .flat_map(|seq| (0..=seq.len() - K).map(move |i| canonical(&seq[i..i + K])))
+        .collect();
+    for i in 0..=fragment.len() - K {
+        assert!(unitig_kmers.contains(&canonical(&fragment[i..i + K])));
+    }
+
+    // Without the sequences only the ids are reported
+    let ids_output =
+        instance.query_graph_unitigs(graph, query, dir.path("unitigs-query-ids-output"), K, false);
+    let ids_result: serde_json::Value =
+        serde_json::from_str(std::fs::read_to_string(&ids_output).unwrap().trim()).unwrap();
+    assert_eq!(&ids_result["unitigs"], &result["unitigs"]);
+    assert!(ids_result.get("sequences").is_none());
+}
+
+#[test]
+fn index_queries_of_a_rebuilt_graph_use_the_new_graph() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("index_queries_of_a_rebuilt_graph_use_the_new_graph");
+
+    let mut random_sequence = random_sequences(0x2545_f491_4f6c_dd1d);
+    let first_sequence = random_sequence(1000);
+    let second_sequence = random_sequence(1200);
+
+    let first_input = dir.path("rebuilt-graph-first-input.fa");
+    let second_input = dir.path("rebuilt-graph-second-input.fa");
+    std::fs::write(&first_input, format!(">0\n{}\n", first_sequence)).unwrap();
+    std::fs::write(&second_input, format!(">0\n{}\n", second_sequence)).unwrap();
+
+    // A fragment of the first sequence only
+    let query = dir.path("rebuilt-graph-query.fa");
+    std::fs::write(
+        &query,
+        format!(">fragment\n{}\n", &first_sequence[100..300]),
+    )
+    .unwrap();
+
+    let query_unitigs = |output_name: &str| {
+        let output = instance.query_graph_unitigs(
+            dir.path("rebuilt-graph.fa"),
+            query.clone(),
+            dir.path(output_name),
+            K,
+            false,
+        );
+        let result: serde_json::Value =
+            serde_json::from_str(std::fs::read_to_string(&output).unwrap().trim()).unwrap();
+        result["unitigs"].as_array().unwrap().len()
+    };
+
+    build_single_input_graph(instance, first_input, dir.path("rebuilt-graph.fa"));
+    assert_eq!(query_unitigs("rebuilt-graph-first-output"), 1);
+
+    // The index built for the first graph is rebuilt instead of reporting its kmers
+    build_single_input_graph(instance, second_input, dir.path("rebuilt-graph.fa"));
+    assert_eq!(query_unitigs("rebuilt-graph-second-output"), 0);
+}
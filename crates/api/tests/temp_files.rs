@@ -0,0 +1,433 @@
+mod common;
+
+use assembler::AssemblerStartingStep;
+use common::{
+    build_example_graph, build_single_input_graph_with_options, example_input, instance_temp_dir,
+    read_canonical_unitigs, test_instance, TestDir, K, PIPELINE_LOCK, THREADS_COUNT,
+};
+use ggcat_api::{
+    BuildOptions, ColoredQueryOutputFormat, ExtraElaboration, GGCATInstance,
+    GeneralSequenceBlockData, QueryOptions,
+};
+use std::collections::BTreeSet;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+// Restores the settings changed by the tests of this file when dropped, also when a test fails,
+// as the pipeline lock is not poisoned by a failure and the next test would inherit them
+struct SettingsReset;
+
+impl Drop for SettingsReset {
+    fn drop(&mut self) {
+        config::KEEP_FILES.store(false, Ordering::Relaxed);
+        config::PREFER_MEMORY.store(true, Ordering::Relaxed);
+        *ggcat_api::debug::DEBUG_ASSEMBLER_FIRST_STEP.lock() =
+            AssemblerStartingStep::MinimizerBucketing;
+        *ggcat_api::debug::DEBUG_ASSEMBLER_LAST_STEP.lock() =
+            AssemblerStartingStep::MaximalUnitigsLinks;
+        *ggcat_api::debug::DEBUG_ASSEMBLER_RESUME_TEMP_DIR.lock() = None;
+        ggcat_api::debug::DEBUG_ORPHAN_LINKS_MISSING_UNITIGS
+            .lock()
+            .clear();
+    }
+}
+
+fn run_temp_dirs() -> BTreeSet<PathBuf> {
+    std::fs::read_dir(instance_temp_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with("build_graph_")
+        })
+        .collect()
+}
+
+fn build_failing_graph(
+    instance: &GGCATInstance,
+    dir: &TestDir,
+    keep_temp_files_until_success: bool,
+) -> BTreeSet<PathBuf> {
+    let before = run_temp_dirs();
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        build_single_input_graph_with_options(
+            instance,
+            dir.path("missing-input.fa"),
+            dir.path("failing-graph.fa"),
+            BuildOptions {
+                keep_temp_files_until_success,
+                ..Default::default()
+            },
+        )
+    }));
+    assert!(result.is_err());
+    run_temp_dirs().difference(&before).cloned().collect()
+}
+
+// The temp dir kept by the query run with the given output prefix, found from its run record
+fn query_run_temp_dir(output_prefix: &Path) -> PathBuf {
+    let record_line = format!("output_file_prefix\t{}", output_prefix.display());
+    std::fs::read_dir(instance_temp_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|dir| {
+            std::fs::read_to_string(dir.join("query-run.txt"))
+                .map(|record| record.lines().any(|line| line == record_line))
+                .unwrap_or(false)
+        })
+        .unwrap()
+}
+
+#[test]
+fn keep_until_success_cleans_up_after_success() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("keep_until_success_cleans_up_after_success");
+
+    let before = run_temp_dirs();
+    build_single_input_graph_with_options(
+        instance,
+        example_input("sal1.fa"),
+        dir.path("keep-until-success-graph.fa"),
+        BuildOptions {
+            keep_temp_files_until_success: true,
+            ..Default::default()
+        },
+    );
+    let after = run_temp_dirs();
+
+    assert_eq!(before, after);
+}
+
+#[test]
+fn keep_until_success_retains_files_after_failure() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("keep_until_success_retains_files_after_failure");
+
+    // By default the temporary files are removed also when a run fails
+    assert!(build_failing_graph(instance, &dir, false).is_empty());
+
+    let retained = build_failing_graph(instance, &dir, true);
+
+    assert_eq!(retained.len(), 1);
+    for temp_dir in retained {
+        assert!(temp_dir.is_dir());
+        std::fs::remove_dir_all(temp_dir).unwrap();
+    }
+}
+
+#[test]
+fn keep_until_success_retains_files_after_an_error() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("keep_until_success_retains_files_after_an_error");
+    let _reset = SettingsReset;
+
+    // A colored build resumed after the kmers merge without a colormap recovery log returns an error
+    let build_erroring_graph = |keep_temp_files_until_success| {
+        let before = run_temp_dirs();
+        *ggcat_api::debug::DEBUG_ASSEMBLER_FIRST_STEP.lock() = AssemblerStartingStep::HashesSorting;
+        let result = instance.try_build_graph(
+            vec![GeneralSequenceBlockData::FASTA((
+                example_input("sal1.fa"),
+                None,
+            ))],
+            dir.path("erroring-graph.fa"),
+            Some(&["sal1".to_string()]),
+            K,
+            THREADS_COUNT,
+            false,
+            None,
+            true,
+            1,
+            ExtraElaboration::None,
+            BuildOptions {
+                keep_temp_files_until_success,
+                ..Default::default()
+            },
+        );
+        assert!(result.is_err());
+        run_temp_dirs()
+            .difference(&before)
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    assert!(build_erroring_graph(false).is_empty());
+
+    let retained = build_erroring_graph(true);
+
+    assert_eq!(retained.len(), 1);
+    for temp_dir in retained {
+        assert!(temp_dir.is_dir());
+        std::fs::remove_dir_all(temp_dir).unwrap();
+    }
+}
+
+#[test]
+fn failed_orphan_links_check_keeps_the_files_until_success() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("failed_orphan_links_check_keeps_the_files_until_success");
+    let _reset = SettingsReset;
+
+    let build = |output_file| {
+        let before = run_temp_dirs();
+        let result = instance.try_build_graph(
+            vec![GeneralSequenceBlockData::FASTA((
+                example_input("sal1.fa"),
+                None,
+            ))],
+            dir.path(output_file),
+            None,
+            K,
+            THREADS_COUNT,
+            false,
+            None,
+            false,
+            1,
+            ExtraElaboration::UnitigLinks,
+            BuildOptions {
+                orphan_links_check: ggcat_api::OrphanLinksCheck::Fail,
+                keep_temp_files_until_success: true,
+                ..Default::default()
+            },
+        );
+        let new_temp_dirs: Vec<_> = run_temp_dirs().difference(&before).cloned().collect();
+        (result, new_temp_dirs)
+    };
+
+    // Without orphan links the build succeeds and removes its files
+    let (result, new_temp_dirs) = build("orphan-links-passed.fa");
+    assert!(result.is_ok());
+    assert!(new_temp_dirs.is_empty());
+
+    // A unitig linked by another one, that has an orphan link if it is missing
+    let graph = std::fs::read_to_string(dir.path("orphan-links-passed.fa")).unwrap();
+    let linked_unitig = graph
+        .lines()
+        .filter(|line| line.starts_with('>'))
+        .flat_map(|line| line.split(' '))
+        .find_map(|field| field.strip_prefix("L:")?.split(':').nth(1)?.parse().ok())
+        .unwrap();
+    *ggcat_api::debug::DEBUG_ORPHAN_LINKS_MISSING_UNITIGS.lock() = vec![linked_unitig];
+    let (result, new_temp_dirs) = build("orphan-links-failed.fa");
+
+    assert!(result.unwrap_err().contains("links to missing unitigs"));
+    assert_eq!(new_temp_dirs.len(), 1);
+    for temp_dir in new_temp_dirs {
+        assert!(temp_dir.is_dir());
+        std::fs::remove_dir_all(temp_dir).unwrap();
+    }
+}
+
+#[test]
+fn build_resumed_after_the_kmers_merge_recovers_the_colormap() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("build_resumed_after_the_kmers_merge_recovers_the_colormap");
+    let _reset = SettingsReset;
+    let full_graph = build_example_graph(instance, dir.path("colormap-recovery-full.fa"));
+
+    // The build stops after the kmers merge, keeping its temp dir on disk
+    let run_temp_dir = dir.path("resumed-build-temp");
+    std::fs::create_dir_all(&run_temp_dir).unwrap();
+    config::KEEP_FILES.store(true, Ordering::Relaxed);
+    config::PREFER_MEMORY.store(false, Ordering::Relaxed);
+    *ggcat_api::debug::DEBUG_ASSEMBLER_RESUME_TEMP_DIR.lock() = Some(run_temp_dir.clone());
+    *ggcat_api::debug::DEBUG_ASSEMBLER_LAST_STEP.lock() = AssemblerStartingStep::KmersMerge;
+
+    let graph_file = dir.path("colormap-recovery-resumed.fa");
+    build_example_graph(instance, graph_file.clone());
+    let colormap_file = graph_file.with_extension("colors.dat");
+    let complete_colormap = std::fs::read(&colormap_file).unwrap();
+
+    // Simulate a build stopped before writing the header and the index of the colormap
+    let mut damaged_colormap = complete_colormap.clone();
+    damaged_colormap.truncate(damaged_colormap.len() - 4);
+    damaged_colormap[..64].fill(0);
+    std::fs::write(&colormap_file, &damaged_colormap).unwrap();
+
+    // The resumed build removes the files it creates, but not the supplied temp dir
+    config::KEEP_FILES.store(false, Ordering::Relaxed);
+    let user_file = run_temp_dir.join("notes.txt");
+    std::fs::write(&user_file, b"resumed build").unwrap();
+
+    *ggcat_api::debug::DEBUG_ASSEMBLER_FIRST_STEP.lock() = AssemblerStartingStep::HashesSorting;
+    *ggcat_api::debug::DEBUG_ASSEMBLER_LAST_STEP.lock() =
+        AssemblerStartingStep::MaximalUnitigsLinks;
+    let resumed_graph = build_example_graph(instance, graph_file);
+
+    assert_eq!(std::fs::read(&user_file).unwrap(), b"resumed build");
+    assert_eq!(std::fs::read(&colormap_file).unwrap(), complete_colormap);
+    assert_eq!(
+        read_canonical_unitigs(&resumed_graph),
+        read_canonical_unitigs(&full_graph)
+    );
+}
+
+#[test]
+fn query_resumed_from_colormap_reading_matches_full_run() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("query_resumed_from_colormap_reading_matches_full_run");
+    let _reset = SettingsReset;
+    let graph_file = build_example_graph(instance, dir.path("resume-graph.fa"));
+    let output_prefix = dir.path("resume-query");
+
+    // The intermediate files must be written to disk and kept to resume the query
+    config::KEEP_FILES.store(true, Ordering::Relaxed);
+    config::PREFER_MEMORY.store(false, Ordering::Relaxed);
+    let full_output = instance.query_graph(
+        graph_file,
+        example_input("query.fa"),
+        output_prefix.clone(),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        ColoredQueryOutputFormat::JsonLinesWithNumbers,
+        QueryOptions::default(),
+    );
+    config::KEEP_FILES.store(false, Ordering::Relaxed);
+    config::PREFER_MEMORY.store(true, Ordering::Relaxed);
+    let full_results = std::fs::read_to_string(&full_output).unwrap();
+
+    let run_temp_dir = query_run_temp_dir(&output_prefix);
+
+    assert!(instance
+        .resume_from_phase(
+            &run_temp_dir,
+            "minimizer-sorting",
+            THREADS_COUNT,
+            QueryOptions::default()
+        )
+        .is_err());
+
+    std::fs::remove_file(&full_output).unwrap();
+    let resumed_output = instance
+        .resume_from_phase(
+            &run_temp_dir,
+            "colormap reading",
+            THREADS_COUNT,
+            QueryOptions::default(),
+        )
+        .unwrap();
+    assert_eq!(resumed_output, full_output);
+
+    let mut full_lines: Vec<_> = full_results.lines().collect();
+    let resumed_results = std::fs::read_to_string(&resumed_output).unwrap();
+    let mut resumed_lines: Vec<_> = resumed_results.lines().collect();
+    full_lines.sort_unstable();
+    resumed_lines.sort_unstable();
+    assert!(!full_lines.is_empty());
+    assert_eq!(full_lines, resumed_lines);
+
+    // The colored counters are consumed by the resumed run
+    let err = instance
+        .resume_from_phase(
+            &run_temp_dir,
+            "colormap reading",
+            THREADS_COUNT,
+            QueryOptions::default(),
+        )
+        .unwrap_err();
+    assert!(err.contains("missing"));
+
+    let _ = std::fs::remove_dir_all(run_temp_dir);
+}
+
+#[test]
+fn colormap_reading_resumes_from_the_checkpointed_buckets() {
+    let _lock = PIPELINE_LOCK.lock();
+    let instance = test_instance();
+    let dir = TestDir::new("colormap_reading_resumes_from_the_checkpointed_buckets");
+    let _reset = SettingsReset;
+    let graph_file = build_example_graph(instance, dir.path("checkpoint-graph.fa"));
+    let output_prefix = dir.path("checkpoint-query");
+
+    // The colormap reading is checkpointed only when the temporary files are kept
+    config::KEEP_FILES.store(true, Ordering::Relaxed);
+    config::PREFER_MEMORY.store(false, Ordering::Relaxed);
+    let full_output = instance.query_graph(
+        graph_file,
+        example_input("query.fa"),
+        output_prefix.clone(),
+        K,
+        THREADS_COUNT,
+        false,
+        None,
+        true,
+        ColoredQueryOutputFormat::JsonLinesWithNumbers,
+        QueryOptions::default(),
+    );
+    let full_results = std::fs::read_to_string(&full_output).unwrap();
+
+    let run_temp_dir = query_run_temp_dir(&output_prefix);
+
+    let checkpoint_file = run_temp_dir.join("colormap-reading.checkpoint");
+    let completed: Vec<u16> = std::fs::read_to_string(&checkpoint_file)
+        .unwrap()
+        .lines()
+        .map(|line| line.parse().unwrap())
+        .collect();
+    assert!(completed.len() >= 2);
+
+    // Simulate a run interrupted after half of the buckets: the last checkpoint line is truncated
+    // and the parts of the other buckets are partially written
+    let (checkpointed, interrupted) = completed.split_at(completed.len() / 2);
+    let mut checkpoint: String = checkpointed
+        .iter()
+        .map(|bucket| format!("{}\n", bucket))
+        .collect();
+    checkpoint.push_str(&interrupted[0].to_string()[..1]);
+    std::fs::write(&checkpoint_file, checkpoint).unwrap();
+    for bucket in interrupted {
+        let part_file = run_temp_dir.join(format!("query_colors_part.{}", bucket));
+        let part = std::fs::read(&part_file).unwrap();
+        std::fs::write(&part_file, &part[..part.len() / 2]).unwrap();
+    }
+    // The checkpointed buckets must not be read again
+    for bucket in checkpointed {
+        std::fs::write(
+            run_temp_dir.join(format!("color_counters.{}", bucket)),
+            b"not a bucket",
+        )
+        .unwrap();
+    }
+
+    std::fs::remove_file(&full_output).unwrap();
+    let resumed_output = instance
+        .resume_from_phase(
+            &run_temp_dir,
+            "colormap reading",
+            THREADS_COUNT,
+            QueryOptions::default(),
+        )
+        .unwrap();
+
+    let mut full_lines: Vec<_> = full_results.lines().collect();
+    let resumed_results = std::fs::read_to_string(&resumed_output).unwrap();
+    let mut resumed_lines: Vec<_> = resumed_results.lines().collect();
+    full_lines.sort_unstable();
+    resumed_lines.sort_unstable();
+    assert!(!full_lines.is_empty());
+    assert_eq!(full_lines, resumed_lines);
+
+    let mut resumed_completed: Vec<u16> = std::fs::read_to_string(&checkpoint_file)
+        .unwrap()
+        .lines()
+        .map(|line| line.parse().unwrap())
+        .collect();
+    resumed_completed.sort_unstable();
+    let mut expected_completed = completed;
+    expected_completed.sort_unstable();
+    assert_eq!(resumed_completed, expected_completed);
+
+    let _ = std::fs::remove_dir_all(run_temp_dir);
+}
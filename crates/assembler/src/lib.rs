@@ -2,25 +2,40 @@
 
 use crate::pipeline::build_unitigs::build_unitigs;
 use crate::pipeline::compute_matchtigs::{compute_matchtigs_thread, MatchtigsStorageBackend};
+use crate::pipeline::connected_components::write_connected_components;
 use crate::pipeline::hashes_sorting::hashes_sorting;
 use crate::pipeline::links_compaction::links_compaction;
 use crate::pipeline::maximal_unitig_links::build_maximal_unitigs_links;
+use crate::pipeline::orphan_links::{check_orphan_links, is_orphan_links_check_enabled};
+use crate::pipeline::output_sink::{
+    create_final_unitigs_writer, take_output_sink, with_kmer_counts_outputs,
+    with_unitig_anchors_output,
+};
 use crate::pipeline::reorganize_reads::reorganize_reads;
+use crate::single_threaded::init_single_threaded_mode;
 use ::dynamic_dispatch::dynamic_dispatch;
 use assembler_kmers_merge::structs::RetType;
 use colors::colors_manager::ColorsManager;
 use colors::colors_manager::ColorsMergeManager;
+use colors::storage::serializer::rebuild_colormap_from_temp;
 use config::{
-    get_compression_level_info, get_memory_mode, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
-    INTERMEDIATE_COMPRESSION_LEVEL_FAST, INTERMEDIATE_COMPRESSION_LEVEL_SLOW, KEEP_FILES,
+    get_compression_level_info, get_memory_mode, keep_temp_files, PipelineSettings,
+    SavedCompressionLevels, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
+    INTERMEDIATE_COMPRESSION_LEVEL_FAST, INTERMEDIATE_COMPRESSION_LEVEL_SLOW,
     MAXIMUM_SECOND_BUCKETS_LOG, MINIMUM_LOG_DELTA_TIME,
 };
 use hashes::{HashFunctionFactory, MinimizerHashFunctionFactory};
+use io::compression_tuning::tune_intermediate_compression_level;
 use io::concurrent::structured_sequences::binary::StructSeqBinaryWriter;
 use io::concurrent::structured_sequences::fasta::FastaWriter;
 use io::concurrent::structured_sequences::StructuredSequenceWriter;
+use io::concurrent::temp_reads::creads_utils::take_corrupted_record_error;
+use io::open_files_limit::limit_buckets_count_log;
 use io::sequences_stream::general::GeneralSequenceBlockData;
+use io::sequences_stream::stranded_inputs::InputStrandMode;
+use io::unused_reads::{get_unused_reads_output, ReadsTracker};
 use io::{compute_stats_from_input_blocks, generate_bucket_names};
+use minimizer_bucketing::counters_analyzer::CountersAnalyzer;
 use parallel_processor::buckets::concurrent::BucketsThreadBuffer;
 use parallel_processor::buckets::writers::compressed_binary_writer::CompressedCheckpointSize;
 use parallel_processor::buckets::writers::lock_free_binary_writer::LockFreeBinaryWriter;
@@ -30,15 +45,38 @@ use parallel_processor::memory_fs::{MemoryFs, RemoveFileMode};
 use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use parallel_processor::utils::scoped_thread_local::ScopedThreadLocal;
 use std::fs::remove_file;
+use std::io::Write;
 use std::path::PathBuf;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Instant;
+use utils::phase_log::{init_phases, log_info, log_warning, print_phases_stats, start_phase};
 
 mod pipeline;
+mod single_threaded;
 mod structs;
 
+pub use assembler_kmers_merge::{
+    set_exact_kmers_mode, set_kmer_counting_mode, set_merge_hashmap_load_factor,
+    set_min_link_coverage, set_target_kmers_memory, KmerCountingMode,
+    DEFAULT_MERGE_HASHMAP_LOAD_FACTOR,
+};
+pub use minimizer_bucketing::buckets_skew::set_skewed_buckets_auto_retry;
+pub use pipeline::build_unitigs::DEBUG_MISMATCHED_UNITIG_JOINS;
 pub use pipeline::compute_matchtigs::MatchtigMode;
+pub use pipeline::connected_components::ComponentsOutputMode;
+pub use pipeline::graphs_concat::concat_graphs;
+pub use pipeline::orphan_links::{
+    find_orphan_links, set_orphan_links_check, OrphanLink, OrphanLinksCheck,
+    DEBUG_ORPHAN_LINKS_MISSING_UNITIGS,
+};
+pub use pipeline::output_sink::set_output_sink;
+pub use pipeline::reproducibility_hash::{
+    compute_reproducibility_hash, write_reproducibility_hash,
+};
+pub use pipeline::unitigs_sorting::sort_unitigs;
+pub use pipeline::unitigs_splitting::split_long_unitigs;
+pub use single_threaded::{set_single_threaded_mode, SingleThreadedMode};
 
 #[derive(Clone, PartialEq, PartialOrd)]
 pub enum AssemblerStartingStep {
@@ -53,7 +91,9 @@ pub enum AssemblerStartingStep {
 
 #[dynamic_dispatch(BucketingHash = [
     hashes::cn_nthash::CanonicalNtHashIteratorFactory,
-    #[cfg(not(feature = "devel-build"))] hashes::fw_nthash::ForwardNtHashIteratorFactory
+    #[cfg(not(feature = "devel-build"))] hashes::fw_nthash::ForwardNtHashIteratorFactory,
+    #[cfg(not(feature = "devel-build"))] hashes::cn_nthash::ordered::CanonicalNtHashIteratorFactory,
+    #[cfg(not(feature = "devel-build"))] hashes::fw_nthash::ordered::ForwardNtHashIteratorFactory
 ], MergingHash = [
     #[cfg(not(feature = "devel-build"))] hashes::fw_seqhash::u16::ForwardSeqHashFactory,
     #[cfg(not(feature = "devel-build"))] hashes::fw_seqhash::u32::ForwardSeqHashFactory,
@@ -66,6 +106,10 @@ pub enum AssemblerStartingStep {
     #[cfg(not(feature = "devel-build"))] hashes::cn_seqhash::u32::CanonicalSeqHashFactory,
     #[cfg(not(feature = "devel-build"))] hashes::cn_seqhash::u64::CanonicalSeqHashFactory,
     hashes::cn_seqhash::u128::CanonicalSeqHashFactory,
+    #[cfg(not(feature = "devel-build"))] hashes::cn_lex_seqhash::u16::CanonicalSeqHashFactory,
+    #[cfg(not(feature = "devel-build"))] hashes::cn_lex_seqhash::u32::CanonicalSeqHashFactory,
+    #[cfg(not(feature = "devel-build"))] hashes::cn_lex_seqhash::u64::CanonicalSeqHashFactory,
+    #[cfg(not(feature = "devel-build"))] hashes::cn_lex_seqhash::u128::CanonicalSeqHashFactory,
     #[cfg(not(feature = "devel-build"))] hashes::cn_rkhash::u32::CanonicalRabinKarpHashFactory,
     #[cfg(not(feature = "devel-build"))] hashes::cn_rkhash::u64::CanonicalRabinKarpHashFactory,
     #[cfg(not(feature = "devel-build"))] hashes::cn_rkhash::u128::CanonicalRabinKarpHashFactory,
@@ -83,39 +127,103 @@ pub fn run_assembler<
     step: AssemblerStartingStep,
     last_step: AssemblerStartingStep,
     input_blocks: Vec<GeneralSequenceBlockData>,
+    strand_mode: InputStrandMode,
     color_names: &[String],
     output_file: PathBuf,
     temp_dir: Option<PathBuf>,
     threads_count: usize,
     min_multiplicity: usize,
+    target_file: Option<PathBuf>,
     buckets_count_log: Option<usize>,
     loopit_number: Option<usize>,
     default_compression_level: Option<u32>,
     generate_maximal_unitigs_links: bool,
     compute_tigs_mode: Option<MatchtigMode>,
+    components_output: Option<ComponentsOutputMode>,
     only_bstats: bool,
-) -> PathBuf {
+    settings: PipelineSettings,
+) -> Result<PathBuf, String> {
     let temp_dir = temp_dir.unwrap_or(PathBuf::new());
 
-    PHASES_TIMES_MONITOR.write().init();
+    init_phases();
+    // Discards the corrupted records of a previous run
+    let _ = take_corrupted_record_error();
 
     let file_stats = compute_stats_from_input_blocks(&input_blocks);
 
-    let buckets_count_log = buckets_count_log.unwrap_or_else(|| file_stats.best_buckets_count_log);
+    let threads_count = if init_single_threaded_mode(threads_count) {
+        log_info("Building in single-threaded mode".to_string());
+        1
+    } else {
+        threads_count
+    };
 
+    let buckets_count_log = limit_buckets_count_log(
+        buckets_count_log.unwrap_or_else(|| file_stats.best_buckets_count_log),
+        settings.max_open_files,
+    );
+
+    let _saved_compression_levels = SavedCompressionLevels::save();
     if let Some(default_compression_level) = default_compression_level {
         INTERMEDIATE_COMPRESSION_LEVEL_SLOW.store(default_compression_level, Ordering::Relaxed);
         INTERMEDIATE_COMPRESSION_LEVEL_FAST.store(default_compression_level, Ordering::Relaxed);
+    } else if let Some(level) = tune_intermediate_compression_level(&input_blocks, &temp_dir) {
+        log_info(format!(
+            "Selected intermediate compression level: {}",
+            level
+        ));
+        INTERMEDIATE_COMPRESSION_LEVEL_SLOW.store(level, Ordering::Relaxed);
     }
 
     let buckets_count = 1 << buckets_count_log;
 
-    let global_colors_table = Arc::new(
-        AssemblerColorsManager::ColorsMergeManagerType::create_colors_table(
-            output_file.with_extension("colors.dat"),
-            color_names,
-        ),
-    );
+    // The colormap is written by the kmers merge, a build resumed after it recovers the colormap
+    // from the log kept in the temp dir instead of creating it again
+    let global_colors_table = if step <= AssemblerStartingStep::KmersMerge {
+        Some(Arc::new(
+            AssemblerColorsManager::ColorsMergeManagerType::create_colors_table(
+                output_file.with_extension("colors.dat"),
+                color_names,
+                k,
+                temp_dir.as_path(),
+                settings.random_seed,
+            ),
+        ))
+    } else {
+        if AssemblerColorsManager::COLORS_ENABLED {
+            rebuild_colormap_from_temp(&temp_dir)?;
+        }
+        None
+    };
+
+    // The reads are tracked from the minimizer bucketing to the kmers merge, so the unused reads
+    // are written only by the builds that run both steps
+    let reads_tracker = match get_unused_reads_output() {
+        Some(unused_reads_file) if step <= AssemblerStartingStep::MinimizerBucketing => Some((
+            ReadsTracker::start(&unused_reads_file, input_blocks.len()),
+            input_blocks.clone(),
+            unused_reads_file,
+        )),
+        Some(_) => {
+            log_warning(
+                "The unused reads are not written by the builds resumed after the minimizer bucketing"
+                    .to_string(),
+            );
+            None
+        }
+        None => None,
+    };
+    let finish_reads_tracking =
+        |reads_tracker: Option<(Arc<ReadsTracker>, Vec<GeneralSequenceBlockData>, PathBuf)>| {
+            if let Some((reads_tracker, input_blocks, unused_reads_file)) = reads_tracker {
+                let unused_count = reads_tracker.finish(&input_blocks, k);
+                log_info(format!(
+                    "Unused reads: {} written to {}",
+                    unused_count,
+                    unused_reads_file.display()
+                ));
+            }
+        };
 
     let (buckets, counters) = if step <= AssemblerStartingStep::MinimizerBucketing {
         assembler_minimizer_bucketing::static_dispatch::minimizer_bucketing::<
@@ -128,11 +236,15 @@ pub fn run_assembler<
             threads_count,
             k,
             m,
+            strand_mode,
+            settings,
         )
     } else {
+        let counters = temp_dir.join("buckets-counters.dat");
+        CountersAnalyzer::load_from_file(&counters, false).check_kmer_length(k)?;
         (
             generate_bucket_names(temp_dir.join("bucket"), buckets_count, None),
-            temp_dir.join("buckets-counters.dat"),
+            counters,
         )
     };
 
@@ -142,10 +254,9 @@ pub fn run_assembler<
     );
 
     if last_step <= AssemblerStartingStep::MinimizerBucketing {
-        PHASES_TIMES_MONITOR
-            .write()
-            .print_stats("Completed minimizer bucketing.".to_string());
-        return PathBuf::new();
+        finish_reads_tracking(reads_tracker);
+        print_phases_stats("Completed minimizer bucketing.".to_string());
+        return Ok(PathBuf::new());
     } else {
         MemoryFs::flush_all_to_disk();
         MemoryFs::free_memory();
@@ -166,20 +277,23 @@ pub fn run_assembler<
                 m,
             );
         });
-        return PathBuf::new();
+        finish_reads_tracking(reads_tracker);
+        return Ok(PathBuf::new());
     }
 
     let RetType { sequences, hashes } = if step <= AssemblerStartingStep::KmersMerge {
         assembler_kmers_merge::kmers_merge::<BucketingHash, MergingHash, AssemblerColorsManager, _>(
             buckets,
             counters,
-            global_colors_table.clone(),
+            global_colors_table.clone().unwrap(),
             buckets_count,
             min_multiplicity,
+            target_file.as_deref(),
             temp_dir.as_path(),
             k,
             m,
             threads_count,
+            settings,
         )
     } else {
         RetType {
@@ -187,30 +301,38 @@ pub fn run_assembler<
             hashes: generate_bucket_names(temp_dir.join("hashes"), buckets_count, None),
         }
     };
+    // All the reads with kmers in the graph are marked by the kmers merge
+    finish_reads_tracking(reads_tracker);
+    // The kmers of a truncated bucket would be missing from the graph
+    take_corrupted_record_error()?;
+
     if last_step <= AssemblerStartingStep::KmersMerge {
-        PHASES_TIMES_MONITOR
-            .write()
-            .print_stats("Completed kmers merge.".to_string());
-        return PathBuf::new();
+        print_phases_stats("Completed kmers merge.".to_string());
+        // The kept buckets are read from disk by a build resumed from the next step
+        MemoryFs::flush_all_to_disk();
+        return Ok(PathBuf::new());
     } else {
         MemoryFs::flush_all_to_disk();
         MemoryFs::free_memory();
     }
 
-    AssemblerColorsManager::ColorsMergeManagerType::print_color_stats(&global_colors_table);
-
-    drop(global_colors_table);
+    if let Some(global_colors_table) = global_colors_table {
+        AssemblerColorsManager::ColorsMergeManagerType::print_color_stats(&global_colors_table);
+    }
 
     let mut links = if step <= AssemblerStartingStep::HashesSorting {
-        hashes_sorting::<MergingHash, _>(hashes, temp_dir.as_path(), buckets_count)
+        hashes_sorting::<MergingHash, _>(
+            hashes,
+            temp_dir.as_path(),
+            buckets_count,
+            settings.random_seed,
+        )
     } else {
         generate_bucket_names(temp_dir.join("links"), buckets_count, None)
     };
     if last_step <= AssemblerStartingStep::HashesSorting {
-        PHASES_TIMES_MONITOR
-            .write()
-            .print_stats("Hashes sorting.".to_string());
-        return PathBuf::new();
+        print_phases_stats("Hashes sorting.".to_string());
+        return Ok(PathBuf::new());
     } else {
         MemoryFs::flush_all_to_disk();
         MemoryFs::free_memory();
@@ -258,9 +380,7 @@ pub fn run_assembler<
             );
         }
 
-        PHASES_TIMES_MONITOR
-            .write()
-            .start_phase("phase: links compaction".to_string());
+        start_phase("phase: links compaction".to_string());
 
         let mut log_timer = Instant::now();
 
@@ -293,6 +413,7 @@ pub fn run_assembler<
                 // &links_manager,
                 &links_scoped_buffer,
                 &results_map_scoped_buffer,
+                settings.random_seed,
             );
 
             if do_logging {
@@ -317,7 +438,7 @@ pub fn run_assembler<
             MemoryFs::remove_file(
                 &link_file,
                 RemoveFileMode::Remove {
-                    remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                    remove_fs: !keep_temp_files(),
                 },
             )
             .unwrap();
@@ -328,26 +449,29 @@ pub fn run_assembler<
     };
 
     if last_step <= AssemblerStartingStep::LinksCompaction {
-        PHASES_TIMES_MONITOR
-            .write()
-            .print_stats("Links Compaction.".to_string());
-        return PathBuf::new();
+        print_phases_stats("Links Compaction.".to_string());
+        return Ok(PathBuf::new());
     } else {
         MemoryFs::flush_all_to_disk();
         MemoryFs::free_memory();
     }
 
-    let final_unitigs_file = StructuredSequenceWriter::new(
-        match output_file.extension() {
-            Some(ext) => match ext.to_string_lossy().to_string().as_str() {
-                "lz4" => FastaWriter::new_compressed_lz4(&output_file, 2),
-                "gz" => FastaWriter::new_compressed_gzip(&output_file, 2),
-                _ => FastaWriter::new_plain(&output_file),
-            },
-            None => FastaWriter::new_plain(&output_file),
-        },
-        k,
-    );
+    // With links the unitigs are written only to the temporary file,
+    // the final output is written in the links phase
+    let final_unitigs_file = if generate_maximal_unitigs_links && compute_tigs_mode.is_none() {
+        None
+    } else {
+        Some(with_unitig_anchors_output::<MergingHash, _, _>(
+            with_kmer_counts_outputs(
+                StructuredSequenceWriter::new(
+                    create_final_unitigs_writer(&output_file, take_output_sink()),
+                    k,
+                ),
+                &output_file,
+            ),
+            &output_file,
+        ))
+    };
 
     // Temporary file to store maximal unitigs data without links info, if further processing is requested
     let compressed_temp_unitigs_file =
@@ -388,7 +512,7 @@ pub fn run_assembler<
                 sequences,
                 reads_map,
                 temp_dir.as_path(),
-                &final_unitigs_file,
+                final_unitigs_file.as_ref().unwrap(),
                 buckets_count,
             )
         }
@@ -403,10 +527,8 @@ pub fn run_assembler<
     };
 
     if last_step <= AssemblerStartingStep::ReorganizeReads {
-        PHASES_TIMES_MONITOR
-            .write()
-            .print_stats("Reorganize reads.".to_string());
-        return PathBuf::new();
+        print_phases_stats("Reorganize reads.".to_string());
+        return Ok(PathBuf::new());
     } else {
         MemoryFs::flush_all_to_disk();
         MemoryFs::free_memory();
@@ -433,7 +555,7 @@ pub fn run_assembler<
                 reorganized_reads,
                 unitigs_map,
                 temp_dir.as_path(),
-                &final_unitigs_file,
+                final_unitigs_file.as_ref().unwrap(),
                 k,
             );
         }
@@ -462,7 +584,7 @@ pub fn run_assembler<
                             k,
                             threads_count,
                             matchtigs_receiver,
-                            &final_unitigs_file,
+                            final_unitigs_file.as_ref().unwrap(),
                             compute_tigs_mode,
                         );
                     })
@@ -482,18 +604,25 @@ pub fn run_assembler<
 
                 handle.join().unwrap();
             } else if generate_maximal_unitigs_links {
-                final_unitigs_file.finalize();
-
-                let final_unitigs_file = StructuredSequenceWriter::new(
-                    match output_file.extension() {
-                        Some(ext) => match ext.to_string_lossy().to_string().as_str() {
-                            "lz4" => FastaWriter::new_compressed_lz4(&output_file, 2),
-                            "gz" => FastaWriter::new_compressed_gzip(&output_file, 2),
-                            _ => FastaWriter::new_plain(&output_file),
-                        },
-                        None => FastaWriter::new_plain(&output_file),
-                    },
-                    k,
+                // The graph is read again after the links phase by the post-build steps, with a sink
+                // it is kept in a temporary file and sent to the sink once they are completed
+                let read_again = components_output.is_some() || is_orphan_links_check_enabled();
+                let (graph_file, streamed_sink, deferred_sink) = match take_output_sink() {
+                    Some(sink) if read_again => {
+                        (temp_dir.join("final_unitigs.fa"), None, Some(sink))
+                    }
+                    sink => (output_file.clone(), sink, None),
+                };
+
+                let final_unitigs_file = with_unitig_anchors_output::<MergingHash, _, _>(
+                    with_kmer_counts_outputs(
+                        StructuredSequenceWriter::new(
+                            create_final_unitigs_writer(&graph_file, streamed_sink),
+                            k,
+                        ),
+                        &output_file,
+                    ),
+                    &output_file,
                 );
 
                 build_maximal_unitigs_links::<
@@ -503,19 +632,38 @@ pub fn run_assembler<
                     FastaWriter<_, _>,
                 >(temp_path, temp_dir.as_path(), &final_unitigs_file, k);
                 final_unitigs_file.finalize();
+
+                if let Some(components_output) = components_output {
+                    write_connected_components(&graph_file, &output_file, components_output);
+                }
+
+                let orphan_links_check = check_orphan_links(&graph_file);
+
+                if let Some(mut sink) = deferred_sink {
+                    let mut graph = std::fs::File::open(&graph_file).unwrap();
+                    std::io::copy(&mut graph, &mut sink)
+                        .and_then(|_| sink.flush())
+                        .map_err(|err| {
+                            format!("Cannot write the graph to the output sink: {}", err)
+                        })?;
+                    drop(graph);
+                    let _ = remove_file(&graph_file);
+                }
+
+                orphan_links_check?;
             }
-        } else {
+        } else if let Some(final_unitigs_file) = final_unitigs_file {
             final_unitigs_file.finalize();
         }
-    } else {
+    } else if let Some(final_unitigs_file) = final_unitigs_file {
         final_unitigs_file.finalize();
     }
 
+    take_corrupted_record_error()?;
+
     let _ = std::fs::remove_dir(temp_dir.as_path());
 
-    PHASES_TIMES_MONITOR
-        .write()
-        .print_stats("Compacted De Bruijn graph construction completed.".to_string());
+    print_phases_stats("Compacted De Bruijn graph construction completed.".to_string());
 
-    output_file
+    Ok(output_file)
 }
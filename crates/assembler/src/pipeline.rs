@@ -1,6 +1,13 @@
 pub mod build_unitigs;
 pub mod compute_matchtigs;
+pub mod connected_components;
+pub mod graphs_concat;
 pub mod hashes_sorting;
 pub mod links_compaction;
 pub mod maximal_unitig_links;
+pub mod orphan_links;
+pub mod output_sink;
 pub mod reorganize_reads;
+pub mod reproducibility_hash;
+pub mod unitigs_sorting;
+pub mod unitigs_splitting;
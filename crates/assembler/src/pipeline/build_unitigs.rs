@@ -1,8 +1,9 @@
 use crate::pipeline::reorganize_reads::ReorganizedReadsExtraData;
+use crate::single_threaded::for_each_bucket;
 use colors::colors_manager::color_types::PartialUnitigsColorStructure;
 use colors::colors_manager::ColorsMergeManager;
 use colors::colors_manager::{color_types, ColorsManager};
-use config::{DEFAULT_OUTPUT_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES};
+use config::{keep_temp_files, DEFAULT_OUTPUT_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT};
 use hashbrown::HashMap;
 use hashes::{HashFunctionFactory, HashableSequence, MinimizerHashFunctionFactory};
 use io::compressed_read::CompressedReadIndipendent;
@@ -18,10 +19,9 @@ use parallel_processor::buckets::readers::compressed_binary_reader::CompressedBi
 use parallel_processor::buckets::readers::lock_free_binary_reader::LockFreeBinaryReader;
 use parallel_processor::buckets::readers::BucketReader;
 use parallel_processor::memory_fs::RemoveFileMode;
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
-use rayon::prelude::*;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering};
+use utils::phase_log::{log_warning, start_phase};
 
 #[cfg(feature = "support_kmer_counters")]
 use io::concurrent::structured_sequences::SequenceAbundance;
@@ -33,6 +33,15 @@ struct FinalUnitigInfo {
     flags: UnitigFlags,
 }
 
+/// Debug only: handle the unitig parts joined across buckets as if they did not share their kmer,
+/// as after a hash collision, to exercise the handling of a mismatching join
+pub static DEBUG_MISMATCHED_UNITIG_JOINS: AtomicBool = AtomicBool::new(false);
+
+// True if the sequence ends with the k given bases
+fn ends_with_kmer(sequence: &[u8], kmer: impl Iterator<Item = u8>, k: usize) -> bool {
+    sequence.len() >= k && sequence[sequence.len() - k..].iter().copied().eq(kmer)
+}
+
 pub trait FastaCompatibleRead {
     type IntermediateData;
     fn write_unpacked_to_buffer(&self, buffer: &mut Vec<u8>) -> Self::IntermediateData;
@@ -78,9 +87,7 @@ pub fn build_unitigs<
     out_file: &StructuredSequenceWriter<PartialUnitigsColorStructure<H, MH, CX>, (), BK>,
     k: usize,
 ) {
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase("phase: unitigs building".to_string());
+    start_phase("phase: unitigs building".to_string());
 
     read_buckets_files.sort();
     unitig_map_files.sort();
@@ -91,10 +98,7 @@ pub fn build_unitigs<
         .collect();
 
     rayon::scope(|_s| {
-        inputs
-            .par_iter()
-            .enumerate()
-            .for_each(|(_index, (read_file, unitigs_map_file))| {
+        for_each_bucket(&inputs, |(read_file, unitigs_map_file)| {
                 let mut tmp_final_unitigs_buffer =
                     FastaWriterConcurrentBuffer::new(out_file, DEFAULT_OUTPUT_BUFFER_SIZE, true);
 
@@ -108,7 +112,7 @@ pub fn build_unitigs<
                 let mut unitigs_map_reader = LockFreeBinaryReader::new(
                     &unitigs_map_file,
                     RemoveFileMode::Remove {
-                        remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                        remove_fs: !keep_temp_files(),
                     },
                     DEFAULT_PREFETCH_AMOUNT,
                 );
@@ -192,7 +196,7 @@ pub fn build_unitigs<
                 CompressedBinaryReader::new(
                     read_file,
                     RemoveFileMode::Remove {
-                        remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                        remove_fs: !keep_temp_files(),
                     },
                     DEFAULT_PREFETCH_AMOUNT,
                 )
@@ -220,20 +224,14 @@ pub fn build_unitigs<
                     final_sequences.nq_group_by(|_a, b| !b.as_ref().unwrap().1.is_start)
                 {
                     let is_backwards = !sequence[0].as_ref().unwrap().1.flags.is_forward();
-                    let is_circular = sequence[0].as_ref().unwrap().1.is_circular;
+                    let mut is_circular = sequence[0].as_ref().unwrap().1.is_circular;
 
                     temp_sequence.clear();
                     CX::ColorsMergeManagerType::<H, MH>::reset_unitig_color_structure(
                         &mut final_unitig_color,
                     );
                     #[cfg(feature = "support_kmer_counters")]
-                    let mut abundance = SequenceAbundance {
-                        first: 0,
-                        sum: 0,
-                        last: 0,
-                    };
-                    #[cfg(feature = "support_kmer_counters")]
-                    let mut prev_last = 0;
+                    let mut abundance = SequenceAbundance::new();
 
                     let mut is_first = true;
 
@@ -262,10 +260,7 @@ pub fn build_unitigs<
                                     0,
                                 );
                                 #[cfg(feature = "support_kmer_counters")]
-                                {
-                                    abundance.first = counters.last;
-                                    abundance.sum += counters.sum;
-                                }
+                                abundance.join_counts::<true>(&counters.counts, 0);
                             } else {
                                 temp_sequence.extend(compr_read.as_bases_iter());
                                 CX::ColorsMergeManagerType::<H, MH>::join_structures::<false>(
@@ -275,13 +270,35 @@ pub fn build_unitigs<
                                     0,
                                 );
                                 #[cfg(feature = "support_kmer_counters")]
-                                {
-                                    abundance.first = counters.first;
-                                    abundance.sum += counters.sum;
-                                }
+                                abundance.join_counts::<false>(&counters.counts, 0);
                             }
                             is_first = false;
                         } else {
+                            // The part of the unitig from the next bucket must start with the last kmer
+                            // of the previous part, otherwise the bucket boundary was stitched off by some bases
+                            let shared_kmer_matches = compr_read.bases_count() >= k
+                                && if flags.is_reverse_complemented() {
+                                    let shared_kmer = compr_read.sub_slice(
+                                        compr_read.bases_count() - k..compr_read.bases_count(),
+                                    );
+                                    ends_with_kmer(
+                                        &temp_sequence,
+                                        shared_kmer.as_reverse_complement_bases_iter(),
+                                        k,
+                                    )
+                                } else {
+                                    let shared_kmer = compr_read.sub_slice(0..k);
+                                    ends_with_kmer(&temp_sequence, shared_kmer.as_bases_iter(), k)
+                                };
+                            if !shared_kmer_matches
+                                || DEBUG_MISMATCHED_UNITIG_JOINS.load(Ordering::Relaxed)
+                            {
+                                // The parts were joined by a hash collision, the unitig ends at the last matching part
+                                log_warning(format!("Warning the parts of a unitig joined across buckets do not share a kmer, this is a bug or an hash collision! The unitig is truncated to {} bases", temp_sequence.len()));
+                                is_circular = false;
+                                break;
+                            }
+
                             if flags.is_reverse_complemented() {
                                 temp_sequence.extend(
                                     compr_read
@@ -295,9 +312,8 @@ pub fn build_unitigs<
                                     &color_extra_buffer.0,
                                     1,
                                 );
-                                #[cfg(feature = "support_kmer_counters")] {
-                                    abundance.sum += counters.sum - counters.last;
-                                }
+                                #[cfg(feature = "support_kmer_counters")]
+                                abundance.join_counts::<true>(&counters.counts, 1);
                             } else {
                                 temp_sequence.extend(
                                     compr_read
@@ -311,24 +327,17 @@ pub fn build_unitigs<
                                     &color_extra_buffer.0,
                                     1,
                                 );
-                                #[cfg(feature = "support_kmer_counters")] {
-                                    abundance.sum += counters.sum - counters.first;
-                                }
+                                #[cfg(feature = "support_kmer_counters")]
+                                abundance.join_counts::<false>(&counters.counts, 1);
                             }
                         }
-                        #[cfg(feature = "support_kmer_counters")] {
-                            prev_last = abundance.last;
-                            abundance.last = counters.last;
-                        }
                     }
 
                     // In case of circular unitigs, remove an extra ending base
                     if is_circular {
                         temp_sequence.pop();
-                        #[cfg(feature = "support_kmer_counters")] {
-                            abundance.sum -= abundance.last;
-                            abundance.last = prev_last;
-                        }
+                        #[cfg(feature = "support_kmer_counters")]
+                        abundance.pop_count();
 
                         CX::ColorsMergeManagerType::<H, MH>::pop_base(&mut final_unitig_color);
                     }
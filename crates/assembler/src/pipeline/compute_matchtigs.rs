@@ -20,11 +20,11 @@ use libmatchtigs::{
     EulertigAlgorithm, EulertigAlgorithmConfiguration, MatchtigEdgeData, PathtigAlgorithm,
 };
 use libmatchtigs::{GreedytigAlgorithm, GreedytigAlgorithmConfiguration, TigAlgorithm};
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::sync::Arc;
 use traitgraph_algo::dijkstra::DijkstraWeightedEdgeData;
+use utils::phase_log::start_phase;
 
 #[cfg(feature = "support_kmer_counters")]
 use io::concurrent::structured_sequences::SequenceAbundance;
@@ -345,9 +345,7 @@ pub fn compute_matchtigs_thread<
         MatchtigMode::PathTigs => "pathtigs",
     };
 
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase(format!("phase: {} building [step1]", phase_name));
+    start_phase(format!("phase: {} building [step1]", phase_name));
 
     /* assign weight to each edge */
     for edge_index in graph.edge_indices_copied() {
@@ -386,9 +384,7 @@ pub fn compute_matchtigs_thread<
         }
     };
 
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase(format!("phase: {} building [step2]", phase_name));
+    start_phase(format!("phase: {} building [step2]", phase_name));
 
     let mut output_buffer =
         FastaWriterConcurrentBuffer::new(&out_file, DEFAULT_OUTPUT_BUFFER_SIZE, true);
@@ -441,11 +437,15 @@ pub fn compute_matchtigs_thread<
             );
         }
         #[cfg(feature = "support_kmer_counters")]
-        let mut abundance = SequenceAbundance {
-            first: handle.2.first,
-            sum: handle.2.sum,
-            last: handle.2.last,
-        };
+        let mut abundance = SequenceAbundance::new();
+        #[cfg(feature = "support_kmer_counters")]
+        {
+            if first_data.is_forwards() {
+                abundance.join_counts::<false>(&handle.2.counts, 0);
+            } else {
+                abundance.join_counts::<true>(&handle.2.counts, 0);
+            }
+        }
 
         let mut previous_data = first_data;
         for edge in walk.iter().skip(1) {
@@ -478,10 +478,7 @@ pub fn compute_matchtigs_thread<
                     kmer_offset,
                 );
                 #[cfg(feature = "support_kmer_counters")]
-                {
-                    abundance.sum += handle.2.sum - handle.2.first;
-                    abundance.last = handle.2.last;
-                }
+                abundance.join_counts::<false>(&handle.2.counts, kmer_offset);
             } else {
                 read_buffer.extend(
                     next_sequence
@@ -495,10 +492,7 @@ pub fn compute_matchtigs_thread<
                     kmer_offset,
                 );
                 #[cfg(feature = "support_kmer_counters")]
-                {
-                    abundance.sum += handle.2.sum - handle.2.last;
-                    abundance.last = handle.2.last;
-                }
+                abundance.join_counts::<true>(&handle.2.counts, kmer_offset);
             }
         }
 
@@ -0,0 +1,293 @@
+use hashbrown::HashMap;
+use io::concurrent::structured_sequences::fasta::FastaWriter;
+use io::concurrent::structured_sequences::StructuredSequenceBackend;
+use io::sequences_reader::SequencesReader;
+use io::unitig_links::parse_header_links;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use utils::phase_log::{log_warning, start_phase};
+
+// Limit to the number of per-component files kept open at the same time,
+// if there are more components the graph is read again for each group of them
+const MAX_OPEN_COMPONENT_FILES: usize = 256;
+
+/// How the unitigs connected components are reported
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ComponentsOutputMode {
+    /// Add a component=<id> tag to each unitig header
+    HeaderTag,
+    /// Additionally write the unitigs of each component to a separate file
+    SeparateFiles,
+    /// Both add the header tag and write the separate files
+    HeaderTagAndSeparateFiles,
+}
+
+impl ComponentsOutputMode {
+    fn header_tag(&self) -> bool {
+        *self != ComponentsOutputMode::SeparateFiles
+    }
+
+    fn separate_files(&self) -> bool {
+        *self != ComponentsOutputMode::HeaderTag
+    }
+}
+
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn ensure_size(&mut self, index: usize) {
+        if index >= self.parents.len() {
+            let old_len = self.parents.len();
+            self.parents.extend(old_len..=index);
+        }
+    }
+
+    fn find(&mut self, mut index: usize) -> usize {
+        while self.parents[index] != index {
+            // Path halving
+            self.parents[index] = self.parents[self.parents[index]];
+            index = self.parents[index];
+        }
+        index
+    }
+
+    fn union(&mut self, first: usize, second: usize) {
+        let first = self.find(first);
+        let second = self.find(second);
+        // Keep the smallest index as the root, so that the numbering does not depend on the links order
+        if first < second {
+            self.parents[second] = first;
+        } else {
+            self.parents[first] = second;
+        }
+    }
+}
+
+/// Assigns to each unitig the index of its connected component, using the links in the graph headers.
+/// The components are numbered in order of their smallest unitig id
+pub fn compute_connected_components(graph_file: impl AsRef<Path>) -> Vec<usize> {
+    let mut union_find = UnionFind { parents: vec![] };
+    let mut sequence_index = 0;
+
+    SequencesReader::new().process_file_extended(
+        graph_file.as_ref(),
+        |seq| {
+            let (id, links) = parse_header_links(seq.ident_data, sequence_index as u64);
+            let id = id as usize;
+            sequence_index += 1;
+
+            union_find.ensure_size(id);
+            for link in links {
+                let other = link.to_id as usize;
+                union_find.ensure_size(other);
+                union_find.union(id, other);
+            }
+        },
+        None,
+        true,
+        false,
+    );
+
+    let mut roots_map = HashMap::new();
+    (0..union_find.parents.len())
+        .map(|unitig| {
+            let root = union_find.find(unitig);
+            let next_component = roots_map.len();
+            *roots_map.entry(root).or_insert(next_component)
+        })
+        .collect()
+}
+
+pub(crate) fn create_fasta_writer(path: &Path) -> FastaWriter<(), ()> {
+    match path.extension() {
+        Some(ext) => match ext.to_string_lossy().to_string().as_str() {
+            "lz4" => FastaWriter::new_compressed_lz4(path, 2),
+            "gz" => FastaWriter::new_compressed_gzip(path, 2),
+            _ => FastaWriter::new_plain(path),
+        },
+        None => FastaWriter::new_plain(path),
+    }
+}
+
+/// Inserts a suffix before all the extensions of the file name (graph.fa.lz4 => graph.<suffix>.fa.lz4)
+fn get_suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    let new_name = match file_name.find('.') {
+        Some(position) => format!(
+            "{}.{}{}",
+            &file_name[..position],
+            suffix,
+            &file_name[position..]
+        ),
+        None => format!("{}.{}", file_name, suffix),
+    };
+    path.with_file_name(new_name)
+}
+
+/// Obtains the path of the file containing the unitigs of a single connected component
+pub fn get_component_file(graph_file: impl AsRef<Path>, component: usize) -> PathBuf {
+    get_suffixed_path(graph_file.as_ref(), &format!("component{}", component))
+}
+
+fn write_sequence_with_tag(buffer: &mut Vec<u8>, ident: &[u8], seq: &[u8], component: usize) {
+    buffer.extend_from_slice(ident);
+    writeln!(buffer, " component={}", component).unwrap();
+    buffer.extend_from_slice(seq);
+    buffer.push(b'\n');
+}
+
+fn write_sequence(buffer: &mut Vec<u8>, ident: &[u8], seq: &[u8]) {
+    buffer.extend_from_slice(ident);
+    buffer.push(b'\n');
+    buffer.extend_from_slice(seq);
+    buffer.push(b'\n');
+}
+
+/// Groups the unitigs of a graph with links by connected component, tagging their headers
+/// and/or writing each component to a separate file named after output_file, that differs from
+/// the graph file when the graph is sent to an output sink. Returns the components count
+pub fn write_connected_components(
+    graph_file: impl AsRef<Path>,
+    output_file: impl AsRef<Path>,
+    mode: ComponentsOutputMode,
+) -> usize {
+    start_phase("phase: connected components".to_string());
+
+    let graph_file = graph_file.as_ref();
+    let output_file = output_file.as_ref();
+    let components = compute_connected_components(graph_file);
+    let components_count = components.iter().max().map(|c| *c + 1).unwrap_or(0);
+
+    let get_component = |ident: &[u8], sequence_index: usize| {
+        let (id, _) = parse_header_links(ident, sequence_index as u64);
+        components[id as usize]
+    };
+
+    let mut buffer = vec![];
+
+    if mode.header_tag() {
+        let temp_file = get_suffixed_path(graph_file, "components-tmp");
+        {
+            let mut writer = create_fasta_writer(&temp_file);
+            let mut sequence_index = 0;
+            SequencesReader::new().process_file_extended(
+                graph_file,
+                |seq| {
+                    let component = get_component(seq.ident_data, sequence_index);
+                    sequence_index += 1;
+                    write_sequence_with_tag(&mut buffer, seq.ident_data, seq.seq, component);
+                    writer.flush_temp_buffer(&mut buffer);
+                },
+                None,
+                true,
+                false,
+            );
+        }
+        std::fs::rename(&temp_file, graph_file).unwrap();
+    }
+
+    if mode.separate_files() {
+        if components_count > MAX_OPEN_COMPONENT_FILES {
+            log_warning(format!(
+                "Warning: writing {} connected components in separate files, this may take some time",
+                components_count
+            ));
+        }
+
+        for first_component in (0..components_count).step_by(MAX_OPEN_COMPONENT_FILES) {
+            let last_component = (first_component + MAX_OPEN_COMPONENT_FILES).min(components_count);
+
+            let mut writers: Vec<_> = (first_component..last_component)
+                .map(|component| create_fasta_writer(&get_component_file(output_file, component)))
+                .collect();
+
+            let mut sequence_index = 0;
+            SequencesReader::new().process_file_extended(
+                graph_file,
+                |seq| {
+                    let component = get_component(seq.ident_data, sequence_index);
+                    sequence_index += 1;
+                    if (first_component..last_component).contains(&component) {
+                        write_sequence(&mut buffer, seq.ident_data, seq.seq);
+                        writers[component - first_component].flush_temp_buffer(&mut buffer);
+                    }
+                },
+                None,
+                true,
+                false,
+            );
+        }
+    }
+
+    components_count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ComponentsOutputMode;
+    use super::{compute_connected_components, get_component_file, write_connected_components};
+    use std::io::{BufRead, BufReader, Write};
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn two_components_graph() {
+        let temp_dir = TestDir::new("two_components_graph");
+        let graph_file = temp_dir.path("components.fa");
+
+        // Unitigs 0, 2, 3 are connected through links, 1 and 4 form the second component
+        let unitigs: [(&str, &str); 5] = [
+            ("0 LN:i:5 L:+:2:+", "ACGTA"),
+            ("1 LN:i:5 L:-:4:+", "TTGCA"),
+            ("2 LN:i:5 L:-:0:- L:+:3:-", "CGTAC"),
+            ("3 LN:i:5 L:+:2:-", "GGTAC"),
+            ("4 LN:i:5 L:-:1:+", "CCTTG"),
+        ];
+
+        {
+            let mut graph = std::fs::File::create(&graph_file).unwrap();
+            for (header, seq) in unitigs.iter() {
+                writeln!(graph, ">{}\n{}", header, seq).unwrap();
+            }
+        }
+
+        assert_eq!(
+            compute_connected_components(&graph_file),
+            vec![0, 1, 0, 0, 1]
+        );
+
+        let components_count = write_connected_components(
+            &graph_file,
+            &graph_file,
+            ComponentsOutputMode::HeaderTagAndSeparateFiles,
+        );
+        assert_eq!(components_count, 2);
+
+        let read_headers = |path| -> Vec<String> {
+            BufReader::new(std::fs::File::open(path).unwrap())
+                .lines()
+                .map(|l| l.unwrap())
+                .filter(|l| l.starts_with('>'))
+                .collect()
+        };
+
+        let tagged = read_headers(graph_file.clone());
+        assert_eq!(tagged.len(), 5);
+        for (header, component) in tagged.iter().zip([0, 1, 0, 0, 1]) {
+            assert!(header.ends_with(&format!(" component={}", component)));
+        }
+
+        let first_ids: Vec<_> = read_headers(get_component_file(&graph_file, 0))
+            .iter()
+            .map(|h| h[1..2].to_string())
+            .collect();
+        assert_eq!(first_ids, vec!["0", "2", "3"]);
+
+        let second_ids: Vec<_> = read_headers(get_component_file(&graph_file, 1))
+            .iter()
+            .map(|h| h[1..2].to_string())
+            .collect();
+        assert_eq!(second_ids, vec!["1", "4"]);
+    }
+}
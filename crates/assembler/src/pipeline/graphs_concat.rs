@@ -0,0 +1,170 @@
+use crate::pipeline::connected_components::create_fasta_writer;
+use io::concurrent::structured_sequences::StructuredSequenceBackend;
+use io::sequences_reader::SequencesReader;
+use io::unitig_links::parse_link_field;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Rewrites a unitig header shifting its id and the ids of its links by offset.
+// Returns the largest id referenced by the header, before shifting
+fn write_renumbered_header(
+    buffer: &mut Vec<u8>,
+    ident: &[u8],
+    offset: usize,
+    sequence_index: usize,
+) -> usize {
+    let ident = ident.strip_prefix(b">").unwrap_or(ident);
+    let mut fields = ident
+        .split(|c| c.is_ascii_whitespace())
+        .filter(|f| !f.is_empty());
+
+    let parse_id = |id: &[u8]| -> Option<usize> { std::str::from_utf8(id).ok()?.parse().ok() };
+
+    let first_field = fields.next().unwrap_or(b"");
+    let (id, rest) = match parse_id(first_field) {
+        Some(id) => (id, None),
+        // Headers without a numeric id are numbered by their position in the file
+        None => (sequence_index, Some(first_field)),
+    };
+    let mut max_id = id;
+
+    write!(buffer, ">{}", id + offset).unwrap();
+    if let Some(field) = rest.filter(|f| !f.is_empty()) {
+        buffer.push(b' ');
+        buffer.extend_from_slice(field);
+    }
+
+    for field in fields {
+        buffer.push(b' ');
+
+        match parse_link_field(id as u64, field) {
+            Some(link) => {
+                let other_id = link.to_id as usize;
+                max_id = max_id.max(other_id);
+                write!(
+                    buffer,
+                    "L:{}:{}:{}",
+                    link.from_strand.sign(),
+                    other_id + offset,
+                    link.to_strand.sign()
+                )
+                .unwrap();
+            }
+            None => buffer.extend_from_slice(field),
+        }
+    }
+    buffer.push(b'\n');
+
+    max_id
+}
+
+/// Concatenates the unitigs of multiple graphs in a single file, renumbering the unitig ids so that
+/// they do not collide and rewriting the links accordingly. The sequences are copied unchanged,
+/// without assembling them again, so kmers shared by different graphs are kept in each of them.
+/// The color annotations of colored graphs still refer to the colormap of their own graph
+pub fn concat_graphs(input_graphs: &[PathBuf], output_file: impl AsRef<Path>) -> PathBuf {
+    let output_file = output_file.as_ref();
+
+    {
+        let mut writer = create_fasta_writer(output_file);
+        let mut buffer = vec![];
+        let mut offset = 0;
+
+        for input_graph in input_graphs {
+            let mut sequence_index = 0;
+            let mut next_offset = offset;
+
+            SequencesReader::new().process_file_extended(
+                input_graph,
+                |seq| {
+                    let max_id = write_renumbered_header(
+                        &mut buffer,
+                        seq.ident_data,
+                        offset,
+                        sequence_index,
+                    );
+                    sequence_index += 1;
+                    next_offset = next_offset.max(offset + max_id + 1);
+
+                    buffer.extend_from_slice(seq.seq);
+                    buffer.push(b'\n');
+                    writer.flush_temp_buffer(&mut buffer);
+                },
+                None,
+                true,
+                false,
+            );
+
+            offset = next_offset;
+        }
+    }
+
+    output_file.to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::concat_graphs;
+    use std::collections::HashSet;
+    use std::io::{BufRead, BufReader, Write};
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn concat_two_graphs() {
+        let temp_dir = TestDir::new("concat_two_graphs");
+        let first_graph = temp_dir.path("concat-first.fa");
+        let second_graph = temp_dir.path("concat-second.fa");
+        let output_graph = temp_dir.path("concat-output.fa");
+
+        let write_graph = |path, unitigs: &[(&str, &str)]| {
+            let mut graph = std::fs::File::create(path).unwrap();
+            for (header, seq) in unitigs.iter() {
+                writeln!(graph, ">{}\n{}", header, seq).unwrap();
+            }
+        };
+
+        write_graph(
+            &first_graph,
+            &[
+                ("0 LN:i:5 L:+:1:+", "ACGTA"),
+                ("1 LN:i:5 L:-:0:- L:+:2:-", "CGTAC"),
+                ("2 LN:i:5 L:+:1:-", "GGTAC"),
+            ],
+        );
+        write_graph(
+            &second_graph,
+            &[("0 LN:i:5 L:-:1:+", "TTGCA"), ("1 LN:i:5 L:-:0:+", "CCTTG")],
+        );
+
+        concat_graphs(&[first_graph.clone(), second_graph.clone()], &output_graph);
+
+        let headers: Vec<String> = BufReader::new(std::fs::File::open(&output_graph).unwrap())
+            .lines()
+            .map(|l| l.unwrap())
+            .filter(|l| l.starts_with('>'))
+            .collect();
+
+        assert_eq!(
+            headers,
+            vec![
+                ">0 LN:i:5 L:+:1:+",
+                ">1 LN:i:5 L:-:0:- L:+:2:-",
+                ">2 LN:i:5 L:+:1:-",
+                ">3 LN:i:5 L:-:4:+",
+                ">4 LN:i:5 L:-:3:+",
+            ]
+        );
+
+        let ids: HashSet<_> = headers
+            .iter()
+            .map(|h| h[1..].split(' ').next().unwrap().to_string())
+            .collect();
+        assert_eq!(ids.len(), headers.len());
+
+        for header in headers.iter() {
+            for link in header.split(' ').filter(|f| f.starts_with("L:")) {
+                assert!(ids.contains(link.split(':').nth(2).unwrap()));
+            }
+        }
+    }
+}
@@ -1,11 +1,13 @@
 use std::path::{Path, PathBuf};
-use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
+use crate::single_threaded::for_each_bucket;
 use config::{
-    get_memory_mode, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
+    get_memory_mode, keep_temp_files, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
+    DEFAULT_PREFETCH_AMOUNT,
 };
 use hashes::HashFunctionFactory;
+use io::get_bucket_index;
 use io::structs::hash_entry::{Direction, HashCompare, HashEntrySerializer};
 use io::structs::unitig_link::{UnitigFlags, UnitigIndex, UnitigLink, UnitigLinkSerializer};
 use nightly_quirks::slice_group_by::SliceGroupBy;
@@ -16,21 +18,19 @@ use parallel_processor::buckets::writers::lock_free_binary_writer::LockFreeBinar
 use parallel_processor::buckets::MultiThreadBuckets;
 use parallel_processor::fast_smart_bucket_sort::fast_smart_radix_sort;
 use parallel_processor::memory_fs::RemoveFileMode;
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use parallel_processor::utils::scoped_thread_local::ScopedThreadLocal;
-use rayon::iter::IntoParallelRefIterator;
-use rayon::iter::ParallelIterator;
 use utils::fast_rand_bool::FastRandBool;
+use utils::phase_log::{log_warning, start_phase};
+use utils::random_seed::RandomComponent;
 use utils::vec_slice::VecSlice;
 
 pub fn hashes_sorting<H: HashFunctionFactory, P: AsRef<Path>>(
     file_hashes_inputs: Vec<PathBuf>,
     output_dir: P,
     buckets_count: usize,
+    random_seed: Option<u64>,
 ) -> Vec<PathBuf> {
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase("phase: hashes sorting".to_string());
+    start_phase("phase: hashes sorting".to_string());
 
     let links_buckets = Arc::new(MultiThreadBuckets::<LockFreeBinaryWriter>::new(
         buckets_count,
@@ -45,9 +45,7 @@ pub fn hashes_sorting<H: HashFunctionFactory, P: AsRef<Path>>(
         BucketsThreadBuffer::new(DEFAULT_PER_CPU_BUFFER_SIZE, buckets_count)
     });
 
-    file_hashes_inputs
-        .par_iter()
-        .for_each(|input| {
+    for_each_bucket(&file_hashes_inputs, |input| {
 
             let mut buffers = buckets_thread_buffers.get();
             let mut links_tmp = BucketsThreadDispatcher::<_, UnitigLinkSerializer>::new(
@@ -55,12 +53,16 @@ pub fn hashes_sorting<H: HashFunctionFactory, P: AsRef<Path>>(
                 buffers.take()
             );
 
-            let mut rand_bool = FastRandBool::<1>::new();
+            let mut rand_bool = FastRandBool::<1>::new(
+                random_seed,
+                RandomComponent::HashesSorting,
+                get_bucket_index(input) as u64
+            );
 
             let mut hashes_vec = Vec::new();
 
             LockFreeBinaryReader::new(input, RemoveFileMode::Remove {
-                remove_fs: !KEEP_FILES.load(Ordering::Relaxed)
+                remove_fs: !keep_temp_files()
             }, DEFAULT_PREFETCH_AMOUNT).decode_all_bucket_items::<HashEntrySerializer<H::HashTypeUnextendable>, _>((), &mut (), |h, _| {
                 hashes_vec.push(h);
             });
@@ -114,10 +116,10 @@ pub fn hashes_sorting<H: HashFunctionFactory, P: AsRef<Path>>(
                         );
                     },
                     1 => {
-                        println!("Warning spurious hash detected ({:?}) with index {}, this is a bug or a collision in the KmersMerge phase!", x[0].hash, x[0].entry());
+                        log_warning(format!("Warning spurious hash detected ({:?}) with index {}, this is a bug or a collision in the KmersMerge phase!", x[0].hash, x[0].entry()));
                     }
                     _ => {
-                        println!("More than 2 equal hashes found in hashes sorting phase, this indicates an hash ({}) collision!",  x[0].hash);
+                        log_warning(format!("More than 2 equal hashes found in hashes sorting phase, this indicates an hash ({}) collision!",  x[0].hash));
                     }
                 }
             }
@@ -1,6 +1,8 @@
+use crate::single_threaded::for_each_bucket;
 use crate::structs::link_mapping::{LinkMapping, LinkMappingSerializer};
 use config::{
-    get_memory_mode, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
+    get_memory_mode, keep_temp_files, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
+    DEFAULT_PREFETCH_AMOUNT,
 };
 use io::get_bucket_index;
 use io::structs::unitig_link::{UnitigFlags, UnitigIndex, UnitigLink, UnitigLinkSerializer};
@@ -14,12 +16,11 @@ use parallel_processor::buckets::MultiThreadBuckets;
 use parallel_processor::fast_smart_bucket_sort::{fast_smart_radix_sort, SortKey};
 use parallel_processor::memory_fs::RemoveFileMode;
 use parallel_processor::utils::scoped_thread_local::ScopedThreadLocal;
-use rayon::iter::IntoParallelRefIterator;
-use rayon::iter::ParallelIterator;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use utils::fast_rand_bool::FastRandBool;
+use utils::random_seed::RandomComponent;
 use utils::vec_slice::VecSlice;
 
 pub fn links_compaction(
@@ -32,6 +33,7 @@ pub fn links_compaction(
     // links_manager: &UnitigLinksManager,
     link_thread_buffers: &ScopedThreadLocal<BucketsThreadBuffer>,
     result_thread_buffers: &ScopedThreadLocal<BucketsThreadBuffer>,
+    random_seed: Option<u64>,
 ) -> (Vec<PathBuf>, u64) {
     let totsum = AtomicU64::new(0);
 
@@ -47,7 +49,7 @@ pub fn links_compaction(
         ),
     ));
 
-    links_inputs.par_iter().for_each(|input| {
+    for_each_bucket(&links_inputs, |input| {
         let bucket_index = get_bucket_index(input);
 
         let mut link_buffers = link_thread_buffers.get();
@@ -68,12 +70,16 @@ pub fn links_compaction(
             result_buffers.take(),
         );
 
-        let mut rand_bool = FastRandBool::<1>::new();
+        let mut rand_bool = FastRandBool::<1>::new(
+            random_seed,
+            RandomComponent::LinksCompaction,
+            ((elab_index as u64) << 32) | bucket_index as u64,
+        );
 
         let file_reader = LockFreeBinaryReader::new(
             input,
             RemoveFileMode::Remove {
-                remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                remove_fs: !keep_temp_files(),
             },
             DEFAULT_PREFETCH_AMOUNT,
         );
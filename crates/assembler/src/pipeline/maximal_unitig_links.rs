@@ -12,11 +12,12 @@ use crate::pipeline::maximal_unitig_links::maximal_unitig_index::{
     DoubleMaximalUnitigLinks, MaximalUnitigFlags, MaximalUnitigIndex, MaximalUnitigLink,
     MaximalUnitigLinkSerializer,
 };
+use crate::single_threaded::{for_each_bucket, workers_count};
 use colors::colors_manager::color_types::PartialUnitigsColorStructure;
 use colors::colors_manager::ColorsManager;
 use config::{
-    get_compression_level_info, get_memory_mode, BucketIndexType, SwapPriority,
-    DEFAULT_OUTPUT_BUFFER_SIZE, DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
+    get_compression_level_info, get_memory_mode, keep_temp_files, BucketIndexType, SwapPriority,
+    DEFAULT_OUTPUT_BUFFER_SIZE, DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT,
 };
 use dashmap::DashSet;
 use hashbrown::HashSet;
@@ -36,13 +37,12 @@ use parallel_processor::buckets::writers::compressed_binary_writer::CompressedBi
 use parallel_processor::buckets::MultiThreadBuckets;
 use parallel_processor::fast_smart_bucket_sort::fast_smart_radix_sort;
 use parallel_processor::memory_fs::RemoveFileMode;
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use parallel_processor::utils::scoped_thread_local::ScopedThreadLocal;
-use rayon::prelude::*;
 use std::cmp::max;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use utils::phase_log::start_phase;
 use utils::vec_slice::VecSlice;
 
 pub fn build_maximal_unitigs_links<
@@ -72,9 +72,7 @@ pub fn build_maximal_unitigs_links<
     let (step_1_hash_files, unitigs_count) = {
         let unitigs_count = AtomicU64::new(0);
 
-        PHASES_TIMES_MONITOR
-            .write()
-            .start_phase("phase: maximal unitigs links building [step 1]".to_string());
+        start_phase("phase: maximal unitigs links building [step 1]".to_string());
 
         let maximal_unitigs_reader_step1 =
             CompressedBinaryReader::new(&in_file, RemoveFileMode::Keep, DEFAULT_PREFETCH_AMOUNT);
@@ -91,9 +89,7 @@ pub fn build_maximal_unitigs_links<
             ));
 
         rayon::scope(|_s| {
-            (0..rayon::current_num_threads())
-                .into_par_iter()
-                .for_each(|_| {
+            for_each_bucket(0..workers_count(), |_| {
                     let mut unitigs_partial_count = 0;
 
                     let mut hashes_tmp = BucketsThreadDispatcher::<
@@ -220,9 +216,7 @@ pub fn build_maximal_unitigs_links<
 
     // Sort the hashes
     let maximal_unitig_links_data_step2 = {
-        PHASES_TIMES_MONITOR
-            .write()
-            .start_phase("phase: maximal unitigs links building [step 2]".to_string());
+        start_phase("phase: maximal unitigs links building [step 2]".to_string());
 
         let maximal_links_buckets = Arc::new(MultiThreadBuckets::<CompressedBinaryWriter>::new(
             buckets_count,
@@ -238,7 +232,7 @@ pub fn build_maximal_unitigs_links<
             BucketsThreadBuffer::new(DEFAULT_PER_CPU_BUFFER_SIZE, buckets_count)
         });
 
-        step_1_hash_files.par_iter().for_each(|input| {
+        for_each_bucket(&step_1_hash_files, |input| {
             let mut buffers = buckets_thread_buffers.get();
             let mut links_tmp = BucketsThreadDispatcher::<_, MaximalUnitigLinkSerializer>::new(
                 &maximal_links_buckets,
@@ -251,7 +245,7 @@ pub fn build_maximal_unitigs_links<
             CompressedBinaryReader::new(
                 input,
                 RemoveFileMode::Remove {
-                    remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                    remove_fs: !keep_temp_files(),
                 },
                 DEFAULT_PREFETCH_AMOUNT,
             )
@@ -311,28 +305,24 @@ pub fn build_maximal_unitigs_links<
         let self_complemental_unitigs = self_complemental_unitigs.into_iter().collect::<HashSet<_>>();
 
 
-        PHASES_TIMES_MONITOR
-            .write()
-            .start_phase("phase: maximal unitigs links building [step 3]".to_string());
+        start_phase("phase: maximal unitigs links building [step 3]".to_string());
 
         let mappings_loader = MaximalUnitigLinksMappingsLoader::new(
             maximal_unitig_links_data_step2,
             entries_per_bucket,
-            rayon::current_num_threads(),
+            workers_count(),
         );
 
         let maximal_unitigs_reader_step3 = CompressedBinaryReader::new(
             &in_file,
             RemoveFileMode::Remove {
-                remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                remove_fs: !keep_temp_files(),
             },
             DEFAULT_PREFETCH_AMOUNT,
         );
 
         rayon::scope(|_s| {
-            (0..rayon::current_num_threads())
-                .into_par_iter()
-                .for_each(|thread_index| {
+            for_each_bucket(0..workers_count(), |thread_index| {
                     let mut tmp_final_unitigs_buffer = FastaWriterConcurrentBuffer::new(
                         out_file,
                         DEFAULT_OUTPUT_BUFFER_SIZE,
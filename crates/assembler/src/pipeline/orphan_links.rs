@@ -0,0 +1,166 @@
+use hashbrown::HashSet;
+use io::sequences_reader::SequencesReader;
+use io::unitig_links::parse_header_links;
+use parking_lot::Mutex;
+use std::path::Path;
+use utils::phase_log::{log_warning, start_phase};
+
+// Orphan links listed in the report, the remaining ones are only counted
+const MAX_REPORTED_ORPHAN_LINKS: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OrphanLinksCheck {
+    Disabled,
+    /// Report the orphan links as warnings
+    Report,
+    /// Report the orphan links and fail the build if there are any
+    Fail,
+}
+
+static ORPHAN_LINKS_CHECK: Mutex<OrphanLinksCheck> = Mutex::new(OrphanLinksCheck::Disabled);
+
+/// Debug only: unitigs considered missing from the graph by the check, as if they were removed
+/// without rerouting the links to them, to exercise the handling of a failed check
+pub static DEBUG_ORPHAN_LINKS_MISSING_UNITIGS: Mutex<Vec<u64>> = Mutex::new(vec![]);
+
+/// Selects if the links of the next builds are checked for references to unitigs
+/// that are not in the output graph, after all the graph modifications are completed
+pub fn set_orphan_links_check(mode: OrphanLinksCheck) {
+    *ORPHAN_LINKS_CHECK.lock() = mode;
+}
+
+/// A link from a unitig to an id that is not in the graph
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OrphanLink {
+    pub unitig_id: u64,
+    pub other_id: u64,
+}
+
+/// Finds the links of a graph that point to unitig ids not present in the graph,
+/// in the order they appear in the graph file
+pub fn find_orphan_links(graph_file: impl AsRef<Path>) -> Vec<OrphanLink> {
+    let mut unitig_ids = HashSet::new();
+    let mut links = vec![];
+    let mut sequence_index = 0;
+
+    SequencesReader::new().process_file_extended(
+        graph_file.as_ref(),
+        |seq| {
+            let (id, unitig_links) = parse_header_links(seq.ident_data, sequence_index);
+            sequence_index += 1;
+
+            unitig_ids.insert(id);
+            links.extend(unitig_links.map(|link| OrphanLink {
+                unitig_id: id,
+                other_id: link.to_id,
+            }));
+        },
+        None,
+        true,
+        false,
+    );
+
+    for missing_id in DEBUG_ORPHAN_LINKS_MISSING_UNITIGS.lock().iter() {
+        unitig_ids.remove(missing_id);
+    }
+
+    links.retain(|link| !unitig_ids.contains(&link.other_id));
+    links
+}
+
+pub(crate) fn is_orphan_links_check_enabled() -> bool {
+    *ORPHAN_LINKS_CHECK.lock() != OrphanLinksCheck::Disabled
+}
+
+// Runs the check selected with set_orphan_links_check on the final graph,
+// returning an error if the graph has orphan links and the check is in fail mode
+pub(crate) fn check_orphan_links(graph_file: &Path) -> Result<(), String> {
+    let mode = *ORPHAN_LINKS_CHECK.lock();
+    if mode == OrphanLinksCheck::Disabled {
+        return Ok(());
+    }
+
+    start_phase("phase: orphan links check".to_string());
+    let orphan_links = find_orphan_links(graph_file);
+    if orphan_links.is_empty() {
+        return Ok(());
+    }
+
+    for link in orphan_links.iter().take(MAX_REPORTED_ORPHAN_LINKS) {
+        log_warning(format!(
+            "Warning: unitig {} links to the missing unitig {}",
+            link.unitig_id, link.other_id
+        ));
+    }
+
+    let message = format!(
+        "Found {} links to missing unitigs in graph {}",
+        orphan_links.len(),
+        graph_file.display()
+    );
+    if mode == OrphanLinksCheck::Fail {
+        return Err(message);
+    }
+    log_warning(format!("Warning: {}", message));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_orphan_links, find_orphan_links, set_orphan_links_check, OrphanLink, OrphanLinksCheck,
+    };
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn removed_unitig_leaves_orphan_link() {
+        let temp_dir = TestDir::new("removed_unitig_leaves_orphan_link");
+        let graph_file = temp_dir.path("orphan-links.fa");
+
+        let unitigs: [(&str, &str); 3] = [
+            ("0 LN:i:5 L:+:1:+", "ACGTA"),
+            ("1 LN:i:5 L:-:0:- L:+:2:-", "CGTAC"),
+            ("2 LN:i:5 L:+:1:-", "GGTAC"),
+        ];
+
+        let write_graph = |skipped: Option<usize>| {
+            let mut graph = std::fs::File::create(&graph_file).unwrap();
+            for (index, (header, seq)) in unitigs.iter().enumerate() {
+                if Some(index) != skipped {
+                    writeln!(graph, ">{}\n{}", header, seq).unwrap();
+                }
+            }
+        };
+
+        write_graph(None);
+        assert!(find_orphan_links(&graph_file).is_empty());
+
+        // Unitig 2 is removed without rerouting the link of unitig 1 to it
+        write_graph(Some(2));
+        assert_eq!(
+            find_orphan_links(&graph_file),
+            vec![OrphanLink {
+                unitig_id: 1,
+                other_id: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn fail_mode_returns_an_error() {
+        let temp_dir = TestDir::new("fail_mode_returns_an_error");
+        let graph_file = temp_dir.path("orphan-links-fail.fa");
+        std::fs::write(
+            &graph_file,
+            ">0 LN:i:5 L:+:1:+\nACGTA\n>1 LN:i:5 L:+:2:-\nCGTAC\n",
+        )
+        .unwrap();
+
+        set_orphan_links_check(OrphanLinksCheck::Report);
+        assert!(check_orphan_links(&graph_file).is_ok());
+        set_orphan_links_check(OrphanLinksCheck::Fail);
+        assert!(check_orphan_links(&graph_file).is_err());
+        set_orphan_links_check(OrphanLinksCheck::Disabled);
+    }
+}
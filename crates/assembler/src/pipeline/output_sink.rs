@@ -0,0 +1,107 @@
+use hashes::HashFunctionFactory;
+use io::concurrent::structured_sequences::fasta::FastaWriter;
+use io::concurrent::structured_sequences::kmer_counts_sidecar::{
+    get_kmer_counts_file, is_kmer_counts_output_enabled,
+};
+use io::concurrent::structured_sequences::low_coverage_bed::{
+    get_low_coverage_bed_file, get_low_coverage_bed_min_coverage,
+};
+use io::concurrent::structured_sequences::unitig_anchors::{
+    encode_unitig_anchors, get_unitig_anchors_file, is_unitig_anchors_output_enabled,
+};
+use io::concurrent::structured_sequences::{IdentSequenceWriter, StructuredSequenceWriter};
+use parking_lot::Mutex;
+use std::io::Write;
+use std::path::Path;
+use utils::phase_log::log_warning;
+
+static OUTPUT_SINK: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// Sets a sink that receives the final unitigs of the next build in plain fasta format,
+/// in place of the output file. Each batch of unitigs is written to the sink as soon as it is
+/// compacted, except with matchtigs/eulertigs that can be written only at the end of the build.
+/// With links the unitigs are streamed during the links phase, after all of them are compacted.
+/// If the graph with links must be read again by the connected components or the orphan links check,
+/// it is kept in the temp dir and sent to the sink only once they are completed
+pub fn set_output_sink(sink: Option<Box<dyn Write + Send>>) {
+    *OUTPUT_SINK.lock() = sink;
+}
+
+/// Takes the output sink of the current build, if set
+pub(crate) fn take_output_sink() -> Option<Box<dyn Write + Send>> {
+    OUTPUT_SINK.lock().take()
+}
+
+/// Creates the writer of the final unitigs, streaming them to the sink if given
+/// and writing them to the output file otherwise
+pub(crate) fn create_final_unitigs_writer<
+    ColorInfo: IdentSequenceWriter,
+    LinksInfo: IdentSequenceWriter,
+>(
+    output_file: &Path,
+    sink: Option<Box<dyn Write + Send>>,
+) -> FastaWriter<ColorInfo, LinksInfo> {
+    if let Some(sink) = sink {
+        return FastaWriter::new_streaming(sink, output_file);
+    }
+
+    match output_file.extension() {
+        Some(ext) => match ext.to_string_lossy().to_string().as_str() {
+            "lz4" => FastaWriter::new_compressed_lz4(output_file, 2),
+            "gz" => FastaWriter::new_compressed_gzip(output_file, 2),
+            _ => FastaWriter::new_plain(output_file),
+        },
+        None => FastaWriter::new_plain(output_file),
+    }
+}
+
+/// Also writes the counts of the kmers of the final unitigs and their low coverage stretches next to
+/// the output file, if enabled. The counts are tracked only in the builds with the kmer counters support
+pub(crate) fn with_kmer_counts_outputs<
+    ColorInfo: IdentSequenceWriter,
+    LinksInfo: IdentSequenceWriter,
+>(
+    mut writer: StructuredSequenceWriter<ColorInfo, LinksInfo, FastaWriter<ColorInfo, LinksInfo>>,
+    output_file: &Path,
+) -> StructuredSequenceWriter<ColorInfo, LinksInfo, FastaWriter<ColorInfo, LinksInfo>> {
+    let min_coverage = get_low_coverage_bed_min_coverage();
+    if !is_kmer_counts_output_enabled() && min_coverage.is_none() {
+        return writer;
+    }
+
+    if !cfg!(feature = "support_kmer_counters") {
+        log_warning(
+            "WARNING: the kmer counts and the low coverage stretches are not written, they are tracked only in the builds with the kmer counters support"
+                .to_string(),
+        );
+        return writer;
+    }
+
+    if is_kmer_counts_output_enabled() {
+        writer = writer.with_kmer_counts(get_kmer_counts_file(output_file));
+    }
+    if let Some(min_coverage) = min_coverage {
+        writer = writer.with_low_coverage_bed(get_low_coverage_bed_file(output_file), min_coverage);
+    }
+    writer
+}
+
+/// Also writes the anchors of the final unitigs next to the output file if enabled,
+/// hashing their first and last kmer with the kmers hash of the build
+pub(crate) fn with_unitig_anchors_output<
+    MergingHash: HashFunctionFactory,
+    ColorInfo: IdentSequenceWriter,
+    LinksInfo: IdentSequenceWriter,
+>(
+    writer: StructuredSequenceWriter<ColorInfo, LinksInfo, FastaWriter<ColorInfo, LinksInfo>>,
+    output_file: &Path,
+) -> StructuredSequenceWriter<ColorInfo, LinksInfo, FastaWriter<ColorInfo, LinksInfo>> {
+    if is_unitig_anchors_output_enabled() {
+        writer.with_unitig_anchors(
+            get_unitig_anchors_file(output_file),
+            encode_unitig_anchors::<MergingHash>,
+        )
+    } else {
+        writer
+    }
+}
@@ -1,7 +1,8 @@
+use crate::single_threaded::for_each_bucket;
 use assembler_kmers_merge::structs::PartialUnitigExtraData;
 use config::{
-    get_compression_level_info, get_memory_mode, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
-    DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
+    get_compression_level_info, get_memory_mode, keep_temp_files, SwapPriority,
+    DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT,
 };
 use hashes::{HashFunctionFactory, HashableSequence, MinimizerHashFunctionFactory};
 use io::concurrent::temp_reads::creads_utils::{
@@ -30,15 +31,12 @@ use parallel_processor::buckets::writers::compressed_binary_writer::CompressedBi
 use parallel_processor::buckets::MultiThreadBuckets;
 use parallel_processor::fast_smart_bucket_sort::{fast_smart_radix_sort, SortKey};
 use parallel_processor::memory_fs::RemoveFileMode;
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use parallel_processor::utils::scoped_thread_local::ScopedThreadLocal;
-use rayon::iter::IntoParallelRefIterator;
-use rayon::iter::ParallelIterator;
 use std::io::{Read, Write};
 use std::mem::transmute;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use utils::phase_log::start_phase;
 
 #[cfg(feature = "support_kmer_counters")]
 use io::concurrent::structured_sequences::SequenceAbundance;
@@ -164,9 +162,7 @@ pub fn reorganize_reads<
     out_file: &StructuredSequenceWriter<PartialUnitigsColorStructure<H, MH, CX>, (), BK>,
     buckets_count: usize,
 ) -> (Vec<PathBuf>, PathBuf) {
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase("phase: reads reorganization".to_string());
+    start_phase("phase: reads reorganization".to_string());
 
     let buckets = Arc::new(MultiThreadBuckets::<CompressedBinaryWriter>::new(
         buckets_count,
@@ -187,7 +183,7 @@ pub fn reorganize_reads<
         BucketsThreadBuffer::new(DEFAULT_PER_CPU_BUFFER_SIZE, buckets_count)
     });
 
-    inputs.par_iter().for_each(|(read_file, mapping_file)| {
+    for_each_bucket(&inputs, |(read_file, mapping_file)| {
         let mut buffers = reads_thread_buffers.get();
 
         let mut tmp_reads_buffer = BucketsThreadDispatcher::<
@@ -211,7 +207,7 @@ pub fn reorganize_reads<
         LockFreeBinaryReader::new(
             mapping_file,
             RemoveFileMode::Remove {
-                remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                remove_fs: !keep_temp_files(),
             },
             DEFAULT_PREFETCH_AMOUNT,
         )
@@ -233,7 +229,7 @@ pub fn reorganize_reads<
         CompressedBinaryReader::new(
             read_file,
             RemoveFileMode::Remove {
-                remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                remove_fs: !keep_temp_files(),
             },
             DEFAULT_PREFETCH_AMOUNT,
         )
@@ -278,9 +274,7 @@ pub fn reorganize_reads<
                         &(),
                         #[cfg(feature = "support_kmer_counters")]
                         SequenceAbundance {
-                            first: extra_data.counters.first,
-                            sum: extra_data.counters.sum,
-                            last: extra_data.counters.last,
+                            counts: extra_data.counters.counts,
                         },
                     );
 
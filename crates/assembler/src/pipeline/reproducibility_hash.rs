@@ -0,0 +1,116 @@
+use io::sequences_reader::SequencesReader;
+use std::path::{Path, PathBuf};
+
+// FNV-1a, as its value does not depend on the platform or on the compiler version
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv1a_update(hash: u64, data: &[u8]) -> u64 {
+    data.iter()
+        .fold(hash, |hash, b| (hash ^ *b as u64).wrapping_mul(FNV_PRIME))
+}
+
+pub(crate) fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|b| match *b {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            b'T' => b'A',
+            b => b,
+        })
+        .collect()
+}
+
+fn canonical_sequence(seq: &[u8]) -> Vec<u8> {
+    let forward = seq.to_ascii_uppercase();
+    let reverse = reverse_complement(&forward);
+    forward.min(reverse)
+}
+
+/// Obtains the standard reproducibility hash file path from a graph file path
+pub fn get_reproducibility_hash_file(graph_file: impl AsRef<Path>) -> PathBuf {
+    graph_file.as_ref().with_extension("hash.txt")
+}
+
+/// Computes a single checksum of the unitigs of a graph, hashing the sorted canonical sequences.
+/// The value does not depend on the order or on the strand of the output unitigs, nor on
+/// their ids and links, so two runs producing the same set of unitigs have the same hash.
+/// All the sequences are loaded in memory to be sorted
+pub fn compute_reproducibility_hash(graph_file: impl AsRef<Path>) -> u64 {
+    let mut sequences = vec![];
+
+    SequencesReader::new().process_file_extended(
+        graph_file.as_ref(),
+        |seq| {
+            sequences.push(canonical_sequence(seq.seq));
+        },
+        None,
+        false,
+        false,
+    );
+
+    sequences.sort_unstable();
+
+    sequences.iter().fold(FNV_OFFSET_BASIS, |hash, seq| {
+        fnv1a_update(fnv1a_update(hash, seq), b"\n")
+    })
+}
+
+/// Computes the reproducibility hash of a graph and writes it, in hexadecimal format,
+/// to the hash file next to the graph
+pub fn write_reproducibility_hash(graph_file: impl AsRef<Path>) -> PathBuf {
+    let hash = compute_reproducibility_hash(graph_file.as_ref());
+    let hash_file = get_reproducibility_hash_file(graph_file);
+
+    std::fs::write(&hash_file, format!("{:016x}\n", hash)).unwrap_or_else(|err| {
+        panic!(
+            "Cannot write reproducibility hash file {}: {}",
+            hash_file.display(),
+            err
+        )
+    });
+
+    hash_file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compute_reproducibility_hash;
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn hash_ignores_unitigs_order_and_strand() {
+        let temp_dir = TestDir::new("hash_ignores_unitigs_order_and_strand");
+        let first_graph = temp_dir.path("reproducibility-hash-first.fa");
+        let second_graph = temp_dir.path("reproducibility-hash-second.fa");
+        let other_graph = temp_dir.path("reproducibility-hash-other.fa");
+
+        let write_graph = |path, unitigs: &[(&str, &str)]| {
+            let mut graph = std::fs::File::create(path).unwrap();
+            for (header, seq) in unitigs.iter() {
+                writeln!(graph, ">{}\n{}", header, seq).unwrap();
+            }
+        };
+
+        write_graph(
+            &first_graph,
+            &[("0 LN:i:6", "ACGTTG"), ("1 LN:i:6", "GGATCA")],
+        );
+        // Same unitigs, in a different order, with different ids and one of them reverse complemented
+        write_graph(
+            &second_graph,
+            &[("0 LN:i:6", "TGATCC"), ("1 LN:i:6 L:+:0:+", "ACGTTG")],
+        );
+        write_graph(
+            &other_graph,
+            &[("0 LN:i:6", "ACGTTG"), ("1 LN:i:6", "GGATCT")],
+        );
+
+        let hash = compute_reproducibility_hash(&first_graph);
+        assert_eq!(hash, compute_reproducibility_hash(&second_graph));
+        assert_ne!(hash, compute_reproducibility_hash(&other_graph));
+    }
+}
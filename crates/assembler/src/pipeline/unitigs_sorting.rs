@@ -0,0 +1,167 @@
+use crate::pipeline::connected_components::create_fasta_writer;
+use crate::pipeline::reproducibility_hash::reverse_complement;
+use crate::pipeline::unitigs_splitting::{get_unitig_id, parse_color_run, split_header_fields};
+use io::concurrent::structured_sequences::StructuredSequenceBackend;
+use io::sequences_reader::SequencesReader;
+use io::unitig_links::{parse_link_field, LinkStrand};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+struct InputUnitig {
+    id: usize,
+    ident: Vec<u8>,
+    // The sequence in its output orientation
+    sequence: Vec<u8>,
+    reversed: bool,
+}
+
+fn flip_strand(strand: LinkStrand, reversed: bool) -> LinkStrand {
+    match (strand, reversed) {
+        (LinkStrand::Forward, true) => LinkStrand::Reverse,
+        (LinkStrand::Reverse, true) => LinkStrand::Forward,
+        (strand, false) => strand,
+    }
+}
+
+/// Rewrites a graph with its unitigs sorted by sequence and renumbered in that order, so that the
+/// output file depends only on the set of unitigs, and not on the buckets scheduling of the build.
+/// With canonical, each unitig is first turned to the smaller of its sequence and its reverse complement,
+/// reversing its colors runs and the strands of its links. The links of each unitig are sorted,
+/// while the other header fields are copied unchanged. All the unitigs are loaded in memory to be sorted.
+/// Returns the number of unitigs in the output graph
+pub fn sort_unitigs(
+    input_graph: impl AsRef<Path>,
+    output_file: impl AsRef<Path>,
+    canonical: bool,
+) -> usize {
+    let mut unitigs = vec![];
+    let mut sequence_index = 0;
+
+    SequencesReader::new().process_file_extended(
+        input_graph.as_ref(),
+        |seq| {
+            let reverse = if canonical {
+                Some(reverse_complement(seq.seq)).filter(|reverse| reverse.as_slice() < seq.seq)
+            } else {
+                None
+            };
+            unitigs.push(InputUnitig {
+                id: get_unitig_id(seq.ident_data, sequence_index),
+                ident: seq.ident_data.to_vec(),
+                reversed: reverse.is_some(),
+                sequence: reverse.unwrap_or_else(|| seq.seq.to_vec()),
+            });
+            sequence_index += 1;
+        },
+        None,
+        true,
+        false,
+    );
+
+    unitigs.sort_unstable_by(|a, b| a.sequence.cmp(&b.sequence));
+
+    // New id of each input unitig, and if it was reversed
+    let ids_map: HashMap<_, _> = unitigs
+        .iter()
+        .enumerate()
+        .map(|(new_id, unitig)| (unitig.id, (new_id, unitig.reversed)))
+        .collect();
+
+    let mut writer = create_fasta_writer(output_file.as_ref());
+    let mut buffer = vec![];
+
+    for (new_id, unitig) in unitigs.iter().enumerate() {
+        let mut color_runs: Vec<_> = split_header_fields(&unitig.ident)
+            .skip(1)
+            .filter(|field| parse_color_run(field).is_some())
+            .collect();
+        if unitig.reversed {
+            color_runs.reverse();
+        }
+
+        let mut links = vec![];
+
+        write!(buffer, ">{}", new_id).unwrap();
+
+        for field in split_header_fields(&unitig.ident).skip(1) {
+            if parse_color_run(field).is_some() {
+                // All the colors runs are written in place of the first one
+                for run in color_runs.drain(..) {
+                    buffer.push(b' ');
+                    buffer.extend_from_slice(run);
+                }
+            } else if let Some(link) = parse_link_field(unitig.id as u64, field) {
+                // Links to unitigs not in the graph are kept unchanged
+                let (to_id, to_reversed) = ids_map
+                    .get(&(link.to_id as usize))
+                    .copied()
+                    .unwrap_or((link.to_id as usize, false));
+                links.push((
+                    flip_strand(link.from_strand, unitig.reversed),
+                    to_id,
+                    flip_strand(link.to_strand, to_reversed),
+                ));
+            } else {
+                buffer.push(b' ');
+                buffer.extend_from_slice(field);
+            }
+        }
+
+        links.sort_unstable();
+        for (from_strand, to_id, to_strand) in links {
+            write!(
+                buffer,
+                " L:{}:{}:{}",
+                from_strand.sign(),
+                to_id,
+                to_strand.sign()
+            )
+            .unwrap();
+        }
+
+        buffer.push(b'\n');
+        buffer.extend_from_slice(&unitig.sequence);
+        buffer.push(b'\n');
+
+        writer.flush_temp_buffer(&mut buffer);
+    }
+
+    unitigs.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sort_unitigs;
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn same_unitigs_in_any_order_and_strand_give_the_same_file() {
+        let temp_dir = TestDir::new("same_unitigs_in_any_order_and_strand_give_the_same_file");
+        let first_graph = temp_dir.path("sort-unitigs-first.fa");
+        let second_graph = temp_dir.path("sort-unitigs-second.fa");
+        let first_sorted = temp_dir.path("sort-unitigs-first-sorted.fa");
+        let second_sorted = temp_dir.path("sort-unitigs-second-sorted.fa");
+
+        // The same two linked unitigs, numbered in the opposite order and with the longer one on the other strand
+        std::fs::File::create(&first_graph)
+            .unwrap()
+            .write_all(b">0 LN:i:6 C:0:1 C:1:2 L:+:1:+\nTTGCAC\n>1 LN:i:5 C:1:2 L:-:0:-\nAAGGC\n")
+            .unwrap();
+        std::fs::File::create(&second_graph)
+            .unwrap()
+            .write_all(b">0 LN:i:6 C:1:2 C:0:1 L:-:1:+\nGTGCAA\n>1 LN:i:5 C:1:2 L:-:0:+\nAAGGC\n")
+            .unwrap();
+
+        assert_eq!(sort_unitigs(&first_graph, &first_sorted, true), 2);
+        assert_eq!(sort_unitigs(&second_graph, &second_sorted, true), 2);
+
+        let sorted = std::fs::read(&first_sorted).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&sorted),
+            ">0 LN:i:5 C:1:2 L:-:1:+\nAAGGC\n>1 LN:i:6 C:1:2 C:0:1 L:-:0:+\nGTGCAA\n"
+        );
+        assert_eq!(sorted, std::fs::read(&second_sorted).unwrap());
+    }
+}
@@ -0,0 +1,264 @@
+use crate::pipeline::connected_components::create_fasta_writer;
+use io::concurrent::structured_sequences::StructuredSequenceBackend;
+use io::sequences_reader::SequencesReader;
+use io::unitig_links::{parse_link_field, LinkStrand};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+fn parse_number<T: std::str::FromStr>(value: &[u8]) -> Option<T> {
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+// Colors format: C:<color in hex>:<count of consecutive kmers with that color>
+pub(crate) fn parse_color_run(field: &[u8]) -> Option<(&[u8], usize)> {
+    let mut parts = field.strip_prefix(b"C:")?.split(|c| *c == b':');
+    let (color, count) = (parts.next()?, parts.next()?);
+    Some((color, parse_number(count)?))
+}
+
+pub(crate) fn split_header_fields(ident: &[u8]) -> impl Iterator<Item = &[u8]> {
+    ident
+        .strip_prefix(b">")
+        .unwrap_or(ident)
+        .split(|c| c.is_ascii_whitespace())
+        .filter(|f| !f.is_empty())
+}
+
+// Returns the id of a unitig, numbering headers without a numeric id by their position
+pub(crate) fn get_unitig_id(ident: &[u8], sequence_index: usize) -> usize {
+    split_header_fields(ident)
+        .next()
+        .and_then(parse_number)
+        .unwrap_or(sequence_index)
+}
+
+// Start offsets of the pieces of a sequence, each piece overlapping the next one by k - 1 bases
+fn get_pieces_starts(length: usize, k: usize, max_unitig_length: usize) -> Vec<usize> {
+    let step = max_unitig_length - (k - 1);
+    let mut starts = vec![0];
+    while starts.last().unwrap() + max_unitig_length < length {
+        starts.push(starts.last().unwrap() + step);
+    }
+    starts
+}
+
+/// Splits the unitigs longer than max_unitig_length into pieces of at most max_unitig_length bases,
+/// overlapping by k - 1 bases so that each kmer belongs to exactly one piece. Consecutive pieces
+/// are linked together and the links of the original unitigs are moved to their first/last piece,
+/// so that the graph topology is preserved. All the unitigs are renumbered.
+/// The KC tag and the colors runs are split by the kmers of each piece, while km is copied unchanged.
+/// Returns the number of unitigs in the output graph
+pub fn split_long_unitigs(
+    input_graph: impl AsRef<Path>,
+    output_file: impl AsRef<Path>,
+    k: usize,
+    max_unitig_length: usize,
+) -> usize {
+    assert!(
+        max_unitig_length >= k,
+        "The maximum unitig length ({}) must be at least k ({})",
+        max_unitig_length,
+        k
+    );
+
+    // First id and number of pieces of each input unitig
+    let mut pieces_map = HashMap::new();
+    let mut pieces_count = 0;
+    let mut sequence_index = 0;
+
+    SequencesReader::new().process_file_extended(
+        input_graph.as_ref(),
+        |seq| {
+            let count = get_pieces_starts(seq.seq.len(), k, max_unitig_length).len();
+            pieces_map.insert(
+                get_unitig_id(seq.ident_data, sequence_index),
+                (pieces_count, count),
+            );
+            pieces_count += count;
+            sequence_index += 1;
+        },
+        None,
+        true,
+        false,
+    );
+
+    let map_link_target = |other_id: usize, reverse: bool| {
+        // Links to unitigs not in the graph are kept unchanged
+        let (first_id, count) = pieces_map.get(&other_id).copied().unwrap_or((other_id, 1));
+        // Entering the reverse strand of a unitig means entering its last piece
+        if reverse {
+            first_id + count - 1
+        } else {
+            first_id
+        }
+    };
+
+    let mut writer = create_fasta_writer(output_file.as_ref());
+    let mut buffer = vec![];
+    let mut sequence_index = 0;
+
+    SequencesReader::new().process_file_extended(
+        input_graph.as_ref(),
+        |seq| {
+            let unitig_id = get_unitig_id(seq.ident_data, sequence_index);
+            let (first_id, _) = pieces_map[&unitig_id];
+            sequence_index += 1;
+
+            let starts = get_pieces_starts(seq.seq.len(), k, max_unitig_length);
+            let total_kmers = (seq.seq.len() + 1).saturating_sub(k).max(1) as u64;
+
+            let mut kmers_count = None;
+            let mut color_runs = vec![];
+            for field in split_header_fields(seq.ident_data).skip(1) {
+                if let Some(count) = field.strip_prefix(b"KC:i:").and_then(parse_number::<u64>) {
+                    kmers_count = Some(count);
+                } else if let Some(run) = parse_color_run(field) {
+                    color_runs.push(run);
+                }
+            }
+
+            for (index, start) in starts.iter().enumerate() {
+                let end = (start + max_unitig_length).min(seq.seq.len());
+                let (first_kmer, last_kmer) = (*start as u64, (end + 1).saturating_sub(k) as u64);
+                let is_first = index == 0;
+                let is_last = index == starts.len() - 1;
+
+                // Keep only the colors runs, or their parts, that cover the kmers of this piece
+                let mut piece_colors = vec![];
+                let mut run_start = 0;
+                for (color, count) in color_runs.iter() {
+                    let run_end = run_start + *count as u64;
+                    let covered = run_end
+                        .min(last_kmer)
+                        .saturating_sub(run_start.max(first_kmer));
+                    if covered > 0 {
+                        piece_colors.extend_from_slice(b" C:");
+                        piece_colors.extend_from_slice(color);
+                        write!(piece_colors, ":{}", covered).unwrap();
+                    }
+                    run_start = run_end;
+                }
+
+                write!(buffer, ">{} LN:i:{}", first_id + index, end - start).unwrap();
+
+                for field in split_header_fields(seq.ident_data).skip(1) {
+                    if field.starts_with(b"LN:i:") {
+                        continue;
+                    } else if parse_color_run(field).is_some() {
+                        // All the colors runs are written in place of the first one
+                        buffer.extend_from_slice(&piece_colors);
+                        piece_colors.clear();
+                    } else if field.starts_with(b"KC:i:") && kmers_count.is_some() {
+                        // Split the counts proportionally to the kmers, keeping their total unchanged
+                        let count_until = |kmer: u64| {
+                            (kmers_count.unwrap() as u128 * kmer.min(total_kmers) as u128
+                                / total_kmers as u128) as u64
+                        };
+                        let piece_count = count_until(last_kmer) - count_until(first_kmer);
+                        write!(buffer, " KC:i:{}", piece_count).unwrap();
+                    } else if let Some(link) = parse_link_field(unitig_id as u64, field) {
+                        // Links leaving the start of the unitig belong to its first piece,
+                        // links leaving its end to the last one
+                        let from_reverse = link.from_strand == LinkStrand::Reverse;
+                        if (from_reverse && is_first) || (!from_reverse && is_last) {
+                            write!(
+                                buffer,
+                                " L:{}:{}:{}",
+                                link.from_strand.sign(),
+                                map_link_target(
+                                    link.to_id as usize,
+                                    link.to_strand == LinkStrand::Reverse
+                                ),
+                                link.to_strand.sign()
+                            )
+                            .unwrap();
+                        }
+                    } else {
+                        buffer.push(b' ');
+                        buffer.extend_from_slice(field);
+                    }
+                }
+
+                if !is_first {
+                    write!(buffer, " L:-:{}:-", first_id + index - 1).unwrap();
+                }
+                if !is_last {
+                    write!(buffer, " L:+:{}:+", first_id + index + 1).unwrap();
+                }
+
+                buffer.push(b'\n');
+                buffer.extend_from_slice(&seq.seq[*start..end]);
+                buffer.push(b'\n');
+            }
+
+            writer.flush_temp_buffer(&mut buffer);
+        },
+        None,
+        true,
+        false,
+    );
+
+    pieces_count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_long_unitigs;
+    use std::io::{BufRead, BufReader, Write};
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn long_unitig_is_split_with_overlaps_and_links() {
+        const K: usize = 5;
+        let temp_dir = TestDir::new("long_unitig_is_split_with_overlaps_and_links");
+        let input_graph = temp_dir.path("split-unitigs-input.fa");
+        let output_graph = temp_dir.path("split-unitigs-output.fa");
+
+        let long_unitig: String = (0..100)
+            .map(|i| b"ACGTTGCA"[(i * 7 + i / 3) % 8] as char)
+            .collect();
+
+        {
+            let mut graph = std::fs::File::create(&input_graph).unwrap();
+            writeln!(
+                graph,
+                ">0 LN:i:100 KC:i:192 km:f:2.0 C:0:50 C:1:46 L:+:1:+\n{}",
+                long_unitig
+            )
+            .unwrap();
+            writeln!(
+                graph,
+                ">1 LN:i:10 KC:i:12 km:f:2.0 C:1:6 L:-:0:-\nTTGACCATGA"
+            )
+            .unwrap();
+        }
+
+        assert_eq!(split_long_unitigs(&input_graph, &output_graph, K, 40), 4);
+
+        let lines: Vec<String> = BufReader::new(std::fs::File::open(&output_graph).unwrap())
+            .lines()
+            .map(|l| l.unwrap())
+            .collect();
+        let headers: Vec<_> = lines.iter().step_by(2).map(|h| h.as_str()).collect();
+        let sequences: Vec<_> = lines.iter().skip(1).step_by(2).collect();
+
+        assert_eq!(
+            headers,
+            vec![
+                ">0 LN:i:40 KC:i:72 km:f:2.0 C:0:36 L:+:1:+",
+                ">1 LN:i:40 KC:i:72 km:f:2.0 C:0:14 C:1:22 L:-:0:- L:+:2:+",
+                ">2 LN:i:28 KC:i:48 km:f:2.0 C:1:24 L:+:3:+ L:-:1:-",
+                ">3 LN:i:10 KC:i:12 km:f:2.0 C:1:6 L:-:2:-",
+            ]
+        );
+
+        // Consecutive pieces overlap by k - 1 bases and together they spell the original unitig
+        let mut joined = sequences[0].clone();
+        for window in sequences[..3].windows(2) {
+            assert_eq!(window[0][window[0].len() - (K - 1)..], window[1][..K - 1]);
+            joined.push_str(&window[1][K - 1..]);
+        }
+        assert_eq!(joined, long_unitig);
+    }
+}
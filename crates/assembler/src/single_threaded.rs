@@ -0,0 +1,55 @@
+use parking_lot::Mutex;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SingleThreadedMode {
+    /// Used when a single thread is requested
+    Auto,
+    Forced,
+    Disabled,
+}
+
+static SINGLE_THREADED_MODE: Mutex<SingleThreadedMode> = Mutex::new(SingleThreadedMode::Auto);
+static SINGLE_THREADED_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Selects when the graph is built in single-threaded mode, where the buckets of each phase
+/// are processed inline one after the other, without the parallel dispatch setup.
+/// The built graph has the same unitigs as the one built with multiple threads, and the same file
+/// when both are sorted with sort_unitigs, as the order and the ids of the unitigs depend on the buckets scheduling
+pub fn set_single_threaded_mode(mode: SingleThreadedMode) {
+    *SINGLE_THREADED_MODE.lock() = mode;
+}
+
+// Chooses the mode of the current build, returning true if it runs single-threaded
+pub(crate) fn init_single_threaded_mode(threads_count: usize) -> bool {
+    let active = match *SINGLE_THREADED_MODE.lock() {
+        SingleThreadedMode::Auto => threads_count <= 1,
+        SingleThreadedMode::Forced => true,
+        SingleThreadedMode::Disabled => false,
+    };
+    SINGLE_THREADED_ACTIVE.store(active, Ordering::Relaxed);
+    active
+}
+
+/// Number of workers used by the phases that split their work between the threads
+pub(crate) fn workers_count() -> usize {
+    if SINGLE_THREADED_ACTIVE.load(Ordering::Relaxed) {
+        1
+    } else {
+        rayon::current_num_threads()
+    }
+}
+
+/// Processes each item on the current thread in single-threaded mode, or in parallel with rayon otherwise
+pub(crate) fn for_each_bucket<I, F>(items: I, func: F)
+where
+    I: IntoParallelIterator + IntoIterator<Item = <I as IntoParallelIterator>::Item>,
+    F: Fn(<I as IntoParallelIterator>::Item) + Sync + Send,
+{
+    if SINGLE_THREADED_ACTIVE.load(Ordering::Relaxed) {
+        items.into_iter().for_each(func);
+    } else {
+        items.into_par_iter().for_each(func);
+    }
+}
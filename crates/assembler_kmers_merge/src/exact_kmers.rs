@@ -0,0 +1,162 @@
+use hashbrown::{HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static EXACT_KMERS_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Enables the exact kmers mode, where the kmers sharing the same hash are compared by their
+/// full sequence so that only identical kmers are merged. Invertible hashes are already exact,
+/// so this affects only the non invertible ones (rabin-karp, or any hash with k > 64)
+pub fn set_exact_kmers_mode(enabled: bool) {
+    EXACT_KMERS_MODE.store(enabled, Ordering::Relaxed);
+}
+
+pub(crate) fn is_exact_kmers_mode() -> bool {
+    EXACT_KMERS_MODE.load(Ordering::Relaxed)
+}
+
+/// Keeps the sequence of each distinct kmer seen with the hashes of a bucket, numbering the kmers
+/// with a different sequence that collide with the first kmer of their hash
+#[derive(Default)]
+pub struct ExactKmersVerifier<T: Hash + Eq + Copy> {
+    // Length of the verified kmers
+    k: usize,
+    // Offset in sequences of the first kmer seen with each hash
+    first_sequences_offsets: HashMap<T, usize>,
+    // Offsets in sequences of the kmers colliding with the first kmer of their hash, in the order they were found
+    colliding_offsets: HashMap<T, Vec<usize>>,
+    sequences: Vec<u8>,
+    kmer_buffer: Vec<u8>,
+}
+
+impl<T: Hash + Eq + Copy> ExactKmersVerifier<T> {
+    pub fn reset(&mut self) {
+        self.first_sequences_offsets.clear();
+        self.colliding_offsets.clear();
+        self.sequences.clear();
+    }
+
+    /// Checks the kmer (with the bases of the strand selected by its hash) against the kmers already seen
+    /// with the same hash, returning its generation: 0 for the first kmer seen with the hash, and the
+    /// position in the order they were found for the different kmers colliding with it
+    pub fn verify(&mut self, hash: T, kmer_bases: impl Iterator<Item = u8>) -> usize {
+        self.kmer_buffer.clear();
+        self.kmer_buffer.extend(kmer_bases);
+        self.k = self.kmer_buffer.len();
+
+        match self.first_sequences_offsets.get(&hash) {
+            None => {
+                self.first_sequences_offsets
+                    .insert(hash, self.sequences.len());
+                self.sequences.extend_from_slice(&self.kmer_buffer);
+                0
+            }
+            Some(&offset) => {
+                if self.sequences[offset..offset + self.k] == self.kmer_buffer[..] {
+                    return 0;
+                }
+
+                let offsets = self.colliding_offsets.entry(hash).or_default();
+                if let Some(position) = offsets.iter().position(|offset| {
+                    self.sequences[*offset..*offset + self.k] == self.kmer_buffer[..]
+                }) {
+                    return position + 1;
+                }
+
+                offsets.push(self.sequences.len());
+                self.sequences.extend_from_slice(&self.kmer_buffer);
+                offsets.len()
+            }
+        }
+    }
+
+    /// True if the hash is shared by kmers with different sequences
+    #[inline(always)]
+    pub fn is_colliding(&self, hash: &T) -> bool {
+        !self.colliding_offsets.is_empty() && self.colliding_offsets.contains_key(hash)
+    }
+
+    /// The generation of an already verified kmer, given with the bases of the strand selected by its hash
+    pub fn find(&self, hash: &T, kmer: &[u8]) -> Option<usize> {
+        let first_offset = *self.first_sequences_offsets.get(hash)?;
+        if &self.sequences[first_offset..first_offset + self.k] == kmer {
+            return Some(0);
+        }
+        self.colliding_offsets
+            .get(hash)?
+            .iter()
+            .position(|offset| &self.sequences[*offset..*offset + self.k] == kmer)
+            .map(|position| position + 1)
+    }
+
+    /// The hashes shared by kmers with different sequences, with the number of kmers colliding
+    /// with the first kmer of each hash
+    pub fn colliding_hashes(&self) -> impl Iterator<Item = (&T, usize)> {
+        self.colliding_offsets
+            .iter()
+            .map(|(hash, offsets)| (hash, offsets.len()))
+    }
+
+    /// The sequence of the kmer with the given hash and generation
+    pub fn sequence(&self, hash: &T, generation: usize) -> Option<&[u8]> {
+        let offset = match generation {
+            0 => *self.first_sequences_offsets.get(hash)?,
+            _ => *self.colliding_offsets.get(hash)?.get(generation - 1)?,
+        };
+        Some(&self.sequences[offset..offset + self.k])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExactKmersVerifier;
+    use hashbrown::HashMap;
+
+    #[test]
+    fn colliding_kmers_stay_separate() {
+        const K: usize = 5;
+
+        // A 2 bits hash, so that most of the distinct kmers collide
+        let tiny_hash = |kmer: &[u8]| {
+            kmer.iter()
+                .fold(0u8, |hash, base| hash.wrapping_mul(31).wrapping_add(*base))
+                & 0x3
+        };
+
+        let sequence = b"ACGTTGCATGCAAACGTTGCAGGTACCATTTACGTTGCA";
+
+        let mut verifier = ExactKmersVerifier::default();
+        let mut generation_counts: HashMap<(u8, usize), u64> = HashMap::new();
+        let mut expected_counts: HashMap<&[u8], u64> = HashMap::new();
+
+        for kmer in sequence.windows(K) {
+            *expected_counts.entry(kmer).or_insert(0) += 1;
+            let generation = verifier.verify(tiny_hash(kmer), kmer.iter().copied());
+            *generation_counts
+                .entry((tiny_hash(kmer), generation))
+                .or_insert(0) += 1;
+        }
+
+        assert!(expected_counts.len() > 4);
+        assert!(verifier.colliding_hashes().count() > 0);
+
+        // Each distinct kmer has its own generation, found again from its sequence
+        assert_eq!(generation_counts.len(), expected_counts.len());
+        for (kmer, expected_count) in expected_counts {
+            let hash = tiny_hash(kmer);
+            let generation = verifier.find(&hash, kmer).unwrap();
+            assert_eq!(verifier.sequence(&hash, generation), Some(kmer));
+            if generation > 0 {
+                assert!(verifier.is_colliding(&hash));
+            }
+            assert_eq!(
+                generation_counts[&(hash, generation)],
+                expected_count,
+                "{}",
+                String::from_utf8_lossy(kmer)
+            );
+        }
+
+        assert_eq!(verifier.find(&tiny_hash(b"TTTTT"), b"TTTTT"), None);
+    }
+}
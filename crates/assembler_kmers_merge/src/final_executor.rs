@@ -19,6 +19,7 @@ use parallel_processor::buckets::writers::lock_free_binary_writer::LockFreeBinar
 use parallel_processor::execution_manager::packet::Packet;
 use std::marker::PhantomData;
 use std::ops::DerefMut;
+use std::sync::atomic::Ordering;
 use structs::map_entry::MapEntry;
 #[cfg(feature = "support_kmer_counters")]
 use structs::unitigs_counters::UnitigsCounters;
@@ -38,6 +39,7 @@ pub struct ParallelKmersMergeFinalExecutor<
 
     forward_seq: Vec<u8>,
     backward_seq: Vec<u8>,
+    kmer_buffer: Vec<u8>,
     unitigs_temp_colors: color_types::TempUnitigColorStructure<H, MH, CX>,
     current_bucket: Option<ResultsBucket<color_types::PartialUnitigsColorStructure<H, MH, CX>>>,
     temp_color_buffer:
@@ -58,6 +60,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
             hashes_tmp: BucketsThreadDispatcher::new(&global_data.hashes_buckets, hashes_buffer),
             forward_seq: Vec::with_capacity(global_data.k),
             backward_seq: Vec::with_capacity(global_data.k),
+            kmer_buffer: Vec::with_capacity(global_data.k),
             unitigs_temp_colors: CX::ColorsMergeManagerType::<H, MH>::alloc_unitig_color_structure(
             ),
             current_bucket: None,
@@ -99,6 +102,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
             }
         } else {
             let mut cursor = 0;
+            let mut kmer_buffer = Vec::with_capacity(k);
 
             let mut last_saved_index = decode_varint(|| {
                 let value = map_struct.encoded_saved_reads_indexes.get(cursor).copied();
@@ -132,22 +136,6 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                 );
 
                 for (base_index, hash) in hashes_iter.iter_enumerate() {
-                    let rhentry = match map_struct.rhash_map.get(&hash.to_unextendable()) {
-                        Some(entry) => entry,
-                        None => {
-                            continue;
-                        }
-                    };
-
-                    let count = rhentry.get_kmer_multiplicity();
-                    if count < global_data.min_multiplicity {
-                        continue;
-                    }
-
-                    if rhentry.is_used() {
-                        continue;
-                    }
-
                     let base_index = read_start * 4 + base_index;
                     let read_bases_start = base_index / 4;
                     let reads_offset = base_index % 4;
@@ -160,11 +148,60 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                     };
 
                     let cread = CompressedRead::from_compressed_reads(reads_slice, reads_offset, k);
+
+                    let rhentry =
+                        match Self::kmer_entry(map_struct, hash, &mut kmer_buffer, |kmer| {
+                            kmer.extend(cread.as_bases_iter())
+                        }) {
+                            Some(entry) => entry,
+                            None => {
+                                continue;
+                            }
+                        };
+
+                    let count = rhentry.get_kmer_multiplicity();
+                    if count < global_data.min_multiplicity {
+                        continue;
+                    }
+
+                    if rhentry.is_used() {
+                        continue;
+                    }
+
                     callback(hash, cread, rhentry);
                 }
             }
         }
     }
+
+    // Finds the map entry of a kmer, comparing its sequence with the other kmers sharing its hash
+    // in the exact kmers mode. fill_kmer writes the kmer bases in the unitig orientation
+    #[inline(always)]
+    fn kmer_entry<'a>(
+        map_struct: &'a ParallelKmersMergeMapPacket<H, MH, CX>,
+        hash: MH::HashTypeExtendable,
+        kmer_buffer: &mut Vec<u8>,
+        fill_kmer: impl FnOnce(&mut Vec<u8>),
+    ) -> Option<&'a MapEntry<color_types::HashMapTempColorIndex<H, MH, CX>>> {
+        let unextendable = hash.to_unextendable();
+        if !map_struct.exact_verifier.is_colliding(&unextendable) {
+            return map_struct.rhash_map.get(&unextendable);
+        }
+
+        kmer_buffer.clear();
+        fill_kmer(kmer_buffer);
+        if !hash.is_forward() {
+            kmer_buffer.reverse();
+            for base in kmer_buffer.iter_mut() {
+                *base = Utils::decompress_base(Utils::compress_base(*base) ^ 2);
+            }
+        }
+
+        match map_struct.exact_verifier.find(&unextendable, kmer_buffer)? {
+            0 => map_struct.rhash_map.get(&unextendable),
+            generation => map_struct.collision_map.get(&(unextendable, generation)),
+        }
+    }
 }
 
 // static DEBUG_MAPS_HOLDER: Mutex<Vec<Box<dyn Any + Sync + Send>>> = const_mutex(Vec::new());
@@ -194,16 +231,56 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
         let current_bucket = self.current_bucket.as_mut().unwrap();
         let bucket_index = current_bucket.get_bucket_index();
 
+        // Kmers outside the target are handled as if they were below the minimum multiplicity
+        if let Some(target_kmers) = &global_data.target_kmers {
+            for (hash, entry) in map_struct.rhash_map.iter_mut() {
+                if !target_kmers.contains(hash) {
+                    entry.set_counter_after_check(0);
+                }
+            }
+            for ((hash, _), entry) in map_struct.collision_map.iter_mut() {
+                if !target_kmers.contains(hash) {
+                    entry.set_counter_after_check(0);
+                }
+            }
+        }
+
         if CX::COLORS_ENABLED {
+            let exact_verifier = &map_struct.exact_verifier;
+            let kmer_buffer = &mut self.kmer_buffer;
             CX::ColorsMergeManagerType::<H, MH>::process_colors(
                 &global_data.colors_global_table,
                 &mut map_struct.temp_colors,
                 &mut map_struct.rhash_map,
+                &mut map_struct.collision_map,
+                |hash, kmer, is_forward| {
+                    if !exact_verifier.is_colliding(&hash) {
+                        return 0;
+                    }
+                    kmer_buffer.clear();
+                    if is_forward {
+                        kmer_buffer.extend(kmer.as_bases_iter());
+                    } else {
+                        kmer_buffer.extend(kmer.as_reverse_complement_bases_iter());
+                    }
+                    exact_verifier.find(&hash, kmer_buffer).unwrap()
+                },
                 global_data.k,
                 global_data.min_multiplicity,
             );
         }
 
+        if global_data.exact_kmers {
+            let colliding_kmers: usize = map_struct
+                .exact_verifier
+                .colliding_hashes()
+                .map(|(_, colliding_count)| colliding_count + 1)
+                .sum();
+            global_data
+                .colliding_kmers_total
+                .fetch_add(colliding_kmers as u64, Ordering::Relaxed);
+        }
+
         let bases_count = map_struct.saved_reads.len() * 4;
         if !MH::INVERTIBLE && bases_count < k {
             return map_struct_packet;
@@ -248,10 +325,11 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
 
             #[cfg(feature = "support_kmer_counters")]
             let mut counters = UnitigsCounters {
-                first: first_count,
-                sum: first_count,
-                last: first_count,
+                counts: vec![first_count],
             };
+            // The counts of the backward extension, in reverse order
+            #[cfg(feature = "support_kmer_counters")]
+            let mut backward_counts = vec![];
 
             let mut try_extend_function =
                 |output: &mut Vec<u8>,
@@ -271,15 +349,34 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                     ts: &mut color_types::TempUnitigColorStructure<H, MH, CX>,
                     entry: &MapEntry<color_types::HashMapTempColorIndex<H, MH, CX>>,
                 ),
-                 #[cfg(feature = "support_kmer_counters")] is_forward: bool| {
+                 is_forward: bool| {
                     let mut temp_data = (hash, 0);
                     let mut current_hash;
 
+                    // The coverage of the link from the first kmer to the second one along the extension,
+                    // the links are counted in the order of the reads
+                    let link_has_min_coverage =
+                        |first: MH::HashTypeExtendable, second: MH::HashTypeExtendable| {
+                            let (first, second) = if is_forward {
+                                (first, second)
+                            } else {
+                                (second, first)
+                            };
+                            map_struct.link_coverage.has_min_coverage(
+                                first.to_unextendable_with_strand(),
+                                second.to_unextendable_with_strand(),
+                                global_data.min_link_coverage,
+                            )
+                        };
+
                     return 'ext_loop: loop {
                         let mut count = 0;
+                        let mut next_entry = None;
                         current_hash = temp_data.0;
                         #[cfg(feature = "support_kmer_counters")]
                         let mut multiplicity = 0;
+                        // The last k - 1 bases of the output, shared by the candidate kmers
+                        let overlap = &output[output.len() - (k - 1)..];
                         for idx in 0..4 {
                             let new_hash = compute_hash_fw(
                                 current_hash,
@@ -287,17 +384,30 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                                 Utils::compress_base(output[output.len() - k]),
                                 idx,
                             );
-                            if let Some(hash) =
-                                map_struct.rhash_map.get(&new_hash.to_unextendable())
-                            {
-                                if hash.get_kmer_multiplicity() >= global_data.min_multiplicity {
+                            if let Some(entry) = Self::kmer_entry(
+                                map_struct,
+                                new_hash,
+                                &mut self.kmer_buffer,
+                                |kmer| {
+                                    kmer.extend_from_slice(overlap);
+                                    kmer.push(Utils::decompress_base(idx));
+                                    // The backward output is stored in reverse order
+                                    if !is_forward {
+                                        kmer.reverse();
+                                    }
+                                },
+                            ) {
+                                if entry.get_kmer_multiplicity() >= global_data.min_multiplicity
+                                    && link_has_min_coverage(current_hash, new_hash)
+                                {
                                     // println!("Forward match extend read {:x?}!", new_hash);
                                     #[cfg(feature = "support_kmer_counters")]
                                     {
-                                        multiplicity = hash.get_kmer_multiplicity() as u64;
+                                        multiplicity = entry.get_kmer_multiplicity() as u64;
                                     }
                                     count += 1;
                                     temp_data = (new_hash, idx);
+                                    next_entry = Some(entry);
                                 }
                             }
                         }
@@ -309,11 +419,21 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                                 let new_hash = temp_data.0;
                                 for idx in 0..4 {
                                     let bw_hash = compute_hash_bw(new_hash, k, temp_data.1, idx);
-                                    if let Some(hash) =
-                                        map_struct.rhash_map.get(&bw_hash.to_unextendable())
-                                    {
+                                    if let Some(hash) = Self::kmer_entry(
+                                        map_struct,
+                                        bw_hash,
+                                        &mut self.kmer_buffer,
+                                        |kmer| {
+                                            kmer.push(Utils::decompress_base(idx));
+                                            kmer.extend_from_slice(overlap);
+                                            if !is_forward {
+                                                kmer.reverse();
+                                            }
+                                        },
+                                    ) {
                                         if hash.get_kmer_multiplicity()
                                             >= global_data.min_multiplicity
+                                            && link_has_min_coverage(bw_hash, new_hash)
                                         {
                                             if ocount > 0 {
                                                 break 'ext_loop (current_hash, false);
@@ -325,10 +445,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                                 assert_eq!(ocount, 1);
                             }
 
-                            let entryref = map_struct
-                                .rhash_map
-                                .get(&temp_data.0.to_unextendable())
-                                .unwrap();
+                            let entryref = next_entry.unwrap();
 
                             let already_used = entryref.is_used();
 
@@ -348,11 +465,10 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
 
                             #[cfg(feature = "support_kmer_counters")]
                             {
-                                counters.sum += multiplicity;
                                 if is_forward {
-                                    counters.last = multiplicity;
+                                    counters.counts.push(multiplicity);
                                 } else {
-                                    counters.first = multiplicity;
+                                    backward_counts.push(multiplicity);
                                 }
                             }
 
@@ -377,7 +493,6 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                         MH::manual_roll_forward,
                         MH::manual_roll_reverse,
                         CX::ColorsMergeManagerType::<H, MH>::extend_forward,
-                        #[cfg(feature = "support_kmer_counters")]
                         true,
                     );
                     (fw_hash, end_ignored)
@@ -393,7 +508,6 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                         MH::manual_roll_reverse,
                         MH::manual_roll_forward,
                         CX::ColorsMergeManagerType::<H, MH>::extend_backward,
-                        #[cfg(feature = "support_kmer_counters")]
                         false,
                     );
                     (bw_hash, begin_ignored)
@@ -406,6 +520,13 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                 &self.backward_seq[..]
             };
 
+            #[cfg(feature = "support_kmer_counters")]
+            {
+                backward_counts.reverse();
+                backward_counts.extend_from_slice(&counters.counts);
+                counters.counts = backward_counts;
+            }
+
             let colors =
                 color_types::ColorsMergeManagerType::<H, MH, CX>::encode_part_unitigs_colors(
                     &mut self.unitigs_temp_colors,
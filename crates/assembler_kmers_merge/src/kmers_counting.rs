@@ -0,0 +1,27 @@
+use parking_lot::Mutex;
+
+/// How the repeated occurrences of a kmer in the same read (e.g. in a tandem repeat)
+/// contribute to its count, and so to the minimum multiplicity filtering
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KmerCountingMode {
+    /// Each occurrence of a kmer is counted
+    CountPerOccurrence,
+    /// A kmer is counted once for each read where it appears
+    CountPerRead,
+}
+
+static KMER_COUNTING_MODE: Mutex<KmerCountingMode> =
+    Mutex::new(KmerCountingMode::CountPerOccurrence);
+
+/// Sets how the kmers repeated in a read are counted in the next builds.
+/// The reads are split in super-kmers before counting, so with CountPerRead the occurrences of a kmer
+/// are merged only inside the same super-kmer, as the ones of the tandem repeats with a period
+/// shorter than the minimizer window. Colored builds always count each occurrence,
+/// as the colors manager needs the exact number of occurrences of each kmer
+pub fn set_kmer_counting_mode(mode: KmerCountingMode) {
+    *KMER_COUNTING_MODE.lock() = mode;
+}
+
+pub(crate) fn get_kmer_counting_mode() -> KmerCountingMode {
+    *KMER_COUNTING_MODE.lock()
+}
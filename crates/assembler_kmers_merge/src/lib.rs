@@ -1,21 +1,30 @@
+use crate::exact_kmers::is_exact_kmers_mode;
 use crate::final_executor::ParallelKmersMergeFinalExecutor;
+use crate::kmers_counting::get_kmer_counting_mode;
+use crate::link_coverage::get_min_link_coverage;
 use crate::map_processor::{ParallelKmersMergeMapProcessor, KMERGE_TEMP_DIR};
 use crate::preprocessor::ParallelKmersMergePreprocessor;
 use crate::structs::{ResultsBucket, RetType};
-use assembler_minimizer_bucketing::AssemblerMinimizerBucketingExecutorFactory;
+use crate::target_kmers::load_target_kmers;
+use ::structs::map_entry::{get_kmer_counters_saturation, MAX_COUNTER_VALUE};
+use assembler_minimizer_bucketing::{
+    AssemblerMinimizerBucketingExecutorFactory, CountedSequenceData,
+};
 use colors::colors_manager::color_types::{
     GlobalColorsTableWriter, MinimizerBucketingSeqColorDataType,
 };
 use colors::colors_manager::{color_types, ColorsManager};
 use config::{
-    get_compression_level_info, get_memory_mode, BucketIndexType, SwapPriority,
+    get_compression_level_info, get_memory_mode, BucketIndexType, PipelineSettings, SwapPriority,
     MINIMUM_SUBBUCKET_KMERS_COUNT, RESPLITTING_MAX_K_M_DIFFERENCE,
 };
 use crossbeam::queue::*;
+use hashes::kmer_set::KmerSet;
 use hashes::HashFunctionFactory;
 use hashes::MinimizerHashFunctionFactory;
 use io::structs::hash_entry::HashEntry;
 use io::structs::hash_entry::{Direction, HashEntrySerializer};
+use io::unused_reads::{get_reads_tracker, ReadsTracker};
 use kmers_transform::processor::KmersTransformProcessor;
 use kmers_transform::{KmersTransform, KmersTransformExecutorFactory};
 use minimizer_bucketing::{MinimizerBucketingCommonData, MinimizerBucketingExecutorFactory};
@@ -27,18 +36,28 @@ use parallel_processor::buckets::{LockFreeBucket, MultiThreadBuckets};
 use parallel_processor::execution_manager::memory_tracker::MemoryTracker;
 #[cfg(feature = "mem-analysis")]
 use parallel_processor::mem_tracker::MemoryInfo;
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use std::cmp::min;
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use utils::owned_drop::OwnedDrop;
+use utils::phase_log::{log_warning, start_phase};
 
+mod exact_kmers;
 mod final_executor;
+mod kmers_counting;
+mod link_coverage;
 mod map_processor;
 mod preprocessor;
 pub mod structs;
+mod target_kmers;
+
+pub use crate::exact_kmers::set_exact_kmers_mode;
+pub use crate::kmers_counting::{set_kmer_counting_mode, KmerCountingMode};
+pub use crate::link_coverage::set_min_link_coverage;
+pub use crate::map_processor::{set_merge_hashmap_load_factor, DEFAULT_MERGE_HASHMAP_LOAD_FACTOR};
+pub use crate::target_kmers::set_target_kmers_memory;
 
 pub struct GlobalMergeData<
     H: MinimizerHashFunctionFactory,
@@ -49,6 +68,18 @@ pub struct GlobalMergeData<
     m: usize,
     buckets_count: usize,
     min_multiplicity: usize,
+    /// Value at which the kmers counters stop increasing
+    kmer_counters_saturation: usize,
+    /// Compare the full sequences of the kmers with the same hash, to avoid merging distinct kmers
+    exact_kmers: bool,
+    /// Count the kmers repeated in the same super-kmer only once
+    count_per_read: bool,
+    /// Minimum occurrences of the link between two kmers to extend a unitig through it
+    min_link_coverage: usize,
+    /// If present, only these kmers are kept
+    target_kmers: Option<KmerSet<MH::HashTypeUnextendable>>,
+    /// Marks the reads with kmers kept in the graph, when the unused reads are written
+    reads_tracker: Option<Arc<ReadsTracker>>,
     colors_global_table: Arc<GlobalColorsTableWriter<H, MH, CX>>,
     output_results_buckets:
         ArrayQueue<ResultsBucket<color_types::PartialUnitigsColorStructure<H, MH, CX>>>,
@@ -57,6 +88,8 @@ pub struct GlobalMergeData<
     sequences_size_total: AtomicU64,
     hasnmap_kmers_total: AtomicU64,
     kmer_batches_count: AtomicU64,
+    saturated_kmers_total: AtomicU64,
+    colliding_kmers_total: AtomicU64,
 }
 
 pub struct ParallelKmersMergeFactory<
@@ -70,7 +103,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
 {
     type SequencesResplitterFactory = AssemblerMinimizerBucketingExecutorFactory<H, CX>;
     type GlobalExtraData = GlobalMergeData<H, MH, CX>;
-    type AssociatedExtraData = MinimizerBucketingSeqColorDataType<CX>;
+    type AssociatedExtraData = CountedSequenceData<MinimizerBucketingSeqColorDataType<CX>>;
 
     type PreprocessorType = ParallelKmersMergePreprocessor<H, MH, CX>;
     type MapProcessorType = ParallelKmersMergeMapProcessor<H, MH, CX>;
@@ -139,19 +172,28 @@ pub fn kmers_merge<
     colors_global_table: Arc<GlobalColorsTableWriter<H, MH, CX>>,
     buckets_count: usize,
     min_multiplicity: usize,
+    target_file: Option<&Path>,
     out_directory: P,
     k: usize,
     m: usize,
     threads_count: usize,
+    settings: PipelineSettings,
 ) -> RetType {
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase("phase: kmers merge".to_string());
+    start_phase("phase: kmers merge".to_string());
 
     H::initialize(k);
     MH::initialize(k);
     *KMERGE_TEMP_DIR.write() = Some(out_directory.as_ref().to_path_buf());
 
+    let target_kmers = target_file.map(|target_file| {
+        load_target_kmers::<MH>(
+            target_file,
+            k,
+            out_directory.as_ref(),
+            settings.strict_input,
+        )
+    });
+
     let hashes_buckets = Arc::new(MultiThreadBuckets::<LockFreeBinaryWriter>::new(
         buckets_count,
         out_directory.as_ref().join("hashes"),
@@ -188,11 +230,41 @@ pub fn kmers_merge<
         assert!(res);
     }
 
+    // The colors manager needs the exact number of occurrences of each kmer,
+    // and a kmer must always be able to reach the minimum multiplicity
+    let kmer_counters_saturation = if CX::COLORS_ENABLED {
+        MAX_COUNTER_VALUE
+    } else {
+        get_kmer_counters_saturation()
+            .max(min_multiplicity.saturating_mul(2).min(MAX_COUNTER_VALUE))
+    };
+
+    let count_per_read = get_kmer_counting_mode() == KmerCountingMode::CountPerRead;
+    if count_per_read && CX::COLORS_ENABLED {
+        log_warning(
+            "WARNING: the kmers are counted per read only in uncolored builds, counting each occurrence"
+                .to_string(),
+        );
+    }
+    let count_per_read = count_per_read && !CX::COLORS_ENABLED;
+
+    let reads_tracker = get_reads_tracker();
+    if let Some(reads_tracker) = &reads_tracker {
+        reads_tracker.start_merge();
+    }
+
     let global_data = Arc::new(GlobalMergeData::<H, MH, CX> {
         k,
         m,
         buckets_count,
         min_multiplicity,
+        kmer_counters_saturation,
+        // Invertible hashes are equal only for identical kmers
+        exact_kmers: is_exact_kmers_mode() && !MH::INVERTIBLE,
+        count_per_read,
+        min_link_coverage: get_min_link_coverage(),
+        target_kmers,
+        reads_tracker,
         colors_global_table,
         output_results_buckets,
         hashes_buckets: hashes_buckets.clone(),
@@ -207,10 +279,13 @@ pub fn kmers_merge<
             k,
             1,
             (),
+            settings,
         )),
         sequences_size_total: AtomicU64::new(0),
         hasnmap_kmers_total: AtomicU64::new(0),
         kmer_batches_count: AtomicU64::new(0),
+        saturated_kmers_total: AtomicU64::new(0),
+        colliding_kmers_total: AtomicU64::new(0),
     });
 
     KmersTransform::<ParallelKmersMergeFactory<H, MH, CX>>::new(
@@ -218,13 +293,30 @@ pub fn kmers_merge<
         out_directory.as_ref(),
         buckets_counters_path,
         buckets_count,
-        global_data,
+        global_data.clone(),
         threads_count,
+        settings,
         k,
         MINIMUM_SUBBUCKET_KMERS_COUNT as u64,
     )
     .parallel_kmers_transform();
 
+    let saturated_kmers_total = global_data.saturated_kmers_total.load(Ordering::Relaxed);
+    if saturated_kmers_total > 0 {
+        log_warning(format!(
+            "WARNING: {} kmers occurrences exceeded the counters saturation value {}, the counts of their kmers are capped",
+            saturated_kmers_total, kmer_counters_saturation
+        ));
+    }
+
+    let colliding_kmers_total = global_data.colliding_kmers_total.load(Ordering::Relaxed);
+    if colliding_kmers_total > 0 {
+        log_warning(format!(
+            "WARNING: {} distinct kmers had colliding hashes, they were told apart by comparing their sequences",
+            colliding_kmers_total
+        ));
+    }
+
     RetType {
         sequences,
         hashes: hashes_buckets.finalize(),
@@ -235,7 +327,7 @@ pub fn kmers_merge<
 mod tests {
     use colors::colors_manager::{ColorsManager, ColorsMergeManager};
     use colors::non_colored::NonColoredManager;
-    use config::{FLUSH_QUEUE_FACTOR, KEEP_FILES, PREFER_MEMORY};
+    use config::{PipelineSettings, FLUSH_QUEUE_FACTOR, KEEP_FILES, PREFER_MEMORY};
     use io::generate_bucket_names;
     use parallel_processor::memory_data_size::MemoryDataSize;
     use parallel_processor::memory_fs::MemoryFs;
@@ -275,7 +367,7 @@ mod tests {
             > as ColorsMergeManager<
                 hashes::cn_nthash::CanonicalNtHashIteratorFactory,
                 hashes::cn_rkhash::u128::CanonicalRabinKarpHashFactory,
-            >>::create_colors_table("", &[]),
+            >>::create_colors_table("", &[], 63, Path::new(""), None),
         );
 
         let k = 63;
@@ -328,10 +420,12 @@ mod tests {
             global_colors_table.clone(),
             buckets_count,
             min_multiplicity,
+            None,
             Path::new(TEMP_DIR),
             k,
             m,
             threads_count,
+            PipelineSettings::default(),
         );
     }
 }
@@ -0,0 +1,106 @@
+use hashbrown::HashMap;
+use hashes::Strand;
+use std::cmp::min;
+use std::hash::Hash;
+
+pub(crate) use assembler_minimizer_bucketing::get_min_link_coverage;
+pub use assembler_minimizer_bucketing::set_min_link_coverage;
+
+/// Counts the occurrences of the links between the consecutive kmers of the reads of a bucket.
+/// The links crossing the super-kmers boundaries are also sent by the bucketing to the bucket
+/// of the following super-kmer, so the counts of a bucket include all the links of its kmers.
+/// A link is identified by the hashes of its kmers with the strands they were read on, in the
+/// order of the reads; a link and its reverse complement share the same key
+#[derive(Default)]
+pub struct LinkCoverageCounter<T: Hash + Eq + Ord + Copy> {
+    counts: HashMap<LinkKey<T>, u32>,
+}
+
+type LinkKey<T> = ((T, Strand), (T, Strand));
+
+impl<T: Hash + Eq + Ord + Copy> LinkCoverageCounter<T> {
+    #[inline(always)]
+    fn link_key(first: (T, Strand), second: (T, Strand)) -> LinkKey<T> {
+        let reverse = ((second.0, second.1.reverse()), (first.0, first.1.reverse()));
+        min((first, second), reverse)
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.clear();
+    }
+
+    pub fn links_count(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Counts an occurrence of the link from the first kmer to the second one
+    #[inline(always)]
+    pub fn add_link(&mut self, first: (T, Strand), second: (T, Strand)) {
+        let count = self
+            .counts
+            .entry(Self::link_key(first, second))
+            .or_insert(0);
+        *count = count.saturating_add(1);
+    }
+
+    pub fn get_coverage(&self, first: (T, Strand), second: (T, Strand)) -> usize {
+        self.counts
+            .get(&Self::link_key(first, second))
+            .map_or(0, |count| *count as usize)
+    }
+
+    /// If the link from the first kmer to the second one can be used to extend a unitig,
+    /// always true if min_coverage is 1
+    #[inline(always)]
+    pub fn has_min_coverage(
+        &self,
+        first: (T, Strand),
+        second: (T, Strand),
+        min_coverage: usize,
+    ) -> bool {
+        min_coverage <= 1 || self.get_coverage(first, second) >= min_coverage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LinkCoverageCounter;
+    use hashes::Strand::{Forward, Reverse};
+
+    #[test]
+    fn single_read_links_are_pruned() {
+        let mut counter = LinkCoverageCounter::default();
+
+        // Three reads going through the 1 -> 2 link, a single one through the 2 -> 3 link
+        for _ in 0..3 {
+            counter.add_link((1u64, Forward), (2, Forward));
+        }
+        counter.add_link((2, Forward), (3, Reverse));
+
+        assert_eq!(counter.get_coverage((1, Forward), (2, Forward)), 3);
+        assert!(counter.has_min_coverage((1, Forward), (2, Forward), 2));
+        assert!(!counter.has_min_coverage((2, Forward), (3, Reverse), 2));
+        // Links never seen are kept only without a threshold
+        assert!(counter.has_min_coverage((1, Forward), (3, Forward), 1));
+        assert!(!counter.has_min_coverage((1, Forward), (3, Forward), 2));
+
+        counter.reset();
+        assert_eq!(counter.links_count(), 0);
+    }
+
+    #[test]
+    fn links_are_keyed_on_the_strands() {
+        let mut counter = LinkCoverageCounter::default();
+
+        counter.add_link((1u64, Forward), (2, Reverse));
+        // The same link read on the reverse complement
+        counter.add_link((2, Forward), (1, Reverse));
+
+        assert_eq!(counter.get_coverage((1, Forward), (2, Reverse)), 2);
+        assert_eq!(counter.get_coverage((2, Forward), (1, Reverse)), 2);
+        // Different links between the same kmers
+        assert_eq!(counter.get_coverage((1, Forward), (2, Forward)), 0);
+        assert_eq!(counter.get_coverage((2, Reverse), (1, Forward)), 0);
+        assert_eq!(counter.links_count(), 1);
+    }
+}
@@ -1,15 +1,19 @@
+use crate::exact_kmers::ExactKmersVerifier;
+use crate::link_coverage::LinkCoverageCounter;
 use crate::ParallelKmersMergeFactory;
+use assembler_minimizer_bucketing::CountedSequenceData;
 use colors::colors_manager::color_types::MinimizerBucketingSeqColorDataType;
 use colors::colors_manager::{color_types, ColorsManager};
 use colors::colors_manager::{ColorsMergeManager, MinimizerBucketingSeqColorData};
 use config::{READ_FLAG_INCL_BEGIN, READ_FLAG_INCL_END};
-use hashbrown::HashMap;
-use hashes::ExtendableHashTraitType;
+use hashbrown::{HashMap, HashSet};
 use hashes::HashFunction;
 use hashes::HashableSequence;
+use hashes::{ExtendableHashTraitType, Strand};
 use hashes::{HashFunctionFactory, MinimizerHashFunctionFactory};
-use io::compressed_read::CompressedReadIndipendent;
+use io::compressed_read::{CompressedRead, CompressedReadBuilder, CompressedReadIndipendent};
 use io::concurrent::temp_reads::extra_data::SequenceExtraDataTempBufferManagement;
+use io::unused_reads::ReadsTracker;
 use io::varint::encode_varint;
 use kmers_transform::processor::KmersTransformProcessor;
 use kmers_transform::{
@@ -25,7 +29,7 @@ use std::cmp::{max, min};
 use std::mem::size_of;
 use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicU64, Ordering};
 use structs::map_entry::MapEntry;
 
 instrumenter::use_instrumenter!();
@@ -42,19 +46,67 @@ pub struct ParallelKmersMergeMapPacket<
     pub saved_reads: Vec<u8>,
     pub encoded_saved_reads_indexes: Vec<u8>,
     pub temp_colors: color_types::ColorsBufferTempStructure<H, MH, CX>,
+    /// Used only in exact kmers mode
+    pub exact_verifier: ExactKmersVerifier<MH::HashTypeUnextendable>,
+    /// The entries of the kmers colliding with the first kmer of their hash, by hash and generation.
+    /// Used only in exact kmers mode
+    pub collision_map: HashMap<
+        (MH::HashTypeUnextendable, usize),
+        MapEntry<color_types::HashMapTempColorIndex<H, MH, CX>>,
+    >,
+    /// Used only with a minimum link coverage
+    pub link_coverage: LinkCoverageCounter<MH::HashTypeUnextendable>,
+    /// The reads of the bucket with the end of their kmers in tracked_reads_kmers.
+    /// Used only when the reads are tracked
+    pub tracked_reads: Vec<((usize, u64), usize)>,
+    /// The kmers of the tracked reads, by hash and generation
+    pub tracked_reads_kmers: Vec<(MH::HashTypeUnextendable, usize)>,
     average_hasmap_size: u64,
     average_sequences_size: u64,
 }
 
+/// The highest load factor of the kmers merge hash maps, the one at which hashbrown grows its tables
+pub const MAX_MERGE_HASHMAP_LOAD_FACTOR: f64 = 0.875;
+
+/// The default load factor of the kmers merge hash maps, that keeps the tables as small as hashbrown allows
+pub const DEFAULT_MERGE_HASHMAP_LOAD_FACTOR: f64 = MAX_MERGE_HASHMAP_LOAD_FACTOR;
+
+const MIN_MERGE_HASHMAP_LOAD_FACTOR: f64 = 0.1;
+
+// The bits of the f64 load factor
+static MERGE_HASHMAP_LOAD_FACTOR: AtomicU64 =
+    AtomicU64::new(DEFAULT_MERGE_HASHMAP_LOAD_FACTOR.to_bits());
+
+/// Sets the load factor of the kmers merge hash maps, the ratio between the expected kmers of a bucket
+/// and the slots of the table allocated for them. Lower load factors allocate larger tables, with shorter
+/// probe sequences that make the merge faster at the cost of more memory. The maps are sized from the
+/// average kmers of the buckets, and the larger buckets still grow them up to hashbrown's own load factor
+/// of 0.875, so values are clamped between 0.1 and 0.875
+pub fn set_merge_hashmap_load_factor(load_factor: f64) {
+    MERGE_HASHMAP_LOAD_FACTOR.store(
+        load_factor
+            .clamp(MIN_MERGE_HASHMAP_LOAD_FACTOR, MAX_MERGE_HASHMAP_LOAD_FACTOR)
+            .to_bits(),
+        Ordering::Relaxed,
+    );
+}
+
+// The hashbrown capacity of a table with expected_size / load_factor slots
 #[inline]
-fn clear_hashmap<K, V>(hashmap: &mut HashMap<K, V>, suggested_size: usize) {
-    let suggested_capacity = (suggested_size / 2).next_power_of_two();
+fn load_factor_capacity(expected_size: usize, load_factor: f64) -> usize {
+    (expected_size as f64 / load_factor * MAX_MERGE_HASHMAP_LOAD_FACTOR) as usize
+}
 
-    if hashmap.capacity() < suggested_capacity {
-        hashmap.clear();
-    } else {
+#[inline]
+fn clear_hashmap<K, V>(hashmap: &mut HashMap<K, V>, expected_size: usize, load_factor: f64) {
+    let capacity = load_factor_capacity(expected_size, load_factor);
+
+    if hashmap.capacity() > capacity * 2 {
         // Reset the hashmap if it gets too big
-        *hashmap = HashMap::with_capacity(suggested_capacity);
+        *hashmap = HashMap::with_capacity(capacity);
+    } else {
+        hashmap.clear();
+        hashmap.reserve(capacity);
     }
 }
 
@@ -65,12 +117,20 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
 
     fn allocate_new(_init_data: &Self::InitData) -> Self {
         Self {
-            rhash_map: HashMap::with_capacity(4096),
+            rhash_map: HashMap::with_capacity(load_factor_capacity(
+                4096,
+                f64::from_bits(MERGE_HASHMAP_LOAD_FACTOR.load(Ordering::Relaxed)),
+            )),
             saved_reads: vec![],
             encoded_saved_reads_indexes: vec![],
             temp_colors: CX::ColorsMergeManagerType::<H, MH>::allocate_temp_buffer_structure(
                 KMERGE_TEMP_DIR.read().deref().as_ref().unwrap(),
             ),
+            exact_verifier: ExactKmersVerifier::default(),
+            collision_map: HashMap::new(),
+            link_coverage: LinkCoverageCounter::default(),
+            tracked_reads: vec![],
+            tracked_reads_kmers: vec![],
             average_hasmap_size: 0,
             average_sequences_size: 0,
         }
@@ -80,6 +140,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
         clear_hashmap(
             &mut self.rhash_map,
             max(8192, self.average_hasmap_size as usize),
+            f64::from_bits(MERGE_HASHMAP_LOAD_FACTOR.load(Ordering::Relaxed)),
         );
 
         let saved_reads_suggested_size = (self.average_sequences_size).next_power_of_two() as usize;
@@ -97,6 +158,11 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
         }
 
         CX::ColorsMergeManagerType::<H, MH>::reinit_temp_buffer_structure(&mut self.temp_colors);
+        self.exact_verifier.reset();
+        self.collision_map.clear();
+        self.link_coverage.reset();
+        self.tracked_reads.clear();
+        self.tracked_reads_kmers.clear();
     }
 }
 
@@ -109,7 +175,16 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                 MH::HashTypeUnextendable,
                 MapEntry<color_types::HashMapTempColorIndex<H, MH, CX>>,
             )>() + 1)
+            + self.collision_map.len()
+                * size_of::<(
+                    (MH::HashTypeUnextendable, usize),
+                    MapEntry<color_types::HashMapTempColorIndex<H, MH, CX>>,
+                )>()
             + self.saved_reads.len()
+            + self.link_coverage.links_count()
+                * (size_of::<(MH::HashTypeUnextendable, Strand)>() * 2 + 4)
+            + self.tracked_reads.len() * size_of::<((usize, u64), usize)>()
+            + self.tracked_reads_kmers.len() * size_of::<(MH::HashTypeUnextendable, usize)>()
     }
 }
 
@@ -124,6 +199,8 @@ pub struct ParallelKmersMergeMapProcessor<
         >,
     >,
     last_saved_len: usize,
+    /// The kmers already counted in the current sequence, in the count per read mode
+    sequence_kmers: HashSet<(MH::HashTypeUnextendable, usize)>,
     mem_tracker: MemoryTracker<KmersTransformProcessor<ParallelKmersMergeFactory<H, MH, CX>>>,
 }
 
@@ -136,9 +213,213 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
         Self {
             map_packet: None,
             last_saved_len: 0,
+            sequence_kmers: HashSet::new(),
             mem_tracker,
         }
     }
+
+    // Adds the kmers of a sequence to the map, returning how many increments were dropped by the
+    // saturation of the counters
+    #[inline(always)]
+    fn add_sequence_kmers(
+        &mut self,
+        global_data: &<ParallelKmersMergeFactory<H, MH, CX> as KmersTransformExecutorFactory>::GlobalExtraData,
+        flags: u8,
+        read: CompressedRead,
+        multiplicity: u64,
+        read_id: Option<(usize, u64)>,
+        kmer_colors: impl Iterator<
+            Item = <MinimizerBucketingSeqColorDataType<CX> as MinimizerBucketingSeqColorData>::KmerColor,
+        >,
+        stats: &mut GroupProcessStats,
+    ) -> u64 {
+        let k = global_data.k;
+
+        let map_packet = self.map_packet.as_mut().unwrap().deref_mut();
+
+        let mut saturated_kmers_count = 0;
+
+        let hashes = MH::new(read, k);
+
+        stats.total_kmers += (read.bases_count() - k + 1) as u64;
+
+        let last_hash_pos = read.bases_count() - k;
+        let mut min_idx = usize::MAX;
+        let mut max_idx = 0;
+
+        let mut previous_hash = None;
+
+        for ((idx, hash), kmer_color) in hashes.iter_enumerate().zip(kmer_colors) {
+            let begin_ignored = flags & READ_FLAG_INCL_BEGIN == 0 && idx == 0;
+            let end_ignored = flags & READ_FLAG_INCL_END == 0 && idx == last_hash_pos;
+
+            let is_forward = hash.is_forward();
+
+            let generation = if global_data.exact_kmers {
+                let kmer = read.sub_slice(idx..(idx + k));
+                if is_forward {
+                    map_packet
+                        .exact_verifier
+                        .verify(hash.to_unextendable(), kmer.as_bases_iter())
+                } else {
+                    map_packet.exact_verifier.verify(
+                        hash.to_unextendable(),
+                        kmer.as_reverse_complement_bases_iter(),
+                    )
+                }
+            } else {
+                0
+            };
+
+            if global_data.min_link_coverage > 1 {
+                let hash = hash.to_unextendable_with_strand();
+                if let Some(previous_hash) = previous_hash {
+                    map_packet.link_coverage.add_link(previous_hash, hash);
+                }
+                previous_hash = Some(hash);
+            }
+
+            let new_entry = || {
+                stats.unique_kmers += 1;
+                MapEntry::new(CX::ColorsMergeManagerType::<H, MH>::new_color_index())
+            };
+
+            // Kmers colliding with the first kmer of their hash get their own entries
+            let entry = if generation == 0 {
+                map_packet
+                    .rhash_map
+                    .entry(hash.to_unextendable())
+                    .or_insert_with(new_entry)
+            } else {
+                map_packet
+                    .collision_map
+                    .entry((hash.to_unextendable(), generation))
+                    .or_insert_with(new_entry)
+            };
+
+            if read_id.is_some() {
+                map_packet
+                    .tracked_reads_kmers
+                    .push((hash.to_unextendable(), generation));
+            }
+
+            entry.update_flags(
+                ((begin_ignored as u8) << ((!is_forward) as u8))
+                    | ((end_ignored as u8) << (is_forward as u8)),
+            );
+
+            let counted = !global_data.count_per_read
+                || self
+                    .sequence_kmers
+                    .insert((hash.to_unextendable(), generation));
+
+            let counter_before = entry.get_counter();
+            if counted {
+                saturated_kmers_count += entry
+                    .incr_by(multiplicity as usize, global_data.kmer_counters_saturation)
+                    as u64;
+            }
+
+            CX::ColorsMergeManagerType::<H, MH>::add_temp_buffer_structure_el(
+                &mut map_packet.temp_colors,
+                &kmer_color,
+                (idx, hash.to_unextendable()),
+                entry,
+            );
+
+            // The counter can skip past the threshold when the sequence has a multiplicity
+            if counter_before < global_data.min_multiplicity
+                && entry.get_counter() >= global_data.min_multiplicity
+            {
+                min_idx = min(min_idx, idx / 4);
+                max_idx = max(max_idx, idx);
+            }
+        }
+
+        if let Some(read_id) = read_id {
+            map_packet
+                .tracked_reads
+                .push((read_id, map_packet.tracked_reads_kmers.len()));
+        }
+
+        CX::ColorsMergeManagerType::<H, MH>::add_temp_buffer_sequence(
+            &mut map_packet.temp_colors,
+            read,
+            multiplicity,
+            global_data.k,
+            global_data.m,
+            flags,
+        );
+
+        if !MH::INVERTIBLE {
+            if min_idx != usize::MAX {
+                encode_varint(
+                    |b| {
+                        map_packet.encoded_saved_reads_indexes.extend_from_slice(b);
+                    },
+                    (map_packet.saved_reads.len() - self.last_saved_len) as u64,
+                );
+                self.last_saved_len = map_packet.saved_reads.len();
+                map_packet
+                    .saved_reads
+                    .extend_from_slice(&read.get_packed_slice()[min_idx..((max_idx + k + 3) / 4)]);
+            }
+        }
+
+        saturated_kmers_count
+    }
+
+    // Marks the reads of the bucket with kmers that reached the minimum multiplicity
+    // and with kmers kept in the graph, once all the kmers of the bucket are counted
+    fn mark_tracked_reads(
+        global_data: &<ParallelKmersMergeFactory<H, MH, CX> as KmersTransformExecutorFactory>::GlobalExtraData,
+        reads_tracker: &ReadsTracker,
+        map_packet: &ParallelKmersMergeMapPacket<H, MH, CX>,
+    ) {
+        let mut kmers_start = 0;
+        for &((block, read_index), kmers_end) in map_packet.tracked_reads.iter() {
+            let mut covered = false;
+            let mut kept = false;
+            for (hash, generation) in &map_packet.tracked_reads_kmers[kmers_start..kmers_end] {
+                let entry = if *generation == 0 {
+                    map_packet.rhash_map.get(hash)
+                } else {
+                    map_packet.collision_map.get(&(*hash, *generation))
+                };
+                if entry.is_some_and(|entry| {
+                    entry.get_kmer_multiplicity() >= global_data.min_multiplicity
+                }) {
+                    covered = true;
+                    if global_data
+                        .target_kmers
+                        .as_ref()
+                        .map_or(true, |target_kmers| target_kmers.contains(hash))
+                    {
+                        kept = true;
+                        break;
+                    }
+                }
+            }
+            kmers_start = kmers_end;
+            reads_tracker.mark_read(block, read_index, covered, kept);
+        }
+    }
+
+    // Counts the link of a (k+1)-mer sent by the bucketing only for its coverage
+    #[inline(always)]
+    fn add_link_record(
+        &mut self,
+        global_data: &<ParallelKmersMergeFactory<H, MH, CX> as KmersTransformExecutorFactory>::GlobalExtraData,
+        read: CompressedRead,
+    ) {
+        let map_packet = self.map_packet.as_mut().unwrap().deref_mut();
+
+        let hashes = MH::new(read, global_data.k);
+        let mut hashes = hashes.iter().map(|hash| hash.to_unextendable_with_strand());
+        let first = hashes.next().unwrap();
+        let second = hashes.next().unwrap();
+        map_packet.link_coverage.add_link(first, second);
+    }
 }
 
 impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager>
@@ -164,98 +445,86 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
         global_data: &<ParallelKmersMergeFactory<H, MH, CX> as KmersTransformExecutorFactory>::GlobalExtraData,
         batch: &Vec<(
             u8,
-            MinimizerBucketingSeqColorDataType<CX>,
+            CountedSequenceData<MinimizerBucketingSeqColorDataType<CX>>,
             CompressedReadIndipendent,
         )>,
         extra_data_buffer: &<MinimizerBucketingSeqColorDataType<CX> as SequenceExtraDataTempBufferManagement>::TempBuffer,
         ref_sequences: &Vec<u8>,
     ) -> GroupProcessStats {
-        let k = global_data.k;
-
-        let map_packet = self.map_packet.as_mut().unwrap().deref_mut();
-
-        let mut kmers_count = 0;
-        let mut unique_kmers_count = 0;
-
-        for (flags, color, read) in batch.iter() {
+        let mut stats = GroupProcessStats {
+            total_kmers: 0,
+            unique_kmers: 0,
+        };
+        let mut saturated_kmers_count = 0;
+        let mut reverse_complement = CompressedReadBuilder::new();
+
+        for (flags, sequence_data, read) in batch.iter() {
             let read = read.as_reference(ref_sequences);
 
-            let hashes = MH::new(read, k);
-
-            kmers_count += (read.bases_count() - k + 1) as u64;
-
-            let last_hash_pos = read.bases_count() - k;
-            let mut min_idx = usize::MAX;
-            let mut max_idx = 0;
-
-            for ((idx, hash), kmer_color) in hashes
-                .iter_enumerate()
-                .zip(color.get_iterator(extra_data_buffer))
-            {
-                let begin_ignored = flags & READ_FLAG_INCL_BEGIN == 0 && idx == 0;
-                let end_ignored = flags & READ_FLAG_INCL_END == 0 && idx == last_hash_pos;
-
-                let is_forward = hash.is_forward();
-
-                let entry = map_packet
-                    .rhash_map
-                    .entry(hash.to_unextendable())
-                    .or_insert_with(|| {
-                        unique_kmers_count += 1;
-                        MapEntry::new(CX::ColorsMergeManagerType::<H, MH>::new_color_index())
-                    });
-
-                entry.update_flags(
-                    ((begin_ignored as u8) << ((!is_forward) as u8))
-                        | ((end_ignored as u8) << (is_forward as u8)),
-                );
-
-                entry.incr();
-
-                CX::ColorsMergeManagerType::<H, MH>::add_temp_buffer_structure_el(
-                    &mut map_packet.temp_colors,
-                    &kmer_color,
-                    (idx, hash.to_unextendable()),
-                    entry,
-                );
-
-                if entry.get_counter() == global_data.min_multiplicity {
-                    min_idx = min(min_idx, idx / 4);
-                    max_idx = max(max_idx, idx);
+            if sequence_data.link_only {
+                self.add_link_record(global_data, read);
+                if sequence_data.both_strands {
+                    reverse_complement.clear();
+                    reverse_complement.push_reverse_complement(&read);
+                    self.add_link_record(global_data, reverse_complement.finish());
                 }
+                continue;
             }
 
-            CX::ColorsMergeManagerType::<H, MH>::add_temp_buffer_sequence(
-                &mut map_packet.temp_colors,
-                read,
-                global_data.k,
-                global_data.m,
+            if global_data.count_per_read {
+                self.sequence_kmers.clear();
+            }
+
+            saturated_kmers_count += self.add_sequence_kmers(
+                global_data,
                 *flags,
+                read,
+                sequence_data.multiplicity,
+                sequence_data.read,
+                sequence_data.colors.get_iterator(extra_data_buffer),
+                &mut stats,
             );
 
-            if !MH::INVERTIBLE {
-                if min_idx != usize::MAX {
-                    encode_varint(
-                        |b| {
-                            map_packet.encoded_saved_reads_indexes.extend_from_slice(b);
-                        },
-                        (map_packet.saved_reads.len() - self.last_saved_len) as u64,
-                    );
-                    self.last_saved_len = map_packet.saved_reads.len();
-                    map_packet.saved_reads.extend_from_slice(
-                        &read.get_packed_slice()[min_idx..((max_idx + k + 3) / 4)],
-                    );
-                }
+            // The canonical inputs of a build where the reverse complementary kmers are different
+            // also add the kmers of their reverse complement, bucketed together by the canonical minimizers
+            if sequence_data.both_strands {
+                let kmers_count = read.bases_count() - global_data.k + 1;
+                let kmer_colors: Vec<_> = sequence_data
+                    .colors
+                    .get_iterator(extra_data_buffer)
+                    .take(kmers_count)
+                    .collect();
+
+                reverse_complement.clear();
+                reverse_complement.push_reverse_complement(&read);
+
+                // The sequence is read backwards, so its begin and end are swapped
+                let reverse_flags = ((flags & READ_FLAG_INCL_BEGIN != 0) as u8
+                    * READ_FLAG_INCL_END)
+                    | ((flags & READ_FLAG_INCL_END != 0) as u8 * READ_FLAG_INCL_BEGIN);
+
+                saturated_kmers_count += self.add_sequence_kmers(
+                    global_data,
+                    reverse_flags,
+                    reverse_complement.finish(),
+                    sequence_data.multiplicity,
+                    sequence_data.read,
+                    kmer_colors.into_iter().rev(),
+                    &mut stats,
+                );
             }
         }
 
-        self.mem_tracker
-            .update_memory_usage(&[map_packet.get_size(), 0]);
-
-        GroupProcessStats {
-            total_kmers: kmers_count,
-            unique_kmers: unique_kmers_count,
+        if saturated_kmers_count > 0 {
+            global_data
+                .saturated_kmers_total
+                .fetch_add(saturated_kmers_count, Ordering::Relaxed);
         }
+
+        let map_size = self.map_packet.as_ref().unwrap().get_size();
+        self.mem_tracker.update_memory_usage(&[map_size, 0]);
+
+        stats
     }
 
     #[instrumenter::track]
@@ -270,6 +539,10 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
 
         let mut map_packet = self.map_packet.take().unwrap();
 
+        if let Some(reads_tracker) = &global_data.reads_tracker {
+            Self::mark_tracked_reads(global_data, reads_tracker, &map_packet);
+        }
+
         let sequences_sizes = map_packet.saved_reads.len() as u64;
         let all_kmers = map_packet.rhash_map.len() as u64;
 
@@ -296,3 +569,37 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
         map_packet
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{clear_hashmap, DEFAULT_MERGE_HASHMAP_LOAD_FACTOR};
+    use hashbrown::HashMap;
+
+    #[test]
+    fn higher_load_factor_allocates_smaller_maps() {
+        const ELEMENTS_COUNT: u64 = 100_000;
+
+        let merged_capacity = |load_factor: f64| {
+            let mut hashmap: HashMap<u64, u64> = HashMap::new();
+            clear_hashmap(&mut hashmap, ELEMENTS_COUNT as usize, load_factor);
+            let capacity = hashmap.capacity();
+
+            // Merges the same kmers twice, summing their counts
+            for i in (0..ELEMENTS_COUNT).chain(0..ELEMENTS_COUNT) {
+                *hashmap.entry(i).or_default() += i;
+            }
+            assert_eq!(hashmap.len(), ELEMENTS_COUNT as usize);
+            assert!((0..ELEMENTS_COUNT).all(|i| hashmap.get(&i) == Some(&(i * 2))));
+
+            // The table sized for the elements does not grow while they are merged
+            assert_eq!(hashmap.capacity(), capacity);
+            capacity
+        };
+
+        let default_capacity = merged_capacity(DEFAULT_MERGE_HASHMAP_LOAD_FACTOR);
+        let half_loaded_capacity = merged_capacity(0.4);
+        let sparse_capacity = merged_capacity(0.2);
+        assert!(default_capacity < half_loaded_capacity);
+        assert!(half_loaded_capacity < sparse_capacity);
+    }
+}
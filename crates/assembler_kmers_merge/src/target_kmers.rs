@@ -0,0 +1,81 @@
+use hashes::kmer_set::{KmerSet, KmerSetBackend, KmerSetBuilder};
+use hashes::{ExtendableHashTraitType, HashFunction, HashFunctionFactory};
+use io::sequences_reader::SequencesReader;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use utils::Utils;
+
+/// 1GB for the kmers of the target sequences kept in memory
+pub const DEFAULT_TARGET_KMERS_MEMORY: usize = 1024 * 1024 * 1024;
+
+static TARGET_KMERS_MEMORY: AtomicUsize = AtomicUsize::new(DEFAULT_TARGET_KMERS_MEMORY);
+
+/// Sets the maximum memory (in bytes) used by the kmers of the target sequences, that are kept in a
+/// sorted index file in the temp dir if they do not fit. With None the default of 1GB is used
+pub fn set_target_kmers_memory(max_memory: Option<usize>) {
+    TARGET_KMERS_MEMORY.store(
+        max_memory.unwrap_or(DEFAULT_TARGET_KMERS_MEMORY),
+        Ordering::Relaxed,
+    );
+}
+
+/// Loads the kmers of the target sequences, together with all the kmers that can be reached from them
+/// with a single forward or backward extension. Only these kmers are kept when assembling a target
+pub fn load_target_kmers<MH: HashFunctionFactory>(
+    target_file: impl AsRef<Path>,
+    k: usize,
+    temp_dir: &Path,
+    strict_input: bool,
+) -> KmerSet<MH::HashTypeUnextendable> {
+    // Each target base starts at most one kmer, added with its 8 adjacent kmers
+    let expected_kmers = std::fs::metadata(target_file.as_ref())
+        .map(|metadata| metadata.len() as usize * 9)
+        .unwrap_or(0);
+    let backend = KmerSetBackend::for_memory_budget::<MH::HashTypeUnextendable>(
+        expected_kmers,
+        TARGET_KMERS_MEMORY.load(Ordering::Relaxed),
+        temp_dir.join("target-kmers.idx"),
+    );
+    let mut target_kmers = KmerSetBuilder::new(backend, expected_kmers);
+    let mut compressed = vec![];
+
+    SequencesReader::new()
+        .with_strict_input(strict_input)
+        .process_file_extended(
+            target_file.as_ref(),
+            |seq| {
+                for part in seq
+                    .seq
+                    .split(|b| !matches!(b, b'A' | b'C' | b'G' | b'T' | b'a' | b'c' | b'g' | b't'))
+                {
+                    if part.len() < k {
+                        continue;
+                    }
+
+                    compressed.clear();
+                    compressed.extend(part.iter().map(|b| Utils::compress_base(*b)));
+
+                    for (index, hash) in MH::new(&compressed[..], k).iter_enumerate() {
+                        target_kmers.insert(hash.to_unextendable());
+
+                        let first_base = compressed[index];
+                        let last_base = compressed[index + k - 1];
+                        for base in 0..4 {
+                            target_kmers.insert(
+                                MH::manual_roll_forward(hash, k, first_base, base)
+                                    .to_unextendable(),
+                            );
+                            target_kmers.insert(
+                                MH::manual_roll_reverse(hash, k, last_base, base).to_unextendable(),
+                            );
+                        }
+                    }
+                }
+            },
+            None,
+            false,
+            false,
+        );
+
+    target_kmers.finish()
+}
@@ -1,37 +1,180 @@
 use ::dynamic_dispatch::dynamic_dispatch;
+use byteorder::ReadBytesExt;
 use colors::colors_manager::color_types::MinimizerBucketingSeqColorDataType;
 use colors::colors_manager::{ColorsManager, MinimizerBucketingSeqColorData};
 use colors::parsers::{SequenceIdent, SingleSequenceInfo};
-use config::{BucketIndexType, ColorIndexType};
+use config::{BucketIndexType, ColorIndexType, PipelineSettings};
 use config::{READ_FLAG_INCL_BEGIN, READ_FLAG_INCL_END};
+use hashes::minimizer_ordering::{get_minimizer_ordering, MinimizerOrdering};
 use hashes::rolling::minqueue::RollingMinQueue;
 use hashes::ExtendableHashTraitType;
 use hashes::HashFunction;
 use hashes::MinimizerHashFunctionFactory;
-use io::concurrent::temp_reads::extra_data::SequenceExtraDataTempBufferManagement;
+use io::concurrent::temp_reads::extra_data::{
+    SequenceExtraDataConsecutiveCompression, SequenceExtraDataTempBufferManagement,
+};
 use io::sequences_reader::{DnaSequence, DnaSequencesFileType};
 use io::sequences_stream::general::{GeneralSequenceBlockData, GeneralSequencesStream};
+use io::sequences_stream::stranded_inputs::InputStrandMode;
 use io::sequences_stream::SequenceInfo;
+use io::unused_reads::{get_reads_tracker, is_reads_tracking, ReadsTracker, UnusedReadReason};
+use io::varint::{decode_varint, encode_varint, VARINT_MAX_SIZE};
+use minimizer_bucketing::buckets_skew::check_buckets_skew;
+use minimizer_bucketing::counters_analyzer::CountersAnalyzer;
+use minimizer_bucketing::minimizers_sampling::sample_minimizers_frequencies;
 use minimizer_bucketing::{
     GenericMinimizerBucketing, MinimizerBucketingCommonData, MinimizerBucketingExecutor,
     MinimizerBucketingExecutorFactory, MinimizerInputSequence,
 };
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
+use parallel_processor::memory_fs::{MemoryFs, RemoveFileMode};
 use std::cmp::max;
+use std::io::{Read, Write};
 use std::marker::PhantomData;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use utils::phase_log::start_phase;
+
+static MIN_LINK_COVERAGE: AtomicUsize = AtomicUsize::new(1);
+
+/// Sets the minimum number of occurrences in the reads of the (k+1)-mer joining two kmers required
+/// to extend a unitig from one kmer to the other. The links below the threshold are dropped before
+/// the unitigs compaction, so that the spurious overlaps do not create branches or join unrelated unitigs.
+/// With 1 (the default) two kmers overlapping by k - 1 bases are always linked, even if no read
+/// contains both of them
+pub fn set_min_link_coverage(min_coverage: usize) {
+    MIN_LINK_COVERAGE.store(min_coverage.max(1), Ordering::Relaxed);
+}
+
+pub fn get_min_link_coverage() -> usize {
+    MIN_LINK_COVERAGE.load(Ordering::Relaxed)
+}
+
+/// The colors of a bucketed sequence, with the number of times each of its kmers is counted
+#[derive(Clone, Debug)]
+pub struct CountedSequenceData<D: MinimizerBucketingSeqColorData> {
+    pub colors: D,
+    pub multiplicity: u64,
+    /// The kmers of the sequence are also added as their reverse complements, set for the
+    /// canonical inputs of a build where the reverse complementary kmers are different
+    pub both_strands: bool,
+    /// The sequence is the (k+1)-mer joining the last kmer of a super-kmer to the first kmer of
+    /// the following one, sent to the bucket of the latter only to count the link coverage
+    pub link_only: bool,
+    /// The input block of the read of the sequence and the index of the read in the block,
+    /// set only when the reads of the build are tracked
+    pub read: Option<(usize, u64)>,
+}
+
+impl<D: MinimizerBucketingSeqColorData> SequenceExtraDataTempBufferManagement
+    for CountedSequenceData<D>
+{
+    type TempBuffer = D::TempBuffer;
+
+    #[inline(always)]
+    fn new_temp_buffer() -> D::TempBuffer {
+        D::new_temp_buffer()
+    }
+
+    #[inline(always)]
+    fn clear_temp_buffer(buffer: &mut D::TempBuffer) {
+        D::clear_temp_buffer(buffer);
+    }
+
+    fn copy_temp_buffer(dest: &mut D::TempBuffer, src: &D::TempBuffer) {
+        D::copy_temp_buffer(dest, src);
+    }
+
+    #[inline(always)]
+    fn copy_extra_from(extra: Self, src: &D::TempBuffer, dst: &mut D::TempBuffer) -> Self {
+        Self {
+            colors: D::copy_extra_from(extra.colors, src, dst),
+            multiplicity: extra.multiplicity,
+            both_strands: extra.both_strands,
+            link_only: extra.link_only,
+            read: extra.read,
+        }
+    }
+}
+
+impl<D: MinimizerBucketingSeqColorData> SequenceExtraDataConsecutiveCompression
+    for CountedSequenceData<D>
+{
+    type LastData = D::LastData;
+
+    #[inline(always)]
+    fn decode_extended(
+        buffer: &mut Self::TempBuffer,
+        reader: &mut impl Read,
+        last_data: Self::LastData,
+    ) -> Option<Self> {
+        let colors = D::decode_extended(buffer, reader, last_data)?;
+        let counts = decode_varint(|| reader.read_u8().ok())?;
+        let read = if is_reads_tracking() {
+            let block = decode_varint(|| reader.read_u8().ok())?;
+            let read_index = decode_varint(|| reader.read_u8().ok())?;
+            Some((block as usize, read_index))
+        } else {
+            None
+        };
+        Some(Self {
+            colors,
+            multiplicity: (counts >> 2) + 1,
+            both_strands: counts & 2 != 0,
+            link_only: counts & 1 != 0,
+            read,
+        })
+    }
+
+    #[inline(always)]
+    fn encode_extended(
+        &self,
+        buffer: &Self::TempBuffer,
+        writer: &mut impl Write,
+        last_data: Self::LastData,
+    ) {
+        self.colors.encode_extended(buffer, writer, last_data);
+        encode_varint(
+            |bytes| writer.write_all(bytes),
+            ((self.multiplicity - 1) << 2)
+                | ((self.both_strands as u64) << 1)
+                | (self.link_only as u64),
+        )
+        .unwrap();
+        if is_reads_tracking() {
+            let (block, read_index) = self.read.unwrap_or_default();
+            encode_varint(|bytes| writer.write_all(bytes), block as u64).unwrap();
+            encode_varint(|bytes| writer.write_all(bytes), read_index).unwrap();
+        }
+    }
+
+    #[inline(always)]
+    fn max_size(&self) -> usize {
+        self.colors.max_size() + VARINT_MAX_SIZE * 3
+    }
+
+    fn obtain_last_data(&self, last_data: Self::LastData) -> Self::LastData {
+        self.colors.obtain_last_data(last_data)
+    }
+}
 
 pub struct AssemblerMinimizerBucketingExecutor<H: MinimizerHashFunctionFactory, CX: ColorsManager> {
     minimizer_queue: RollingMinQueue<H>,
     global_data: Arc<MinimizerBucketingCommonData<()>>,
+    /// Also send the links crossing the super-kmers boundaries to the bucket of the following super-kmer
+    push_boundary_links: bool,
+    reads_tracker: Option<Arc<ReadsTracker>>,
     _phantom: PhantomData<CX>,
 }
 
 pub struct AssemblerPreprocessInfo<CX: ColorsManager> {
     color_info: MinimizerBucketingSeqColorDataType<CX>,
     color_info_buffer: <MinimizerBucketingSeqColorDataType<CX> as SequenceExtraDataTempBufferManagement>::TempBuffer,
+    multiplicity: u64,
+    both_strands: bool,
+    link_only: bool,
+    read: Option<(usize, u64)>,
     include_first: bool,
     include_last: bool,
 }
@@ -42,6 +185,10 @@ impl<CX: ColorsManager> Default for AssemblerPreprocessInfo<CX> {
             color_info: MinimizerBucketingSeqColorDataType::<CX>::default(),
             color_info_buffer:
                     <MinimizerBucketingSeqColorDataType<CX> as SequenceExtraDataTempBufferManagement>::new_temp_buffer(),
+            multiplicity: 1,
+            both_strands: false,
+            link_only: false,
+            read: None,
             include_first: false,
             include_last: false,
         }
@@ -51,6 +198,7 @@ impl<CX: ColorsManager> Default for AssemblerPreprocessInfo<CX> {
 #[derive(Clone, Default)]
 pub struct InputFileInfo {
     file_color: ColorIndexType,
+    both_strands: bool,
 }
 
 pub struct AssemblerMinimizerBucketingExecutorFactory<
@@ -62,7 +210,7 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager> MinimizerBucketingExecu
     for AssemblerMinimizerBucketingExecutorFactory<H, CX>
 {
     type GlobalData = ();
-    type ExtraData = MinimizerBucketingSeqColorDataType<CX>;
+    type ExtraData = CountedSequenceData<MinimizerBucketingSeqColorDataType<CX>>;
     type PreprocessInfo = AssemblerPreprocessInfo<CX>;
     type StreamInfo = InputFileInfo;
 
@@ -77,9 +225,15 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager> MinimizerBucketingExecu
         Self::ExecutorType {
             minimizer_queue: RollingMinQueue::new(global_data.k - global_data.m),
             global_data: global_data.clone(),
+            push_boundary_links: get_min_link_coverage() > 1,
+            reads_tracker: get_reads_tracker(),
             _phantom: PhantomData,
         }
     }
+
+    fn reports_discarded_sequences() -> bool {
+        is_reads_tracking()
+    }
 }
 
 impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
@@ -90,7 +244,7 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
         &mut self,
         stream_info: &<AssemblerMinimizerBucketingExecutorFactory<H, CX> as MinimizerBucketingExecutorFactory>::StreamInfo,
         sequence_info: SequenceInfo,
-        _read_index: u64,
+        read_index: u64,
         sequence: &DnaSequence,
         preprocess_info: &mut <AssemblerMinimizerBucketingExecutorFactory<H, CX> as MinimizerBucketingExecutorFactory>::PreprocessInfo,
     ) {
@@ -115,10 +269,31 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
             },
             &mut preprocess_info.color_info_buffer,
         );
+        preprocess_info.multiplicity = sequence_info.multiplicity;
+        preprocess_info.both_strands = stream_info.both_strands;
+        preprocess_info.link_only = false;
+        preprocess_info.read = self.reads_tracker.as_ref().map(|tracker| {
+            let block = stream_info.file_color as usize;
+            tracker.record_read(block, read_index);
+            (block, read_index)
+        });
         preprocess_info.include_first = true;
         preprocess_info.include_last = true;
     }
 
+    fn discard_sequence(
+        &mut self,
+        _stream_info: &<AssemblerMinimizerBucketingExecutorFactory<H, CX> as MinimizerBucketingExecutorFactory>::StreamInfo,
+        _read_index: u64,
+        sequence: &DnaSequence,
+    ) {
+        if let Some(tracker) = &self.reads_tracker {
+            let reason = UnusedReadReason::for_discarded_read(sequence.seq, self.global_data.k)
+                .unwrap_or(UnusedReadReason::TooShort);
+            tracker.write_read(sequence.ident_data, sequence.seq, reason);
+        }
+    }
+
     #[inline(always)]
     fn reprocess_sequence(
         &mut self,
@@ -131,10 +306,14 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
             &mut preprocess_info.color_info_buffer,
         );
         preprocess_info.color_info = MinimizerBucketingSeqColorDataType::<CX>::copy_extra_from(
-            extra_data.clone(),
+            extra_data.colors.clone(),
             extra_data_buffer,
             &mut preprocess_info.color_info_buffer,
         );
+        preprocess_info.multiplicity = extra_data.multiplicity;
+        preprocess_info.both_strands = extra_data.both_strands;
+        preprocess_info.link_only = extra_data.link_only;
+        preprocess_info.read = extra_data.read;
         preprocess_info.include_first = (flags & READ_FLAG_INCL_BEGIN) != 0;
         preprocess_info.include_last = (flags & READ_FLAG_INCL_END) != 0;
     }
@@ -152,6 +331,36 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
         second_bits: usize,
         mut push_sequence: F,
     ){
+        let k = self.global_data.k;
+
+        // The link records are never split, they follow the first kmer of the super-kmer they link to
+        if preprocess_info.link_only {
+            let hashes = H::new(
+                sequence.get_subslice((sequence.seq_len() - k)..sequence.seq_len()),
+                self.global_data.m,
+            );
+            let min_hash = self
+                .minimizer_queue
+                .make_iter(hashes.iter().map(|x| x.to_unextendable()))
+                .next()
+                .unwrap();
+            push_sequence(
+                H::get_bucket(used_bits, first_bits, min_hash),
+                H::get_bucket(used_bits + first_bits, second_bits, min_hash),
+                sequence,
+                0,
+                CountedSequenceData {
+                    colors: preprocess_info.color_info.get_subslice(0..2),
+                    multiplicity: preprocess_info.multiplicity,
+                    both_strands: preprocess_info.both_strands,
+                    link_only: true,
+                    read: preprocess_info.read,
+                },
+                &preprocess_info.color_info_buffer,
+            );
+            return;
+        }
+
         let hashes = H::new(sequence, self.global_data.m);
 
         let mut rolling_iter = self
@@ -170,7 +379,7 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
             0
         };
 
-        let end_index = sequence.seq_len() - self.global_data.k;
+        let end_index = sequence.seq_len() - k;
 
         for (index, min_hash) in rolling_iter.enumerate() {
             let index = index + additional_offset;
@@ -181,13 +390,41 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
                 push_sequence(
                     H::get_bucket(used_bits, first_bits, last_hash),
                     H::get_bucket(used_bits + first_bits, second_bits, last_hash),
-                    sequence.get_subslice((max(1, last_index) - 1)..(index + self.global_data.k)),
+                    sequence.get_subslice((max(1, last_index) - 1)..(index + k)),
                     include_first as u8,
-                    preprocess_info
-                        .color_info
-                        .get_subslice((max(1, last_index) - 1)..(index + 1)), // FIXME: Check if the subslice is correct
+                    CountedSequenceData {
+                        colors: preprocess_info
+                            .color_info
+                            .get_subslice((max(1, last_index) - 1)..(index + 1)), // FIXME: Check if the subslice is correct
+                        multiplicity: preprocess_info.multiplicity,
+                        both_strands: preprocess_info.both_strands,
+                        link_only: false,
+                        read: preprocess_info.read,
+                    },
                     &preprocess_info.color_info_buffer,
                 );
+
+                // The link between the last two kmers of the super-kmer is counted only in its bucket,
+                // the bucket of the next super-kmer needs it to know the coverage of the links of its first kmer
+                if self.push_boundary_links {
+                    push_sequence(
+                        H::get_bucket(used_bits, first_bits, min_hash),
+                        H::get_bucket(used_bits + first_bits, second_bits, min_hash),
+                        sequence.get_subslice((index - 1)..(index + k)),
+                        0,
+                        CountedSequenceData {
+                            colors: preprocess_info
+                                .color_info
+                                .get_subslice((index - 1)..(index + 1)),
+                            multiplicity: preprocess_info.multiplicity,
+                            both_strands: preprocess_info.both_strands,
+                            link_only: true,
+                            read: preprocess_info.read,
+                        },
+                        &preprocess_info.color_info_buffer,
+                    );
+                }
+
                 last_index = index + 1;
                 last_hash = min_hash;
                 include_first = false;
@@ -201,9 +438,15 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
             H::get_bucket(used_bits + first_bits, second_bits, last_hash),
             sequence.get_subslice(start_index..sequence.seq_len()),
             include_first as u8 | ((include_last as u8) << 1),
-            preprocess_info
-                .color_info
-                .get_subslice(start_index..(sequence.seq_len() + 1 - self.global_data.k)), // FIXME: Check if the subslice is correct,
+            CountedSequenceData {
+                colors: preprocess_info
+                    .color_info
+                    .get_subslice(start_index..(sequence.seq_len() + 1 - k)), // FIXME: Check if the subslice is correct,
+                multiplicity: preprocess_info.multiplicity,
+                both_strands: preprocess_info.both_strands,
+                link_only: false,
+                read: preprocess_info.read,
+            },
             &preprocess_info.color_info_buffer,
         );
     }
@@ -211,7 +454,9 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
 
 #[dynamic_dispatch(H = [
     hashes::cn_nthash::CanonicalNtHashIteratorFactory,
-    #[cfg(not(feature = "devel-build"))] hashes::fw_nthash::ForwardNtHashIteratorFactory
+    #[cfg(not(feature = "devel-build"))] hashes::fw_nthash::ForwardNtHashIteratorFactory,
+    #[cfg(not(feature = "devel-build"))] hashes::cn_nthash::ordered::CanonicalNtHashIteratorFactory,
+    #[cfg(not(feature = "devel-build"))] hashes::fw_nthash::ordered::ForwardNtHashIteratorFactory
 ], CX = [
     #[cfg(not(feature = "devel-build"))] colors::bundles::multifile_building::ColorBundleMultifileBuilding,
     colors::non_colored::NonColoredManager,
@@ -223,21 +468,27 @@ pub fn minimizer_bucketing<H: MinimizerHashFunctionFactory, CX: ColorsManager>(
     threads_count: usize,
     k: usize,
     m: usize,
+    strand_mode: InputStrandMode,
+    settings: PipelineSettings,
 ) -> (Vec<PathBuf>, PathBuf) {
     H::initialize(k);
 
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase("phase: reads bucketing".to_string());
+    // The canonical inputs are added on both strands if the reverse complementary kmers are different,
+    // the minimizers must then be canonical to bucket the kmers of both strands together
+    let forward_only_kmers =
+        GeneralSequenceBlockData::has_forward_only_kmers(&input_blocks, strand_mode);
 
     let mut input_files: Vec<_> = input_blocks
         .into_iter()
         .enumerate()
         .map(|(i, f)| {
+            let both_strands =
+                forward_only_kmers && f.strand_mode(strand_mode) == InputStrandMode::Canonical;
             (
                 f,
                 InputFileInfo {
                     file_color: i as ColorIndexType,
+                    both_strands,
                 },
             )
         })
@@ -246,19 +497,44 @@ pub fn minimizer_bucketing<H: MinimizerHashFunctionFactory, CX: ColorsManager>(
     input_files.sort_by_cached_key(|(file, _)| file.estimated_bases_count());
     input_files.reverse();
 
-    GenericMinimizerBucketing::do_bucketing::<
-        AssemblerMinimizerBucketingExecutorFactory<H, CX>,
-        GeneralSequencesStream,
-    >(
-        input_files.into_iter(),
-        output_path,
-        buckets_count,
-        threads_count,
-        k,
-        m,
-        (),
-        Some(k - 1),
-        false,
-        k,
-    )
+    if get_minimizer_ordering() == MinimizerOrdering::FrequencyAware {
+        sample_minimizers_frequencies::<H, GeneralSequencesStream>(
+            input_files.iter().map(|(block, _)| block),
+            m,
+        );
+    }
+
+    let mut attempt = 0;
+    loop {
+        start_phase("phase: reads bucketing".to_string());
+
+        let (buckets, counters_file) = GenericMinimizerBucketing::do_bucketing::<
+            AssemblerMinimizerBucketingExecutorFactory<H, CX>,
+            GeneralSequencesStream,
+        >(
+            input_files.clone().into_iter(),
+            output_path,
+            buckets_count,
+            threads_count,
+            k,
+            m,
+            (),
+            Some(k - 1),
+            // The headers are needed only to write the unused reads
+            is_reads_tracking(),
+            k,
+            settings,
+        );
+
+        let buckets_counts =
+            CountersAnalyzer::load_from_file(&counters_file, false).get_buckets_counts();
+        if !check_buckets_skew(&buckets_counts, attempt, settings.random_seed) {
+            break (buckets, counters_file);
+        }
+
+        for bucket in buckets {
+            MemoryFs::remove_file(&bucket, RemoveFileMode::Remove { remove_fs: true }).unwrap();
+        }
+        attempt += 1;
+    }
 }
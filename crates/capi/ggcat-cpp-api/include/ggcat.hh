@@ -34,12 +34,22 @@ namespace ggcat
         ExtraElaborationStep_Eulertigs = 3,
         /// Generate pathtigs
         ExtraElaborationStep_Pathtigs = 4,
+        /// Generate unitig links and add a component=<id> tag to each unitig header
+        ExtraElaborationStep_ComponentsHeaderTag = 5,
+        /// Generate unitig links and write each connected component to a separate file
+        ExtraElaborationStep_ComponentsSeparateFiles = 6,
+        /// Both add the component=<id> tag and write each connected component to a separate file
+        ExtraElaborationStep_ComponentsHeaderTagAndSeparateFiles = 7,
     };
 
     enum ColoredQueryOutputFormat
     {
         ColoredQueryOutputFormat_JsonLinesWithNumbers = 0,
         ColoredQueryOutputFormat_JsonLinesWithNames = 1,
+        /// A tab-separated record per query with its best color, match count, fraction and confidence
+        ColoredQueryOutputFormat_Classification = 2,
+        /// A tab-separated matrix of the matched kmers counts, with a row per query and a column per color
+        ColoredQueryOutputFormat_CountMatrix = 3,
     };
 
     enum DnaSequencesFileType
@@ -5,7 +5,10 @@ use std::{mem::transmute, path::PathBuf};
 use ggcat_api::{
     ColorIndexType, DnaSequence, DnaSequencesFileType, DynamicSequencesStream, SequenceInfo,
 };
-use ggcat_api::{ExtraElaboration, GGCATConfig, GGCATInstance, GeneralSequenceBlockData};
+use ggcat_api::{
+    ComponentsOutputMode, ExtraElaboration, GGCATConfig, GGCATInstance, GeneralSequenceBlockData,
+    LogFormat, ReadsSizeEncoding,
+};
 
 #[repr(transparent)]
 struct GGCATInstanceFFI(GGCATInstance);
@@ -30,6 +33,12 @@ fn ggcat_create(config: ffi::GGCATConfigFFI) -> &'static GGCATInstanceFFI {
         } else {
             None
         },
+        io_threads_count: None,
+        compute_threads_count: None,
+        log_format: LogFormat::Human,
+        verbose_phases_log: false,
+        reads_size_encoding: ReadsSizeEncoding::Varint,
+        max_read_length: None,
     });
     unsafe { std::mem::transmute(instance) }
 }
@@ -68,6 +77,9 @@ fn ggcat_build(
     const EXTRA_ELABORATION_STEP_GREEDY_MATCHTIGS: usize = 2;
     const EXTRA_ELABORATION_STEP_EULERTIGS: usize = 3;
     const EXTRA_ELABORATION_STEP_PATHTIGS: usize = 4;
+    const EXTRA_ELABORATION_STEP_COMPONENTS_HEADER_TAG: usize = 5;
+    const EXTRA_ELABORATION_STEP_COMPONENTS_SEPARATE_FILES: usize = 6;
+    const EXTRA_ELABORATION_STEP_COMPONENTS_HEADER_TAG_AND_SEPARATE_FILES: usize = 7;
 
     instance
         .0
@@ -95,8 +107,20 @@ fn ggcat_build(
                 EXTRA_ELABORATION_STEP_GREEDY_MATCHTIGS => ExtraElaboration::GreedyMatchtigs,
                 EXTRA_ELABORATION_STEP_EULERTIGS => ExtraElaboration::Eulertigs,
                 EXTRA_ELABORATION_STEP_PATHTIGS => ExtraElaboration::Pathtigs,
+                EXTRA_ELABORATION_STEP_COMPONENTS_HEADER_TAG => {
+                    ExtraElaboration::ConnectedComponents(ComponentsOutputMode::HeaderTag)
+                }
+                EXTRA_ELABORATION_STEP_COMPONENTS_SEPARATE_FILES => {
+                    ExtraElaboration::ConnectedComponents(ComponentsOutputMode::SeparateFiles)
+                }
+                EXTRA_ELABORATION_STEP_COMPONENTS_HEADER_TAG_AND_SEPARATE_FILES => {
+                    ExtraElaboration::ConnectedComponents(
+                        ComponentsOutputMode::HeaderTagAndSeparateFiles,
+                    )
+                }
                 _ => panic!("Invalid extra_elab value: {}", extra_elab),
             },
+            ggcat_api::BuildOptions::default(),
         )
         .to_str()
         .unwrap()
@@ -235,6 +259,7 @@ fn ggcat_build_from_streams(
                     },
                     SequenceInfo {
                         color: Some(info.color),
+                        multiplicity: 1,
                     },
                 );
             }
@@ -311,6 +336,8 @@ fn ggcat_query_graph(
 ) -> String {
     const COLORED_QUERY_OUTPUT_FORMAT_JSON_LINES_WITH_NUMBERS: usize = 0;
     const COLORED_QUERY_OUTPUT_FORMAT_JSON_LINES_WITH_NAMES: usize = 1;
+    const COLORED_QUERY_OUTPUT_FORMAT_CLASSIFICATION: usize = 2;
+    const COLORED_QUERY_OUTPUT_FORMAT_COUNT_MATRIX: usize = 3;
 
     instance
         .0
@@ -334,8 +361,15 @@ fn ggcat_query_graph(
                 COLORED_QUERY_OUTPUT_FORMAT_JSON_LINES_WITH_NAMES => {
                     ggcat_api::ColoredQueryOutputFormat::JsonLinesWithNames
                 }
+                COLORED_QUERY_OUTPUT_FORMAT_CLASSIFICATION => {
+                    ggcat_api::ColoredQueryOutputFormat::Classification
+                }
+                COLORED_QUERY_OUTPUT_FORMAT_COUNT_MATRIX => {
+                    ggcat_api::ColoredQueryOutputFormat::CountMatrix
+                }
                 _ => panic!("Invalid color_output_format value: {}", color_output_format),
             },
+            ggcat_api::QueryOptions::default(),
         )
         .to_str()
         .unwrap()
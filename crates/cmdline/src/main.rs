@@ -8,7 +8,7 @@ mod benchmarks;
 
 use ahash::HashMap;
 use backtrace::Backtrace;
-use ggcat_api::{ExtraElaboration, GGCATConfig, GGCATInstance};
+use ggcat_api::{BuildOptions, ExtraElaboration, GGCATConfig, GGCATInstance, QueryOptions};
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Write};
 use std::panic;
@@ -41,6 +41,85 @@ arg_enum! {
     }
 }
 
+arg_enum! {
+    #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+    pub enum MinimizerOrdering {
+        Hash = 0,
+        Random = 1,
+        FrequencyAware = 2
+    }
+}
+
+arg_enum! {
+    #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+    pub enum CanonicalCompatibility {
+        Ggcat = 0,
+        Bifrost = 1,
+        Cuttlefish = 2
+    }
+}
+
+arg_enum! {
+    #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+    pub enum CoverageStatistic {
+        Mean = 0,
+        Median = 1,
+        Min = 2,
+        Max = 3
+    }
+}
+
+arg_enum! {
+    #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+    pub enum KmerCountingMode {
+        CountPerOccurrence = 0,
+        CountPerRead = 1
+    }
+}
+
+arg_enum! {
+    #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+    pub enum CircularInputs {
+        Disabled = 0,
+        HeaderTag = 1,
+        All = 2
+    }
+}
+
+arg_enum! {
+    #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+    pub enum SharedSequencePolicy {
+        AllColors = 0,
+        FirstColor = 1,
+        FlagShared = 2
+    }
+}
+
+arg_enum! {
+    #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+    pub enum ComponentsOutput {
+        HeaderTag = 0,
+        SeparateFiles = 1,
+        HeaderTagAndSeparateFiles = 2
+    }
+}
+
+arg_enum! {
+    #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+    pub enum LogFormat {
+        Human = 0,
+        Json = 1
+    }
+}
+
+arg_enum! {
+    #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+    pub enum ReadsSizeEncoding {
+        Varint = 0,
+        FixedWidth = 1
+    }
+}
+
 arg_enum! {
     #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
     pub enum HashType {
@@ -57,7 +136,10 @@ use colors::colors_manager::ColorMapReader;
 use colors::storage::deserializer::ColorsDeserializer;
 use colors::DefaultColorsSerializer;
 use config::ColorIndexType;
+use io::sequences_stream::circular_fasta::CircularFastaStream;
 use io::sequences_stream::general::GeneralSequenceBlockData;
+use io::sequences_stream::kmer_counts_dump::KmerCountsDumpStream;
+use io::sequences_stream::stranded_inputs::{InputStrandMode, StrandedInputsStream};
 use parallel_processor::memory_fs::MemoryFs;
 use std::io::BufRead;
 use structopt::clap::{arg_enum, ArgGroup};
@@ -67,7 +149,10 @@ enum CliArgs {
     Build(AssemblerArgs),
     Query(QueryArgs),
     DumpColors(DumpColorsArgs),
+    DiffColormaps(DiffColormapsArgs),
     Matches(MatchesArgs),
+    Concat(ConcatArgs),
+    CompareSpectra(CompareSpectraArgs),
     // Utils(CmdUtilsArgs),
 }
 
@@ -98,13 +183,36 @@ struct CommonArgs {
     #[structopt(long = "keep-temp-files")]
     pub keep_temp_files: bool,
 
+    /// Keep the intermediate temporary files of the build or query until its end, removing them only if it succeeds
+    #[structopt(long = "no-temp-remove-until-end")]
+    pub no_temp_remove_until_end: bool,
+
     #[structopt(short = "j", long, default_value = "16")]
     pub threads_count: usize,
 
+    /// Number of threads reading the files and flushing the buckets to disk, overlapping with the compute
+    /// threads: a quarter of them flush the buckets and the others read the files of each step.
+    /// Derived from the threads count if not given
+    #[structopt(long = "threads-io")]
+    pub threads_io: Option<usize>,
+
+    /// Number of threads processing the reads and the buckets, derived from the threads count if not given
+    #[structopt(long = "threads-compute")]
+    pub threads_compute: Option<usize>,
+
     /// Hash type used to identify kmers
     #[structopt(short = "w", long, default_value = "Auto")]
     pub hash_type: HashType,
 
+    /// Order used to choose the minimizers, FrequencyAware reduces the buckets skew on low-complexity genomes
+    #[structopt(long = "minimizer-ordering", default_value = "Hash")]
+    pub minimizer_ordering: MinimizerOrdering,
+
+    /// Canonicalizes the kmers as the given tool, to compare the graphs with the ones it builds.
+    /// Bifrost and Cuttlefish choose the lexicographically smallest strand (requires the sequence hash, k <= 64)
+    #[structopt(long = "canonical-compatibility", default_value = "Ggcat")]
+    pub canonical_compatibility: CanonicalCompatibility,
+
     /// Treats reverse complementary kmers as different
     #[structopt(short = "f", long)]
     pub forward_only: bool,
@@ -124,10 +232,43 @@ struct CommonArgs {
     #[structopt(short = "b", long = "buckets-count-log")]
     pub buckets_count_log: Option<usize>,
 
+    /// Maximum number of files kept open at the same time, lowering the buckets count if needed
+    /// (a few dozen files are reserved besides the two per bucket). Below the minimum buckets count
+    /// the reads bucketing writes the buckets in waves, reading the inputs once per wave
+    #[structopt(long = "max-open-files")]
+    pub max_open_files: Option<usize>,
+
     /// The level of lz4 compression to be used for the intermediate files
     #[structopt(long = "intermediate-compression-level")]
     pub intermediate_compression_level: Option<u32>,
 
+    /// Seed for all the randomized steps, to make the runs reproducible given the same seed and threads count
+    #[structopt(long)]
+    pub seed: Option<u64>,
+
+    /// Format of the phases logs and warnings, with Json each event is written to stderr as a json line
+    #[structopt(long = "log-format", default_value = "Human")]
+    pub log_format: LogFormat,
+
+    /// Time the hashing, the bucket writing and the flushing within the minimizer bucketing, reported at
+    /// the end of the phase and in its json event. Adds the overhead of reading the clock for each sequence
+    #[structopt(long = "subphase-timing")]
+    pub subphase_timing: bool,
+
+    /// Also print the CPU utilization of each phase, as its CPU time over its wall time
+    #[structopt(long = "verbose-phases")]
+    pub verbose_phases: bool,
+
+    /// Encoding of the reads sizes in the temporary buckets, FixedWidth avoids the varint decoding
+    /// when the reads are uniformly long, at the cost of larger buckets for short reads
+    #[structopt(long = "reads-size-encoding", default_value = "Varint")]
+    pub reads_size_encoding: ReadsSizeEncoding,
+
+    /// Maximum length of a read in the temporary buckets, a longer read is reported as a corrupted
+    /// bucket and fails the run. Defaults to the longest read of the fixed width encoding
+    #[structopt(long = "max-read-length")]
+    pub max_read_length: Option<u64>,
+
     #[structopt(long = "only-bstats", hidden = true)]
     pub only_bstats: bool,
 }
@@ -146,6 +287,42 @@ struct AssemblerArgs {
     #[structopt(short = "d", long = "colored-input-lists")]
     pub colored_input_lists: Vec<PathBuf>,
 
+    /// Append the input files not in the colored input lists to the existing color with this name of the
+    /// graph given with --append-to, so that their kmers are counted in that color, as for more sequencing
+    /// of the same library
+    #[structopt(long = "same-color-as", requires_all = &["colored-input-lists", "append-to"])]
+    pub same_color_as: Option<String>,
+
+    /// The existing graph whose colors keep their indices in the rebuilt graph (requires --same-color-as)
+    #[structopt(long = "append-to", requires = "same-color-as")]
+    pub append_to: Option<PathBuf>,
+
+    /// Input files whose kmers are added only as read, while the reverse complementary kmers of the
+    /// other inputs are merged. Builds a graph where the reverse complementary kmers are different
+    #[structopt(long = "forward-only-input", number_of_values = 1)]
+    pub forward_only_inputs: Vec<PathBuf>,
+
+    /// Input files whose reverse complementary kmers are merged in a forward-only (-f) build
+    #[structopt(long = "canonical-input", number_of_values = 1)]
+    pub canonical_inputs: Vec<PathBuf>,
+
+    /// Stranded RNA-seq mode, keeping the orientation of the reads in the whole build: the kmers are hashed
+    /// forward-only (as with -f), so that the unitigs and their links follow the 5'->3' direction of the
+    /// transcripts. The options that merge or reorient the two strands are refused
+    #[structopt(
+        long = "strand-specific",
+        conflicts_with_all = &[
+            "canonical-inputs",
+            "kmer-counts-input",
+            "greedy-matchtigs",
+            "eulertigs",
+            "pathtigs",
+            "orient-to-reference",
+            "scaffold-pairs"
+        ]
+    )]
+    pub strand_specific: bool,
+
     /// Enable colors
     #[structopt(short, long)]
     pub colors: bool,
@@ -154,6 +331,91 @@ struct AssemblerArgs {
     #[structopt(short = "s", long = "min-multiplicity", default_value = "2")]
     pub min_multiplicity: usize,
 
+    /// Replace the minimum multiplicity with the valley between the errors and the coverage peak
+    /// in the kmer spectrum of the inputs, keeping all their distinct kmers in memory while counting
+    /// (ignored with kmer counts inputs)
+    #[structopt(long = "auto-min-multiplicity")]
+    pub auto_min_multiplicity: bool,
+
+    /// The statistic of the unitig kmers counts reported as its coverage (requires the kmer-counters feature)
+    #[structopt(long = "coverage-statistic", default_value = "Mean")]
+    pub coverage_statistic: CoverageStatistic,
+
+    /// How the kmers repeated in the same read (e.g. tandem repeats) are counted for the minimum multiplicity,
+    /// CountPerRead counts them once in each super-kmer of a read (ignored with colors)
+    #[structopt(long = "kmer-counting", default_value = "CountPerOccurrence")]
+    pub kmer_counting: KmerCountingMode,
+
+    /// Value at which the kmers counts saturate instead of wrapping around (ignored with colors)
+    #[structopt(long = "max-kmer-count")]
+    pub max_kmer_count: Option<usize>,
+
+    /// Compare the full sequences of the kmers with colliding hashes so that only identical kmers are merged,
+    /// slower and using more memory. Needed only with rabin-karp hashes or k > 64
+    #[structopt(long = "exact-kmers")]
+    pub exact_kmers: bool,
+
+    /// Minimum number of reads containing two consecutive kmers required to link them in a unitig,
+    /// pruning the spurious links between kmers that overlap by k - 1 bases without being adjacent in the reads
+    #[structopt(long = "min-link-coverage", default_value = "1")]
+    pub min_link_coverage: usize,
+
+    /// Load factor of the kmers merge hash maps, sized for the average kmers of a bucket. Lower values
+    /// allocate larger tables that make the merge faster at the cost of more memory, between 0.1 and 0.875
+    #[structopt(long = "merge-load-factor", default_value = "0.875")]
+    pub merge_load_factor: f64,
+
+    /// Process the buckets inline on a single thread, skipping the parallel setup. Used by default
+    /// with a single thread
+    #[structopt(long = "single-threaded")]
+    pub single_threaded: bool,
+
+    /// Sort the output unitigs by their canonical sequence and renumber them, so that the same inputs give
+    /// a byte-identical graph with any threads count. Only for the uncolored unitigs, without sidecar outputs
+    #[structopt(long = "deterministic-output")]
+    pub deterministic_output: bool,
+
+    /// Repeat the minimizer bucketing with a perturbed random minimizer ordering if the largest bucket
+    /// is more than 10 times the median one. Without it the skewed buckets are only reported
+    #[structopt(long = "retry-skewed-buckets")]
+    pub retry_skewed_buckets: bool,
+
+    /// The inputs are text dumps of pre-counted kmers, with a kmer and its count on each line
+    /// (as written by kmc_tools dump or jellyfish dump -c), instead of reads
+    #[structopt(long = "kmer-counts-input")]
+    pub kmer_counts_input: bool,
+
+    /// Sample the start of the input to choose the buckets count and the minimizer length with the
+    /// lowest peak memory for the available memory, unless given explicitly.
+    /// The chosen parameters are written to a run manifest next to the output file
+    #[structopt(long = "auto-tune")]
+    pub auto_tune: bool,
+
+    /// Sample the start of the input to report the distinct minimizers found with the chosen minimizer length,
+    /// warning if they are too few for the buckets count
+    #[structopt(long = "check-minimizers")]
+    pub check_minimizers: bool,
+
+    /// Minimum multiplicity of a kmer in a color required to assign that color to the kmer
+    #[structopt(long = "min-color-coverage", default_value = "1")]
+    pub min_color_coverage: usize,
+
+    /// Replace the colors of the kmers found in more than this number of colors with a single
+    /// "ubiquitous" color, added after the input ones
+    #[structopt(long = "max-kmer-colors")]
+    pub max_kmer_colors: Option<usize>,
+
+    /// Store the colors of the kmers found in more than this number of colors approximately, as a sample
+    /// of this number of their colors and an "approximate" color added after the input ones, that marks
+    /// the approximate matches of the queries
+    #[structopt(long = "max-exact-color-set")]
+    pub max_exact_color_set: Option<usize>,
+
+    /// How the kmers found in multiple colors are assigned: to all their colors, only to the first one
+    /// in the input order, or to a single "shared" color added after the input ones
+    #[structopt(long = "shared-sequence-policy", default_value = "AllColors")]
+    pub shared_sequence_policy: SharedSequencePolicy,
+
     // /// Minimum correctness probability for each kmer (using fastq quality checks)
     // #[structopt(short = "q", long = "quality-threshold")]
     // pub quality_threshold: Option<f64>,
@@ -169,6 +431,11 @@ struct AssemblerArgs {
     #[structopt(long = "last-step", default_value = "BuildUnitigs")]
     pub last_step: AssemblerStartingStep,
 
+    /// The temporary directory of a build run kept with --keep-temp-files, to resume it from --step.
+    /// The colormap of a colored build is recovered from it if the run stopped after the kmers merge
+    #[structopt(long = "resume-temp-dir")]
+    pub resume_temp_dir: Option<PathBuf>,
+
     /// Generate maximal unitigs connections references, in BCALM2 format L:<+/->:<other id>:<+/->
     #[structopt(
         short = "e",
@@ -189,6 +456,173 @@ struct AssemblerArgs {
     #[structopt(long = "pathtigs", group = "output-mode")]
     pub pathtigs: bool,
 
+    /// Group the maximal unitigs by connected component, tagging each header with component=<id>
+    /// and/or writing each component in a separate file. Implies the generation of the unitigs links
+    #[structopt(
+        long = "connected-components",
+        conflicts_with_all = &["greedy-matchtigs", "eulertigs", "pathtigs"]
+    )]
+    pub connected_components: Option<ComponentsOutput>,
+
+    /// Check that the links of the output graph reference only unitigs present in the graph,
+    /// reporting the links to missing unitigs as warnings. Requires the unitigs links
+    #[structopt(long = "report-orphan-links")]
+    pub report_orphan_links: bool,
+
+    /// Fail the build if the links of the output graph reference missing unitigs
+    #[structopt(long = "fail-on-orphan-links")]
+    pub fail_on_orphan_links: bool,
+
+    /// Validate the fasta input files before building the graph, failing on the first malformed record.
+    /// Without it, the records with characters that are not nucleotides or IUPAC codes are skipped with a warning
+    #[structopt(long = "strict-input")]
+    pub strict_input: bool,
+
+    /// Which fasta records are circular, adding the kmers spanning their origin: none, the ones with
+    /// a circular tag in the header (as circular=true or topology=circular) or all of them
+    #[structopt(long = "circular-inputs", default_value = "Disabled")]
+    pub circular_inputs: CircularInputs,
+
+    /// Select the level of lz4 compression of the intermediate files from the measured compression and disk speeds
+    #[structopt(
+        long = "adaptive-compression-level",
+        conflicts_with = "intermediate-compression-level"
+    )]
+    pub adaptive_compression_level: bool,
+
+    /// Write a checksum of the output unitigs, independent of their order and strand, next to the output file
+    #[structopt(long = "reproducibility-hash")]
+    pub reproducibility_hash: bool,
+
+    /// Split the unitigs longer than this length into pieces overlapping by k - 1 bases, linked together
+    #[structopt(long = "max-unitig-length")]
+    pub max_unitig_length: Option<usize>,
+
+    /// Split the output into files of at most this number of unitigs each, named as the output file
+    /// with the part number before its extension (e.g. output.part001.fasta.lz4), replacing the output file.
+    /// The ids of the unitigs in each part are listed in the <output>.parts.tsv index
+    #[structopt(long = "max-records-per-file")]
+    pub max_records_per_file: Option<usize>,
+
+    /// Orient each unitig as the strand of this reference fasta file where its kmers are found
+    #[structopt(long = "orient-to-reference")]
+    pub orient_to_reference: Option<PathBuf>,
+
+    /// Order and orient the unitigs into scaffolds using the read pairs in these two files (first and
+    /// second mates), writing them to a .scaffolds.fa file next to the output file
+    #[structopt(long = "scaffold-pairs", number_of_values = 2)]
+    pub scaffold_pairs: Option<Vec<PathBuf>>,
+
+    /// Minimum number of read pairs required to join two unitigs in a scaffold
+    #[structopt(long = "scaffold-min-pairs", default_value = "3")]
+    pub scaffold_min_pairs: usize,
+
+    /// The insert size of the scaffolding read pairs, estimated from the pairs if not given
+    #[structopt(long = "scaffold-insert-size")]
+    pub scaffold_insert_size: Option<usize>,
+
+    /// Also write the unitigs of each color to a separate fasta file next to the output file (requires colors)
+    #[structopt(long = "unitigs-by-color", requires = "colors")]
+    pub unitigs_by_color: bool,
+
+    /// Also write the jaccard similarity of the shared kmers between each pair of colors next to the output
+    /// file, as a symmetric matrix (requires colors)
+    #[structopt(long = "color-jaccard", requires = "colors")]
+    pub color_jaccard: bool,
+
+    /// Also write the kmers found in exactly one color next to the output file, grouped by color, as
+    /// candidate color specific markers (requires colors)
+    #[structopt(long = "unique-kmers", requires = "colors")]
+    pub unique_kmers: bool,
+
+    /// Also write the hashes of the first and last kmer of each unitig next to the output file, computed with
+    /// the kmers hash of the build, as stable anchors for external coordinates
+    #[structopt(long = "unitig-anchors")]
+    pub unitig_anchors: bool,
+
+    /// Also write a parquet table next to the output file, with a row for each unitig with its id, length,
+    /// coverage, GC content and colors, to be loaded in a DataFrame. Requires a build with the parquet feature
+    #[structopt(long = "unitigs-parquet")]
+    pub unitigs_parquet: bool,
+
+    /// Also write the graph in GraphViz DOT format next to the output file, with a node for each unitig
+    /// and an edge for each link, to render small graphs
+    #[structopt(long = "unitigs-dot")]
+    pub unitigs_dot: bool,
+
+    /// The maximum number of unitigs of a graph written in DOT format, larger graphs are skipped with a warning
+    #[structopt(long = "dot-max-nodes", default_value = "10000")]
+    pub dot_max_nodes: usize,
+
+    /// Also write the graph in GFA format next to the output file, tagging each segment with its colors
+    /// for visualization in Bandage (requires colors)
+    #[structopt(long = "colored-gfa", requires = "colors")]
+    pub colored_gfa: bool,
+
+    /// The name of the colors tag of the GFA segments
+    #[structopt(long = "gfa-color-tag", default_value = "CL")]
+    pub gfa_color_tag: String,
+
+    /// The maximum number of colors listed in the tag of each GFA segment, the remaining ones are only counted
+    #[structopt(long = "gfa-max-colors", default_value = "16")]
+    pub gfa_max_colors: usize,
+
+    /// List the color indices instead of the color names in the GFA segments tags
+    #[structopt(long = "gfa-color-indices")]
+    pub gfa_color_indices: bool,
+
+    /// Add to each GFA segment the coverage of each of its colors, as a CV:B:i tag with color and coverage
+    /// pairs for the colors with a non-zero coverage. The coverage of a color is the total number of
+    /// occurrences of the segment kmers in the inputs of the color
+    #[structopt(long = "gfa-color-coverage")]
+    pub gfa_color_coverage: bool,
+
+    /// Also write the number of distinct kmers of each color in each abundance bin next to the output
+    /// file, counting the kmers of the inputs of each color (requires colors)
+    #[structopt(long = "abundance-profile", requires = "colors")]
+    pub abundance_profile: bool,
+
+    /// The lower bounds of the abundance bins of the abundance profile, comma separated
+    #[structopt(long = "abundance-bins", use_delimiter = true)]
+    pub abundance_bins: Option<Vec<u64>>,
+
+    /// Also write the runs of ambiguous bases (N) of the input sequences, that split their kmers,
+    /// as a BED file next to the output file
+    #[structopt(long = "n-gaps-bed", conflicts_with = "kmer-counts-input")]
+    pub n_gaps_bed: bool,
+
+    /// The minimum length of the runs of ambiguous bases written in the N gaps BED file
+    #[structopt(long = "min-n-gap-length", default_value = "1")]
+    pub min_n_gap_length: usize,
+
+    /// Also write the stretches of the unitigs where the coverage of the bases, as tracked while building
+    /// the graph, is below this value as a BED file next to the output file, for curation.
+    /// Requires a build with the kmer-counters feature
+    #[structopt(long = "low-coverage-bed", conflicts_with = "kmer-counts-input")]
+    pub low_coverage_bed: Option<u32>,
+
+    /// Also write the kmers coverage of the graph projected on this reference as a bedGraph track next
+    /// to the output file, for the visualization in a genome browser. The coverage is taken from the
+    /// kmer counts tracked while building the graph, that are written next to the output file too.
+    /// Requires a build with the kmer-counters feature
+    #[structopt(long = "coverage-track", conflicts_with = "kmer-counts-input")]
+    pub coverage_track: Option<PathBuf>,
+
+    /// Write the input reads that have no kmer in the graph to this fasta file, tagging each one with
+    /// the reason found while building the graph (too_short, all_n, low_coverage or not_in_target),
+    /// to troubleshoot a low assembly yield
+    #[structopt(long = "unused-reads", conflicts_with = "kmer-counts-input")]
+    pub unused_reads: Option<PathBuf>,
+
+    /// Assemble only the kmers of the sequences in this fasta file and the ones adjacent to them
+    #[structopt(long = "target")]
+    pub target: Option<PathBuf>,
+
+    /// Maximum memory (GB) used by the kmers of the target sequences, that are kept in a sorted index
+    /// file in the temp dir if they do not fit [default: 1]
+    #[structopt(long = "target-memory", requires = "target")]
+    pub target_memory: Option<f64>,
+
     #[structopt(flatten)]
     pub common_args: CommonArgs,
 }
@@ -199,12 +633,49 @@ struct DumpColorsArgs {
     output_file: PathBuf,
 }
 
+#[derive(StructOpt, Debug)]
+struct DiffColormapsArgs {
+    /// The colormap before the update
+    old_colormap: PathBuf,
+    /// The colormap after the update
+    new_colormap: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+struct ConcatArgs {
+    /// The input graphs, with links
+    input_graphs: Vec<PathBuf>,
+
+    #[structopt(short = "o", long = "output-file", default_value = "output.fasta.lz4")]
+    output_file: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+struct CompareSpectraArgs {
+    /// The input files of the first sample
+    #[structopt(long = "first", required = true)]
+    first_inputs: Vec<PathBuf>,
+
+    /// The input files of the second sample
+    #[structopt(long = "second", required = true)]
+    second_inputs: Vec<PathBuf>,
+
+    /// Specifies the k-mers length
+    #[structopt(short, long = "kmer-length")]
+    kmer_length: usize,
+
+    #[structopt(short = "o", long = "output-file", default_value = "spectra.tsv")]
+    output_file: PathBuf,
+}
+
 arg_enum! {
     /// Format of the queries output
     #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     pub enum ColoredQueryOutputFormat {
         JsonLinesWithNumbers,
         JsonLinesWithNames,
+        Classification,
+        CountMatrix,
     }
 }
 
@@ -223,12 +694,78 @@ struct QueryArgs {
     #[structopt(short = "o", long = "output-file-prefix", default_value = "output")]
     pub output_file_prefix: PathBuf,
 
+    /// With Classification, a tab-separated record is written for each matched query with its best color,
+    /// matched kmers, their fraction and a 0-60 confidence from the gap to the runner-up color.
+    /// With CountMatrix, a tab-separated matrix of the matched kmers is written with a row for each query
+    /// and a column for each color, or a line for each matched query and color with more than 4096 colors
     #[structopt(long = "colored-query-output-format")]
     pub colored_query_output_format: Option<ColoredQueryOutputFormat>,
 
+    /// Maximum memory (GB) shared by the buckets whose matches are sorted by color when reading the colormap,
+    /// the buckets that do not fit are sorted in runs spilled to disk [default: 1]
+    #[structopt(long = "counters-sort-memory")]
+    pub counters_sort_memory: Option<f64>,
+
+    /// Only check that the queries are well formed without running the query, reporting the number of valid,
+    /// empty, too short and malformed queries. Fails if any query is not valid with --strict-input,
+    /// otherwise the invalid queries are reported with a warning
+    #[structopt(long = "validate-only")]
+    pub validate_only: bool,
+
+    /// Fail the query validation if any query is not valid, instead of warning about the invalid queries
+    #[structopt(long = "strict-input")]
+    pub strict_input: bool,
+
+    /// Report the matched unitigs and the positions of the matches instead of the matching kmers counts
+    #[structopt(long = "report-positions")]
+    pub report_positions: bool,
+
+    /// Report the ids of the unitigs matched by the kmers of each query instead of the matching kmers counts
+    #[structopt(long = "report-unitigs")]
+    pub report_unitigs: bool,
+
+    /// Also report the sequences of the matched unitigs, loading them with an additional pass on the graph
+    #[structopt(long = "with-unitig-sequences", requires = "report-unitigs")]
+    pub with_unitig_sequences: bool,
+
+    /// Report for each query and color the strand of the graph where most of its kmers matched,
+    /// as a +/- column of the output
+    #[structopt(long = "report-matched-strand")]
+    pub report_matched_strand: bool,
+
+    /// Drop the colors covering less than this fraction of the kmers of a query from its colored output,
+    /// independently of the query length
+    #[structopt(long = "min-color-abundance", default_value = "0")]
+    pub min_color_abundance: f64,
+
+    /// Expand the IUPAC degenerate bases of the query kmers, matching all their variants.
+    /// Queries with a kmer having more variants than the given maximum are reported as too degenerate
+    /// (not supported with colors)
+    #[structopt(long = "expand-degenerate")]
+    pub expand_degenerate: Option<usize>,
+
+    /// Count only the matched kmers in a run of at least this number of consecutive matching kmers
+    /// along the query, dropping the scattered single matches (not supported with colors)
+    #[structopt(long = "min-run-length", default_value = "1")]
+    pub min_run_length: usize,
+
+    /// Query only the kmers of the unitigs whose ids are listed in this file, separated by whitespace
+    /// (not supported with --expand-degenerate, --min-run-length, --report-positions or --report-unitigs)
+    #[structopt(long = "unitigs-subset")]
+    pub unitigs_subset: Option<PathBuf>,
+
     #[structopt(short = "x", long, default_value = "MinimizerBucketing")]
     pub step: QuerierStartingStep,
 
+    /// Run again only the phases of a query run kept with --keep-temp-files starting from the given one
+    /// (e.g. "colormap reading"), with the inputs and parameters of that run
+    #[structopt(long = "resume-from-phase", requires = "resume-temp-dir")]
+    pub resume_from_phase: Option<String>,
+
+    /// The temporary directory of the query run to resume
+    #[structopt(long = "resume-temp-dir")]
+    pub resume_temp_dir: Option<PathBuf>,
+
     #[structopt(flatten)]
     pub common_args: CommonArgs,
 }
@@ -248,6 +785,18 @@ fn initialize(args: &CommonArgs, out_file: &PathBuf) -> &'static GGCATInstance {
         total_threads_count: args.threads_count,
         intermediate_compression_level: args.intermediate_compression_level,
         stats_file: Some(out_file.with_extension("stats.log")),
+        io_threads_count: args.threads_io,
+        compute_threads_count: args.threads_compute,
+        log_format: match args.log_format {
+            LogFormat::Human => ggcat_api::LogFormat::Human,
+            LogFormat::Json => ggcat_api::LogFormat::Json,
+        },
+        verbose_phases_log: args.verbose_phases,
+        reads_size_encoding: match args.reads_size_encoding {
+            ReadsSizeEncoding::Varint => ggcat_api::ReadsSizeEncoding::Varint,
+            ReadsSizeEncoding::FixedWidth => ggcat_api::ReadsSizeEncoding::FixedWidth,
+        },
+        max_read_length: args.max_read_length,
     });
 
     ggcat_api::debug::DEBUG_KEEP_FILES.store(args.keep_temp_files, Ordering::Relaxed);
@@ -273,6 +822,24 @@ fn initialize(args: &CommonArgs, out_file: &PathBuf) -> &'static GGCATInstance {
     instance
 }
 
+fn convert_minimizer_ordering(ordering: MinimizerOrdering) -> ggcat_api::MinimizerOrdering {
+    match ordering {
+        MinimizerOrdering::Hash => ggcat_api::MinimizerOrdering::Hash,
+        MinimizerOrdering::Random => ggcat_api::MinimizerOrdering::Random,
+        MinimizerOrdering::FrequencyAware => ggcat_api::MinimizerOrdering::FrequencyAware,
+    }
+}
+
+fn convert_canonical_compatibility(
+    compatibility: CanonicalCompatibility,
+) -> ggcat_api::CanonicalCompatibility {
+    match compatibility {
+        CanonicalCompatibility::Ggcat => ggcat_api::CanonicalCompatibility::Ggcat,
+        CanonicalCompatibility::Bifrost => ggcat_api::CanonicalCompatibility::Bifrost,
+        CanonicalCompatibility::Cuttlefish => ggcat_api::CanonicalCompatibility::Cuttlefish,
+    }
+}
+
 fn convert_assembler_step(step: AssemblerStartingStep) -> assembler::AssemblerStartingStep {
     match step {
         AssemblerStartingStep::MinimizerBucketing => {
@@ -292,7 +859,10 @@ fn convert_assembler_step(step: AssemblerStartingStep) -> assembler::AssemblerSt
 fn run_assembler_from_args(instance: &GGCATInstance, args: AssemblerArgs) {
     let mut inputs: Vec<_> = args.input.iter().cloned().map(|f| (f, None)).collect();
 
-    if (args.input_lists.len() > 0 || args.input.len() > 0) && args.colored_input_lists.len() > 0 {
+    if (args.input_lists.len() > 0 || args.input.len() > 0)
+        && args.colored_input_lists.len() > 0
+        && args.same_color_as.is_none()
+    {
         println!("Cannot specify both colored input lists and other files/lists");
         exit(1);
     }
@@ -357,21 +927,162 @@ fn run_assembler_from_args(instance: &GGCATInstance, args: AssemblerArgs) {
         colors.into_iter().map(|(c, _)| c).collect()
     };
 
+    let color_names = match (&args.same_color_as, &args.append_to) {
+        (Some(color_name), Some(existing_graph)) => {
+            match GGCATInstance::append_inputs_to_color(
+                existing_graph,
+                args.common_args.kmer_length,
+                &mut inputs,
+                &color_names,
+                color_name,
+            ) {
+                Ok(color_names) => color_names,
+                Err(err) => {
+                    println!("ERROR: {}", err);
+                    exit(1);
+                }
+            }
+        }
+        _ => color_names,
+    };
+
     if inputs.is_empty() {
         println!("ERROR: No input files specified!");
         exit(1);
     }
 
-    let inputs = inputs
-        .into_iter()
-        .map(|x| GeneralSequenceBlockData::FASTA(x))
-        .collect();
+    // With a strand mode for some of the files the build hashes the reverse complementary kmers as
+    // different if any input is forward-only, adding the kmers of the canonical files on both strands
+    let stranded_inputs = !args.forward_only_inputs.is_empty() || !args.canonical_inputs.is_empty();
+    let forward_only_build = args.common_args.forward_only || args.strand_specific;
+
+    if stranded_inputs && args.kmer_counts_input {
+        println!("ERROR: the inputs strand mode cannot be selected with kmer counts inputs");
+        exit(1);
+    }
+    let circular_inputs = match args.circular_inputs {
+        CircularInputs::Disabled => ggcat_api::CircularInputs::Disabled,
+        CircularInputs::HeaderTag => ggcat_api::CircularInputs::HeaderTag,
+        CircularInputs::All => ggcat_api::CircularInputs::All,
+    };
+    if circular_inputs != ggcat_api::CircularInputs::Disabled && args.kmer_counts_input {
+        println!("ERROR: the circular inputs cannot be selected with kmer counts inputs");
+        exit(1);
+    }
+    if let Some(file) = args
+        .forward_only_inputs
+        .iter()
+        .find(|file| args.canonical_inputs.contains(file))
+    {
+        println!(
+            "ERROR: the input {} cannot be both forward-only and canonical",
+            file.display()
+        );
+        exit(1);
+    }
+
+    // The input files of each color, to count their kmers for the abundance profile
+    let mut color_inputs: Vec<Vec<PathBuf>> = vec![];
+    if args.abundance_profile {
+        for (index, (file, color)) in inputs.iter().enumerate() {
+            let color = color.map(|c| c as usize).unwrap_or(index);
+            if color_inputs.len() <= color {
+                color_inputs.resize(color + 1, vec![]);
+            }
+            color_inputs[color].push(file.clone());
+        }
+    }
+
+    let min_multiplicity = if args.auto_min_multiplicity && !args.kmer_counts_input {
+        let min_multiplicity = GGCATInstance::estimate_min_multiplicity(
+            inputs.iter().map(|(file, _)| file.clone()).collect(),
+            args.common_args.kmer_length,
+        );
+        println!("Estimated minimum multiplicity: {}", min_multiplicity);
+        min_multiplicity
+    } else {
+        args.min_multiplicity
+    };
+
+    let input_files: Vec<_> = if args.n_gaps_bed {
+        inputs.iter().map(|(file, _)| file.clone()).collect()
+    } else {
+        vec![]
+    };
+
+    let inputs: Vec<_> = if args.kmer_counts_input {
+        match KmerCountsDumpStream::new(
+            inputs.into_iter().map(|(file, _)| file).collect(),
+            args.common_args.kmer_length,
+        ) {
+            Ok(stream) => stream.into_input_blocks(),
+            Err(err) => {
+                println!("ERROR: {}", err);
+                exit(1);
+            }
+        }
+    } else if stranded_inputs {
+        StrandedInputsStream::new(
+            inputs
+                .into_iter()
+                .map(|(file, color)| {
+                    let strand_mode = if args.forward_only_inputs.contains(&file) {
+                        InputStrandMode::ForwardOnly
+                    } else if args.canonical_inputs.contains(&file) || !forward_only_build {
+                        InputStrandMode::Canonical
+                    } else {
+                        InputStrandMode::ForwardOnly
+                    };
+                    (file, color, strand_mode)
+                })
+                .collect(),
+        )
+        .with_circular_inputs(circular_inputs)
+        .with_strict_input(args.strict_input)
+        .into_input_blocks()
+    } else if circular_inputs != ggcat_api::CircularInputs::Disabled {
+        CircularFastaStream::new(inputs, circular_inputs)
+            .with_strict_input(args.strict_input)
+            .into_input_blocks()
+    } else {
+        inputs
+            .into_iter()
+            .map(|x| GeneralSequenceBlockData::FASTA(x))
+            .collect()
+    };
+
+    if let Some(max_unitig_length) = args.max_unitig_length {
+        if max_unitig_length < args.common_args.kmer_length {
+            println!(
+                "ERROR: the maximum unitig length must be at least the kmers length ({})",
+                args.common_args.kmer_length
+            );
+            exit(1);
+        }
+    }
+
+    if args.unitigs_parquet && !cfg!(feature = "parquet") {
+        println!("ERROR: the unitigs parquet table requires a build with the parquet feature");
+        exit(1);
+    }
+
+    if args.max_records_per_file == Some(0) {
+        println!("ERROR: the maximum number of records per file must be positive");
+        exit(1);
+    }
+
+    if args.strict_input {
+        if let Err(err) = GGCATInstance::validate_inputs(&inputs) {
+            println!("ERROR: {}", err);
+            exit(1);
+        }
+    }
 
     *ggcat_api::debug::DEBUG_ASSEMBLER_FIRST_STEP.lock() = convert_assembler_step(args.step);
     *ggcat_api::debug::DEBUG_ASSEMBLER_LAST_STEP.lock() = convert_assembler_step(args.last_step);
+    *ggcat_api::debug::DEBUG_ASSEMBLER_RESUME_TEMP_DIR.lock() = args.resume_temp_dir.clone();
     ggcat_api::debug::DEBUG_LINK_PHASE_ITERATION_START_STEP.store(args.number, Ordering::Relaxed);
-
-    let output_file = instance.build_graph(
+    let output_file = match instance.try_build_graph(
         inputs,
         args.output_file,
         Some(&color_names),
@@ -380,8 +1091,16 @@ fn run_assembler_from_args(instance: &GGCATInstance, args: AssemblerArgs) {
         args.common_args.forward_only,
         args.common_args.minimizer_length,
         args.colors,
-        args.min_multiplicity,
-        if args.generate_maximal_unitigs_links {
+        min_multiplicity,
+        if let Some(connected_components) = args.connected_components {
+            ExtraElaboration::ConnectedComponents(match connected_components {
+                ComponentsOutput::HeaderTag => ggcat_api::ComponentsOutputMode::HeaderTag,
+                ComponentsOutput::SeparateFiles => ggcat_api::ComponentsOutputMode::SeparateFiles,
+                ComponentsOutput::HeaderTagAndSeparateFiles => {
+                    ggcat_api::ComponentsOutputMode::HeaderTagAndSeparateFiles
+                }
+            })
+        } else if args.generate_maximal_unitigs_links {
             ExtraElaboration::UnitigLinks
         } else if args.greedy_matchtigs {
             ExtraElaboration::GreedyMatchtigs
@@ -392,9 +1111,318 @@ fn run_assembler_from_args(instance: &GGCATInstance, args: AssemblerArgs) {
         } else {
             ExtraElaboration::None
         },
-    );
+        BuildOptions {
+            target_sequences: args.target,
+            target_kmers_memory: args
+                .target_memory
+                .map(|memory| (memory * (1024 * 1024 * 1024) as f64) as usize),
+            strand_specific: args.strand_specific,
+            output_sink: None,
+            kmer_counts_output: args.coverage_track.is_some(),
+            low_coverage_bed: args.low_coverage_bed,
+            unitig_anchors_output: args.unitig_anchors,
+            unused_reads_output: args.unused_reads.clone(),
+            coverage_statistic: match args.coverage_statistic {
+                CoverageStatistic::Mean => ggcat_api::CoverageStatistic::Mean,
+                CoverageStatistic::Median => ggcat_api::CoverageStatistic::Median,
+                CoverageStatistic::Min => ggcat_api::CoverageStatistic::Min,
+                CoverageStatistic::Max => ggcat_api::CoverageStatistic::Max,
+            },
+            min_color_coverage: args.min_color_coverage,
+            color_coverage_output: args.gfa_color_coverage,
+            max_kmer_colors: args.max_kmer_colors,
+            max_exact_color_set: args.max_exact_color_set,
+            shared_sequence_policy: match args.shared_sequence_policy {
+                SharedSequencePolicy::AllColors => ggcat_api::SharedSequencePolicy::AllColors,
+                SharedSequencePolicy::FirstColor => ggcat_api::SharedSequencePolicy::FirstColor,
+                SharedSequencePolicy::FlagShared => ggcat_api::SharedSequencePolicy::FlagShared,
+            },
+            adaptive_compression_level: args.adaptive_compression_level,
+            kmer_counters_saturation: args.max_kmer_count,
+            exact_kmers: args.exact_kmers,
+            kmer_counting_mode: match args.kmer_counting {
+                KmerCountingMode::CountPerOccurrence => {
+                    ggcat_api::KmerCountingMode::CountPerOccurrence
+                }
+                KmerCountingMode::CountPerRead => ggcat_api::KmerCountingMode::CountPerRead,
+            },
+            min_link_coverage: args.min_link_coverage,
+            merge_hashmap_load_factor: args.merge_load_factor,
+            single_threaded_mode: if args.single_threaded {
+                ggcat_api::SingleThreadedMode::Forced
+            } else {
+                ggcat_api::SingleThreadedMode::Auto
+            },
+            deterministic_output: args.deterministic_output,
+            skewed_buckets_auto_retry: args.retry_skewed_buckets,
+            orphan_links_check: if args.fail_on_orphan_links {
+                ggcat_api::OrphanLinksCheck::Fail
+            } else if args.report_orphan_links {
+                ggcat_api::OrphanLinksCheck::Report
+            } else {
+                ggcat_api::OrphanLinksCheck::Disabled
+            },
+            auto_tuning: args.auto_tune,
+            minimizers_check: args.check_minimizers,
+            minimizer_ordering: convert_minimizer_ordering(args.common_args.minimizer_ordering),
+            canonical_compatibility: convert_canonical_compatibility(
+                args.common_args.canonical_compatibility,
+            ),
+            io_threads_count: args.common_args.threads_io,
+            compute_threads_count: args.common_args.threads_compute,
+            max_open_files: args.common_args.max_open_files,
+            random_seed: args.common_args.seed,
+            strict_input: args.strict_input,
+            subphase_timing: args.common_args.subphase_timing,
+            keep_temp_files_until_success: args.common_args.no_temp_remove_until_end,
+        },
+    ) {
+        Ok(output_file) => output_file,
+        Err(err) => {
+            println!("ERROR: {}", err);
+            exit(1);
+        }
+    };
+
+    if let Some(max_unitig_length) = args.max_unitig_length {
+        let split_file = output_file.with_file_name(format!(
+            "split-{}",
+            output_file.file_name().unwrap().to_string_lossy()
+        ));
+        GGCATInstance::split_long_unitigs(
+            &output_file,
+            &split_file,
+            args.common_args.kmer_length,
+            max_unitig_length,
+        );
+        std::fs::rename(&split_file, &output_file).unwrap();
+    }
+
+    if let Some(reference_file) = args.orient_to_reference {
+        let oriented_file = output_file.with_file_name(format!(
+            "oriented-{}",
+            output_file.file_name().unwrap().to_string_lossy()
+        ));
+        instance.orient_unitigs_to_reference(
+            output_file.clone(),
+            reference_file,
+            oriented_file.clone(),
+            args.common_args.kmer_length,
+        );
+        std::fs::rename(&oriented_file, &output_file).unwrap();
+        // The strands in the index do not match the oriented graph
+        let _ = std::fs::remove_file(querier::unitigs_index::get_unitigs_index_file(&output_file));
+    }
 
     println!("Final output saved to: {}", output_file.display());
+
+    if let Some(scaffold_pairs) = args.scaffold_pairs {
+        let scaffolds_file = output_file.with_extension("scaffolds.fa");
+        match instance.scaffold_unitigs(
+            output_file.clone(),
+            scaffold_pairs[0].clone(),
+            scaffold_pairs[1].clone(),
+            scaffolds_file.clone(),
+            args.common_args.kmer_length,
+            args.scaffold_min_pairs,
+            args.scaffold_insert_size,
+        ) {
+            Ok(result) => println!(
+                "{} scaffolds with {} joins (insert size {}) saved to: {}",
+                result.scaffolds_count,
+                result.joins_count,
+                result.insert_size,
+                scaffolds_file.display()
+            ),
+            Err(err) => {
+                println!("ERROR: Cannot scaffold the unitigs: {}", err);
+                exit(1);
+            }
+        }
+    }
+
+    if args.unitigs_by_color {
+        let output_prefix = output_file.with_file_name(
+            output_file
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .split('.')
+                .next()
+                .unwrap()
+                .to_string(),
+        );
+        let color_files = GGCATInstance::write_unitigs_by_color(
+            &output_file,
+            &output_prefix,
+            args.common_args.max_open_files,
+        );
+        println!(
+            "Unitigs of {} colors saved to: {}.color<index>.fa",
+            color_files.len(),
+            output_prefix.display()
+        );
+    }
+
+    if args.colored_gfa {
+        let gfa_file = output_file.with_extension("gfa");
+        let segments_count = GGCATInstance::write_colored_gfa(
+            &output_file,
+            &gfa_file,
+            args.common_args.kmer_length,
+            &args.gfa_color_tag,
+            args.gfa_max_colors,
+            args.gfa_color_indices,
+            args.gfa_color_coverage,
+        );
+        println!(
+            "Colored GFA with {} segments saved to: {}",
+            segments_count,
+            gfa_file.display()
+        );
+    }
+
+    if args.abundance_profile {
+        let _ = std::fs::create_dir_all(&args.common_args.temp_dir);
+        let color_tables: Vec<_> = color_inputs
+            .into_iter()
+            .enumerate()
+            .map(|(color, files)| {
+                instance.build_kmer_counts_table(
+                    files,
+                    args.common_args
+                        .temp_dir
+                        .join(format!("abundance-profile-color{}.counts.tbl", color)),
+                    args.common_args.kmer_length,
+                )
+            })
+            .collect();
+
+        let profile_file = output_file.with_extension("abundance_profile.tsv");
+        let result = GGCATInstance::write_color_abundance_profile(
+            &output_file,
+            &color_tables,
+            args.abundance_bins
+                .as_deref()
+                .unwrap_or(querier::abundance_profile::DEFAULT_ABUNDANCE_BINS),
+            &profile_file,
+        );
+        for table in color_tables {
+            let _ = std::fs::remove_file(table);
+        }
+
+        match result {
+            Ok(_) => println!("Abundance profile saved to: {}", profile_file.display()),
+            Err(err) => {
+                println!("ERROR: {}", err);
+                exit(1);
+            }
+        }
+    }
+
+    if args.n_gaps_bed {
+        let n_gaps_file = output_file.with_extension("n_gaps.bed");
+        let gaps_count =
+            ggcat_api::write_n_gaps_bed(&input_files, &n_gaps_file, args.min_n_gap_length);
+        println!("{} N gaps saved to: {}", gaps_count, n_gaps_file.display());
+    }
+
+    if args.low_coverage_bed.is_some() {
+        let bed_file = ggcat_api::get_low_coverage_bed_file(&output_file);
+        if bed_file.exists() {
+            println!("Low coverage intervals saved to: {}", bed_file.display());
+        }
+    }
+
+    if let Some(reference) = &args.coverage_track {
+        let track_file = querier::coverage_track::get_coverage_bedgraph_file(&output_file);
+        let intervals_count = match instance.write_coverage_bedgraph(
+            output_file.clone(),
+            reference,
+            &track_file,
+            args.common_args.kmer_length,
+        ) {
+            Ok(intervals_count) => intervals_count,
+            Err(err) => {
+                println!("ERROR: {}", err);
+                exit(1);
+            }
+        };
+        println!(
+            "{} coverage intervals saved to: {}",
+            intervals_count,
+            track_file.display()
+        );
+    }
+
+    if args.reproducibility_hash {
+        let hash_file = GGCATInstance::write_reproducibility_hash(&output_file);
+        println!("Reproducibility hash saved to: {}", hash_file.display());
+    }
+
+    if args.color_jaccard {
+        let jaccard_file = querier::color_jaccard::get_color_jaccard_file(&output_file);
+        GGCATInstance::write_color_jaccard_matrix(&output_file, &jaccard_file);
+        println!("Colors jaccard matrix saved to: {}", jaccard_file.display());
+    }
+
+    if args.unique_kmers {
+        let unique_kmers_file = querier::unique_kmers::get_unique_kmers_file(&output_file);
+        let unique_kmers =
+            match instance.write_unique_kmers_report(&output_file, &unique_kmers_file) {
+                Ok(unique_kmers) => unique_kmers,
+                Err(err) => {
+                    println!("ERROR: {}", err);
+                    exit(1);
+                }
+            };
+        println!(
+            "{} unique kmers saved to: {}",
+            unique_kmers.iter().sum::<u64>(),
+            unique_kmers_file.display()
+        );
+    }
+
+    if args.unitig_anchors {
+        let anchors_file = ggcat_api::get_unitig_anchors_file(&output_file);
+        println!("Unitig anchors saved to: {}", anchors_file.display());
+    }
+
+    #[cfg(feature = "parquet")]
+    if args.unitigs_parquet {
+        let table_file = querier::unitigs_parquet::get_unitigs_parquet_file(&output_file);
+        let rows_count =
+            GGCATInstance::write_unitigs_parquet(&output_file, &table_file, args.colors);
+        println!(
+            "Unitigs table with {} rows saved to: {}",
+            rows_count,
+            table_file.display()
+        );
+    }
+
+    if args.unitigs_dot {
+        let dot_file = querier::unitigs_dot::get_unitigs_dot_file(&output_file);
+        match GGCATInstance::write_unitigs_dot(&output_file, &dot_file, args.dot_max_nodes) {
+            Ok(nodes_count) => println!(
+                "DOT graph with {} nodes saved to: {}",
+                nodes_count,
+                dot_file.display()
+            ),
+            Err(err) => ggcat_api::log_warning(format!("WARNING: {}", err)),
+        }
+    }
+
+    if let Some(max_records_per_file) = args.max_records_per_file {
+        let index_file = output_file.with_extension("parts.tsv");
+        let part_files =
+            GGCATInstance::write_output_parts(&output_file, max_records_per_file, &index_file);
+        std::fs::remove_file(&output_file).unwrap();
+        println!(
+            "Output split into {} files, indexed in: {}",
+            part_files.len(),
+            index_file.display()
+        );
+    }
 }
 
 fn convert_querier_step(step: QuerierStartingStep) -> querier::QuerierStartingStep {
@@ -408,6 +1436,131 @@ fn convert_querier_step(step: QuerierStartingStep) -> querier::QuerierStartingSt
 
 fn run_querier_from_args(instance: &GGCATInstance, args: QueryArgs) -> PathBuf {
     *ggcat_api::debug::DEBUG_QUERIER_FIRST_STEP.lock() = convert_querier_step(args.step);
+    let mut options = QueryOptions {
+        unitigs_subset: None,
+        matched_strand_report: args.report_matched_strand,
+        min_color_abundance: args.min_color_abundance,
+        counters_sort_memory: args
+            .counters_sort_memory
+            .map(|memory| (memory * (1024 * 1024 * 1024) as f64) as usize),
+        minimizer_ordering: convert_minimizer_ordering(args.common_args.minimizer_ordering),
+        canonical_compatibility: convert_canonical_compatibility(
+            args.common_args.canonical_compatibility,
+        ),
+        io_threads_count: args.common_args.threads_io,
+        compute_threads_count: args.common_args.threads_compute,
+        max_open_files: args.common_args.max_open_files,
+        random_seed: args.common_args.seed,
+        strict_input: args.strict_input,
+        subphase_timing: args.common_args.subphase_timing,
+        keep_temp_files_until_success: args.common_args.no_temp_remove_until_end,
+    };
+
+    if let (Some(phase_name), Some(temp_dir)) = (&args.resume_from_phase, &args.resume_temp_dir) {
+        return match instance.resume_from_phase(
+            temp_dir,
+            phase_name,
+            args.common_args.threads_count,
+            options,
+        ) {
+            Ok(output_file) => output_file,
+            Err(err) => {
+                println!("ERROR: {}", err);
+                exit(1);
+            }
+        };
+    }
+
+    // The unitigs subset is applied only by the kmers query, the other query modes read the whole graph
+    if args.unitigs_subset.is_some()
+        && (args.expand_degenerate.is_some()
+            || args.min_run_length > 1
+            || args.report_positions
+            || args.report_unitigs)
+    {
+        println!(
+            "ERROR: the unitigs subset is not supported with --expand-degenerate, --min-run-length, --report-positions or --report-unitigs"
+        );
+        exit(1);
+    }
+
+    if let Some(max_expansions) = args.expand_degenerate {
+        if args.colors {
+            println!("ERROR: the degenerate kmers expansion is not supported with colors");
+            exit(1);
+        }
+        if args.min_run_length > 1 || args.report_positions || args.report_unitigs {
+            println!(
+                "ERROR: the degenerate kmers expansion cannot be combined with --min-run-length, --report-positions or --report-unitigs"
+            );
+            exit(1);
+        }
+        return instance.query_graph_degenerate(
+            args.input_graph,
+            args.input_query,
+            args.output_file_prefix,
+            args.common_args.kmer_length,
+            max_expansions,
+        );
+    }
+
+    if args.min_run_length > 1 {
+        if args.colors {
+            println!("ERROR: the minimum run length is not supported with colors");
+            exit(1);
+        }
+        return instance.query_graph_min_run_length(
+            args.input_graph,
+            args.input_query,
+            args.output_file_prefix,
+            args.common_args.kmer_length,
+            args.min_run_length,
+        );
+    }
+
+    if let Some(unitigs_subset) = &args.unitigs_subset {
+        let unitig_ids = std::fs::read_to_string(unitigs_subset)
+            .map_err(|err| err.to_string())
+            .and_then(|ids| {
+                ids.split_whitespace()
+                    .map(|id| {
+                        id.parse::<u64>()
+                            .map_err(|_| format!("invalid unitig id '{}'", id))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            });
+
+        match unitig_ids {
+            Ok(unitig_ids) => options.unitigs_subset = Some(unitig_ids),
+            Err(err) => {
+                println!(
+                    "ERROR: Cannot read unitigs subset file {}: {}",
+                    unitigs_subset.display(),
+                    err
+                );
+                exit(1);
+            }
+        }
+    }
+
+    if args.report_positions {
+        return instance.query_graph_positions(
+            args.input_graph,
+            args.input_query,
+            args.output_file_prefix,
+            args.common_args.kmer_length,
+        );
+    }
+
+    if args.report_unitigs {
+        return instance.query_graph_unitigs(
+            args.input_graph,
+            args.input_query,
+            args.output_file_prefix,
+            args.common_args.kmer_length,
+            args.with_unitig_sequences,
+        );
+    }
 
     instance.query_graph(
         args.input_graph,
@@ -428,7 +1581,12 @@ fn run_querier_from_args(instance: &GGCATInstance, args: QueryArgs) -> PathBuf {
             ColoredQueryOutputFormat::JsonLinesWithNames => {
                 querier::ColoredQueryOutputFormat::JsonLinesWithNames
             }
+            ColoredQueryOutputFormat::Classification => {
+                querier::ColoredQueryOutputFormat::Classification
+            }
+            ColoredQueryOutputFormat::CountMatrix => querier::ColoredQueryOutputFormat::CountMatrix,
         },
+        options,
     )
 }
 
@@ -494,10 +1652,32 @@ fn main() {
             return; // Skip final memory deallocation
         }
         CliArgs::Query(args) => {
+            if args.validate_only {
+                match GGCATInstance::validate_queries(
+                    &[args.input_query.clone()],
+                    args.common_args.kmer_length,
+                    args.strict_input,
+                ) {
+                    Ok(counts) => println!(
+                        "{} of the {} queries are valid",
+                        counts.valid,
+                        counts.valid + counts.invalid_count()
+                    ),
+                    Err(err) => {
+                        println!("ERROR: {}", err);
+                        exit(1);
+                    }
+                }
+                return; // Skip final memory deallocation
+            }
+
             initialize(&args.common_args, &args.output_file_prefix);
 
             if !args.colors && args.colored_query_output_format.is_some() {
-                println!("Warning: colored query output format is specified, but the graph is not colored");
+                ggcat_api::log_warning(
+                    "Warning: colored query output format is specified, but the graph is not colored"
+                        .to_string(),
+                );
             }
 
             let _guard = instrumenter::initialize_tracing(
@@ -529,6 +1709,60 @@ fn main() {
             drop(output_file);
             println!("Colors written to {}", output_file_name.display());
 
+            return; // Skip final memory deallocation
+        }
+        CliArgs::DiffColormaps(args) => {
+            let diff = GGCATInstance::diff_colormaps(&args.old_colormap, &args.new_colormap);
+            for name in &diff.added_colors {
+                println!("Added color: {}", name);
+            }
+            for name in &diff.removed_colors {
+                println!("Removed color: {}", name);
+            }
+            for (label, subsets) in [
+                ("Added", &diff.added_subsets),
+                ("Removed", &diff.removed_subsets),
+                ("Changed", &diff.changed_subsets),
+            ] {
+                if !subsets.is_empty() {
+                    println!(
+                        "{} color subsets ({}): {}",
+                        label,
+                        subsets.len(),
+                        subsets
+                            .iter()
+                            .map(|subset| subset.to_string())
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    );
+                }
+            }
+            if diff.is_empty() {
+                println!("The colormaps are identical");
+            }
+
+            return; // Skip final memory deallocation
+        }
+        CliArgs::Concat(args) => {
+            let output_file_name =
+                GGCATInstance::concat_graphs(args.input_graphs, args.output_file);
+            println!("Final output saved to: {}", output_file_name.display());
+
+            return; // Skip final memory deallocation
+        }
+        CliArgs::CompareSpectra(args) => {
+            let comparison = GGCATInstance::compare_kmer_spectra(
+                args.first_inputs,
+                args.second_inputs,
+                args.kmer_length,
+                &args.output_file,
+            );
+            println!(
+                "L1 distance: {:.6}, cosine similarity: {:.6}",
+                comparison.l1_distance, comparison.cosine_similarity
+            );
+            println!("Spectra saved to: {}", args.output_file.display());
+
             return; // Skip final memory deallocation
         }
     }
@@ -1,4 +1,5 @@
 use crate::parsers::SingleSequenceInfo;
+use crate::storage::serializer::ColormapSentinels;
 use config::{BucketIndexType, ColorCounterType, ColorIndexType};
 use dynamic_dispatch::dynamic_dispatch;
 use hashbrown::HashMap;
@@ -72,6 +73,28 @@ pub trait ColorMapReader {
     fn get_color_name(&self, index: ColorIndexType, json_escaped: bool) -> &str;
     fn colors_count(&self) -> usize;
     fn colors_subsets_count(&self) -> u64;
+
+    /// The k-mers length used to build the colormap, if known
+    fn kmer_length(&self) -> Option<usize>;
+
+    /// The sentinel colors of the colormap and how its shared kmers were assigned
+    fn sentinels(&self) -> ColormapSentinels;
+
+    /// The number of input colors, the sentinel colors follow them
+    fn input_colors_count(&self) -> usize {
+        self.colors_count() - self.sentinels().count()
+    }
+
+    /// Checks that the colormap was built with the requested k-mers length
+    fn check_kmer_length(&self, k: usize) -> Result<(), String> {
+        match self.kmer_length() {
+            Some(stored_k) if stored_k != k => Err(format!(
+                "The colormap was built with k={}, but k={} was requested. Please use the same k-mers length used to build the graph",
+                stored_k, k
+            )),
+            _ => Ok(()),
+        }
+    }
 }
 
 impl ColorMapReader for () {
@@ -86,6 +109,14 @@ impl ColorMapReader for () {
     fn colors_subsets_count(&self) -> u64 {
         0
     }
+
+    fn kmer_length(&self) -> Option<usize> {
+        None
+    }
+
+    fn sentinels(&self) -> ColormapSentinels {
+        ColormapSentinels::default()
+    }
 }
 
 /// Helper trait to manage colors parsing from different sources (actually 2, color from file or color from annotated dbg graph)
@@ -126,10 +157,15 @@ pub trait ColorsMergeManager<H: MinimizerHashFunctionFactory, MH: HashFunctionFa
     type GlobalColorsTableWriter: Sync + Send + 'static;
     type GlobalColorsTableReader: ColorMapReader + Sync + Send + 'static;
 
-    /// Creates a new colors table at the given path
+    /// Creates a new colors table at the given path, built with k-mers of length k.
+    /// The final write of the table is saved in temp_dir, to be repeated if it fails.
+    /// The colors hashing keys are derived from the random seed of the run
     fn create_colors_table(
         path: impl AsRef<Path>,
         color_names: &[String],
+        k: usize,
+        temp_dir: &Path,
+        random_seed: Option<u64>,
     ) -> Self::GlobalColorsTableWriter;
 
     /// Creates a new colors table at the given path
@@ -149,9 +185,11 @@ pub trait ColorsMergeManager<H: MinimizerHashFunctionFactory, MH: HashFunctionFa
         entry: &mut MapEntry<Self::HashMapTempColorIndex>,
     );
 
+    /// Records a sequence of the bucket, whose kmers were each counted multiplicity times
     fn add_temp_buffer_sequence(
         data: &mut Self::ColorsBufferTempStructure,
         sequence: CompressedRead,
+        multiplicity: u64,
         k: usize,
         m: usize,
         flags: u8,
@@ -161,11 +199,18 @@ pub trait ColorsMergeManager<H: MinimizerHashFunctionFactory, MH: HashFunctionFa
     type HashMapTempColorIndex: 'static + Send + Sync;
     fn new_color_index() -> Self::HashMapTempColorIndex;
 
-    /// This step finds the color subset indexes for each map entry
+    /// This step finds the color subset indexes for each map entry. The kmers sharing their hash with
+    /// a different kmer (in the exact kmers mode) have their entries in collision_map, keyed by the hash and
+    /// by the generation that kmer_generation returns for the kmer and its hash strand, 0 being the kmers of map
     fn process_colors(
         global_colors_table: &Self::GlobalColorsTableWriter,
         data: &mut Self::ColorsBufferTempStructure,
         map: &mut HashMap<MH::HashTypeUnextendable, MapEntry<Self::HashMapTempColorIndex>>,
+        collision_map: &mut HashMap<
+            (MH::HashTypeUnextendable, usize),
+            MapEntry<Self::HashMapTempColorIndex>,
+        >,
+        kmer_generation: impl FnMut(MH::HashTypeUnextendable, CompressedRead, bool) -> usize,
         k: usize,
         min_multiplicity: usize,
     );
@@ -1,26 +1,41 @@
 // use crate::storage::roaring::ColorsStorage;
-use crate::storage::serializer::ColorsSerializer;
+use crate::storage::serializer::{ColormapSentinels, ColorsSerializer};
 use crate::storage::ColorsSerializerTrait;
+use crate::subsets_coverage::{get_subsets_coverage_file, SubsetsCoverageWriter};
 use config::ColorIndexType;
 use dashmap::DashMap;
 use hashes::dummy_hasher::DummyHasherBuilder;
-use rand::{thread_rng, RngCore};
+use rand::RngCore;
 use siphasher::sip128::{Hasher128, SipHasher13};
 use std::hash::Hash;
 use std::path::Path;
+use utils::random_seed::{seeded_rng, RandomComponent};
 
 pub struct ColorsMemMapWriter<C: ColorsSerializerTrait> {
     colors: DashMap<u128, ColorIndexType, DummyHasherBuilder>,
     colors_storage: ColorsSerializer<C>,
+    subsets_coverage: Option<SubsetsCoverageWriter>,
     hash_keys: (u64, u64),
 }
 
 impl<C: ColorsSerializerTrait> ColorsMemMapWriter<C> {
-    pub fn new(file: impl AsRef<Path>, color_names: &[String]) -> Self {
-        let mut rng = thread_rng();
+    pub fn new(
+        file: impl AsRef<Path>,
+        color_names: &[String],
+        k: usize,
+        sentinels: ColormapSentinels,
+        temp_dir: &Path,
+        random_seed: Option<u64>,
+        // Also write the coverage of each subset, see get_id_with_coverage
+        subsets_coverage: bool,
+    ) -> Self {
+        let mut rng = seeded_rng(random_seed, RandomComponent::ColorsHashing, 0);
+        let subsets_coverage = subsets_coverage
+            .then(|| SubsetsCoverageWriter::create(get_subsets_coverage_file(file.as_ref())));
         Self {
             colors: DashMap::with_hasher(DummyHasherBuilder),
-            colors_storage: ColorsSerializer::new(file, color_names),
+            colors_storage: ColorsSerializer::new(file, color_names, k, sentinels, Some(temp_dir)),
+            subsets_coverage,
             hash_keys: (rng.next_u64(), rng.next_u64()),
         }
     }
@@ -44,6 +59,36 @@ impl<C: ColorsSerializerTrait> ColorsMemMapWriter<C> {
         }
     }
 
+    /// Returns the id of a subset of colors with the given occurrences of its kmers in each of the colors.
+    /// The same colors with a different coverage get a different subset, that lists the same colors in the
+    /// colormap, and the coverage of each new subset is written to the subsets coverage file
+    pub fn get_id_with_coverage(
+        &self,
+        colors: &[ColorIndexType],
+        coverage: &[u32],
+    ) -> ColorIndexType {
+        let mut hasher = SipHasher13::new_with_keys(self.hash_keys.0, self.hash_keys.1);
+        colors.hash(&mut hasher);
+        coverage.hash(&mut hasher);
+        let hash = hasher.finish128().as_u128();
+
+        match self.colors.get(&hash) {
+            None => {
+                let color = self.colors_storage.serialize_colors(colors);
+                if let Some(subsets_coverage) = &self.subsets_coverage {
+                    subsets_coverage.write_subset(color, coverage);
+                }
+                self.colors.insert(hash, color);
+                color
+            }
+            Some(id) => *id,
+        }
+    }
+
+    pub fn colors_count(&self) -> u64 {
+        self.colors_storage.colors_count()
+    }
+
     pub fn print_stats(&self) {
         self.colors_storage.print_stats();
     }
@@ -10,6 +10,7 @@ pub mod managers;
 pub mod non_colored;
 pub mod parsers;
 pub mod storage;
+pub mod subsets_coverage;
 
 pub(crate) mod async_slice_queue;
 
@@ -1,5 +1,6 @@
 use crate::colors_manager::ColorsMergeManager;
 use crate::colors_memmap_writer::ColorsMemMapWriter;
+use crate::storage::serializer::ColormapSentinels;
 use crate::DefaultColorsSerializer;
 use atoi::{FromRadix10, FromRadix16};
 use bstr::ByteSlice;
@@ -24,13 +25,14 @@ use parallel_processor::buckets::readers::compressed_binary_reader::CompressedBi
 use parallel_processor::buckets::writers::compressed_binary_writer::CompressedBinaryWriter;
 use parallel_processor::buckets::LockFreeBucket;
 use parallel_processor::memory_fs::RemoveFileMode;
+use parking_lot::Mutex;
 use std::collections::VecDeque;
 use std::io::{Cursor, Read, Write};
 use std::marker::PhantomData;
 use std::mem::size_of;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use structs::map_entry::{MapEntry, COUNTER_BITS};
 
 const COLOR_SEQUENCES_SUBBUKETS: usize = 32;
@@ -111,6 +113,10 @@ pub struct MultipleColorsManager<H: MinimizerHashFunctionFactory, MH: HashFuncti
     kmers_count: usize,
     sequences_count: usize,
     temp_colors_buffer: Vec<ColorIndexType>,
+    // The occurrences of the current kmer in each of its colors, for the subsets coverage
+    temp_color_occurrences: Vec<(ColorIndexType, u32)>,
+    kmer_coverage: Vec<u32>,
+    last_kmer_coverage: Vec<u32>,
     temp_dir: PathBuf,
     _phantom: PhantomData<(H, MH)>,
 }
@@ -119,6 +125,173 @@ const VISITED_BIT: usize = 1 << (COUNTER_BITS - 1);
 const TEMP_BUFFER_START_SIZE: usize = 1024 * 64;
 const READS_BUFFERS_MAX_CAPACITY: usize = 1024 * 32;
 
+static MIN_COLOR_COVERAGE: AtomicUsize = AtomicUsize::new(1);
+
+/// Sets the minimum number of occurrences of a kmer in a color required to assign that color to the kmer,
+/// to avoid spurious colors coming from a few contaminating reads
+pub fn set_min_color_coverage(min_coverage: usize) {
+    MIN_COLOR_COVERAGE.store(min_coverage.max(1), Ordering::Relaxed);
+}
+
+static COLOR_COVERAGE_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Also write the coverage of each colors subset next to the colormap, as the number of occurrences of its
+/// kmers in each of its colors. The kmers with the same colors and a different coverage are assigned
+/// to different subsets, so the colormap can grow considerably. The sentinel colors have no occurrences
+pub fn set_color_coverage_output(enabled: bool) {
+    COLOR_COVERAGE_OUTPUT.store(enabled, Ordering::Relaxed);
+}
+
+static MAX_KMER_COLORS: AtomicUsize = AtomicUsize::new(0);
+
+/// Name of the sentinel color that replaces the colors of the ubiquitous kmers
+pub const UBIQUITOUS_COLOR_NAME: &str = "ubiquitous";
+
+/// Caps the number of colors of each kmer. The kmers found in more colors are considered ubiquitous
+/// and their colors are replaced by a single sentinel color, named as UBIQUITOUS_COLOR_NAME and added
+/// after all the input colors, reducing the colormap size when most kmers are shared by all the samples.
+/// With None (the default) the colors are not capped
+pub fn set_max_kmer_colors(max_colors: Option<usize>) {
+    MAX_KMER_COLORS.store(max_colors.unwrap_or(0), Ordering::Relaxed);
+}
+
+fn is_kmer_colors_capped() -> bool {
+    MAX_KMER_COLORS.load(Ordering::Relaxed) != 0
+}
+
+/// Replaces the first unique_count colors with the ubiquitous color if they are more than max_colors,
+/// returning the new unique colors count
+fn cap_kmer_colors(
+    colors: &mut [ColorIndexType],
+    unique_count: usize,
+    max_colors: usize,
+    ubiquitous_color: ColorIndexType,
+) -> usize {
+    if unique_count > max_colors {
+        colors[0] = ubiquitous_color;
+        1
+    } else {
+        unique_count
+    }
+}
+
+static MAX_EXACT_COLOR_SET: AtomicUsize = AtomicUsize::new(0);
+
+/// Name of the sentinel color that marks the approximate colors subsets
+pub const APPROXIMATE_COLOR_NAME: &str = "approximate";
+
+/// Bounds the size of the colors subsets stored in the colormap. The kmers found in more colors keep only
+/// an evenly spaced sample of max_size of their colors, plus a sentinel color named as APPROXIMATE_COLOR_NAME,
+/// added after the input colors (and after the ubiquitous color, if the kmer colors are capped).
+/// The queries matching the sentinel color report it as any other color, marking their colors as approximate.
+/// The subsampling is applied after the colors cap. With None (the default) all the subsets are exact
+pub fn set_max_exact_color_set(max_size: Option<usize>) {
+    MAX_EXACT_COLOR_SET.store(max_size.unwrap_or(0), Ordering::Relaxed);
+}
+
+fn is_color_sets_subsampled() -> bool {
+    MAX_EXACT_COLOR_SET.load(Ordering::Relaxed) != 0
+}
+
+/// Replaces the first unique_count sorted colors with an evenly spaced sample of max_size of them followed
+/// by the approximate color if they are more than max_size, returning the new unique colors count
+fn subsample_kmer_colors(
+    colors: &mut [ColorIndexType],
+    unique_count: usize,
+    max_size: usize,
+    approximate_color: ColorIndexType,
+) -> usize {
+    if unique_count <= max_size {
+        return unique_count;
+    }
+    // The sampled index is never before the written one, so the sample can be taken in place
+    for i in 0..max_size {
+        colors[i] = colors[i * unique_count / max_size];
+    }
+    colors[max_size] = approximate_color;
+    max_size + 1
+}
+
+/// How the kmers found in more than one color are assigned to the colors
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SharedSequencePolicy {
+    /// A shared kmer belongs to all the colors where it is found
+    AllColors,
+    /// A shared kmer belongs only to the first of its colors, in the input order
+    FirstColor,
+    /// A shared kmer belongs only to a single sentinel color, named as SHARED_COLOR_NAME
+    FlagShared,
+}
+
+static SHARED_SEQUENCE_POLICY: Mutex<SharedSequencePolicy> =
+    Mutex::new(SharedSequencePolicy::AllColors);
+
+/// Name of the sentinel color assigned to the shared kmers with SharedSequencePolicy::FlagShared
+pub const SHARED_COLOR_NAME: &str = "shared";
+
+/// Sets how the kmers shared by multiple colors are assigned in the next colored builds.
+/// With FlagShared the sentinel color is added after all the input colors (and after the
+/// ubiquitous and the approximate colors, if enabled). The policy is applied after the colors cap,
+/// so an ubiquitous kmer keeps the ubiquitous color
+pub fn set_shared_sequence_policy(policy: SharedSequencePolicy) {
+    *SHARED_SEQUENCE_POLICY.lock() = policy;
+}
+
+fn get_shared_sequence_policy() -> SharedSequencePolicy {
+    *SHARED_SEQUENCE_POLICY.lock()
+}
+
+/// Applies the shared sequence policy to the first unique_count sorted colors,
+/// returning the new unique colors count
+fn apply_shared_sequence_policy(
+    colors: &mut [ColorIndexType],
+    unique_count: usize,
+    policy: SharedSequencePolicy,
+    shared_color: ColorIndexType,
+) -> usize {
+    if unique_count <= 1 {
+        return unique_count;
+    }
+    match policy {
+        SharedSequencePolicy::AllColors => unique_count,
+        SharedSequencePolicy::FirstColor => 1,
+        SharedSequencePolicy::FlagShared => {
+            colors[0] = shared_color;
+            1
+        }
+    }
+}
+
+/// Moves to the front the unique colors with at least min_coverage occurrences in the sorted colors slice,
+/// returning their count. If no color has enough occurrences, the most frequent ones are kept
+/// instead, so that each kmer is assigned to at least one color
+fn dedup_covered_colors(colors: &mut [ColorIndexType], min_coverage: usize) -> usize {
+    let max_coverage = colors
+        .iter()
+        .dedup_with_count()
+        .map(|(count, _)| count)
+        .max()
+        .unwrap_or(0);
+    let min_coverage = min_coverage.min(max_coverage);
+
+    let mut unique_count = 0;
+    let mut start = 0;
+    while start < colors.len() {
+        let color = colors[start];
+        let mut end = start + 1;
+        while end < colors.len() && colors[end] == color {
+            end += 1;
+        }
+
+        if end - start >= min_coverage {
+            colors[unique_count] = color;
+            unique_count += 1;
+        }
+        start = end;
+    }
+    unique_count
+}
+
 impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManager<H, MH>
     for MultipleColorsManager<H, MH>
 {
@@ -129,8 +302,48 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
     fn create_colors_table(
         path: impl AsRef<Path>,
         color_names: &[String],
+        k: usize,
+        temp_dir: &Path,
+        random_seed: Option<u64>,
     ) -> Self::GlobalColorsTableWriter {
-        ColorsMemMapWriter::new(path, color_names)
+        let sentinels = ColormapSentinels {
+            ubiquitous: is_kmer_colors_capped(),
+            approximate: is_color_sets_subsampled(),
+            shared: get_shared_sequence_policy() == SharedSequencePolicy::FlagShared,
+            first_color: get_shared_sequence_policy() == SharedSequencePolicy::FirstColor,
+        };
+        let color_coverage_output = COLOR_COVERAGE_OUTPUT.load(Ordering::Relaxed);
+        if sentinels.count() > 0 {
+            let mut color_names = color_names.to_vec();
+            if sentinels.ubiquitous {
+                color_names.push(UBIQUITOUS_COLOR_NAME.to_string());
+            }
+            if sentinels.approximate {
+                color_names.push(APPROXIMATE_COLOR_NAME.to_string());
+            }
+            if sentinels.shared {
+                color_names.push(SHARED_COLOR_NAME.to_string());
+            }
+            ColorsMemMapWriter::new(
+                path,
+                &color_names,
+                k,
+                sentinels,
+                temp_dir,
+                random_seed,
+                color_coverage_output,
+            )
+        } else {
+            ColorsMemMapWriter::new(
+                path,
+                color_names,
+                k,
+                sentinels,
+                temp_dir,
+                random_seed,
+                color_coverage_output,
+            )
+        }
     }
 
     fn open_colors_table(_path: impl AsRef<Path>) -> Self::GlobalColorsTableReader {
@@ -152,6 +365,9 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
             kmers_count: 0,
             sequences_count: 0,
             temp_colors_buffer: vec![],
+            temp_color_occurrences: vec![],
+            kmer_coverage: vec![],
+            last_kmer_coverage: vec![],
             temp_dir: temp_dir.to_path_buf(),
             _phantom: PhantomData,
         }
@@ -178,6 +394,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
     fn add_temp_buffer_sequence(
         data: &mut Self::ColorsBufferTempStructure,
         sequence: CompressedRead,
+        multiplicity: u64,
         k: usize,
         m: usize,
         flags: u8,
@@ -205,12 +422,20 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
         } else {
             decr_val as u8
         };
+        // The multiplicity is written only if the kmers are counted more than once
+        let multiplicity_flag = ((multiplicity != 1) as u8) << 1;
 
-        encode_varint_flags::<_, _, typenum::U1>(
+        encode_varint_flags::<_, _, typenum::U2>(
             |b| data.sequences[bucket].buffer.extend_from_slice(b),
             sequence.bases_count() as u64,
-            kmer_length_dist_flag,
+            kmer_length_dist_flag | multiplicity_flag,
         );
+        if multiplicity != 1 {
+            encode_varint(
+                |b| data.sequences[bucket].buffer.extend_from_slice(b),
+                multiplicity,
+            );
+        }
         sequence.copy_to_buffer(&mut data.sequences[bucket].buffer);
         data.sequences[bucket].flush(&data.temp_dir)
     }
@@ -229,9 +454,33 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
         global_colors_table: &Self::GlobalColorsTableWriter,
         data: &mut Self::ColorsBufferTempStructure,
         map: &mut HashMap<MH::HashTypeUnextendable, MapEntry<Self::HashMapTempColorIndex>>,
+        collision_map: &mut HashMap<
+            (MH::HashTypeUnextendable, usize),
+            MapEntry<Self::HashMapTempColorIndex>,
+        >,
+        mut kmer_generation: impl FnMut(MH::HashTypeUnextendable, CompressedRead, bool) -> usize,
         k: usize,
         min_multiplicity: usize,
     ) {
+        let min_color_coverage = MIN_COLOR_COVERAGE.load(Ordering::Relaxed);
+        let color_coverage_output = COLOR_COVERAGE_OUTPUT.load(Ordering::Relaxed);
+        let max_kmer_colors = MAX_KMER_COLORS.load(Ordering::Relaxed);
+        let max_exact_color_set = MAX_EXACT_COLOR_SET.load(Ordering::Relaxed);
+        let shared_sequence_policy = get_shared_sequence_policy();
+        // The sentinel colors are the last ones of the colors table,
+        // in the ubiquitous, approximate and shared order
+        let shared_color = (global_colors_table.colors_count() as ColorIndexType).saturating_sub(1);
+        let approximate_color = if shared_sequence_policy == SharedSequencePolicy::FlagShared {
+            shared_color.saturating_sub(1)
+        } else {
+            shared_color
+        };
+        let ubiquitous_color = if max_exact_color_set != 0 {
+            approximate_color.saturating_sub(1)
+        } else {
+            approximate_color
+        };
+
         for buffer in data.sequences.iter_mut() {
             data.temp_colors_buffer.clear();
 
@@ -242,6 +491,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
 
             let mut last_partition = 0..0;
             let mut last_color = 0;
+            data.last_kmer_coverage.clear();
 
             loop {
                 // TODO: Distinguish between error and no more data
@@ -251,9 +501,14 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
 
                 let color = ColorIndexType::from_ne_bytes(color_buf);
 
-                let (read_length, only_extra_ending) =
-                    decode_varint_flags::<_, typenum::U1>(|| stream.read_u8().ok()).unwrap();
-                let only_extra_ending = if only_extra_ending != 0 { true } else { false };
+                let (read_length, read_flags) =
+                    decode_varint_flags::<_, typenum::U2>(|| stream.read_u8().ok()).unwrap();
+                let only_extra_ending = read_flags & 1 != 0;
+                let multiplicity = if read_flags & 2 != 0 {
+                    decode_varint(|| stream.read_u8().ok()).unwrap() as usize
+                } else {
+                    1
+                };
 
                 let read_bytes_count = (read_length as usize + 3) / 4;
 
@@ -271,8 +526,16 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
 
                 let mut is_first = !only_extra_ending;
 
-                for kmer_hash in hashes.iter() {
-                    let entry = map.get_mut(&kmer_hash.to_unextendable()).unwrap();
+                for (idx, kmer_hash) in hashes.iter_enumerate() {
+                    let hash = kmer_hash.to_unextendable();
+                    let entry = match kmer_generation(
+                        hash,
+                        read.sub_slice(idx..(idx + k)),
+                        kmer_hash.is_forward(),
+                    ) {
+                        0 => map.get_mut(&hash).unwrap(),
+                        generation => collision_map.get_mut(&(hash, generation)).unwrap(),
+                    };
 
                     let is_first = {
                         let tmp = is_first;
@@ -332,31 +595,96 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
                         () => entry.color_index & !VISITED_BIT,
                     };
 
+                    // The color is added once for each time the kmer was counted
                     let col_count = data.temp_colors_buffer[position] as usize;
-                    data.temp_colors_buffer[position] += 1;
+                    data.temp_colors_buffer[position] += multiplicity as ColorIndexType;
 
-                    assert_eq!(data.temp_colors_buffer[position + col_count], 0);
-                    data.temp_colors_buffer[position + col_count] = color;
+                    let added_colors =
+                        (position + col_count)..(position + col_count + multiplicity);
+                    assert!(data.temp_colors_buffer[added_colors.clone()]
+                        .iter()
+                        .all(|slot| *slot == 0));
+                    data.temp_colors_buffer[added_colors.clone()].fill(color);
 
-                    let has_all_colors = (position + col_count + 1)
-                        == data.temp_colors_buffer.len()
-                        || data.temp_colors_buffer[position + col_count + 1] != 0;
+                    let has_all_colors = added_colors.end == data.temp_colors_buffer.len()
+                        || data.temp_colors_buffer[added_colors.end] != 0;
 
                     // All colors were added, let's assign the final color
                     if has_all_colors {
-                        let colors_range = &mut data.temp_colors_buffer
-                            [(position + 1)..(position + col_count + 1)];
+                        let colors_range =
+                            &mut data.temp_colors_buffer[(position + 1)..added_colors.end];
 
                         colors_range.sort_unstable();
 
+                        // The occurrences of the kmer in each color, before the colors are filtered
+                        if color_coverage_output {
+                            data.temp_color_occurrences.clear();
+                            data.temp_color_occurrences.extend(
+                                colors_range
+                                    .iter()
+                                    .dedup_with_count()
+                                    .map(|(count, color)| {
+                                        (*color, count.min(u32::MAX as usize) as u32)
+                                    }),
+                            );
+                        }
+
                         // Get the new partition indexes, start to dedup last element
-                        let new_partition =
-                            (position + 1)..(position + 1 + colors_range.partition_dedup().0.len());
+                        let mut unique_count = if min_color_coverage > 1 {
+                            dedup_covered_colors(colors_range, min_color_coverage)
+                        } else {
+                            colors_range.partition_dedup().0.len()
+                        };
+                        if max_kmer_colors != 0 {
+                            unique_count = cap_kmer_colors(
+                                colors_range,
+                                unique_count,
+                                max_kmer_colors,
+                                ubiquitous_color,
+                            );
+                        }
+                        if max_exact_color_set != 0 {
+                            unique_count = subsample_kmer_colors(
+                                colors_range,
+                                unique_count,
+                                max_exact_color_set,
+                                approximate_color,
+                            );
+                        }
+                        // The ubiquitous kmers have a single color, so they are left unchanged
+                        unique_count = apply_shared_sequence_policy(
+                            colors_range,
+                            unique_count,
+                            shared_sequence_policy,
+                            shared_color,
+                        );
+                        let new_partition = (position + 1)..(position + 1 + unique_count);
 
                         let unique_colors = &data.temp_colors_buffer[new_partition.clone()];
 
                         // Assign the subset color index to the current kmer
-                        if unique_colors != &data.temp_colors_buffer[last_partition.clone()] {
+                        if color_coverage_output {
+                            data.kmer_coverage.clear();
+                            data.kmer_coverage.extend(unique_colors.iter().map(|color| {
+                                data.temp_color_occurrences
+                                    .binary_search_by_key(color, |(color, _)| *color)
+                                    .map(|index| data.temp_color_occurrences[index].1)
+                                    .unwrap_or(0)
+                            }));
+
+                            if unique_colors != &data.temp_colors_buffer[last_partition.clone()]
+                                || data.kmer_coverage != data.last_kmer_coverage
+                            {
+                                last_color = global_colors_table
+                                    .get_id_with_coverage(unique_colors, &data.kmer_coverage);
+                                last_partition = new_partition;
+                                std::mem::swap(
+                                    &mut data.kmer_coverage,
+                                    &mut data.last_kmer_coverage,
+                                );
+                            }
+                        } else if unique_colors != &data.temp_colors_buffer[last_partition.clone()]
+                        {
                             last_color = global_colors_table.get_id(unique_colors);
                             last_partition = new_partition;
                         }
@@ -713,3 +1041,63 @@ impl IdentSequenceWriter for UnitigColorData {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_shared_sequence_policy, cap_kmer_colors, dedup_covered_colors, subsample_kmer_colors,
+        SharedSequencePolicy,
+    };
+
+    #[test]
+    fn stray_colors_are_excluded() {
+        let mut colors = [0, 0, 0, 1, 2, 2];
+        let count = dedup_covered_colors(&mut colors, 2);
+        assert_eq!(&colors[..count], &[0, 2]);
+
+        // A kmer found only in poorly covered colors keeps the most frequent ones
+        let mut colors = [3, 4, 4, 5];
+        let count = dedup_covered_colors(&mut colors, 3);
+        assert_eq!(&colors[..count], &[4]);
+    }
+
+    #[test]
+    fn colors_above_cap_become_ubiquitous() {
+        let mut colors = [0, 1, 2];
+        let count = cap_kmer_colors(&mut colors, 3, 2, 3);
+        assert_eq!(&colors[..count], &[3]);
+
+        let mut colors = [0, 2, 2];
+        let count = cap_kmer_colors(&mut colors, 2, 2, 3);
+        assert_eq!(&colors[..count], &[0, 2]);
+    }
+
+    #[test]
+    fn large_color_sets_are_subsampled() {
+        let mut colors = [0, 1, 2, 3, 4, 4];
+        let count = subsample_kmer_colors(&mut colors, 5, 3, 6);
+        assert_eq!(&colors[..count], &[0, 1, 3, 6]);
+
+        let mut colors = [1, 3, 3];
+        let count = subsample_kmer_colors(&mut colors, 2, 3, 6);
+        assert_eq!(&colors[..count], &[1, 3]);
+    }
+
+    #[test]
+    fn shared_colors_follow_the_policy() {
+        for (policy, expected) in [
+            (SharedSequencePolicy::AllColors, &[1, 2][..]),
+            (SharedSequencePolicy::FirstColor, &[1][..]),
+            (SharedSequencePolicy::FlagShared, &[4][..]),
+        ] {
+            let mut colors = [1, 2];
+            let count = apply_shared_sequence_policy(&mut colors, 2, policy, 4);
+            assert_eq!(&colors[..count], expected);
+
+            // Kmers of a single color are never shared
+            let mut colors = [2];
+            let count = apply_shared_sequence_policy(&mut colors, 1, policy, 4);
+            assert_eq!(&colors[..count], &[2]);
+        }
+    }
+}
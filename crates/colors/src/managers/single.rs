@@ -32,6 +32,9 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
     fn create_colors_table(
         _path: impl AsRef<Path>,
         _color_names: &[String],
+        _k: usize,
+        _temp_dir: &Path,
+        _random_seed: Option<u64>,
     ) -> Self::GlobalColorsTableWriter {
         ()
     }
@@ -67,6 +70,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
     fn add_temp_buffer_sequence(
         _data: &mut Self::ColorsBufferTempStructure,
         _sequence: CompressedRead,
+        _multiplicity: u64,
         _k: usize,
         _m: usize,
         _flags: u8,
@@ -85,6 +89,11 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
         _global_colors_table: &Self::GlobalColorsTableWriter,
         _data: &mut Self::ColorsBufferTempStructure,
         _map: &mut HashMap<MH::HashTypeUnextendable, MapEntry<Self::HashMapTempColorIndex>>,
+        _collision_map: &mut HashMap<
+            (MH::HashTypeUnextendable, usize),
+            MapEntry<Self::HashMapTempColorIndex>,
+        >,
+        _kmer_generation: impl FnMut(MH::HashTypeUnextendable, CompressedRead, bool) -> usize,
         _k: usize,
         _min_multiplicity: usize,
     ) {
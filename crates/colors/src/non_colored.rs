@@ -120,6 +120,9 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
     fn create_colors_table(
         _path: impl AsRef<Path>,
         _color_names: &[String],
+        _k: usize,
+        _temp_dir: &Path,
+        _random_seed: Option<u64>,
     ) -> Self::GlobalColorsTableWriter {
         ()
     }
@@ -153,6 +156,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
     fn add_temp_buffer_sequence(
         _data: &mut Self::ColorsBufferTempStructure,
         _sequence: CompressedRead,
+        _multiplicity: u64,
         _k: usize,
         _m: usize,
         _flags: u8,
@@ -174,6 +178,15 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
             <MH as HashFunctionFactory>::HashTypeUnextendable,
             MapEntry<Self::HashMapTempColorIndex>,
         >,
+        _collision_map: &mut HashMap<
+            (<MH as HashFunctionFactory>::HashTypeUnextendable, usize),
+            MapEntry<Self::HashMapTempColorIndex>,
+        >,
+        _kmer_generation: impl FnMut(
+            <MH as HashFunctionFactory>::HashTypeUnextendable,
+            CompressedRead,
+            bool,
+        ) -> usize,
         _k: usize,
         _min_multiplicity: usize,
     ) {
@@ -3,6 +3,7 @@ use config::ColorIndexType;
 use std::io::Read;
 
 pub mod deserializer;
+pub mod diff;
 pub mod roaring;
 pub mod run_length;
 pub mod serializer;
@@ -1,5 +1,7 @@
 use crate::colors_manager::ColorMapReader;
-use crate::storage::serializer::{ColorsFileHeader, ColorsIndexEntry, ColorsIndexMap};
+use crate::storage::serializer::{
+    ColormapSentinels, ColorsFileHeader, ColorsIndexEntry, ColorsIndexMap, STORAGE_VERSION,
+};
 use crate::storage::ColorsSerializerTrait;
 use config::ColorIndexType;
 use desse::Desse;
@@ -18,6 +20,8 @@ pub struct ColorsDeserializer<DS: ColorsSerializerTrait> {
     current_chunk: ColorsIndexEntry,
     current_chunk_size: ColorIndexType,
     current_index: ColorIndexType,
+    kmer_length: usize,
+    sentinels: ColormapSentinels,
     _phantom: PhantomData<DS>,
 }
 
@@ -33,6 +37,11 @@ impl<DS: ColorsSerializerTrait> ColorsDeserializer<DS> {
 
         let header: ColorsFileHeader = ColorsFileHeader::deserialize_from(&header_buffer);
         assert_eq!(header.magic, DS::MAGIC);
+        assert_eq!(
+            header.version, STORAGE_VERSION,
+            "Unsupported colormap version {}, please rebuild the graph",
+            header.version
+        );
 
         let color_names = if read_color_names {
             let mut compressed_stream = lz4::Decoder::new(BufReader::new(file)).unwrap();
@@ -73,6 +82,8 @@ impl<DS: ColorsSerializerTrait> ColorsDeserializer<DS> {
             current_chunk: first_chunk,
             current_chunk_size,
             current_index: first_chunk.start_index,
+            kmer_length: header.kmer_length as usize,
+            sentinels: header.get_sentinels(),
             _phantom: Default::default(),
         }
     }
@@ -148,4 +159,73 @@ impl<DS: ColorsSerializerTrait> ColorMapReader for ColorsDeserializer<DS> {
     fn colors_subsets_count(&self) -> u64 {
         self.colors_index.subsets_count as u64
     }
+
+    fn kmer_length(&self) -> Option<usize> {
+        Some(self.kmer_length)
+    }
+
+    fn sentinels(&self) -> ColormapSentinels {
+        self.sentinels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::colors_manager::ColorMapReader;
+    use crate::storage::deserializer::ColorsDeserializer;
+    use crate::storage::serializer::{ColormapSentinels, ColorsSerializer};
+    use crate::DefaultColorsSerializer;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn kmer_length_mismatch() {
+        let temp_dir = TestDir::new("kmer_length_mismatch");
+        let colormap_file = temp_dir.path("kmer-length.colors.dat");
+
+        let serializer = ColorsSerializer::<DefaultColorsSerializer>::new(
+            &colormap_file,
+            &["first".to_string(), "second".to_string()],
+            31,
+            Default::default(),
+            None,
+        );
+        serializer.serialize_colors(&[0, 1]);
+        drop(serializer);
+
+        let deserializer = ColorsDeserializer::<DefaultColorsSerializer>::new(&colormap_file, true);
+
+        assert_eq!(deserializer.kmer_length(), Some(31));
+        assert!(deserializer.check_kmer_length(31).is_ok());
+
+        let error = deserializer.check_kmer_length(27).unwrap_err();
+        assert!(error.contains("k=31"), "{}", error);
+        assert!(error.contains("k=27"), "{}", error);
+    }
+
+    #[test]
+    fn sentinels_are_read_from_the_header() {
+        let temp_dir = TestDir::new("sentinels_are_read_from_the_header");
+        let colormap_file = temp_dir.path("sentinels.colors.dat");
+
+        let sentinels = ColormapSentinels {
+            ubiquitous: true,
+            approximate: false,
+            shared: true,
+            first_color: false,
+        };
+        let serializer = ColorsSerializer::<DefaultColorsSerializer>::new(
+            &colormap_file,
+            &["first", "second", "ubiquitous", "shared"].map(|name| name.to_string()),
+            31,
+            sentinels,
+            None,
+        );
+        serializer.serialize_colors(&[0, 1]);
+        drop(serializer);
+
+        let deserializer = ColorsDeserializer::<DefaultColorsSerializer>::new(&colormap_file, true);
+
+        assert_eq!(deserializer.sentinels(), sentinels);
+        assert_eq!(deserializer.input_colors_count(), 2);
+    }
 }
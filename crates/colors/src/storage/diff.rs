@@ -0,0 +1,166 @@
+use crate::colors_manager::ColorMapReader;
+use crate::storage::deserializer::ColorsDeserializer;
+use crate::DefaultColorsSerializer;
+use config::ColorIndexType;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// The differences between an old and a new colormap. The color subsets are matched by index,
+/// and their colors are compared by name, so that the subsets of a colormap with the same colors
+/// in a different order are unchanged
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ColormapDiff {
+    /// The color names found only in the new colormap
+    pub added_colors: Vec<String>,
+    /// The color names found only in the old colormap
+    pub removed_colors: Vec<String>,
+    /// The subset indices found only in the new colormap
+    pub added_subsets: Vec<ColorIndexType>,
+    /// The subset indices found only in the old colormap
+    pub removed_subsets: Vec<ColorIndexType>,
+    /// The subset indices found in both colormaps with different colors
+    pub changed_subsets: Vec<ColorIndexType>,
+}
+
+impl ColormapDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_colors.is_empty()
+            && self.removed_colors.is_empty()
+            && self.added_subsets.is_empty()
+            && self.removed_subsets.is_empty()
+            && self.changed_subsets.is_empty()
+    }
+}
+
+fn color_names(deserializer: &ColorsDeserializer<DefaultColorsSerializer>) -> Vec<String> {
+    (0..deserializer.colors_count())
+        .map(|color| {
+            deserializer
+                .get_color_name(color as ColorIndexType, false)
+                .to_string()
+        })
+        .collect()
+}
+
+/// Compares two colormaps, as the ones of a graph before and after an append or a rebuild.
+/// Both colormaps are streamed once, decoding their common subsets in index order
+pub fn diff_colormaps(
+    old_colormap: impl AsRef<Path>,
+    new_colormap: impl AsRef<Path>,
+) -> ColormapDiff {
+    let mut old_deserializer =
+        ColorsDeserializer::<DefaultColorsSerializer>::new(old_colormap, true);
+    let mut new_deserializer =
+        ColorsDeserializer::<DefaultColorsSerializer>::new(new_colormap, true);
+
+    let old_names = color_names(&old_deserializer);
+    let new_names = color_names(&new_deserializer);
+
+    let mut diff = ColormapDiff::default();
+    {
+        let old_names_set: HashSet<_> = old_names.iter().collect();
+        let new_names_set: HashSet<_> = new_names.iter().collect();
+        diff.added_colors = new_names
+            .iter()
+            .filter(|name| !old_names_set.contains(name))
+            .cloned()
+            .collect();
+        diff.removed_colors = old_names
+            .iter()
+            .filter(|name| !new_names_set.contains(name))
+            .cloned()
+            .collect();
+    }
+
+    let old_subsets_count = old_deserializer.colors_subsets_count() as ColorIndexType;
+    let new_subsets_count = new_deserializer.colors_subsets_count() as ColorIndexType;
+
+    let mut old_colors = vec![];
+    let mut new_colors = vec![];
+    let mut old_subset_names = vec![];
+    let mut new_subset_names = vec![];
+
+    for subset in 0..old_subsets_count.min(new_subsets_count) {
+        old_colors.clear();
+        new_colors.clear();
+        old_deserializer.get_color_mappings(subset, &mut old_colors);
+        new_deserializer.get_color_mappings(subset, &mut new_colors);
+
+        old_subset_names.clear();
+        old_subset_names.extend(old_colors.iter().map(|c| &old_names[*c as usize]));
+        old_subset_names.sort_unstable();
+        new_subset_names.clear();
+        new_subset_names.extend(new_colors.iter().map(|c| &new_names[*c as usize]));
+        new_subset_names.sort_unstable();
+
+        if old_subset_names != new_subset_names {
+            diff.changed_subsets.push(subset);
+        }
+    }
+
+    diff.added_subsets = (old_subsets_count..new_subsets_count).collect();
+    diff.removed_subsets = (new_subsets_count..old_subsets_count).collect();
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff_colormaps;
+    use crate::storage::serializer::ColorsSerializer;
+    use crate::DefaultColorsSerializer;
+    use std::path::PathBuf;
+    use utils::test_dir::TestDir;
+
+    fn write_colormap(
+        temp_dir: &TestDir,
+        name: &str,
+        colors: &[&str],
+        subsets: &[&[u32]],
+    ) -> PathBuf {
+        let colormap_file = temp_dir.path(name);
+        let serializer = ColorsSerializer::<DefaultColorsSerializer>::new(
+            &colormap_file,
+            &colors.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+            31,
+            Default::default(),
+            None,
+        );
+        for subset in subsets {
+            serializer.serialize_colors(subset);
+        }
+        drop(serializer);
+        colormap_file
+    }
+
+    #[test]
+    fn appended_color_is_reported() {
+        let temp_dir = TestDir::new("appended_color_is_reported");
+        let old_colormap = write_colormap(
+            &temp_dir,
+            "diff-old.colors.dat",
+            &["s0", "s1"],
+            &[&[0], &[1], &[0, 1]],
+        );
+        // The appended color adds two subsets after the existing ones, that are unchanged
+        let new_colormap = write_colormap(
+            &temp_dir,
+            "diff-new.colors.dat",
+            &["s0", "s1", "s2"],
+            &[&[0], &[1], &[0, 1], &[2], &[0, 2]],
+        );
+
+        let diff = diff_colormaps(&old_colormap, &new_colormap);
+        assert_eq!(diff.added_colors, vec!["s2".to_string()]);
+        assert!(diff.removed_colors.is_empty());
+        assert_eq!(diff.added_subsets, vec![3, 4]);
+        assert!(diff.removed_subsets.is_empty());
+        assert!(diff.changed_subsets.is_empty());
+
+        let reverse_diff = diff_colormaps(&new_colormap, &old_colormap);
+        assert_eq!(reverse_diff.removed_colors, vec!["s2".to_string()]);
+        assert_eq!(reverse_diff.removed_subsets, vec![3, 4]);
+
+        assert!(diff_colormaps(&old_colormap, &old_colormap).is_empty());
+    }
+}
@@ -5,14 +5,48 @@ use desse::{Desse, DesseSized};
 use io::chunks_writer::ChunksWriter;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
 use std::mem::ManuallyDrop;
 use std::ops::{Deref, DerefMut};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 
-const STORAGE_VERSION: u64 = 1;
+pub(crate) const STORAGE_VERSION: u64 = 3;
+
+/// The sentinel colors added after the input colors of a colormap, in this order,
+/// and the policy used to assign the shared kmers, recorded in the colormap header
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ColormapSentinels {
+    pub ubiquitous: bool,
+    pub approximate: bool,
+    pub shared: bool,
+    /// The shared kmers were assigned only to their first color, so the colors subsets are not exact
+    pub first_color: bool,
+}
+
+impl ColormapSentinels {
+    fn to_flags(self) -> u64 {
+        (self.ubiquitous as u64)
+            | ((self.approximate as u64) << 1)
+            | ((self.shared as u64) << 2)
+            | ((self.first_color as u64) << 3)
+    }
+
+    fn from_flags(flags: u64) -> Self {
+        Self {
+            ubiquitous: flags & 1 != 0,
+            approximate: flags & 2 != 0,
+            shared: flags & 4 != 0,
+            first_color: flags & 8 != 0,
+        }
+    }
+
+    /// The number of sentinel colors, the last ones of the colormap
+    pub fn count(&self) -> usize {
+        self.ubiquitous as usize + self.approximate as usize + self.shared as usize
+    }
+}
 
 #[derive(Debug, Desse, DesseSized, Default)]
 pub(crate) struct ColorsFileHeader {
@@ -23,6 +57,14 @@ pub(crate) struct ColorsFileHeader {
     pub subsets_count: u64,
     pub total_size: u64,
     pub total_uncompressed_size: u64,
+    pub kmer_length: u64,
+    pub sentinels: u64,
+}
+
+impl ColorsFileHeader {
+    pub fn get_sentinels(&self) -> ColormapSentinels {
+        ColormapSentinels::from_flags(self.sentinels)
+    }
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq)]
@@ -37,14 +79,188 @@ pub(crate) struct ColorsIndexMap {
     pub subsets_count: u64,
 }
 
+// Name of the file in the temp directory holding the recovery log of the colormap
+const COLORMAP_RECOVERY_FILE: &str = "colormap-recovery.dat";
+
+/// A record of the recovery log of a colormap, appended in the temp directory while the colormap is written
+#[derive(Serialize, Deserialize)]
+enum ColormapRecoveryRecord {
+    /// Written when the colormap is created
+    Colormap {
+        colormap_file: PathBuf,
+        magic: [u8; 16],
+        colors_count: u64,
+        kmer_length: u64,
+        sentinels: u64,
+    },
+    /// A chunk of color subsets, written after its data is flushed to the colormap
+    Chunk(ColorsIndexEntry),
+    /// All the color subsets were written to the colormap, at the end of the kmers merge
+    Completed {
+        index_position: u64,
+        subsets_count: u64,
+        total_uncompressed_size: u64,
+    },
+}
+
+fn append_recovery_record(log: &mut BufWriter<File>, record: &ColormapRecoveryRecord) {
+    bincode::serialize_into(&mut *log, record).unwrap();
+    log.flush().unwrap();
+}
+
+/// The index and header of a colormap, written after all its color subsets
+struct ColormapFinalization {
+    colormap_file: PathBuf,
+    magic: [u8; 16],
+    colors_count: u64,
+    kmer_length: u64,
+    sentinels: u64,
+    index_position: u64,
+    total_uncompressed_size: u64,
+    index_map: ColorsIndexMap,
+}
+
+impl ColormapFinalization {
+    fn write_to(&self, colors_file: &mut (impl Write + Seek)) -> std::io::Result<()> {
+        colors_file.seek(SeekFrom::Start(self.index_position))?;
+        bincode::serialize_into(&mut *colors_file, &self.index_map)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+        colors_file.flush()?;
+
+        let total_size = colors_file.stream_position()?;
+        colors_file.seek(SeekFrom::Start(0))?;
+
+        colors_file.write_all(
+            &ColorsFileHeader {
+                magic: self.magic,
+                version: STORAGE_VERSION,
+                index_offset: self.index_position,
+                colors_count: self.colors_count,
+                subsets_count: self.index_map.subsets_count,
+                total_size,
+                total_uncompressed_size: self.total_uncompressed_size,
+                kmer_length: self.kmer_length,
+                sentinels: self.sentinels,
+            }
+            .serialize()[..],
+        )?;
+        colors_file.flush()
+    }
+
+    // Replays the recovery log of a colormap, that must include all its color subsets
+    fn read_recovery_log(recovery_file: &Path) -> Result<Self, String> {
+        let mut log = BufReader::new(File::open(recovery_file).map_err(|err| {
+            format!(
+                "Cannot open colormap recovery file {}: {}",
+                recovery_file.display(),
+                err
+            )
+        })?);
+
+        let invalid_log = || format!("Invalid colormap recovery file {}", recovery_file.display());
+
+        let Ok(ColormapRecoveryRecord::Colormap {
+            colormap_file,
+            magic,
+            colors_count,
+            kmer_length,
+            sentinels,
+        }) = bincode::deserialize_from::<_, ColormapRecoveryRecord>(&mut log)
+        else {
+            return Err(invalid_log());
+        };
+
+        let mut pairs = vec![];
+        // A truncated record at the end of the log was being written when the build stopped
+        while let Ok(record) = bincode::deserialize_from::<_, ColormapRecoveryRecord>(&mut log) {
+            match record {
+                ColormapRecoveryRecord::Chunk(entry) => pairs.push(entry),
+                ColormapRecoveryRecord::Completed {
+                    index_position,
+                    subsets_count,
+                    total_uncompressed_size,
+                } => {
+                    pairs.sort();
+                    return Ok(Self {
+                        colormap_file,
+                        magic,
+                        colors_count,
+                        kmer_length,
+                        sentinels,
+                        index_position,
+                        total_uncompressed_size,
+                        index_map: ColorsIndexMap {
+                            pairs,
+                            subsets_count,
+                        },
+                    });
+                }
+                ColormapRecoveryRecord::Colormap { .. } => return Err(invalid_log()),
+            }
+        }
+
+        Err(format!(
+            "The colormap {} is incomplete, the kmers merge stopped after writing {} chunks of color subsets",
+            colormap_file.display(),
+            pairs.len()
+        ))
+    }
+}
+
+/// Writes the index and the header of a colormap from the recovery log kept in the temp directory
+/// of the build (with KEEP_FILES or keep_temp_files_until_success). The log records each chunk of color
+/// subsets as it is written to the colormap and the completion of the kmers merge, so a colormap
+/// can be recovered without merging the kmers again if the build stopped at any point after the merge.
+/// Returns the recovered colormap
+pub fn rebuild_colormap_from_temp(temp_dir: impl AsRef<Path>) -> Result<PathBuf, String> {
+    let finalization =
+        ColormapFinalization::read_recovery_log(&temp_dir.as_ref().join(COLORMAP_RECOVERY_FILE))?;
+
+    let mut colors_file = OpenOptions::new()
+        .write(true)
+        .open(&finalization.colormap_file)
+        .map_err(|err| {
+            format!(
+                "Cannot open colormap {}: {}",
+                finalization.colormap_file.display(),
+                err
+            )
+        })?;
+
+    // Drop any partially written index
+    colors_file
+        .set_len(finalization.index_position)
+        .and_then(|_| finalization.write_to(&mut colors_file))
+        .map_err(|err| {
+            format!(
+                "Cannot write colormap {}: {}",
+                finalization.colormap_file.display(),
+                err
+            )
+        })?;
+
+    Ok(finalization.colormap_file)
+}
+
 pub struct ColorsSerializer<SI: ColorsSerializerTrait> {
+    colormap_file: PathBuf,
     colors_count: u64,
+    kmer_length: u64,
+    sentinels: u64,
     serializer_impl: ManuallyDrop<SI>,
 }
 
 impl<SI: ColorsSerializerTrait> ColorsSerializer<SI> {
-    pub fn new(file: impl AsRef<Path>, color_names: &[String]) -> Self {
-        let mut colormap_file = File::create(file).unwrap();
+    /// Creates a colormap, writing its recovery log in recovery_dir if given.
+    /// The color names include the sentinel colors, if any
+    pub fn new(
+        file: impl AsRef<Path>,
+        color_names: &[String],
+        k: usize,
+        sentinels: ColormapSentinels,
+        recovery_dir: Option<&Path>,
+    ) -> Self {
+        let mut colormap_file = File::create(&file).unwrap();
 
         colormap_file
             .write_all(&ColorsFileHeader::default().serialize()[..])
@@ -63,6 +279,27 @@ impl<SI: ColorsSerializerTrait> ColorsSerializer<SI> {
         };
 
         let file_offset = colormap_file.stream_position().unwrap();
+        let colors_count = color_names.len() as u64;
+
+        let recovery_log = recovery_dir.map(|recovery_dir| {
+            let mut recovery_log =
+                BufWriter::new(File::create(recovery_dir.join(COLORMAP_RECOVERY_FILE)).unwrap());
+            append_recovery_record(
+                &mut recovery_log,
+                &ColormapRecoveryRecord::Colormap {
+                    // Absolute, to recover the colormap from a different working directory
+                    colormap_file: file
+                        .as_ref()
+                        .canonicalize()
+                        .unwrap_or_else(|_| file.as_ref().to_path_buf()),
+                    magic: SI::MAGIC,
+                    colors_count,
+                    kmer_length: k as u64,
+                    sentinels: sentinels.to_flags(),
+                },
+            );
+            recovery_log
+        });
 
         let color_processor = ColorsFlushProcessing {
             colormap_file: Mutex::new((
@@ -71,15 +308,17 @@ impl<SI: ColorsSerializerTrait> ColorsSerializer<SI> {
                     pairs: vec![],
                     subsets_count: 0,
                 },
+                recovery_log,
             )),
             offset: AtomicU64::new(file_offset),
             uncompressed_size: AtomicU64::new(0),
         };
 
-        let colors_count = color_names.len() as u64;
-
         Self {
+            colormap_file: file.as_ref().to_path_buf(),
             colors_count,
+            kmer_length: k as u64,
+            sentinels: sentinels.to_flags(),
             serializer_impl: ManuallyDrop::new(SI::new(
                 color_processor,
                 COLORS_SINGLE_BATCH_SIZE as usize,
@@ -92,15 +331,15 @@ impl<SI: ColorsSerializerTrait> ColorsSerializer<SI> {
         self.serializer_impl.serialize_colors(colors)
     }
 
+    pub fn colors_count(&self) -> u64 {
+        self.colors_count
+    }
+
     pub fn print_stats(&self) {
         self.serializer_impl.print_stats()
     }
 }
 
-fn bincode_serialize_ref<S: Write, D: Serialize>(ser: &mut S, data: &D) {
-    bincode::serialize_into(ser, data).unwrap();
-}
-
 impl<SI: ColorsSerializerTrait> Drop for ColorsSerializer<SI> {
     fn drop(&mut self) {
         let subsets_count = self.serializer_impl.get_subsets_count();
@@ -109,43 +348,47 @@ impl<SI: ColorsSerializerTrait> Drop for ColorsSerializer<SI> {
             unsafe { std::ptr::read(self.serializer_impl.deref() as *const SI).finalize() };
 
         let mut colors_lock = chunks_writer.colormap_file.lock();
-        let (colors_file, index_map) = colors_lock.deref_mut();
-        index_map.pairs.sort();
-        index_map.subsets_count = subsets_count;
-
+        let (colors_file, index_map, recovery_log) = colors_lock.deref_mut();
         colors_file.flush().unwrap();
 
-        let index_position = colors_file.stream_position().unwrap();
-
-        bincode_serialize_ref(colors_file, index_map);
-        colors_file.flush().unwrap();
+        let mut index_map = std::mem::replace(
+            index_map,
+            ColorsIndexMap {
+                pairs: vec![],
+                subsets_count: 0,
+            },
+        );
+        index_map.pairs.sort();
+        index_map.subsets_count = subsets_count;
 
-        let total_size = colors_file.stream_position().unwrap();
-        colors_file.seek(SeekFrom::Start(0)).unwrap();
+        let finalization = ColormapFinalization {
+            colormap_file: self.colormap_file.clone(),
+            magic: SI::MAGIC,
+            colors_count: self.colors_count,
+            kmer_length: self.kmer_length,
+            sentinels: self.sentinels,
+            index_position: colors_file.stream_position().unwrap(),
+            total_uncompressed_size: chunks_writer.uncompressed_size.load(Ordering::Relaxed),
+            index_map,
+        };
 
-        colors_file
-            .write_all(
-                &ColorsFileHeader {
-                    magic: SI::MAGIC,
-                    version: STORAGE_VERSION,
-                    index_offset: index_position,
-                    colors_count: self.colors_count,
+        if let Some(recovery_log) = recovery_log {
+            append_recovery_record(
+                recovery_log,
+                &ColormapRecoveryRecord::Completed {
+                    index_position: finalization.index_position,
                     subsets_count,
-                    total_size,
-                    total_uncompressed_size: chunks_writer
-                        .uncompressed_size
-                        .load(Ordering::Relaxed),
-                }
-                .serialize()[..],
-            )
-            .unwrap();
+                    total_uncompressed_size: finalization.total_uncompressed_size,
+                },
+            );
+        }
 
-        colors_file.flush().unwrap();
+        finalization.write_to(colors_file).unwrap();
     }
 }
 
 pub struct ColorsFlushProcessing {
-    colormap_file: Mutex<(BufWriter<File>, ColorsIndexMap)>,
+    colormap_file: Mutex<(BufWriter<File>, ColorsIndexMap, Option<BufWriter<File>>)>,
     offset: AtomicU64,
     uncompressed_size: AtomicU64,
 }
@@ -202,10 +445,88 @@ impl ChunksWriter for ColorsFlushProcessing {
 
         let file_offset = self.offset.fetch_add(data.len() as u64, Ordering::Relaxed);
 
-        file_lock.0.write_all(data.as_slice()).unwrap();
-        file_lock.1.pairs.push(ColorsIndexEntry {
+        let (colors_file, index_map, recovery_log) = file_lock.deref_mut();
+
+        let entry = ColorsIndexEntry {
             start_index,
             file_offset,
-        });
+        };
+        colors_file.write_all(data.as_slice()).unwrap();
+        index_map.pairs.push(entry);
+
+        // The chunk is recorded only after its data reaches the colormap
+        if let Some(recovery_log) = recovery_log {
+            colors_file.flush().unwrap();
+            append_recovery_record(recovery_log, &ColormapRecoveryRecord::Chunk(entry));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        rebuild_colormap_from_temp, ColorsFileHeader, ColorsSerializer, COLORMAP_RECOVERY_FILE,
+    };
+    use crate::storage::deserializer::ColorsDeserializer;
+    use crate::DefaultColorsSerializer;
+    use desse::DesseSized;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn failed_colormap_write_is_recovered_from_temp() {
+        let test_dir = TestDir::new("failed_colormap_write_is_recovered_from_temp");
+        let temp_dir = test_dir.path("run");
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        let colormap_file = temp_dir.join("recovery.colors.dat");
+
+        let subsets: Vec<Vec<u32>> = (0..1000u32)
+            .map(|i| (0..(i % 7 + 1)).map(|c| c * 3 + i % 5).collect())
+            .collect();
+
+        let serializer = ColorsSerializer::<DefaultColorsSerializer>::new(
+            &colormap_file,
+            &(0..20).map(|c| format!("color{}", c)).collect::<Vec<_>>(),
+            31,
+            Default::default(),
+            Some(&temp_dir),
+        );
+        for subset in &subsets {
+            serializer.serialize_colors(subset);
+        }
+        drop(serializer);
+        let complete_colormap = std::fs::read(&colormap_file).unwrap();
+
+        // Simulate a write failure after the color subsets, with a missing header and a partial index
+        let mut damaged_colormap = complete_colormap.clone();
+        damaged_colormap.truncate(damaged_colormap.len() - 4);
+        damaged_colormap[..ColorsFileHeader::SIZE].fill(0);
+        OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&colormap_file)
+            .unwrap()
+            .write_all(&damaged_colormap)
+            .unwrap();
+
+        let recovered_file = rebuild_colormap_from_temp(&temp_dir).unwrap();
+        assert_eq!(std::fs::read(&recovered_file).unwrap(), complete_colormap);
+
+        let mut deserializer =
+            ColorsDeserializer::<DefaultColorsSerializer>::new(&recovered_file, true);
+        let mut colors = vec![];
+        for (index, subset) in subsets.iter().enumerate() {
+            deserializer.get_color_mappings(index as u32, &mut colors);
+            assert_eq!(&colors, subset);
+        }
+
+        // A build stopped before the end of the kmers merge has no completion record in the log
+        let recovery_file = temp_dir.join(COLORMAP_RECOVERY_FILE);
+        let recovery_log = std::fs::read(&recovery_file).unwrap();
+        std::fs::write(&recovery_file, &recovery_log[..recovery_log.len() - 1]).unwrap();
+        assert!(rebuild_colormap_from_temp(&temp_dir)
+            .unwrap_err()
+            .contains("incomplete"));
     }
 }
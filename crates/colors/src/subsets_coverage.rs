@@ -0,0 +1,138 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use config::ColorIndexType;
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+const SUBSETS_COVERAGE_MAGIC: [u8; 16] = *b"GGCAT_SUBSET_CV_";
+const SUBSETS_COVERAGE_VERSION: u64 = 1;
+
+/// Obtains the standard subsets coverage file path from a colormap file path
+pub fn get_subsets_coverage_file(colormap_file: impl AsRef<Path>) -> PathBuf {
+    colormap_file.as_ref().with_extension("coverage.dat")
+}
+
+/// Writes the coverage of the colors subsets next to the colormap, as the number of occurrences
+/// of each kmer of a subset in each of the subset colors, in the order of the colors of the subset.
+/// The records are written as the subsets are created, so they are not sorted by subset
+pub struct SubsetsCoverageWriter {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl SubsetsCoverageWriter {
+    pub fn create(path: impl AsRef<Path>) -> Self {
+        let mut writer = BufWriter::new(File::create(path.as_ref()).unwrap_or_else(|err| {
+            panic!(
+                "Cannot create subsets coverage file {}: {}",
+                path.as_ref().display(),
+                err
+            )
+        }));
+        writer.write_all(&SUBSETS_COVERAGE_MAGIC).unwrap();
+        writer
+            .write_u64::<LittleEndian>(SUBSETS_COVERAGE_VERSION)
+            .unwrap();
+
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    pub fn write_subset(&self, subset: ColorIndexType, coverage: &[u32]) {
+        let mut writer = self.writer.lock();
+        writer.write_u32::<LittleEndian>(subset).unwrap();
+        writer
+            .write_u32::<LittleEndian>(coverage.len() as u32)
+            .unwrap();
+        for count in coverage {
+            writer.write_u32::<LittleEndian>(*count).unwrap();
+        }
+    }
+}
+
+impl Drop for SubsetsCoverageWriter {
+    fn drop(&mut self) {
+        self.writer.get_mut().flush().unwrap();
+    }
+}
+
+/// The coverage of the colors subsets of a graph, as written while building it, loaded in memory
+pub struct SubsetsCoverage {
+    subsets: HashMap<ColorIndexType, Vec<u32>>,
+}
+
+impl SubsetsCoverage {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|err| {
+            format!(
+                "Cannot open subsets coverage file {}: {}",
+                path.display(),
+                err
+            )
+        })?;
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 16];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| format!("{} is not a subsets coverage file", path.display()))?;
+        if magic != SUBSETS_COVERAGE_MAGIC {
+            return Err(format!("{} is not a subsets coverage file", path.display()));
+        }
+        let version = reader
+            .read_u64::<LittleEndian>()
+            .map_err(|e| e.to_string())?;
+        if version != SUBSETS_COVERAGE_VERSION {
+            return Err(format!(
+                "Unsupported subsets coverage file version {} (expected {})",
+                version, SUBSETS_COVERAGE_VERSION
+            ));
+        }
+
+        let truncated = || format!("The subsets coverage file {} is truncated", path.display());
+
+        let mut subsets = HashMap::new();
+        while let Ok(subset) = reader.read_u32::<LittleEndian>() {
+            let colors_count = reader.read_u32::<LittleEndian>().map_err(|_| truncated())?;
+            let coverage = (0..colors_count)
+                .map(|_| reader.read_u32::<LittleEndian>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| truncated())?;
+            subsets.insert(subset, coverage);
+        }
+
+        Ok(Self { subsets })
+    }
+
+    /// The occurrences of each kmer of the subset in each of its colors, in the order of the subset colors
+    pub fn get(&self, subset: ColorIndexType) -> Option<&[u32]> {
+        self.subsets
+            .get(&subset)
+            .map(|coverage| coverage.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SubsetsCoverage, SubsetsCoverageWriter};
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn subsets_coverage_is_read_back_by_subset() {
+        let temp_dir = TestDir::new("subsets_coverage_is_read_back_by_subset");
+        let path = temp_dir.path("subsets.coverage.dat");
+
+        let writer = SubsetsCoverageWriter::create(&path);
+        writer.write_subset(3, &[2, 5]);
+        writer.write_subset(0, &[1]);
+        drop(writer);
+
+        let coverage = SubsetsCoverage::open(&path).unwrap();
+        assert_eq!(coverage.get(0), Some(&[1][..]));
+        assert_eq!(coverage.get(3), Some(&[2, 5][..]));
+        assert_eq!(coverage.get(1), None);
+    }
+}
@@ -4,6 +4,7 @@ use parallel_processor::buckets::writers::compressed_binary_writer::{
 };
 use parallel_processor::memory_data_size::MemoryDataSize;
 use parallel_processor::memory_fs::file::internal::MemoryFileMode;
+use std::cmp::max;
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::time::Duration;
 
@@ -60,6 +61,9 @@ pub const MAXIMUM_JIT_PROCESSED_BUCKETS: usize = 16;
 
 pub const MAX_INTERMEDIATE_MAP_SIZE: u64 = 1024 * 1024 * 32;
 
+// Bases used to find the most frequent minimizers with the frequency aware ordering
+pub const MINIMIZERS_FREQUENCY_SAMPLE_BASES: usize = 1024 * 1024 * 128;
+
 // Assembler include flags
 pub const READ_FLAG_INCL_BEGIN: u8 = 1 << 0;
 pub const READ_FLAG_INCL_END: u8 = 1 << 1;
@@ -85,6 +89,8 @@ impl SwapPriority {
 
 // Functions depending on global config parameters set at runtime
 pub static KEEP_FILES: AtomicBool = AtomicBool::new(false);
+// Applied from the options of the running build or query
+static KEEP_FILES_UNTIL_SUCCESS: AtomicBool = AtomicBool::new(false);
 pub static INTERMEDIATE_COMPRESSION_LEVEL_SLOW: AtomicU32 = AtomicU32::new(3);
 pub static INTERMEDIATE_COMPRESSION_LEVEL_FAST: AtomicU32 = AtomicU32::new(0);
 pub static PREFER_MEMORY: AtomicBool = AtomicBool::new(false);
@@ -97,9 +103,95 @@ pub fn get_memory_mode(swap_priority: usize) -> MemoryFileMode {
     }
 }
 
+/// Keeps the temporary files of the running build or query until its end, as the readers cannot defer
+/// the removal of the files to the end of a successful run
+pub fn set_keep_files_until_success(keep_files_until_success: bool) {
+    KEEP_FILES_UNTIL_SUCCESS.store(keep_files_until_success, Ordering::Relaxed);
+}
+
+/// True if the temporary files should not be removed as soon as they are consumed
+pub fn keep_temp_files() -> bool {
+    KEEP_FILES.load(Ordering::Relaxed) || KEEP_FILES_UNTIL_SUCCESS.load(Ordering::Relaxed)
+}
+
 pub fn get_compression_level_info() -> CompressionLevelInfo {
     CompressionLevelInfo {
         fast_disk: INTERMEDIATE_COMPRESSION_LEVEL_FAST.load(Ordering::Relaxed),
         slow_disk: INTERMEDIATE_COMPRESSION_LEVEL_SLOW.load(Ordering::Relaxed),
     }
 }
+
+/// The intermediate compression levels saved before a build or query changes them, restored when dropped
+/// so that the levels given or selected for a run never leak into the next ones
+pub struct SavedCompressionLevels {
+    fast_disk: u32,
+    slow_disk: u32,
+}
+
+impl SavedCompressionLevels {
+    pub fn save() -> Self {
+        Self {
+            fast_disk: INTERMEDIATE_COMPRESSION_LEVEL_FAST.load(Ordering::Relaxed),
+            slow_disk: INTERMEDIATE_COMPRESSION_LEVEL_SLOW.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Drop for SavedCompressionLevels {
+    fn drop(&mut self) {
+        INTERMEDIATE_COMPRESSION_LEVEL_FAST.store(self.fast_disk, Ordering::Relaxed);
+        INTERMEDIATE_COMPRESSION_LEVEL_SLOW.store(self.slow_disk, Ordering::Relaxed);
+    }
+}
+
+/// The settings shared by all the phases of a single build or query, passed down from its options
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PipelineSettings {
+    /// The threads used to prefetch the input and intermediate files of each step, so that I/O and compute
+    /// can overlap on slow disks. With None they are derived from the total threads count of each step
+    pub io_threads_count: Option<usize>,
+    /// The threads used to process the reads and the buckets of each step.
+    /// With None they are derived from the total threads count of each step
+    pub compute_threads_count: Option<usize>,
+    /// The maximum number of files that the run can keep open at the same time, None for no limit
+    pub max_open_files: Option<usize>,
+    /// The seed used to derive the seeds of all the randomized components.
+    /// With None each run uses different random values
+    pub random_seed: Option<u64>,
+    /// Fails on the input records with characters that are not nucleotides or IUPAC codes,
+    /// instead of skipping them with a warning
+    pub strict_input: bool,
+    /// Enables the diagnostic timers of the sub-phases within a phase
+    pub subphase_timing: bool,
+}
+
+impl PipelineSettings {
+    /// The threads reading the files of a step, the I/O threads that do not flush the buckets,
+    /// or the given default derived from the total threads count if the I/O threads count is not set
+    pub fn read_threads_count(&self, default: usize) -> usize {
+        match self.io_threads_count {
+            None => default,
+            Some(threads_count) => {
+                let threads_count = max(1, threads_count);
+                max(1, threads_count - max(1, threads_count / 4))
+            }
+        }
+    }
+
+    /// The compute threads count, or the given default derived from the total threads count if not set
+    pub fn compute_threads_count(&self, default: usize) -> usize {
+        match self.compute_threads_count {
+            None => default,
+            Some(threads_count) => max(1, threads_count),
+        }
+    }
+}
+
+/// The threads flushing the buckets to disk for the given I/O threads count, a quarter of them,
+/// or the given default derived from the total threads count if the I/O threads count is not set
+pub fn flush_threads_count(io_threads_count: Option<usize>, default: usize) -> usize {
+    match io_threads_count {
+        None => default,
+        Some(threads_count) => max(1, threads_count / 4),
+    }
+}
@@ -3,16 +3,18 @@ use colors::bundles::graph_querying::ColorBundleGraphQuerying;
 use colors::colors_manager::{ColorMapReader, ColorsManager, ColorsMergeManager};
 use colors::DefaultColorsSerializer;
 use config::{
-    ColorIndexType, INTERMEDIATE_COMPRESSION_LEVEL_FAST, INTERMEDIATE_COMPRESSION_LEVEL_SLOW,
+    ColorIndexType, PipelineSettings, SavedCompressionLevels, INTERMEDIATE_COMPRESSION_LEVEL_FAST,
+    INTERMEDIATE_COMPRESSION_LEVEL_SLOW,
 };
 use io::compute_stats_from_input_blocks;
+use io::open_files_limit::limit_buckets_count_log;
 use io::sequences_stream::general::GeneralSequenceBlockData;
 use parallel_processor::memory_fs::MemoryFs;
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use pipeline::dumper_colormap_reading::colormap_reading;
 use std::fs::remove_file;
 use std::path::PathBuf;
 use std::sync::atomic::Ordering;
+use utils::phase_log::init_phases;
 
 mod pipeline;
 
@@ -39,25 +41,34 @@ pub fn dump_unitigs(
     threads_count: usize,
     single_thread_output_function: bool,
     default_compression_level: Option<u32>,
+    settings: PipelineSettings,
     output_function: impl Fn(&[u8], &[ColorIndexType], bool) + Send + Sync,
 ) {
     let temp_dir = temp_dir.unwrap_or(PathBuf::new());
 
-    PHASES_TIMES_MONITOR.write().init();
+    init_phases();
 
     let color_map = <ColorBundleGraphQuerying as ColorsManager>::ColorsMergeManagerType::<
         hashes::cn_nthash::CanonicalNtHashIteratorFactory,
         hashes::cn_rkhash::u128::CanonicalRabinKarpHashFactory,
     >::open_colors_table(graph_input.with_extension("colors.dat"));
 
+    if let Err(err) = color_map.check_kmer_length(k) {
+        panic!("{}", err);
+    }
+
     // TODO: Support GFA input
     let file_stats = compute_stats_from_input_blocks(&[GeneralSequenceBlockData::FASTA((
         graph_input.clone(),
         None,
     ))]);
 
-    let buckets_count_log = buckets_count_log.unwrap_or_else(|| file_stats.best_buckets_count_log);
+    let buckets_count_log = limit_buckets_count_log(
+        buckets_count_log.unwrap_or_else(|| file_stats.best_buckets_count_log),
+        settings.max_open_files,
+    );
 
+    let _saved_compression_levels = SavedCompressionLevels::save();
     if let Some(default_compression_level) = default_compression_level {
         INTERMEDIATE_COMPRESSION_LEVEL_SLOW.store(default_compression_level, Ordering::Relaxed);
         INTERMEDIATE_COMPRESSION_LEVEL_FAST.store(default_compression_level, Ordering::Relaxed);
@@ -73,6 +84,7 @@ pub fn dump_unitigs(
         k,
         m,
         color_map.colors_subsets_count(),
+        settings,
     );
     let _ = remove_file(buckets_stats);
 
@@ -2,8 +2,8 @@ use byteorder::ReadBytesExt;
 use colors::colors_manager::color_types::SingleKmerColorDataType;
 use colors::colors_manager::ColorsManager;
 use config::{
-    get_compression_level_info, get_memory_mode, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
-    DEFAULT_PREFETCH_AMOUNT, KEEP_FILES, MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
+    get_compression_level_info, get_memory_mode, keep_temp_files, SwapPriority,
+    DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
 };
 use io::concurrent::temp_reads::extra_data::{SequenceExtraData, SequenceExtraDataOwned};
 use io::varint::{decode_varint, encode_varint, VARINT_MAX_SIZE};
@@ -14,7 +14,7 @@ use parallel_processor::buckets::writers::compressed_binary_writer::CompressedBi
 use parallel_processor::buckets::MultiThreadBuckets;
 use parallel_processor::fast_smart_bucket_sort::{fast_smart_radix_sort, SortKey};
 use parallel_processor::memory_fs::RemoveFileMode;
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
+use utils::phase_log::start_phase;
 use parallel_processor::utils::scoped_thread_local::ScopedThreadLocal;
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
@@ -81,9 +81,7 @@ pub fn counters_sorting<CX: ColorsManager>(
     output_file: PathBuf,
     query_kmers_count: &[u64],
 ) -> Vec<PathBuf> {
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase("phase: counters sorting".to_string());
+    start_phase("phase: counters sorting".to_string());
 
     let buckets_count = file_counters_inputs.len();
 
@@ -131,7 +129,7 @@ pub fn counters_sorting<CX: ColorsManager>(
             LockFreeBinaryReader::new(
                 input,
                 RemoveFileMode::Remove {
-                    remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                    remove_fs: !keep_temp_files(),
                 },
                 DEFAULT_PREFETCH_AMOUNT,
             )
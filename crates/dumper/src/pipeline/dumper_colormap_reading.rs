@@ -3,7 +3,7 @@ use colors::colors_manager::color_types::SingleKmerColorDataType;
 use colors::colors_manager::ColorsManager;
 use colors::storage::deserializer::ColorsDeserializer;
 use colors::storage::ColorsSerializerTrait;
-use config::{ColorIndexType, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES};
+use config::{keep_temp_files, ColorIndexType, DEFAULT_PREFETCH_AMOUNT};
 use io::compressed_read::CompressedReadIndipendent;
 use io::concurrent::temp_reads::creads_utils::CompressedReadsBucketDataSerializer;
 use nightly_quirks::slice_group_by::SliceGroupBy;
@@ -11,13 +11,12 @@ use parallel_processor::buckets::readers::compressed_binary_reader::CompressedBi
 use parallel_processor::buckets::readers::BucketReader;
 use parallel_processor::fast_smart_bucket_sort::{fast_smart_radix_sort, FastSortable, SortKey};
 use parallel_processor::memory_fs::RemoveFileMode;
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use parallel_processor::utils::scoped_thread_local::ScopedThreadLocal;
 use parking_lot::Mutex;
 use rayon::prelude::*;
 use std::marker::PhantomData;
 use std::path::PathBuf;
-use std::sync::atomic::Ordering;
+use utils::phase_log::start_phase;
 
 pub fn colormap_reading<
     CX: ColorsManager<SingleKmerColorDataType = ColorIndexType>,
@@ -28,9 +27,7 @@ pub fn colormap_reading<
     single_thread_output_function: bool,
     output_function: impl Fn(&[u8], &[ColorIndexType], bool) + Send + Sync,
 ) {
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase("phase: colormap reading".to_string());
+    start_phase("phase: colormap reading".to_string());
 
     let tlocal_colormap_decoder =
         ScopedThreadLocal::new(move || ColorsDeserializer::<CD>::new(&colormap_file, false));
@@ -48,7 +45,7 @@ pub fn colormap_reading<
         CompressedBinaryReader::new(
             input,
             RemoveFileMode::Remove {
-                remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                remove_fs: !keep_temp_files(),
             },
             DEFAULT_PREFETCH_AMOUNT,
         )
@@ -3,7 +3,7 @@ use colors::colors_manager::color_types::{
 };
 use colors::colors_manager::{ColorsManager, MinimizerBucketingSeqColorData};
 use colors::parsers::{SequenceIdent, SingleSequenceInfo};
-use config::BucketIndexType;
+use config::{BucketIndexType, PipelineSettings};
 use io::concurrent::temp_reads::extra_data::{
     HasEmptyExtraBuffer, SequenceExtraDataConsecutiveCompression,
     SequenceExtraDataTempBufferManagement,
@@ -16,12 +16,12 @@ use minimizer_bucketing::{
     MinimizerBucketingExecutorFactory, MinimizerInputSequence,
 };
 use parallel_processor::fast_smart_bucket_sort::FastSortable;
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use std::io::{Read, Write};
 use std::marker::PhantomData;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use utils::phase_log::{log_warning, start_phase};
 
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub struct DumperKmersReferenceData<
@@ -166,11 +166,11 @@ impl<CX: ColorsManager> MinimizerBucketingExecutor<DumperMinimizerBucketingExecu
                 if CX::COLORS_ENABLED
                     && (color.debug_count() != sequence.seq.len() - self.global_data.k + 1)
                 {
-                    println!(
+                    log_warning(format!(
                         "WARN: Sequence does not have enough colors, please check matching k size:\n{}\n{}",
                         std::str::from_utf8(sequence.ident_data).unwrap(),
                         std::str::from_utf8(sequence.seq).unwrap()
-                    );
+                    ));
                 }
 
                 Some(ReadData { colors: color })
@@ -254,10 +254,9 @@ pub fn minimizer_bucketing<CX: ColorsManager>(
     k: usize,
     m: usize,
     colors_count: u64,
+    settings: PipelineSettings,
 ) -> (Vec<PathBuf>, PathBuf) {
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase("phase: unitigs reorganization".to_string());
+    start_phase("phase: unitigs reorganization".to_string());
 
     let input_files = vec![((graph_file, None), ())];
 
@@ -278,5 +277,6 @@ pub fn minimizer_bucketing<CX: ColorsManager>(
         None,
         CX::COLORS_ENABLED,
         k,
+        settings,
     )
 }
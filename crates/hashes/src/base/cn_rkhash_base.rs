@@ -63,6 +63,10 @@ pub struct CanonicalRabinKarpHashIterator<N: HashableSequence> {
 
 impl<N: HashableSequence> CanonicalRabinKarpHashIterator<N> {
     pub fn new(seq: N, k: usize) -> Result<CanonicalRabinKarpHashIterator<N>, &'static str> {
+        if k > seq.bases_count() {
+            return Err("K out of range!");
+        }
+
         let mut fh: HashIntegerType = 0;
         let mut bw: HashIntegerType = 0;
         for i in 0..(k - 1) {
@@ -293,11 +297,35 @@ impl HashFunctionFactory for CanonicalRabinKarpHashFactory {
 
 #[cfg(test)]
 mod tests {
-    use super::CanonicalRabinKarpHashFactory;
-    use crate::tests::test_hash_function;
+    use super::{
+        bkw_l, fwd_l, CanonicalRabinKarpHashFactory, CanonicalRabinKarpHashIterator,
+        HashIntegerType, MULTIPLIER,
+    };
+    use crate::tests::{test_hash_function, test_single_kmer_reads};
 
     #[test]
     fn cn_rkhash_test() {
         test_hash_function::<CanonicalRabinKarpHashFactory>(&(2..4096).collect::<Vec<_>>(), true);
     }
+
+    #[test]
+    fn cn_rkhash_single_kmer_reads() {
+        test_single_kmer_reads::<CanonicalRabinKarpHashFactory>(
+            &[2, 3, 15, 31, 32, 33, 63, 64, 65, 255],
+            true,
+            Some(&|kmer: &[u8]| {
+                // The reverse strand hash has the complement of the last base as the first one
+                let forward = kmer.iter().fold(0 as HashIntegerType, |hash, base| {
+                    hash.wrapping_mul(MULTIPLIER).wrapping_add(fwd_l(*base))
+                });
+                let reverse = kmer.iter().rev().fold(0 as HashIntegerType, |hash, base| {
+                    hash.wrapping_mul(MULTIPLIER).wrapping_add(bkw_l(*base))
+                });
+                forward.min(reverse)
+            }),
+        );
+
+        // Reads shorter than k have no kmers to read
+        assert!(CanonicalRabinKarpHashIterator::new(&[0u8, 1, 2, 3][..], 5).is_err());
+    }
 }
@@ -1,7 +1,6 @@
 use crate::{ExtendableHashTraitType, HashFunction, HashFunctionFactory, HashableSequence};
 use config::BucketIndexType;
 use dynamic_dispatch::dynamic_dispatch;
-use std::cmp::min;
 use std::mem::size_of;
 
 pub struct CanonicalSeqHashIterator<N: HashableSequence> {
@@ -88,12 +87,21 @@ impl ExtendableHashTraitType for ExtCanonicalSeqHash {
 
     #[inline(always)]
     fn to_unextendable(self) -> Self::HashTypeUnextendable {
-        min(self.0, self.1)
+        if self.is_forward() {
+            self.0
+        } else {
+            self.1
+        }
     }
 
     #[inline(always)]
     fn is_forward(&self) -> bool {
-        self.0 < self.1
+        // Resolved at compile time, the lexicographic variant is a separate hash factory
+        if LEXICOGRAPHIC {
+            lexicographic_key(self.0) < lexicographic_key(self.1)
+        } else {
+            self.0 < self.1
+        }
     }
 
     fn is_rc_symmetric(&self) -> bool {
@@ -217,11 +225,23 @@ fn xrc(base: HashIntegerType) -> HashIntegerType {
     base ^ 2
 }
 
+// Maps the bases to the lexicographic order A < C < G < T and moves the first base to the most significant
+// bits, so that the keys of the two strands of a kmer compare as their sequences
+#[inline(always)]
+fn lexicographic_key(hash: HashIntegerType) -> HashIntegerType {
+    const LOW_BITS: HashIntegerType = HashIntegerType::MAX / 3;
+    // A=0, C=1, T=2, G=3 => A=0, C=1, G=2, T=3
+    let ranks = hash ^ ((hash >> 1) & LOW_BITS);
+    // The unused high bases are zero in both the strands, and they stay equal after the reversal
+    let reversed = ranks.reverse_bits();
+    ((reversed >> 1) & LOW_BITS) | ((reversed & LOW_BITS) << 1)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::CanonicalSeqHashFactory;
-    use super::HashIntegerType;
-    use crate::tests::test_hash_function;
+    use super::{xrc, CanonicalSeqHashFactory};
+    use super::{HashIntegerType, LEXICOGRAPHIC};
+    use crate::tests::{test_hash_function, test_single_kmer_reads};
     use std::mem::size_of;
 
     #[test]
@@ -231,4 +251,37 @@ mod tests {
             true,
         );
     }
+
+    #[test]
+    fn cn_seqhash_single_kmer_reads() {
+        test_single_kmer_reads::<CanonicalSeqHashFactory>(
+            &(2..=(size_of::<HashIntegerType>() * 4)).collect::<Vec<_>>(),
+            true,
+            Some(&|kmer: &[u8]| {
+                let (forward, reverse) =
+                    kmer.iter()
+                        .enumerate()
+                        .fold((0, 0), |(forward, reverse), (i, base)| {
+                            let base = *base as HashIntegerType;
+                            (
+                                forward | (base << (i * 2)),
+                                reverse | (xrc(base) << ((kmer.len() - 1 - i) * 2)),
+                            )
+                        });
+                if LEXICOGRAPHIC {
+                    // A < C < G < T, with A=0, C=1, T=2, G=3
+                    let rank = |base: u8| [0, 1, 3, 2][base as usize];
+                    let forward_ranks = kmer.iter().map(|base| rank(*base));
+                    let reverse_ranks = kmer.iter().rev().map(|base| rank(*base ^ 2));
+                    if forward_ranks.le(reverse_ranks) {
+                        forward
+                    } else {
+                        reverse
+                    }
+                } else {
+                    forward.min(reverse)
+                }
+            }),
+        );
+    }
 }
@@ -38,6 +38,10 @@ pub struct ForwardRabinKarpHashIterator<N: HashableSequence> {
 
 impl<N: HashableSequence> ForwardRabinKarpHashIterator<N> {
     pub fn new(seq: N, k: usize) -> Result<ForwardRabinKarpHashIterator<N>, &'static str> {
+        if k > seq.bases_count() {
+            return Err("K out of range!");
+        }
+
         let mut fh = 0 as HashIntegerType;
         for i in 0..(k - 1) {
             fh = fh
@@ -240,11 +244,30 @@ impl HashFunctionFactory for ForwardRabinKarpHashFactory {
 
 #[cfg(test)]
 mod tests {
-    use super::ForwardRabinKarpHashFactory;
-    use crate::tests::test_hash_function;
+    use super::{
+        fwd_l, ForwardRabinKarpHashFactory, ForwardRabinKarpHashIterator, HashIntegerType,
+        MULTIPLIER,
+    };
+    use crate::tests::{test_hash_function, test_single_kmer_reads};
 
     #[test]
     fn fw_rkhash_test() {
         test_hash_function::<ForwardRabinKarpHashFactory>(&(2..4096).collect::<Vec<_>>(), false);
     }
+
+    #[test]
+    fn fw_rkhash_single_kmer_reads() {
+        test_single_kmer_reads::<ForwardRabinKarpHashFactory>(
+            &[2, 3, 15, 31, 32, 33, 63, 64, 65, 255],
+            false,
+            Some(&|kmer: &[u8]| {
+                kmer.iter().fold(0 as HashIntegerType, |hash, base| {
+                    hash.wrapping_mul(MULTIPLIER).wrapping_add(fwd_l(*base))
+                })
+            }),
+        );
+
+        // Reads shorter than k have no kmers to read
+        assert!(ForwardRabinKarpHashIterator::new(&[0u8, 1, 2, 3][..], 5).is_err());
+    }
 }
@@ -196,7 +196,7 @@ mod tests {
 
     use super::ForwardSeqHashFactory;
     use super::HashIntegerType;
-    use crate::tests::test_hash_function;
+    use crate::tests::{test_hash_function, test_single_kmer_reads};
     use std::mem::size_of;
 
     #[test]
@@ -206,4 +206,17 @@ mod tests {
             false,
         );
     }
+
+    #[test]
+    fn fw_seqhash_single_kmer_reads() {
+        test_single_kmer_reads::<ForwardSeqHashFactory>(
+            &(2..=(size_of::<HashIntegerType>() * 4)).collect::<Vec<_>>(),
+            false,
+            Some(&|kmer: &[u8]| {
+                kmer.iter().enumerate().fold(0, |hash, (i, base)| {
+                    hash | ((*base as HashIntegerType) << (i * 2))
+                })
+            }),
+        );
+    }
 }
@@ -0,0 +1,99 @@
+/// The canonicalization semantics of the kmers, to build graphs directly comparable with the ones built
+/// by other tools. In all the modes the kmers containing a base other than A, C, G or T (N and the IUPAC
+/// ambiguity codes) are skipped, as the input sequences are split on them, and the lowercase bases are
+/// read as uppercase ones, matching both Bifrost and Cuttlefish. The modes differ in the strand of a kmer
+/// chosen as the canonical one. They apply only to the sequence hash, used by default with k <= 64,
+/// selecting the hash factory (cn_seqhash or cn_lex_seqhash) when the build is dispatched
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CanonicalCompatibility {
+    /// The canonical kmer has the smallest 2-bit encoding between the two strands,
+    /// with A=0, C=1, T=2, G=3 and the first base in the least significant bits
+    Ggcat = 0,
+    /// The canonical kmer is the lexicographically smallest between the kmer
+    /// and its reverse complement (A < C < G < T), as Kmer::rep in Bifrost
+    Bifrost = 1,
+    /// The canonical kmer is the lexicographically smallest between the kmer
+    /// and its reverse complement (A < C < G < T), as Kmer::canonical in Cuttlefish
+    Cuttlefish = 2,
+}
+
+impl CanonicalCompatibility {
+    /// If the canonical strand is the lexicographically smallest one
+    #[inline(always)]
+    pub fn is_lexicographic(&self) -> bool {
+        match self {
+            CanonicalCompatibility::Ggcat => false,
+            CanonicalCompatibility::Bifrost | CanonicalCompatibility::Cuttlefish => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CanonicalCompatibility;
+    use crate::{
+        cn_lex_seqhash, cn_seqhash, ExtendableHashTraitType, HashFunction, HashFunctionFactory,
+    };
+    use utils::Utils;
+
+    // The canonical kmers of each tool, as (kmer, ggcat, bifrost, cuttlefish) lines,
+    // see test-data/README.md for how they were generated
+    const CANONICAL_KMERS: &str = include_str!("../test-data/canonical_kmers.tsv");
+
+    fn canonical_kmer(kmer: &[u8], compatibility: CanonicalCompatibility) -> Vec<u8> {
+        let compressed: Vec<_> = kmer.iter().map(|b| Utils::compress_base(*b)).collect();
+        let k = kmer.len();
+        let hash = match (compatibility.is_lexicographic(), k <= 32) {
+            (false, true) => cn_seqhash::u64::CanonicalSeqHashFactory::new(&compressed[..], k)
+                .iter()
+                .next()
+                .unwrap()
+                .to_unextendable() as u128,
+            (false, false) => cn_seqhash::u128::CanonicalSeqHashFactory::new(&compressed[..], k)
+                .iter()
+                .next()
+                .unwrap()
+                .to_unextendable(),
+            (true, true) => cn_lex_seqhash::u64::CanonicalSeqHashFactory::new(&compressed[..], k)
+                .iter()
+                .next()
+                .unwrap()
+                .to_unextendable() as u128,
+            (true, false) => cn_lex_seqhash::u128::CanonicalSeqHashFactory::new(&compressed[..], k)
+                .iter()
+                .next()
+                .unwrap()
+                .to_unextendable(),
+        };
+
+        (0..kmer.len())
+            .map(|i| Utils::decompress_base(((hash >> (i * 2)) & 0x3) as u8))
+            .collect()
+    }
+
+    #[test]
+    fn canonical_kmers_match_the_reference_tools() {
+        for line in CANONICAL_KMERS.lines().filter(|l| !l.starts_with('#')) {
+            let fields: Vec<_> = line.split('\t').collect();
+            let kmer = fields[0].as_bytes();
+
+            for (compatibility, expected) in [
+                (CanonicalCompatibility::Ggcat, fields[1]),
+                (CanonicalCompatibility::Bifrost, fields[2]),
+                (CanonicalCompatibility::Cuttlefish, fields[3]),
+            ] {
+                // The kmer length is not supported by the tool
+                if expected == "-" {
+                    continue;
+                }
+                assert_eq!(
+                    std::str::from_utf8(&canonical_kmer(kmer, compatibility)).unwrap(),
+                    expected,
+                    "kmer: {} compatibility: {:?}",
+                    fields[0],
+                    compatibility
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,26 @@
+//! The canonical sequence hash choosing the lexicographically smallest strand of each kmer
+//! (A < C < G < T) as the canonical one, as Bifrost and Cuttlefish do
+
+pub mod u16 {
+    type HashIntegerType = u16;
+    const LEXICOGRAPHIC: bool = true;
+    include!("base/cn_seqhash_base.rs");
+}
+
+pub mod u32 {
+    type HashIntegerType = u32;
+    const LEXICOGRAPHIC: bool = true;
+    include!("base/cn_seqhash_base.rs");
+}
+
+pub mod u64 {
+    type HashIntegerType = u64;
+    const LEXICOGRAPHIC: bool = true;
+    include!("base/cn_seqhash_base.rs");
+}
+
+pub mod u128 {
+    type HashIntegerType = u128;
+    const LEXICOGRAPHIC: bool = true;
+    include!("base/cn_seqhash_base.rs");
+}
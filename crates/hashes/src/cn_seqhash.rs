@@ -1,19 +1,23 @@
 pub mod u16 {
     type HashIntegerType = u16;
+    const LEXICOGRAPHIC: bool = false;
     include!("base/cn_seqhash_base.rs");
 }
 
 pub mod u32 {
     type HashIntegerType = u32;
+    const LEXICOGRAPHIC: bool = false;
     include!("base/cn_seqhash_base.rs");
 }
 
 pub mod u64 {
     type HashIntegerType = u64;
+    const LEXICOGRAPHIC: bool = false;
     include!("base/cn_seqhash_base.rs");
 }
 
 pub mod u128 {
     type HashIntegerType = u128;
+    const LEXICOGRAPHIC: bool = false;
     include!("base/cn_seqhash_base.rs");
 }
@@ -0,0 +1,394 @@
+use crate::UnextendableHashTraitType;
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BinaryHeap, HashSet};
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter, Write};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+/// Where a set of canonical kmer hashes is stored
+#[derive(Clone, Debug, PartialEq)]
+pub enum KmerSetBackend {
+    /// An in-memory hash set, exact
+    InMemory,
+    /// A sorted index file searched from disk, exact. The hashes are sorted in runs of at most
+    /// max_memory bytes while the set is built
+    OnDiskSorted {
+        index_file: PathBuf,
+        max_memory: usize,
+    },
+    /// A Bloom filter, approximate with the given false positive rate and without false negatives
+    Bloom { false_positive_rate: f64 },
+}
+
+impl KmerSetBackend {
+    /// Chooses the exact backend for the expected number of kmers that fits the memory budget,
+    /// keeping the set in memory if possible and in the given index file otherwise
+    pub fn for_memory_budget<T>(
+        expected_kmers: usize,
+        max_memory: usize,
+        index_file: impl AsRef<Path>,
+    ) -> Self {
+        // The hash sets keep some free capacity besides the hashes
+        let in_memory_size = expected_kmers * std::mem::size_of::<T>() * 2;
+        if in_memory_size <= max_memory {
+            KmerSetBackend::InMemory
+        } else {
+            KmerSetBackend::OnDiskSorted {
+                index_file: index_file.as_ref().to_path_buf(),
+                max_memory,
+            }
+        }
+    }
+}
+
+fn bloom_hash<T: Hash>(hash: &T, seed: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    hash.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A Bloom filter of kmer hashes, with double hashing to derive its hash functions
+pub struct KmerBloomFilter {
+    bits: Vec<u64>,
+    bits_count: u64,
+    hashes_count: u32,
+}
+
+impl KmerBloomFilter {
+    pub fn new(expected_kmers: usize, false_positive_rate: f64) -> Self {
+        let expected_kmers = expected_kmers.max(1) as f64;
+        let false_positive_rate = false_positive_rate.clamp(1e-9, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+
+        let bits_count = ((-expected_kmers * false_positive_rate.ln()) / (ln2 * ln2))
+            .ceil()
+            .max(64.0) as u64;
+        let hashes_count = ((bits_count as f64 / expected_kmers) * ln2)
+            .round()
+            .clamp(1.0, 16.0) as u32;
+
+        Self {
+            bits: vec![0; bits_count.div_ceil(64) as usize],
+            bits_count,
+            hashes_count,
+        }
+    }
+
+    fn bit_positions<T: Hash>(&self, hash: &T) -> impl Iterator<Item = u64> {
+        let first = bloom_hash(hash, 0);
+        let second = bloom_hash(hash, 1) | 1;
+        let bits_count = self.bits_count;
+        (0..self.hashes_count as u64)
+            .map(move |i| first.wrapping_add(i.wrapping_mul(second)) % bits_count)
+    }
+
+    pub fn insert<T: Hash>(&mut self, hash: &T) {
+        for position in self.bit_positions(hash) {
+            self.bits[(position / 64) as usize] |= 1 << (position % 64);
+        }
+    }
+
+    pub fn contains<T: Hash>(&self, hash: &T) -> bool {
+        self.bit_positions(hash)
+            .all(|position| self.bits[(position / 64) as usize] & (1 << (position % 64)) != 0)
+    }
+}
+
+// The largest serialized hash, of a 128 bits hash
+const MAX_ENTRY_SIZE: usize = 16;
+
+/// A sorted file of kmer hashes, searched with a binary search without loading it in memory.
+/// The entries are read at their offsets, so the file can be searched by multiple threads at once
+pub struct SortedKmersFile<T> {
+    file: File,
+    entry_size: u64,
+    entries_count: u64,
+    _phantom: std::marker::PhantomData<T>,
+}
+
+impl<T: UnextendableHashTraitType> SortedKmersFile<T> {
+    fn entry_size() -> u64 {
+        bincode::serialized_size(&T::default()).unwrap()
+    }
+
+    pub fn open(index_file: impl AsRef<Path>) -> Result<Self, String> {
+        let file = File::open(index_file.as_ref()).map_err(|err| {
+            format!(
+                "Cannot open kmers set file {}: {}",
+                index_file.as_ref().display(),
+                err
+            )
+        })?;
+        let entry_size = Self::entry_size();
+        assert!(entry_size as usize <= MAX_ENTRY_SIZE);
+        let entries_count = file.metadata().map_err(|e| e.to_string())?.len() / entry_size;
+
+        Ok(Self {
+            file,
+            entry_size,
+            entries_count,
+            _phantom: Default::default(),
+        })
+    }
+
+    pub fn kmers_count(&self) -> u64 {
+        self.entries_count
+    }
+
+    pub fn contains(&self, hash: &T) -> bool {
+        let mut entry = [0; MAX_ENTRY_SIZE];
+        let entry = &mut entry[..self.entry_size as usize];
+
+        let mut start = 0;
+        let mut end = self.entries_count;
+        while start < end {
+            let middle = start + (end - start) / 2;
+            self.file
+                .read_exact_at(entry, middle * self.entry_size)
+                .unwrap();
+            let value: T = bincode::deserialize(entry).unwrap();
+
+            if value < *hash {
+                start = middle + 1;
+            } else if value > *hash {
+                end = middle;
+            } else {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// A set of canonical kmer hashes, with the backend chosen for the available memory
+pub enum KmerSet<T> {
+    InMemory(HashSet<T>),
+    OnDiskSorted(SortedKmersFile<T>),
+    Bloom(KmerBloomFilter),
+}
+
+impl<T: UnextendableHashTraitType> KmerSet<T> {
+    pub fn contains(&self, hash: &T) -> bool {
+        match self {
+            KmerSet::InMemory(set) => set.contains(hash),
+            KmerSet::OnDiskSorted(file) => file.contains(hash),
+            KmerSet::Bloom(filter) => filter.contains(hash),
+        }
+    }
+
+    /// False if the set can contain hashes that were not inserted
+    pub fn is_exact(&self) -> bool {
+        !matches!(self, KmerSet::Bloom(_))
+    }
+}
+
+/// Builds a KmerSet with the given backend, inserting the hashes one by one
+pub struct KmerSetBuilder<T> {
+    backend: KmerSetBackend,
+    in_memory: HashSet<T>,
+    bloom: Option<KmerBloomFilter>,
+    buffer: Vec<T>,
+    max_buffer_items: usize,
+    runs: Vec<PathBuf>,
+}
+
+impl<T: UnextendableHashTraitType> KmerSetBuilder<T> {
+    pub fn new(backend: KmerSetBackend, expected_kmers: usize) -> Self {
+        let bloom = match &backend {
+            KmerSetBackend::Bloom {
+                false_positive_rate,
+            } => Some(KmerBloomFilter::new(expected_kmers, *false_positive_rate)),
+            _ => None,
+        };
+        let max_buffer_items = match &backend {
+            KmerSetBackend::OnDiskSorted { max_memory, .. } => {
+                (max_memory / std::mem::size_of::<T>().max(1)).max(1)
+            }
+            _ => 0,
+        };
+
+        Self {
+            backend,
+            in_memory: HashSet::new(),
+            bloom,
+            buffer: vec![],
+            max_buffer_items,
+            runs: vec![],
+        }
+    }
+
+    pub fn insert(&mut self, hash: T) {
+        match self.backend {
+            KmerSetBackend::InMemory => {
+                self.in_memory.insert(hash);
+            }
+            KmerSetBackend::OnDiskSorted { .. } => {
+                self.buffer.push(hash);
+                if self.buffer.len() >= self.max_buffer_items {
+                    self.spill();
+                }
+            }
+            KmerSetBackend::Bloom { .. } => self.bloom.as_mut().unwrap().insert(&hash),
+        }
+    }
+
+    fn index_file(&self) -> &Path {
+        match &self.backend {
+            KmerSetBackend::OnDiskSorted { index_file, .. } => index_file,
+            _ => unreachable!(),
+        }
+    }
+
+    fn spill(&mut self) {
+        self.buffer.sort_unstable();
+        self.buffer.dedup();
+
+        let run_file = self
+            .index_file()
+            .with_extension(format!("run{}", self.runs.len()));
+        let mut writer = BufWriter::new(File::create(&run_file).unwrap_or_else(|err| {
+            panic!(
+                "Cannot create kmers set run {}: {}",
+                run_file.display(),
+                err
+            )
+        }));
+        for hash in &self.buffer {
+            bincode::serialize_into(&mut writer, hash).unwrap();
+        }
+        writer.flush().unwrap();
+
+        self.runs.push(run_file);
+        self.buffer.clear();
+    }
+
+    pub fn finish(mut self) -> KmerSet<T> {
+        match self.backend {
+            KmerSetBackend::InMemory => KmerSet::InMemory(std::mem::take(&mut self.in_memory)),
+            KmerSetBackend::Bloom { .. } => KmerSet::Bloom(self.bloom.take().unwrap()),
+            KmerSetBackend::OnDiskSorted { .. } => {
+                self.spill();
+                let index_file = self.index_file().to_path_buf();
+
+                // Merges the sorted runs, dropping the hashes found in multiple runs
+                let mut readers: Vec<_> = self
+                    .runs
+                    .iter()
+                    .map(|run| BufReader::new(File::open(run).unwrap()))
+                    .collect();
+                let mut heap = BinaryHeap::new();
+                for (run, reader) in readers.iter_mut().enumerate() {
+                    if let Ok(hash) = bincode::deserialize_from::<_, T>(reader) {
+                        heap.push(Reverse((hash, run)));
+                    }
+                }
+
+                let mut writer = BufWriter::new(File::create(&index_file).unwrap_or_else(|err| {
+                    panic!(
+                        "Cannot create kmers set file {}: {}",
+                        index_file.display(),
+                        err
+                    )
+                }));
+                let mut last = None;
+                while let Some(Reverse((hash, run))) = heap.pop() {
+                    if let Ok(next) = bincode::deserialize_from::<_, T>(&mut readers[run]) {
+                        heap.push(Reverse((next, run)));
+                    }
+                    if last != Some(hash) {
+                        bincode::serialize_into(&mut writer, &hash).unwrap();
+                        last = Some(hash);
+                    }
+                }
+                writer.flush().unwrap();
+
+                drop(readers);
+                for run in self.runs.drain(..) {
+                    let _ = std::fs::remove_file(run);
+                }
+
+                KmerSet::OnDiskSorted(
+                    SortedKmersFile::open(&index_file).unwrap_or_else(|err| panic!("{}", err)),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KmerSet, KmerSetBackend, KmerSetBuilder};
+    use std::collections::HashSet;
+    use utils::test_dir::TestDir;
+
+    fn random_hashes(seed: u64, count: usize) -> Vec<u64> {
+        let mut state = seed;
+        (0..count)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state
+            })
+            .collect()
+    }
+
+    fn build_set(backend: KmerSetBackend, hashes: &[u64]) -> KmerSet<u64> {
+        let mut builder = KmerSetBuilder::new(backend, hashes.len());
+        // Some hashes are inserted twice
+        for hash in hashes.iter().chain(&hashes[..100]) {
+            builder.insert(*hash);
+        }
+        builder.finish()
+    }
+
+    #[test]
+    fn exact_backends_membership() {
+        let inserted = random_hashes(0x9e37_79b9_7f4a_7c15, 5000);
+        let inserted_set: HashSet<_> = inserted.iter().copied().collect();
+        let missing: Vec<_> = random_hashes(0x2545_f491_4f6c_dd1d, 5000)
+            .into_iter()
+            .filter(|hash| !inserted_set.contains(hash))
+            .collect();
+
+        let temp_dir = TestDir::new("exact_backends_membership");
+        let index_file = temp_dir.path("kmer-set.idx");
+        for backend in [
+            KmerSetBackend::InMemory,
+            // Small runs to merge multiple of them
+            KmerSetBackend::OnDiskSorted {
+                index_file: index_file.clone(),
+                max_memory: 8 * 700,
+            },
+        ] {
+            let set = build_set(backend, &inserted);
+            assert!(set.is_exact());
+            assert!(inserted.iter().all(|hash| set.contains(hash)));
+            assert!(missing.iter().all(|hash| !set.contains(hash)));
+        }
+
+        assert!(!index_file.with_extension("run0").exists());
+    }
+
+    #[test]
+    fn bloom_backend_has_no_false_negatives() {
+        let inserted = random_hashes(0x9e37_79b9_7f4a_7c15, 5000);
+        let others = random_hashes(0x2545_f491_4f6c_dd1d, 5000);
+
+        let set = build_set(
+            KmerSetBackend::Bloom {
+                false_positive_rate: 0.01,
+            },
+            &inserted,
+        );
+        assert!(!set.is_exact());
+        assert!(inserted.iter().all(|hash| set.contains(hash)));
+
+        let false_positives = others.iter().filter(|hash| set.contains(hash)).count();
+        assert!(false_positives < others.len() / 20);
+    }
+}
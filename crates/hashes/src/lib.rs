@@ -2,15 +2,19 @@
 
 use dynamic_dispatch::dynamic_dispatch;
 
+pub mod cn_lex_seqhash;
 pub mod cn_nthash;
 pub mod cn_seqhash;
 pub mod fw_nthash;
 pub mod fw_seqhash;
 mod nthash_base;
 
+pub mod canonical_compatibility;
 pub mod cn_rkhash;
 pub mod dummy_hasher;
 pub mod fw_rkhash;
+pub mod kmer_set;
+pub mod minimizer_ordering;
 pub mod rolling;
 
 use std::fmt::{Debug, Display};
@@ -56,11 +60,41 @@ impl<
 {
 }
 
+/// Strand of a kmer selected to compute its canonical hash
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Strand {
+    Forward,
+    Reverse,
+}
+
+impl Strand {
+    /// The strand of the same kmer read on the reverse complement
+    #[inline(always)]
+    pub fn reverse(self) -> Self {
+        match self {
+            Strand::Forward => Strand::Reverse,
+            Strand::Reverse => Strand::Forward,
+        }
+    }
+}
+
 pub trait ExtendableHashTraitType: Copy + Clone + Debug + Eq + Ord + Send + Sync {
     type HashTypeUnextendable: UnextendableHashTraitType;
     fn to_unextendable(self) -> Self::HashTypeUnextendable;
     fn is_forward(&self) -> bool;
     fn is_rc_symmetric(&self) -> bool;
+
+    /// Same as to_unextendable, also returning the strand that was selected.
+    /// Reverse complement symmetric kmers are always reported on the forward strand
+    #[inline(always)]
+    fn to_unextendable_with_strand(self) -> (Self::HashTypeUnextendable, Strand) {
+        let strand = if self.is_forward() || self.is_rc_symmetric() {
+            Strand::Forward
+        } else {
+            Strand::Reverse
+        };
+        (self.to_unextendable(), strand)
+    }
 }
 
 #[dynamic_dispatch]
@@ -252,6 +286,59 @@ pub mod tests {
         }
     }
 
+    /// Checks that a read of exactly k bases, whose only hash is computed from the initial state of
+    /// the iterator, emits a single hash equal to the brute force one of the kmer, if given, and to the
+    /// hash of the same kmer found inside a longer read. If canonical, the hash of the reverse complement
+    /// read must have the same canonical value.
+    /// The brute force function receives the compressed bases of the kmer
+    pub fn test_single_kmer_reads<FACTORY: HashFunctionFactory>(
+        kvalues: &[usize],
+        canonical: bool,
+        brute_force: Option<&dyn Fn(&[u8]) -> FACTORY::HashTypeUnextendable>,
+    ) {
+        for kval in kvalues {
+            FACTORY::initialize(*kval);
+
+            let test_bases = generate_bases(*kval * 3, 991 + *kval as u64);
+            let long_hashes = compute_hashes::<FACTORY>(test_bases.as_slice(), *kval, true);
+
+            for offset in [0, 1, *kval / 2, *kval, *kval * 2] {
+                let kmer = &test_bases[offset..offset + *kval];
+
+                let hashes = compute_hashes::<FACTORY>(kmer, *kval, true);
+                assert_eq!(hashes.len(), 1);
+                assert_eq!(hashes[0], long_hashes[offset]);
+
+                if let Some(brute_force) = brute_force {
+                    assert_eq!(
+                        hashes[0].to_unextendable(),
+                        brute_force(to_compressed(kmer).as_slice()),
+                        "k: {} kmer: {}",
+                        kval,
+                        std::str::from_utf8(kmer).unwrap()
+                    );
+                }
+
+                if canonical {
+                    let rc_kmer = kmer
+                        .iter()
+                        .rev()
+                        .map(|x| match *x {
+                            b'A' => b'T',
+                            b'C' => b'G',
+                            b'G' => b'C',
+                            b'T' => b'A',
+                            _ => unreachable!(),
+                        })
+                        .collect::<Vec<_>>();
+                    let rc_hashes = compute_hashes::<FACTORY>(rc_kmer.as_slice(), *kval, true);
+                    assert_eq!(rc_hashes.len(), 1);
+                    assert_eq!(rc_hashes[0].to_unextendable(), hashes[0].to_unextendable());
+                }
+            }
+        }
+    }
+
     pub fn test_hash_function<FACTORY: HashFunctionFactory>(kvalues: &[usize], canonical: bool) {
         for kval in kvalues {
             FACTORY::initialize(*kval);
@@ -336,6 +423,17 @@ pub mod tests {
                         .rev()
                         .collect::<Vec<_>>(),
                 );
+
+                // A kmer and its reverse complement select opposite strands
+                for (hash, rc_hash) in hashes.iter().zip(rc_hashes.iter().rev()) {
+                    if hash.is_rc_symmetric() {
+                        continue;
+                    }
+                    let (value, strand) = hash.to_unextendable_with_strand();
+                    let (rc_value, rc_strand) = rc_hash.to_unextendable_with_strand();
+                    assert_eq!(value, rc_value);
+                    assert_ne!(strand, rc_strand);
+                }
             }
 
             // Manual forward+reverse test
@@ -0,0 +1,268 @@
+use config::MinimizerType;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+
+/// The order used to choose the minimizer among the m-mers of a kmer
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MinimizerOrdering {
+    /// The minimizer is the m-mer with the smallest hash value
+    Hash = 0,
+    /// The hash values are shuffled with a fixed random permutation before comparing them
+    Random = 1,
+    /// Minimizers frequently found in a sample of the input are chosen only if there
+    /// is no other alternative, to avoid overloading a few buckets on low-complexity genomes
+    FrequencyAware = 2,
+}
+
+static MINIMIZER_ORDERING: AtomicU8 = AtomicU8::new(MinimizerOrdering::Hash as u8);
+
+const FREQUENCY_TABLE_BITS: usize = 18;
+const FREQUENCY_TABLE_SIZE: usize = 1 << FREQUENCY_TABLE_BITS;
+
+// A slot is considered frequent if its count is at least this factor above the average
+const FREQUENT_MINIMIZER_FACTOR: u64 = 64;
+const FREQUENT_MINIMIZER_MIN_COUNT: u64 = 16;
+
+const FREQUENT_MINIMIZER_FLAG: MinimizerType = 1 << (MinimizerType::BITS - 1);
+
+const RANDOM_ORDER_MULTIPLIER: MinimizerType = 0x9E37_79B1;
+const DEFAULT_RANDOM_ORDER_KEY: MinimizerType = 0x5BD1_E995;
+static RANDOM_ORDER_KEY: AtomicU32 = AtomicU32::new(DEFAULT_RANDOM_ORDER_KEY);
+
+const FREQUENT_MINIMIZERS_EMPTY: AtomicU64 = AtomicU64::new(0);
+static FREQUENT_MINIMIZERS: [AtomicU64; FREQUENCY_TABLE_SIZE / 64] =
+    [FREQUENT_MINIMIZERS_EMPTY; FREQUENCY_TABLE_SIZE / 64];
+
+pub fn set_minimizer_ordering(ordering: MinimizerOrdering) {
+    MINIMIZER_ORDERING.store(ordering as u8, Ordering::Relaxed);
+}
+
+/// Sets the key of the random permutation used by the Random ordering
+pub fn set_random_order_key(key: MinimizerType) {
+    RANDOM_ORDER_KEY.store(key, Ordering::Relaxed);
+}
+
+/// Restores the hash ordering, the default random permutation key and an empty frequent minimizers set,
+/// so that a run does not inherit the ordering state selected or sampled by a previous one
+pub fn reset_minimizer_ordering() {
+    set_minimizer_ordering(MinimizerOrdering::Hash);
+    set_random_order_key(DEFAULT_RANDOM_ORDER_KEY);
+    for bits in FREQUENT_MINIMIZERS.iter() {
+        bits.store(0, Ordering::Relaxed);
+    }
+}
+
+pub fn get_minimizer_ordering() -> MinimizerOrdering {
+    match MINIMIZER_ORDERING.load(Ordering::Relaxed) {
+        0 => MinimizerOrdering::Hash,
+        1 => MinimizerOrdering::Random,
+        _ => MinimizerOrdering::FrequencyAware,
+    }
+}
+
+#[inline(always)]
+fn frequency_slot(minimizer: MinimizerType) -> usize {
+    minimizer as usize % FREQUENCY_TABLE_SIZE
+}
+
+/// Bijective shuffle of the minimizers space, so that no new ties are introduced
+#[inline(always)]
+pub fn random_order(minimizer: MinimizerType) -> MinimizerType {
+    random_order_with_key(minimizer, RANDOM_ORDER_KEY.load(Ordering::Relaxed))
+}
+
+/// The shuffle of the Random ordering with the given key instead of the selected one
+#[inline(always)]
+pub fn random_order_with_key(minimizer: MinimizerType, key: MinimizerType) -> MinimizerType {
+    minimizer.wrapping_mul(RANDOM_ORDER_MULTIPLIER) ^ key
+}
+
+#[inline(always)]
+pub fn frequency_order(minimizer: MinimizerType, frequent: bool) -> MinimizerType {
+    if frequent {
+        minimizer | FREQUENT_MINIMIZER_FLAG
+    } else {
+        minimizer & !FREQUENT_MINIMIZER_FLAG
+    }
+}
+
+#[inline(always)]
+fn is_frequent_minimizer(minimizer: MinimizerType) -> bool {
+    let slot = frequency_slot(minimizer);
+    (FREQUENT_MINIMIZERS[slot / 64].load(Ordering::Relaxed) >> (slot % 64)) & 1 != 0
+}
+
+/// Maps a minimizer to its rank in the currently selected ordering, used by the ordered hash factories
+#[inline(always)]
+pub fn order_minimizer(minimizer: MinimizerType) -> MinimizerType {
+    match MINIMIZER_ORDERING.load(Ordering::Relaxed) {
+        0 => minimizer,
+        1 => random_order(minimizer),
+        _ => frequency_order(minimizer, is_frequent_minimizer(minimizer)),
+    }
+}
+
+/// Counts the minimizers found in a sample of the input, to find the most frequent ones
+pub struct MinimizersFrequencySampler {
+    counters: Vec<u32>,
+    total: u64,
+}
+
+/// Set of frequent minimizers computed by a MinimizersFrequencySampler
+pub struct FrequentMinimizers {
+    bits: Vec<u64>,
+}
+
+impl MinimizersFrequencySampler {
+    pub fn new() -> Self {
+        Self {
+            counters: vec![0; FREQUENCY_TABLE_SIZE],
+            total: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn add(&mut self, minimizer: MinimizerType) {
+        let counter = &mut self.counters[frequency_slot(minimizer)];
+        *counter = counter.saturating_add(1);
+        self.total += 1;
+    }
+
+    pub fn sampled_count(&self) -> u64 {
+        self.total
+    }
+
+    pub fn get_frequent(&self) -> FrequentMinimizers {
+        let threshold = (self.total * FREQUENT_MINIMIZER_FACTOR / FREQUENCY_TABLE_SIZE as u64)
+            .max(FREQUENT_MINIMIZER_MIN_COUNT);
+
+        let mut bits = vec![0; FREQUENCY_TABLE_SIZE / 64];
+        for (slot, count) in self.counters.iter().enumerate() {
+            if *count as u64 >= threshold {
+                bits[slot / 64] |= 1 << (slot % 64);
+            }
+        }
+        FrequentMinimizers { bits }
+    }
+}
+
+impl FrequentMinimizers {
+    #[inline(always)]
+    pub fn is_frequent(&self, minimizer: MinimizerType) -> bool {
+        let slot = frequency_slot(minimizer);
+        (self.bits[slot / 64] >> (slot % 64)) & 1 != 0
+    }
+
+    pub fn frequent_count(&self) -> usize {
+        self.bits.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    /// Sets these minimizers as the ones to avoid with the FrequencyAware ordering
+    pub fn install(&self) {
+        for (global, bits) in FREQUENT_MINIMIZERS.iter().zip(self.bits.iter()) {
+            global.store(*bits, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{frequency_order, order_minimizer, MinimizersFrequencySampler};
+    use crate::cn_nthash::CanonicalNtHashIteratorFactory;
+    use crate::{cn_nthash, fw_nthash};
+    use crate::{
+        ExtendableHashTraitType, HashFunction, HashFunctionFactory, MinimizerHashFunctionFactory,
+    };
+    use config::MinimizerType;
+    use rand::{RngCore, SeedableRng};
+
+    const K: usize = 31;
+    const M: usize = 11;
+    const BUCKETS_LOG: usize = 6;
+
+    fn mmers(seq: &[u8]) -> Vec<u64> {
+        CanonicalNtHashIteratorFactory::new(seq, M)
+            .iter()
+            .map(|h| h.to_unextendable())
+            .collect()
+    }
+
+    fn max_bucket_load(seq: &[u8], order: impl Fn(u64) -> MinimizerType) -> usize {
+        let hashes = mmers(seq);
+        let mut buckets = vec![0; 1 << BUCKETS_LOG];
+        for window in hashes.windows(K - M + 1) {
+            let minimizer = *window.iter().min_by_key(|h| order(**h)).unwrap();
+            let bucket = CanonicalNtHashIteratorFactory::get_bucket(0, BUCKETS_LOG, minimizer);
+            buckets[bucket as usize] += 1;
+        }
+        *buckets.iter().max().unwrap()
+    }
+
+    #[test]
+    fn only_the_ordered_factories_follow_the_selected_ordering() {
+        for hash in mmers(b"ACGTTGCATGCCATGACTGACTTACGGATC") {
+            let minimizer = hash as MinimizerType;
+            assert_eq!(
+                cn_nthash::CanonicalNtHashIteratorFactory::get_full_minimizer(hash),
+                minimizer
+            );
+            assert_eq!(
+                fw_nthash::ForwardNtHashIteratorFactory::get_full_minimizer(hash),
+                minimizer
+            );
+            assert_eq!(
+                cn_nthash::ordered::CanonicalNtHashIteratorFactory::get_full_minimizer(hash),
+                order_minimizer(minimizer)
+            );
+            assert_eq!(
+                fw_nthash::ordered::ForwardNtHashIteratorFactory::get_full_minimizer(hash),
+                order_minimizer(minimizer)
+            );
+        }
+    }
+
+    #[test]
+    fn frequency_aware_ordering_balances_buckets() {
+        let hash_order = |h: u64| h as MinimizerType;
+
+        // Pick the microsatellite whose minimizer is the smallest one with the hash ordering,
+        // the worst case for the default ordering on low-complexity genomes
+        let repeat_units = (2..=4).flat_map(|len| {
+            (0..(1usize << (2 * len)))
+                .map(move |code| (0..len).map(|i| b"ACGT"[(code >> (2 * i)) & 3]).collect())
+        });
+        let repeat_unit: Vec<u8> = repeat_units
+            .min_by_key(|unit: &Vec<u8>| {
+                let repeat = unit.repeat(M);
+                mmers(&repeat).into_iter().map(hash_order).min().unwrap()
+            })
+            .unwrap();
+
+        let mut rng = pcg_rand::Pcg64::seed_from_u64(37);
+        let mut sequence = vec![];
+        for _ in 0..500 {
+            for _ in 0..40 {
+                sequence.push(b"ACGT"[(rng.next_u32() % 4) as usize]);
+            }
+            sequence.extend(repeat_unit.iter().cycle().take(30));
+        }
+
+        let mut sampler = MinimizersFrequencySampler::new();
+        for hash in mmers(&sequence) {
+            sampler.add(hash as MinimizerType);
+        }
+        let frequent = sampler.get_frequent();
+        assert!(frequent.frequent_count() > 0);
+
+        let hash_load = max_bucket_load(&sequence, hash_order);
+        let frequency_load = max_bucket_load(&sequence, |h| {
+            frequency_order(h as MinimizerType, frequent.is_frequent(h as MinimizerType))
+        });
+
+        assert!(
+            frequency_load * 2 < hash_load,
+            "Frequency aware: {} hash: {}",
+            frequency_load,
+            hash_load
+        );
+    }
+}
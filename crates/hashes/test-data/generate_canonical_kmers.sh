@@ -0,0 +1,69 @@
+#!/usr/bin/env bash
+# Regenerates canonical_kmers.tsv from the kmers in its first column, running the reference tools:
+#  - bifrost: Kmer::rep() of the Bifrost library, built with MAX_KMER_SIZE=64 (k <= 63)
+#  - cuttlefish: the canonical kmers of the KMC database Cuttlefish is built from, as printed by kmc_dump.
+#    Cuttlefish supports only odd k, the even k rows are written as '-'
+#  - ggcat: the strand with the smallest 2-bit encoding (A=0, C=1, T=2, G=3, first base in the least
+#    significant bits), as the default canonical sequence hash
+#
+# Usage: generate_canonical_kmers.sh <bifrost install prefix>
+# Requires g++, kmc and kmc_dump in the PATH
+set -euo pipefail
+
+BIFROST_PREFIX=$1
+DATA_DIR=$(cd "$(dirname "$0")" && pwd)
+FIXTURE="$DATA_DIR/canonical_kmers.tsv"
+WORK_DIR=$(mktemp -d)
+trap 'rm -rf "$WORK_DIR"' EXIT
+
+cat > "$WORK_DIR/bifrost_rep.cpp" <<'CPP'
+#include <bifrost/Kmer.hpp>
+#include <iostream>
+#include <string>
+
+int main() {
+    std::string kmer;
+    while (std::cin >> kmer) {
+        Kmer::set_k(kmer.size());
+        std::cout << Kmer(kmer.c_str()).rep().toString() << std::endl;
+    }
+    return 0;
+}
+CPP
+g++ -O2 -std=c++11 -DMAX_KMER_SIZE=64 -I"$BIFROST_PREFIX/include" "$WORK_DIR/bifrost_rep.cpp" \
+    -L"$BIFROST_PREFIX/lib" -lbifrost -lpthread -lz -o "$WORK_DIR/bifrost_rep"
+
+ggcat_canonical() {
+    python3 -c '
+import sys
+code = {"A": 0, "C": 1, "T": 2, "G": 3}
+comp = {"A": "T", "C": "G", "G": "C", "T": "A"}
+kmer = sys.argv[1]
+rc = "".join(comp[b] for b in reversed(kmer))
+encode = lambda s: sum(code[b] << (2 * i) for i, b in enumerate(s))
+print(kmer if encode(kmer) <= encode(rc) else rc)
+' "$1"
+}
+
+cuttlefish_canonical() {
+    local kmer=$1
+    if (( ${#kmer} % 2 == 0 )); then
+        echo "-"
+        return
+    fi
+    printf ">kmer\n%s\n" "$kmer" > "$WORK_DIR/kmer.fa"
+    mkdir -p "$WORK_DIR/kmc"
+    kmc -k"${#kmer}" -ci1 -cs1 -fm "$WORK_DIR/kmer.fa" "$WORK_DIR/db" "$WORK_DIR/kmc" > /dev/null
+    kmc_dump "$WORK_DIR/db" "$WORK_DIR/dump.txt" > /dev/null
+    cut -f1 "$WORK_DIR/dump.txt"
+}
+
+{
+    printf "#kmer\tggcat\tbifrost\tcuttlefish\n"
+    grep -v '^#' "$FIXTURE" | cut -f1 | while read -r kmer; do
+        printf "%s\t%s\t%s\t%s\n" "$kmer" "$(ggcat_canonical "$kmer")" \
+            "$(echo "$kmer" | "$WORK_DIR/bifrost_rep")" "$(cuttlefish_canonical "$kmer")"
+    done
+} > "$WORK_DIR/canonical_kmers.tsv"
+
+mv "$WORK_DIR/canonical_kmers.tsv" "$FIXTURE"
@@ -0,0 +1,97 @@
+use parallel_processor::buckets::bucket_writer::BucketItemSerializer;
+use parallel_processor::buckets::readers::compressed_binary_reader::CompressedBinaryReader;
+
+/// Lazily decodes the items of a bucket, allowing to process them as a stream instead of
+/// collecting them in the decode_all_bucket_items callback.
+/// The bucket is decoded as a single stream, so this is suitable only for serializers that do not
+/// keep state between consecutive items, and whose items do not borrow from the read buffers
+pub fn iter_bucket_items<'a, S, T: 'a>(
+    reader: &'a mut CompressedBinaryReader,
+) -> impl Iterator<Item = T> + 'a
+where
+    S: for<'b> BucketItemSerializer<ReadBuffer = (), ExtraDataBuffer = (), ReadType<'b> = T> + 'a,
+{
+    let mut stream = reader.get_single_stream();
+    let mut deserializer = S::new();
+    std::iter::from_fn(move || deserializer.read_from(&mut stream, &mut (), &mut ()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::iter_bucket_items;
+    use crate::structs::hash_entry::{Direction, HashEntry, HashEntrySerializer};
+    use config::{
+        get_compression_level_info, get_memory_mode, SwapPriority, DEFAULT_PREFETCH_AMOUNT,
+        PARTIAL_VECS_CHECKPOINT_SIZE,
+    };
+    use parallel_processor::buckets::bucket_writer::BucketItemSerializer;
+    use parallel_processor::buckets::readers::compressed_binary_reader::CompressedBinaryReader;
+    use parallel_processor::buckets::readers::BucketReader;
+    use parallel_processor::buckets::writers::compressed_binary_writer::CompressedBinaryWriter;
+    use parallel_processor::buckets::LockFreeBucket;
+    use parallel_processor::memory_fs::RemoveFileMode;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn iterator_matches_callback_items() {
+        let temp_dir = TestDir::new("iterator_matches_callback_items");
+        let writer = CompressedBinaryWriter::new(
+            temp_dir.path("bucket-items").as_path(),
+            &(
+                get_memory_mode(SwapPriority::HashBuckets),
+                PARTIAL_VECS_CHECKPOINT_SIZE,
+                get_compression_level_info(),
+            ),
+            0,
+        );
+
+        let mut serializer = HashEntrySerializer::<u64>::new();
+        let mut buffer = vec![];
+        for i in 0..10000u64 {
+            let direction = if i % 3 == 0 {
+                Direction::Forward
+            } else {
+                Direction::Backward
+            };
+            let entry = HashEntry::new(
+                i.wrapping_mul(0x9E3779B97F4A7C15),
+                (i % 7) as u16,
+                i,
+                direction,
+            );
+            serializer.write_to(&entry, &mut buffer, &(), &());
+        }
+        writer.write_data(&buffer);
+
+        let bucket_path = writer.get_path();
+        writer.finalize();
+
+        let to_tuple = |h: HashEntry<u64>| (h.hash, h.bucket(), h.entry(), h.direction());
+
+        let mut callback_items = vec![];
+        let callback_reader = CompressedBinaryReader::new(
+            &bucket_path,
+            RemoveFileMode::Keep,
+            DEFAULT_PREFETCH_AMOUNT,
+        );
+        callback_reader.decode_all_bucket_items::<HashEntrySerializer<u64>, _>(
+            (),
+            &mut (),
+            |h, _| {
+                callback_items.push(to_tuple(h));
+            },
+        );
+
+        let mut reader = CompressedBinaryReader::new(
+            &bucket_path,
+            RemoveFileMode::Remove { remove_fs: true },
+            DEFAULT_PREFETCH_AMOUNT,
+        );
+        let iterator_items: Vec<_> = iter_bucket_items::<HashEntrySerializer<u64>, _>(&mut reader)
+            .map(to_tuple)
+            .collect();
+
+        assert_eq!(callback_items.len(), 10000);
+        assert_eq!(iterator_items, callback_items);
+    }
+}
@@ -0,0 +1,41 @@
+/// Which fasta records are read as circular sequences, wrapping their kmers around the origin.
+/// The first k - 1 bases of a circular record are appended to its end, so that also the kmers spanning
+/// the origin are read. Selected for each input stream, as with CircularFastaStream
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CircularInputs {
+    /// All the records are linear
+    Disabled,
+    /// Only the records with a circular tag in their header, as circular=true or topology=circular
+    HeaderTag,
+    /// All the fasta records are circular
+    All,
+}
+
+/// If a fasta header marks its record as circular, with one of the tags used by the common
+/// assemblers and databases (circular=true, circular=yes, topology=circular, [topology=circular])
+pub fn is_circular_header(header: &[u8]) -> bool {
+    header
+        .split(|c| c.is_ascii_whitespace())
+        .map(|field| {
+            field
+                .strip_prefix(b"[")
+                .and_then(|field| field.strip_suffix(b"]"))
+                .unwrap_or(field)
+        })
+        .any(|field| {
+            field.eq_ignore_ascii_case(b"circular=true")
+                || field.eq_ignore_ascii_case(b"circular=yes")
+                || field.eq_ignore_ascii_case(b"topology=circular")
+        })
+}
+
+impl CircularInputs {
+    /// If a record with the given header is read as circular
+    pub fn is_circular_record(&self, header: &[u8]) -> bool {
+        match self {
+            CircularInputs::Disabled => false,
+            CircularInputs::HeaderTag => is_circular_header(header),
+            CircularInputs::All => true,
+        }
+    }
+}
@@ -107,7 +107,7 @@ impl<'a> CompressedRead<'a> {
     }
 
     #[inline(always)]
-    fn compress_from_plain(seq: &'a [u8], mut writer: impl FnMut(&[u8])) {
+    pub(crate) fn compress_from_plain(seq: &'a [u8], mut writer: impl FnMut(&[u8])) {
         for chunk in seq.chunks(16) {
             let mut value = 0;
             for aa in chunk.iter().rev() {
@@ -212,6 +212,21 @@ impl<'a> CompressedRead<'a> {
             .map(move |i| unsafe { Utils::decompress_base(self.get_base_unchecked(i) ^ 2) })
     }
 
+    /// True if the two reads are equal, either directly or one as the reverse complement
+    /// of the other. The packed bases are compared without unpacking them
+    pub fn canonical_eq(&self, other: &CompressedRead) -> bool {
+        if self.size != other.size {
+            return false;
+        }
+
+        let last = self.size.wrapping_sub(1);
+        unsafe {
+            (0..self.size).all(|i| self.get_base_unchecked(i) == other.get_base_unchecked(i))
+                || (0..self.size)
+                    .all(|i| self.get_base_unchecked(i) == (other.get_base_unchecked(last - i) ^ 2))
+        }
+    }
+
     pub fn to_string(&self) -> String {
         String::from_iter(
             (0..self.size)
@@ -257,3 +272,239 @@ impl<'a> HashableSequence for CompressedRead<'a> {
         self.size
     }
 }
+
+/// Packed sequence built by appending bases and reads, with the same layout of the compressed
+/// reads (4 bases per byte, starting from the low bits), so that it can be used as a CompressedRead
+#[derive(Clone, Default)]
+pub struct CompressedReadBuilder {
+    data: Vec<u8>,
+    size: usize,
+}
+
+impl CompressedReadBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(bases_count: usize) -> Self {
+        Self {
+            data: Vec::with_capacity((bases_count + 3) / 4),
+            size: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.size = 0;
+    }
+
+    pub fn bases_count(&self) -> usize {
+        self.size
+    }
+
+    // The bits after the last base are always kept to zero, to be able to add the next bases with an or
+    #[inline(always)]
+    fn push_compressed_base(&mut self, base: u8) {
+        let offset = self.size % 4;
+        if offset == 0 {
+            self.data.push(0);
+        }
+        *self.data.last_mut().unwrap() |= (base & 0x3) << (offset * 2);
+        self.size += 1;
+    }
+
+    /// Appends a plain base (A, C, G or T)
+    #[inline(always)]
+    pub fn push_base(&mut self, base: u8) {
+        self.push_compressed_base(Utils::compress_base(base));
+    }
+
+    /// Appends the bases of a read after its first skip_prefix ones, as when joining overlapping reads.
+    /// The bases are copied a whole byte at a time after aligning the end of the sequence to a byte
+    pub fn push_read(&mut self, read: &CompressedRead, skip_prefix: usize) {
+        let mut index = skip_prefix.min(read.size);
+
+        while self.size % 4 != 0 && index < read.size {
+            self.push_compressed_base(unsafe { read.get_base_unchecked(index) });
+            index += 1;
+        }
+
+        let full_bytes = (read.size - index) / 4;
+        let read_offset = read.start as usize + index;
+        let packed = read.get_packed_slice();
+        let first_byte = read_offset / 4;
+
+        if read_offset % 4 == 0 {
+            self.data
+                .extend_from_slice(&packed[first_byte..first_byte + full_bytes]);
+        } else {
+            let right_offset = (read_offset % 4) * 2;
+            let left_offset = 8 - right_offset;
+            self.data.extend(
+                (first_byte..first_byte + full_bytes)
+                    .map(|b| (packed[b] >> right_offset) | (packed[b + 1] << left_offset)),
+            );
+        }
+        self.size += full_bytes * 4;
+        index += full_bytes * 4;
+
+        for i in index..read.size {
+            self.push_compressed_base(unsafe { read.get_base_unchecked(i) });
+        }
+    }
+
+    /// Appends the reverse complement of a read
+    pub fn push_reverse_complement(&mut self, read: &CompressedRead) {
+        for i in (0..read.size).rev() {
+            self.push_compressed_base(unsafe { read.get_base_unchecked(i) } ^ 2);
+        }
+    }
+
+    /// The built sequence, valid until the builder is modified
+    pub fn finish(&self) -> CompressedRead<'_> {
+        CompressedRead::new_from_compressed(&self.data, self.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompressedReadBuilder, CompressedReadIndipendent};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+        seq.iter()
+            .rev()
+            .map(|b| match *b {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                _ => b'A',
+            })
+            .collect()
+    }
+
+    fn random_sequence(rng: &mut StdRng, length: usize) -> Vec<u8> {
+        (0..length).map(|_| b"ACGT"[rng.gen_range(0..4)]).collect()
+    }
+
+    #[test]
+    fn canonical_eq_matches_reverse_complements() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+
+        for _ in 0..1000 {
+            let length = rng.gen_range(1..100);
+            let offset = rng.gen_range(0..4);
+
+            let sequence = random_sequence(&mut rng, length);
+            let reverse = reverse_complement(&sequence);
+            let different = loop {
+                let candidate = random_sequence(&mut rng, length);
+                if candidate != sequence && candidate != reverse {
+                    break candidate;
+                }
+            };
+
+            let mut storage = vec![];
+            // Pack the first read after some bases, so that it does not start at a byte boundary
+            let mut padded = random_sequence(&mut rng, offset);
+            padded.extend_from_slice(&sequence);
+            let padded_read = CompressedReadIndipendent::from_plain(&padded, &mut storage);
+            let reverse_read = CompressedReadIndipendent::from_plain(&reverse, &mut storage);
+            let different_read = CompressedReadIndipendent::from_plain(&different, &mut storage);
+
+            let read = padded_read
+                .as_reference(&storage)
+                .sub_slice(offset..offset + length);
+            let reverse_read = reverse_read.as_reference(&storage);
+            let different_read = different_read.as_reference(&storage);
+
+            assert_eq!(read.to_string().as_bytes(), &sequence[..]);
+            assert!(read.canonical_eq(&read));
+            assert!(read.canonical_eq(&reverse_read));
+            assert!(reverse_read.canonical_eq(&read));
+            assert!(!read.canonical_eq(&different_read));
+            assert!(!different_read.canonical_eq(&reverse_read));
+            assert!(!read.canonical_eq(&read.sub_slice(0..length - 1)));
+        }
+    }
+    #[test]
+    fn builder_packs_bases_and_read_chunks() {
+        let mut builder = CompressedReadBuilder::new();
+        for base in b"ACGTTGCAG" {
+            builder.push_base(*base);
+        }
+        // A=0, C=1, T=2, G=3, from the low bits of each byte
+        assert_eq!(
+            builder.finish().get_packed_slice(),
+            &[0b10_11_01_00, 0b00_01_11_10, 0b11]
+        );
+        assert_eq!(builder.finish().to_string(), "ACGTTGCAG");
+
+        let mut rng = StdRng::seed_from_u64(0xb1d);
+
+        for _ in 0..1000 {
+            let chunks_count = rng.gen_range(1..8);
+            let mut storage = vec![];
+            let mut reads = vec![];
+            let mut expected = vec![];
+
+            for _ in 0..chunks_count {
+                let length = rng.gen_range(1..40);
+                let sequence = random_sequence(&mut rng, length);
+                let offset = rng.gen_range(0..4).min(sequence.len() - 1);
+                let skip_prefix = rng.gen_range(0..=sequence.len() - offset);
+                expected.extend_from_slice(&sequence[offset + skip_prefix..]);
+                reads.push((
+                    CompressedReadIndipendent::from_plain(&sequence, &mut storage),
+                    offset,
+                    skip_prefix,
+                ));
+            }
+
+            let mut builder = CompressedReadBuilder::new();
+            for (read, offset, skip_prefix) in &reads {
+                let read = read.as_reference(&storage);
+                // Reads not starting at a byte boundary
+                builder.push_read(&read.sub_slice(*offset..read.get_length()), *skip_prefix);
+            }
+
+            let mut expected_storage = vec![];
+            let expected_read =
+                CompressedReadIndipendent::from_plain(&expected, &mut expected_storage);
+
+            assert_eq!(builder.bases_count(), expected.len());
+            assert_eq!(builder.finish().to_string().as_bytes(), &expected[..]);
+            assert_eq!(
+                builder.finish().get_packed_slice(),
+                expected_read
+                    .as_reference(&expected_storage)
+                    .get_packed_slice()
+            );
+        }
+    }
+
+    #[test]
+    fn builder_appends_reverse_complements() {
+        let mut rng = StdRng::seed_from_u64(0x2c0);
+
+        for _ in 0..1000 {
+            let length = rng.gen_range(1..100);
+            let offset = rng.gen_range(0..4);
+            let sequence = random_sequence(&mut rng, offset + length);
+
+            let mut storage = vec![];
+            let read = CompressedReadIndipendent::from_plain(&sequence, &mut storage);
+            let read = read
+                .as_reference(&storage)
+                .sub_slice(offset..offset + length);
+
+            let mut builder = CompressedReadBuilder::new();
+            builder.push_reverse_complement(&read);
+            assert_eq!(
+                builder.finish().to_string().as_bytes(),
+                &reverse_complement(&sequence[offset..])[..]
+            );
+        }
+    }
+}
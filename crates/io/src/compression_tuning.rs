@@ -0,0 +1,212 @@
+use crate::compressed_read::CompressedRead;
+use crate::lines_reader::LinesReader;
+use crate::sequences_stream::general::GeneralSequenceBlockData;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// The lz4 levels that can be selected adaptively, the higher ones are too slow for temporary files
+pub const ADAPTIVE_LEVELS_RANGE: RangeInclusive<u32> = 0..=9;
+const SAMPLED_LEVELS: [u32; 5] = [0, 1, 3, 6, 9];
+
+// Maximum size of the input read to build the sample of the temporary data
+const INPUT_SAMPLE_MAX_SIZE: u64 = 1024 * 1024 * 16;
+const SINK_CALIBRATION_SIZE: usize = 1024 * 1024 * 32;
+
+// A level is preferred to a lower one only if it is faster by more than this factor,
+// so that small measurement noise does not change the choice
+const LEVEL_CHANGE_MIN_GAIN: f64 = 1.05;
+
+static ADAPTIVE_COMPRESSION_LEVEL: AtomicBool = AtomicBool::new(false);
+
+/// Enables the selection of the intermediate files compression level from the measured
+/// compression speed and disk write speed, when no explicit level is given
+pub fn set_adaptive_compression_level(enabled: bool) {
+    ADAPTIVE_COMPRESSION_LEVEL.store(enabled, Ordering::Relaxed);
+}
+
+/// Measured performance of a compression level on a sample of the temporary data
+#[derive(Copy, Clone, Debug)]
+pub struct CompressionSample {
+    pub level: u32,
+    /// Compressed size over uncompressed size
+    pub ratio: f64,
+    /// Uncompressed bytes compressed per second
+    pub compression_throughput: f64,
+}
+
+impl CompressionSample {
+    /// Uncompressed bytes per second that can be stored using this level with a sink
+    /// writing sink_throughput bytes per second
+    pub fn effective_throughput(&self, sink_throughput: f64) -> f64 {
+        1.0 / (1.0 / self.compression_throughput + self.ratio / sink_throughput)
+    }
+}
+
+/// Chooses the level that maximizes the effective throughput, in the adaptive levels range
+pub fn choose_compression_level(
+    samples: &[CompressionSample],
+    sink_throughput: f64,
+) -> Option<u32> {
+    let mut samples: Vec<_> = samples
+        .iter()
+        .filter(|s| ADAPTIVE_LEVELS_RANGE.contains(&s.level))
+        .collect();
+    samples.sort_by_key(|s| s.level);
+
+    let mut best: Option<(u32, f64)> = None;
+    for sample in samples {
+        let throughput = sample.effective_throughput(sink_throughput);
+        if best.map_or(true, |(_, best_throughput)| {
+            throughput > best_throughput * LEVEL_CHANGE_MIN_GAIN
+        }) {
+            best = Some((sample.level, throughput));
+        }
+    }
+
+    best.map(|(level, _)| level)
+}
+
+/// Compresses the data with each of the sampled levels, measuring their ratio and speed
+pub fn sample_compression_levels(data: &[u8]) -> Vec<CompressionSample> {
+    SAMPLED_LEVELS
+        .iter()
+        .map(|&level| {
+            let start = Instant::now();
+            let mut encoder = lz4::EncoderBuilder::new()
+                .level(level)
+                .build(Vec::with_capacity(data.len()))
+                .unwrap();
+            encoder.write_all(data).unwrap();
+            let (compressed, result) = encoder.finish();
+            result.unwrap();
+            let elapsed = start.elapsed().as_secs_f64().max(1e-9);
+
+            CompressionSample {
+                level,
+                ratio: compressed.len() as f64 / data.len().max(1) as f64,
+                compression_throughput: data.len() as f64 / elapsed,
+            }
+        })
+        .collect()
+}
+
+/// Measures the write speed of the temporary directory, in bytes per second
+pub fn measure_sink_throughput(temp_dir: &Path) -> Option<f64> {
+    std::fs::create_dir_all(temp_dir).ok()?;
+    let path = temp_dir.join("compression-calibration.tmp");
+    let data = vec![0xa5; SINK_CALIBRATION_SIZE];
+
+    let start = Instant::now();
+    let result = File::create(&path).and_then(|mut file| {
+        file.write_all(&data)?;
+        file.sync_all()
+    });
+    let elapsed = start.elapsed().as_secs_f64().max(1e-9);
+    let _ = std::fs::remove_file(&path);
+
+    result.ok().map(|_| data.len() as f64 / elapsed)
+}
+
+// Packs the bases found at the start of the first input file, as they are stored in the temporary
+// buckets. Only uncompressed fasta files are sampled
+fn read_packed_input_sample(inputs: &[GeneralSequenceBlockData]) -> Option<Vec<u8>> {
+    let path = match inputs.first()? {
+        GeneralSequenceBlockData::FASTA((path, _)) => path,
+        _ => return None,
+    };
+
+    if !LinesReader::is_uncompressed_file(path) {
+        return None;
+    }
+
+    let mut sample = vec![];
+    let reader = BufReader::new(File::open(path).ok()?.take(INPUT_SAMPLE_MAX_SIZE));
+    for line in reader.split(b'\n') {
+        let line = line.ok()?;
+        let line = line.strip_suffix(b"\r").unwrap_or(&line);
+        if !line.is_empty()
+            && line
+                .iter()
+                .all(|b| matches!(b, b'A' | b'C' | b'G' | b'T' | b'a' | b'c' | b'g' | b't'))
+        {
+            CompressedRead::from_plain_write_directly_to_buffer_with_flags::<typenum::U2>(
+                line,
+                &mut sample,
+                0,
+            );
+        }
+    }
+
+    (!sample.is_empty()).then_some(sample)
+}
+
+/// Chooses the compression level of the intermediate files if the adaptive selection is enabled,
+/// sampling the inputs and the temporary directory speed. The level affects only the writing of
+/// new files, as the lz4 decoding does not depend on it, so resuming a run is not affected
+pub fn tune_intermediate_compression_level(
+    inputs: &[GeneralSequenceBlockData],
+    temp_dir: &Path,
+) -> Option<u32> {
+    if !ADAPTIVE_COMPRESSION_LEVEL.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    let sample = read_packed_input_sample(inputs)?;
+    let sink_throughput = measure_sink_throughput(temp_dir)?;
+    choose_compression_level(&sample_compression_levels(&sample), sink_throughput)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{choose_compression_level, CompressionSample};
+
+    #[test]
+    fn slower_sinks_choose_higher_levels() {
+        const MB: f64 = 1024.0 * 1024.0;
+
+        let samples = [
+            CompressionSample {
+                level: 0,
+                ratio: 0.6,
+                compression_throughput: 800.0 * MB,
+            },
+            CompressionSample {
+                level: 3,
+                ratio: 0.45,
+                compression_throughput: 200.0 * MB,
+            },
+            CompressionSample {
+                level: 9,
+                ratio: 0.35,
+                compression_throughput: 40.0 * MB,
+            },
+            // Outside of the allowed range
+            CompressionSample {
+                level: 12,
+                ratio: 0.3,
+                compression_throughput: 10.0 * MB,
+            },
+        ];
+
+        let fast_sink_level = choose_compression_level(&samples, 2000.0 * MB).unwrap();
+        let slow_sink_level = choose_compression_level(&samples, 20.0 * MB).unwrap();
+        let very_slow_sink_level = choose_compression_level(&samples, 2.0 * MB).unwrap();
+
+        assert_eq!(fast_sink_level, 0);
+        assert!(slow_sink_level > fast_sink_level);
+        assert!(very_slow_sink_level > slow_sink_level);
+        assert_eq!(very_slow_sink_level, 9);
+
+        // The choice does not depend on the order of the samples
+        let mut reversed = samples;
+        reversed.reverse();
+        assert_eq!(
+            choose_compression_level(&reversed, 20.0 * MB),
+            Some(slow_sink_level)
+        );
+    }
+}
@@ -1,12 +1,19 @@
 use super::temp_reads::extra_data::SequenceExtraDataConsecutiveCompression;
+use kmer_counts_sidecar::KmerCountsWriter;
+use low_coverage_bed::LowCoverageBedWriter;
 use parking_lot::{Condvar, Mutex};
 use std::io::Write;
 use std::marker::PhantomData;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use unitig_anchors::{UnitigAnchorsEncoder, UnitigAnchorsWriter};
 
 pub mod binary;
 pub mod concurrent;
+pub mod coverage;
 pub mod fasta;
+pub mod kmer_counts_sidecar;
+pub mod low_coverage_bed;
+pub mod unitig_anchors;
 
 pub trait IdentSequenceWriter: SequenceExtraDataConsecutiveCompression + Sized {
     fn write_as_ident(&self, stream: &mut impl Write, extra_buffer: &Self::TempBuffer);
@@ -31,12 +38,35 @@ impl IdentSequenceWriter for () {
     }
 }
 
+/// The counts of each kmer of a sequence, in order
 #[derive(Clone, Debug)]
 #[cfg(feature = "support_kmer_counters")]
 pub struct SequenceAbundance {
-    pub first: u64,
-    pub sum: u64,
-    pub last: u64,
+    pub counts: Vec<u64>,
+}
+
+#[cfg(feature = "support_kmer_counters")]
+impl SequenceAbundance {
+    pub fn new() -> Self {
+        Self { counts: vec![] }
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Appends the counts of another sequence, skipping its first kmers shared with this sequence
+    pub fn join_counts<const REVERSE: bool>(&mut self, counts: &[u64], skip: usize) {
+        if REVERSE {
+            self.counts.extend(counts.iter().rev().skip(skip));
+        } else {
+            self.counts.extend(counts.iter().skip(skip));
+        }
+    }
+
+    pub fn pop_count(&mut self) {
+        self.counts.pop();
+    }
 }
 
 #[cfg(feature = "support_kmer_counters")]
@@ -80,6 +110,9 @@ pub struct StructuredSequenceWriter<
     current_index: Mutex<(u64, u64)>,
     k: usize,
     backend: Mutex<Backend>,
+    kmer_counts: Option<KmerCountsWriter>,
+    low_coverage_bed: Option<LowCoverageBedWriter>,
+    unitig_anchors: Option<UnitigAnchorsWriter>,
     index_condvar: Condvar,
     _phantom: PhantomData<(ColorInfo, LinksInfo, Backend)>,
 }
@@ -95,11 +128,39 @@ impl<
             current_index: Mutex::new((0, 0)),
             k,
             backend: Mutex::new(backend),
+            kmer_counts: None,
+            low_coverage_bed: None,
+            unitig_anchors: None,
             index_condvar: Condvar::new(),
             _phantom: PhantomData,
         }
     }
 
+    /// Also writes the count of each kmer of the sequences to the given file, in the order of the sequences.
+    /// The counts are written only in the builds with the kmer counters support
+    pub fn with_kmer_counts(mut self, kmer_counts_file: impl AsRef<Path>) -> Self {
+        self.kmer_counts = Some(KmerCountsWriter::create(kmer_counts_file, self.k));
+        self
+    }
+
+    /// Also writes the stretches of the sequences with a bases coverage below min_coverage to the given BED file.
+    /// The stretches are written only in the builds with the kmer counters support
+    pub fn with_low_coverage_bed(mut self, bed_file: impl AsRef<Path>, min_coverage: u64) -> Self {
+        self.low_coverage_bed = Some(LowCoverageBedWriter::create(bed_file, min_coverage));
+        self
+    }
+
+    /// Also writes the hashes of the first and the last kmer of the sequences to the given file,
+    /// in the order of the sequences, encoded with the kmers hash of the build
+    pub fn with_unitig_anchors(
+        mut self,
+        anchors_file: impl AsRef<Path>,
+        encoder: UnitigAnchorsEncoder,
+    ) -> Self {
+        self.unitig_anchors = Some(UnitigAnchorsWriter::create(anchors_file, encoder));
+        self
+    }
+
     fn write_sequences<'a>(
         &self,
         buffer: &mut Backend::SequenceTempBuffer,
@@ -123,9 +184,35 @@ impl<
             }
         };
 
+        #[cfg(feature = "support_kmer_counters")]
+        let mut kmer_counts_buffer = vec![];
+        #[cfg(feature = "support_kmer_counters")]
+        let mut low_coverage_buffer = vec![];
+        let mut anchors_buffer = vec![];
+
         let mut current_index = start_sequence_index;
         // Write the sequences to a temporary buffer
         for (sequence, color_info, links_info, _abundance) in sequences {
+            #[cfg(feature = "support_kmer_counters")]
+            if self.kmer_counts.is_some() {
+                kmer_counts_sidecar::encode_unitig_kmer_counts(
+                    &mut kmer_counts_buffer,
+                    current_index,
+                    &_abundance.counts,
+                );
+            }
+            #[cfg(feature = "support_kmer_counters")]
+            if let Some(low_coverage_bed) = &self.low_coverage_bed {
+                low_coverage_bed.encode_unitig(
+                    &mut low_coverage_buffer,
+                    current_index,
+                    &_abundance.counts,
+                    self.k,
+                );
+            }
+            if let Some(unitig_anchors) = &self.unitig_anchors {
+                unitig_anchors.encode_unitig(&mut anchors_buffer, current_index, sequence, self.k);
+            }
             Backend::write_sequence(
                 self.k,
                 buffer,
@@ -146,6 +233,17 @@ impl<
 
             if index_lock.1 == start_sequence_index {
                 self.backend.lock().flush_temp_buffer(buffer);
+                #[cfg(feature = "support_kmer_counters")]
+                if let Some(kmer_counts) = &self.kmer_counts {
+                    kmer_counts.write_encoded(&kmer_counts_buffer);
+                }
+                #[cfg(feature = "support_kmer_counters")]
+                if let Some(low_coverage_bed) = &self.low_coverage_bed {
+                    low_coverage_bed.write_encoded(&low_coverage_buffer);
+                }
+                if let Some(unitig_anchors) = &self.unitig_anchors {
+                    unitig_anchors.write_encoded(&anchors_buffer);
+                }
                 index_lock.1 += sequences_count;
 
                 self.index_condvar.notify_all();
@@ -164,5 +262,14 @@ impl<
 
     pub fn finalize(self) {
         self.backend.into_inner().finalize();
+        if let Some(kmer_counts) = self.kmer_counts {
+            kmer_counts.finalize();
+        }
+        if let Some(low_coverage_bed) = self.low_coverage_bed {
+            low_coverage_bed.finalize();
+        }
+        if let Some(unitig_anchors) = self.unitig_anchors {
+            unitig_anchors.finalize();
+        }
     }
 }
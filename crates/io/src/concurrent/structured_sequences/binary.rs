@@ -106,12 +106,13 @@ impl<CX: SequenceExtraDataConsecutiveCompression, LX: SequenceExtraData>
     ) -> Option<Self> {
         let index = decode_varint(|| reader.read_u8().ok())?;
         #[cfg(feature = "support_kmer_counters")]
-        let (abundance_first, abundance_sum, abundance_last) = {
-            (
-                decode_varint(|| reader.read_u8().ok())?,
-                decode_varint(|| reader.read_u8().ok())?,
-                decode_varint(|| reader.read_u8().ok())?,
-            )
+        let abundance_counts = {
+            let counts_len = decode_varint(|| reader.read_u8().ok())?;
+            let mut counts = Vec::with_capacity(counts_len as usize);
+            for _ in 0..counts_len {
+                counts.push(decode_varint(|| reader.read_u8().ok())?);
+            }
+            counts
         };
 
         Some((
@@ -121,9 +122,7 @@ impl<CX: SequenceExtraDataConsecutiveCompression, LX: SequenceExtraData>
             match () {
                 #[cfg(feature = "support_kmer_counters")]
                 () => SequenceAbundanceType {
-                    first: abundance_first,
-                    sum: abundance_sum,
-                    last: abundance_last,
+                    counts: abundance_counts,
                 },
                 #[cfg(not(feature = "support_kmer_counters"))]
                 () => (),
@@ -140,9 +139,10 @@ impl<CX: SequenceExtraDataConsecutiveCompression, LX: SequenceExtraData>
         encode_varint(|b| writer.write_all(b).ok(), self.0).unwrap();
         #[cfg(feature = "support_kmer_counters")]
         {
-            encode_varint(|b| writer.write_all(b).ok(), self.3.first).unwrap();
-            encode_varint(|b| writer.write_all(b).ok(), self.3.sum).unwrap();
-            encode_varint(|b| writer.write_all(b).ok(), self.3.last).unwrap();
+            encode_varint(|b| writer.write_all(b).ok(), self.3.counts.len() as u64).unwrap();
+            for count in self.3.counts.iter() {
+                encode_varint(|b| writer.write_all(b).ok(), *count).unwrap();
+            }
         }
 
         self.1.encode_extended(&buffer.0, writer, last_data);
@@ -150,7 +150,15 @@ impl<CX: SequenceExtraDataConsecutiveCompression, LX: SequenceExtraData>
     }
 
     fn max_size(&self) -> usize {
-        VARINT_MAX_SIZE * 4 + self.1.max_size() + self.2.max_size()
+        VARINT_MAX_SIZE * 4
+            + self.1.max_size()
+            + self.2.max_size()
+            + match () {
+                #[cfg(feature = "support_kmer_counters")]
+                () => VARINT_MAX_SIZE * self.3.counts.len(),
+                #[cfg(not(feature = "support_kmer_counters"))]
+                () => 0,
+            }
     }
 
     fn obtain_last_data(&self, last_data: Self::LastData) -> Self::LastData {
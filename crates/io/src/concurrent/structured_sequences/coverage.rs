@@ -0,0 +1,70 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The statistic of the kmers counts of each unitig reported as its coverage (km tag)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CoverageStatistic {
+    Mean = 0,
+    /// Less affected than the mean by high coverage repetitive kmers
+    Median = 1,
+    Min = 2,
+    Max = 3,
+}
+
+static COVERAGE_STATISTIC: AtomicU8 = AtomicU8::new(CoverageStatistic::Mean as u8);
+
+pub fn set_coverage_statistic(statistic: CoverageStatistic) {
+    COVERAGE_STATISTIC.store(statistic as u8, Ordering::Relaxed);
+}
+
+pub fn get_coverage_statistic() -> CoverageStatistic {
+    match COVERAGE_STATISTIC.load(Ordering::Relaxed) {
+        0 => CoverageStatistic::Mean,
+        1 => CoverageStatistic::Median,
+        2 => CoverageStatistic::Min,
+        _ => CoverageStatistic::Max,
+    }
+}
+
+/// Computes the coverage statistic of the kmers counts of a unitig.
+/// The counts are reordered when computing the median
+pub fn compute_coverage(counts: &mut [u64], statistic: CoverageStatistic) -> f64 {
+    if counts.is_empty() {
+        return 0.0;
+    }
+
+    match statistic {
+        CoverageStatistic::Mean => counts.iter().sum::<u64>() as f64 / counts.len() as f64,
+        CoverageStatistic::Median => {
+            let middle = counts.len() / 2;
+            let (lower, median, _) = counts.select_nth_unstable(middle);
+            let median = *median;
+            if counts.len() % 2 == 0 {
+                let lower_median = *lower.iter().max().unwrap();
+                (lower_median + median) as f64 / 2.0
+            } else {
+                median as f64
+            }
+        }
+        CoverageStatistic::Min => *counts.iter().min().unwrap() as f64,
+        CoverageStatistic::Max => *counts.iter().max().unwrap() as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compute_coverage, CoverageStatistic};
+
+    #[test]
+    fn median_coverage_ignores_spike() {
+        // A repetitive kmer at the end of the unitig with a much higher coverage
+        let mut counts = vec![10, 12, 11, 10, 13, 11, 12, 500];
+
+        let mean = compute_coverage(&mut counts, CoverageStatistic::Mean);
+        let median = compute_coverage(&mut counts, CoverageStatistic::Median);
+
+        assert_eq!(median, 11.5);
+        assert!(median < mean);
+        assert_eq!(compute_coverage(&mut counts, CoverageStatistic::Min), 10.0);
+        assert_eq!(compute_coverage(&mut counts, CoverageStatistic::Max), 500.0);
+    }
+}
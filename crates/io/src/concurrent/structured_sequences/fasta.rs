@@ -8,12 +8,15 @@ use std::io::{BufWriter, Write};
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
 
+#[cfg(feature = "support_kmer_counters")]
+use super::coverage::{compute_coverage, get_coverage_statistic};
 #[cfg(feature = "support_kmer_counters")]
 use super::SequenceAbundance;
 
 pub struct FastaWriter<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter> {
     writer: Box<dyn Write>,
     path: PathBuf,
+    flush_batches: bool,
     _phantom: PhantomData<(ColorInfo, LinksInfo)>,
 }
 
@@ -42,6 +45,7 @@ impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter>
                 compress_stream,
             )),
             path: path.as_ref().to_path_buf(),
+            flush_batches: false,
             _phantom: PhantomData,
         }
     }
@@ -64,6 +68,7 @@ impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter>
                 compress_stream,
             )),
             path: path.as_ref().to_path_buf(),
+            flush_batches: false,
             _phantom: PhantomData,
         }
     }
@@ -75,6 +80,19 @@ impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter>
                 File::create(&path).unwrap(),
             )),
             path: path.as_ref().to_path_buf(),
+            flush_batches: false,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Writes the plain sequences to a sink instead of a file, flushing it after each batch
+    /// of sequences so that a consumer can read them while the graph is still being built.
+    /// The path is used only to identify the output
+    pub fn new_streaming(sink: Box<dyn Write + Send>, path: impl AsRef<Path>) -> Self {
+        FastaWriter {
+            writer: sink,
+            path: path.as_ref().to_path_buf(),
+            flush_batches: true,
             _phantom: PhantomData,
         }
     }
@@ -102,15 +120,19 @@ impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter>
         #[cfg(feature = "support_kmer_counters")] abundance: SequenceAbundance,
     ) {
         #[cfg(feature = "support_kmer_counters")]
-        write!(
-            buffer,
-            ">{} LN:i:{} KC:i:{} km:f:{:.1}",
-            sequence_index,
-            sequence.len(),
-            abundance.sum,
-            abundance.sum as f64 / (sequence.len() - _k + 1) as f64
-        )
-        .unwrap();
+        {
+            let mut abundance = abundance;
+            let sum = abundance.sum();
+            write!(
+                buffer,
+                ">{} LN:i:{} KC:i:{} km:f:{:.1}",
+                sequence_index,
+                sequence.len(),
+                sum,
+                compute_coverage(&mut abundance.counts, get_coverage_statistic())
+            )
+            .unwrap();
+        }
 
         #[cfg(not(feature = "support_kmer_counters"))]
         write!(buffer, ">{} LN:i:{}", sequence_index, sequence.len(),).unwrap();
@@ -128,6 +150,9 @@ impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter>
 
     fn flush_temp_buffer(&mut self, buffer: &mut Self::SequenceTempBuffer) {
         self.writer.write_all(buffer).unwrap();
+        if self.flush_batches {
+            self.writer.flush().unwrap();
+        }
         buffer.clear();
     }
 
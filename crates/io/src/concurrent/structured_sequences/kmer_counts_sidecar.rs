@@ -0,0 +1,208 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const KMER_COUNTS_MAGIC: [u8; 16] = *b"GGCAT_KMER_CNTS_";
+const KMER_COUNTS_VERSION: u64 = 1;
+
+// magic + version + k
+const HEADER_SIZE: u64 = 16 + 8 * 2;
+
+static KMER_COUNTS_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Also write the count of each kmer of the unitigs, as tracked while building the graph, to a file next
+/// to the output graph. The counts are available only in the builds with the kmer counters support
+pub fn set_kmer_counts_output(enabled: bool) {
+    KMER_COUNTS_OUTPUT.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_kmer_counts_output_enabled() -> bool {
+    KMER_COUNTS_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Obtains the standard kmer counts file path from a graph file path
+pub fn get_kmer_counts_file(graph_file: impl AsRef<Path>) -> PathBuf {
+    graph_file.as_ref().with_extension("kmer_counts")
+}
+
+/// Appends the record of a unitig to a buffer, with its id, its kmers count and the count of each kmer
+/// in the order of the written unitig. The counts are saturated to 32 bits
+pub fn encode_unitig_kmer_counts(buffer: &mut Vec<u8>, unitig_id: u64, counts: &[u64]) {
+    buffer.write_u64::<LittleEndian>(unitig_id).unwrap();
+    buffer
+        .write_u32::<LittleEndian>(counts.len() as u32)
+        .unwrap();
+    for count in counts {
+        buffer
+            .write_u32::<LittleEndian>((*count).min(u32::MAX as u64) as u32)
+            .unwrap();
+    }
+}
+
+/// Writes the kmer counts of the unitigs next to the output graph, in the order of the unitigs
+pub struct KmerCountsWriter {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl KmerCountsWriter {
+    pub fn create(path: impl AsRef<Path>, k: usize) -> Self {
+        let mut writer = BufWriter::new(File::create(path.as_ref()).unwrap_or_else(|err| {
+            panic!(
+                "Cannot create kmer counts file {}: {}",
+                path.as_ref().display(),
+                err
+            )
+        }));
+        writer.write_all(&KMER_COUNTS_MAGIC).unwrap();
+        writer
+            .write_u64::<LittleEndian>(KMER_COUNTS_VERSION)
+            .unwrap();
+        writer.write_u64::<LittleEndian>(k as u64).unwrap();
+
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// Writes records encoded with encode_unitig_kmer_counts
+    pub fn write_encoded(&self, records: &[u8]) {
+        self.writer.lock().write_all(records).unwrap();
+    }
+
+    pub fn finalize(self) {
+        self.writer.into_inner().flush().unwrap();
+    }
+}
+
+/// The kmer counts of the unitigs of a graph, as written while building it.
+/// The records are read at their offsets, so the counts of any unitig can be read without a lock
+pub struct KmerCountsFile {
+    file: File,
+    k: usize,
+    // The offset and kmers count of the record of each unitig, indexed by unitig id
+    records: Vec<(u64, u32)>,
+}
+
+impl KmerCountsFile {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|err| format!("Cannot open kmer counts file {}: {}", path.display(), err))?;
+        let mut reader = BufReader::new(&file);
+
+        let mut magic = [0; 16];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| format!("{} is not a kmer counts file", path.display()))?;
+        if magic != KMER_COUNTS_MAGIC {
+            return Err(format!("{} is not a kmer counts file", path.display()));
+        }
+        let version = reader
+            .read_u64::<LittleEndian>()
+            .map_err(|e| e.to_string())?;
+        if version != KMER_COUNTS_VERSION {
+            return Err(format!(
+                "Unsupported kmer counts file version {} (expected {})",
+                version, KMER_COUNTS_VERSION
+            ));
+        }
+        let k = reader
+            .read_u64::<LittleEndian>()
+            .map_err(|e| e.to_string())? as usize;
+
+        // The unitigs are written with consecutive ids, only their offsets are kept in memory
+        let mut records = vec![];
+        let mut offset = HEADER_SIZE;
+        while let Ok(unitig_id) = reader.read_u64::<LittleEndian>() {
+            let kmers_count = reader
+                .read_u32::<LittleEndian>()
+                .map_err(|_| format!("The kmer counts file {} is truncated", path.display()))?;
+            if unitig_id != records.len() as u64 {
+                return Err(format!(
+                    "The kmer counts file {} has unitig {} at position {}",
+                    path.display(),
+                    unitig_id,
+                    records.len()
+                ));
+            }
+            records.push((offset + 12, kmers_count));
+            offset += 12 + kmers_count as u64 * 4;
+            let skipped = std::io::copy(
+                &mut (&mut reader).take(kmers_count as u64 * 4),
+                &mut std::io::sink(),
+            )
+            .map_err(|e| e.to_string())?;
+            if skipped != kmers_count as u64 * 4 {
+                return Err(format!(
+                    "The kmer counts file {} is truncated",
+                    path.display()
+                ));
+            }
+        }
+        drop(reader);
+
+        Ok(Self { file, k, records })
+    }
+
+    pub fn kmer_length(&self) -> usize {
+        self.k
+    }
+
+    pub fn unitigs_count(&self) -> u64 {
+        self.records.len() as u64
+    }
+
+    /// Reads the count of each kmer of a unitig, in the order of the unitig in the graph.
+    /// Returns false if the unitig is not in the file
+    pub fn read_counts(&self, unitig_id: u64, counts: &mut Vec<u32>) -> bool {
+        counts.clear();
+        let Some((offset, kmers_count)) = self.records.get(unitig_id as usize).copied() else {
+            return false;
+        };
+
+        let mut bytes = vec![0; kmers_count as usize * 4];
+        self.file.read_exact_at(&mut bytes, offset).unwrap();
+        counts.extend(
+            bytes
+                .chunks_exact(4)
+                .map(|count| u32::from_le_bytes(count.try_into().unwrap())),
+        );
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_unitig_kmer_counts, KmerCountsFile, KmerCountsWriter};
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn kmer_counts_are_read_back_by_unitig() {
+        let temp_dir = TestDir::new("kmer_counts_are_read_back_by_unitig");
+        let path = temp_dir.path("unitigs.kmer_counts");
+
+        let unitigs_counts: [&[u64]; 3] = [&[3, 3, 4], &[1], &[7, u64::MAX]];
+        let writer = KmerCountsWriter::create(&path, 31);
+        let mut records = vec![];
+        for (unitig_id, counts) in unitigs_counts.iter().enumerate() {
+            encode_unitig_kmer_counts(&mut records, unitig_id as u64, counts);
+        }
+        writer.write_encoded(&records);
+        writer.finalize();
+
+        let file = KmerCountsFile::open(&path).unwrap();
+        assert_eq!(file.kmer_length(), 31);
+        assert_eq!(file.unitigs_count(), 3);
+
+        let mut counts = vec![];
+        assert!(file.read_counts(2, &mut counts));
+        assert_eq!(counts, [7, u32::MAX]);
+        assert!(file.read_counts(0, &mut counts));
+        assert_eq!(counts, [3, 3, 4]);
+        assert!(!file.read_counts(3, &mut counts));
+    }
+}
@@ -0,0 +1,121 @@
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// 0 disables the low coverage BED output
+static LOW_COVERAGE_BED_MIN_COVERAGE: AtomicU64 = AtomicU64::new(0);
+
+/// Also write the stretches of the unitigs where the coverage of the bases, as tracked while building the graph,
+/// is below min_coverage as a BED file next to the output graph. None disables the BED output.
+/// The coverage is available only in the builds with the kmer counters support
+pub fn set_low_coverage_bed(min_coverage: Option<u32>) {
+    LOW_COVERAGE_BED_MIN_COVERAGE.store(min_coverage.unwrap_or(0) as u64, Ordering::Relaxed);
+}
+
+pub fn get_low_coverage_bed_min_coverage() -> Option<u64> {
+    match LOW_COVERAGE_BED_MIN_COVERAGE.load(Ordering::Relaxed) {
+        0 => None,
+        min_coverage => Some(min_coverage),
+    }
+}
+
+/// Obtains the standard low coverage BED file path from a graph file path
+pub fn get_low_coverage_bed_file(graph_file: impl AsRef<Path>) -> PathBuf {
+    graph_file.as_ref().with_extension("low_coverage.bed")
+}
+
+/// The coverage of each base of a unitig, as the maximum count of the kmers covering it,
+/// given the count of each kmer of the unitig in order
+pub fn bases_coverage(kmers_counts: &[u64], k: usize) -> Vec<u64> {
+    if kmers_counts.is_empty() {
+        return vec![];
+    }
+
+    let mut coverage = vec![0; kmers_counts.len() + k - 1];
+    for (offset, count) in kmers_counts.iter().enumerate() {
+        for base_coverage in &mut coverage[offset..offset + k] {
+            *base_coverage = (*base_coverage).max(*count);
+        }
+    }
+    coverage
+}
+
+/// Finds the runs of bases with a coverage below min_coverage, as 0-based, end exclusive intervals
+pub fn low_coverage_intervals(bases_coverage: &[u64], min_coverage: u64) -> Vec<(usize, usize)> {
+    let mut intervals = vec![];
+    let mut start = None;
+
+    for (position, coverage) in bases_coverage.iter().enumerate() {
+        match (start, *coverage < min_coverage) {
+            (None, true) => start = Some(position),
+            (Some(interval_start), false) => {
+                intervals.push((interval_start, position));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(interval_start) = start {
+        intervals.push((interval_start, bases_coverage.len()));
+    }
+    intervals
+}
+
+/// Writes the low coverage stretches of the unitigs as a BED file, with the unitig id and the
+/// 0-based, end exclusive interval of each stretch, in the order of the unitigs
+pub struct LowCoverageBedWriter {
+    writer: Mutex<BufWriter<File>>,
+    min_coverage: u64,
+}
+
+impl LowCoverageBedWriter {
+    pub fn create(path: impl AsRef<Path>, min_coverage: u64) -> Self {
+        let writer = BufWriter::new(File::create(path.as_ref()).unwrap_or_else(|err| {
+            panic!(
+                "Cannot create low coverage file {}: {}",
+                path.as_ref().display(),
+                err
+            )
+        }));
+
+        Self {
+            writer: Mutex::new(writer),
+            min_coverage,
+        }
+    }
+
+    /// Appends the BED lines of the low coverage stretches of a unitig to a buffer,
+    /// given the count of each kmer of the unitig in order
+    pub fn encode_unitig(&self, buffer: &mut Vec<u8>, unitig_id: u64, counts: &[u64], k: usize) {
+        let coverage = bases_coverage(counts, k);
+        for (start, end) in low_coverage_intervals(&coverage, self.min_coverage) {
+            writeln!(buffer, "{}\t{}\t{}", unitig_id, start, end).unwrap();
+        }
+    }
+
+    /// Writes lines encoded with encode_unitig
+    pub fn write_encoded(&self, lines: &[u8]) {
+        self.writer.lock().write_all(lines).unwrap();
+    }
+
+    pub fn finalize(self) {
+        self.writer.into_inner().flush().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bases_coverage, low_coverage_intervals};
+
+    #[test]
+    fn low_coverage_bases_are_covered_by_low_count_kmers_only() {
+        // k = 3, the middle kmer has a low count but its bases are covered by the neighbours too
+        let coverage = bases_coverage(&[5, 1, 5, 1, 1], 3);
+        assert_eq!(coverage, [5, 5, 5, 5, 5, 1, 1]);
+        assert_eq!(low_coverage_intervals(&coverage, 2), [(5, 7)]);
+        assert_eq!(low_coverage_intervals(&coverage, 6), [(0, 7)]);
+        assert!(low_coverage_intervals(&coverage, 1).is_empty());
+    }
+}
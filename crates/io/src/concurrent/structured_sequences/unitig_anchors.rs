@@ -0,0 +1,160 @@
+use hashes::{ExtendableHashTraitType, HashFunction, HashFunctionFactory};
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static UNITIG_ANCHORS_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Also write the hashes of the first and the last kmer of each unitig, computed with the kmers hash
+/// of the build, to a file next to the output graph. The hashes depend only on the kmers sequences,
+/// so they are the same across the builds with the same hash, k and strand mode
+pub fn set_unitig_anchors_output(enabled: bool) {
+    UNITIG_ANCHORS_OUTPUT.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_unitig_anchors_output_enabled() -> bool {
+    UNITIG_ANCHORS_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Obtains the standard unitig anchors file path from a graph file path
+pub fn get_unitig_anchors_file(graph_file: impl AsRef<Path>) -> PathBuf {
+    graph_file.as_ref().with_extension("anchors.tsv")
+}
+
+/// The hashes of the first and the last kmer of a unitig, None if it is shorter than k.
+/// With a canonical hash they are swapped if the unitig is written on the other strand
+pub fn unitig_anchor_hashes<H: HashFunctionFactory>(
+    sequence: &[u8],
+    k: usize,
+) -> Option<(H::HashTypeUnextendable, H::HashTypeUnextendable)> {
+    if sequence.len() < k {
+        return None;
+    }
+
+    let mut anchors = None;
+    for (_, hash) in H::new(sequence, k).iter_enumerate() {
+        let hash = hash.to_unextendable();
+        anchors = Some(match anchors {
+            None => (hash, hash),
+            Some((first, _)) => (first, hash),
+        });
+    }
+    anchors
+}
+
+/// Appends the anchors line of a unitig to a buffer, with its id and the hashes of its first and last kmer
+pub fn encode_unitig_anchors<H: HashFunctionFactory>(
+    buffer: &mut Vec<u8>,
+    unitig_id: u64,
+    sequence: &[u8],
+    k: usize,
+) {
+    if let Some((first_hash, last_hash)) = unitig_anchor_hashes::<H>(sequence, k) {
+        writeln!(buffer, "{}\t{}\t{}", unitig_id, first_hash, last_hash).unwrap();
+    }
+}
+
+/// Encodes the anchors line of a unitig with the kmers hash of the build, as encode_unitig_anchors
+pub type UnitigAnchorsEncoder = fn(&mut Vec<u8>, u64, &[u8], usize);
+
+/// Writes the anchors of the unitigs next to the output graph, in the order of the unitigs
+pub struct UnitigAnchorsWriter {
+    writer: Mutex<BufWriter<File>>,
+    encoder: UnitigAnchorsEncoder,
+}
+
+impl UnitigAnchorsWriter {
+    pub fn create(path: impl AsRef<Path>, encoder: UnitigAnchorsEncoder) -> Self {
+        let mut writer = BufWriter::new(File::create(path.as_ref()).unwrap_or_else(|err| {
+            panic!(
+                "Cannot create unitig anchors file {}: {}",
+                path.as_ref().display(),
+                err
+            )
+        }));
+        writeln!(writer, "#unitig_id\tfirst_kmer_hash\tlast_kmer_hash").unwrap();
+
+        Self {
+            writer: Mutex::new(writer),
+            encoder,
+        }
+    }
+
+    pub fn encode_unitig(&self, buffer: &mut Vec<u8>, unitig_id: u64, sequence: &[u8], k: usize) {
+        (self.encoder)(buffer, unitig_id, sequence, k);
+    }
+
+    /// Writes lines encoded with encode_unitig
+    pub fn write_encoded(&self, lines: &[u8]) {
+        self.writer.lock().write_all(lines).unwrap();
+    }
+
+    pub fn finalize(self) {
+        self.writer.into_inner().flush().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unitig_anchor_hashes;
+    use hashes::cn_seqhash::u64::CanonicalSeqHashFactory;
+    use hashes::fw_seqhash::u64::ForwardSeqHashFactory;
+    use hashes::{ExtendableHashTraitType, HashFunction, HashFunctionFactory};
+
+    fn kmer_hash<H: HashFunctionFactory>(kmer: &[u8]) -> H::HashTypeUnextendable {
+        H::new(kmer, kmer.len())
+            .iter()
+            .next()
+            .unwrap()
+            .to_unextendable()
+    }
+
+    #[test]
+    fn anchors_are_the_hashes_of_the_unitig_ends() {
+        const K: usize = 7;
+        let unitig = b"ACGGTTCAGGATCCATG".to_vec();
+        let reverse: Vec<_> = unitig
+            .iter()
+            .rev()
+            .map(|b| match *b {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                _ => b'A',
+            })
+            .collect();
+
+        let canonical = (
+            kmer_hash::<CanonicalSeqHashFactory>(&unitig[..K]),
+            kmer_hash::<CanonicalSeqHashFactory>(&unitig[unitig.len() - K..]),
+        );
+        assert_eq!(
+            unitig_anchor_hashes::<CanonicalSeqHashFactory>(&unitig, K),
+            Some(canonical)
+        );
+        assert_eq!(
+            unitig_anchor_hashes::<CanonicalSeqHashFactory>(&reverse, K),
+            Some((canonical.1, canonical.0))
+        );
+        assert_eq!(
+            unitig_anchor_hashes::<CanonicalSeqHashFactory>(&unitig[..K - 1], K),
+            None
+        );
+
+        // The forward-only hashes of the other strand are different kmers
+        let forward = (
+            kmer_hash::<ForwardSeqHashFactory>(&unitig[..K]),
+            kmer_hash::<ForwardSeqHashFactory>(&unitig[unitig.len() - K..]),
+        );
+        assert_eq!(
+            unitig_anchor_hashes::<ForwardSeqHashFactory>(&unitig, K),
+            Some(forward)
+        );
+        assert_ne!(
+            unitig_anchor_hashes::<ForwardSeqHashFactory>(&reverse, K),
+            Some((forward.1, forward.0))
+        );
+    }
+}
@@ -1,12 +1,94 @@
 use crate::compressed_read::CompressedRead;
 use crate::varint::{decode_varint_flags, encode_varint_flags, VARINT_FLAGS_MAX_SIZE};
-use byteorder::ReadBytesExt;
+use byteorder::{LittleEndian, ReadBytesExt};
 use parallel_processor::buckets::bucket_writer::BucketItemSerializer;
+use parking_lot::Mutex;
 use std::io::Read;
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 use super::extra_data::SequenceExtraDataConsecutiveCompression;
 
+static FIXED_WIDTH_READS_SIZES: AtomicBool = AtomicBool::new(false);
+
+/// Encoding of the bases count and flags of each read in the temporary buckets
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReadsSizeEncoding {
+    /// A varint sharing its first byte with the flags, smaller for short reads
+    Varint = 0,
+    /// A flags byte followed by a 4 bytes size, avoiding the varint decoding branches
+    /// when the reads are known to be uniformly long
+    FixedWidth = 1,
+}
+
+impl ReadsSizeEncoding {
+    fn from_header(header: u8) -> Option<Self> {
+        match header {
+            0 => Some(Self::Varint),
+            1 => Some(Self::FixedWidth),
+            _ => None,
+        }
+    }
+}
+
+/// Selects the encoding of the reads sizes in the newly written buckets. The encoding is stored
+/// in a header before the first read of each bucket chunk, so the buckets can always be read back
+pub fn set_reads_size_encoding(encoding: ReadsSizeEncoding) {
+    FIXED_WIDTH_READS_SIZES.store(encoding == ReadsSizeEncoding::FixedWidth, Ordering::Relaxed);
+}
+
+fn get_reads_size_encoding() -> ReadsSizeEncoding {
+    if FIXED_WIDTH_READS_SIZES.load(Ordering::Relaxed) {
+        ReadsSizeEncoding::FixedWidth
+    } else {
+        ReadsSizeEncoding::Varint
+    }
+}
+
+// Flags byte + 4 bytes size
+const FIXED_WIDTH_SIZE: usize = 5;
+
+// The fixed width encoding cannot store longer reads
+const DEFAULT_MAX_READ_LENGTH: u64 = u32::MAX as u64;
+
+static MAX_READ_LENGTH: AtomicU64 = AtomicU64::new(DEFAULT_MAX_READ_LENGTH);
+
+/// Sets the maximum bases count of a read in the temporary buckets. A longer read can only come
+/// from a corrupted record, that is rejected before allocating the buffer for its bases
+pub fn set_max_read_length(max_bases: u64) {
+    MAX_READ_LENGTH.store(max_bases, Ordering::Relaxed);
+}
+
+static CORRUPTED_RECORD: Mutex<Option<String>> = Mutex::new(None);
+
+/// Returns an error describing the first corrupted record found in the temporary buckets since the
+/// last call. A corrupted record ends the items of its bucket, so the pipelines check this after
+/// reading the buckets instead of using a truncated bucket
+pub fn take_corrupted_record_error() -> Result<(), String> {
+    match CORRUPTED_RECORD.lock().take() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+fn report_corrupted_record(error: String) {
+    CORRUPTED_RECORD.lock().get_or_insert(error);
+}
+
+// The fixed width encoding stores the bases count in 4 bytes, a longer read cannot be written with it
+fn fixed_width_read_size(bases_count: usize) -> [u8; 4] {
+    u32::try_from(bases_count)
+        .unwrap_or_else(|_| {
+            panic!(
+                "Read of {} bases is too long for the fixed width reads size encoding, that stores up to {} bases",
+                bases_count,
+                u32::MAX
+            )
+        })
+        .to_le_bytes()
+}
+
+#[derive(Copy, Clone)]
 enum ReadData<'a> {
     Plain(&'a [u8]),
     Packed(CompressedRead<'a>),
@@ -44,6 +126,10 @@ pub struct CompressedReadsBucketDataSerializer<
     const WITH_SECOND_BUCKET: bool,
 > {
     last_data: E::LastData,
+    // Encoding of the current chunk, None if its header was not yet written or read
+    size_encoding: Option<ReadsSizeEncoding>,
+    // Encoding selected for the chunks written by this serializer
+    write_size_encoding: ReadsSizeEncoding,
     _phantom: PhantomData<FlagsCount>,
 }
 
@@ -65,6 +151,8 @@ impl<
     fn new() -> Self {
         Self {
             last_data: Default::default(),
+            size_encoding: None,
+            write_size_encoding: get_reads_size_encoding(),
             _phantom: PhantomData,
         }
     }
@@ -72,6 +160,7 @@ impl<
     #[inline(always)]
     fn reset(&mut self) {
         self.last_data = Default::default();
+        self.size_encoding = None;
     }
 
     #[inline(always)]
@@ -82,6 +171,16 @@ impl<
         extra_data: &Self::ExtraData,
         extra_data_buffer: &Self::ExtraDataBuffer,
     ) {
+        let size_encoding = match self.size_encoding {
+            Some(size_encoding) => size_encoding,
+            None => {
+                let size_encoding = self.write_size_encoding;
+                bucket.push(size_encoding as u8);
+                self.size_encoding = Some(size_encoding);
+                size_encoding
+            }
+        };
+
         if WITH_SECOND_BUCKET {
             bucket.push(element.extra_bucket);
         }
@@ -89,15 +188,20 @@ impl<
         extra_data.encode_extended(extra_data_buffer, bucket, self.last_data);
         self.last_data = extra_data.obtain_last_data(self.last_data);
 
-        match element.read {
-            ReadData::Plain(read) => {
+        match (element.read, size_encoding) {
+            (ReadData::Plain(read), ReadsSizeEncoding::Varint) => {
                 CompressedRead::from_plain_write_directly_to_buffer_with_flags::<FlagsCount>(
                     read,
                     bucket,
                     element.flags,
                 );
             }
-            ReadData::Packed(read) => {
+            (ReadData::Plain(read), ReadsSizeEncoding::FixedWidth) => {
+                bucket.push(element.flags);
+                bucket.extend_from_slice(&fixed_width_read_size(read.len()));
+                CompressedRead::compress_from_plain(read, |b| bucket.extend_from_slice(b));
+            }
+            (ReadData::Packed(read), ReadsSizeEncoding::Varint) => {
                 encode_varint_flags::<_, _, FlagsCount>(
                     |b| bucket.extend_from_slice(b),
                     read.size as u64,
@@ -105,6 +209,11 @@ impl<
                 );
                 read.copy_to_buffer(bucket);
             }
+            (ReadData::Packed(read), ReadsSizeEncoding::FixedWidth) => {
+                bucket.push(element.flags);
+                bucket.extend_from_slice(&fixed_width_read_size(read.size));
+                read.copy_to_buffer(bucket);
+            }
         }
     }
 
@@ -115,6 +224,15 @@ impl<
         read_buffer: &'b mut Self::ReadBuffer,
         extra_read_buffer: &mut Self::ExtraDataBuffer,
     ) -> Option<Self::ReadType<'b>> {
+        let size_encoding = match self.size_encoding {
+            Some(size_encoding) => size_encoding,
+            None => {
+                let size_encoding = ReadsSizeEncoding::from_header(stream.read_u8().ok()?)?;
+                self.size_encoding = Some(size_encoding);
+                size_encoding
+            }
+        };
+
         let second_bucket = if WITH_SECOND_BUCKET {
             stream.read_u8().ok()?
         } else {
@@ -124,12 +242,29 @@ impl<
         let extra = E::decode_extended(extra_read_buffer, &mut stream, self.last_data)?;
         self.last_data = extra.obtain_last_data(self.last_data);
 
-        let (size, flags) = decode_varint_flags::<_, FlagsCount>(|| stream.read_u8().ok())?;
+        let (size, flags) = match size_encoding {
+            ReadsSizeEncoding::Varint => {
+                decode_varint_flags::<_, FlagsCount>(|| stream.read_u8().ok())?
+            }
+            ReadsSizeEncoding::FixedWidth => {
+                let flags = stream.read_u8().ok()?;
+                (stream.read_u32::<LittleEndian>().ok()? as u64, flags)
+            }
+        };
 
         if size == 0 {
             return None;
         }
 
+        let max_read_length = MAX_READ_LENGTH.load(Ordering::Relaxed);
+        if size > max_read_length {
+            report_corrupted_record(format!(
+                "Corrupted temporary bucket record: read of {} bases, longer than the maximum of {} bases",
+                size, max_read_length
+            ));
+            return None;
+        }
+
         read_buffer.clear();
 
         let bytes = ((size + 3) / 4) as usize;
@@ -139,7 +274,14 @@ impl<
             read_buffer.set_len(buffer_start + bytes);
         }
 
-        stream.read_exact(&mut read_buffer[buffer_start..]).unwrap();
+        if let Err(err) = stream.read_exact(&mut read_buffer[buffer_start..]) {
+            read_buffer.truncate(buffer_start);
+            report_corrupted_record(format!(
+                "Corrupted temporary bucket record: read of {} bases is truncated: {}",
+                size, err
+            ));
+            return None;
+        }
 
         Some((
             flags,
@@ -156,9 +298,155 @@ impl<
             ReadData::Packed(read) => read.size,
         };
 
-        ((bases_count + 3) / 4)
+        // The size encoding header can be written before the read
+        1 + ((bases_count + 3) / 4)
             + extra.max_size()
-            + VARINT_FLAGS_MAX_SIZE
+            + VARINT_FLAGS_MAX_SIZE.max(FIXED_WIDTH_SIZE)
             + if WITH_SECOND_BUCKET { 1 } else { 0 }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        take_corrupted_record_error, CompressedReadsBucketData,
+        CompressedReadsBucketDataSerializer, ReadsSizeEncoding,
+    };
+    use crate::compressed_read::CompressedReadIndipendent;
+    use crate::varint::encode_varint_flags;
+    use parallel_processor::buckets::bucket_writer::BucketItemSerializer;
+    use parking_lot::Mutex;
+
+    // The corrupted records are reported to a global error, checked by one test at a time
+    static CORRUPTED_RECORD_LOCK: Mutex<()> = Mutex::new(());
+
+    // The records are made only of single bytes, varints and bases packed in little endian order,
+    // so their encoding must not depend on the byte order of the machine that writes them
+    #[test]
+    fn records_encoding_is_byte_order_independent() {
+        const SEQUENCE: &[u8] = b"ACGTACGTACGTACGTTG";
+
+        let mut serializer = CompressedReadsBucketDataSerializer::<(), typenum::U2, false>::new();
+        let mut bucket = vec![];
+        serializer.write_to(
+            &CompressedReadsBucketData::new(SEQUENCE, 1, 0),
+            &mut bucket,
+            &(),
+            &(),
+        );
+
+        // The size encoding header, then flags and size in the first byte,
+        // then 4 bases per byte starting from the low bits
+        assert_eq!(bucket, vec![0x00, 0x52, 0xb4, 0xb4, 0xb4, 0xb4, 0x0e]);
+
+        let mut deserializer = CompressedReadsBucketDataSerializer::<(), typenum::U2, false>::new();
+        let mut read_buffer = vec![];
+        let (flags, _, _, read) = deserializer
+            .read_from(&bucket[..], &mut read_buffer, &mut ())
+            .unwrap();
+
+        assert_eq!(flags, 1);
+        assert_eq!(read.to_string().as_bytes(), SEQUENCE);
+    }
+
+    #[test]
+    fn absurd_read_size_is_rejected_before_allocating() {
+        let _lock = CORRUPTED_RECORD_LOCK.lock();
+
+        // The size encoding header, then a varint size of 2^40 bases
+        let mut bucket = vec![ReadsSizeEncoding::Varint as u8];
+        encode_varint_flags::<_, _, typenum::U2>(|b| bucket.extend_from_slice(b), 1 << 40, 0);
+        bucket.extend_from_slice(&[0; 16]);
+
+        let mut deserializer = CompressedReadsBucketDataSerializer::<(), typenum::U2, false>::new();
+        let mut read_buffer = vec![];
+        assert!(deserializer
+            .read_from(&bucket[..], &mut read_buffer, &mut ())
+            .is_none());
+        assert_eq!(read_buffer.capacity(), 0);
+
+        let error = take_corrupted_record_error().unwrap_err();
+        assert!(error.starts_with("Corrupted temporary bucket record: read of 1099511627776 bases"));
+        // The error is reported once
+        assert!(take_corrupted_record_error().is_ok());
+    }
+
+    #[test]
+    fn truncated_read_is_reported_as_corrupted() {
+        let _lock = CORRUPTED_RECORD_LOCK.lock();
+
+        let mut serializer = CompressedReadsBucketDataSerializer::<(), typenum::U2, false>::new();
+        serializer.write_size_encoding = ReadsSizeEncoding::FixedWidth;
+        let mut bucket = vec![];
+        serializer.write_to(
+            &CompressedReadsBucketData::new(b"ACGTACGTACGTACGTTG", 0, 0),
+            &mut bucket,
+            &(),
+            &(),
+        );
+        // The bucket ends in the middle of the bases of the read
+        bucket.truncate(bucket.len() - 2);
+
+        let mut deserializer = CompressedReadsBucketDataSerializer::<(), typenum::U2, false>::new();
+        let mut read_buffer = vec![];
+        assert!(deserializer
+            .read_from(&bucket[..], &mut read_buffer, &mut ())
+            .is_none());
+        assert!(read_buffer.is_empty());
+
+        let error = take_corrupted_record_error().unwrap_err();
+        assert!(
+            error.starts_with("Corrupted temporary bucket record: read of 18 bases is truncated")
+        );
+    }
+
+    #[test]
+    fn long_reads_round_trip_with_both_size_encodings() {
+        let reads: Vec<Vec<u8>> = [100_000, 1, 70_001, 255, 16_384]
+            .iter()
+            .map(|length| (0..*length).map(|i| b"ACGT"[(i * 7 + i / 3) % 4]).collect())
+            .collect();
+
+        for encoding in [ReadsSizeEncoding::Varint, ReadsSizeEncoding::FixedWidth] {
+            let mut serializer =
+                CompressedReadsBucketDataSerializer::<(), typenum::U2, true>::new();
+            serializer.write_size_encoding = encoding;
+
+            let mut packed_storage = vec![];
+            let mut bucket = vec![];
+            for (index, read) in reads.iter().enumerate() {
+                let flags = (index % 4) as u8;
+                packed_storage.clear();
+                let packed = CompressedReadIndipendent::from_plain(read, &mut packed_storage);
+
+                // Alternate the plain and the packed inputs
+                let data = if index % 2 == 0 {
+                    CompressedReadsBucketData::new(read, flags, index as u8)
+                } else {
+                    CompressedReadsBucketData::new_packed(
+                        packed.as_reference(&packed_storage),
+                        flags,
+                        index as u8,
+                    )
+                };
+                serializer.write_to(&data, &mut bucket, &(), &());
+            }
+            assert_eq!(bucket[0], encoding as u8);
+
+            // The reader does not know the encoding in advance, it is read from the header
+            let mut deserializer =
+                CompressedReadsBucketDataSerializer::<(), typenum::U2, true>::new();
+            let mut stream = &bucket[..];
+            let mut read_buffer = vec![];
+            for (index, read) in reads.iter().enumerate() {
+                let (flags, second_bucket, _, decoded) = deserializer
+                    .read_from(&mut stream, &mut read_buffer, &mut ())
+                    .unwrap();
+                assert_eq!(flags, (index % 4) as u8);
+                assert_eq!(second_bucket, index as u8);
+                assert_eq!(decoded.to_string().as_bytes(), &read[..]);
+            }
+            assert!(stream.is_empty());
+        }
+    }
+}
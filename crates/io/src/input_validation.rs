@@ -0,0 +1,375 @@
+use crate::lines_reader::LinesReader;
+use std::collections::HashMap;
+use std::path::Path;
+use utils::phase_log::log_warning;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum LineKind {
+    Header,
+    Sequence,
+    Comment,
+}
+
+// Nucleotides and IUPAC ambiguity codes
+pub(crate) const fn is_sequence_char(c: u8) -> bool {
+    matches!(
+        c.to_ascii_uppercase(),
+        b'A' | b'C'
+            | b'G'
+            | b'T'
+            | b'U'
+            | b'N'
+            | b'R'
+            | b'Y'
+            | b'K'
+            | b'M'
+            | b'S'
+            | b'W'
+            | b'B'
+            | b'D'
+            | b'H'
+            | b'V'
+    )
+}
+
+struct FastaValidator {
+    line_number: usize,
+    new_line: bool,
+    line_kind: LineKind,
+    header: Vec<u8>,
+    header_line: usize,
+    records_count: usize,
+    record_bases: usize,
+    headers: HashMap<Vec<u8>, usize>,
+}
+
+impl FastaValidator {
+    fn new() -> Self {
+        Self {
+            line_number: 0,
+            new_line: true,
+            line_kind: LineKind::Sequence,
+            header: vec![],
+            header_line: 0,
+            records_count: 0,
+            record_bases: 0,
+            headers: HashMap::new(),
+        }
+    }
+
+    fn record_name(&self) -> String {
+        let name = self.header[1..]
+            .split(|c| c.is_ascii_whitespace())
+            .next()
+            .unwrap_or(&[]);
+        String::from_utf8_lossy(name).to_string()
+    }
+
+    fn end_header(&mut self) -> Result<(), String> {
+        let name = self.record_name();
+        if name.is_empty() {
+            return Err(format!("line {}: empty header", self.header_line));
+        }
+
+        if let Some(first_line) = self.headers.get(name.as_bytes()) {
+            return Err(format!(
+                "line {}: duplicate header '{}', first found at line {}",
+                self.header_line, name, first_line
+            ));
+        }
+        self.headers.insert(name.into_bytes(), self.header_line);
+        Ok(())
+    }
+
+    fn end_record(&mut self) -> Result<(), String> {
+        if self.records_count > 0 && self.record_bases == 0 {
+            return Err(format!(
+                "line {}: record '{}' has no sequence",
+                self.header_line,
+                self.record_name()
+            ));
+        }
+        Ok(())
+    }
+
+    fn process_line(&mut self, line: &[u8], partial: bool, finished: bool) -> Result<(), String> {
+        if finished {
+            // The last line has no newline at its end
+            if !self.new_line && self.line_kind == LineKind::Header {
+                self.end_header()?;
+            }
+            self.end_record()?;
+
+            if self.records_count == 0 {
+                return Err("the file contains no sequences".to_string());
+            }
+            return Ok(());
+        }
+
+        if self.new_line {
+            self.line_number += 1;
+            self.line_kind = match line.first() {
+                Some(b'>') => {
+                    self.end_record()?;
+                    self.records_count += 1;
+                    self.record_bases = 0;
+                    self.header.clear();
+                    self.header_line = self.line_number;
+                    LineKind::Header
+                }
+                Some(b';') => LineKind::Comment,
+                _ => LineKind::Sequence,
+            };
+        }
+
+        match self.line_kind {
+            LineKind::Header => self.header.extend_from_slice(line),
+            LineKind::Comment => {}
+            LineKind::Sequence => {
+                if self.records_count == 0 && !line.is_empty() {
+                    return Err(format!(
+                        "line {}: sequence data found before the first header",
+                        self.line_number
+                    ));
+                }
+
+                if let Some(invalid) = line.iter().find(|c| !is_sequence_char(**c)) {
+                    return Err(format!(
+                        "line {}: invalid sequence character '{}'",
+                        self.line_number,
+                        (*invalid as char).escape_default()
+                    ));
+                }
+                self.record_bases += line.len();
+            }
+        }
+
+        self.new_line = !partial;
+        if !partial && self.line_kind == LineKind::Header {
+            self.end_header()?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a fasta file is well formed, failing on the first malformed record with an error
+/// describing the problem and its line. Empty files, sequence lines before the first header,
+/// empty or duplicated headers, empty records and characters that are not nucleotides or IUPAC codes are rejected
+pub fn validate_fasta_file(path: impl AsRef<Path>) -> Result<(), String> {
+    let path = path.as_ref();
+    let mut validator = FastaValidator::new();
+    let mut result = Ok(());
+
+    LinesReader::new().process_lines(
+        path,
+        |line, partial, finished| {
+            if result.is_ok() {
+                result = validator.process_line(line, partial, finished);
+            }
+        },
+        false,
+    );
+
+    result.map_err(|err| format!("Malformed input file '{}': {}", path.display(), err))
+}
+
+/// The number of queries of a query set in each validity category
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueriesValidation {
+    /// The queries with at least one kmer and only nucleotides or IUPAC codes
+    pub valid: usize,
+    /// The queries shorter than the kmers length, that have no kmers to be queried
+    pub too_short: usize,
+    /// The queries with a header and no sequence
+    pub empty: usize,
+    /// The queries with characters that are not nucleotides or IUPAC codes,
+    /// and the sequence data found before the first header
+    pub malformed: usize,
+}
+
+impl QueriesValidation {
+    pub fn invalid_count(&self) -> usize {
+        self.too_short + self.empty + self.malformed
+    }
+}
+
+struct QueriesScanner {
+    k: usize,
+    new_line: bool,
+    line_kind: LineKind,
+    record_open: bool,
+    record_bases: usize,
+    record_malformed: bool,
+    counts: QueriesValidation,
+}
+
+impl QueriesScanner {
+    fn end_record(&mut self) {
+        if !self.record_open {
+            return;
+        }
+        if self.record_malformed {
+            self.counts.malformed += 1;
+        } else if self.record_bases == 0 {
+            self.counts.empty += 1;
+        } else if self.record_bases < self.k {
+            self.counts.too_short += 1;
+        } else {
+            self.counts.valid += 1;
+        }
+        self.record_open = false;
+    }
+
+    fn process_line(&mut self, line: &[u8], partial: bool, finished: bool) {
+        if finished {
+            self.end_record();
+            return;
+        }
+
+        if self.new_line {
+            self.line_kind = match line.first() {
+                Some(b'>') => {
+                    self.end_record();
+                    self.record_open = true;
+                    self.record_bases = 0;
+                    self.record_malformed = false;
+                    LineKind::Header
+                }
+                Some(b';') => LineKind::Comment,
+                _ => LineKind::Sequence,
+            };
+        }
+
+        if self.line_kind == LineKind::Sequence && !line.is_empty() {
+            // The data before the first header is counted as a single malformed query
+            if !self.record_open {
+                self.record_open = true;
+                self.record_bases = 0;
+                self.record_malformed = true;
+            }
+            self.record_malformed |= !line.iter().all(|c| is_sequence_char(*c));
+            self.record_bases += line.len();
+        }
+
+        self.new_line = !partial;
+    }
+}
+
+/// Scans the fasta query files without running the query, counting the valid, too short (with less than k bases),
+/// empty and malformed queries, with the same characters accepted by the strict input validation.
+/// With strict_input an error is returned if any query is not valid, otherwise a warning is logged
+pub fn validate_queries(
+    query_files: &[impl AsRef<Path>],
+    k: usize,
+    strict_input: bool,
+) -> Result<QueriesValidation, String> {
+    let mut scanner = QueriesScanner {
+        k,
+        new_line: true,
+        line_kind: LineKind::Sequence,
+        record_open: false,
+        record_bases: 0,
+        record_malformed: false,
+        counts: QueriesValidation::default(),
+    };
+
+    for file in query_files {
+        scanner.new_line = true;
+        LinesReader::new().process_lines(
+            file,
+            |line, partial, finished| scanner.process_line(line, partial, finished),
+            false,
+        );
+    }
+
+    let counts = scanner.counts;
+    if counts.invalid_count() > 0 {
+        let message = format!(
+            "{} of the {} queries are not valid: {} empty, {} shorter than k, {} malformed",
+            counts.invalid_count(),
+            counts.valid + counts.invalid_count(),
+            counts.empty,
+            counts.too_short,
+            counts.malformed
+        );
+        if strict_input {
+            return Err(message);
+        }
+        log_warning(format!("WARNING: {}", message));
+    }
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_fasta_file, validate_queries, QueriesValidation};
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    fn validate(name: &str, content: &str) -> Result<(), String> {
+        let temp_dir = TestDir::new(&format!("validation-{}", name));
+        let path = temp_dir.path("input.fa");
+        std::fs::File::create(&path)
+            .unwrap()
+            .write_all(content.as_bytes())
+            .unwrap();
+        let result = validate_fasta_file(&path);
+        result.map_err(|err| err.split("': ").nth(1).unwrap().to_string())
+    }
+
+    #[test]
+    fn strict_fasta_validation() {
+        assert_eq!(
+            validate("valid", ">seq1 desc\nACGTN\nacgt\n; comment\n>seq2\nRYKM"),
+            Ok(())
+        );
+
+        assert_eq!(
+            validate("empty", ""),
+            Err("the file contains no sequences".to_string())
+        );
+
+        assert_eq!(
+            validate("before-header", "\nACGT\n>seq1\nACGT\n"),
+            Err("line 2: sequence data found before the first header".to_string())
+        );
+
+        assert_eq!(
+            validate("duplicate", ">seq1\nACGT\n>seq2\nACGT\n>seq1 other\nACGT\n"),
+            Err("line 5: duplicate header 'seq1', first found at line 1".to_string())
+        );
+
+        assert_eq!(
+            validate("invalid-char", ">seq1\nACGT\nAC-GT\n"),
+            Err("line 3: invalid sequence character '-'".to_string())
+        );
+
+        assert_eq!(
+            validate("empty-record", ">seq1\n>seq2\nACGT\n"),
+            Err("line 1: record 'seq1' has no sequence".to_string())
+        );
+    }
+
+    #[test]
+    fn query_set_validation_counts() {
+        let temp_dir = TestDir::new("query_set_validation_counts");
+        let path = temp_dir.path("validation-queries.fa");
+        std::fs::write(
+            &path,
+            ">valid1\nACGTACGTAC\n>short\nACG\n>empty\n>malformed\nACGT-ACGT\n\
+             ; comment\n>valid2\nACGTA\nCGTAC\n>empty2\n",
+        )
+        .unwrap();
+        let result = validate_queries(&[&path], 5, false);
+
+        assert_eq!(
+            result,
+            Ok(QueriesValidation {
+                valid: 2,
+                too_short: 1,
+                empty: 2,
+                malformed: 1,
+            })
+        );
+    }
+}
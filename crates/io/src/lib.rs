@@ -3,14 +3,24 @@ use config::{BucketIndexType, MAX_BUCKETS_COUNT_LOG, MAX_BUCKET_SIZE, MIN_BUCKET
 use std::cmp::{max, min};
 use std::path::{Path, PathBuf};
 
+pub mod bucket_items;
 pub mod chunks_writer;
+pub mod circular_inputs;
 pub mod compressed_read;
+pub mod compression_tuning;
 pub mod concurrent;
+pub mod input_validation;
 pub mod lines_reader;
+pub mod mmap_sink;
+pub mod n_gaps;
+pub mod open_files_limit;
 // pub mod reads_writer;
 pub mod sequences_reader;
 pub mod sequences_stream;
 pub mod structs;
+pub mod unitig_links;
+pub mod unused_reads;
+pub mod url_reader;
 pub mod varint;
 
 pub fn get_bucket_index(bucket_file: impl AsRef<Path>) -> BucketIndexType {
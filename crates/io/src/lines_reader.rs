@@ -1,16 +1,28 @@
+use crate::url_reader::{is_url_input, UrlReader};
 use bstr::ByteSlice;
 use config::DEFAULT_OUTPUT_BUFFER_SIZE;
 use parallel_processor::mt_debug_counters::counter::{AtomicCounter, AvgMode, SumMode};
 use parallel_processor::mt_debug_counters::{declare_avg_counter_i64, declare_counter_i64};
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufRead, BufReader, Read};
+use std::ops::ControlFlow;
 use std::path::Path;
 use streaming_libdeflate_rs::decompress_file_buffered;
+use utils::phase_log::log_warning;
 
 pub struct LinesReader {
     buffer: Vec<u8>,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum InputCompression {
+    None,
+    Gzip,
+    Lz4,
+    Xz,
+    Bzip2,
+}
+
 static COUNTER_THREADS_BUSY_READING: AtomicCounter<SumMode> =
     declare_counter_i64!("line_reading_threads", SumMode, false);
 
@@ -33,7 +45,7 @@ impl LinesReader {
     fn read_stream_buffered(
         &mut self,
         mut stream: impl Read,
-        mut callback: impl FnMut(&[u8]),
+        mut callback: impl FnMut(&[u8]) -> ControlFlow<()>,
     ) -> Result<(), ()> {
         COUNTER_THREADS_BUSY_READING.inc();
 
@@ -43,61 +55,168 @@ impl LinesReader {
             COUNTER_THREADS_BUSY_READING.sub(1);
             if count == 0 {
                 COUNTER_THREADS_PROCESSING_READS.inc();
-                callback(&[]);
+                let _ = callback(&[]);
                 COUNTER_THREADS_PROCESSING_READS.sub(1);
                 return Ok(());
             }
             COUNTER_THREADS_PROCESSING_READS.inc();
-            callback(&self.buffer[0..count]);
+            let flow = callback(&self.buffer[0..count]);
             COUNTER_THREADS_PROCESSING_READS.sub(1);
+            if flow.is_break() {
+                return Ok(());
+            }
             COUNTER_THREADS_BUSY_READING.inc();
         }
         Err(())
     }
 
+    fn detect_compression_from_magic(magic: &[u8], path: &Path) -> InputCompression {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            InputCompression::Gzip
+        } else if magic.starts_with(&[0x04, 0x22, 0x4d, 0x18]) {
+            InputCompression::Lz4
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            InputCompression::Xz
+        } else if magic.starts_with(b"BZh") {
+            InputCompression::Bzip2
+        } else {
+            match path.extension().and_then(|x| x.to_str()) {
+                Some("gz") => InputCompression::Gzip,
+                Some("lz4") => InputCompression::Lz4,
+                Some("xz") => InputCompression::Xz,
+                Some("bz2") => InputCompression::Bzip2,
+                _ => InputCompression::None,
+            }
+        }
+    }
+
+    fn detect_compression(path: &Path) -> InputCompression {
+        let mut magic = [0; 6];
+        let magic_len = File::open(path)
+            .and_then(|mut file| {
+                let mut total = 0;
+                while total < magic.len() {
+                    match file.read(&mut magic[total..])? {
+                        0 => break,
+                        count => total += count,
+                    }
+                }
+                Ok(total)
+            })
+            .unwrap_or(0);
+
+        Self::detect_compression_from_magic(&magic[..magic_len], path)
+    }
+
+    /// True if the file is not compressed with any of the supported formats
+    pub(crate) fn is_uncompressed_file(path: &Path) -> bool {
+        Self::detect_compression(path) == InputCompression::None
+    }
+
+    // Streams the data of an url, decompressing it on the fly, panicking if the url cannot be opened.
+    // Gzip inputs are read as multiple members, so bgzf files are supported
+    fn read_url_stream(
+        &mut self,
+        url: &Path,
+        callback: impl FnMut(&[u8]) -> ControlFlow<()>,
+    ) -> Result<(), ()> {
+        let mut stream = BufReader::with_capacity(
+            DEFAULT_OUTPUT_BUFFER_SIZE,
+            UrlReader::new(url.to_str().unwrap()),
+        );
+
+        let magic = stream
+            .fill_buf()
+            .unwrap_or_else(|err| panic!("Cannot open url {}: {}", url.display(), err));
+        let compression = Self::detect_compression_from_magic(&magic[..magic.len().min(6)], url);
+
+        match compression {
+            InputCompression::Gzip => {
+                self.read_stream_buffered(flate2::read::MultiGzDecoder::new(stream), callback)
+            }
+            InputCompression::Lz4 => {
+                self.read_stream_buffered(lz4::Decoder::new(stream).unwrap(), callback)
+            }
+            InputCompression::Xz => {
+                self.read_stream_buffered(xz2::read::XzDecoder::new_multi_decoder(stream), callback)
+            }
+            InputCompression::Bzip2 => {
+                self.read_stream_buffered(bzip2::read::MultiBzDecoder::new(stream), callback)
+            }
+            InputCompression::None => self.read_stream_buffered(stream, callback),
+        }
+    }
+
     fn read_binary_file(
         &mut self,
         path: impl AsRef<Path>,
-        mut callback: impl FnMut(&[u8]),
+        mut callback: impl FnMut(&[u8]) -> ControlFlow<()>,
         remove: bool,
     ) {
-        if path.as_ref().extension().filter(|x| *x == "gz").is_some() {
-            if let Err(_err) = decompress_file_buffered(
-                &path,
-                |data| {
-                    callback(data);
-                    Ok(())
-                },
-                DEFAULT_OUTPUT_BUFFER_SIZE,
-            ) {
-                println!(
-                    "WARNING: Error while reading file {}",
+        if is_url_input(path.as_ref()) {
+            // The reader already retried the download, so a partial input cannot be recovered
+            if self.read_url_stream(path.as_ref(), callback).is_err() {
+                panic!(
+                    "Cannot read url {}: the download failed after all the retries",
                     path.as_ref().display()
                 );
             }
-            callback(&[]);
-        } else if path.as_ref().extension().filter(|x| *x == "lz4").is_some() {
-            let file = lz4::Decoder::new(
-                File::open(&path).expect(&format!("Cannot open file {}", path.as_ref().display())),
-            )
-            .unwrap();
-            self.read_stream_buffered(file, callback)
-                .unwrap_or_else(|_| {
-                    println!(
-                        "WARNING: Error while reading file {}",
-                        path.as_ref().display()
-                    );
-                });
-        } else {
-            let file =
-                File::open(&path).expect(&format!("Cannot open file {}", path.as_ref().display()));
-            self.read_stream_buffered(file, callback)
-                .unwrap_or_else(|_| {
-                    println!(
-                        "WARNING: Error while reading file {}",
-                        path.as_ref().display()
-                    );
-                });
+            return;
+        }
+
+        let open_file =
+            || File::open(&path).expect(&format!("Cannot open file {}", path.as_ref().display()));
+
+        let result = match Self::detect_compression(path.as_ref()) {
+            InputCompression::Gzip => {
+                let mut stopped = false;
+                let result = decompress_file_buffered(
+                    &path,
+                    |data| match callback(data) {
+                        ControlFlow::Continue(()) => Ok(()),
+                        ControlFlow::Break(()) => {
+                            stopped = true;
+                            Err(())
+                        }
+                    },
+                    DEFAULT_OUTPUT_BUFFER_SIZE,
+                )
+                .map_err(|_| ());
+                if stopped {
+                    Ok(())
+                } else {
+                    let _ = callback(&[]);
+                    result
+                }
+            }
+            InputCompression::Lz4 => {
+                self.read_stream_buffered(lz4::Decoder::new(open_file()).unwrap(), callback)
+            }
+            InputCompression::Xz => {
+                log_warning(format!(
+                    "WARNING: File {} is xz compressed, its decompression is slow and can limit the reading speed",
+                    path.as_ref().display()
+                ));
+                self.read_stream_buffered(
+                    xz2::read::XzDecoder::new_multi_decoder(open_file()),
+                    callback,
+                )
+            }
+            InputCompression::Bzip2 => {
+                log_warning(format!(
+                    "WARNING: File {} is bzip2 compressed, its decompression is slow and can limit the reading speed",
+                    path.as_ref().display()
+                ));
+                self.read_stream_buffered(bzip2::read::MultiBzDecoder::new(open_file()), callback)
+            }
+            InputCompression::None => self.read_stream_buffered(open_file(), callback),
+        };
+
+        if result.is_err() {
+            log_warning(format!(
+                "WARNING: Error while reading file {}",
+                path.as_ref().display()
+            ));
         }
 
         if remove {
@@ -143,6 +262,28 @@ impl LinesReader {
             bool, /* finished (last line) */
         ),
         remove: bool,
+    ) {
+        self.process_lines_until(
+            file,
+            |line, partial, finished| {
+                callback(line, partial, finished);
+                ControlFlow::Continue(())
+            },
+            remove,
+        );
+    }
+
+    /// Like process_lines, but stops reading the file as soon as the callback breaks,
+    /// without reading or decompressing the rest of it. The last line is not signaled in that case
+    pub fn process_lines_until(
+        &mut self,
+        file: impl AsRef<Path>,
+        mut callback: impl FnMut(
+            &[u8],
+            bool, /* partial (line continues on next call) */
+            bool, /* finished (last line) */
+        ) -> ControlFlow<()>,
+        remove: bool,
     ) {
         let mut line_pending = false;
 
@@ -152,26 +293,25 @@ impl LinesReader {
                 // File finished
                 if buffer.len() == 0 {
                     if line_pending {
-                        eprintln!(
+                        log_warning(format!(
                             "WARNING: No newline at ending of file '{}'",
                             file.as_ref().display()
-                        );
+                        ));
                     }
-                    callback(&[], false, true);
-                    return;
+                    return callback(&[], false, true);
                 }
 
                 loop {
                     let (full, line) = Self::split_line(&mut buffer);
 
                     if full {
-                        callback(line, false, false);
+                        callback(line, false, false)?;
                     } else {
                         line_pending = line.len() > 0;
                         if line_pending {
-                            callback(line, true, false);
+                            callback(line, true, false)?;
                         }
-                        break;
+                        return ControlFlow::Continue(());
                     }
                 }
             },
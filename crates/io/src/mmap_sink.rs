@@ -0,0 +1,210 @@
+use memmap2::{Mmap, MmapMut};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The magic at the start of the memory-mapped output files
+pub const MMAP_SINK_MAGIC: [u8; 8] = *b"GGCATMAP";
+
+// The magic followed by the committed length of the data, as a little endian u64
+const HEADER_SIZE: usize = 16;
+const COMMITTED_LENGTH_OFFSET: usize = 8;
+
+const DEFAULT_INITIAL_CAPACITY: usize = 1024 * 1024 * 16;
+
+fn committed_length(map: &[u8]) -> &AtomicU64 {
+    // The mappings are page aligned, so the length at offset 8 is aligned for the atomic
+    unsafe { &*(map.as_ptr().add(COMMITTED_LENGTH_OFFSET) as *const AtomicU64) }
+}
+
+/// Writes a growable memory-mapped file that can be read while it is written, by other threads or
+/// processes. The written bytes are published on each flush by storing the committed length in the
+/// file header, so that the readers only see the data up to the last flush. When used as the output sink
+/// of a build, the unitigs are flushed in batches of complete records, and the readers never see
+/// half-written unitigs. The file is grown by doubling it, its tail after the committed length is zeroed
+pub struct MmapSinkWriter {
+    file: File,
+    mmap: MmapMut,
+    written: usize,
+}
+
+impl MmapSinkWriter {
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::with_capacity(path, DEFAULT_INITIAL_CAPACITY)
+    }
+
+    pub fn with_capacity(path: impl AsRef<Path>, capacity: usize) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len((HEADER_SIZE + capacity.max(1)) as u64)?;
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+        committed_length(&mmap).store(0, Ordering::Release);
+        mmap[..MMAP_SINK_MAGIC.len()].copy_from_slice(&MMAP_SINK_MAGIC);
+
+        Ok(Self {
+            file,
+            mmap,
+            written: 0,
+        })
+    }
+
+    fn reserve(&mut self, additional: usize) -> std::io::Result<()> {
+        let required = HEADER_SIZE + self.written + additional;
+        if required <= self.mmap.len() {
+            return Ok(());
+        }
+
+        // The file is only grown, so the mappings of the readers stay valid
+        self.file.set_len(required.next_power_of_two() as u64)?;
+        self.mmap = unsafe { MmapMut::map_mut(&self.file)? };
+        Ok(())
+    }
+
+    /// The number of data bytes visible to the readers
+    pub fn committed_len(&self) -> usize {
+        u64::from_le(committed_length(&self.mmap).load(Ordering::Acquire)) as usize
+    }
+
+    /// Publishes all the bytes written so far
+    pub fn commit(&mut self) {
+        committed_length(&self.mmap).store((self.written as u64).to_le(), Ordering::Release);
+    }
+}
+
+impl Write for MmapSinkWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.reserve(buf.len())?;
+        let start = HEADER_SIZE + self.written;
+        self.mmap[start..start + buf.len()].copy_from_slice(buf);
+        self.written += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.commit();
+        Ok(())
+    }
+}
+
+impl Drop for MmapSinkWriter {
+    fn drop(&mut self) {
+        let _ = self.mmap.flush();
+    }
+}
+
+/// Reads the committed data of a file written by a [MmapSinkWriter], also while it is being written
+pub struct MmapSinkReader {
+    file: File,
+    mmap: Mmap,
+    read: usize,
+}
+
+impl MmapSinkReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|err| format!("Cannot open mmap file {}: {}", path.display(), err))?;
+        let mmap = unsafe { Mmap::map(&file) }
+            .map_err(|err| format!("Cannot map file {}: {}", path.display(), err))?;
+
+        if mmap.len() < HEADER_SIZE || mmap[..MMAP_SINK_MAGIC.len()] != MMAP_SINK_MAGIC {
+            return Err(format!(
+                "File {} is not a ggcat memory-mapped output",
+                path.display()
+            ));
+        }
+
+        Ok(Self {
+            file,
+            mmap,
+            read: 0,
+        })
+    }
+
+    /// The number of data bytes published by the writer
+    pub fn committed_len(&self) -> usize {
+        u64::from_le(committed_length(&self.mmap).load(Ordering::Acquire)) as usize
+    }
+
+    /// Returns the data committed after the previous call, that is made of complete records if the
+    /// writer is flushed only after complete records
+    pub fn read_committed(&mut self) -> std::io::Result<&[u8]> {
+        let committed = self.committed_len();
+        if HEADER_SIZE + committed > self.mmap.len() {
+            // The writer grew the file
+            self.mmap = unsafe { Mmap::map(&self.file)? };
+        }
+
+        let start = self.read;
+        self.read = committed;
+        Ok(&self.mmap[HEADER_SIZE + start..HEADER_SIZE + committed])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MmapSinkReader, MmapSinkWriter};
+    use std::io::Write;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn reader_sees_only_committed_records() {
+        let temp_dir = TestDir::new("reader_sees_only_committed_records");
+        let mmap_file = temp_dir.path("mmap-sink.bin");
+        const RECORDS_COUNT: usize = 2000;
+
+        // A small capacity to grow the file multiple times while it is read
+        let mut writer = MmapSinkWriter::with_capacity(&mmap_file, 64).unwrap();
+        let done = Arc::new(AtomicBool::new(false));
+
+        let reader_thread = {
+            let mmap_file = mmap_file.clone();
+            let done = done.clone();
+            std::thread::spawn(move || {
+                let mut reader = MmapSinkReader::open(&mmap_file).unwrap();
+                let mut records = vec![];
+                loop {
+                    let finished = done.load(Ordering::Acquire);
+                    let data = reader.read_committed().unwrap();
+                    assert!(data.is_empty() || data.ends_with(b"\n"));
+                    records.extend(
+                        std::str::from_utf8(data)
+                            .unwrap()
+                            .lines()
+                            .map(|line| line.to_string()),
+                    );
+                    if finished {
+                        break records;
+                    }
+                    std::thread::yield_now();
+                }
+            })
+        };
+
+        for i in 0..RECORDS_COUNT {
+            // Each record is written in two parts, and committed only when complete
+            write!(writer, ">{} ", i).unwrap();
+            writeln!(writer, "{}", "ACGT".repeat(i % 13)).unwrap();
+            writer.flush().unwrap();
+        }
+        // Not committed, the readers never see it
+        write!(writer, ">partial").unwrap();
+        done.store(true, Ordering::Release);
+
+        let records = reader_thread.join().unwrap();
+        assert_eq!(records.len(), RECORDS_COUNT);
+        for (i, record) in records.iter().enumerate() {
+            assert_eq!(record, &format!(">{} {}", i, "ACGT".repeat(i % 13)));
+        }
+
+        drop(writer);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::sequences_reader::SequencesReader;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Finds the runs of ambiguous bases of a sequence, as (start, length) pairs.
+/// The sequences readers map all the ambiguous bases to N
+pub fn find_n_gaps(seq: &[u8], min_length: usize) -> Vec<(usize, usize)> {
+    let mut gaps = vec![];
+    let mut position = 0;
+
+    while position < seq.len() {
+        if seq[position] != b'N' {
+            position += 1;
+            continue;
+        }
+
+        let start = position;
+        while position < seq.len() && seq[position] == b'N' {
+            position += 1;
+        }
+        if position - start >= min_length {
+            gaps.push((start, position - start));
+        }
+    }
+    gaps
+}
+
+// The record name is the first word of the header
+fn record_name(ident: &[u8]) -> &[u8] {
+    let ident = ident
+        .strip_prefix(b">")
+        .or_else(|| ident.strip_prefix(b"@"))
+        .unwrap_or(ident);
+    ident
+        .split(|c| c.is_ascii_whitespace())
+        .next()
+        .unwrap_or(ident)
+}
+
+/// Writes the runs of ambiguous bases of the input sequences, that split their kmers, as a BED file
+/// with the name of the record and the 0-based, end exclusive interval of each run.
+/// Only the runs of at least min_gap_length bases are written. Returns the number of written gaps
+pub fn write_n_gaps_bed(
+    input_files: &[PathBuf],
+    output_file: impl AsRef<Path>,
+    min_gap_length: usize,
+) -> usize {
+    let mut writer = BufWriter::new(File::create(output_file.as_ref()).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create N gaps file {}: {}",
+            output_file.as_ref().display(),
+            err
+        )
+    }));
+    let mut gaps_count = 0;
+
+    for input_file in input_files {
+        SequencesReader::new().process_file_extended(
+            input_file,
+            |seq| {
+                for (start, length) in find_n_gaps(seq.seq, min_gap_length.max(1)) {
+                    writer.write_all(record_name(seq.ident_data)).unwrap();
+                    writeln!(writer, "\t{}\t{}", start, start + length).unwrap();
+                    gaps_count += 1;
+                }
+            },
+            None,
+            true,
+            false,
+        );
+    }
+
+    writer.flush().unwrap();
+    gaps_count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_n_gaps, write_n_gaps_bed};
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn n_run_is_recorded_with_position_and_length() {
+        assert_eq!(find_n_gaps(b"ACGTNNACGTNACG", 2), vec![(4, 2)]);
+        assert_eq!(find_n_gaps(b"NACGTN", 1), vec![(0, 1), (5, 1)]);
+
+        let temp_dir = TestDir::new("n_run_is_recorded_with_position_and_length");
+        let input_file = temp_dir.path("n-gaps.fa");
+        let output_file = temp_dir.path("n-gaps.bed");
+
+        // Ambiguity codes other than N are also gaps
+        let sequence = format!("{}{}{}", "ACGT".repeat(10), "NNNNNRYNNN", "TGCA".repeat(10));
+        std::fs::write(
+            &input_file,
+            format!(">first description\n{}\n>second\nACGTACGT\n", sequence),
+        )
+        .unwrap();
+
+        assert_eq!(write_n_gaps_bed(&[input_file.clone()], &output_file, 1), 1);
+        assert_eq!(
+            std::fs::read_to_string(&output_file).unwrap(),
+            "first\t40\t50\n"
+        );
+    }
+}
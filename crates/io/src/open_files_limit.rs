@@ -0,0 +1,128 @@
+use config::MIN_BUCKETS_COUNT_LOG;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use utils::phase_log::log_warning;
+
+// Files open at the same time besides the buckets ones (inputs, outputs, colormap, counters)
+const RESERVED_OPEN_FILES: usize = 64;
+// Each bucket is written by the reads bucketing and by the hashes of the kmers merge,
+// while the files of the previous phase are still being read
+const OPEN_FILES_PER_BUCKET: usize = 2;
+
+// Output files open at the same time by the runs writing a file for each color, if there is no limit
+const DEFAULT_MAX_OPEN_OUTPUT_FILES: usize = 512;
+
+static BUCKETING_OPEN_FILES: AtomicUsize = AtomicUsize::new(0);
+static BUCKETING_PEAK_OPEN_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// The largest buckets count log whose files fit in the maximum number of files that a run can keep open
+/// at the same time, for systems with a low descriptors limit. It is never lower than the minimum buckets
+/// count log: if even the minimum buckets count does not fit, the reads bucketing writes the buckets in waves,
+/// see buckets_waves. None if there is no limit
+pub fn max_buckets_count_log_for_open_files(max_open_files: Option<usize>) -> Option<usize> {
+    let max_open_files = max_open_files?;
+
+    let max_buckets = max_open_files.saturating_sub(RESERVED_OPEN_FILES) / OPEN_FILES_PER_BUCKET;
+    Some((max_buckets.max(1).ilog2() as usize).max(MIN_BUCKETS_COUNT_LOG))
+}
+
+/// Reduces the buckets count log of a run to fit the open files limit, warning if it is changed
+pub fn limit_buckets_count_log(buckets_count_log: usize, max_open_files: Option<usize>) -> usize {
+    match max_buckets_count_log_for_open_files(max_open_files) {
+        Some(max_log) if buckets_count_log > max_log => {
+            log_warning(format!(
+                "WARNING: using {} buckets instead of {} to keep at most {} files open",
+                1usize << max_log,
+                1usize << buckets_count_log,
+                max_open_files.unwrap()
+            ));
+            max_log
+        }
+        _ => buckets_count_log,
+    }
+}
+
+/// Splits the buckets written by the reads bucketing in waves of consecutive buckets whose files fit
+/// the open files limit, reading the inputs once for each wave. There is a single wave if there is
+/// no limit or all the buckets fit
+pub fn buckets_waves(buckets_count: usize, max_open_files: Option<usize>) -> Vec<Range<usize>> {
+    split_in_waves(
+        buckets_count,
+        max_open_files
+            .unwrap_or(usize::MAX)
+            .saturating_sub(RESERVED_OPEN_FILES),
+    )
+}
+
+/// Splits the output files of a run writing a file for each color in waves of consecutive files
+/// whose writers fit the open files limit, or a default limit if there is none
+pub fn output_files_waves(files_count: usize, max_open_files: Option<usize>) -> Vec<Range<usize>> {
+    split_in_waves(
+        files_count,
+        max_open_files
+            .unwrap_or(usize::MAX)
+            .saturating_sub(RESERVED_OPEN_FILES)
+            .min(DEFAULT_MAX_OPEN_OUTPUT_FILES),
+    )
+}
+
+fn split_in_waves(count: usize, max_wave_size: usize) -> Vec<Range<usize>> {
+    let max_wave_size = max_wave_size.max(1);
+    let waves_count = count.div_ceil(max_wave_size).max(1);
+    let wave_size = count.div_ceil(waves_count);
+
+    (0..waves_count)
+        .map(|wave| (wave * wave_size)..((wave + 1) * wave_size).min(count))
+        .filter(|wave| !wave.is_empty())
+        .collect()
+}
+
+/// Tracks the bucket files open at the same time by the reads bucketing, to check their peak
+pub struct BucketingOpenFiles(usize);
+
+impl BucketingOpenFiles {
+    pub fn open(files_count: usize) -> Self {
+        let open_files =
+            BUCKETING_OPEN_FILES.fetch_add(files_count, Ordering::Relaxed) + files_count;
+        BUCKETING_PEAK_OPEN_FILES.fetch_max(open_files, Ordering::Relaxed);
+        Self(files_count)
+    }
+
+    /// The maximum number of bucket files open at the same time by the reads bucketing since the last reset
+    pub fn peak() -> usize {
+        BUCKETING_PEAK_OPEN_FILES.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_peak() {
+        BUCKETING_PEAK_OPEN_FILES.store(
+            BUCKETING_OPEN_FILES.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+impl Drop for BucketingOpenFiles {
+    fn drop(&mut self) {
+        BUCKETING_OPEN_FILES.fetch_sub(self.0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{buckets_waves, RESERVED_OPEN_FILES};
+
+    #[test]
+    fn buckets_waves_cover_all_the_buckets_within_the_limit() {
+        assert_eq!(buckets_waves(1024, None), vec![0..1024]);
+
+        let waves = buckets_waves(1024, Some(RESERVED_OPEN_FILES + 300));
+
+        assert_eq!(waves.len(), 4);
+        assert_eq!(waves.first().unwrap().start, 0);
+        assert_eq!(waves.last().unwrap().end, 1024);
+        for (wave, next_wave) in waves.iter().zip(waves.iter().skip(1)) {
+            assert_eq!(wave.end, next_wave.start);
+        }
+        assert!(waves.iter().all(|wave| wave.len() <= 300));
+    }
+}
@@ -1,8 +1,12 @@
+use crate::circular_inputs::CircularInputs;
+use crate::input_validation::is_sequence_char;
 use crate::lines_reader::LinesReader;
 use config::DEFAULT_OUTPUT_BUFFER_SIZE;
 use nightly_quirks::branch_pred::unlikely;
 use std::cmp::max;
+use std::ops::ControlFlow;
 use std::path::Path;
+use utils::phase_log::log_warning;
 
 const IDENT_STATE: usize = 0;
 const SEQ_STATE: usize = 1;
@@ -36,50 +40,145 @@ const SEQ_LETTERS_MAPPING: [u8; 256] = {
     lookup
 };
 
+// Nucleotides and IUPAC codes, the other characters are mapped to N by the lookup above
+// but they usually mean that the input is not a sequences file
+const VALID_SEQ_LETTERS: [bool; 256] = {
+    let mut lookup = [false; 256];
+    let mut i = 0;
+    while i < 256 {
+        lookup[i] = is_sequence_char(i as u8);
+        i += 1;
+    }
+    lookup
+};
+
 pub struct SequencesReader {
     lines_reader: LinesReader,
+    strict_input: bool,
+    circular_inputs: CircularInputs,
 }
 
 impl SequencesReader {
     pub fn new() -> Self {
         Self {
             lines_reader: LinesReader::new(),
+            strict_input: false,
+            circular_inputs: CircularInputs::Disabled,
         }
     }
 
-    fn normalize_sequence(seq: &mut [u8]) {
+    /// Fails on the records with characters that are not nucleotides or IUPAC codes,
+    /// instead of skipping them with a warning. Used for the user inputs of a run with strict input
+    pub fn with_strict_input(mut self, strict_input: bool) -> Self {
+        self.strict_input = strict_input;
+        self
+    }
+
+    /// Reads the fasta records selected by the mode as circular, by default all the records are linear.
+    /// Applied only when the long records are split keeping k - 1 bases of overlap, as for the inputs of a build
+    pub fn with_circular_inputs(mut self, circular_inputs: CircularInputs) -> Self {
+        self.circular_inputs = circular_inputs;
+        self
+    }
+
+    // Returns the first character that is not a nucleotide or an IUPAC code, if any
+    fn normalize_sequence(seq: &mut [u8]) -> Option<u8> {
+        let mut invalid = None;
         for el in seq.iter_mut() {
+            if unlikely(!VALID_SEQ_LETTERS[*el as usize]) && invalid.is_none() {
+                invalid = Some(*el);
+            }
             *el = SEQ_LETTERS_MAPPING[*el as usize];
         }
+        invalid
     }
 
-    pub fn process_file_extended<F: FnMut(DnaSequence)>(
-        &mut self,
-        source: impl AsRef<Path>,
-        func: F,
-        line_split_copyback: Option<usize>,
-        copy_ident: bool,
-        remove_file: bool,
-    ) {
+    // Checks if a record can be processed, failing on invalid characters in strict mode
+    // or counting the record as skipped otherwise
+    fn accept_record(
+        invalid: Option<u8>,
+        strict_input: bool,
+        source: &Path,
+        skipped_records: &mut usize,
+    ) -> bool {
+        match invalid {
+            None => true,
+            Some(invalid) => {
+                if strict_input {
+                    panic!(
+                        "Invalid sequence character '{}' in file '{}', it may not be a sequences file",
+                        (invalid as char).escape_default(),
+                        source.display()
+                    );
+                }
+                *skipped_records += 1;
+                false
+            }
+        }
+    }
+
+    fn warn_skipped_records(source: &Path, skipped_records: usize) {
+        if skipped_records > 0 {
+            log_warning(format!(
+                "Skipped {} records with invalid sequence characters in file '{}'",
+                skipped_records,
+                source.display()
+            ));
+        }
+    }
+
+    /// Detects the type of a sequences file from its extensions, ignoring the compression ones
+    pub fn get_file_type(source: impl AsRef<Path>) -> Option<DnaSequencesFileType> {
         const FASTQ_EXTS: &[&str] = &["fq", "fastq"];
         const FASTA_EXTS: &[&str] = &["fa", "fasta", "fna", "ffn"];
 
-        let mut file_type = None;
         let mut tmp = source.as_ref().file_name().unwrap().to_str().unwrap();
         let mut path: &Path = tmp.as_ref();
 
         while let Some(ext) = path.extension() {
             if FASTQ_EXTS.contains(&ext.to_str().unwrap()) {
-                file_type = Some(DnaSequencesFileType::FASTQ);
-                break;
+                return Some(DnaSequencesFileType::FASTQ);
             }
             if FASTA_EXTS.contains(&ext.to_str().unwrap()) {
-                file_type = Some(DnaSequencesFileType::FASTA);
-                break;
+                return Some(DnaSequencesFileType::FASTA);
             }
             tmp = &tmp[0..tmp.len() - ext.len() - 1];
             path = tmp.as_ref()
         }
+        None
+    }
+
+    pub fn process_file_extended<F: FnMut(DnaSequence)>(
+        &mut self,
+        source: impl AsRef<Path>,
+        mut func: F,
+        line_split_copyback: Option<usize>,
+        copy_ident: bool,
+        remove_file: bool,
+    ) {
+        self.process_file_until(
+            source,
+            |seq| {
+                func(seq);
+                ControlFlow::Continue(())
+            },
+            line_split_copyback,
+            copy_ident,
+            remove_file,
+        );
+    }
+
+    /// Like process_file_extended, but stops reading the file as soon as the callback breaks,
+    /// so that only the needed start of a large or compressed file is read
+    pub fn process_file_until<F: FnMut(DnaSequence) -> ControlFlow<()>>(
+        &mut self,
+        source: impl AsRef<Path>,
+        func: F,
+        line_split_copyback: Option<usize>,
+        copy_ident: bool,
+        remove_file: bool,
+    ) {
+        let file_type = Self::get_file_type(&source);
 
         match file_type {
             None => panic!(
@@ -106,7 +205,7 @@ impl SequencesReader {
     fn process_fasta(
         &mut self,
         source: impl AsRef<Path>,
-        mut func: impl FnMut(DnaSequence),
+        mut func: impl FnMut(DnaSequence) -> ControlFlow<()>,
         line_split_copyback: Option<usize>,
         copy_ident: bool,
         remove_file: bool,
@@ -115,13 +214,28 @@ impl SequencesReader {
         let mut on_comment = false;
         let mut state = SEQ_STATE;
         let mut new_line = true;
+        let strict_input = self.strict_input;
+        let source_path = source.as_ref().to_path_buf();
+        let mut skipped_records = 0;
+        // Set when a part of the current record was already found invalid
+        let mut record_skipped = false;
+
+        let circular_inputs = self.circular_inputs;
+        // The kmers of the circular records are wrapped around the origin by appending their first
+        // k - 1 bases, only when the long records are split keeping k - 1 bases of overlap
+        let wrap_length =
+            line_split_copyback.filter(|_| circular_inputs != CircularInputs::Disabled);
+        // The header of the current record, kept only to find its circular tag
+        let mut record_header = vec![];
+        let mut record_circular = false;
+        let mut circular_prefix = vec![];
 
         let flush_size = max(
             DEFAULT_OUTPUT_BUFFER_SIZE,
             line_split_copyback.unwrap_or(0) * 2,
         );
 
-        self.lines_reader.process_lines(
+        self.lines_reader.process_lines_until(
             source,
             |line: &[u8], partial, finished| {
                 if on_comment {
@@ -130,13 +244,32 @@ impl SequencesReader {
                 // If a new ident line is found (or it's the last line)
                 else if finished || (new_line && line.len() > 0 && line[0] == b'>') {
                     if intermediate[SEQ_STATE].len() > 0 {
-                        Self::normalize_sequence(&mut intermediate[SEQ_STATE]);
-                        func(DnaSequence {
-                            ident_data: &intermediate[IDENT_STATE],
-                            seq: &intermediate[SEQ_STATE],
-                            format: DnaSequencesFileType::FASTA,
-                        });
+                        let invalid = Self::normalize_sequence(&mut intermediate[SEQ_STATE]);
+                        if let (true, Some(wrap_length)) = (record_circular, wrap_length) {
+                            if circular_prefix.is_empty()
+                                && intermediate[SEQ_STATE].len() >= wrap_length
+                            {
+                                circular_prefix
+                                    .extend_from_slice(&intermediate[SEQ_STATE][..wrap_length]);
+                            }
+                            intermediate[SEQ_STATE].extend_from_slice(&circular_prefix);
+                        }
+                        if !record_skipped
+                            && Self::accept_record(
+                                invalid,
+                                strict_input,
+                                &source_path,
+                                &mut skipped_records,
+                            )
+                        {
+                            func(DnaSequence {
+                                ident_data: &intermediate[IDENT_STATE],
+                                seq: &intermediate[SEQ_STATE],
+                                format: DnaSequencesFileType::FASTA,
+                            })?;
+                        }
                     }
+                    record_skipped = false;
 
                     if copy_ident {
                         intermediate[IDENT_STATE].clear();
@@ -144,6 +277,14 @@ impl SequencesReader {
                     }
                     intermediate[SEQ_STATE].clear();
 
+                    record_header.clear();
+                    if circular_inputs == CircularInputs::HeaderTag {
+                        record_header.extend_from_slice(line);
+                    }
+                    record_circular =
+                        !partial && circular_inputs.is_circular_record(&record_header);
+                    circular_prefix.clear();
+
                     state = if partial { IDENT_STATE } else { SEQ_STATE };
                 } else if new_line && line.len() > 0 && line[0] == b';' {
                     on_comment = true;
@@ -151,8 +292,12 @@ impl SequencesReader {
                     if copy_ident {
                         intermediate[IDENT_STATE].extend_from_slice(line);
                     }
+                    if circular_inputs == CircularInputs::HeaderTag {
+                        record_header.extend_from_slice(line);
+                    }
 
                     if !partial {
+                        record_circular = circular_inputs.is_circular_record(&record_header);
                         state = SEQ_STATE;
                     }
                 } else {
@@ -161,40 +306,60 @@ impl SequencesReader {
 
                 if let Some(copyback) = line_split_copyback {
                     if intermediate[SEQ_STATE].len() >= flush_size {
-                        Self::normalize_sequence(&mut intermediate[SEQ_STATE]);
-                        func(DnaSequence {
-                            ident_data: &intermediate[IDENT_STATE],
-                            seq: &intermediate[SEQ_STATE],
-                            format: DnaSequencesFileType::FASTQ,
-                        });
+                        let invalid = Self::normalize_sequence(&mut intermediate[SEQ_STATE]);
+                        if record_circular && circular_prefix.is_empty() {
+                            circular_prefix.extend_from_slice(&intermediate[SEQ_STATE][..copyback]);
+                        }
+                        if !record_skipped {
+                            if Self::accept_record(
+                                invalid,
+                                strict_input,
+                                &source_path,
+                                &mut skipped_records,
+                            ) {
+                                func(DnaSequence {
+                                    ident_data: &intermediate[IDENT_STATE],
+                                    seq: &intermediate[SEQ_STATE],
+                                    format: DnaSequencesFileType::FASTQ,
+                                })?;
+                            } else {
+                                record_skipped = true;
+                            }
+                        }
                         let copy_start = intermediate[SEQ_STATE].len() - copyback;
                         intermediate[SEQ_STATE].copy_within(copy_start.., 0);
                         intermediate[SEQ_STATE].truncate(copyback);
                     }
                 }
                 new_line = !partial;
+                ControlFlow::Continue(())
             },
             remove_file,
         );
+
+        Self::warn_skipped_records(&source_path, skipped_records);
     }
 
     fn process_fastq(
         &mut self,
         source: impl AsRef<Path>,
-        mut func: impl FnMut(DnaSequence),
+        mut func: impl FnMut(DnaSequence) -> ControlFlow<()>,
         // get_quality: bool,
         remove_file: bool,
     ) {
         let mut state = IDENT_STATE;
         let mut skipped_plus = false;
+        let strict_input = self.strict_input;
+        let source_path = source.as_ref().to_path_buf();
+        let mut skipped_records = 0;
 
         let mut intermediate = [Vec::new(), Vec::new(), Vec::new()];
 
-        self.lines_reader.process_lines(
+        self.lines_reader.process_lines_until(
             source,
             |line: &[u8], partial, finished| {
                 if unlikely(finished) {
-                    return;
+                    return ControlFlow::Continue(());
                 }
 
                 if state == QUAL_STATE {
@@ -202,7 +367,7 @@ impl SequencesReader {
                         if !partial {
                             skipped_plus = true;
                         }
-                        return;
+                        return ControlFlow::Continue(());
                     }
 
                     // if get_quality {
@@ -210,23 +375,33 @@ impl SequencesReader {
                     // }
 
                     if !partial {
-                        Self::normalize_sequence(&mut intermediate[SEQ_STATE]);
-                        func(DnaSequence {
-                            ident_data: &intermediate[IDENT_STATE],
-                            seq: &intermediate[SEQ_STATE],
-                            // qual: if get_quality {
-                            //     Some(&intermediate[QUAL_STATE])
-                            // } else {
-                            //     None
-                            // },
-                            format: DnaSequencesFileType::FASTQ,
-                        });
+                        let invalid = Self::normalize_sequence(&mut intermediate[SEQ_STATE]);
+                        let flow = if Self::accept_record(
+                            invalid,
+                            strict_input,
+                            &source_path,
+                            &mut skipped_records,
+                        ) {
+                            func(DnaSequence {
+                                ident_data: &intermediate[IDENT_STATE],
+                                seq: &intermediate[SEQ_STATE],
+                                // qual: if get_quality {
+                                //     Some(&intermediate[QUAL_STATE])
+                                // } else {
+                                //     None
+                                // },
+                                format: DnaSequencesFileType::FASTQ,
+                            })
+                        } else {
+                            ControlFlow::Continue(())
+                        };
 
                         intermediate[IDENT_STATE].clear();
                         intermediate[SEQ_STATE].clear();
                         intermediate[QUAL_STATE].clear();
 
                         skipped_plus = false;
+                        flow?;
                     }
                 } else {
                     intermediate[state].extend_from_slice(line);
@@ -235,8 +410,136 @@ impl SequencesReader {
                 if !partial {
                     state = (state + 1) % 3;
                 }
+                ControlFlow::Continue(())
             },
             remove_file,
         );
+
+        Self::warn_skipped_records(&source_path, skipped_records);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SequencesReader;
+    use crate::circular_inputs::CircularInputs;
+    use std::io::Write;
+    use std::ops::ControlFlow;
+    use std::path::PathBuf;
+    use utils::test_dir::TestDir;
+
+    fn write_binary_garbage_input(temp_dir: &TestDir) -> PathBuf {
+        let path = temp_dir.path("binary-input.fa");
+        let mut file = std::fs::File::create(&path).unwrap();
+        file.write_all(b">valid1\nACGTNacgtRY\n>garbage\nACG")
+            .unwrap();
+        file.write_all(&[0x89, b'P', b'N', b'G', 0x00, 0x1a, 0xff])
+            .unwrap();
+        file.write_all(b"\n>valid2\nTTGCA\n").unwrap();
+        path
+    }
+
+    #[test]
+    fn binary_records_are_skipped() {
+        let temp_dir = TestDir::new("binary_records_are_skipped");
+        let path = write_binary_garbage_input(&temp_dir);
+        let mut reader = SequencesReader::new();
+        reader.strict_input = false;
+
+        let mut records = vec![];
+        reader.process_file_extended(
+            &path,
+            |seq| records.push((seq.ident_data.to_vec(), seq.seq.to_vec())),
+            None,
+            true,
+            false,
+        );
+
+        assert_eq!(
+            records,
+            vec![
+                (b">valid1".to_vec(), b"ACGTNACGTNN".to_vec()),
+                (b">valid2".to_vec(), b"TTGCA".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid sequence character")]
+    fn binary_records_fail_in_strict_mode() {
+        let temp_dir = TestDir::new("binary_records_fail_in_strict_mode");
+        let path = write_binary_garbage_input(&temp_dir);
+        let mut reader = SequencesReader::new();
+        reader.strict_input = true;
+
+        reader.process_file_extended(&path, |_| {}, None, false, false);
+    }
+
+    #[test]
+    fn circular_records_wrap_around_the_origin() {
+        let temp_dir = TestDir::new("circular_records_wrap_around_the_origin");
+        let path = temp_dir.path("circular-input.fa");
+        std::fs::write(
+            &path,
+            b">plasmid circular=true\nACGTTGCA\n>chromosome\nAACCGGTT\n>short [topology=circular]\nAC\n",
+        )
+        .unwrap();
+
+        let read_records = |circular_inputs| {
+            let mut reader = SequencesReader::new();
+            reader.circular_inputs = circular_inputs;
+            let mut records = vec![];
+            reader.process_file_extended(
+                &path,
+                |seq| records.push(seq.seq.to_vec()),
+                Some(3),
+                false,
+                false,
+            );
+            records
+        };
+
+        // With k = 4 the first 3 bases are appended, the records shorter than k - 1 are left unchanged
+        assert_eq!(
+            read_records(CircularInputs::HeaderTag),
+            vec![
+                b"ACGTTGCAACG".to_vec(),
+                b"AACCGGTT".to_vec(),
+                b"AC".to_vec()
+            ]
+        );
+        assert_eq!(
+            read_records(CircularInputs::All)[1],
+            b"AACCGGTTAAC".to_vec()
+        );
+        assert_eq!(
+            read_records(CircularInputs::Disabled)[0],
+            b"ACGTTGCA".to_vec()
+        );
+    }
+
+    #[test]
+    fn reading_stops_when_the_callback_breaks() {
+        let temp_dir = TestDir::new("reading_stops_when_the_callback_breaks");
+        let path = temp_dir.path("stop-reading.fa");
+        std::fs::write(&path, b">0\nACGT\n>1\nCCGG\n>2\nTTAA\n").unwrap();
+
+        let mut records = vec![];
+        SequencesReader::new().process_file_until(
+            &path,
+            |seq| {
+                records.push(seq.seq.to_vec());
+                if records.len() == 2 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            },
+            None,
+            false,
+            false,
+        );
+
+        assert_eq!(records, vec![b"ACGT".to_vec(), b"CCGG".to_vec()]);
     }
 }
@@ -1,5 +1,9 @@
+pub mod circular_fasta;
 pub mod fasta;
 pub mod general;
+pub mod kmer_counts_dump;
+pub mod stranded_inputs;
+pub mod unitigs_kmer_counts;
 
 use crate::sequences_reader::DnaSequence;
 use config::ColorIndexType;
@@ -7,12 +11,16 @@ use config::ColorIndexType;
 #[derive(Copy, Clone)]
 pub struct SequenceInfo {
     pub color: Option<ColorIndexType>,
+    /// How many times the sequence is counted, greater than one only for the pre-counted kmers
+    pub multiplicity: u64,
 }
 
 pub trait GenericSequencesStream: 'static {
     type SequenceBlockData: Sync + Send + 'static;
 
-    fn new() -> Self;
+    /// With strict_input the records with characters that are not nucleotides or IUPAC codes
+    /// fail the read, otherwise they are skipped with a warning
+    fn new(strict_input: bool) -> Self;
 
     fn read_block(
         &mut self,
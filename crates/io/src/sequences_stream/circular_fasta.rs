@@ -0,0 +1,77 @@
+use crate::circular_inputs::CircularInputs;
+use crate::sequences_reader::{DnaSequence, SequencesReader};
+use crate::sequences_stream::fasta::FastaFileSequencesStream;
+use crate::sequences_stream::general::{DynamicSequencesStream, GeneralSequenceBlockData};
+use crate::sequences_stream::SequenceInfo;
+use config::ColorIndexType;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Input stream of fasta files with circular records, selected by the given mode. Only the build
+/// reading this stream wraps the kmers of the circular records around their origin, the other readers
+/// of the same files read them as linear. Each file is a separate block, with its own color
+pub struct CircularFastaStream {
+    files: Vec<(PathBuf, Option<ColorIndexType>)>,
+    circular_inputs: CircularInputs,
+    strict_input: bool,
+}
+
+impl CircularFastaStream {
+    pub fn new(
+        files: Vec<(PathBuf, Option<ColorIndexType>)>,
+        circular_inputs: CircularInputs,
+    ) -> Self {
+        Self {
+            files,
+            circular_inputs,
+            strict_input: false,
+        }
+    }
+
+    /// Fails on the records with characters that are not nucleotides or IUPAC codes,
+    /// instead of skipping them with a warning
+    pub fn with_strict_input(mut self, strict_input: bool) -> Self {
+        self.strict_input = strict_input;
+        self
+    }
+
+    /// The input blocks of the build, one for each file
+    pub fn into_input_blocks(self) -> Vec<GeneralSequenceBlockData> {
+        let files_count = self.files.len();
+        let stream: Arc<dyn DynamicSequencesStream> = Arc::new(self);
+        (0..files_count)
+            .map(|block| GeneralSequenceBlockData::Dynamic((stream.clone(), block)))
+            .collect()
+    }
+}
+
+impl DynamicSequencesStream for CircularFastaStream {
+    fn read_block(
+        &self,
+        block: usize,
+        copy_ident_data: bool,
+        partial_read_copyback: Option<usize>,
+        callback: &mut dyn FnMut(DnaSequence, SequenceInfo),
+    ) {
+        let (file, color) = &self.files[block];
+        let info = SequenceInfo {
+            color: *color,
+            multiplicity: 1,
+        };
+
+        SequencesReader::new()
+            .with_circular_inputs(self.circular_inputs)
+            .with_strict_input(self.strict_input)
+            .process_file_extended(
+                file,
+                |seq| callback(seq, info),
+                partial_read_copyback,
+                copy_ident_data,
+                false,
+            );
+    }
+
+    fn estimated_base_count(&self, block: usize) -> u64 {
+        FastaFileSequencesStream::get_estimated_bases_count(&self.files[block].0)
+    }
+}
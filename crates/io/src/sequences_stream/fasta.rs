@@ -1,5 +1,7 @@
 use crate::sequences_reader::{DnaSequence, SequencesReader};
 use crate::sequences_stream::{GenericSequencesStream, SequenceInfo};
+use crate::url_reader::{get_url_content_length, is_url_input};
+use std::ops::ControlFlow;
 use std::path::PathBuf;
 
 pub struct FastaFileSequencesStream {
@@ -11,13 +13,18 @@ impl FastaFileSequencesStream {
         // TODO: Improve this ratio estimation
         const COMPRESSED_READS_RATIO: f64 = 0.5;
 
-        let length = std::fs::metadata(file)
-            .expect(&format!("Error while opening file {}", file.display()))
-            .len();
+        let length = if is_url_input(file) {
+            // Unknown sizes give the minimum number of buckets
+            get_url_content_length(file.to_str().unwrap()).unwrap_or(0)
+        } else {
+            std::fs::metadata(file)
+                .expect(&format!("Error while opening file {}", file.display()))
+                .len()
+        };
 
         let file_bases_count = if file
             .extension()
-            .map(|x| x == "gz" || x == "lz4")
+            .map(|x| x == "gz" || x == "lz4" || x == "xz" || x == "bz2")
             .unwrap_or(false)
         {
             (length as f64 * COMPRESSED_READS_RATIO) as u64
@@ -26,14 +33,39 @@ impl FastaFileSequencesStream {
         };
         file_bases_count
     }
+
+    /// Reads a block until the callback breaks, without reading the rest of the file
+    pub fn read_block_until(
+        &mut self,
+        block: &<Self as GenericSequencesStream>::SequenceBlockData,
+        copy_ident_data: bool,
+        partial_read_copyback: Option<usize>,
+        mut callback: impl FnMut(DnaSequence, SequenceInfo) -> ControlFlow<()>,
+    ) {
+        self.sequences_reader.process_file_until(
+            &block.0,
+            |x| {
+                callback(
+                    x,
+                    SequenceInfo {
+                        color: block.1,
+                        multiplicity: 1,
+                    },
+                )
+            },
+            partial_read_copyback,
+            copy_ident_data,
+            false,
+        );
+    }
 }
 
 impl GenericSequencesStream for FastaFileSequencesStream {
     type SequenceBlockData = (PathBuf, Option<u32>);
 
-    fn new() -> Self {
+    fn new(strict_input: bool) -> Self {
         Self {
-            sequences_reader: SequencesReader::new(),
+            sequences_reader: SequencesReader::new().with_strict_input(strict_input),
         }
     }
 
@@ -46,7 +78,15 @@ impl GenericSequencesStream for FastaFileSequencesStream {
     ) {
         self.sequences_reader.process_file_extended(
             &block.0,
-            |x| callback(x, SequenceInfo { color: block.1 }),
+            |x| {
+                callback(
+                    x,
+                    SequenceInfo {
+                        color: block.1,
+                        multiplicity: 1,
+                    },
+                )
+            },
             partial_read_copyback,
             copy_ident_data,
             false,
@@ -1,6 +1,8 @@
 use crate::sequences_reader::DnaSequence;
 use crate::sequences_stream::fasta::FastaFileSequencesStream;
+use crate::sequences_stream::stranded_inputs::InputStrandMode;
 use crate::sequences_stream::{GenericSequencesStream, SequenceInfo};
+use std::ops::ControlFlow;
 use std::sync::Arc;
 
 pub trait DynamicSequencesStream: Sync + Send + 'static {
@@ -13,8 +15,14 @@ pub trait DynamicSequencesStream: Sync + Send + 'static {
     );
 
     fn estimated_base_count(&self, block: usize) -> u64;
+
+    /// The strand mode of the sequences of the block, None to use the one of the build
+    fn strand_mode(&self, _block: usize) -> Option<InputStrandMode> {
+        None
+    }
 }
 
+#[derive(Clone)]
 pub enum GeneralSequenceBlockData {
     FASTA(<FastaFileSequencesStream as GenericSequencesStream>::SequenceBlockData),
     GFA(),
@@ -35,18 +43,85 @@ impl GeneralSequenceBlockData {
             }
         }
     }
+
+    /// The strand mode of the kmers of the block, given the one of the build
+    pub fn strand_mode(&self, build_strand_mode: InputStrandMode) -> InputStrandMode {
+        match self {
+            GeneralSequenceBlockData::FASTA(_) | GeneralSequenceBlockData::GFA() => {
+                build_strand_mode
+            }
+            GeneralSequenceBlockData::Dynamic((reader, block)) => {
+                reader.strand_mode(*block).unwrap_or(build_strand_mode)
+            }
+        }
+    }
+
+    /// True if the kmers of some of the blocks are kept only on the strand they are read from,
+    /// so that the reverse complementary kmers must be hashed as different kmers
+    pub fn has_forward_only_kmers(
+        blocks: &[GeneralSequenceBlockData],
+        build_strand_mode: InputStrandMode,
+    ) -> bool {
+        blocks
+            .iter()
+            .any(|block| block.strand_mode(build_strand_mode) == InputStrandMode::ForwardOnly)
+    }
 }
 
 pub struct GeneralSequencesStream {
     fasta_file_reader: Option<FastaFileSequencesStream>,
+    strict_input: bool,
+}
+
+impl GeneralSequencesStream {
+    /// Reads a block until the callback breaks. The files are not read past that point,
+    /// the dynamic streams are read to their end but their remaining sequences are not passed to the callback
+    pub fn read_block_until(
+        &mut self,
+        block: &GeneralSequenceBlockData,
+        copy_ident_data: bool,
+        partial_read_copyback: Option<usize>,
+        mut callback: impl FnMut(DnaSequence, SequenceInfo) -> ControlFlow<()>,
+    ) {
+        match block {
+            GeneralSequenceBlockData::FASTA(block) => {
+                if self.fasta_file_reader.is_none() {
+                    self.fasta_file_reader = Some(FastaFileSequencesStream::new(self.strict_input));
+                }
+                self.fasta_file_reader.as_mut().unwrap().read_block_until(
+                    block,
+                    copy_ident_data,
+                    partial_read_copyback,
+                    callback,
+                );
+            }
+            GeneralSequenceBlockData::GFA() => {
+                unimplemented!();
+            }
+            GeneralSequenceBlockData::Dynamic((reader, index)) => {
+                let mut stopped = false;
+                reader.read_block(
+                    *index,
+                    copy_ident_data,
+                    partial_read_copyback,
+                    &mut |seq, info| {
+                        if !stopped {
+                            stopped = callback(seq, info).is_break();
+                        }
+                    },
+                );
+            }
+        }
+    }
 }
 
 impl GenericSequencesStream for GeneralSequencesStream {
     type SequenceBlockData = GeneralSequenceBlockData;
 
-    fn new() -> Self {
+    fn new(strict_input: bool) -> Self {
         Self {
             fasta_file_reader: None,
+            strict_input,
         }
     }
 
@@ -60,7 +135,7 @@ impl GenericSequencesStream for GeneralSequencesStream {
         match block {
             GeneralSequenceBlockData::FASTA(block) => {
                 if self.fasta_file_reader.is_none() {
-                    self.fasta_file_reader = Some(FastaFileSequencesStream::new());
+                    self.fasta_file_reader = Some(FastaFileSequencesStream::new(self.strict_input));
                 }
                 self.fasta_file_reader.as_mut().unwrap().read_block(
                     block,
@@ -0,0 +1,142 @@
+use crate::sequences_reader::{DnaSequence, DnaSequencesFileType};
+use crate::sequences_stream::general::{DynamicSequencesStream, GeneralSequenceBlockData};
+use crate::sequences_stream::SequenceInfo;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Input stream reading pre-counted kmers from text dumps with a kmer and its count on each line,
+/// separated by whitespace, as written by `kmc_tools transform <db> dump` or `jellyfish dump -c`.
+/// Each kmer is given to the build once, with its count as the multiplicity of the sequence, so that
+/// the multiplicity filtering and the kmer counters see the same counts as the original reads.
+/// Each dump file is a separate block, with the color equal to its index in the inputs
+pub struct KmerCountsDumpStream {
+    files: Vec<PathBuf>,
+    k: usize,
+}
+
+// Parses a dump line into its kmer and count, None for the empty lines
+fn parse_dump_line(line: &str, index: usize, file: &Path) -> Option<(String, u64)> {
+    let mut fields = line.split_whitespace();
+    let kmer = fields.next()?;
+    let count: u64 = fields
+        .next()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or_else(|| {
+            panic!(
+                "Missing or invalid count at line {} of kmer counts dump {}",
+                index + 1,
+                file.display()
+            )
+        });
+
+    let kmer = kmer.to_ascii_uppercase();
+    if !kmer.bytes().all(|b| matches!(b, b'A' | b'C' | b'G' | b'T')) {
+        panic!(
+            "Invalid kmer '{}' at line {} of kmer counts dump {}",
+            kmer,
+            index + 1,
+            file.display()
+        );
+    }
+    Some((kmer, count))
+}
+
+impl KmerCountsDumpStream {
+    /// Opens the dumps of kmers of length k, returning an error if a dump cannot be read
+    /// or if its first kmer has a different length
+    pub fn new(files: Vec<PathBuf>, k: usize) -> Result<Self, String> {
+        for file in &files {
+            let reader = BufReader::new(File::open(file).map_err(|err| {
+                format!("Cannot open kmer counts dump {}: {}", file.display(), err)
+            })?);
+
+            for (index, line) in reader.lines().enumerate() {
+                let line = line.map_err(|err| {
+                    format!("Cannot read kmer counts dump {}: {}", file.display(), err)
+                })?;
+                let Some(kmer) = line.split_whitespace().next() else {
+                    continue;
+                };
+                if kmer.len() != k {
+                    return Err(format!(
+                        "The kmer counts dump {} has kmers of length {} (line {}), but the graph is built with k = {}",
+                        file.display(),
+                        kmer.len(),
+                        index + 1,
+                        k
+                    ));
+                }
+                break;
+            }
+        }
+
+        Ok(Self { files, k })
+    }
+
+    /// The input blocks of the build, one for each dump file
+    pub fn into_input_blocks(self) -> Vec<GeneralSequenceBlockData> {
+        let files_count = self.files.len();
+        let stream: Arc<dyn DynamicSequencesStream> = Arc::new(self);
+        (0..files_count)
+            .map(|block| GeneralSequenceBlockData::Dynamic((stream.clone(), block)))
+            .collect()
+    }
+}
+
+impl DynamicSequencesStream for KmerCountsDumpStream {
+    fn read_block(
+        &self,
+        block: usize,
+        _copy_ident_data: bool,
+        _partial_read_copyback: Option<usize>,
+        callback: &mut dyn FnMut(DnaSequence, SequenceInfo),
+    ) {
+        let file = &self.files[block];
+        let reader = BufReader::new(File::open(file).unwrap_or_else(|err| {
+            panic!("Cannot open kmer counts dump {}: {}", file.display(), err)
+        }));
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line.unwrap_or_else(|err| {
+                panic!("Cannot read kmer counts dump {}: {}", file.display(), err)
+            });
+
+            let Some((kmer, count)) = parse_dump_line(&line, index, file) else {
+                continue;
+            };
+            if kmer.len() != self.k {
+                panic!(
+                    "Kmer '{}' at line {} of kmer counts dump {} has length {} instead of {}",
+                    kmer,
+                    index + 1,
+                    file.display(),
+                    kmer.len(),
+                    self.k
+                );
+            }
+            if count == 0 {
+                continue;
+            }
+
+            callback(
+                DnaSequence {
+                    ident_data: &[],
+                    seq: kmer.as_bytes(),
+                    format: DnaSequencesFileType::FASTA,
+                },
+                SequenceInfo {
+                    color: None,
+                    multiplicity: count,
+                },
+            );
+        }
+    }
+
+    fn estimated_base_count(&self, block: usize) -> u64 {
+        std::fs::metadata(&self.files[block])
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,95 @@
+use crate::circular_inputs::CircularInputs;
+use crate::sequences_reader::{DnaSequence, SequencesReader};
+use crate::sequences_stream::fasta::FastaFileSequencesStream;
+use crate::sequences_stream::general::{DynamicSequencesStream, GeneralSequenceBlockData};
+use crate::sequences_stream::SequenceInfo;
+use config::ColorIndexType;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// How the kmers of an input file are oriented
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputStrandMode {
+    /// The kmers and their reverse complements are the same kmer
+    Canonical,
+    /// Only the kmers as read from the file are added
+    ForwardOnly,
+}
+
+/// Input stream of fasta files with a strand mode for each file, to mix canonical and
+/// forward-only inputs in the same build. Each sequence is read once: if some of the files are
+/// forward-only the reverse complementary kmers are hashed as different kmers, and the kmers of the
+/// canonical files are added on both strands while merging them.
+/// Each file is a separate block, with its own color
+pub struct StrandedInputsStream {
+    files: Vec<(PathBuf, Option<ColorIndexType>, InputStrandMode)>,
+    circular_inputs: CircularInputs,
+    strict_input: bool,
+}
+
+impl StrandedInputsStream {
+    pub fn new(files: Vec<(PathBuf, Option<ColorIndexType>, InputStrandMode)>) -> Self {
+        Self {
+            files,
+            circular_inputs: CircularInputs::Disabled,
+            strict_input: false,
+        }
+    }
+
+    /// Reads the records of the files selected by the mode as circular
+    pub fn with_circular_inputs(mut self, circular_inputs: CircularInputs) -> Self {
+        self.circular_inputs = circular_inputs;
+        self
+    }
+
+    /// Fails on the records with characters that are not nucleotides or IUPAC codes,
+    /// instead of skipping them with a warning
+    pub fn with_strict_input(mut self, strict_input: bool) -> Self {
+        self.strict_input = strict_input;
+        self
+    }
+
+    /// The input blocks of the build, one for each file
+    pub fn into_input_blocks(self) -> Vec<GeneralSequenceBlockData> {
+        let files_count = self.files.len();
+        let stream: Arc<dyn DynamicSequencesStream> = Arc::new(self);
+        (0..files_count)
+            .map(|block| GeneralSequenceBlockData::Dynamic((stream.clone(), block)))
+            .collect()
+    }
+}
+
+impl DynamicSequencesStream for StrandedInputsStream {
+    fn read_block(
+        &self,
+        block: usize,
+        copy_ident_data: bool,
+        partial_read_copyback: Option<usize>,
+        callback: &mut dyn FnMut(DnaSequence, SequenceInfo),
+    ) {
+        let (file, color, _) = &self.files[block];
+        let info = SequenceInfo {
+            color: *color,
+            multiplicity: 1,
+        };
+
+        SequencesReader::new()
+            .with_circular_inputs(self.circular_inputs)
+            .with_strict_input(self.strict_input)
+            .process_file_extended(
+                file,
+                |seq| callback(seq, info),
+                partial_read_copyback,
+                copy_ident_data,
+                false,
+            );
+    }
+
+    fn estimated_base_count(&self, block: usize) -> u64 {
+        FastaFileSequencesStream::get_estimated_bases_count(&self.files[block].0)
+    }
+
+    fn strand_mode(&self, block: usize) -> Option<InputStrandMode> {
+        Some(self.files[block].2)
+    }
+}
@@ -0,0 +1,124 @@
+use crate::concurrent::structured_sequences::kmer_counts_sidecar::{
+    get_kmer_counts_file, KmerCountsFile,
+};
+use crate::sequences_reader::{DnaSequence, DnaSequencesFileType, SequencesReader};
+use crate::sequences_stream::general::{DynamicSequencesStream, GeneralSequenceBlockData};
+use crate::sequences_stream::SequenceInfo;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Input stream reading the kmers of built graphs with their counts, as written next to each graph while
+/// building it. The consecutive kmers of a unitig with the same count are given to the build as a single
+/// sequence, with the count as its multiplicity, so that the multiplicity filtering sees the same counts
+/// as a build from the original reads. Each graph is a separate block, with the color equal to its index
+pub struct UnitigsKmerCountsStream {
+    graphs: Vec<(PathBuf, KmerCountsFile)>,
+}
+
+impl UnitigsKmerCountsStream {
+    /// Opens the kmer counts of the graphs, returning an error if they were not written
+    /// or if a graph has a different kmers length
+    pub fn new(graphs: Vec<PathBuf>, k: usize) -> Result<Self, String> {
+        let graphs = graphs
+            .into_iter()
+            .map(|graph| {
+                let kmer_counts = KmerCountsFile::open(get_kmer_counts_file(&graph))?;
+                if kmer_counts.kmer_length() != k {
+                    return Err(format!(
+                        "The graph {} has kmers of length {}, but the graph is built with k = {}",
+                        graph.display(),
+                        kmer_counts.kmer_length(),
+                        k
+                    ));
+                }
+                Ok((graph, kmer_counts))
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { graphs })
+    }
+
+    /// The input blocks of the build, one for each graph
+    pub fn into_input_blocks(self) -> Vec<GeneralSequenceBlockData> {
+        let graphs_count = self.graphs.len();
+        let stream: Arc<dyn DynamicSequencesStream> = Arc::new(self);
+        (0..graphs_count)
+            .map(|block| GeneralSequenceBlockData::Dynamic((stream.clone(), block)))
+            .collect()
+    }
+}
+
+impl DynamicSequencesStream for UnitigsKmerCountsStream {
+    fn read_block(
+        &self,
+        block: usize,
+        _copy_ident_data: bool,
+        _partial_read_copyback: Option<usize>,
+        callback: &mut dyn FnMut(DnaSequence, SequenceInfo),
+    ) {
+        let (graph, kmer_counts) = &self.graphs[block];
+        let k = kmer_counts.kmer_length();
+        let mut counts = vec![];
+
+        SequencesReader::new().process_file_extended(
+            graph,
+            |unitig| {
+                let unitig_id = std::str::from_utf8(
+                    unitig
+                        .ident_data
+                        .get(1..)
+                        .unwrap_or(&[])
+                        .split(|c| c.is_ascii_whitespace())
+                        .next()
+                        .unwrap_or(&[]),
+                )
+                .ok()
+                .and_then(|id| id.parse().ok())
+                .unwrap_or_else(|| {
+                    panic!(
+                        "Invalid unitig header '{}' in graph {}",
+                        String::from_utf8_lossy(unitig.ident_data),
+                        graph.display()
+                    )
+                });
+
+                if !kmer_counts.read_counts(unitig_id, &mut counts)
+                    || counts.len() + k - 1 != unitig.seq.len()
+                {
+                    panic!(
+                        "The kmer counts of unitig {} do not match the graph {}",
+                        unitig_id,
+                        graph.display()
+                    );
+                }
+
+                let mut start = 0;
+                for end in 1..=counts.len() {
+                    if end == counts.len() || counts[end] != counts[start] {
+                        callback(
+                            DnaSequence {
+                                ident_data: &[],
+                                seq: &unitig.seq[start..end + k - 1],
+                                format: DnaSequencesFileType::FASTA,
+                            },
+                            SequenceInfo {
+                                color: None,
+                                multiplicity: counts[start] as u64,
+                            },
+                        );
+                        start = end;
+                    }
+                }
+            },
+            None,
+            true,
+            false,
+        );
+    }
+
+    fn estimated_base_count(&self, block: usize) -> u64 {
+        std::fs::metadata(&self.graphs[block].0)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,160 @@
+use crate::sequences_reader::SequencesReader;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::sync_channel;
+
+// Unitigs whose links are sent together to the iterator
+const LINKS_BATCH_UNITIGS: usize = 1024;
+// Batches read ahead of the iterator
+const LINKS_BATCHES_QUEUE_SIZE: usize = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LinkStrand {
+    Forward,
+    Reverse,
+}
+
+impl LinkStrand {
+    fn from_sign(sign: &[u8]) -> Option<Self> {
+        match sign {
+            b"+" => Some(LinkStrand::Forward),
+            b"-" => Some(LinkStrand::Reverse),
+            _ => None,
+        }
+    }
+
+    /// The sign of the strand in the BCALM2 and GFA formats
+    pub fn sign(&self) -> char {
+        match self {
+            LinkStrand::Forward => '+',
+            LinkStrand::Reverse => '-',
+        }
+    }
+}
+
+/// A link between two unitigs of a compacted graph: the end of the from unitig, read on from_strand,
+/// overlaps by k - 1 bases the start of the to unitig, read on to_strand
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnitigLink {
+    pub from_id: u64,
+    pub from_strand: LinkStrand,
+    pub to_id: u64,
+    pub to_strand: LinkStrand,
+}
+
+/// Parses a link field of a unitig header, in the BCALM2 format L:<+/->:<other id>:<+/->
+pub fn parse_link_field(from_id: u64, field: &[u8]) -> Option<UnitigLink> {
+    let mut parts = field.strip_prefix(b"L:")?.split(|c| *c == b':');
+    let from_strand = LinkStrand::from_sign(parts.next()?)?;
+    let to_id = std::str::from_utf8(parts.next()?).ok()?.parse().ok()?;
+    let to_strand = LinkStrand::from_sign(parts.next()?)?;
+    Some(UnitigLink {
+        from_id,
+        from_strand,
+        to_id,
+        to_strand,
+    })
+}
+
+/// Parses the id and the links of a unitig header, the unitigs without a numeric id get default_id
+pub fn parse_header_links(
+    ident: &[u8],
+    default_id: u64,
+) -> (u64, impl Iterator<Item = UnitigLink> + '_) {
+    let ident = ident.strip_prefix(b">").unwrap_or(ident);
+    let mut fields = ident.split(|c| c.is_ascii_whitespace());
+
+    let id = fields
+        .next()
+        .and_then(|id| std::str::from_utf8(id).ok())
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(default_id);
+
+    (
+        id,
+        fields.filter_map(move |field| parse_link_field(id, field)),
+    )
+}
+
+/// Iterates the links in the headers of a graph built with the unitigs links, in the order of the
+/// unitigs. Each link is listed by both its unitigs, so it is returned a second time from the
+/// other unitig with the strands swapped and inverted.
+/// The graph is read on a separate thread, a few batches ahead of the iterator
+pub fn iter_links(graph_file: impl AsRef<Path>) -> impl Iterator<Item = UnitigLink> {
+    let graph_file: PathBuf = graph_file.as_ref().to_path_buf();
+    let (sender, receiver) = sync_channel(LINKS_BATCHES_QUEUE_SIZE);
+
+    std::thread::Builder::new()
+        .name("links-reader".to_string())
+        .spawn(move || {
+            let mut batch = vec![];
+            let mut batch_unitigs = 0;
+            let mut sequence_index = 0;
+            // Stop sending once the iterator is dropped
+            let mut receiver_alive = true;
+
+            SequencesReader::new().process_file_extended(
+                &graph_file,
+                |seq| {
+                    if !receiver_alive {
+                        return;
+                    }
+
+                    let (_, links) = parse_header_links(seq.ident_data, sequence_index);
+                    sequence_index += 1;
+                    batch.extend(links);
+                    batch_unitigs += 1;
+
+                    if batch_unitigs == LINKS_BATCH_UNITIGS {
+                        receiver_alive = sender.send(std::mem::take(&mut batch)).is_ok();
+                        batch_unitigs = 0;
+                    }
+                },
+                None,
+                true,
+                false,
+            );
+
+            if receiver_alive && !batch.is_empty() {
+                let _ = sender.send(batch);
+            }
+        })
+        .unwrap();
+
+    receiver.into_iter().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{iter_links, LinkStrand, UnitigLink};
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn iterated_links_match_the_graph_topology() {
+        let temp_dir = TestDir::new("iterated_links_match_the_graph_topology");
+        let graph_file = temp_dir.path("unitig-links.fa");
+        // 0 -> 1 -> rc(2), with 3 disconnected
+        std::fs::write(
+            &graph_file,
+            ">0 LN:i:5 L:+:1:+\nACGTA\n>1 LN:i:5 L:-:0:- L:+:2:-\nCGTAC\n>2 LN:i:5 L:+:1:-\nGGTAC\n>3 LN:i:5\nTTTTT\n",
+        )
+        .unwrap();
+
+        let link = |from_id, from_strand, to_id, to_strand| UnitigLink {
+            from_id,
+            from_strand,
+            to_id,
+            to_strand,
+        };
+        use LinkStrand::{Forward, Reverse};
+
+        assert_eq!(
+            iter_links(&graph_file).collect::<Vec<_>>(),
+            vec![
+                link(0, Forward, 1, Forward),
+                link(1, Reverse, 0, Reverse),
+                link(1, Forward, 2, Reverse),
+                link(2, Forward, 1, Reverse),
+            ]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::sequences_stream::general::{GeneralSequenceBlockData, GeneralSequencesStream};
+use crate::sequences_stream::GenericSequencesStream;
+use parking_lot::{Mutex, RwLock};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// Why a read did not contribute any kmer to the graph
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnusedReadReason {
+    /// The read is shorter than the kmers, found while reading the inputs
+    TooShort,
+    /// All the kmers of the read contain ambiguous bases (N), found while reading the inputs
+    AmbiguousBases,
+    /// None of the kmers of the read reached the minimum multiplicity in the kmers merge
+    LowCoverage,
+    /// Some kmers of the read reached the minimum multiplicity, but none of them is in the target
+    NotInTarget,
+}
+
+impl UnusedReadReason {
+    /// The value of the reason tag in the unused reads headers
+    pub fn tag(&self) -> &'static str {
+        match self {
+            UnusedReadReason::TooShort => "too_short",
+            UnusedReadReason::AmbiguousBases => "all_n",
+            UnusedReadReason::LowCoverage => "low_coverage",
+            UnusedReadReason::NotInTarget => "not_in_target",
+        }
+    }
+
+    /// The reason why a read is dropped while reading the inputs, if it has no stretch
+    /// of k bases without N
+    pub fn for_discarded_read(read: &[u8], k: usize) -> Option<Self> {
+        if read.len() < k {
+            Some(UnusedReadReason::TooShort)
+        } else if read.split(|b| *b == b'N').all(|stretch| stretch.len() < k) {
+            Some(UnusedReadReason::AmbiguousBases)
+        } else {
+            None
+        }
+    }
+}
+
+static UNUSED_READS_OUTPUT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Also write the input reads that do not contribute any kmer to the next built graphs to a fasta file,
+/// each with a `reason:` tag added to its header. None disables the output
+pub fn set_unused_reads_output(output_file: Option<PathBuf>) {
+    *UNUSED_READS_OUTPUT.lock() = output_file;
+}
+
+pub fn get_unused_reads_output() -> Option<PathBuf> {
+    UNUSED_READS_OUTPUT.lock().clone()
+}
+
+// Read in the bucketing hot path, to know if the read ids must be carried to the kmers merge
+static READS_TRACKING: AtomicBool = AtomicBool::new(false);
+static READS_TRACKER: RwLock<Option<Arc<ReadsTracker>>> = RwLock::new(None);
+
+/// True if the reads of the current build are tracked, so that each bucketed sequence
+/// carries the id of its read
+#[inline(always)]
+pub fn is_reads_tracking() -> bool {
+    READS_TRACKING.load(Ordering::Relaxed)
+}
+
+pub fn get_reads_tracker() -> Option<Arc<ReadsTracker>> {
+    READS_TRACKER.read().clone()
+}
+
+// The state bits of each read, set by the kmers merge
+const READ_COVERED: u64 = 1;
+const READ_KEPT: u64 = 2;
+const READ_STATE_BITS: u64 = 2;
+
+/// Tracks the reads of a build that do not contribute any kmer to the graph. The reads dropped while
+/// reading the inputs are written as soon as they are found, while the kmers merge marks the reads
+/// with kmers in the graph with two bits for each read. The reads left unmarked are written at the end
+/// of the build, copying them from the inputs with the reason found by the merge
+pub struct ReadsTracker {
+    writer: Mutex<BufWriter<File>>,
+    unused_count: AtomicU64,
+    // The number of reads of each input block, as counted by the reads bucketing
+    blocks_reads: Vec<AtomicU64>,
+    reads_states: OnceLock<Vec<Vec<AtomicU64>>>,
+}
+
+impl ReadsTracker {
+    /// Starts tracking the reads of a build with the given number of input blocks
+    pub fn start(output_file: impl AsRef<Path>, blocks_count: usize) -> Arc<Self> {
+        let writer = BufWriter::new(File::create(output_file.as_ref()).unwrap_or_else(|err| {
+            panic!(
+                "Cannot create unused reads file {}: {}",
+                output_file.as_ref().display(),
+                err
+            )
+        }));
+
+        let tracker = Arc::new(Self {
+            writer: Mutex::new(writer),
+            unused_count: AtomicU64::new(0),
+            blocks_reads: (0..blocks_count).map(|_| AtomicU64::new(0)).collect(),
+            reads_states: OnceLock::new(),
+        });
+        *READS_TRACKER.write() = Some(tracker.clone());
+        READS_TRACKING.store(true, Ordering::Relaxed);
+        tracker
+    }
+
+    /// Writes an unused read, with its reason added to its header
+    pub fn write_read(&self, ident: &[u8], read: &[u8], reason: UnusedReadReason) {
+        // Fasta and fastq headers start with > or @
+        let ident = ident.get(1..).unwrap_or(&[]);
+        let mut writer = self.writer.lock();
+        writer.write_all(b">").unwrap();
+        writer.write_all(ident).unwrap();
+        writeln!(writer, " reason:{}", reason.tag()).unwrap();
+        writer.write_all(read).unwrap();
+        writeln!(writer).unwrap();
+        drop(writer);
+        self.unused_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a read of an input block, given its index in the block
+    #[inline(always)]
+    pub fn record_read(&self, block: usize, read_index: u64) {
+        self.blocks_reads[block].fetch_max(read_index + 1, Ordering::Relaxed);
+    }
+
+    /// Allocates the states of the counted reads, before the kmers merge
+    pub fn start_merge(&self) {
+        self.reads_states.get_or_init(|| {
+            self.blocks_reads
+                .iter()
+                .map(|reads| {
+                    let words = (reads.load(Ordering::Relaxed) * READ_STATE_BITS).div_ceil(64);
+                    (0..words).map(|_| AtomicU64::new(0)).collect()
+                })
+                .collect()
+        });
+    }
+
+    /// Marks a read as having kmers that reached the minimum multiplicity (covered)
+    /// and kmers that are kept in the graph (kept)
+    pub fn mark_read(&self, block: usize, read_index: u64, covered: bool, kept: bool) {
+        let state = (covered as u64 * READ_COVERED) | (kept as u64 * READ_KEPT);
+        if state == 0 {
+            return;
+        }
+        let bit = read_index * READ_STATE_BITS;
+        self.reads_states.get().unwrap()[block][(bit / 64) as usize]
+            .fetch_or(state << (bit % 64), Ordering::Relaxed);
+    }
+
+    fn read_state(&self, block: usize, read_index: u64) -> u64 {
+        let bit = read_index * READ_STATE_BITS;
+        let word =
+            self.reads_states.get().unwrap()[block][(bit / 64) as usize].load(Ordering::Relaxed);
+        (word >> (bit % 64)) & (READ_COVERED | READ_KEPT)
+    }
+
+    /// Writes the reads that were not kept by the kmers merge, reading the input blocks again
+    /// in the same order used by the reads bucketing, and stops the tracking.
+    /// Returns the total number of unused reads
+    pub fn finish(&self, input_blocks: &[GeneralSequenceBlockData], k: usize) -> u64 {
+        if self.reads_states.get().is_some() {
+            let mut stream = GeneralSequencesStream::new(false);
+            for (block_index, block) in input_blocks.iter().enumerate() {
+                let mut read_index = 0;
+                stream.read_block(block, true, Some(k - 1), |seq, _info| {
+                    // The reads dropped while reading the inputs are already written
+                    if UnusedReadReason::for_discarded_read(seq.seq, k).is_none() {
+                        let state = self.read_state(block_index, read_index);
+                        if state & READ_KEPT == 0 {
+                            let reason = if state & READ_COVERED != 0 {
+                                UnusedReadReason::NotInTarget
+                            } else {
+                                UnusedReadReason::LowCoverage
+                            };
+                            self.write_read(seq.ident_data, seq.seq, reason);
+                        }
+                    }
+                    read_index += 1;
+                });
+            }
+        }
+
+        READS_TRACKING.store(false, Ordering::Relaxed);
+        *READS_TRACKER.write() = None;
+        self.writer.lock().flush().unwrap();
+        self.unused_count.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnusedReadReason;
+
+    #[test]
+    fn discarded_reads_have_no_stretch_of_k_valid_bases() {
+        assert_eq!(
+            UnusedReadReason::for_discarded_read(b"ACGT", 5),
+            Some(UnusedReadReason::TooShort)
+        );
+        assert_eq!(
+            UnusedReadReason::for_discarded_read(b"ACGTNACGTNNACG", 5),
+            Some(UnusedReadReason::AmbiguousBases)
+        );
+        assert_eq!(UnusedReadReason::for_discarded_read(b"NNACGTANN", 5), None);
+    }
+}
@@ -0,0 +1,174 @@
+use std::io::{Error, ErrorKind, Read};
+use std::path::Path;
+use std::time::Duration;
+
+const MAX_CONSECUTIVE_FAILURES: u32 = 5;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// True if the input is an http(s) or s3 url instead of a local file
+pub fn is_url_input(path: &Path) -> bool {
+    path.to_str().map_or(false, |path| {
+        path.starts_with("http://") || path.starts_with("https://") || path.starts_with("s3://")
+    })
+}
+
+// Converts s3://<bucket>/<key> urls to https ones, using the endpoint in AWS_ENDPOINT_URL if set.
+// Only public objects can be read, as the requests are not signed
+fn resolve_url(url: &str) -> String {
+    match url.strip_prefix("s3://") {
+        Some(object) => {
+            let (bucket, key) = object.split_once('/').unwrap_or((object, ""));
+            match std::env::var("AWS_ENDPOINT_URL") {
+                Ok(endpoint) => format!("{}/{}/{}", endpoint.trim_end_matches('/'), bucket, key),
+                Err(_) => format!("https://{}.s3.amazonaws.com/{}", bucket, key),
+            }
+        }
+        None => url.to_string(),
+    }
+}
+
+fn create_agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .build()
+}
+
+/// Returns the size of the data at the url, if the server reports it
+pub fn get_url_content_length(url: &str) -> Option<u64> {
+    create_agent()
+        .head(&resolve_url(url))
+        .call()
+        .ok()?
+        .header("Content-Length")?
+        .parse()
+        .ok()
+}
+
+enum ReadFailure {
+    Transient(Error),
+    Fatal(Error),
+}
+
+/// Streams the data at an http(s) or s3 url. When the connection fails the download is resumed
+/// from the first byte not yet read with a range request, retrying a few times with increasing delays
+pub struct UrlReader {
+    agent: ureq::Agent,
+    url: String,
+    position: u64,
+    length: Option<u64>,
+    body: Option<Box<dyn Read + Send + Sync>>,
+    // Offset of the next byte of the current body, it can be before position
+    // if the server does not support range requests
+    body_position: u64,
+}
+
+impl UrlReader {
+    pub fn new(url: &str) -> Self {
+        Self {
+            agent: create_agent(),
+            url: resolve_url(url),
+            position: 0,
+            length: None,
+            body: None,
+            body_position: 0,
+        }
+    }
+
+    fn open_body(&mut self) -> Result<(), ReadFailure> {
+        let mut request = self.agent.get(&self.url);
+        if self.position > 0 {
+            request = request.set("Range", &format!("bytes={}-", self.position));
+        }
+
+        let response = request.call().map_err(|err| {
+            let transient = match &err {
+                ureq::Error::Status(code, _) => *code == 429 || *code >= 500,
+                ureq::Error::Transport(_) => true,
+            };
+            let error = Error::new(
+                ErrorKind::Other,
+                format!("Cannot download {}: {}", self.url, err),
+            );
+            if transient {
+                ReadFailure::Transient(error)
+            } else {
+                ReadFailure::Fatal(error)
+            }
+        })?;
+
+        if self.position == 0 {
+            self.length = response
+                .header("Content-Length")
+                .and_then(|length| length.parse().ok());
+        }
+
+        self.body_position = if response.status() == 206 {
+            self.position
+        } else {
+            0
+        };
+        self.body = Some(response.into_reader());
+        Ok(())
+    }
+
+    fn read_body(&mut self, buf: &mut [u8]) -> Result<usize, ReadFailure> {
+        if self.body.is_none() {
+            self.open_body()?;
+        }
+        let body = self.body.as_mut().unwrap();
+
+        // Skip the bytes already read if the download restarted from the beginning
+        while self.body_position < self.position {
+            let skip_size = (self.position - self.body_position).min(buf.len() as u64) as usize;
+            match body.read(&mut buf[..skip_size]) {
+                Ok(0) => {
+                    return Err(ReadFailure::Transient(Error::from(
+                        ErrorKind::UnexpectedEof,
+                    )))
+                }
+                Ok(count) => self.body_position += count as u64,
+                Err(err) => return Err(ReadFailure::Transient(err)),
+            }
+        }
+
+        let count = body.read(buf).map_err(ReadFailure::Transient)?;
+
+        if count == 0 && self.length.map_or(false, |length| self.position < length) {
+            return Err(ReadFailure::Transient(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("Connection closed while downloading {}", self.url),
+            )));
+        }
+
+        self.position += count as u64;
+        self.body_position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Read for UrlReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut failures = 0;
+        loop {
+            match self.read_body(buf) {
+                Ok(count) => return Ok(count),
+                Err(ReadFailure::Transient(err)) => {
+                    failures += 1;
+                    if failures > MAX_CONSECUTIVE_FAILURES {
+                        return Err(err);
+                    }
+                    self.body = None;
+                    std::thread::sleep(RETRY_BASE_DELAY * (1 << (failures - 1)));
+                }
+                Err(ReadFailure::Fatal(err)) => return Err(err),
+            }
+        }
+    }
+}
@@ -6,8 +6,9 @@ use crate::processor::KmersTransformProcessor;
 use crate::reader::{InputBucketDesc, KmersTransformReader};
 use crate::resplitter::KmersTransformResplitter;
 use config::{
-    BucketIndexType, KEEP_FILES, KMERS_TRANSFORM_READS_CHUNKS_SIZE, MAXIMUM_JIT_PROCESSED_BUCKETS,
-    MAXIMUM_SECOND_BUCKETS_COUNT, MINIMUM_LOG_DELTA_TIME, PACKETS_PRIORITY_FILES,
+    keep_temp_files, BucketIndexType, PipelineSettings, KMERS_TRANSFORM_READS_CHUNKS_SIZE,
+    MAXIMUM_JIT_PROCESSED_BUCKETS, MAXIMUM_SECOND_BUCKETS_COUNT, MINIMUM_LOG_DELTA_TIME,
+    PACKETS_PRIORITY_FILES,
 };
 use io::compressed_read::{CompressedRead, CompressedReadIndipendent};
 use io::concurrent::temp_reads::extra_data::{
@@ -168,13 +169,11 @@ impl<F: KmersTransformExecutorFactory> KmersTransform<F> {
         buckets_count: usize,
         global_extra_data: Arc<F::GlobalExtraData>,
         threads_count: usize,
+        settings: PipelineSettings,
         k: usize,
         min_bucket_size: u64,
     ) -> Self {
-        let counters = CountersAnalyzer::load_from_file(
-            buckets_counters_path,
-            !KEEP_FILES.load(Ordering::Relaxed),
-        );
+        let counters = CountersAnalyzer::load_from_file(buckets_counters_path, !keep_temp_files());
 
         let mut total_buckets_size = 0;
 
@@ -241,8 +240,8 @@ impl<F: KmersTransformExecutorFactory> KmersTransform<F> {
             buckets_list
         };
 
-        let compute_threads_count = max(1, threads_count);
-        let read_threads_count = max(1, threads_count / 4 * 3);
+        let compute_threads_count = settings.compute_threads_count(max(1, threads_count));
+        let read_threads_count = settings.read_threads_count(max(1, threads_count / 4 * 3));
 
         let max_buckets = max(MAXIMUM_SECOND_BUCKETS_COUNT, compute_threads_count);
 
@@ -6,8 +6,8 @@ use crate::{
     KmersTransformPreprocessor,
 };
 use config::{
-    get_compression_level_info, get_memory_mode, SwapPriority, DEFAULT_OUTPUT_BUFFER_SIZE,
-    DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
+    get_compression_level_info, get_memory_mode, keep_temp_files, SwapPriority,
+    DEFAULT_OUTPUT_BUFFER_SIZE, DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT,
     MAXIMUM_JIT_PROCESSED_BUCKETS, MAX_INTERMEDIATE_MAP_SIZE, MIN_BUCKET_CHUNKS_FOR_READING_THREAD,
     PACKETS_PRIORITY_DEFAULT, PACKETS_PRIORITY_REWRITTEN, PARTIAL_VECS_CHECKPOINT_SIZE,
     USE_SECOND_BUCKET,
@@ -132,7 +132,7 @@ impl<F: KmersTransformExecutorFactory> KmersTransformReader<F> {
             &file.path,
             true,
             RemoveFileMode::Remove {
-                remove_fs: file.rewritten || !KEEP_FILES.load(Ordering::Relaxed),
+                remove_fs: file.rewritten || !keep_temp_files(),
             },
             DEFAULT_PREFETCH_AMOUNT,
         );
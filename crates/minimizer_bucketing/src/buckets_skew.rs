@@ -0,0 +1,219 @@
+use config::MinimizerType;
+use hashes::minimizer_ordering::{
+    get_minimizer_ordering, set_minimizer_ordering, set_random_order_key, MinimizerOrdering,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use utils::phase_log::log_warning;
+use utils::random_seed::{derive_seed, RandomComponent};
+
+// A bucket is skewed if it is larger than this factor times the median bucket
+const SKEWED_BUCKET_FACTOR: u64 = 10;
+// Smaller buckets are never considered skewed, as with tiny inputs the sizes are too noisy
+const SKEWED_BUCKET_MIN_COUNT: u64 = 1024;
+const MAX_SKEWED_BUCKETING_RETRIES: usize = 2;
+
+static SKEWED_BUCKETS_AUTO_RETRY: AtomicBool = AtomicBool::new(false);
+
+/// Enables repeating the minimizer bucketing with a perturbed random minimizer ordering when a single
+/// bucket is much larger than the others, as its processing would be a bottleneck for the next phases.
+/// Without it the skewed buckets are only reported
+pub fn set_skewed_buckets_auto_retry(enabled: bool) {
+    SKEWED_BUCKETS_AUTO_RETRY.store(enabled, Ordering::Relaxed);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BucketsSkew {
+    pub largest_bucket: usize,
+    pub largest_count: u64,
+    pub median_count: u64,
+}
+
+/// Finds the largest bucket, if it is more than 10 times larger than the median one
+pub fn find_skewed_bucket(buckets_counts: &[u64]) -> Option<BucketsSkew> {
+    let (largest_bucket, largest_count) = buckets_counts
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by_key(|(_, count)| *count)?;
+
+    let mut sorted_counts = buckets_counts.to_vec();
+    sorted_counts.sort_unstable();
+    let median_count = sorted_counts[sorted_counts.len() / 2];
+
+    if largest_count >= SKEWED_BUCKET_MIN_COUNT
+        && largest_count > SKEWED_BUCKET_FACTOR * median_count.max(1)
+    {
+        Some(BucketsSkew {
+            largest_bucket,
+            largest_count,
+            median_count,
+        })
+    } else {
+        None
+    }
+}
+
+/// Checks the sizes of the buckets produced by a bucketing attempt (starting from 0), logging if
+/// they are skewed. In auto retry mode it also switches to a random minimizer ordering with a new
+/// seed derived from the seed of the run, returning true if the bucketing should be repeated
+pub fn check_buckets_skew(
+    buckets_counts: &[u64],
+    attempt: usize,
+    random_seed: Option<u64>,
+) -> bool {
+    let retry_key = skew_retry_key(
+        buckets_counts,
+        attempt,
+        random_seed,
+        SKEWED_BUCKETS_AUTO_RETRY.load(Ordering::Relaxed),
+    );
+
+    if let Some(key) = retry_key {
+        set_minimizer_ordering(MinimizerOrdering::Random);
+        set_random_order_key(key);
+    }
+    retry_key.is_some()
+}
+
+// Logs the skewed buckets, returning the key of the random ordering to repeat the bucketing with
+fn skew_retry_key(
+    buckets_counts: &[u64],
+    attempt: usize,
+    random_seed: Option<u64>,
+    auto_retry: bool,
+) -> Option<MinimizerType> {
+    let skew = find_skewed_bucket(buckets_counts)?;
+
+    let retry = auto_retry && attempt < MAX_SKEWED_BUCKETING_RETRIES;
+
+    log_warning(format!(
+        "Warning: minimizer bucket {} has {} sequences, {:.1}x the median bucket ({}) with the {:?} minimizer ordering, {}",
+        skew.largest_bucket,
+        skew.largest_count,
+        skew.largest_count as f64 / skew.median_count.max(1) as f64,
+        skew.median_count,
+        get_minimizer_ordering(),
+        if retry {
+            "repeating the bucketing with a perturbed minimizer seed"
+        } else {
+            "its processing may be a bottleneck"
+        }
+    ));
+
+    retry.then(|| {
+        derive_seed(
+            random_seed,
+            RandomComponent::MinimizerOrdering,
+            attempt as u64 + 1,
+        ) as MinimizerType
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_skewed_bucket, skew_retry_key};
+    use config::MinimizerType;
+    use hashes::cn_nthash::CanonicalNtHashIteratorFactory;
+    use hashes::minimizer_ordering::random_order_with_key;
+    use hashes::{ExtendableHashTraitType, HashFunction, HashFunctionFactory};
+
+    // The ordering is applied explicitly, so the unordered factory does not depend on the selected one
+    type H = CanonicalNtHashIteratorFactory;
+
+    const K: usize = 31;
+    const M: usize = 12;
+    const BUCKETS_BITS: usize = 4;
+
+    fn random_bases(state: &mut u64, count: usize) -> Vec<u8> {
+        (0..count)
+            .map(|_| {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                b"ACGT"[(*state >> 32) as usize % 4]
+            })
+            .collect()
+    }
+
+    // The hash ordering without a key, else the random ordering with the given key
+    fn order(hash: u64, random_key: Option<MinimizerType>) -> MinimizerType {
+        match random_key {
+            None => hash as MinimizerType,
+            Some(key) => random_order_with_key(hash as MinimizerType, key),
+        }
+    }
+
+    // Counts the sequences written to each bucket, splitting the reads where the minimizer changes
+    fn bucket_reads(reads: &[Vec<u8>], random_key: Option<MinimizerType>) -> Vec<u64> {
+        let mut counts = vec![0; 1 << BUCKETS_BITS];
+        for read in reads {
+            let hashes: Vec<_> = H::new(&read[..], M)
+                .iter()
+                .map(|hash| hash.to_unextendable())
+                .collect();
+
+            let mut last_minimizer = None;
+            for window in hashes.windows(K - M + 1) {
+                let minimizer = *window
+                    .iter()
+                    .min_by_key(|hash| order(**hash, random_key))
+                    .unwrap();
+                if last_minimizer != Some(minimizer) {
+                    counts[H::get_bucket(0, BUCKETS_BITS, minimizer) as usize] += 1;
+                    last_minimizer = Some(minimizer);
+                }
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn skewed_buckets_are_balanced_by_retry() {
+        let mut state = 0x2545_f491_4f6c_dd1d;
+
+        // The m-mer with the smallest hash among many random ones is the minimizer of every kmer
+        // containing it with the hash ordering
+        let dominant = (0..4096)
+            .map(|_| random_bases(&mut state, M))
+            .min_by_key(|mmer| {
+                H::new(&mmer[..], M)
+                    .iter()
+                    .next()
+                    .unwrap()
+                    .to_unextendable()
+            })
+            .unwrap();
+
+        // Reads with the dominant m-mer repeated often enough to be in all their kmers
+        let reads: Vec<_> = (0..2048)
+            .map(|_| {
+                let mut read = vec![];
+                for _ in 0..4 {
+                    read.extend_from_slice(&dominant);
+                    read.extend_from_slice(&random_bases(&mut state, K - 2 * M));
+                }
+                read
+            })
+            .collect();
+
+        let initial_counts = bucket_reads(&reads, None);
+        assert!(find_skewed_bucket(&initial_counts).is_some());
+
+        let mut counts = initial_counts.clone();
+
+        let mut attempt = 0;
+        while let Some(key) = skew_retry_key(&counts, attempt, Some(7), true) {
+            attempt += 1;
+            counts = bucket_reads(&reads, Some(key));
+        }
+
+        assert!(attempt > 0);
+        assert_eq!(find_skewed_bucket(&counts), None);
+
+        // Fraction of the sequences in the largest bucket
+        let largest_fraction = |counts: &[u64]| {
+            *counts.iter().max().unwrap() as f64 / counts.iter().sum::<u64>() as f64
+        };
+        assert!(largest_fraction(&counts) < largest_fraction(&initial_counts) / 2.0);
+    }
+}
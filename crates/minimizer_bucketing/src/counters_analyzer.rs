@@ -14,10 +14,11 @@ pub struct BucketCounter {
 pub struct CountersAnalyzer {
     counters: Vec<Vec<BucketCounter>>,
     median: u64,
+    kmer_length: usize,
 }
 
 impl CountersAnalyzer {
-    pub fn new(counters: Vec<Vec<AtomicU64>>) -> Self {
+    pub fn new(counters: Vec<Vec<AtomicU64>>, k: usize) -> Self {
         let mut sorted_counters: Vec<(u64, usize, usize)> = Vec::new();
 
         let counters: Vec<Vec<BucketCounter>> = counters
@@ -44,13 +45,37 @@ impl CountersAnalyzer {
             0
         };
 
-        Self { counters, median }
+        Self {
+            counters,
+            median,
+            kmer_length: k,
+        }
     }
 
     pub fn get_counters_for_bucket(&self, bucket: BucketIndexType) -> &Vec<BucketCounter> {
         &self.counters[bucket as usize]
     }
 
+    /// The number of sequences written to each bucket, summing its sub-buckets
+    pub fn get_buckets_counts(&self) -> Vec<u64> {
+        self.counters
+            .iter()
+            .map(|bucket| bucket.iter().map(|c| c.count).sum())
+            .collect()
+    }
+
+    /// Checks that the buckets were produced with the requested k-mers length
+    pub fn check_kmer_length(&self, k: usize) -> Result<(), String> {
+        if self.kmer_length != k {
+            Err(format!(
+                "The temporary buckets were built with k={}, but k={} was requested. Please restart from the first step",
+                self.kmer_length, k
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn print_debug(&self) {
         println!("************** BUCKETS DEBUG: **************");
         // for (i, cnt_bucket) in self.counters.iter().enumerate() {
@@ -1,6 +1,8 @@
 #![feature(impl_trait_in_assoc_type)]
 
+pub mod buckets_skew;
 pub mod counters_analyzer;
+pub mod minimizers_sampling;
 mod queue_data;
 mod reader;
 mod sequences_splitter;
@@ -10,11 +12,11 @@ use crate::queue_data::MinimizerBucketingQueueData;
 use crate::reader::MinimizerBucketingFilesReader;
 use crate::sequences_splitter::SequencesSplitter;
 use config::{
-    get_compression_level_info, get_memory_mode, BucketIndexType, SwapPriority,
+    get_compression_level_info, get_memory_mode, BucketIndexType, PipelineSettings, SwapPriority,
     DEFAULT_PER_CPU_BUFFER_SIZE, MINIMIZER_BUCKETS_CHECKPOINT_SIZE, PACKETS_PRIORITY_DEFAULT,
     READ_INTERMEDIATE_CHUNKS_SIZE, READ_INTERMEDIATE_QUEUE_MULTIPLIER,
 };
-use config::{MAXIMUM_SECOND_BUCKETS_COUNT, USE_SECOND_BUCKET};
+use config::{MAXIMUM_SECOND_BUCKETS_COUNT, PREFER_MEMORY, USE_SECOND_BUCKET};
 use hashes::HashableSequence;
 use io::compressed_read::CompressedRead;
 use io::concurrent::temp_reads::creads_utils::{
@@ -23,6 +25,7 @@ use io::concurrent::temp_reads::creads_utils::{
 use io::concurrent::temp_reads::extra_data::{
     SequenceExtraDataConsecutiveCompression, SequenceExtraDataTempBufferManagement,
 };
+use io::open_files_limit::{buckets_waves, BucketingOpenFiles};
 use io::sequences_reader::DnaSequence;
 use io::sequences_stream::{GenericSequencesStream, SequenceInfo};
 use parallel_processor::buckets::concurrent::{BucketsThreadBuffer, BucketsThreadDispatcher};
@@ -46,6 +49,7 @@ use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use utils::phase_log::{SubPhase, SubPhaseTimer};
 
 pub trait MinimizerInputSequence: HashableSequence + Copy {
     fn get_subslice(&self, range: Range<usize>) -> Self;
@@ -95,6 +99,12 @@ pub trait MinimizerBucketingExecutorFactory: Sized {
 
     fn new(global_data: &Arc<MinimizerBucketingCommonData<Self::GlobalData>>)
         -> Self::ExecutorType;
+
+    /// True if the sequences without any kmer to bucket must be passed to the executors,
+    /// instead of being dropped when they are read
+    fn reports_discarded_sequences() -> bool {
+        false
+    }
 }
 
 pub trait MinimizerBucketingExecutor<Factory: MinimizerBucketingExecutorFactory>:
@@ -109,6 +119,16 @@ pub trait MinimizerBucketingExecutor<Factory: MinimizerBucketingExecutorFactory>
         preprocess_info: &mut Factory::PreprocessInfo,
     );
 
+    /// Called for the sequences without any stretch of k valid bases, only in the first pass over
+    /// the inputs, if the factory reports the discarded sequences
+    fn discard_sequence(
+        &mut self,
+        _stream_info: &Factory::StreamInfo,
+        _read_index: u64,
+        _sequence: &DnaSequence,
+    ) {
+    }
+
     fn reprocess_sequence(
         &mut self,
         flags: u8,
@@ -149,6 +169,10 @@ pub struct MinimizerBucketingCommonData<GlobalData> {
     pub max_second_buckets_count_bits: usize,
     pub global_counters: Vec<Vec<AtomicU64>>,
     pub global_data: GlobalData,
+    pub settings: PipelineSettings,
+    /// The index of the current pass over the inputs, when the buckets are written in waves.
+    /// The side effects of reading the inputs should happen only in the first one
+    pub current_wave: AtomicUsize,
 }
 
 impl<GlobalData> MinimizerBucketingCommonData<GlobalData> {
@@ -159,6 +183,7 @@ impl<GlobalData> MinimizerBucketingCommonData<GlobalData> {
         ignored_length: usize,
         max_second_buckets_count: usize,
         global_data: GlobalData,
+        settings: PipelineSettings,
     ) -> Self {
         Self {
             k,
@@ -178,12 +203,16 @@ impl<GlobalData> MinimizerBucketingCommonData<GlobalData> {
                 })
                 .collect(),
             global_data,
+            settings,
+            current_wave: AtomicUsize::new(0),
         }
     }
 }
 
 pub struct MinimizerBucketingExecutionContext<GlobalData> {
     pub buckets: Arc<MultiThreadBuckets<CompressedBinaryWriter>>,
+    /// The buckets written by this pass over the inputs, the other ones are skipped
+    pub buckets_wave: Range<usize>,
     pub common: Arc<MinimizerBucketingCommonData<GlobalData>>,
     pub current_file: AtomicUsize,
     pub executor_group_address: RwLock<Option<ExecutorAddress>>,
@@ -194,6 +223,8 @@ pub struct MinimizerBucketingExecutionContext<GlobalData> {
 
     pub partial_read_copyback: Option<usize>,
     pub copy_ident: bool,
+    /// The sequences shorter than the ignored length are passed to the executors to be reported
+    pub report_discarded: bool,
 }
 
 pub struct GenericMinimizerBucketing;
@@ -244,17 +275,25 @@ impl<E: MinimizerBucketingExecutorFactory + Sync + Send + 'static> MinimizerBuck
             let mut preprocess_info = Default::default();
             let input_packet = input_packet.deref();
 
+            let report_discarded = context.report_discarded
+                && context.common.current_wave.load(Ordering::Relaxed) == 0;
+
+            let hashing_timer =
+                SubPhaseTimer::start(SubPhase::Hashing, context.common.settings.subphase_timing);
             for (index, (x, seq_info)) in input_packet.iter_sequences().enumerate() {
+                let read_index = input_packet.start_read_index + index as u64;
                 total_bases += x.seq.len() as u64;
                 buckets_processor.preprocess_dna_sequence(
                     &input_packet.stream_info,
                     seq_info,
-                    input_packet.start_read_index + index as u64,
+                    read_index,
                     &x,
                     &mut preprocess_info,
                 );
 
+                let mut bucketed = false;
                 sequences_splitter.process_sequences(&x, &mut |sequence: &[u8], range| {
+                    bucketed = true;
                     buckets_processor.process_sequence(
                         &preprocess_info,
                         sequence,
@@ -263,6 +302,10 @@ impl<E: MinimizerBucketingExecutorFactory + Sync + Send + 'static> MinimizerBuck
                         context.common.buckets_count_bits,
                         context.common.max_second_buckets_count_bits,
                         |bucket, next_bucket, seq, flags, extra, extra_buffer| {
+                            if !context.buckets_wave.contains(&(bucket as usize)) {
+                                return;
+                            }
+
                             let counter = &mut counters
                                 [((bucket as usize) << counters_log) + (next_bucket as usize)];
 
@@ -272,8 +315,12 @@ impl<E: MinimizerBucketingExecutorFactory + Sync + Send + 'static> MinimizerBuck
                                     .fetch_add(256, Ordering::Relaxed);
                             }
 
+                            let _bucket_writing_timer = SubPhaseTimer::start(
+                                SubPhase::BucketWriting,
+                                context.common.settings.subphase_timing,
+                            );
                             tmp_reads_buffer.add_element_extended(
-                                bucket,
+                                bucket - context.buckets_wave.start as BucketIndexType,
                                 &extra,
                                 extra_buffer,
                                 &CompressedReadsBucketData::new(seq, flags, next_bucket as u8),
@@ -282,8 +329,13 @@ impl<E: MinimizerBucketingExecutorFactory + Sync + Send + 'static> MinimizerBuck
                     );
                 });
 
+                if !bucketed && report_discarded {
+                    buckets_processor.discard_sequence(&input_packet.stream_info, read_index, &x);
+                }
+
                 sequences_count += 1;
             }
+            drop(hashing_timer);
 
             SEQ_COUNT.fetch_add(sequences_count, Ordering::Relaxed);
             let total_bases_count =
@@ -333,6 +385,8 @@ impl<E: MinimizerBucketingExecutorFactory + Sync + Send + 'static> MinimizerBuck
             }
         }
 
+        let _flushing_timer =
+            SubPhaseTimer::start(SubPhase::Flushing, context.common.settings.subphase_timing);
         tmp_reads_buffer.finalize();
     }
 }
@@ -398,6 +452,19 @@ impl<E: MinimizerBucketingExecutorFactory + Sync + Send + 'static> AsyncExecutor
 //     }
 // }
 
+/// Moves a bucket written in a wave to the path it has when all the buckets are written at once,
+/// if the buckets are written only on disk. The buckets that can be kept in memory keep the name of their wave
+fn place_wave_bucket(output_path: &Path, wave_file: PathBuf, bucket_index: usize) -> PathBuf {
+    let bucket_file = output_path
+        .join("bucket")
+        .with_extension(bucket_index.to_string());
+    if !PREFER_MEMORY.load(Ordering::Relaxed) && std::fs::rename(&wave_file, &bucket_file).is_ok() {
+        bucket_file
+    } else {
+        wave_file
+    }
+}
+
 impl GenericMinimizerBucketing {
     pub fn do_bucketing<
         E: MinimizerBucketingExecutorFactory + Sync + Send + 'static,
@@ -413,123 +480,176 @@ impl GenericMinimizerBucketing {
         partial_read_copyback: Option<usize>,
         copy_ident: bool,
         ignored_length: usize,
-    ) -> (Vec<PathBuf>, PathBuf) {
-        let read_threads_count = max(1, threads_count / 2);
-        let compute_threads_count = max(1, threads_count.saturating_sub(read_threads_count / 4));
-
-        let buckets = Arc::new(MultiThreadBuckets::<CompressedBinaryWriter>::new(
-            buckets_count,
-            output_path.join("bucket"),
-            &(
-                get_memory_mode(SwapPriority::MinimizerBuckets),
-                MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
-                get_compression_level_info(),
-            ),
-        ));
+        settings: PipelineSettings,
+    ) -> (Vec<PathBuf>, PathBuf)
+    where
+        S::SequenceBlockData: Clone,
+    {
+        let read_threads_count = settings.read_threads_count(max(1, threads_count / 2));
+        let compute_threads_count = settings
+            .compute_threads_count(max(1, threads_count.saturating_sub(read_threads_count / 4)));
 
         let second_buckets_count = max(
             MAXIMUM_SECOND_BUCKETS_COUNT,
             threads_count.next_power_of_two(),
         );
 
-        let global_context = Arc::new(MinimizerBucketingExecutionContext {
-            buckets,
-            current_file: AtomicUsize::new(0),
-            executor_group_address: RwLock::new(Some(
-                MinimizerBucketingExecWriter::<E>::generate_new_address(()),
-            )),
-            processed_files: AtomicUsize::new(0),
-            total_files: input_blocks.len(),
-            common: Arc::new(MinimizerBucketingCommonData::new(
-                k,
-                m,
-                buckets_count,
-                ignored_length,
-                second_buckets_count,
-                global_data,
-            )),
-            threads_count: compute_threads_count,
-            partial_read_copyback,
-            read_threads_count,
-            copy_ident,
-        });
-
-        {
-            let max_read_buffers_count =
-                compute_threads_count * READ_INTERMEDIATE_QUEUE_MULTIPLIER.load(Ordering::Relaxed);
-
-            let execution_context = ExecutionContext::new();
-
-            let disk_thread_pool = ExecThreadPool::new(
-                &execution_context,
-                global_context.read_threads_count,
-                "mm_disk",
-            );
-            let compute_thread_pool =
-                ExecThreadPool::new(&execution_context, compute_threads_count, "mm_comp");
+        let common = Arc::new(MinimizerBucketingCommonData::new(
+            k,
+            m,
+            buckets_count,
+            ignored_length,
+            second_buckets_count,
+            global_data,
+            settings,
+        ));
 
-            let mut input_files = ExecutorInput::from_iter(
-                input_blocks.into_iter(),
-                ExecutorInputAddressMode::Single,
+        // With a low open files limit the buckets are written in waves, each one reading all the inputs
+        // and writing only its own buckets
+        let waves = buckets_waves(buckets_count, settings.max_open_files);
+        let input_blocks: Vec<_> = input_blocks.collect();
+        let mut buckets_files = Vec::with_capacity(buckets_count);
+
+        for (wave_index, wave) in waves.iter().enumerate() {
+            common.current_wave.store(wave_index, Ordering::Relaxed);
+            let _open_files = BucketingOpenFiles::open(wave.len());
+
+            let buckets = Arc::new(MultiThreadBuckets::<CompressedBinaryWriter>::new(
+                wave.len(),
+                if waves.len() == 1 {
+                    output_path.join("bucket")
+                } else {
+                    output_path.join(format!("bucket-wave{}", wave_index))
+                },
+                &(
+                    get_memory_mode(SwapPriority::MinimizerBuckets),
+                    MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
+                    get_compression_level_info(),
+                ),
+            ));
+
+            let global_context = Arc::new(MinimizerBucketingExecutionContext {
+                buckets,
+                buckets_wave: wave.clone(),
+                current_file: AtomicUsize::new(0),
+                executor_group_address: RwLock::new(Some(
+                    MinimizerBucketingExecWriter::<E>::generate_new_address(()),
+                )),
+                processed_files: AtomicUsize::new(0),
+                total_files: input_blocks.len(),
+                common: common.clone(),
+                threads_count: compute_threads_count,
+                partial_read_copyback,
+                read_threads_count,
+                copy_ident,
+                report_discarded: E::reports_discarded_sequences(),
+            });
+
+            Self::run_bucketing_wave::<E, S>(
+                &global_context,
+                input_blocks.iter().cloned(),
+                compute_threads_count,
             );
 
-            let reader_executors = disk_thread_pool
-                .register_executors::<MinimizerBucketingFilesReader<E::GlobalData, E::StreamInfo, S>>(
-                    global_context.read_threads_count,
-                    PoolAllocMode::Shared {
-                        capacity: max_read_buffers_count,
+            let global_context = Arc::try_unwrap(global_context)
+                .unwrap_or_else(|_| panic!("Cannot get execution context!"));
+
+            let _flushing_timer =
+                SubPhaseTimer::start(SubPhase::Flushing, settings.subphase_timing);
+            let wave_files = global_context.buckets.finalize();
+            if waves.len() == 1 {
+                buckets_files = wave_files;
+            } else {
+                buckets_files.extend(wave_files.into_iter().enumerate().map(
+                    |(index, wave_file)| {
+                        place_wave_bucket(output_path, wave_file, wave.start + index)
                     },
-                    READ_INTERMEDIATE_CHUNKS_SIZE,
-                    &global_context,
-                );
+                ));
+            }
+        }
 
-            let writer_executors = compute_thread_pool
-                .register_executors::<MinimizerBucketingExecWriter<E>>(
-                    compute_threads_count,
-                    PoolAllocMode::None,
-                    (),
-                    &global_context,
-                );
+        let common_context = Arc::try_unwrap(common)
+            .unwrap_or_else(|_| panic!("Cannot get common execution context!"));
 
-            input_files.set_output_executor::<MinimizerBucketingFilesReader<E::GlobalData, E::StreamInfo, S>>(
-                &execution_context,
-                (),
-                PACKETS_PRIORITY_DEFAULT,
-            );
+        let counters_analyzer = CountersAnalyzer::new(common_context.global_counters, k);
+        // counters_analyzer.print_debug();
 
-            execution_context.register_executors_batch(
-                vec![global_context
-                    .executor_group_address
-                    .read()
-                    .as_ref()
-                    .unwrap()
-                    .clone()],
-                PACKETS_PRIORITY_DEFAULT,
-            );
+        let counters_file = output_path.join("buckets-counters.dat");
 
-            execution_context.start();
-            execution_context.wait_for_completion(reader_executors);
+        counters_analyzer.serialize_to_file(&counters_file);
 
-            global_context.executor_group_address.write().take();
+        (buckets_files, counters_file)
+    }
 
-            execution_context.wait_for_completion(writer_executors);
+    fn run_bucketing_wave<
+        E: MinimizerBucketingExecutorFactory + Sync + Send + 'static,
+        S: GenericSequencesStream,
+    >(
+        global_context: &Arc<MinimizerBucketingExecutionContext<E::GlobalData>>,
+        input_blocks: impl ExactSizeIterator<Item = (S::SequenceBlockData, E::StreamInfo)>,
+        compute_threads_count: usize,
+    ) {
+        let max_read_buffers_count =
+            compute_threads_count * READ_INTERMEDIATE_QUEUE_MULTIPLIER.load(Ordering::Relaxed);
 
-            execution_context.join_all();
-        }
+        let execution_context = ExecutionContext::new();
 
-        let global_context = Arc::try_unwrap(global_context)
-            .unwrap_or_else(|_| panic!("Cannot get execution context!"));
+        let disk_thread_pool = ExecThreadPool::new(
+            &execution_context,
+            global_context.read_threads_count,
+            "mm_disk",
+        );
+        let compute_thread_pool =
+            ExecThreadPool::new(&execution_context, compute_threads_count, "mm_comp");
 
-        let common_context = Arc::try_unwrap(global_context.common)
-            .unwrap_or_else(|_| panic!("Cannot get common execution context!"));
+        let mut input_files =
+            ExecutorInput::from_iter(input_blocks, ExecutorInputAddressMode::Single);
 
-        let counters_analyzer = CountersAnalyzer::new(common_context.global_counters);
-        // counters_analyzer.print_debug();
+        let reader_executors = disk_thread_pool.register_executors::<MinimizerBucketingFilesReader<
+            E::GlobalData,
+            E::StreamInfo,
+            S,
+        >>(
+            global_context.read_threads_count,
+            PoolAllocMode::Shared {
+                capacity: max_read_buffers_count,
+            },
+            READ_INTERMEDIATE_CHUNKS_SIZE,
+            global_context,
+        );
 
-        let counters_file = output_path.join("buckets-counters.dat");
+        let writer_executors = compute_thread_pool
+            .register_executors::<MinimizerBucketingExecWriter<E>>(
+                compute_threads_count,
+                PoolAllocMode::None,
+                (),
+                global_context,
+            );
 
-        counters_analyzer.serialize_to_file(&counters_file);
+        input_files
+            .set_output_executor::<MinimizerBucketingFilesReader<E::GlobalData, E::StreamInfo, S>>(
+                &execution_context,
+                (),
+                PACKETS_PRIORITY_DEFAULT,
+            );
+
+        execution_context.register_executors_batch(
+            vec![global_context
+                .executor_group_address
+                .read()
+                .as_ref()
+                .unwrap()
+                .clone()],
+            PACKETS_PRIORITY_DEFAULT,
+        );
+
+        execution_context.start();
+        execution_context.wait_for_completion(reader_executors);
+
+        global_context.executor_group_address.write().take();
+
+        execution_context.wait_for_completion(writer_executors);
 
-        (global_context.buckets.finalize(), counters_file)
+        execution_context.join_all();
     }
 }
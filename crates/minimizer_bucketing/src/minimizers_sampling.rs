@@ -0,0 +1,57 @@
+use config::{MinimizerType, MINIMIZERS_FREQUENCY_SAMPLE_BASES};
+use hashes::minimizer_ordering::MinimizersFrequencySampler;
+use hashes::{ExtendableHashTraitType, HashFunction, MinimizerHashFunctionFactory};
+use io::sequences_stream::GenericSequencesStream;
+use utils::phase_log::{log_info, start_phase};
+
+/// Counts the m-mers of the first part of the input and installs the most frequent ones
+/// as the minimizers to avoid with the frequency aware ordering
+pub fn sample_minimizers_frequencies<
+    'a,
+    H: MinimizerHashFunctionFactory,
+    S: GenericSequencesStream,
+>(
+    input_blocks: impl Iterator<Item = &'a S::SequenceBlockData>,
+    m: usize,
+) where
+    S::SequenceBlockData: 'a,
+{
+    start_phase("phase: minimizers sampling".to_string());
+
+    let mut sampler = MinimizersFrequencySampler::new();
+    // The invalid records are reported by the bucketing, that reads the whole input
+    let mut stream = S::new(false);
+    let mut sampled_bases = 0;
+
+    for block in input_blocks {
+        if sampled_bases >= MINIMIZERS_FREQUENCY_SAMPLE_BASES {
+            break;
+        }
+
+        stream.read_block(block, false, Some(m - 1), |sequence, _info| {
+            if sampled_bases >= MINIMIZERS_FREQUENCY_SAMPLE_BASES {
+                return;
+            }
+            sampled_bases += sequence.seq.len();
+
+            for part in sequence.seq.split(|b| *b == b'N') {
+                if part.len() < m {
+                    continue;
+                }
+                for hash in H::new(part, m).iter() {
+                    // Sample the minimizers before applying the ordering
+                    sampler.add(H::get_u64(hash.to_unextendable()) as MinimizerType);
+                }
+            }
+        });
+    }
+
+    let frequent = sampler.get_frequent();
+    frequent.install();
+
+    log_info(format!(
+        "Sampled {} m-mers, found {} frequent minimizers classes",
+        sampler.sampled_count(),
+        frequent.frequent_count()
+    ));
+}
@@ -49,7 +49,7 @@ impl<
     ) {
         let packets_pool = ops.pool_alloc_await(0).await;
 
-        let mut sequences_stream = SequencesStream::new();
+        let mut sequences_stream = SequencesStream::new(context.common.settings.strict_input);
 
         while let Some(mut input_packet) = ops.receive_packet().await {
             let mut data_packet = packets_pool.alloc_packet().await;
@@ -72,7 +72,7 @@ impl<
                 |x, seq_info| {
                     let mut data = data_packet.deref_mut();
 
-                    if x.seq.len() < context.common.ignored_length {
+                    if x.seq.len() < context.common.ignored_length && !context.report_discarded {
                         return;
                     }
 
@@ -0,0 +1,183 @@
+use crate::color_unitigs::DecodedSubsets;
+use crate::kmer_counts::KmerCountsTable;
+use colors::colors_manager::ColorMapReader;
+use colors::storage::deserializer::ColorsDeserializer;
+use colors::DefaultColorsSerializer;
+use config::ColorIndexType;
+use io::sequences_reader::SequencesReader;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Lower bounds of the default abundance bins, the last bin has no upper bound
+pub const DEFAULT_ABUNDANCE_BINS: &[u64] = &[1, 2, 3, 5, 10, 20, 50, 100, 1000];
+
+/// The number of distinct kmers of each color in each abundance bin
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AbundanceProfile {
+    /// Lower bound of each bin, in increasing order
+    pub bins: Vec<u64>,
+    /// Distinct kmers count, indexed by color and then by bin
+    pub counts: Vec<Vec<u64>>,
+}
+
+impl AbundanceProfile {
+    fn new(bins: &[u64], colors_count: usize) -> Self {
+        Self {
+            bins: bins.to_vec(),
+            counts: vec![vec![0; bins.len()]; colors_count],
+        }
+    }
+
+    /// The bin of an abundance, None if it is below the first bin
+    pub fn bin_index(&self, abundance: u64) -> Option<usize> {
+        self.bins
+            .partition_point(|bin| *bin <= abundance)
+            .checked_sub(1)
+    }
+
+    /// The label of a bin, as `2`, `3-4` or `1000+` for the last bin
+    pub fn bin_label(&self, bin: usize) -> String {
+        match self.bins.get(bin + 1) {
+            None => format!("{}+", self.bins[bin]),
+            Some(next) if *next == self.bins[bin] + 1 => self.bins[bin].to_string(),
+            Some(next) => format!("{}-{}", self.bins[bin], next - 1),
+        }
+    }
+}
+
+// Colors format: C:<subset in hex>:<count of consecutive kmers with that subset>
+fn parse_color_run(field: &[u8]) -> Option<(ColorIndexType, usize)> {
+    let mut parts = std::str::from_utf8(field.strip_prefix(b"C:")?)
+        .ok()?
+        .split(':');
+    let subset = ColorIndexType::from_str_radix(parts.next()?, 16).ok()?;
+    let kmers_count = parts.next()?.parse().ok()?;
+    Some((subset, kmers_count))
+}
+
+/// Computes the abundance profile of each color of a graph, counting each distinct kmer of a color
+/// in the bin of its abundance in that color. The abundances are looked up in a kmer counts table
+/// for each color, indexed by color, as built by build_kmer_counts_table from the inputs of the color.
+/// The colors of the kmers are decoded from the colormap next to the graph
+pub fn compute_abundance_profile(
+    graph_input: impl AsRef<Path>,
+    color_tables: &[PathBuf],
+    bins: &[u64],
+) -> Result<(AbundanceProfile, Vec<String>), String> {
+    if bins.is_empty() || bins.windows(2).any(|bins| bins[0] >= bins[1]) {
+        return Err("The abundance bins must be non-empty and increasing".to_string());
+    }
+
+    let colormap_file = graph_input.as_ref().with_extension("colors.dat");
+    let mut colors_deserializer =
+        ColorsDeserializer::<DefaultColorsSerializer>::new(colormap_file, true);
+
+    if color_tables.len() != colors_deserializer.colors_count() {
+        return Err(format!(
+            "The graph has {} colors, but {} kmer counts tables were given",
+            colors_deserializer.colors_count(),
+            color_tables.len()
+        ));
+    }
+
+    let mut tables = color_tables
+        .iter()
+        .map(KmerCountsTable::open)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let k = match tables.first() {
+        Some(table) => table.kmer_length(),
+        None => return Err("The graph has no colors".to_string()),
+    };
+    if tables.iter().any(|table| table.kmer_length() != k) {
+        return Err("The kmer counts tables have different kmer lengths".to_string());
+    }
+    colors_deserializer.check_kmer_length(k)?;
+
+    let color_names = (0..colors_deserializer.colors_count())
+        .map(|color| {
+            colors_deserializer
+                .get_color_name(color as ColorIndexType, false)
+                .to_string()
+        })
+        .collect();
+
+    let mut profile = AbundanceProfile::new(bins, color_tables.len());
+    let mut decoded_subsets = DecodedSubsets::new();
+
+    SequencesReader::new().process_file_extended(
+        graph_input.as_ref(),
+        |seq| {
+            let mut kmer_position = 0;
+            for (subset, kmers_count) in seq
+                .ident_data
+                .split(|c| *c == b' ')
+                .filter_map(parse_color_run)
+            {
+                let colors = decoded_subsets.get(&mut colors_deserializer, subset);
+
+                for position in kmer_position..(kmer_position + kmers_count) {
+                    let kmer = &seq.seq[position..position + k];
+                    for color in colors.iter() {
+                        let color = *color as usize;
+                        let abundance = tables[color].get_count(kmer);
+                        if let Some(bin) = profile.bin_index(abundance) {
+                            profile.counts[color][bin] += 1;
+                        }
+                    }
+                }
+                kmer_position += kmers_count;
+            }
+        },
+        None,
+        true,
+        false,
+    );
+
+    Ok((profile, color_names))
+}
+
+/// Writes an abundance profile as a tab-separated matrix, with a row for each color
+/// and a column for each abundance bin
+pub fn write_abundance_profile(
+    profile: &AbundanceProfile,
+    color_names: &[String],
+    output_file: impl AsRef<Path>,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(output_file)?);
+
+    write!(writer, "#color")?;
+    for bin in 0..profile.bins.len() {
+        write!(writer, "\t{}", profile.bin_label(bin))?;
+    }
+    writeln!(writer)?;
+
+    for (color_name, counts) in color_names.iter().zip(profile.counts.iter()) {
+        write!(writer, "{}", color_name)?;
+        for count in counts {
+            write!(writer, "\t{}", count)?;
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AbundanceProfile;
+
+    #[test]
+    fn abundance_bins_and_labels() {
+        let profile = AbundanceProfile::new(&[1, 2, 3, 5, 10], 1);
+
+        assert_eq!(profile.bin_index(0), None);
+        assert_eq!(profile.bin_index(1), Some(0));
+        assert_eq!(profile.bin_index(4), Some(2));
+        assert_eq!(profile.bin_index(9), Some(3));
+        assert_eq!(profile.bin_index(500), Some(4));
+
+        let labels: Vec<_> = (0..5).map(|bin| profile.bin_label(bin)).collect();
+        assert_eq!(labels, ["1", "2", "3-4", "5-9", "10+"]);
+    }
+}
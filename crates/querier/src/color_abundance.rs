@@ -0,0 +1,38 @@
+use parking_lot::Mutex;
+
+static MIN_COLOR_ABUNDANCE: Mutex<f64> = Mutex::new(0.0);
+
+/// Drops from the colored query output the (query, color) pairs where the color covers less than this
+/// fraction of the query kmers. Differently from a minimum number of matched kmers, the threshold
+/// does not depend on the query length, so the noise colors matching a few kmers of long queries
+/// are dropped while the short queries fully matched by a color are kept. With 0 (the default)
+/// all the matched colors are reported
+pub fn set_min_color_abundance(min_abundance: f64) {
+    *MIN_COLOR_ABUNDANCE.lock() = min_abundance.max(0.0);
+}
+
+pub(crate) fn get_min_color_abundance() -> f64 {
+    *MIN_COLOR_ABUNDANCE.lock()
+}
+
+/// If a color with the given matched kmers of a query is above the minimum abundance,
+/// computed as the mean matched coverage of the query kmers in that color
+pub fn has_min_color_abundance(matched_kmers: u64, query_kmers: u64, min_abundance: f64) -> bool {
+    min_abundance <= 0.0 || (matched_kmers as f64) >= min_abundance * (query_kmers as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::has_min_color_abundance;
+
+    #[test]
+    fn colors_are_filtered_by_coverage_and_not_by_count() {
+        // Many matched kmers in a long query
+        assert!(!has_min_color_abundance(200, 1000, 0.5));
+        // Few matched kmers in a short query
+        assert!(has_min_color_abundance(20, 25, 0.5));
+        assert!(has_min_color_abundance(5, 10, 0.5));
+        // Without a threshold every match is kept
+        assert!(has_min_color_abundance(1, 1000, 0.0));
+    }
+}
@@ -0,0 +1,259 @@
+use crate::color_unitigs::parse_color_subset_kmers;
+use colors::colors_manager::ColorMapReader;
+use colors::storage::deserializer::ColorsDeserializer;
+use colors::DefaultColorsSerializer;
+use config::ColorIndexType;
+use io::sequences_reader::SequencesReader;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Maximum number of colors of the dense jaccard matrix, with more colors only the pairs
+/// of colors sharing kmers are written, as (color, color, jaccard) lines
+pub const MAX_DENSE_JACCARD_COLORS: usize = 4096;
+
+/// Maximum number of pairs of colors sharing kmers held in memory at once with more than
+/// MAX_DENSE_JACCARD_COLORS colors, about 32 bytes each. With more pairs, the pairs are
+/// accumulated and written in multiple passes over the colors subsets
+pub const MAX_SPARSE_JACCARD_PAIRS: u64 = 1 << 24;
+
+/// Obtains the standard jaccard matrix file path from a graph file path
+pub fn get_color_jaccard_file(graph_file: impl AsRef<Path>) -> PathBuf {
+    graph_file.as_ref().with_extension("jaccard.tsv")
+}
+
+// The shared kmers of each pair of colors, as an upper triangular matrix
+struct DenseSharedKmers {
+    colors_count: usize,
+    cells: Vec<u64>,
+}
+
+impl DenseSharedKmers {
+    fn new(colors_count: usize) -> Self {
+        Self {
+            colors_count,
+            cells: vec![0; colors_count * (colors_count + 1) / 2],
+        }
+    }
+
+    fn cell_index(&self, first: usize, second: usize) -> usize {
+        // The row of the first color starts after the previous rows, shorter by one cell each
+        first * self.colors_count - first * first.saturating_sub(1) / 2 + (second - first)
+    }
+
+    // The first color must be lower than the second one
+    fn add(&mut self, first: ColorIndexType, second: ColorIndexType, kmers: u64) {
+        let index = self.cell_index(first as usize, second as usize);
+        self.cells[index] += kmers;
+    }
+
+    fn get(&self, first: ColorIndexType, second: ColorIndexType) -> u64 {
+        self.cells[self.cell_index(first as usize, second as usize)]
+    }
+}
+
+// Decodes the input colors of a subset, sorted and without the sentinel colors
+fn decode_input_colors(
+    colors_deserializer: &mut ColorsDeserializer<DefaultColorsSerializer>,
+    subset: ColorIndexType,
+    input_colors_count: usize,
+    colors: &mut Vec<ColorIndexType>,
+) {
+    colors.clear();
+    colors_deserializer.get_color_mappings(subset, colors);
+    colors.retain(|color| (*color as usize) < input_colors_count);
+    colors.sort_unstable();
+    colors.dedup();
+}
+
+// Splits the first colors of the pairs in consecutive ranges with at most max_pairs pairs each,
+// given the pairs of each first color in the subsets. A first color has at most a pair with each
+// following color, and a color with more pairs than max_pairs has a range by itself
+fn split_in_pairs_passes(first_colors_pairs: &[u64], max_pairs: u64) -> Vec<Range<usize>> {
+    let colors_count = first_colors_pairs.len();
+    let mut passes = vec![];
+    let mut start = 0;
+    let mut pass_pairs = 0;
+
+    for (color, pairs) in first_colors_pairs.iter().enumerate() {
+        let pairs = (*pairs).min((colors_count - color - 1) as u64);
+        if color > start && pass_pairs + pairs > max_pairs {
+            passes.push(start..color);
+            start = color;
+            pass_pairs = 0;
+        }
+        pass_pairs += pairs;
+    }
+    if start < colors_count {
+        passes.push(start..colors_count);
+    }
+    passes
+}
+
+/// The jaccard similarity of two colors, given the kmers of each color and their shared kmers
+pub fn jaccard_similarity(first_kmers: u64, second_kmers: u64, shared_kmers: u64) -> f64 {
+    let union = first_kmers + second_kmers - shared_kmers;
+    if union == 0 {
+        0.0
+    } else {
+        shared_kmers as f64 / union as f64
+    }
+}
+
+/// Writes the all-pairs jaccard similarity between the colors of a graph, as the kmers shared by two
+/// colors over the kmers in any of them. The graph is streamed once, counting the kmers of each colors
+/// subset, then the subsets are decoded from the colormap and their kmers are added to all their pairs
+/// of colors, so the memory does not depend on the kmers count. The sentinel colors are not written.
+/// Up to MAX_DENSE_JACCARD_COLORS colors the symmetric matrix is written as a tsv with a row and a
+/// column for each color, with more colors only the pairs of colors sharing kmers are written,
+/// accumulating at most MAX_SPARSE_JACCARD_PAIRS pairs at once. Returns the number of input colors
+pub fn write_color_jaccard_matrix(
+    graph_input: impl AsRef<Path>,
+    output_file: impl AsRef<Path>,
+) -> usize {
+    let colormap_file = graph_input.as_ref().with_extension("colors.dat");
+    let mut colors_deserializer =
+        ColorsDeserializer::<DefaultColorsSerializer>::new(colormap_file, true);
+    let colors_count = colors_deserializer.input_colors_count();
+
+    let mut subsets_kmers: HashMap<ColorIndexType, u64> = HashMap::new();
+    SequencesReader::new().process_file_extended(
+        graph_input.as_ref(),
+        |seq| {
+            for field in seq.ident_data.split(|c| *c == b' ') {
+                if let Some((subset, kmers_count)) = parse_color_subset_kmers(field) {
+                    *subsets_kmers.entry(subset).or_insert(0) += kmers_count;
+                }
+            }
+        },
+        None,
+        true,
+        false,
+    );
+
+    let mut subsets_kmers: Vec<_> = subsets_kmers.into_iter().collect();
+    subsets_kmers.sort_unstable();
+
+    // The kmers of each color and the pairs of each color with the following colors in the subsets
+    let mut colors_kmers = vec![0u64; colors_count];
+    let mut first_colors_pairs = vec![0u64; colors_count];
+    let mut colors = vec![];
+    for (subset, kmers_count) in &subsets_kmers {
+        decode_input_colors(&mut colors_deserializer, *subset, colors_count, &mut colors);
+        for (index, color) in colors.iter().enumerate() {
+            colors_kmers[*color as usize] += kmers_count;
+            first_colors_pairs[*color as usize] += (colors.len() - index - 1) as u64;
+        }
+    }
+
+    let mut writer = BufWriter::new(File::create(output_file.as_ref()).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create jaccard matrix file {}: {}",
+            output_file.as_ref().display(),
+            err
+        )
+    }));
+
+    if colors_count <= MAX_DENSE_JACCARD_COLORS {
+        let mut shared_kmers = DenseSharedKmers::new(colors_count);
+        for (subset, kmers_count) in &subsets_kmers {
+            decode_input_colors(&mut colors_deserializer, *subset, colors_count, &mut colors);
+            for (index, first) in colors.iter().enumerate() {
+                for second in &colors[index + 1..] {
+                    shared_kmers.add(*first, *second, *kmers_count);
+                }
+            }
+        }
+
+        let color_name =
+            |color: usize| colors_deserializer.get_color_name(color as ColorIndexType, false);
+
+        write!(writer, "color").unwrap();
+        for color in 0..colors_count {
+            write!(writer, "\t{}", color_name(color)).unwrap();
+        }
+        writeln!(writer).unwrap();
+
+        for first in 0..colors_count {
+            write!(writer, "{}", color_name(first)).unwrap();
+            for second in 0..colors_count {
+                let jaccard = if first == second {
+                    if colors_kmers[first] > 0 {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                } else {
+                    jaccard_similarity(
+                        colors_kmers[first],
+                        colors_kmers[second],
+                        shared_kmers.get(
+                            first.min(second) as ColorIndexType,
+                            first.max(second) as ColorIndexType,
+                        ),
+                    )
+                };
+                write!(writer, "\t{:.6}", jaccard).unwrap();
+            }
+            writeln!(writer).unwrap();
+        }
+    } else {
+        writeln!(writer, "color\tother_color\tjaccard").unwrap();
+
+        // Each pass accumulates only the pairs with the first color in its range,
+        // decoding all the subsets again
+        for pass in split_in_pairs_passes(&first_colors_pairs, MAX_SPARSE_JACCARD_PAIRS) {
+            let mut pairs: HashMap<(ColorIndexType, ColorIndexType), u64> = HashMap::new();
+            for (subset, kmers_count) in &subsets_kmers {
+                decode_input_colors(&mut colors_deserializer, *subset, colors_count, &mut colors);
+                let pass_start = colors.partition_point(|color| (*color as usize) < pass.start);
+                for (index, first) in colors.iter().enumerate().skip(pass_start) {
+                    if *first as usize >= pass.end {
+                        break;
+                    }
+                    for second in &colors[index + 1..] {
+                        *pairs.entry((*first, *second)).or_insert(0) += kmers_count;
+                    }
+                }
+            }
+
+            let mut pairs: Vec<_> = pairs.into_iter().collect();
+            pairs.sort_unstable();
+            for ((first, second), shared) in pairs {
+                writeln!(
+                    writer,
+                    "{}\t{}\t{:.6}",
+                    colors_deserializer.get_color_name(first, false),
+                    colors_deserializer.get_color_name(second, false),
+                    jaccard_similarity(
+                        colors_kmers[first as usize],
+                        colors_kmers[second as usize],
+                        shared
+                    )
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    writer.flush().unwrap();
+    colors_count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_in_pairs_passes;
+
+    #[test]
+    fn pairs_passes_hold_at_most_the_max_pairs() {
+        // The last colors have at most a pair with each following color
+        let passes = split_in_pairs_passes(&[3, 2, 4, 1, 5, 9], 4);
+        assert_eq!(passes, [0..1, 1..2, 2..4, 4..6]);
+        assert_eq!(split_in_pairs_passes(&[1, 1, 1, 0], 10), [0..4]);
+        // A color with too many pairs has a pass by itself
+        assert_eq!(split_in_pairs_passes(&[9, 1, 0, 0], 2), [0..1, 1..4]);
+        assert!(split_in_pairs_passes(&[], 2).is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+use colors::colors_manager::ColorMapReader;
+use colors::storage::deserializer::ColorsDeserializer;
+use colors::DefaultColorsSerializer;
+use config::ColorIndexType;
+use io::open_files_limit::output_files_waves;
+use io::sequences_reader::SequencesReader;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// Colors format: C:<subset in hex>:<count of consecutive kmers with that subset>
+pub(crate) fn parse_color_subset(field: &[u8]) -> Option<ColorIndexType> {
+    let subset = field.strip_prefix(b"C:")?.split(|c| *c == b':').next()?;
+    ColorIndexType::from_str_radix(std::str::from_utf8(subset).ok()?, 16).ok()
+}
+
+/// Parses a colors field as the colors subset and the count of its consecutive kmers
+pub(crate) fn parse_color_subset_kmers(field: &[u8]) -> Option<(ColorIndexType, u64)> {
+    let subset = parse_color_subset(field)?;
+    let kmers_count = field.split(|c| *c == b':').nth(2)?;
+    Some((subset, std::str::from_utf8(kmers_count).ok()?.parse().ok()?))
+}
+
+// Colors held at most by a cache of decoded subsets, that is cleared when it gets full
+const MAX_DECODED_SUBSETS_COLORS: usize = 1 << 22;
+
+/// The colors subsets of a graph decoded from its colormap, cached as the same subsets are repeated
+/// by many unitigs. The cache is cleared when it holds too many colors, so that its memory is bounded
+/// on the graphs with many distinct subsets
+pub(crate) struct DecodedSubsets {
+    subsets: HashMap<ColorIndexType, Vec<ColorIndexType>>,
+    cached_colors: usize,
+}
+
+impl DecodedSubsets {
+    pub(crate) fn new() -> Self {
+        Self {
+            subsets: HashMap::new(),
+            cached_colors: 0,
+        }
+    }
+
+    /// The colors of a subset, decoded from the colormap if not cached
+    pub(crate) fn get(
+        &mut self,
+        colors_deserializer: &mut ColorsDeserializer<DefaultColorsSerializer>,
+        subset: ColorIndexType,
+    ) -> &[ColorIndexType] {
+        if !self.subsets.contains_key(&subset) {
+            if self.cached_colors >= MAX_DECODED_SUBSETS_COLORS {
+                self.subsets.clear();
+                self.cached_colors = 0;
+            }
+            let mut colors = vec![];
+            colors_deserializer.get_color_mappings(subset, &mut colors);
+            self.cached_colors += colors.len().max(1);
+            self.subsets.insert(subset, colors);
+        }
+        &self.subsets[&subset]
+    }
+}
+
+/// The path of the fasta file with the unitigs of a color
+pub fn get_color_unitigs_file(output_prefix: impl AsRef<Path>, color: ColorIndexType) -> PathBuf {
+    PathBuf::from(format!(
+        "{}.color{}.fa",
+        output_prefix.as_ref().display(),
+        color
+    ))
+}
+
+/// Writes the unitigs of a colored graph to a separate fasta file for each color, named as
+/// given by get_color_unitigs_file. A unitig is written to the file of every color in any of its
+/// colors subsets, so the unitigs shared by multiple colors appear in each of their files.
+/// The headers are kept unchanged, and each colors subset is decoded from the colormap only the
+/// first time it is seen. The graph is streamed once for each wave of colors whose files fit
+/// the open files limit, if any.
+/// Returns the written files, indexed by color
+pub fn write_unitigs_by_color(
+    graph_input: impl AsRef<Path>,
+    output_prefix: impl AsRef<Path>,
+    max_open_files: Option<usize>,
+) -> Vec<PathBuf> {
+    let colormap_file = graph_input.as_ref().with_extension("colors.dat");
+    let mut colors_deserializer =
+        ColorsDeserializer::<DefaultColorsSerializer>::new(colormap_file, true);
+
+    let output_files: Vec<_> = (0..colors_deserializer.colors_count())
+        .map(|color| get_color_unitigs_file(&output_prefix, color as ColorIndexType))
+        .collect();
+
+    let mut decoded_subsets = DecodedSubsets::new();
+    let mut unitig_colors = vec![];
+
+    for wave in output_files_waves(output_files.len(), max_open_files) {
+        let mut writers: Vec<_> = output_files[wave.clone()]
+            .iter()
+            .map(|file| {
+                BufWriter::new(File::create(file).unwrap_or_else(|err| {
+                    panic!(
+                        "Cannot create color unitigs file {}: {}",
+                        file.display(),
+                        err
+                    )
+                }))
+            })
+            .collect();
+
+        SequencesReader::new().process_file_extended(
+            graph_input.as_ref(),
+            |seq| {
+                unitig_colors.clear();
+                for subset in seq
+                    .ident_data
+                    .split(|c| *c == b' ')
+                    .filter_map(parse_color_subset)
+                {
+                    unitig_colors
+                        .extend_from_slice(decoded_subsets.get(&mut colors_deserializer, subset));
+                }
+                unitig_colors.sort_unstable();
+                unitig_colors.dedup();
+
+                for color in &unitig_colors {
+                    if !wave.contains(&(*color as usize)) {
+                        continue;
+                    }
+                    let writer = &mut writers[*color as usize - wave.start];
+                    writer.write_all(seq.ident_data).unwrap();
+                    writer.write_all(b"\n").unwrap();
+                    writer.write_all(seq.seq).unwrap();
+                    writer.write_all(b"\n").unwrap();
+                }
+            },
+            None,
+            true,
+            false,
+        );
+
+        for mut writer in writers {
+            writer.flush().unwrap();
+        }
+    }
+
+    output_files
+}
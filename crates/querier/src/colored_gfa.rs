@@ -0,0 +1,180 @@
+use crate::color_unitigs::{parse_color_subset_kmers, DecodedSubsets};
+use crate::unitigs_index::parse_unitig_id;
+use colors::colors_manager::ColorMapReader;
+use colors::storage::deserializer::ColorsDeserializer;
+use colors::subsets_coverage::{get_subsets_coverage_file, SubsetsCoverage};
+use colors::DefaultColorsSerializer;
+use io::sequences_reader::SequencesReader;
+use io::unitig_links::iter_links;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+pub const DEFAULT_GFA_COLOR_TAG: &str = "CL";
+pub const DEFAULT_GFA_MAX_LISTED_COLORS: usize = 16;
+// The total occurrences of the kmers of a segment in the inputs of each of its colors
+pub const GFA_COLOR_COVERAGE_TAG: &str = "CV";
+
+/// Formats the value of the colors tag of a segment, listing up to max_listed colors
+/// and the count of the remaining ones, as in `a,b,c,+5 more`
+pub fn format_colors_tag_value<'a>(
+    colors: impl ExactSizeIterator<Item = &'a str>,
+    max_listed: usize,
+) -> String {
+    let colors_count = colors.len();
+    let mut value = colors.take(max_listed).collect::<Vec<_>>().join(",");
+    if colors_count > max_listed {
+        if max_listed > 0 {
+            value.push(',');
+        }
+        value.push_str(&format!("+{} more", colors_count - max_listed));
+    }
+    value
+}
+
+/// Converts a colored graph to GFA 1.0, for visualization in Bandage and similar tools.
+/// Each `S` line carries a `<color_tag>:Z:` tag with the colors of the unitig, as their names
+/// or as their indices, truncated to max_listed_colors with the count of the remaining ones.
+/// The colors subsets are decoded from the colormap next to the graph, only the first time they are seen.
+/// The links in the graph headers, if any, are written as `L` lines with an overlap of k - 1 bases.
+/// With color_coverage, each `S` line also has a `CV:B:i` tag with the coverage of each color of the unitig,
+/// the total occurrences of its kmers in the inputs of the color, read from the subsets coverage written
+/// next to the colormap by the builds with the color coverage output. The tag is sparse encoded as pairs
+/// of color index and coverage, for the colors with a non-zero coverage only, saturated to i32::MAX.
+/// Returns the number of written segments
+pub fn write_colored_gfa(
+    graph_input: impl AsRef<Path>,
+    output_file: impl AsRef<Path>,
+    kmer_length: usize,
+    color_tag: &str,
+    max_listed_colors: usize,
+    color_indices: bool,
+    color_coverage: bool,
+) -> usize {
+    let colormap_file = graph_input.as_ref().with_extension("colors.dat");
+    let subsets_coverage = color_coverage.then(|| {
+        SubsetsCoverage::open(get_subsets_coverage_file(&colormap_file)).unwrap_or_else(|err| {
+            panic!(
+                "{}, build the graph with the color coverage output to write the color coverage",
+                err
+            )
+        })
+    });
+    let mut colors_deserializer =
+        ColorsDeserializer::<DefaultColorsSerializer>::new(colormap_file, true);
+
+    let mut writer = BufWriter::new(File::create(output_file.as_ref()).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create gfa file {}: {}",
+            output_file.as_ref().display(),
+            err
+        )
+    }));
+    writeln!(writer, "H\tVN:Z:1.0").unwrap();
+
+    let mut decoded_subsets = DecodedSubsets::new();
+    let mut unitig_colors = vec![];
+    let mut unitig_color_coverage = BTreeMap::new();
+    let mut segments_count = 0;
+
+    SequencesReader::new().process_file_extended(
+        graph_input.as_ref(),
+        |seq| {
+            let id = parse_unitig_id(seq.ident_data).unwrap_or(segments_count as u64);
+            segments_count += 1;
+
+            unitig_colors.clear();
+            unitig_color_coverage.clear();
+            for field in seq.ident_data.split(|c| *c == b' ') {
+                if let Some((subset, kmers_count)) = parse_color_subset_kmers(field) {
+                    let colors = decoded_subsets.get(&mut colors_deserializer, subset);
+                    unitig_colors.extend_from_slice(colors);
+                    if let Some(subsets_coverage) = &subsets_coverage {
+                        // Each kmer of the run has the coverage of the subset
+                        let coverage = subsets_coverage.get(subset).unwrap_or(&[]);
+                        for (color, occurrences) in colors.iter().zip(coverage) {
+                            *unitig_color_coverage.entry(*color).or_insert(0u64) +=
+                                kmers_count * *occurrences as u64;
+                        }
+                    }
+                }
+            }
+            unitig_colors.sort_unstable();
+            unitig_colors.dedup();
+
+            let colors_value = if color_indices {
+                let indices: Vec<_> = unitig_colors.iter().map(|c| c.to_string()).collect();
+                format_colors_tag_value(indices.iter().map(|c| c.as_str()), max_listed_colors)
+            } else {
+                format_colors_tag_value(
+                    unitig_colors
+                        .iter()
+                        .map(|c| colors_deserializer.get_color_name(*c, false)),
+                    max_listed_colors,
+                )
+            };
+
+            write!(
+                writer,
+                "S\t{}\t{}\tLN:i:{}\t{}:Z:{}",
+                id,
+                std::str::from_utf8(seq.seq).unwrap(),
+                seq.seq.len(),
+                color_tag,
+                colors_value
+            )
+            .unwrap();
+            unitig_color_coverage.retain(|_, coverage| *coverage > 0);
+            if !unitig_color_coverage.is_empty() {
+                write!(writer, "\t{}:B:i", GFA_COLOR_COVERAGE_TAG).unwrap();
+                for (color, coverage) in unitig_color_coverage.iter() {
+                    write!(writer, ",{},{}", color, (*coverage).min(i32::MAX as u64)).unwrap();
+                }
+            }
+            writeln!(writer).unwrap();
+        },
+        None,
+        true,
+        false,
+    );
+
+    // Each link is listed by both its unitigs, keep it only once
+    for link in iter_links(graph_input.as_ref()).filter(|link| link.from_id <= link.to_id) {
+        writeln!(
+            writer,
+            "L\t{}\t{}\t{}\t{}\t{}M",
+            link.from_id,
+            link.from_strand.sign(),
+            link.to_id,
+            link.to_strand.sign(),
+            kmer_length - 1
+        )
+        .unwrap();
+    }
+
+    writer.flush().unwrap();
+    segments_count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_colors_tag_value;
+
+    #[test]
+    fn large_color_sets_are_truncated_with_a_count() {
+        let colors = ["a", "b", "c", "d", "e"];
+        assert_eq!(
+            format_colors_tag_value(colors.iter().copied(), 8),
+            "a,b,c,d,e"
+        );
+        assert_eq!(
+            format_colors_tag_value(colors.iter().copied(), 2),
+            "a,b,+3 more"
+        );
+        assert_eq!(
+            format_colors_tag_value(colors.iter().copied(), 0),
+            "+5 more"
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use config::ColorIndexType;
+use std::io::Write;
+
+/// Maximum number of colors written as columns of the count matrix, with more colors
+/// the matrix is written in the sparse format
+pub const MAX_DENSE_MATRIX_COLORS: usize = 4096;
+
+/// If the count matrix of a graph with the given colors is written in the sparse format,
+/// with a (query, color, count) line for each non-zero cell
+pub fn is_sparse_count_matrix(colors_count: usize) -> bool {
+    colors_count > MAX_DENSE_MATRIX_COLORS
+}
+
+/// Writes the header of the count matrix, with a column for each color if not sparse
+pub fn write_count_matrix_header<'a>(
+    writer: &mut impl Write,
+    color_names: impl Iterator<Item = &'a str>,
+    sparse: bool,
+) -> std::io::Result<()> {
+    if sparse {
+        return writeln!(writer, "query\tcolor\tcount");
+    }
+
+    write!(writer, "query")?;
+    for color_name in color_names {
+        write!(writer, "\t{}", color_name)?;
+    }
+    writeln!(writer)
+}
+
+/// Writes the row of a query, with the matched kmers count of each color and 0 for the missing colors.
+/// The matches must be sorted by color. The row buffer is reused across the rows of the matrix
+pub fn write_count_matrix_row<'a>(
+    writer: &mut impl Write,
+    query_name: &str,
+    matches: &[(ColorIndexType, u64)],
+    colors_count: usize,
+    color_name: impl Fn(ColorIndexType) -> &'a str,
+    row: &mut Vec<u64>,
+) -> std::io::Result<()> {
+    if is_sparse_count_matrix(colors_count) {
+        for (color, count) in matches {
+            writeln!(writer, "{}\t{}\t{}", query_name, color_name(*color), count)?;
+        }
+        return Ok(());
+    }
+
+    row.clear();
+    row.resize(colors_count, 0);
+    for (color, count) in matches {
+        row[*color as usize] = *count;
+    }
+
+    write!(writer, "{}", query_name)?;
+    for count in row.iter() {
+        write!(writer, "\t{}", count)?;
+    }
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_count_matrix_header, write_count_matrix_row, MAX_DENSE_MATRIX_COLORS};
+
+    #[test]
+    fn count_matrix_has_a_cell_for_each_query_and_color() {
+        let color_names = ["sal1", "sal2", "sal3", "sal4"];
+        let queries: [(&str, &[(u32, u64)]); 3] = [
+            ("first", &[(0, 12), (2, 5)]),
+            ("unmatched", &[]),
+            ("last", &[(1, 1), (2, 7), (3, 30)]),
+        ];
+
+        let mut output = vec![];
+        let mut row = vec![];
+        write_count_matrix_header(&mut output, color_names.iter().copied(), false).unwrap();
+        for (query_name, matches) in &queries {
+            write_count_matrix_row(
+                &mut output,
+                query_name,
+                matches,
+                color_names.len(),
+                |color| color_names[color as usize],
+                &mut row,
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "query\tsal1\tsal2\tsal3\tsal4\n\
+             first\t12\t0\t5\t0\n\
+             unmatched\t0\t0\t0\t0\n\
+             last\t0\t1\t7\t30\n"
+        );
+
+        // Too many colors for the wide format
+        let mut output = vec![];
+        write_count_matrix_row(
+            &mut output,
+            "first",
+            queries[0].1,
+            MAX_DENSE_MATRIX_COLORS + 1,
+            |color| color_names[color as usize],
+            &mut row,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "first\tsal1\t12\nfirst\tsal3\t5\n"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use crate::unitigs_index::UnitigsIndex;
+use io::concurrent::structured_sequences::kmer_counts_sidecar::KmerCountsFile;
+use io::sequences_reader::SequencesReader;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Obtains the standard coverage track file path from a graph file path
+pub fn get_coverage_bedgraph_file(graph_file: impl AsRef<Path>) -> PathBuf {
+    graph_file.as_ref().with_extension("coverage.bedgraph")
+}
+
+/// The counts of the kmers of the last looked up unitig, as consecutive kmers of a reference
+/// usually fall in the same unitig
+struct UnitigCountsCache {
+    unitig_id: Option<u64>,
+    counts: Vec<u32>,
+}
+
+/// The graph coverage of each position of a reference sequence, as the count of the kmer starting
+/// at that position tracked while building the graph, if the kmer is in the graph. The positions whose
+/// kmer is absent from the graph, contains ambiguous bases or is past the end of the sequence have a coverage of 0
+fn reference_coverage(
+    index: &mut UnitigsIndex,
+    kmer_counts: &KmerCountsFile,
+    cache: &mut UnitigCountsCache,
+    sequence: &[u8],
+) -> Vec<u64> {
+    let k = index.kmer_length();
+    let mut coverage = vec![0; sequence.len()];
+    if sequence.len() < k {
+        return coverage;
+    }
+
+    for (position, kmer) in sequence.windows(k).enumerate() {
+        if kmer.contains(&b'N') {
+            continue;
+        }
+        let Some(unitig_position) = index.lookup(kmer) else {
+            continue;
+        };
+
+        if cache.unitig_id != Some(unitig_position.unitig_id) {
+            cache.unitig_id = Some(unitig_position.unitig_id);
+            kmer_counts.read_counts(unitig_position.unitig_id, &mut cache.counts);
+        }
+        coverage[position] = cache
+            .counts
+            .get(unitig_position.offset as usize)
+            .copied()
+            .unwrap_or(0) as u64;
+    }
+    coverage
+}
+
+/// Writes the kmers coverage of the graph projected on a reference as a bedGraph track, for the
+/// visualization in a genome browser. Each position of each reference sequence gets the count of the
+/// kmer starting there, as written next to the graph while building it, if the kmer is in the kmers
+/// index of the graph, and 0 otherwise. The consecutive positions with the same coverage are merged in
+/// a single interval. Returns the number of written intervals
+pub fn write_coverage_bedgraph(
+    index_file: impl AsRef<Path>,
+    kmer_counts_file: impl AsRef<Path>,
+    reference: impl AsRef<Path>,
+    output_file: impl AsRef<Path>,
+) -> Result<usize, String> {
+    let mut index = UnitigsIndex::open(index_file)?;
+    let kmer_counts = KmerCountsFile::open(kmer_counts_file)?;
+    if index.kmer_length() != kmer_counts.kmer_length() {
+        return Err(format!(
+            "The kmers index has k={} but the kmer counts of the graph have k={}",
+            index.kmer_length(),
+            kmer_counts.kmer_length()
+        ));
+    }
+
+    let mut writer = BufWriter::new(File::create(output_file.as_ref()).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create coverage track file {}: {}",
+            output_file.as_ref().display(),
+            err
+        )
+    }));
+    writeln!(writer, "track type=bedGraph name=\"kmers coverage\"").unwrap();
+
+    let mut cache = UnitigCountsCache {
+        unitig_id: None,
+        counts: vec![],
+    };
+    let mut intervals_count = 0;
+    SequencesReader::new().process_file_extended(
+        reference.as_ref(),
+        |seq| {
+            let chromosome = String::from_utf8_lossy(
+                seq.ident_data
+                    .strip_prefix(b">")
+                    .unwrap_or(seq.ident_data)
+                    .split(|c| c.is_ascii_whitespace())
+                    .next()
+                    .unwrap_or(&[]),
+            );
+
+            let coverage = reference_coverage(&mut index, &kmer_counts, &mut cache, seq.seq);
+            let mut start = 0;
+            for end in 1..=coverage.len() {
+                if end == coverage.len() || coverage[end] != coverage[start] {
+                    writeln!(
+                        writer,
+                        "{}\t{}\t{}\t{}",
+                        chromosome, start, end, coverage[start]
+                    )
+                    .unwrap();
+                    intervals_count += 1;
+                    start = end;
+                }
+            }
+        },
+        None,
+        true,
+        false,
+    );
+
+    writer.flush().unwrap();
+    Ok(intervals_count)
+}
@@ -0,0 +1,226 @@
+use crate::unitigs_index::UnitigsIndex;
+use io::sequences_reader::SequencesReader;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Result of a query that can contain IUPAC degenerate bases
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DegenerateQueryResult {
+    /// The matched unitigs, each with the number of query kmers having at least one variant in it
+    Matches(Vec<(u64, usize)>),
+    /// A kmer of the query expands to more variants than the allowed maximum
+    TooDegenerate { kmer_index: usize, variants: usize },
+}
+
+// Concrete bases of each IUPAC code, an empty set for unknown characters
+fn iupac_bases(base: u8) -> &'static [u8] {
+    match base.to_ascii_uppercase() {
+        b'A' => b"A",
+        b'C' => b"C",
+        b'G' => b"G",
+        b'T' | b'U' => b"T",
+        b'R' => b"AG",
+        b'Y' => b"CT",
+        b'S' => b"CG",
+        b'W' => b"AT",
+        b'K' => b"GT",
+        b'M' => b"AC",
+        b'B' => b"CGT",
+        b'D' => b"AGT",
+        b'H' => b"ACT",
+        b'V' => b"ACG",
+        b'N' => b"ACGT",
+        _ => b"",
+    }
+}
+
+fn variants_count(kmer: &[u8]) -> usize {
+    kmer.iter().fold(1usize, |count, b| {
+        count.saturating_mul(iupac_bases(*b).len())
+    })
+}
+
+/// Expands a kmer with degenerate bases into all its concrete variants
+pub fn expand_degenerate_kmer(kmer: &[u8]) -> Vec<Vec<u8>> {
+    let mut variants = vec![vec![]];
+
+    for base in kmer {
+        let bases = iupac_bases(*base);
+        variants = variants
+            .into_iter()
+            .flat_map(|variant| {
+                bases.iter().map(move |b| {
+                    let mut variant = variant.clone();
+                    variant.push(*b);
+                    variant
+                })
+            })
+            .collect();
+    }
+
+    variants
+}
+
+/// Queries a sequence expanding its degenerate kmers, aggregating the matches of all the variants.
+/// If any kmer has more than max_expansions variants the query is not executed
+pub fn query_degenerate_sequence(
+    index: &mut UnitigsIndex,
+    query: &[u8],
+    max_expansions: usize,
+) -> DegenerateQueryResult {
+    let k = index.kmer_length();
+
+    if query.len() < k {
+        return DegenerateQueryResult::Matches(vec![]);
+    }
+
+    let kmers_count = query.len() - k + 1;
+
+    for kmer_index in 0..kmers_count {
+        let variants = variants_count(&query[kmer_index..kmer_index + k]);
+        if variants > max_expansions {
+            return DegenerateQueryResult::TooDegenerate {
+                kmer_index,
+                variants,
+            };
+        }
+    }
+
+    let mut matches = BTreeMap::new();
+    let mut kmer_unitigs = BTreeSet::new();
+
+    for kmer_index in 0..kmers_count {
+        kmer_unitigs.clear();
+        for variant in expand_degenerate_kmer(&query[kmer_index..kmer_index + k]) {
+            if let Some(position) = index.lookup(&variant) {
+                kmer_unitigs.insert(position.unitig_id);
+            }
+        }
+
+        for unitig_id in kmer_unitigs.iter() {
+            *matches.entry(*unitig_id).or_insert(0) += 1;
+        }
+    }
+
+    DegenerateQueryResult::Matches(matches.into_iter().collect())
+}
+
+/// Reports, for each query, the unitigs matched by any of the variants of its degenerate kmers.
+/// Queries with a kmer expanding to more than max_expansions variants are reported as
+/// too degenerate. The output is written in jsonl format, with one line per query.
+pub fn query_degenerate(
+    index_file: impl AsRef<Path>,
+    query_input: impl AsRef<Path>,
+    output_file_prefix: impl AsRef<Path>,
+    max_expansions: usize,
+) -> PathBuf {
+    let mut index = UnitigsIndex::open(index_file).unwrap_or_else(|err| panic!("{}", err));
+
+    let output_file = output_file_prefix
+        .as_ref()
+        .with_extension("degenerate.jsonl");
+    let mut writer = BufWriter::new(File::create(&output_file).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create output file {}: {}",
+            output_file.display(),
+            err
+        )
+    }));
+
+    let mut query_index = 0;
+    SequencesReader::new().process_file_extended(
+        query_input,
+        |seq| {
+            match query_degenerate_sequence(&mut index, seq.seq, max_expansions) {
+                DegenerateQueryResult::Matches(matches) => {
+                    write!(writer, "{{\"query_index\":{}, \"matches\":[", query_index).unwrap();
+                    for (i, (unitig_id, kmers)) in matches.iter().enumerate() {
+                        if i > 0 {
+                            write!(writer, ",").unwrap();
+                        }
+                        write!(
+                            writer,
+                            "{{\"unitig\":{}, \"kmers\":{}}}",
+                            unitig_id, kmers
+                        )
+                        .unwrap();
+                    }
+                    writeln!(writer, "]}}").unwrap();
+                }
+                DegenerateQueryResult::TooDegenerate {
+                    kmer_index,
+                    variants,
+                } => {
+                    writeln!(
+                        writer,
+                        "{{\"query_index\":{}, \"too_degenerate\":true, \"kmer_index\":{}, \"variants\":{}}}",
+                        query_index, kmer_index, variants
+                    )
+                    .unwrap();
+                }
+            }
+            query_index += 1;
+        },
+        None,
+        false,
+        false,
+    );
+
+    writer.flush().unwrap();
+    output_file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{query_degenerate_sequence, DegenerateQueryResult};
+    use crate::unitigs_index::{build_unitigs_index, UnitigsIndex};
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn degenerate_primer_matches_all_variants() {
+        const K: usize = 15;
+        // The two unitigs contain the primer with an A and a G at its degenerate position
+        let unitigs: [&[u8]; 2] = [
+            b"GATCCACGTTGCAAGGCTTACCGATTAGC",
+            b"TTGACACGTTGCGAGGCTTATAGGCATCG",
+        ];
+
+        let temp_dir = TestDir::new("degenerate_primer_matches_all_variants");
+        let graph_file = temp_dir.path("degenerate-query.fa");
+        let index_file = temp_dir.path("degenerate-query.kmers.idx");
+
+        {
+            let mut graph = std::fs::File::create(&graph_file).unwrap();
+            for (id, unitig) in unitigs.iter().enumerate() {
+                writeln!(graph, ">{} LN:i:{}", id, unitig.len()).unwrap();
+                graph.write_all(unitig).unwrap();
+                writeln!(graph).unwrap();
+            }
+        }
+
+        build_unitigs_index(&graph_file, &index_file, K);
+        let mut index = UnitigsIndex::open(&index_file).unwrap();
+
+        assert_eq!(
+            query_degenerate_sequence(&mut index, b"ACGTTGCRAGGCTTA", 16),
+            DegenerateQueryResult::Matches(vec![(0, 1), (1, 1)])
+        );
+
+        // Without expansion only the exact variant is matched
+        assert_eq!(
+            query_degenerate_sequence(&mut index, b"ACGTTGCAAGGCTTA", 16),
+            DegenerateQueryResult::Matches(vec![(0, 1)])
+        );
+
+        assert_eq!(
+            query_degenerate_sequence(&mut index, b"ACGNNGCRAGGCTTA", 16),
+            DegenerateQueryResult::TooDegenerate {
+                kmer_index: 0,
+                variants: 32
+            }
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::reference_orientation::create_graph_writer;
+use io::concurrent::structured_sequences::StructuredSequenceBackend;
+use io::sequences_reader::SequencesReader;
+use io::unitig_links::LinkStrand;
+use std::collections::HashMap;
+use std::path::Path;
+use utils::phase_log::log_warning;
+
+fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|b| match *b {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            b'T' => b'A',
+            other => other,
+        })
+        .collect()
+}
+
+// Calls the callback with each run of valid bases of the sequence at least k bases long, in uppercase.
+// The runs are separated by the non-ACGT bases, as they cannot be part of a unitig
+fn for_each_unitig_sequence(
+    seq: &[u8],
+    k: usize,
+    buffer: &mut Vec<u8>,
+    mut callback: impl FnMut(&[u8]),
+) {
+    for run in seq.split(|b| !matches!(b.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T')) {
+        if run.len() >= k {
+            buffer.clear();
+            buffer.extend(run.iter().map(|b| b.to_ascii_uppercase()));
+            callback(buffer);
+        }
+    }
+}
+
+/// Converts the records of an existing fasta assembly into a unitigs graph that can be queried as the
+/// ones built by ggcat, writing it to output_file (compressed as its extension).
+/// Each record is written as a unitig with a sequential id, the records are split at the non-ACGT bases
+/// and the parts shorter than k are skipped. The input is read twice, so that only the k - 1 bases
+/// at the ends of each unitig are kept in memory. With rebuild_links the unitigs headers also list the links,
+/// in the BCALM2 format, between the unitigs whose ends overlap by k - 1 bases.
+/// Returns the number of written unitigs
+pub fn import_fasta_as_unitigs(
+    input_file: impl AsRef<Path>,
+    output_file: impl AsRef<Path>,
+    k: usize,
+    rebuild_links: bool,
+) -> u64 {
+    assert!(k > 1, "The kmers length must be at least 2");
+    let overlap = k - 1;
+
+    // The first k - 1 bases of each unitig when read on each strand
+    let mut unitig_starts: HashMap<Vec<u8>, Vec<(u64, LinkStrand)>> = HashMap::new();
+    let mut buffer = vec![];
+
+    if rebuild_links {
+        let mut unitig_id = 0;
+        SequencesReader::new().process_file_extended(
+            input_file.as_ref(),
+            |seq| {
+                for_each_unitig_sequence(seq.seq, k, &mut buffer, |unitig| {
+                    unitig_starts
+                        .entry(unitig[..overlap].to_vec())
+                        .or_default()
+                        .push((unitig_id, LinkStrand::Forward));
+                    unitig_starts
+                        .entry(reverse_complement(&unitig[unitig.len() - overlap..]))
+                        .or_default()
+                        .push((unitig_id, LinkStrand::Reverse));
+                    unitig_id += 1;
+                });
+            },
+            None,
+            false,
+            false,
+        );
+    }
+
+    let mut writer = create_graph_writer(output_file.as_ref());
+    let mut unitig_id = 0;
+    let mut skipped_records = 0;
+    let mut output_buffer = vec![];
+
+    SequencesReader::new().process_file_extended(
+        input_file.as_ref(),
+        |seq| {
+            let first_id = unitig_id;
+            for_each_unitig_sequence(seq.seq, k, &mut buffer, |unitig| {
+                output_buffer
+                    .extend_from_slice(format!(">{} LN:i:{}", unitig_id, unitig.len()).as_bytes());
+
+                if rebuild_links {
+                    // The end of the unitig read on each strand, linked to the unitigs starting with it
+                    let ends = [
+                        (
+                            LinkStrand::Forward,
+                            unitig[unitig.len() - overlap..].to_vec(),
+                        ),
+                        (LinkStrand::Reverse, reverse_complement(&unitig[..overlap])),
+                    ];
+                    for (from_strand, end) in ends {
+                        for (to_id, to_strand) in unitig_starts.get(&end).into_iter().flatten() {
+                            output_buffer.extend_from_slice(
+                                format!(" L:{}:{}:{}", from_strand.sign(), to_id, to_strand.sign())
+                                    .as_bytes(),
+                            );
+                        }
+                    }
+                }
+
+                output_buffer.push(b'\n');
+                output_buffer.extend_from_slice(unitig);
+                output_buffer.push(b'\n');
+                writer.flush_temp_buffer(&mut output_buffer);
+                unitig_id += 1;
+            });
+            if unitig_id == first_id {
+                skipped_records += 1;
+            }
+        },
+        None,
+        false,
+        false,
+    );
+
+    writer.finalize();
+
+    if skipped_records > 0 {
+        log_warning(format!(
+            "WARNING: {} fasta records without a run of at least {} valid bases were not imported",
+            skipped_records, k
+        ));
+    }
+
+    unitig_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import_fasta_as_unitigs, reverse_complement};
+    use std::io::{BufRead, BufReader};
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn imported_records_are_linked_by_their_overlaps() {
+        const K: usize = 5;
+        let temp_dir = TestDir::new("imported_records_are_linked_by_their_overlaps");
+        let input_file = temp_dir.path("fasta-import.fa");
+        let output_file = temp_dir.path("fasta-import.unitigs.fa");
+
+        // The second record starts with the end of the first one, the third one is reversed
+        // and the fourth one is split at the N base, with its short second part skipped
+        let first = b"AAGTTGAC".to_vec();
+        let second = b"TGACAGGTC".to_vec();
+        let third = reverse_complement(b"GGTCCATTA");
+        std::fs::write(
+            &input_file,
+            format!(
+                ">first\n{}\n>second\n{}\n>third\n{}\n>fourth\nacccaNTTA\n",
+                String::from_utf8_lossy(&first),
+                String::from_utf8_lossy(&second),
+                String::from_utf8_lossy(&third)
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(
+            import_fasta_as_unitigs(&input_file, &output_file, K, true),
+            4
+        );
+
+        let lines: Vec<_> = BufReader::new(std::fs::File::open(&output_file).unwrap())
+            .lines()
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ">0 LN:i:8 L:+:1:+".to_string(),
+                "AAGTTGAC".to_string(),
+                ">1 LN:i:9 L:+:2:- L:-:0:-".to_string(),
+                "TGACAGGTC".to_string(),
+                ">2 LN:i:9 L:+:1:-".to_string(),
+                String::from_utf8(third).unwrap(),
+                ">3 LN:i:5".to_string(),
+                "ACCCA".to_string(),
+            ]
+        );
+    }
+}
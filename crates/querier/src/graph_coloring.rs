@@ -0,0 +1,116 @@
+use crate::positions_query::query_sequence_intervals;
+use crate::unitigs_index::UnitigsIndex;
+use config::ColorIndexType;
+use io::sequences_reader::SequencesReader;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Colors an already built (uncolored) graph by matching the kmers of each sample against the
+/// kmers index of the graph, without assembling it again.
+/// Each sample file is a color, numbered by its position in sample_files, and it is assigned to all
+/// the unitigs that share at least one kmer with the sample.
+/// Returns, for each unitig with at least one color, the sorted list of its colors
+pub fn color_existing_graph(
+    index_file: impl AsRef<Path>,
+    sample_files: &[PathBuf],
+) -> BTreeMap<u64, Vec<ColorIndexType>> {
+    let mut index = UnitigsIndex::open(index_file).unwrap_or_else(|err| panic!("{}", err));
+    let mut colormap: BTreeMap<u64, Vec<ColorIndexType>> = BTreeMap::new();
+
+    for (color, sample_file) in sample_files.iter().enumerate() {
+        let color = color as ColorIndexType;
+
+        SequencesReader::new().process_file_extended(
+            sample_file,
+            |seq| {
+                for interval in query_sequence_intervals(&mut index, seq.seq) {
+                    let colors = colormap.entry(interval.unitig_id).or_default();
+                    // Colors are processed in order, so only the last one can be a duplicate
+                    if colors.last() != Some(&color) {
+                        colors.push(color);
+                    }
+                }
+            },
+            None,
+            false,
+            false,
+        );
+    }
+
+    colormap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::color_existing_graph;
+    use crate::unitigs_index::build_unitigs_index;
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn color_graph_with_two_samples() {
+        const K: usize = 15;
+        let unitigs: [&[u8]; 3] = [
+            b"ACGTTGCAAGGCTTACCGATTAGCCATGACCTAGG",
+            b"TTAGGCATCGGATCCAGTAGCTTGACAACGTGCATGCCA",
+            b"GATCGTACCTGAATGCGTTCAGGACTTCGAAGTCCTA",
+        ];
+
+        let temp_dir = TestDir::new("color_graph_with_two_samples");
+        let graph_file = temp_dir.path("graph-coloring.fa");
+        let index_file = temp_dir.path("graph-coloring.kmers.idx");
+        let samples = [
+            temp_dir.path("graph-coloring-sample0.fa"),
+            temp_dir.path("graph-coloring-sample1.fa"),
+        ];
+
+        {
+            let mut graph = std::fs::File::create(&graph_file).unwrap();
+            for (id, unitig) in unitigs.iter().enumerate() {
+                writeln!(graph, ">{} LN:i:{}", id, unitig.len()).unwrap();
+                graph.write_all(unitig).unwrap();
+                writeln!(graph).unwrap();
+            }
+        }
+
+        let write_sample = |path, reads: &[&[u8]]| {
+            let mut sample = std::fs::File::create(path).unwrap();
+            for (i, read) in reads.iter().enumerate() {
+                writeln!(sample, ">read{}", i).unwrap();
+                sample.write_all(read).unwrap();
+                writeln!(sample).unwrap();
+            }
+        };
+
+        // The first sample covers the first two unitigs, the second sample the last two,
+        // with the third unitig matched on the reverse strand
+        let third_rc: Vec<u8> = unitigs[2][5..30]
+            .iter()
+            .rev()
+            .map(|b| match *b {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                b'T' => b'A',
+                _ => unreachable!(),
+            })
+            .collect();
+        write_sample(&samples[0], &[&unitigs[0][3..25], &unitigs[1][10..30]]);
+        write_sample(
+            &samples[1],
+            &[
+                &unitigs[1][..20],
+                &third_rc[..],
+                &b"CCCCCCCCCCCCCCCCCCCC"[..],
+            ],
+        );
+
+        build_unitigs_index(&graph_file, &index_file, K);
+        let colormap = color_existing_graph(&index_file, &samples);
+
+        assert_eq!(
+            colormap.into_iter().collect::<Vec<_>>(),
+            vec![(0, vec![0]), (1, vec![0, 1]), (2, vec![1])]
+        );
+    }
+}
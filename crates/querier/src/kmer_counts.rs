@@ -0,0 +1,283 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use hashbrown::HashMap;
+use io::sequences_reader::SequencesReader;
+use rayon::slice::ParallelSliceMut;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const TABLE_MAGIC: [u8; 16] = *b"GGCAT_KMER_COUNT";
+const TABLE_VERSION: u64 = 1;
+
+// magic + version + k + entries count
+const HEADER_SIZE: u64 = 16 + 8 * 3;
+// count, after the packed kmer
+const COUNT_SIZE: u64 = 8;
+
+/// Obtains the standard kmer counts table file path from an input file path
+pub fn get_kmer_counts_table_file(input_file: impl AsRef<Path>) -> PathBuf {
+    input_file.as_ref().with_extension("counts.tbl")
+}
+
+fn base_code(base: u8) -> Option<u8> {
+    match base.to_ascii_uppercase() {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    }
+}
+
+/// Returns the canonical form of a kmer, the lexicographically smallest between it and its
+/// reverse complement, or None if the kmer contains non ACGT bases
+pub fn canonical_kmer(kmer: &[u8]) -> Option<Vec<u8>> {
+    let forward = kmer.to_ascii_uppercase();
+    if forward.iter().any(|b| base_code(*b).is_none()) {
+        return None;
+    }
+
+    let reverse: Vec<u8> = forward
+        .iter()
+        .rev()
+        .map(|b| match *b {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            _ => b'A',
+        })
+        .collect();
+
+    Some(forward.min(reverse))
+}
+
+// Packs 4 bases per byte, starting from the high bits, so that the packed kmers
+// are sorted in the same order as the plain ones
+fn pack_kmer(kmer: &[u8]) -> Vec<u8> {
+    let mut packed = vec![0; (kmer.len() + 3) / 4];
+    for (i, base) in kmer.iter().enumerate() {
+        packed[i / 4] |= base_code(*base).unwrap() << (6 - 2 * (i % 4));
+    }
+    packed
+}
+
+// Counts the occurrences of each canonical kmer of the input files, packed 4 bases per byte.
+// Kmers containing non ACGT bases are not counted
+pub(crate) fn count_kmers(input_files: &[PathBuf], k: usize) -> HashMap<Vec<u8>, u64> {
+    let mut counts: HashMap<Vec<u8>, u64> = HashMap::new();
+
+    for input_file in input_files {
+        SequencesReader::new().process_file_extended(
+            input_file,
+            |seq| {
+                if seq.seq.len() < k {
+                    return;
+                }
+
+                for kmer in seq.seq.windows(k) {
+                    if let Some(canonical) = canonical_kmer(kmer) {
+                        *counts.entry(pack_kmer(&canonical)).or_insert(0) += 1;
+                    }
+                }
+            },
+            None,
+            false,
+            false,
+        );
+    }
+
+    counts
+}
+
+/// Builds a table with the exact number of occurrences of each canonical kmer of the input files,
+/// sorted to allow binary search lookups directly from disk.
+/// Kmers containing non ACGT bases are not counted. All the distinct kmers are kept in memory
+/// while counting, so this is intended for moderately sized inputs
+pub fn build_kmer_counts_table(input_files: &[PathBuf], output_file: impl AsRef<Path>, k: usize) {
+    let counts = count_kmers(input_files, k);
+
+    let mut entries: Vec<_> = counts.into_iter().collect();
+    entries.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    let mut writer = BufWriter::new(File::create(output_file.as_ref()).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create kmer counts table file {}: {}",
+            output_file.as_ref().display(),
+            err
+        )
+    }));
+
+    writer.write_all(&TABLE_MAGIC).unwrap();
+    writer.write_u64::<LittleEndian>(TABLE_VERSION).unwrap();
+    writer.write_u64::<LittleEndian>(k as u64).unwrap();
+    writer
+        .write_u64::<LittleEndian>(entries.len() as u64)
+        .unwrap();
+
+    for (kmer, count) in entries.iter() {
+        writer.write_all(kmer).unwrap();
+        writer.write_u64::<LittleEndian>(*count).unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+/// Kmer counts table reader, performing the lookups with a binary search on the table file
+/// without loading it in memory
+pub struct KmerCountsTable {
+    file: File,
+    k: usize,
+    entries_count: u64,
+    entry_buffer: Vec<u8>,
+}
+
+impl KmerCountsTable {
+    pub fn open(table_file: impl AsRef<Path>) -> Result<Self, String> {
+        let mut file = File::open(table_file.as_ref()).map_err(|err| {
+            format!(
+                "Cannot open kmer counts table file {}: {}",
+                table_file.as_ref().display(),
+                err
+            )
+        })?;
+
+        let mut magic = [0; 16];
+        file.read_exact(&mut magic).map_err(|e| e.to_string())?;
+        if magic != TABLE_MAGIC {
+            return Err("Invalid kmer counts table file".to_string());
+        }
+
+        let version = file.read_u64::<LittleEndian>().map_err(|e| e.to_string())?;
+        if version != TABLE_VERSION {
+            return Err(format!(
+                "Unsupported kmer counts table version {}, please rebuild the table",
+                version
+            ));
+        }
+
+        let k = file.read_u64::<LittleEndian>().map_err(|e| e.to_string())? as usize;
+        let entries_count = file.read_u64::<LittleEndian>().map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            file,
+            k,
+            entries_count,
+            entry_buffer: vec![0; (k + 3) / 4],
+        })
+    }
+
+    pub fn kmer_length(&self) -> usize {
+        self.k
+    }
+
+    /// Number of distinct canonical kmers in the table
+    pub fn kmers_count(&self) -> u64 {
+        self.entries_count
+    }
+
+    fn entry_size(&self) -> u64 {
+        self.entry_buffer.len() as u64 + COUNT_SIZE
+    }
+
+    // Reads the packed kmer of an entry into the entry buffer, returning its count
+    fn read_entry(&mut self, index: u64) -> u64 {
+        self.file
+            .seek(SeekFrom::Start(HEADER_SIZE + index * self.entry_size()))
+            .unwrap();
+        self.file.read_exact(&mut self.entry_buffer).unwrap();
+        self.file.read_u64::<LittleEndian>().unwrap()
+    }
+
+    /// Returns the exact count of the given kmer (in plain ACGT format) and of its reverse
+    /// complement, 0 if the kmer is not in the table or contains non ACGT bases
+    pub fn get_count(&mut self, kmer: &[u8]) -> u64 {
+        assert_eq!(
+            kmer.len(),
+            self.k,
+            "The kmer length must be equal to the table k ({})",
+            self.k
+        );
+
+        let packed = match canonical_kmer(kmer) {
+            Some(canonical) => pack_kmer(&canonical),
+            None => return 0,
+        };
+
+        let mut start = 0;
+        let mut end = self.entries_count;
+
+        while start < end {
+            let middle = start + (end - start) / 2;
+            let count = self.read_entry(middle);
+
+            match self.entry_buffer.cmp(&packed) {
+                std::cmp::Ordering::Less => start = middle + 1,
+                std::cmp::Ordering::Greater => end = middle,
+                std::cmp::Ordering::Equal => return count,
+            }
+        }
+
+        0
+    }
+
+    /// Looks up each kmer, returning it in canonical form with its count.
+    /// Kmers with non ACGT bases are returned unchanged, with a count of 0
+    pub fn query_kmer_counts<'a>(
+        &'a mut self,
+        kmers: impl Iterator<Item = &'a [u8]> + 'a,
+    ) -> impl Iterator<Item = (Vec<u8>, u64)> + 'a {
+        kmers.map(move |kmer| {
+            let count = self.get_count(kmer);
+            (canonical_kmer(kmer).unwrap_or_else(|| kmer.to_vec()), count)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_kmer_counts_table, KmerCountsTable};
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn kmer_counts_lookup() {
+        const K: usize = 7;
+        let temp_dir = TestDir::new("kmer_counts_lookup");
+        let input_file = temp_dir.path("kmer-counts.fa");
+        let table_file = temp_dir.path("kmer-counts.counts.tbl");
+
+        {
+            let mut input = std::fs::File::create(&input_file).unwrap();
+            // ACGTTGC appears twice on the forward strand and once as GCAACGT (reverse complement)
+            writeln!(
+                input,
+                ">read0\nACGTTGCAAT\n>read1\nTTACGTTGC\n>read2\nGGCAACGTNACG"
+            )
+            .unwrap();
+        }
+
+        build_kmer_counts_table(&[input_file.clone()], &table_file, K);
+        let mut table = KmerCountsTable::open(&table_file).unwrap();
+
+        let results: Vec<_> = table
+            .query_kmer_counts(
+                [
+                    &b"ACGTTGC"[..],
+                    &b"GCAACGT"[..],
+                    &b"AAAAAAA"[..],
+                    &b"ACGTNAC"[..],
+                ]
+                .into_iter(),
+            )
+            .collect();
+
+        assert_eq!(
+            results,
+            vec![
+                (b"ACGTTGC".to_vec(), 3),
+                (b"ACGTTGC".to_vec(), 3),
+                (b"AAAAAAA".to_vec(), 0),
+                (b"ACGTNAC".to_vec(), 0),
+            ]
+        );
+    }
+}
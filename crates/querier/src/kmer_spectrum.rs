@@ -0,0 +1,226 @@
+use crate::kmer_counts::count_kmers;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The number of distinct canonical kmers of a sample with each abundance, indexed by abundance
+pub fn compute_kmer_spectrum(input_files: &[PathBuf], k: usize) -> Vec<u64> {
+    let mut spectrum = vec![0];
+    for count in count_kmers(input_files, k).into_values() {
+        let count = count as usize;
+        if spectrum.len() <= count {
+            spectrum.resize(count + 1, 0);
+        }
+        spectrum[count] += 1;
+    }
+    spectrum
+}
+
+/// The minimum multiplicity suggested when the spectrum has no clear valley,
+/// dropping only the kmers seen once
+pub const DEFAULT_MIN_MULTIPLICITY: usize = 2;
+
+// The coverage peak must be at least this many times higher than the valley before it
+const MIN_PEAK_TO_VALLEY_RATIO: f64 = 1.5;
+
+/// Finds the valley between the peak of the erroneous kmers, at low abundance, and the peak of the
+/// true coverage in a kmer spectrum, returning its abundance as the minimum multiplicity that keeps
+/// only the kmers of the coverage peak. Returns None if the spectrum has no clear valley, as when
+/// it decreases monotonically (coverage too low to separate the errors) or it has no error peak
+pub fn estimate_min_multiplicity(spectrum: &[u64]) -> Option<usize> {
+    // The end of the initial decline of the erroneous kmers
+    let decline_end = (1..spectrum.len().saturating_sub(1))
+        .find(|&abundance| spectrum[abundance + 1] >= spectrum[abundance])?;
+
+    let (peak, peak_count) = spectrum
+        .iter()
+        .enumerate()
+        .skip(decline_end)
+        .max_by_key(|(abundance, count)| (**count, std::cmp::Reverse(*abundance)))?;
+
+    // The lowest point between the error peak and the coverage peak, ignoring the local fluctuations
+    let (valley, valley_count) = spectrum
+        .iter()
+        .enumerate()
+        .take(peak + 1)
+        .skip(1)
+        .min_by_key(|(abundance, count)| (**count, *abundance))?;
+
+    if valley <= 1
+        || valley >= peak
+        || (*peak_count as f64) < MIN_PEAK_TO_VALLEY_RATIO * (*valley_count as f64)
+    {
+        return None;
+    }
+    Some(valley)
+}
+
+/// Distances between the kmer spectra of two samples
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SpectraComparison {
+    /// L1 distance between the spectra normalized to sum 1, in 0..=2.
+    /// It does not depend on the number of distinct kmers of each sample
+    pub l1_distance: f64,
+    /// Cosine similarity between the spectra, in 0..=1
+    pub cosine_similarity: f64,
+}
+
+/// Compares two kmer spectra, as computed by compute_kmer_spectrum
+pub fn compare_spectra(first: &[u64], second: &[u64]) -> SpectraComparison {
+    let first_total = first.iter().sum::<u64>().max(1) as f64;
+    let second_total = second.iter().sum::<u64>().max(1) as f64;
+
+    let mut l1_distance = 0.0;
+    let mut dot_product = 0.0;
+    for abundance in 0..first.len().max(second.len()) {
+        let first_count = first.get(abundance).copied().unwrap_or(0) as f64;
+        let second_count = second.get(abundance).copied().unwrap_or(0) as f64;
+        l1_distance += (first_count / first_total - second_count / second_total).abs();
+        dot_product += first_count * second_count;
+    }
+
+    let norm = |spectrum: &[u64]| {
+        spectrum
+            .iter()
+            .map(|count| (*count as f64).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    };
+    let norms_product = norm(first) * norm(second);
+
+    SpectraComparison {
+        l1_distance,
+        cosine_similarity: if norms_product > 0.0 {
+            dot_product / norms_product
+        } else {
+            0.0
+        },
+    }
+}
+
+/// Writes the spectra of two samples side by side as a tab-separated table, with a row
+/// for each abundance found in any of the samples, preceded by the distances between them
+pub fn write_spectra_comparison(
+    first: &[u64],
+    second: &[u64],
+    comparison: &SpectraComparison,
+    output_file: impl AsRef<Path>,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(output_file)?);
+
+    writeln!(writer, "#l1_distance\t{:.6}", comparison.l1_distance)?;
+    writeln!(
+        writer,
+        "#cosine_similarity\t{:.6}",
+        comparison.cosine_similarity
+    )?;
+    writeln!(writer, "#abundance\tfirst\tsecond")?;
+
+    for abundance in 1..first.len().max(second.len()) {
+        let first_count = first.get(abundance).copied().unwrap_or(0);
+        let second_count = second.get(abundance).copied().unwrap_or(0);
+        if first_count > 0 || second_count > 0 {
+            writeln!(writer, "{}\t{}\t{}", abundance, first_count, second_count)?;
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_spectra, compute_kmer_spectrum, estimate_min_multiplicity};
+    use std::path::PathBuf;
+    use utils::test_dir::TestDir;
+
+    const K: usize = 15;
+
+    fn write_sample(temp_dir: &TestDir, name: &str, sequences: &[(&str, usize)]) -> PathBuf {
+        let file = temp_dir.path(name);
+        let mut content = String::new();
+        for (sequence, copies) in sequences {
+            for _ in 0..*copies {
+                content.push_str(&format!(">read\n{}\n", sequence));
+            }
+        }
+        std::fs::write(&file, content).unwrap();
+        file
+    }
+
+    #[test]
+    fn similar_samples_have_closer_spectra() {
+        let temp_dir = TestDir::new("similar_samples_have_closer_spectra");
+        let mut state: u64 = 0x1f83_d9ab_fb41_bd6b;
+        let mut random_sequence = |length: usize| -> String {
+            (0..length)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    ['A', 'C', 'G', 'T'][(state >> 32) as usize % 4]
+                })
+                .collect()
+        };
+        let (first, second, third) = (
+            random_sequence(500),
+            random_sequence(500),
+            random_sequence(500),
+        );
+
+        // The same coverage profile over different sequences, and a sample with a contaminant at high coverage
+        let reference = write_sample(
+            &temp_dir,
+            "spectrum-reference.fa",
+            &[(&first, 1), (&second, 3)],
+        );
+        let same_profile = write_sample(&temp_dir, "spectrum-same.fa", &[(&third, 1), (&first, 3)]);
+        let contaminated = write_sample(
+            &temp_dir,
+            "spectrum-contaminated.fa",
+            &[(&first, 1), (&second, 3), (&third, 20)],
+        );
+
+        let reference_spectrum = compute_kmer_spectrum(&[reference.clone()], K);
+        let kmers_count = (500 - K + 1) as u64;
+        assert_eq!(reference_spectrum[1], kmers_count);
+        assert_eq!(reference_spectrum[3], kmers_count);
+
+        let same_comparison = compare_spectra(
+            &reference_spectrum,
+            &compute_kmer_spectrum(&[same_profile.clone()], K),
+        );
+        assert!(same_comparison.l1_distance < 1e-9);
+        assert!((same_comparison.cosine_similarity - 1.0).abs() < 1e-9);
+
+        let contaminated_comparison = compare_spectra(
+            &reference_spectrum,
+            &compute_kmer_spectrum(&[contaminated.clone()], K),
+        );
+        assert!(contaminated_comparison.l1_distance > 0.5);
+        assert!(contaminated_comparison.cosine_similarity < 0.9);
+    }
+
+    #[test]
+    fn min_multiplicity_is_in_the_spectrum_valley() {
+        // Errors decaying from abundance 1 and a coverage peak around abundance 20, with a little noise
+        let spectrum: Vec<u64> = (0..60u64)
+            .map(|abundance| {
+                if abundance == 0 {
+                    return 0;
+                }
+                let errors = 100_000 / (abundance * abundance * abundance);
+                let distance = abundance as f64 - 20.0;
+                let coverage = (5_000.0 * (-distance * distance / 50.0).exp()) as u64;
+                errors + coverage + abundance % 3
+            })
+            .collect();
+
+        let cutoff = estimate_min_multiplicity(&spectrum).unwrap();
+        assert!((6..=12).contains(&cutoff), "{}", cutoff);
+        assert!(spectrum[cutoff] < spectrum[1] && spectrum[cutoff] < spectrum[20]);
+
+        // Only errors, and only the coverage peak
+        assert_eq!(estimate_min_multiplicity(&[0, 1000, 200, 50, 10, 2]), None);
+        assert_eq!(estimate_min_multiplicity(&[0, 10, 50, 200, 50, 10]), None);
+        assert_eq!(estimate_min_multiplicity(&[]), None);
+    }
+}
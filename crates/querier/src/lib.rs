@@ -3,22 +3,59 @@ use crate::pipeline::colormap_reading::colormap_reading;
 use crate::pipeline::counters_sorting::counters_sorting;
 use crate::pipeline::parallel_kmers_query::parallel_kmers_counting;
 use crate::pipeline::querier_minimizer_bucketing::minimizer_bucketing;
+use crate::unitigs_subset::write_unitigs_subset;
 use ::dynamic_dispatch::dynamic_dispatch;
 use colors::colors_manager::{ColorMapReader, ColorsManager, ColorsMergeManager};
 use colors::DefaultColorsSerializer;
-use config::{INTERMEDIATE_COMPRESSION_LEVEL_FAST, INTERMEDIATE_COMPRESSION_LEVEL_SLOW};
+use config::{
+    ColorIndexType, PipelineSettings, SavedCompressionLevels, INTERMEDIATE_COMPRESSION_LEVEL_FAST,
+    INTERMEDIATE_COMPRESSION_LEVEL_SLOW,
+};
 use hashes::{HashFunctionFactory, MinimizerHashFunctionFactory};
+use io::concurrent::temp_reads::creads_utils::take_corrupted_record_error;
+use io::open_files_limit::limit_buckets_count_log;
 use io::sequences_reader::SequencesReader;
 use io::sequences_stream::general::GeneralSequenceBlockData;
 use io::{compute_stats_from_input_blocks, generate_bucket_names};
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
 use std::sync::atomic::Ordering;
+use utils::phase_log::{init_phases, print_phases_stats};
 
+pub mod abundance_profile;
+pub mod color_abundance;
+pub mod color_jaccard;
+pub mod color_unitigs;
+pub mod colored_gfa;
+pub mod count_matrix;
+pub mod coverage_track;
+pub mod degenerate_query;
+pub mod fasta_import;
+pub mod graph_coloring;
+pub mod kmer_counts;
+pub mod kmer_spectrum;
+pub mod matched_strand;
+pub mod output_parts;
+pub mod phase_resume;
 mod pipeline;
+pub mod positions_query;
+pub mod query_classification;
+pub mod query_results_merge;
+pub mod reference_orientation;
+pub mod regions_query;
+pub mod run_length_query;
+pub mod scaffolding;
 mod structs;
+pub mod unique_kmers;
+pub mod unitigs_dot;
+pub mod unitigs_index;
+#[cfg(feature = "parquet")]
+pub mod unitigs_parquet;
+pub mod unitigs_query;
+pub mod unitigs_subset;
+
+pub use pipeline::counters_external_sort::set_counters_sort_memory;
 
 #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
 pub enum QuerierStartingStep {
@@ -32,11 +69,34 @@ pub enum QuerierStartingStep {
 pub enum ColoredQueryOutputFormat {
     JsonLinesWithNumbers,
     JsonLinesWithNames,
+    /// A tab-separated record per query with its best color, as described in query_classification
+    Classification,
+    /// A tab-separated matrix with a row for each query and a column for each color, as described in count_matrix
+    CountMatrix,
+}
+
+impl ColoredQueryOutputFormat {
+    /// The extension of the output file, used when the output prefix has none
+    pub fn default_extension(&self) -> &'static str {
+        match self {
+            ColoredQueryOutputFormat::Classification | ColoredQueryOutputFormat::CountMatrix => {
+                "tsv"
+            }
+            _ => "jsonl",
+        }
+    }
 }
 
+/// Callback receiving the matches of a single query, as (color index, matched kmers count) pairs,
+/// as soon as the output bucket containing the query is completed.
+/// It is called concurrently from multiple threads and the queries are not reported in order.
+pub type QueryResultCallback = dyn Fn(u64, &[(ColorIndexType, u64)]) + Sync + Send;
+
 #[dynamic_dispatch(BucketingHash = [
     hashes::cn_nthash::CanonicalNtHashIteratorFactory,
-    #[cfg(not(feature = "devel-build"))]  hashes::fw_nthash::ForwardNtHashIteratorFactory
+    #[cfg(not(feature = "devel-build"))]  hashes::fw_nthash::ForwardNtHashIteratorFactory,
+    #[cfg(not(feature = "devel-build"))]  hashes::cn_nthash::ordered::CanonicalNtHashIteratorFactory,
+    #[cfg(not(feature = "devel-build"))]  hashes::fw_nthash::ordered::ForwardNtHashIteratorFactory
 ], MergingHash = [
     #[cfg(not(feature = "devel-build"))] hashes::fw_seqhash::u16::ForwardSeqHashFactory,
     #[cfg(not(feature = "devel-build"))] hashes::fw_seqhash::u32::ForwardSeqHashFactory,
@@ -49,6 +109,10 @@ pub enum ColoredQueryOutputFormat {
     #[cfg(not(feature = "devel-build"))] hashes::cn_seqhash::u32::CanonicalSeqHashFactory,
     hashes::cn_seqhash::u64::CanonicalSeqHashFactory,
     #[cfg(not(feature = "devel-build"))] hashes::cn_seqhash::u128::CanonicalSeqHashFactory,
+    #[cfg(not(feature = "devel-build"))] hashes::cn_lex_seqhash::u16::CanonicalSeqHashFactory,
+    #[cfg(not(feature = "devel-build"))] hashes::cn_lex_seqhash::u32::CanonicalSeqHashFactory,
+    #[cfg(not(feature = "devel-build"))] hashes::cn_lex_seqhash::u64::CanonicalSeqHashFactory,
+    #[cfg(not(feature = "devel-build"))] hashes::cn_lex_seqhash::u128::CanonicalSeqHashFactory,
     #[cfg(not(feature = "devel-build"))] hashes::cn_rkhash::u32::CanonicalRabinKarpHashFactory,
     #[cfg(not(feature = "devel-build"))] hashes::cn_rkhash::u64::CanonicalRabinKarpHashFactory,
     #[cfg(not(feature = "devel-build"))] hashes::cn_rkhash::u128::CanonicalRabinKarpHashFactory,
@@ -72,10 +136,15 @@ pub fn run_query<
     threads_count: usize,
     default_compression_level: Option<u32>,
     colored_query_output_format: ColoredQueryOutputFormat,
+    result_callback: Option<&QueryResultCallback>,
+    unitigs_subset: Option<Vec<u64>>,
+    settings: PipelineSettings,
 ) -> PathBuf {
     let temp_dir = temp_dir.unwrap_or(PathBuf::new());
 
-    PHASES_TIMES_MONITOR.write().init();
+    init_phases();
+    // Discards the corrupted records of a previous run
+    let _ = take_corrupted_record_error();
 
     BucketingHash::initialize(k);
     MergingHash::initialize(k);
@@ -84,14 +153,33 @@ pub fn run_query<
         graph_input.with_extension("colors.dat"),
     );
 
+    if let Err(err) = color_map.check_kmer_length(k) {
+        panic!("{}", err);
+    }
+
+    // Only the kmers of the selected unitigs are searched,
+    // while the colormap is still the one of the full graph
+    let searched_graph = match &unitigs_subset {
+        Some(unitig_ids) => {
+            let subset_file = temp_dir.join("unitigs-subset.fa");
+            write_unitigs_subset(&graph_input, unitig_ids, &subset_file);
+            subset_file
+        }
+        None => graph_input.clone(),
+    };
+
     // TODO: Support GFA input
     let file_stats = compute_stats_from_input_blocks(&[
-        GeneralSequenceBlockData::FASTA((graph_input.clone(), None)),
+        GeneralSequenceBlockData::FASTA((searched_graph.clone(), None)),
         GeneralSequenceBlockData::FASTA((query_input.clone(), None)),
     ]);
 
-    let buckets_count_log = buckets_count_log.unwrap_or_else(|| file_stats.best_buckets_count_log);
+    let buckets_count_log = limit_buckets_count_log(
+        buckets_count_log.unwrap_or_else(|| file_stats.best_buckets_count_log),
+        settings.max_open_files,
+    );
 
+    let _saved_compression_levels = SavedCompressionLevels::save();
     if let Some(default_compression_level) = default_compression_level {
         INTERMEDIATE_COMPRESSION_LEVEL_SLOW.store(default_compression_level, Ordering::Relaxed);
         INTERMEDIATE_COMPRESSION_LEVEL_FAST.store(default_compression_level, Ordering::Relaxed);
@@ -101,13 +189,14 @@ pub fn run_query<
 
     let ((buckets, counters), queries_count) = if step <= QuerierStartingStep::MinimizerBucketing {
         minimizer_bucketing::<BucketingHash, QuerierColorsManager>(
-            graph_input.clone(),
+            searched_graph,
             query_input.clone(),
             temp_dir.as_path(),
             buckets_count,
             threads_count,
             k,
             m,
+            settings,
         )
     } else {
         (
@@ -134,6 +223,7 @@ pub fn run_query<
             k,
             m,
             threads_count,
+            settings,
         )
     } else {
         generate_bucket_names(temp_dir.join("counters"), buckets_count, None)
@@ -141,18 +231,36 @@ pub fn run_query<
 
     let colored_buckets_prefix = temp_dir.join("color_counters");
 
-    let query_kmers_count = {
+    let with_query_names = colored_query_output_format == ColoredQueryOutputFormat::Classification
+        || colored_query_output_format == ColoredQueryOutputFormat::CountMatrix;
+    let (query_kmers_count, query_names) = {
         let mut sequences_lengths = vec![];
-        SequencesReader::new().process_file_extended(
-            &query_input,
-            |seq| {
-                sequences_lengths.push((seq.seq.len().saturating_sub(k - 1)) as u64);
-            },
-            None,
-            false,
-            false,
-        );
-        sequences_lengths
+        let mut sequences_names = vec![];
+        SequencesReader::new()
+            .with_strict_input(settings.strict_input)
+            .process_file_extended(
+                &query_input,
+                |seq| {
+                    sequences_lengths.push((seq.seq.len().saturating_sub(k - 1)) as u64);
+                    if with_query_names {
+                        sequences_names.push(
+                            String::from_utf8_lossy(
+                                seq.ident_data
+                                    .strip_prefix(b">")
+                                    .unwrap_or(seq.ident_data)
+                                    .split(|c| c.is_ascii_whitespace())
+                                    .next()
+                                    .unwrap_or(&[]),
+                            )
+                            .into_owned(),
+                        );
+                    }
+                },
+                None,
+                with_query_names,
+                false,
+            );
+        (sequences_lengths, sequences_names)
     };
 
     let colored_buckets = if step <= QuerierStartingStep::CountersSorting {
@@ -183,17 +291,21 @@ pub fn run_query<
             output_file_prefix.clone(),
             temp_dir,
             &query_kmers_count,
+            &query_names,
             colored_query_output_format,
+            result_callback,
         );
     }
 
-    PHASES_TIMES_MONITOR
-        .write()
-        .print_stats("Query completed.".to_string());
+    if let Err(err) = take_corrupted_record_error() {
+        panic!("{}", err);
+    }
+
+    print_phases_stats("Query completed.".to_string());
 
     let output_file_name = if output_file_prefix.extension().is_none() {
         if QuerierColorsManager::COLORS_ENABLED {
-            output_file_prefix.with_extension("jsonl")
+            output_file_prefix.with_extension(colored_query_output_format.default_extension())
         } else {
             output_file_prefix.with_extension("csv")
         }
@@ -0,0 +1,23 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static MATCHED_STRAND_REPORT: AtomicBool = AtomicBool::new(false);
+
+/// Reports for each (query, color) the strand of the graph where the majority of the query kmers matched,
+/// as a + / - column of the query output
+pub fn set_matched_strand_report(enabled: bool) {
+    MATCHED_STRAND_REPORT.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_matched_strand_report() -> bool {
+    MATCHED_STRAND_REPORT.load(Ordering::Relaxed)
+}
+
+/// The strand with the majority of the matched kmers, given the total matches and the ones on the
+/// reverse strand of the graph. Ties are reported on the forward strand
+pub fn matched_strand(matched_kmers: u64, reverse_matched_kmers: u64) -> char {
+    if reverse_matched_kmers * 2 > matched_kmers {
+        '-'
+    } else {
+        '+'
+    }
+}
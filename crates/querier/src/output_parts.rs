@@ -0,0 +1,198 @@
+use crate::reference_orientation::create_graph_writer;
+use crate::unitigs_index::parse_unitig_id;
+use io::concurrent::structured_sequences::fasta::FastaWriter;
+use io::concurrent::structured_sequences::StructuredSequenceBackend;
+use io::sequences_reader::SequencesReader;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The path of a part of the output file, with the 1-based part number before the file extensions,
+/// e.g. unitigs.fa.lz4 -> unitigs.part001.fa.lz4
+pub fn part_file_path(output_file: impl AsRef<Path>, part_number: usize) -> PathBuf {
+    let output_file = output_file.as_ref();
+    let file_name = output_file
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let part_name = match file_name.split_once('.') {
+        Some((stem, extensions)) => format!("{}.part{:03}.{}", stem, part_number, extensions),
+        None => format!("{}.part{:03}", file_name, part_number),
+    };
+    output_file.with_file_name(part_name)
+}
+
+/// Splits the graph into files of at most max_records_per_file unitigs each, named as described in
+/// part_file_path and compressed as the graph file. The unitigs keep their headers and their order.
+/// An index of the unitigs in each part is written to index_file, with a tab-separated line
+/// with the part file name and the first and last id of each run of consecutive ids.
+/// The unitig ids are taken from the sequence headers, falling back to the sequence
+/// ordinal if an header does not start with a number.
+/// Returns the written part files
+pub fn write_output_parts(
+    graph_input: impl AsRef<Path>,
+    max_records_per_file: usize,
+    index_file: impl AsRef<Path>,
+) -> Vec<PathBuf> {
+    assert!(
+        max_records_per_file > 0,
+        "The maximum number of records per file must be positive"
+    );
+
+    let mut index_writer =
+        BufWriter::new(File::create(index_file.as_ref()).unwrap_or_else(|err| {
+            panic!(
+                "Cannot create output parts index {}: {}",
+                index_file.as_ref().display(),
+                err
+            )
+        }));
+    writeln!(index_writer, "#part\tfirst_id\tlast_id").unwrap();
+
+    let mut part_files = vec![];
+    let mut writer: Option<FastaWriter<(), ()>> = None;
+    let mut part_records = 0;
+    // The run of consecutive ids of the current part that is not yet written to the index
+    let mut ids_run: Option<(u64, u64)> = None;
+
+    let mut write_ids_run = |part_file: &Path, ids_run: &mut Option<(u64, u64)>| {
+        if let Some((first_id, last_id)) = ids_run.take() {
+            writeln!(
+                index_writer,
+                "{}\t{}\t{}",
+                part_file.file_name().unwrap().to_string_lossy(),
+                first_id,
+                last_id
+            )
+            .unwrap();
+        }
+    };
+
+    let mut buffer = vec![];
+    let mut sequence_index = 0;
+
+    SequencesReader::new().process_file_extended(
+        graph_input.as_ref(),
+        |seq| {
+            let unitig_id = parse_unitig_id(seq.ident_data).unwrap_or(sequence_index);
+            sequence_index += 1;
+
+            if writer.is_none() || part_records == max_records_per_file {
+                if let Some(part_file) = part_files.last() {
+                    write_ids_run(part_file, &mut ids_run);
+                }
+                if let Some(writer) = writer.take() {
+                    writer.finalize();
+                }
+
+                let part_file = part_file_path(graph_input.as_ref(), part_files.len() + 1);
+                writer = Some(create_graph_writer(&part_file));
+                part_files.push(part_file);
+                part_records = 0;
+            }
+
+            ids_run = match ids_run {
+                Some((first_id, last_id)) if last_id + 1 == unitig_id => {
+                    Some((first_id, unitig_id))
+                }
+                _ => {
+                    write_ids_run(part_files.last().unwrap(), &mut ids_run);
+                    Some((unitig_id, unitig_id))
+                }
+            };
+
+            buffer.extend_from_slice(seq.ident_data);
+            buffer.extend_from_slice(b"\n");
+            buffer.extend_from_slice(seq.seq);
+            buffer.extend_from_slice(b"\n");
+            writer.as_mut().unwrap().flush_temp_buffer(&mut buffer);
+            part_records += 1;
+        },
+        None,
+        true,
+        false,
+    );
+
+    if let Some(part_file) = part_files.last() {
+        write_ids_run(part_file, &mut ids_run);
+    }
+    if let Some(writer) = writer {
+        writer.finalize();
+    }
+    index_writer.flush().unwrap();
+
+    part_files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{part_file_path, write_output_parts};
+    use std::collections::BTreeMap;
+    use std::io::{BufRead, BufReader};
+    use std::path::PathBuf;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn unitigs_are_split_in_parts_exactly_once() {
+        let temp_dir = TestDir::new("unitigs_are_split_in_parts_exactly_once");
+        let graph_file = temp_dir.path("output-parts.fa");
+
+        let mut graph = String::new();
+        for id in 0..7 {
+            graph.push_str(&format!(
+                ">{} LN:i:{}\n{}\n",
+                id,
+                4 * (id + 1),
+                "ACGT".repeat(id + 1)
+            ));
+        }
+        std::fs::write(&graph_file, graph).unwrap();
+
+        let index_file = temp_dir.path("output-parts.parts.tsv");
+        let part_files = write_output_parts(&graph_file, 3, &index_file);
+
+        assert_eq!(
+            part_files,
+            (1..=3)
+                .map(|part| part_file_path(&graph_file, part))
+                .collect::<Vec<PathBuf>>()
+        );
+        assert_eq!(
+            part_files[0].file_name().unwrap(),
+            "ggcat-output-parts-test.part001.fa"
+        );
+
+        // Each unitig is found exactly once, in the part listed by the index
+        let mut unitig_parts = BTreeMap::new();
+        for part_file in &part_files {
+            let mut records = 0;
+            for line in BufReader::new(std::fs::File::open(part_file).unwrap()).lines() {
+                let line = line.unwrap();
+                if let Some(header) = line.strip_prefix('>') {
+                    let id: u64 = header.split(' ').next().unwrap().parse().unwrap();
+                    let part_name = part_file.file_name().unwrap().to_string_lossy().to_string();
+                    assert!(unitig_parts.insert(id, part_name).is_none());
+                    records += 1;
+                }
+            }
+            assert!(records <= 3);
+        }
+        assert_eq!(
+            unitig_parts.keys().copied().collect::<Vec<_>>(),
+            (0..7).collect::<Vec<_>>()
+        );
+
+        let index = std::fs::read_to_string(&index_file).unwrap();
+        let mut index_parts = BTreeMap::new();
+        for line in index.lines().skip(1) {
+            let fields: Vec<_> = line.split('\t').collect();
+            let (first_id, last_id): (u64, u64) =
+                (fields[1].parse().unwrap(), fields[2].parse().unwrap());
+            for id in first_id..=last_id {
+                assert!(index_parts.insert(id, fields[0].to_string()).is_none());
+            }
+        }
+        assert_eq!(index_parts, unitig_parts);
+    }
+}
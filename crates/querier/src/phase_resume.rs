@@ -0,0 +1,73 @@
+use crate::QuerierStartingStep;
+use io::generate_bucket_names;
+use std::path::{Path, PathBuf};
+
+impl QuerierStartingStep {
+    /// Parses the name of a query phase, as printed in the phases log (e.g. "colormap reading"),
+    /// ignoring the case and treating dashes and underscores as spaces
+    pub fn from_phase_name(name: &str) -> Result<Self, String> {
+        let normalized = name.trim().to_ascii_lowercase().replace(['-', '_'], " ");
+
+        match normalized.as_str() {
+            "minimizer bucketing" => Ok(QuerierStartingStep::MinimizerBucketing),
+            "kmers counting" => Ok(QuerierStartingStep::KmersCounting),
+            "counters sorting" => Ok(QuerierStartingStep::CountersSorting),
+            "colormap reading" => Ok(QuerierStartingStep::ColorMapReading),
+            _ => Err(format!(
+                "Unknown query phase '{}', expected one of: minimizer bucketing, kmers counting, counters sorting, colormap reading",
+                name
+            )),
+        }
+    }
+}
+
+/// The intermediate files of a query run in the given temp dir that are read by the phase
+fn phase_prerequisites(
+    temp_dir: &Path,
+    step: QuerierStartingStep,
+    buckets_count: usize,
+) -> Vec<PathBuf> {
+    match step {
+        QuerierStartingStep::MinimizerBucketing => vec![],
+        QuerierStartingStep::KmersCounting => {
+            let mut files = generate_bucket_names(temp_dir.join("bucket"), buckets_count, None);
+            files.push(temp_dir.join("buckets-counters.dat"));
+            files
+        }
+        QuerierStartingStep::CountersSorting => {
+            generate_bucket_names(temp_dir.join("counters"), buckets_count, None)
+        }
+        QuerierStartingStep::ColorMapReading => {
+            generate_bucket_names(temp_dir.join("color_counters"), buckets_count, None)
+        }
+    }
+}
+
+/// Checks that the intermediate files needed to run a query starting from the given phase
+/// were kept in the temp dir of a previous run
+pub fn check_phase_prerequisites(
+    temp_dir: &Path,
+    step: QuerierStartingStep,
+    buckets_count: usize,
+    colors: bool,
+) -> Result<(), String> {
+    if step == QuerierStartingStep::ColorMapReading && !colors {
+        return Err("The colormap reading phase is run only by colored queries".to_string());
+    }
+
+    let missing: Vec<_> = phase_prerequisites(temp_dir, step, buckets_count)
+        .into_iter()
+        .filter(|file| !file.exists())
+        .collect();
+
+    match missing.first() {
+        None => Ok(()),
+        Some(first) => Err(format!(
+            "Cannot resume from phase {:?}: {} of its intermediate files are missing from {} (e.g. {}), run the query again keeping the temporary files",
+            step,
+            missing.len(),
+            temp_dir.display(),
+            first.display()
+        )),
+    }
+}
@@ -1,5 +1,7 @@
 pub mod colored_query_output;
+pub mod colormap_checkpoint;
 pub mod colormap_reading;
+pub mod counters_external_sort;
 pub mod counters_sorting;
 pub mod parallel_kmers_query;
 pub mod querier_minimizer_bucketing;
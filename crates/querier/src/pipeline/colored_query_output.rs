@@ -1,10 +1,19 @@
+use crate::color_abundance::{get_min_color_abundance, has_min_color_abundance};
+use crate::count_matrix::{
+    is_sparse_count_matrix, write_count_matrix_header, write_count_matrix_row,
+};
+use crate::matched_strand::{is_matched_strand_report, matched_strand};
+use crate::query_classification::{
+    write_classification_record, QueryClassification, CLASSIFICATION_HEADER,
+    CLASSIFICATION_STRAND_HEADER,
+};
 use crate::structs::query_colored_counters::{ColorsRange, QueryColoredCountersSerializer};
-use crate::ColoredQueryOutputFormat;
+use crate::{ColoredQueryOutputFormat, QueryResultCallback};
 use colors::colors_manager::ColorMapReader;
 use colors::colors_manager::{ColorsManager, ColorsMergeManager};
 use config::{
-    get_compression_level_info, get_memory_mode, ColorIndexType, SwapPriority,
-    DEFAULT_PREFETCH_AMOUNT, KEEP_FILES, QUERIES_COUNT_MIN_BATCH,
+    get_compression_level_info, get_memory_mode, keep_temp_files, ColorIndexType, SwapPriority,
+    DEFAULT_PREFETCH_AMOUNT, QUERIES_COUNT_MIN_BATCH,
 };
 use flate2::Compression;
 use hashes::{HashFunctionFactory, MinimizerHashFunctionFactory};
@@ -15,7 +24,6 @@ use parallel_processor::buckets::readers::BucketReader;
 use parallel_processor::buckets::writers::compressed_binary_writer::CompressedBinaryWriter;
 use parallel_processor::buckets::LockFreeBucket;
 use parallel_processor::memory_fs::RemoveFileMode;
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use parking_lot::{Condvar, Mutex};
 use rayon::prelude::*;
 use std::fs::File;
@@ -24,6 +32,7 @@ use std::io::Write;
 use std::ops::DerefMut;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use utils::phase_log::{log_warning, start_phase};
 
 enum QueryOutputFileWriter {
     Plain(File),
@@ -59,11 +68,12 @@ pub fn colored_query_output<
     output_file: PathBuf,
     temp_dir: PathBuf,
     query_kmers_count: &[u64],
+    // The query names, needed only with the classification and count matrix formats
+    query_names: &[String],
     colored_query_output_format: ColoredQueryOutputFormat,
+    result_callback: Option<&QueryResultCallback>,
 ) {
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase("phase: colored query output".to_string());
+    start_phase("phase: colored query output".to_string());
 
     let buckets_count = colored_query_buckets.len();
 
@@ -78,7 +88,7 @@ pub fn colored_query_output<
     let buckets_channel = Mutex::new(colored_query_buckets);
 
     let output_file = if output_file.extension().is_none() {
-        output_file.with_extension("jsonl")
+        output_file.with_extension(colored_query_output_format.default_extension())
     } else {
         output_file
     };
@@ -102,6 +112,37 @@ pub fn colored_query_output<
         ),
         0,
     ));
+    let with_strand = is_matched_strand_report();
+    let min_color_abundance = get_min_color_abundance();
+    if colored_query_output_format == ColoredQueryOutputFormat::Classification {
+        if with_strand {
+            writeln!(
+                query_output.lock().0,
+                "{}\t{}",
+                CLASSIFICATION_HEADER,
+                CLASSIFICATION_STRAND_HEADER
+            )
+            .unwrap();
+        } else {
+            writeln!(query_output.lock().0, "{}", CLASSIFICATION_HEADER).unwrap();
+        }
+    }
+    let colors_count = colormap.colors_count();
+    if colored_query_output_format == ColoredQueryOutputFormat::CountMatrix {
+        let sparse = is_sparse_count_matrix(colors_count);
+        if sparse {
+            log_warning(format!(
+                "WARNING: writing the count matrix in the sparse format, as the graph has {} colors",
+                colors_count
+            ));
+        }
+        write_count_matrix_header(
+            &mut query_output.lock().0,
+            (0..colors_count).map(|color| colormap.get_color_name(color as ColorIndexType, false)),
+            sparse,
+        )
+        .unwrap();
+    }
     let output_sync_condvar = Condvar::new();
 
     (0..rayon::current_num_threads())
@@ -111,6 +152,7 @@ pub fn colored_query_output<
             struct QueryColorListItem {
                 color: ColorIndexType,
                 count: u64,
+                reverse_count: u64,
                 next_index: usize,
             }
 
@@ -118,6 +160,9 @@ pub fn colored_query_output<
             let mut queries_results =
                 vec![(0u32 /* epoch */, 0usize /* list index */); max_bucket_queries_count];
             let mut temp_colors_list = vec![];
+            let mut query_matches = vec![];
+            let mut query_strands = vec![];
+            let mut matrix_row = vec![];
 
             let mut epoch = 0;
 
@@ -136,7 +181,7 @@ pub fn colored_query_output<
                 CompressedBinaryReader::new(
                     &input,
                     RemoveFileMode::Remove {
-                        remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                        remove_fs: !keep_temp_files(),
                     },
                     DEFAULT_PREFETCH_AMOUNT,
                 )
@@ -164,6 +209,7 @@ pub fn colored_query_output<
                                     queries_colors_list_pool.push(QueryColorListItem {
                                         color,
                                         count: query.count,
+                                        reverse_count: query.reverse_count,
                                         next_index: *colors_map_index,
                                     });
                                     *colors_map_index = queries_colors_list_pool.len() - 1;
@@ -175,6 +221,14 @@ pub fn colored_query_output<
 
                 let bucket_index = get_bucket_index(input);
 
+                // The unmatched queries of the bucket have a row of zeros in the wide count matrix
+                let with_unmatched_queries = colored_query_output_format
+                    == ColoredQueryOutputFormat::CountMatrix
+                    && !is_sparse_count_matrix(colors_count);
+                let end_query_index = ((bucket_index as usize + 1) * max_bucket_queries_count
+                    / buckets_count)
+                    .min(query_kmers_count.len());
+
                 let compressed_stream = CompressedBinaryWriter::new(
                     &temp_dir.join("query-data"),
                     &(
@@ -188,60 +242,138 @@ pub fn colored_query_output<
                 let mut jsonline_buffer = vec![];
                 for (query, mut query_colors_list_index) in
                     queries_results.iter().enumerate().filter_map(|(i, r)| {
-                        if r.0 != epoch {
-                            None
-                        } else {
+                        if r.0 == epoch {
                             Some((i + start_query_index, r.1))
+                        } else if with_unmatched_queries && i + start_query_index < end_query_index
+                        {
+                            Some((i + start_query_index, usize::MAX))
+                        } else {
+                            None
                         }
                     })
                 {
                     jsonline_buffer.clear();
-                    write!(
-                        jsonline_buffer,
-                        "{{\"query_index\":{}, \"matches\":{{",
-                        query
-                    )
-                    .unwrap();
 
                     temp_colors_list.clear();
                     while query_colors_list_index != usize::MAX {
                         let el = &queries_colors_list_pool[query_colors_list_index];
-                        temp_colors_list.push((el.color, el.count));
+                        temp_colors_list.push((el.color, el.count, el.reverse_count));
                         query_colors_list_index = el.next_index;
                     }
                     temp_colors_list.sort_unstable_by_key(|r| r.0);
-
-                    for (i, qc) in temp_colors_list.nq_group_by(|a, b| a.0 == b.0).enumerate() {
-                        let color_index = qc[0].0;
-                        let color_presence = qc.iter().map(|x| x.1).sum::<u64>();
-
-                        if i != 0 {
-                            write!(jsonline_buffer, ",").unwrap();
+                    query_matches.clear();
+                    query_strands.clear();
+
+                    for qc in temp_colors_list.nq_group_by(|a, b| a.0 == b.0) {
+                        let count = qc.iter().map(|x| x.1).sum::<u64>();
+                        if !has_min_color_abundance(
+                            count,
+                            query_kmers_count[query],
+                            min_color_abundance,
+                        ) {
+                            continue;
                         }
+                        query_matches.push((qc[0].0, count));
+                        query_strands.push(matched_strand(count, qc.iter().map(|x| x.2).sum()));
+                    }
 
-                        match colored_query_output_format {
-                            ColoredQueryOutputFormat::JsonLinesWithNumbers => {
-                                write!(jsonline_buffer, "\"{}\"", color_index)
-                            }
-                            ColoredQueryOutputFormat::JsonLinesWithNames => {
-                                write!(
-                                    jsonline_buffer,
-                                    "\"{}\"",
-                                    colormap.get_color_name(color_index, true)
-                                )
-                            }
-                        }
+                    if colored_query_output_format == ColoredQueryOutputFormat::CountMatrix {
+                        write_count_matrix_row(
+                            &mut jsonline_buffer,
+                            &query_names[query],
+                            &query_matches,
+                            colors_count,
+                            |color| colormap.get_color_name(color, false),
+                            &mut matrix_row,
+                        )
                         .unwrap();
-
+                    } else if colored_query_output_format
+                        == ColoredQueryOutputFormat::Classification
+                    {
+                        if let Some(classification) =
+                            QueryClassification::from_matches(&query_matches)
+                        {
+                            write_classification_record(
+                                &mut jsonline_buffer,
+                                &query_names[query],
+                                &colormap.get_color_name(classification.color, true),
+                                &classification,
+                                query_kmers_count[query],
+                                with_strand.then(|| {
+                                    query_strands[query_matches
+                                        .binary_search_by_key(&classification.color, |m| m.0)
+                                        .unwrap()]
+                                }),
+                            )
+                            .unwrap();
+                        }
+                    } else {
                         write!(
                             jsonline_buffer,
-                            ": {:.2}",
-                            (color_presence as f64) / (query_kmers_count[query as usize] as f64)
+                            "{{\"query_index\":{}, \"matches\":{{",
+                            query
                         )
                         .unwrap();
+
+                        for (i, (color_index, color_presence)) in query_matches.iter().enumerate() {
+                            if i != 0 {
+                                write!(jsonline_buffer, ",").unwrap();
+                            }
+
+                            match colored_query_output_format {
+                                ColoredQueryOutputFormat::JsonLinesWithNames => {
+                                    write!(
+                                        jsonline_buffer,
+                                        "\"{}\"",
+                                        colormap.get_color_name(*color_index, true)
+                                    )
+                                }
+                                _ => write!(jsonline_buffer, "\"{}\"", color_index),
+                            }
+                            .unwrap();
+
+                            write!(
+                                jsonline_buffer,
+                                ": {:.2}",
+                                (*color_presence as f64)
+                                    / (query_kmers_count[query as usize] as f64)
+                            )
+                            .unwrap();
+                        }
+                        write!(jsonline_buffer, "}}").unwrap();
+
+                        if with_strand {
+                            write!(jsonline_buffer, ", \"strands\":{{").unwrap();
+                            for (i, ((color_index, _), strand)) in
+                                query_matches.iter().zip(query_strands.iter()).enumerate()
+                            {
+                                if i != 0 {
+                                    write!(jsonline_buffer, ",").unwrap();
+                                }
+
+                                match colored_query_output_format {
+                                    ColoredQueryOutputFormat::JsonLinesWithNames => write!(
+                                        jsonline_buffer,
+                                        "\"{}\"",
+                                        colormap.get_color_name(*color_index, true)
+                                    ),
+                                    _ => write!(jsonline_buffer, "\"{}\"", color_index),
+                                }
+                                .unwrap();
+
+                                write!(jsonline_buffer, ": \"{}\"", strand).unwrap();
+                            }
+                            write!(jsonline_buffer, "}}").unwrap();
+                        }
+                        writeln!(jsonline_buffer, "}}").unwrap();
                     }
-                    writeln!(jsonline_buffer, "}}}}").unwrap();
                     compressed_stream.write_data(&jsonline_buffer);
+
+                    if let Some(result_callback) = result_callback {
+                        if !query_matches.is_empty() {
+                            result_callback(query as u64, &query_matches);
+                        }
+                    }
                 }
 
                 let stream_path = compressed_stream.get_path();
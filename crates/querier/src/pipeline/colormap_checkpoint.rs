@@ -0,0 +1,175 @@
+use crate::structs::query_colored_counters::{QueryColorDesc, QueryColoredCounters};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use config::BucketIndexType;
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The file listing the input buckets of the colormap reading whose output was completely written
+pub const COLORMAP_READING_CHECKPOINT_FILE: &str = "colormap-reading.checkpoint";
+
+/// Records the progress of the colormap reading in the temp dir, so that a resumed run skips the input
+/// buckets already processed. The output of each input bucket is written to its own part file, that is
+/// synced to disk before the bucket is appended to the checkpoint, one index per line. The part files of
+/// the buckets missing from the checkpoint may be partially written, and are rewritten from the start
+pub(crate) struct ColormapReadingCheckpoint {
+    temp_dir: PathBuf,
+    completed: HashSet<BucketIndexType>,
+    checkpoint_file: Mutex<File>,
+}
+
+impl ColormapReadingCheckpoint {
+    pub fn open(temp_dir: &Path) -> Self {
+        let checkpoint_path = temp_dir.join(COLORMAP_READING_CHECKPOINT_FILE);
+        let contents = std::fs::read_to_string(&checkpoint_path).unwrap_or_default();
+
+        // The last line may be truncated if the run was interrupted while writing it
+        let completed_lines = match contents.rfind('\n') {
+            Some(end) => &contents[..end],
+            None => "",
+        };
+
+        let mut checkpoint = Self {
+            temp_dir: temp_dir.to_path_buf(),
+            completed: HashSet::new(),
+            checkpoint_file: Mutex::new(
+                OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(true)
+                    .open(&checkpoint_path)
+                    .unwrap_or_else(|err| {
+                        panic!(
+                            "Cannot open colormap reading checkpoint {}: {}",
+                            checkpoint_path.display(),
+                            err
+                        )
+                    }),
+            ),
+        };
+
+        // The checkpoint is rewritten with the completed buckets whose part file is still present
+        let completed: Vec<_> = completed_lines
+            .lines()
+            .filter_map(|line| line.trim().parse::<BucketIndexType>().ok())
+            .filter(|bucket| checkpoint.part_file(*bucket).exists())
+            .collect();
+        for bucket in completed {
+            checkpoint.record_completed(bucket);
+            checkpoint.completed.insert(bucket);
+        }
+
+        checkpoint
+    }
+
+    fn part_file(&self, input_bucket: BucketIndexType) -> PathBuf {
+        self.temp_dir
+            .join(format!("query_colors_part.{}", input_bucket))
+    }
+
+    fn record_completed(&self, input_bucket: BucketIndexType) {
+        let mut checkpoint_file = self.checkpoint_file.lock();
+        writeln!(checkpoint_file, "{}", input_bucket).unwrap();
+        checkpoint_file.sync_data().unwrap();
+    }
+
+    pub fn is_completed(&self, input_bucket: BucketIndexType) -> bool {
+        self.completed.contains(&input_bucket)
+    }
+
+    /// Starts writing the output of an input bucket, discarding any previous partial output
+    pub fn part_writer(&self, input_bucket: BucketIndexType) -> ColoredCountersPartWriter {
+        let part_file = self.part_file(input_bucket);
+        ColoredCountersPartWriter {
+            writer: BufWriter::new(File::create(&part_file).unwrap_or_else(|err| {
+                panic!(
+                    "Cannot create colormap reading part {}: {}",
+                    part_file.display(),
+                    err
+                )
+            })),
+        }
+    }
+
+    /// Marks an input bucket as completed, after its output is synced to disk
+    pub fn complete(&self, input_bucket: BucketIndexType, part_writer: ColoredCountersPartWriter) {
+        let part_file = part_writer.writer.into_inner().unwrap();
+        part_file.sync_all().unwrap();
+        self.record_completed(input_bucket);
+    }
+
+    /// Reads the output of a completed input bucket, with the output bucket of each colored counters
+    pub fn read_part(
+        &self,
+        input_bucket: BucketIndexType,
+        mut callback: impl FnMut(BucketIndexType, QueryColoredCounters),
+    ) {
+        let part_file = self.part_file(input_bucket);
+        let mut reader = BufReader::new(File::open(&part_file).unwrap_or_else(|err| {
+            panic!(
+                "Cannot open colormap reading part {}: {}",
+                part_file.display(),
+                err
+            )
+        }));
+
+        let mut queries = vec![];
+        let mut colors = vec![];
+        while let Ok(bucket) = reader.read_u16::<LittleEndian>() {
+            let queries_count = reader.read_u32::<LittleEndian>().unwrap();
+            queries.clear();
+            for _ in 0..queries_count {
+                queries.push(QueryColorDesc {
+                    query_index: reader.read_u64::<LittleEndian>().unwrap(),
+                    count: reader.read_u64::<LittleEndian>().unwrap(),
+                    reverse_count: reader.read_u64::<LittleEndian>().unwrap(),
+                });
+            }
+
+            let colors_count = reader.read_u32::<LittleEndian>().unwrap();
+            colors.clear();
+            for _ in 0..colors_count {
+                colors.push(reader.read_u32::<LittleEndian>().unwrap());
+            }
+
+            callback(
+                bucket,
+                QueryColoredCounters {
+                    queries: &queries,
+                    colors: &colors,
+                },
+            );
+        }
+    }
+}
+
+/// Writes the colored counters of an input bucket of the colormap reading, with their output bucket
+pub(crate) struct ColoredCountersPartWriter {
+    writer: BufWriter<File>,
+}
+
+impl ColoredCountersPartWriter {
+    pub fn add(&mut self, bucket: BucketIndexType, counters: &QueryColoredCounters) {
+        self.writer.write_u16::<LittleEndian>(bucket).unwrap();
+        self.writer
+            .write_u32::<LittleEndian>(counters.queries.len() as u32)
+            .unwrap();
+        for query in counters.queries {
+            self.writer
+                .write_u64::<LittleEndian>(query.query_index)
+                .unwrap();
+            self.writer.write_u64::<LittleEndian>(query.count).unwrap();
+            self.writer
+                .write_u64::<LittleEndian>(query.reverse_count)
+                .unwrap();
+        }
+        self.writer
+            .write_u32::<LittleEndian>(counters.colors.len() as u32)
+            .unwrap();
+        for color in counters.colors {
+            self.writer.write_u32::<LittleEndian>(*color).unwrap();
+        }
+    }
+}
@@ -1,3 +1,7 @@
+use crate::pipeline::colormap_checkpoint::ColormapReadingCheckpoint;
+use crate::pipeline::counters_external_sort::{
+    counters_sort_max_items, CountersSortBudget, CountersSorter,
+};
 use crate::pipeline::counters_sorting::{CounterEntry, CounterEntrySerializer};
 use crate::structs::query_colored_counters::{
     ColorsRange, QueryColorDesc, QueryColoredCounters, QueryColoredCountersSerializer,
@@ -5,10 +9,11 @@ use crate::structs::query_colored_counters::{
 use colors::storage::deserializer::ColorsDeserializer;
 use colors::storage::ColorsSerializerTrait;
 use config::{
-    get_compression_level_info, get_memory_mode, BucketIndexType, ColorIndexType, SwapPriority,
-    DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
+    get_compression_level_info, get_memory_mode, keep_temp_files, BucketIndexType, ColorIndexType,
+    SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT,
     MINIMIZER_BUCKETS_CHECKPOINT_SIZE, QUERIES_COUNT_MIN_BATCH,
 };
+use io::get_bucket_index;
 use nightly_quirks::prelude::*;
 use nightly_quirks::slice_group_by::SliceGroupBy;
 use parallel_processor::buckets::concurrent::{BucketsThreadBuffer, BucketsThreadDispatcher};
@@ -18,12 +23,45 @@ use parallel_processor::buckets::writers::compressed_binary_writer::CompressedBi
 use parallel_processor::buckets::MultiThreadBuckets;
 use parallel_processor::fast_smart_bucket_sort::{fast_smart_radix_sort, SortKey};
 use parallel_processor::memory_fs::RemoveFileMode;
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use parallel_processor::utils::scoped_thread_local::ScopedThreadLocal;
 use rayon::prelude::*;
 use std::path::PathBuf;
-use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use utils::phase_log::start_phase;
+
+struct CountersCompare;
+impl SortKey<(CounterEntry<ColorIndexType>, ColorIndexType)> for CountersCompare {
+    type KeyType = u32;
+    const KEY_BITS: usize = std::mem::size_of::<u32>() * 8;
+
+    fn compare(
+        left: &(CounterEntry<ColorIndexType>, ColorIndexType),
+        right: &(CounterEntry<ColorIndexType>, ColorIndexType),
+    ) -> std::cmp::Ordering {
+        (left.1, left.0.query_index, left.0.counter).cmp(&(
+            right.1,
+            right.0.query_index,
+            right.0.counter,
+        ))
+    }
+
+    fn get_shifted(value: &(CounterEntry<ColorIndexType>, ColorIndexType), rhs: u8) -> u8 {
+        (value.1 >> rhs) as u8
+    }
+}
+
+/// Sorts the counters by color, then by query index and count, so that the output does not
+/// depend on the order the counters were written by the threads of the previous phase
+pub(crate) fn sort_query_counters(
+    counters_vec: &mut [(CounterEntry<ColorIndexType>, ColorIndexType)],
+) {
+    fast_smart_radix_sort::<_, CountersCompare, false>(counters_vec);
+
+    // The radix sort is keyed only on the color
+    for queries_by_color in counters_vec.nq_group_by_mut(|a, b| a.1 == b.1) {
+        queries_by_color.sort_unstable_by_key(|(entry, _)| (entry.query_index, entry.counter));
+    }
+}
 
 pub fn colormap_reading<CD: ColorsSerializerTrait>(
     colormap_file: PathBuf,
@@ -31,9 +69,7 @@ pub fn colormap_reading<CD: ColorsSerializerTrait>(
     temp_dir: PathBuf,
     queries_count: u64,
 ) -> Vec<PathBuf> {
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase("phase: colormap reading".to_string());
+    start_phase("phase: colormap reading".to_string());
 
     let buckets_count = colored_query_buckets.len();
     let buckets_prefix_path = temp_dir.join("query_colors");
@@ -55,7 +91,24 @@ pub fn colormap_reading<CD: ColorsSerializerTrait>(
     let tlocal_colormap_decoder =
         ScopedThreadLocal::new(move || ColorsDeserializer::<CD>::new(&colormap_file, false));
 
+    // When the temporary files are kept the query can be resumed, then the output of each input bucket
+    // is written to its own part file and checkpointed, and a resumed run skips the completed buckets
+    let checkpoint = keep_temp_files().then(|| ColormapReadingCheckpoint::open(&temp_dir));
+
+    // The sort memory is shared by the buckets read in parallel
+    let counters_sort_budget = CountersSortBudget::new(counters_sort_max_items());
+
     colored_query_buckets.par_iter().for_each(|input| {
+        let input_bucket = get_bucket_index(input);
+        if let Some(checkpoint) = &checkpoint {
+            if checkpoint.is_completed(input_bucket) {
+                return;
+            }
+        }
+        let mut part_writer = checkpoint
+            .as_ref()
+            .map(|checkpoint| checkpoint.part_writer(input_bucket));
+
         let mut colormap_decoder = tlocal_colormap_decoder.get();
         let mut temp_colors_buffer = Vec::new();
         let mut temp_queries_buffer = Vec::new();
@@ -68,11 +121,12 @@ pub fn colormap_reading<CD: ColorsSerializerTrait>(
                 thread_buffer.take(),
             );
 
-        let mut counters_vec: Vec<(CounterEntry<ColorIndexType>, ColorIndexType)> = Vec::new();
+        // The buckets that do not fit in the sort memory are sorted in runs spilled to the temp dir
+        let mut counters_sorter = CountersSorter::new(temp_dir.clone(), &counters_sort_budget);
         CompressedBinaryReader::new(
             input,
             RemoveFileMode::Remove {
-                remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                remove_fs: !keep_temp_files(),
             },
             DEFAULT_PREFETCH_AMOUNT,
         )
@@ -80,35 +134,19 @@ pub fn colormap_reading<CD: ColorsSerializerTrait>(
             (),
             &mut (),
             |h, _| {
-                counters_vec.push(h);
+                counters_sorter.push(h);
             },
         );
 
-        struct CountersCompare;
-        impl SortKey<(CounterEntry<ColorIndexType>, ColorIndexType)> for CountersCompare {
-            type KeyType = u32;
-            const KEY_BITS: usize = std::mem::size_of::<u32>() * 8;
-
-            fn compare(
-                left: &(CounterEntry<ColorIndexType>, ColorIndexType),
-                right: &(CounterEntry<ColorIndexType>, ColorIndexType),
-            ) -> std::cmp::Ordering {
-                left.1.cmp(&right.1)
-            }
-
-            fn get_shifted(value: &(CounterEntry<ColorIndexType>, ColorIndexType), rhs: u8) -> u8 {
-                (value.1 >> rhs) as u8
-            }
-        }
-
-        fast_smart_radix_sort::<_, CountersCompare, false>(&mut counters_vec[..]);
-
-        for queries_by_color in counters_vec.nq_group_by_mut(|a, b| a.1 == b.1) {
+        // The counters of a large color may be streamed in consecutive groups
+        let mut last_color = None;
+        counters_sorter.for_each_color_group(|queries_by_color| {
             let color = queries_by_color[0].1;
-            temp_colors_buffer.clear();
-            colormap_decoder.get_color_mappings(color, &mut temp_colors_buffer);
+            if last_color != Some(color) {
+                last_color = Some(color);
+                temp_colors_buffer.clear();
+                colormap_decoder.get_color_mappings(color, &mut temp_colors_buffer);
 
-            {
                 temp_encoded_buffer.clear();
                 let mut range_start = ColorIndexType::MAX;
                 let mut range_end = ColorIndexType::MAX;
@@ -132,9 +170,8 @@ pub fn colormap_reading<CD: ColorsSerializerTrait>(
                 temp_queries_buffer.extend(queries_by_color.iter().map(|q| QueryColorDesc {
                     query_index: q.0.query_index,
                     count: q.0.counter,
+                    reverse_count: q.0.reverse_counter,
                 }));
-
-                temp_queries_buffer.sort_unstable_by_key(|c| c.query_index);
             }
 
             // println!(
@@ -156,18 +193,108 @@ pub fn colormap_reading<CD: ColorsSerializerTrait>(
                 get_query_bucket(a.query_index) == get_query_bucket(b.query_index)
             }) {
                 let bucket = get_query_bucket(entries[0].query_index);
-                colored_buckets_writer.add_element(
-                    bucket,
-                    &(),
-                    &QueryColoredCounters {
-                        queries: entries,
-                        colors: &temp_encoded_buffer,
-                    },
-                );
+                let counters = QueryColoredCounters {
+                    queries: entries,
+                    colors: &temp_encoded_buffer,
+                };
+                match &mut part_writer {
+                    Some(part_writer) => part_writer.add(bucket, &counters),
+                    None => colored_buckets_writer.add_element(bucket, &(), &counters),
+                }
             }
-        }
+        });
         thread_buffer.put_back(colored_buckets_writer.finalize().0);
+
+        if let (Some(checkpoint), Some(part_writer)) = (&checkpoint, part_writer) {
+            checkpoint.complete(input_bucket, part_writer);
+        }
     });
 
+    if let Some(checkpoint) = &checkpoint {
+        // All the input buckets are completed, their parts are dispatched to the output buckets
+        colored_query_buckets.par_iter().for_each(|input| {
+            let mut thread_buffer = thread_buffers.get();
+            let mut colored_buckets_writer =
+                BucketsThreadDispatcher::<_, QueryColoredCountersSerializer>::new(
+                    &correct_color_buckets,
+                    thread_buffer.take(),
+                );
+            checkpoint.read_part(get_bucket_index(input), |bucket, counters| {
+                colored_buckets_writer.add_element(bucket, &(), &counters);
+            });
+            thread_buffer.put_back(colored_buckets_writer.finalize().0);
+        });
+    }
+
     correct_color_buckets.finalize()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::sort_query_counters;
+    use crate::pipeline::counters_sorting::CounterEntry;
+    use config::ColorIndexType;
+    use std::marker::PhantomData;
+
+    fn counters(
+        entries: &[(ColorIndexType, u64, u64)],
+    ) -> Vec<(CounterEntry<ColorIndexType>, ColorIndexType)> {
+        entries
+            .iter()
+            .map(|&(color, query_index, counter)| {
+                (
+                    CounterEntry {
+                        query_index,
+                        counter,
+                        reverse_counter: 0,
+                        _phantom: PhantomData,
+                    },
+                    color,
+                )
+            })
+            .collect()
+    }
+
+    fn keys(
+        counters: &[(CounterEntry<ColorIndexType>, ColorIndexType)],
+    ) -> Vec<(ColorIndexType, u64, u64)> {
+        counters
+            .iter()
+            .map(|(entry, color)| (*color, entry.query_index, entry.counter))
+            .collect()
+    }
+
+    #[test]
+    fn counters_are_sorted_in_total_order() {
+        // Duplicate (color, query) entries with different counts, written in different orders
+        let entries = [
+            (3, 7, 2),
+            (1, 5, 4),
+            (3, 2, 9),
+            (1, 5, 1),
+            (3, 7, 1),
+            (1, 5, 4),
+            (0, 9, 3),
+            (3, 2, 9),
+            (1, 1, 8),
+        ];
+
+        let mut expected = entries.to_vec();
+        expected.sort_unstable();
+
+        let mut state: u64 = 0x853c_49e6_748f_ea9b;
+        for _ in 0..100 {
+            let mut shuffled = entries.to_vec();
+            for i in (1..shuffled.len()).rev() {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                shuffled.swap(i, (state % (i as u64 + 1)) as usize);
+            }
+
+            let mut sorted = counters(&shuffled);
+            sort_query_counters(&mut sorted);
+            assert_eq!(keys(&sorted), expected);
+        }
+    }
+}
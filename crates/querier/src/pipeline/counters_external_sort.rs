@@ -0,0 +1,392 @@
+use crate::pipeline::colormap_reading::sort_query_counters;
+use crate::pipeline::counters_sorting::{CounterEntry, CounterEntrySerializer};
+use config::{
+    get_compression_level_info, get_memory_mode, ColorIndexType, SwapPriority,
+    DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, PARTIAL_VECS_CHECKPOINT_SIZE,
+};
+use io::bucket_items::iter_bucket_items;
+use nightly_quirks::slice_group_by::SliceGroupBy;
+use parallel_processor::buckets::bucket_writer::BucketItemSerializer;
+use parallel_processor::buckets::readers::compressed_binary_reader::CompressedBinaryReader;
+use parallel_processor::buckets::writers::compressed_binary_writer::CompressedBinaryWriter;
+use parallel_processor::buckets::LockFreeBucket;
+use parallel_processor::memory_fs::RemoveFileMode;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub(crate) type ColorCounter = (CounterEntry<ColorIndexType>, ColorIndexType);
+
+/// 1GB for the counters of all the buckets sorted in memory
+pub const DEFAULT_COUNTERS_SORT_MEMORY: usize = 1024 * 1024 * 1024;
+
+static COUNTERS_SORT_MEMORY: AtomicUsize = AtomicUsize::new(DEFAULT_COUNTERS_SORT_MEMORY);
+
+/// Sets the maximum memory (in bytes) shared by the buckets whose query counters are sorted by color
+/// when reading the colormap. The buckets that do not fit are sorted in runs spilled to temporary files
+/// and then merged, producing the same order. With None the default of 1GB is used
+pub fn set_counters_sort_memory(max_memory: Option<usize>) {
+    COUNTERS_SORT_MEMORY.store(
+        max_memory.unwrap_or(DEFAULT_COUNTERS_SORT_MEMORY),
+        Ordering::Relaxed,
+    );
+}
+
+/// The maximum number of counters sorted in memory
+pub(crate) fn counters_sort_max_items() -> usize {
+    (COUNTERS_SORT_MEMORY.load(Ordering::Relaxed) / std::mem::size_of::<ColorCounter>()).max(1)
+}
+
+// The budget is reserved by the sorters in chunks of this fraction of the total
+const BUDGET_CHUNKS: usize = 64;
+
+// The runs merged at once, the runs exceeding it are first merged into larger runs
+const MAX_MERGED_RUNS: usize = 32;
+
+/// The counters memory shared by the buckets sorted in parallel. Each sorter reserves it in chunks
+/// while its counters grow, and spills them to a run when no more memory is available. The first
+/// chunk of a sorter is always reserved, so the budget is exceeded by at most one chunk per thread
+pub(crate) struct CountersSortBudget {
+    max_items: usize,
+    reserved_items: AtomicUsize,
+}
+
+impl CountersSortBudget {
+    pub fn new(max_items: usize) -> Self {
+        Self {
+            max_items: max_items.max(1),
+            reserved_items: AtomicUsize::new(0),
+        }
+    }
+
+    fn chunk_items(&self) -> usize {
+        (self.max_items / BUDGET_CHUNKS).max(1)
+    }
+
+    // Returns the reserved counters, 0 if the budget is exhausted and the reservation is not forced
+    fn reserve(&self, force: bool) -> usize {
+        let chunk = self.chunk_items();
+        if force {
+            self.reserved_items.fetch_add(chunk, Ordering::Relaxed);
+            return chunk;
+        }
+        match self
+            .reserved_items
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |reserved| {
+                (reserved + chunk <= self.max_items).then_some(reserved + chunk)
+            }) {
+            Ok(_) => chunk,
+            Err(_) => 0,
+        }
+    }
+
+    fn release(&self, items: usize) {
+        self.reserved_items.fetch_sub(items, Ordering::Relaxed);
+    }
+}
+
+// The order of the sorted counters, the reverse counter only breaks the ties of the merge
+type CounterKey = (ColorIndexType, u64, u64, u64);
+
+fn counter_key(counter: &ColorCounter) -> CounterKey {
+    (
+        counter.1,
+        counter.0.query_index,
+        counter.0.counter,
+        counter.0.reverse_counter,
+    )
+}
+
+// Writes a sorted run to a temporary bucket file
+struct RunWriter {
+    writer: CompressedBinaryWriter,
+    serializer: CounterEntrySerializer<ColorIndexType>,
+    buffer: Vec<u8>,
+}
+
+impl RunWriter {
+    fn new(temp_dir: &Path) -> Self {
+        static COUNTERS_SORT_RUN_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+        Self {
+            writer: CompressedBinaryWriter::new(
+                temp_dir.join("counters-sort-run").as_path(),
+                &(
+                    get_memory_mode(SwapPriority::QueryCounters),
+                    PARTIAL_VECS_CHECKPOINT_SIZE,
+                    get_compression_level_info(),
+                ),
+                COUNTERS_SORT_RUN_INDEX.fetch_add(1, Ordering::Relaxed),
+            ),
+            serializer: CounterEntrySerializer::new(),
+            buffer: Vec::with_capacity(DEFAULT_PER_CPU_BUFFER_SIZE.as_bytes()),
+        }
+    }
+
+    fn push(&mut self, (entry, color): ColorCounter) {
+        self.serializer
+            .write_to(&entry, &mut self.buffer, &color, &());
+        if self.buffer.len() >= DEFAULT_PER_CPU_BUFFER_SIZE.as_bytes() {
+            self.writer.write_data(&self.buffer);
+            self.buffer.clear();
+        }
+    }
+
+    fn finalize(self) -> PathBuf {
+        self.writer.write_data(&self.buffer);
+        let path = self.writer.get_path();
+        self.writer.finalize();
+        path
+    }
+}
+
+// Merges the sorted runs, removing them once read
+fn merge_runs(runs: &[PathBuf], mut output: impl FnMut(ColorCounter)) {
+    let mut readers: Vec<_> = runs
+        .iter()
+        .map(|run| {
+            CompressedBinaryReader::new(
+                run,
+                RemoveFileMode::Remove { remove_fs: true },
+                DEFAULT_PREFETCH_AMOUNT,
+            )
+        })
+        .collect();
+    let mut runs_counters: Vec<_> = readers
+        .iter_mut()
+        .map(|reader| iter_bucket_items::<CounterEntrySerializer<ColorIndexType>, _>(reader))
+        .collect();
+
+    let mut heap = BinaryHeap::new();
+    let mut current = vec![];
+    for (run, counters) in runs_counters.iter_mut().enumerate() {
+        let counter = counters.next();
+        if let Some(counter) = &counter {
+            heap.push(Reverse((counter_key(counter), run)));
+        }
+        current.push(counter);
+    }
+
+    while let Some(Reverse((_, run))) = heap.pop() {
+        let counter = current[run].take().unwrap();
+        if let Some(next) = runs_counters[run].next() {
+            heap.push(Reverse((counter_key(&next), run)));
+            current[run] = Some(next);
+        }
+        output(counter);
+    }
+}
+
+/// Sorts the query counters of a bucket by color, then by query index and count. The counters are
+/// sorted in memory while they fit in the shared budget, otherwise they are sorted in runs written to
+/// temporary bucket files, that are merged while the color groups are visited
+pub(crate) struct CountersSorter<'a> {
+    temp_dir: PathBuf,
+    budget: &'a CountersSortBudget,
+    reserved_items: usize,
+    runs: Vec<PathBuf>,
+    buffer: Vec<ColorCounter>,
+}
+
+impl<'a> CountersSorter<'a> {
+    pub fn new(temp_dir: PathBuf, budget: &'a CountersSortBudget) -> Self {
+        Self {
+            temp_dir,
+            budget,
+            reserved_items: 0,
+            runs: vec![],
+            buffer: vec![],
+        }
+    }
+
+    pub fn push(&mut self, counter: ColorCounter) {
+        if self.buffer.len() >= self.reserved_items {
+            let reserved = self.budget.reserve(self.reserved_items == 0);
+            if reserved > 0 {
+                self.reserved_items += reserved;
+                self.buffer.reserve_exact(reserved);
+            } else {
+                self.spill();
+            }
+        }
+        self.buffer.push(counter);
+    }
+
+    fn spill(&mut self) {
+        sort_query_counters(&mut self.buffer);
+        let mut run_writer = RunWriter::new(&self.temp_dir);
+        for counter in self.buffer.drain(..) {
+            run_writer.push(counter);
+        }
+        self.runs.push(run_writer.finalize());
+    }
+
+    /// Calls the callback with the counters of each color, in sorted order. The counters of a color
+    /// that exceed the reserved memory are passed in consecutive calls, each with at most the reserved
+    /// counters, so that the spilled buckets are streamed without loading a whole color
+    pub fn for_each_color_group(mut self, mut callback: impl FnMut(&mut [ColorCounter])) {
+        if self.runs.is_empty() {
+            sort_query_counters(&mut self.buffer);
+            for queries_by_color in self.buffer.nq_group_by_mut(|a, b| a.1 == b.1) {
+                callback(queries_by_color);
+            }
+            return;
+        }
+
+        if !self.buffer.is_empty() {
+            self.spill();
+        }
+
+        // Merges the runs in passes of at most MAX_MERGED_RUNS, to bound the open runs
+        let mut runs = std::mem::take(&mut self.runs);
+        while runs.len() > MAX_MERGED_RUNS {
+            let merged: Vec<_> = runs.drain(..MAX_MERGED_RUNS).collect();
+            let mut run_writer = RunWriter::new(&self.temp_dir);
+            merge_runs(&merged, |counter| run_writer.push(counter));
+            runs.push(run_writer.finalize());
+        }
+
+        // The group buffer reuses the reserved memory of the sorter
+        let max_group_items = self.reserved_items.max(1);
+        let mut group = std::mem::take(&mut self.buffer);
+        merge_runs(&runs, |counter| {
+            if matches!(group.last(), Some(last) if last.1 != counter.1)
+                || group.len() >= max_group_items
+            {
+                callback(&mut group);
+                group.clear();
+            }
+            group.push(counter);
+        });
+        if !group.is_empty() {
+            callback(&mut group);
+        }
+    }
+}
+
+impl Drop for CountersSorter<'_> {
+    fn drop(&mut self) {
+        self.budget.release(self.reserved_items);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorCounter, CountersSortBudget, CountersSorter, MAX_MERGED_RUNS};
+    use crate::pipeline::colormap_reading::sort_query_counters;
+    use crate::pipeline::counters_sorting::CounterEntry;
+    use config::ColorIndexType;
+    use std::marker::PhantomData;
+    use std::path::Path;
+    use utils::test_dir::TestDir;
+
+    fn random_counters(mut state: u64, count: usize) -> Vec<ColorCounter> {
+        (0..count)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (
+                    CounterEntry {
+                        query_index: (state >> 8) % 50,
+                        counter: (state >> 24) % 10,
+                        reverse_counter: 0,
+                        _phantom: PhantomData,
+                    },
+                    (state >> 40) as ColorIndexType % 20,
+                )
+            })
+            .collect()
+    }
+
+    fn keys(counters: &[ColorCounter]) -> Vec<(ColorIndexType, u64, u64)> {
+        counters
+            .iter()
+            .map(|(entry, color)| (*color, entry.query_index, entry.counter))
+            .collect()
+    }
+
+    // The sorted keys, with the size of the largest group passed to the callback
+    fn sorted_keys(sorter: CountersSorter) -> (Vec<(ColorIndexType, u64, u64)>, usize) {
+        let mut sorted = vec![];
+        let mut max_group_items = 0;
+        sorter.for_each_color_group(|queries_by_color| {
+            assert!(queries_by_color
+                .iter()
+                .all(|(_, color)| *color == queries_by_color[0].1));
+            max_group_items = max_group_items.max(queries_by_color.len());
+            sorted.extend(keys(queries_by_color));
+        });
+        (sorted, max_group_items)
+    }
+
+    fn is_empty_dir(dir: &Path) -> bool {
+        std::fs::read_dir(dir).unwrap().next().is_none()
+    }
+
+    #[test]
+    fn external_sort_matches_the_in_memory_sort() {
+        let counters = random_counters(0x2545_f491_4f6c_dd1d, 1000);
+        let mut expected = counters.clone();
+        sort_query_counters(&mut expected);
+        let expected = keys(&expected);
+
+        let test_dir = TestDir::new("external_sort_matches_the_in_memory_sort");
+        let temp_dir = test_dir.path("sort");
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        for max_items in [7, 1000, 5000] {
+            let budget = CountersSortBudget::new(max_items);
+            // The budget is reserved in whole chunks
+            let capacity = max_items / budget.chunk_items() * budget.chunk_items();
+            let mut sorter = CountersSorter::new(temp_dir.clone(), &budget);
+            for counter in counters.iter() {
+                sorter.push(counter.clone());
+            }
+            let spilled_runs = sorter.runs.len();
+            assert_eq!(spilled_runs, (counters.len() - 1) / capacity);
+            if max_items == 7 {
+                assert!(spilled_runs > MAX_MERGED_RUNS);
+            }
+
+            let (sorted, max_group_items) = sorted_keys(sorter);
+            assert_eq!(sorted, expected);
+            // The spilled colors are streamed in groups of at most the reserved counters
+            if spilled_runs > 0 {
+                assert!(max_group_items <= capacity);
+            }
+            assert_eq!(budget.reserved_items.into_inner(), 0);
+            assert!(is_empty_dir(&temp_dir));
+        }
+    }
+
+    #[test]
+    fn sorters_share_the_budget() {
+        let counters = random_counters(0x9e37_79b9_7f4a_7c15, 1000);
+        let mut expected = counters.clone();
+        sort_query_counters(&mut expected);
+        let expected = keys(&expected);
+
+        let test_dir = TestDir::new("sorters_share_the_budget");
+        let temp_dir = test_dir.path("sort");
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        // The first sorter holds the whole budget, the second one spills after its first chunk
+        let budget = CountersSortBudget::new(640);
+        let mut first_sorter = CountersSorter::new(temp_dir.clone(), &budget);
+        let mut second_sorter = CountersSorter::new(temp_dir.clone(), &budget);
+        for counter in counters.iter().take(640) {
+            first_sorter.push(counter.clone());
+        }
+        for counter in counters.iter() {
+            second_sorter.push(counter.clone());
+        }
+        assert!(first_sorter.runs.is_empty());
+        assert_eq!(second_sorter.runs.len(), (counters.len() - 1) / 10);
+
+        assert_eq!(sorted_keys(second_sorter).0, expected);
+        drop(first_sorter);
+        assert_eq!(budget.reserved_items.into_inner(), 0);
+    }
+}
@@ -1,9 +1,10 @@
+use crate::matched_strand::{is_matched_strand_report, matched_strand};
 use byteorder::ReadBytesExt;
 use colors::colors_manager::color_types::SingleKmerColorDataType;
 use colors::colors_manager::ColorsManager;
 use config::{
-    get_compression_level_info, get_memory_mode, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
-    DEFAULT_PREFETCH_AMOUNT, KEEP_FILES, MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
+    get_compression_level_info, get_memory_mode, keep_temp_files, SwapPriority,
+    DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
 };
 use io::concurrent::temp_reads::extra_data::{
     SequenceExtraDataConsecutiveCompression, SequenceExtraDataOwned,
@@ -18,7 +19,6 @@ use parallel_processor::buckets::writers::compressed_binary_writer::CompressedBi
 use parallel_processor::buckets::MultiThreadBuckets;
 use parallel_processor::fast_smart_bucket_sort::{fast_smart_radix_sort, SortKey};
 use parallel_processor::memory_fs::RemoveFileMode;
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use parallel_processor::utils::scoped_thread_local::ScopedThreadLocal;
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
@@ -27,11 +27,14 @@ use std::marker::PhantomData;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use utils::phase_log::start_phase;
 
 #[derive(Debug, Clone)]
 pub struct CounterEntry<CX: SequenceExtraDataConsecutiveCompression<TempBuffer = ()>> {
     pub query_index: u64,
     pub counter: u64,
+    /// The matched kmers found on the reverse strand of the graph
+    pub reverse_counter: u64,
     pub _phantom: PhantomData<CX>,
 }
 
@@ -68,6 +71,7 @@ impl<CX: SequenceExtraDataConsecutiveCompression<TempBuffer = ()>> BucketItemSer
     ) {
         encode_varint(|b| bucket.extend_from_slice(b), element.query_index);
         encode_varint(|b| bucket.extend_from_slice(b), element.counter);
+        encode_varint(|b| bucket.extend_from_slice(b), element.reverse_counter);
         extra_data.encode(bucket, self.0);
         self.0 = extra_data.obtain_last_data(self.0);
     }
@@ -80,12 +84,14 @@ impl<CX: SequenceExtraDataConsecutiveCompression<TempBuffer = ()>> BucketItemSer
     ) -> Option<Self::ReadType<'a>> {
         let query_index = decode_varint(|| stream.read_u8().ok())?;
         let counter = decode_varint(|| stream.read_u8().ok())?;
+        let reverse_counter = decode_varint(|| stream.read_u8().ok())?;
         let color = CX::decode(&mut stream, self.0)?;
         self.0 = color.obtain_last_data(self.0);
         Some((
             CounterEntry {
                 query_index,
                 counter,
+                reverse_counter,
                 _phantom: PhantomData,
             },
             color,
@@ -94,7 +100,7 @@ impl<CX: SequenceExtraDataConsecutiveCompression<TempBuffer = ()>> BucketItemSer
 
     #[inline(always)]
     fn get_size(&self, _: &Self::InputElementType<'_>, data: &Self::ExtraData) -> usize {
-        VARINT_MAX_SIZE * 2 + data.max_size()
+        VARINT_MAX_SIZE * 3 + data.max_size()
     }
 }
 
@@ -106,9 +112,7 @@ pub fn counters_sorting<CX: ColorsManager>(
     output_file: PathBuf,
     query_kmers_count: &[u64],
 ) -> Vec<PathBuf> {
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase("phase: counters sorting".to_string());
+    start_phase("phase: counters sorting".to_string());
 
     let buckets_count = file_counters_inputs.len();
 
@@ -116,7 +120,8 @@ pub fn counters_sorting<CX: ColorsManager>(
         vec![]
     } else {
         let mut counters = Vec::with_capacity(query_kmers_count.len());
-        counters.extend((0..query_kmers_count.len()).map(|_| AtomicU64::new(0)));
+        counters
+            .extend((0..query_kmers_count.len()).map(|_| (AtomicU64::new(0), AtomicU64::new(0))));
         counters
     };
 
@@ -158,7 +163,7 @@ pub fn counters_sorting<CX: ColorsManager>(
         LockFreeBinaryReader::new(
             input,
             RemoveFileMode::Remove {
-                remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                remove_fs: !keep_temp_files(),
             },
             DEFAULT_PREFETCH_AMOUNT,
         )
@@ -205,15 +210,21 @@ pub fn counters_sorting<CX: ColorsManager>(
                         &CounterEntry {
                             query_index,
                             counter: entry.iter().map(|e| e.0.counter).sum(),
+                            reverse_counter: entry.iter().map(|e| e.0.reverse_counter).sum(),
                             _phantom: PhantomData,
                         },
                     );
                 }
             } else {
-                final_counters[query_index as usize - 1].store(
+                let (counter, reverse_counter) = &final_counters[query_index as usize - 1];
+                counter.store(
                     query_results.iter().map(|e| e.0.counter).sum(),
                     Ordering::Relaxed,
                 );
+                reverse_counter.store(
+                    query_results.iter().map(|e| e.0.reverse_counter).sum(),
+                    Ordering::Relaxed,
+                );
             }
         }
 
@@ -227,32 +238,38 @@ pub fn counters_sorting<CX: ColorsManager>(
             output_file
         };
 
+        let with_strand = is_matched_strand_report();
+
         let mut writer = csv::Writer::from_path(output_file).unwrap();
-        writer
-            .write_record(&[
-                "query_index",
-                "matched_kmers",
-                "query_kmers",
-                "match_percentage",
-            ])
-            .unwrap();
+        let mut header = vec![
+            "query_index",
+            "matched_kmers",
+            "query_kmers",
+            "match_percentage",
+        ];
+        if with_strand {
+            header.push("strand");
+        }
+        writer.write_record(&header).unwrap();
 
-        for (query_index, (info, counter)) in query_kmers_count
+        for (query_index, (info, (counter, reverse_counter))) in query_kmers_count
             .iter()
             .zip(final_counters.iter())
             .enumerate()
         {
-            writer
-                .write_record(&[
-                    query_index.to_string(),
-                    counter.load(Ordering::Relaxed).to_string(),
-                    info.to_string(),
-                    format!(
-                        "{:.2}",
-                        (counter.load(Ordering::Relaxed) as f64 / *info as f64)
-                    ),
-                ])
-                .unwrap();
+            let counter = counter.load(Ordering::Relaxed);
+            let mut record = vec![
+                query_index.to_string(),
+                counter.to_string(),
+                info.to_string(),
+                format!("{:.2}", (counter as f64 / *info as f64)),
+            ];
+            if with_strand {
+                record.push(
+                    matched_strand(counter, reverse_counter.load(Ordering::Relaxed)).to_string(),
+                );
+            }
+            writer.write_record(&record).unwrap();
         }
         vec![]
     } else {
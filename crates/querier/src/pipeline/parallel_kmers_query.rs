@@ -8,13 +8,13 @@ use colors::colors_manager::color_types::{
 };
 use colors::colors_manager::{ColorsManager, MinimizerBucketingSeqColorData};
 use config::{
-    get_memory_mode, BucketIndexType, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
+    get_memory_mode, BucketIndexType, PipelineSettings, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
     MINIMUM_SUBBUCKET_KMERS_COUNT, RESPLITTING_MAX_K_M_DIFFERENCE,
 };
 use hashbrown::HashMap;
 use hashes::HashFunction;
 use hashes::HashFunctionFactory;
-use hashes::{ExtendableHashTraitType, MinimizerHashFunctionFactory};
+use hashes::{ExtendableHashTraitType, MinimizerHashFunctionFactory, Strand};
 use io::compressed_read::CompressedRead;
 use io::compressed_read::CompressedReadIndipendent;
 use io::concurrent::temp_reads::extra_data::{
@@ -33,7 +33,6 @@ use parallel_processor::buckets::MultiThreadBuckets;
 use parallel_processor::execution_manager::memory_tracker::MemoryTracker;
 use parallel_processor::execution_manager::objects_pool::PoolObjectTrait;
 use parallel_processor::execution_manager::packet::{Packet, PacketTrait};
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use std::cmp::min;
 use std::io::{Read, Write};
 use std::marker::PhantomData;
@@ -42,6 +41,7 @@ use std::num::NonZeroU64;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use utils::phase_log::start_phase;
 
 use super::counters_sorting::CounterEntrySerializer;
 
@@ -243,8 +243,8 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
 }
 
 struct ParallelKmersQueryMapPacket<MH: HashFunctionFactory, CX: Sync + Send + 'static> {
-    phmap: HashMap<MH::HashTypeUnextendable, CX>,
-    query_reads: Vec<(u64, MH::HashTypeUnextendable)>,
+    phmap: HashMap<MH::HashTypeUnextendable, (CX, Strand)>,
+    query_reads: Vec<(u64, MH::HashTypeUnextendable, Strand)>,
 }
 
 impl<MH: HashFunctionFactory, CX: Sync + Send + 'static> PoolObjectTrait
@@ -324,14 +324,14 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                         .iter()
                         .zip(col_info.get_iterator(&extra_data_buffer.0))
                     {
-                        map_packet.phmap.insert(hash.to_unextendable(), color);
+                        let (hash, strand) = hash.to_unextendable_with_strand();
+                        map_packet.phmap.insert(hash, (color, strand));
                     }
                 }
                 QueryKmersReferenceData::Query(index) => {
                     for hash in hashes.iter() {
-                        map_packet
-                            .query_reads
-                            .push((index.get(), hash.to_unextendable()));
+                        let (hash, strand) = hash.to_unextendable_with_strand();
+                        map_packet.query_reads.push((index.get(), hash, strand));
                     }
                 }
             }
@@ -360,7 +360,8 @@ struct ParallelKmersQueryFinalExecutor<
         LockFreeBinaryWriter,
         CounterEntrySerializer<SingleKmerColorDataType<CX>>,
     >,
-    query_map: HashMap<(u64, SingleKmerColorDataType<CX>), u64>,
+    // The matched kmers of each (query, color) pair, and how many of them are on the reverse strand
+    query_map: HashMap<(u64, SingleKmerColorDataType<CX>), (u64, u64)>,
     _phantom: PhantomData<(H, MH, CX)>,
 }
 
@@ -377,22 +378,28 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
     ) -> Packet<ParallelKmersQueryMapPacket<MH, SingleKmerColorDataType<CX>>> {
         let map_struct_ref = map_struct.deref();
 
-        for (query_index, kmer_hash) in &map_struct_ref.query_reads {
-            if let Some(entry_color) = map_struct_ref.phmap.get(kmer_hash) {
-                *self
+        for (query_index, kmer_hash, query_strand) in &map_struct_ref.query_reads {
+            if let Some((entry_color, entry_strand)) = map_struct_ref.phmap.get(kmer_hash) {
+                let counters = self
                     .query_map
                     .entry((*query_index, entry_color.clone()))
-                    .or_insert(0) += 1;
+                    .or_insert((0, 0));
+                counters.0 += 1;
+                // The query kmer matches the reverse strand of the graph kmer
+                if query_strand != entry_strand {
+                    counters.1 += 1;
+                }
             }
         }
 
-        for ((query_index, color_index), counter) in self.query_map.drain() {
+        for ((query_index, color_index), (counter, reverse_counter)) in self.query_map.drain() {
             self.counters_tmp.add_element(
                 (query_index % 0xFF) as BucketIndexType,
                 &color_index,
                 &CounterEntry {
                     query_index,
                     counter,
+                    reverse_counter,
                     _phantom: PhantomData,
                 },
             )
@@ -419,10 +426,9 @@ pub fn parallel_kmers_counting<
     k: usize,
     m: usize,
     threads_count: usize,
+    settings: PipelineSettings,
 ) -> Vec<PathBuf> {
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase("phase: kmers counting".to_string());
+    start_phase("phase: kmers counting".to_string());
 
     let counters_buckets = Arc::new(MultiThreadBuckets::<LockFreeBinaryWriter>::new(
         buckets_count,
@@ -450,6 +456,7 @@ pub fn parallel_kmers_counting<
             QuerierMinimizerBucketingGlobalData {
                 queries_count: Default::default(),
             },
+            settings,
         )),
     });
 
@@ -460,6 +467,7 @@ pub fn parallel_kmers_counting<
         buckets_count,
         global_data.clone(),
         threads_count,
+        settings,
         k,
         MINIMUM_SUBBUCKET_KMERS_COUNT as u64,
     )
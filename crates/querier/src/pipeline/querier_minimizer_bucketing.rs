@@ -3,7 +3,8 @@ use byteorder::ReadBytesExt;
 use colors::colors_manager::color_types::MinimizerBucketingSeqColorDataType;
 use colors::colors_manager::{ColorsManager, MinimizerBucketingSeqColorData};
 use colors::parsers::{SequenceIdent, SingleSequenceInfo};
-use config::BucketIndexType;
+use config::{BucketIndexType, PipelineSettings};
+use hashes::minimizer_ordering::{get_minimizer_ordering, MinimizerOrdering};
 use hashes::rolling::minqueue::RollingMinQueue;
 use hashes::ExtendableHashTraitType;
 use hashes::HashFunction;
@@ -15,11 +16,11 @@ use io::sequences_reader::{DnaSequence, DnaSequencesFileType};
 use io::sequences_stream::fasta::FastaFileSequencesStream;
 use io::sequences_stream::SequenceInfo;
 use io::varint::{decode_varint, encode_varint, VARINT_MAX_SIZE};
+use minimizer_bucketing::minimizers_sampling::sample_minimizers_frequencies;
 use minimizer_bucketing::{
     GenericMinimizerBucketing, MinimizerBucketingCommonData, MinimizerBucketingExecutor,
     MinimizerBucketingExecutorFactory, MinimizerInputSequence,
 };
-use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use std::io::{Read, Write};
 use std::marker::PhantomData;
 use std::num::NonZeroU64;
@@ -27,6 +28,7 @@ use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use utils::phase_log::{log_warning, start_phase};
 
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub struct KmersQueryData(pub u64);
@@ -165,20 +167,23 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
                 if CX::COLORS_ENABLED
                     && (color.debug_count() != sequence.seq.len() - self.global_data.k + 1)
                 {
-                    println!(
+                    log_warning(format!(
                         "WARN: Sequence does not have enough colors, please check matching k size:\n{}\n{}",
                         std::str::from_utf8(sequence.ident_data).unwrap(),
                         std::str::from_utf8(sequence.seq).unwrap()
-                    );
+                    ));
                 }
 
                 ReadType::Graph { color }
             }
             FileType::Query => {
-                self.global_data
-                    .global_data
-                    .queries_count
-                    .fetch_add(1, Ordering::Relaxed);
+                // The queries are read again in each wave of buckets
+                if self.global_data.current_wave.load(Ordering::Relaxed) == 0 {
+                    self.global_data
+                        .global_data
+                        .queries_count
+                        .fetch_add(1, Ordering::Relaxed);
+                }
                 ReadType::Query(NonZeroU64::new(read_index + 1).unwrap())
             }
         }
@@ -274,16 +279,22 @@ pub fn minimizer_bucketing<H: MinimizerHashFunctionFactory, CX: ColorsManager>(
     threads_count: usize,
     k: usize,
     m: usize,
+    settings: PipelineSettings,
 ) -> ((Vec<PathBuf>, PathBuf), u64) {
-    PHASES_TIMES_MONITOR
-        .write()
-        .start_phase("phase: graph + query bucketing".to_string());
-
     let input_files = vec![
         ((graph_file, None), FileType::Graph),
         ((query_file, None), FileType::Query),
     ];
 
+    if get_minimizer_ordering() == MinimizerOrdering::FrequencyAware {
+        sample_minimizers_frequencies::<H, FastaFileSequencesStream>(
+            input_files.iter().map(|(block, _)| block),
+            m,
+        );
+    }
+
+    start_phase("phase: graph + query bucketing".to_string());
+
     let queries_count = Arc::new(AtomicUsize::new(0));
 
     (
@@ -303,6 +314,7 @@ pub fn minimizer_bucketing<H: MinimizerHashFunctionFactory, CX: ColorsManager>(
             None,
             CX::COLORS_ENABLED,
             0,
+            settings,
         ),
         queries_count.load(Ordering::Relaxed) as u64,
     )
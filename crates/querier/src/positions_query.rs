@@ -0,0 +1,208 @@
+use crate::unitigs_index::{UnitigPosition, UnitigsIndex};
+use io::sequences_reader::SequencesReader;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// A run of consecutive query kmers matching consecutive kmers of the same unitig.
+/// All the ranges are in bases and end-exclusive
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AlignmentInterval {
+    pub unitig_id: u64,
+    pub query_start: usize,
+    pub query_end: usize,
+    pub unitig_start: usize,
+    pub unitig_end: usize,
+    /// True if the query matches the forward strand of the unitig
+    pub forward: bool,
+}
+
+struct PendingInterval {
+    first_kmer: usize,
+    last_kmer: usize,
+    first_position: UnitigPosition,
+    last_position: UnitigPosition,
+}
+
+impl PendingInterval {
+    fn extends_with(&self, kmer_index: usize, position: &UnitigPosition) -> bool {
+        let last = &self.last_position;
+        let expected_offset = if last.forward {
+            last.offset as i64 + 1
+        } else {
+            last.offset as i64 - 1
+        };
+
+        kmer_index == self.last_kmer + 1
+            && position.unitig_id == last.unitig_id
+            && position.forward == last.forward
+            && position.offset as i64 == expected_offset
+    }
+
+    fn to_interval(&self, k: usize) -> AlignmentInterval {
+        let (first_offset, last_offset) = if self.first_position.forward {
+            (self.first_position.offset, self.last_position.offset)
+        } else {
+            (self.last_position.offset, self.first_position.offset)
+        };
+
+        AlignmentInterval {
+            unitig_id: self.first_position.unitig_id,
+            query_start: self.first_kmer,
+            query_end: self.last_kmer + k,
+            unitig_start: first_offset as usize,
+            unitig_end: last_offset as usize + k,
+            forward: self.first_position.forward,
+        }
+    }
+}
+
+/// Finds the alignment intervals of a single query sequence
+pub fn query_sequence_intervals(index: &mut UnitigsIndex, query: &[u8]) -> Vec<AlignmentInterval> {
+    let k = index.kmer_length();
+    let mut intervals = vec![];
+
+    if query.len() < k {
+        return intervals;
+    }
+
+    let mut pending: Option<PendingInterval> = None;
+
+    for kmer_index in 0..=(query.len() - k) {
+        let kmer = &query[kmer_index..kmer_index + k];
+
+        let position = if kmer.contains(&b'N') {
+            None
+        } else {
+            index.lookup(kmer)
+        };
+
+        let Some(position) = position else {
+            continue;
+        };
+
+        match &mut pending {
+            Some(current) if current.extends_with(kmer_index, &position) => {
+                current.last_kmer = kmer_index;
+                current.last_position = position;
+            }
+            _ => {
+                if let Some(current) = pending.take() {
+                    intervals.push(current.to_interval(k));
+                }
+                pending = Some(PendingInterval {
+                    first_kmer: kmer_index,
+                    last_kmer: kmer_index,
+                    first_position: position,
+                    last_position: position,
+                });
+            }
+        }
+    }
+
+    if let Some(current) = pending {
+        intervals.push(current.to_interval(k));
+    }
+
+    intervals
+}
+
+/// Reports, for each query, the alignment intervals of its kmers on the graph unitigs.
+/// The output is written in jsonl format, with one line per query.
+pub fn query_positions(
+    index_file: impl AsRef<Path>,
+    query_input: impl AsRef<Path>,
+    output_file_prefix: impl AsRef<Path>,
+) -> PathBuf {
+    let mut index = UnitigsIndex::open(index_file).unwrap_or_else(|err| panic!("{}", err));
+
+    let output_file = output_file_prefix
+        .as_ref()
+        .with_extension("positions.jsonl");
+    let mut writer = BufWriter::new(File::create(&output_file).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create output file {}: {}",
+            output_file.display(),
+            err
+        )
+    }));
+
+    let mut query_index = 0;
+    SequencesReader::new().process_file_extended(
+        query_input,
+        |seq| {
+            let intervals = query_sequence_intervals(&mut index, seq.seq);
+
+            write!(writer, "{{\"query_index\":{}, \"matches\":[", query_index).unwrap();
+            for (i, interval) in intervals.iter().enumerate() {
+                if i > 0 {
+                    write!(writer, ",").unwrap();
+                }
+                write!(
+                    writer,
+                    "{{\"unitig\":{}, \"query_start\":{}, \"query_end\":{}, \"unitig_start\":{}, \"unitig_end\":{}, \"strand\":\"{}\"}}",
+                    interval.unitig_id,
+                    interval.query_start,
+                    interval.query_end,
+                    interval.unitig_start,
+                    interval.unitig_end,
+                    if interval.forward { '+' } else { '-' }
+                )
+                .unwrap();
+            }
+            writeln!(writer, "]}}").unwrap();
+            query_index += 1;
+        },
+        None,
+        false,
+        false,
+    );
+
+    writer.flush().unwrap();
+    output_file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{query_sequence_intervals, AlignmentInterval};
+    use crate::unitigs_index::{build_unitigs_index, UnitigsIndex};
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn query_interval_spans_unitig() {
+        const K: usize = 15;
+        let unitig = b"ACGTTGCAAGGCTTACCGATTAGCCATGACCTAGGTTAGGCATCGGATCCAGTAG";
+
+        let temp_dir = TestDir::new("query_interval_spans_unitig");
+        let graph_file = temp_dir.path("positions-query.fa");
+        let index_file = temp_dir.path("positions-query.kmers.idx");
+
+        {
+            let mut graph = std::fs::File::create(&graph_file).unwrap();
+            writeln!(graph, ">0 LN:i:{}", unitig.len()).unwrap();
+            graph.write_all(unitig).unwrap();
+            writeln!(graph).unwrap();
+        }
+
+        build_unitigs_index(&graph_file, &index_file, K);
+        let mut index = UnitigsIndex::open(&index_file).unwrap();
+
+        // The query spans the whole unitig, with some unrelated bases on both sides
+        let mut query = b"GGGGGGGG".to_vec();
+        query.extend_from_slice(unitig);
+        query.extend_from_slice(b"CCCCCCCC");
+
+        assert_eq!(
+            query_sequence_intervals(&mut index, &query),
+            vec![AlignmentInterval {
+                unitig_id: 0,
+                query_start: 8,
+                query_end: 8 + unitig.len(),
+                unitig_start: 0,
+                unitig_end: unitig.len(),
+                forward: true,
+            }]
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use config::ColorIndexType;
+use std::io::Write;
+
+// Confidence of a query matching only its best color, as the highest mapping quality of most aligners
+const MAX_CLASSIFICATION_CONFIDENCE: u64 = 60;
+
+pub const CLASSIFICATION_HEADER: &str = "#query\tcolor\tmatched_kmers\tfraction\tconfidence";
+
+/// Header of the column added with the matched strand of the best color
+pub const CLASSIFICATION_STRAND_HEADER: &str = "strand";
+
+/// The color with the most matched kmers of a query, with the lowest color index winning the ties
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct QueryClassification {
+    pub color: ColorIndexType,
+    pub count: u64,
+    pub runner_up_count: u64,
+}
+
+impl QueryClassification {
+    /// Classifies a query from its matches, as (color index, matched kmers count) pairs
+    pub fn from_matches(matches: &[(ColorIndexType, u64)]) -> Option<Self> {
+        let &(color, count) = matches
+            .iter()
+            .min_by_key(|(color, count)| (std::cmp::Reverse(*count), *color))?;
+
+        let runner_up_count = matches
+            .iter()
+            .filter(|(other_color, _)| *other_color != color)
+            .map(|(_, count)| *count)
+            .max()
+            .unwrap_or(0);
+
+        Some(Self {
+            color,
+            count,
+            runner_up_count,
+        })
+    }
+
+    /// A MAPQ-like confidence in 0..=60, proportional to the gap between the best color and the
+    /// runner-up relative to the best color count. It is 0 for ties and 60 with a single matched color
+    pub fn confidence(&self) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        (self.count - self.runner_up_count) * MAX_CLASSIFICATION_CONFIDENCE / self.count
+    }
+}
+
+/// Writes the tab-separated classification record of a query:
+/// query name, best color, its matched kmers, their fraction over the query kmers and the confidence,
+/// followed by the matched strand of the best color if given
+pub fn write_classification_record(
+    writer: &mut impl Write,
+    query_name: &str,
+    color_name: &str,
+    classification: &QueryClassification,
+    query_kmers_count: u64,
+    strand: Option<char>,
+) -> std::io::Result<()> {
+    write!(
+        writer,
+        "{}\t{}\t{}\t{:.2}\t{}",
+        query_name,
+        color_name,
+        classification.count,
+        classification.count as f64 / query_kmers_count.max(1) as f64,
+        classification.confidence()
+    )?;
+    if let Some(strand) = strand {
+        write!(writer, "\t{}", strand)?;
+    }
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_classification_record, QueryClassification, CLASSIFICATION_HEADER};
+    use std::io::Write;
+
+    #[test]
+    fn classification_records_match_golden_file() {
+        let queries = [
+            ("clear_best", vec![(0, 10), (2, 95), (1, 5)], 100),
+            ("tie", vec![(3, 40), (1, 40)], 50),
+            ("single_color", vec![(4, 25)], 100),
+        ];
+        let color_names = ["sal1", "sal2", "sal3", "sal4", "sal5"];
+
+        let mut output = vec![];
+        writeln!(output, "{}", CLASSIFICATION_HEADER).unwrap();
+        for (query_name, matches, kmers_count) in &queries {
+            let classification = QueryClassification::from_matches(matches).unwrap();
+            write_classification_record(
+                &mut output,
+                query_name,
+                color_names[classification.color as usize],
+                &classification,
+                *kmers_count,
+                None,
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            include_str!("../test-data/query_classification.golden.tsv")
+        );
+        assert_eq!(QueryClassification::from_matches(&[]), None);
+    }
+}
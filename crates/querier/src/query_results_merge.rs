@@ -0,0 +1,199 @@
+use config::ColorIndexType;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// The matches of a query in a single graph, as (color index, matched kmers count) pairs,
+/// in the same form given to the query results callback
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryResult {
+    pub query_index: u64,
+    pub matches: Vec<(ColorIndexType, u64)>,
+}
+
+/// A color matched by a query, namespaced by the tag of the graph it belongs to,
+/// as the color indices of different graphs are unrelated
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphColorHit {
+    pub graph: String,
+    pub color: ColorIndexType,
+    pub count: u64,
+}
+
+/// The matches of a query in all the merged graphs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergedQueryResult {
+    pub query_index: u64,
+    pub hits: Vec<GraphColorHit>,
+}
+
+/// Merges the results of the same queries on multiple graphs, given as (graph tag, results) pairs,
+/// into a single result for each query. The results of each graph can be in any order, as reported
+/// by the query callback. The merged results are sorted by query index, and the hits of each
+/// query follow the order of the graphs and then of the colors.
+/// Queries without matches in any graph are not reported
+pub fn merge_query_results<I: IntoIterator<Item = QueryResult>>(
+    graphs_results: impl IntoIterator<Item = (String, I)>,
+) -> Vec<MergedQueryResult> {
+    let mut merged: BTreeMap<u64, Vec<(usize, GraphColorHit)>> = BTreeMap::new();
+
+    for (graph_index, (graph, results)) in graphs_results.into_iter().enumerate() {
+        for result in results {
+            let hits = merged.entry(result.query_index).or_default();
+            for (color, count) in result.matches {
+                hits.push((
+                    graph_index,
+                    GraphColorHit {
+                        graph: graph.clone(),
+                        color,
+                        count,
+                    },
+                ));
+            }
+        }
+    }
+
+    merged
+        .into_iter()
+        .filter(|(_, hits)| !hits.is_empty())
+        .map(|(query_index, mut hits)| {
+            hits.sort_by_key(|(graph_index, hit)| (*graph_index, hit.color));
+            MergedQueryResult {
+                query_index,
+                hits: hits.into_iter().map(|(_, hit)| hit).collect(),
+            }
+        })
+        .collect()
+}
+
+fn write_json_string(writer: &mut impl Write, value: &str) -> std::io::Result<()> {
+    write!(writer, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(writer, "\\\"")?,
+            '\\' => write!(writer, "\\\\")?,
+            c if c.is_control() => write!(writer, "\\u{:04x}", c as u32)?,
+            c => write!(writer, "{}", c)?,
+        }
+    }
+    write!(writer, "\"")
+}
+
+/// Writes the merged results in json lines format, with the matched kmers counts of each query
+/// grouped by graph tag, e.g. {"query_index":0, "matches":{"graph1":{"0": 12, "3": 5}, "graph2":{"1": 7}}}
+pub fn write_merged_query_results(results: &[MergedQueryResult], output_file: impl AsRef<Path>) {
+    let mut writer = BufWriter::new(File::create(output_file.as_ref()).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create merged query results file {}: {}",
+            output_file.as_ref().display(),
+            err
+        )
+    }));
+
+    for result in results {
+        write!(
+            writer,
+            "{{\"query_index\":{}, \"matches\":{{",
+            result.query_index
+        )
+        .unwrap();
+
+        let mut last_graph: Option<&str> = None;
+        for hit in &result.hits {
+            if last_graph != Some(hit.graph.as_str()) {
+                if last_graph.is_some() {
+                    write!(writer, "}},").unwrap();
+                }
+                write_json_string(&mut writer, &hit.graph).unwrap();
+                write!(writer, ":{{").unwrap();
+            } else {
+                write!(writer, ",").unwrap();
+            }
+            write!(writer, "\"{}\": {}", hit.color, hit.count).unwrap();
+            last_graph = Some(hit.graph.as_str());
+        }
+        if last_graph.is_some() {
+            write!(writer, "}}").unwrap();
+        }
+        writeln!(writer, "}}}}").unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_query_results, write_merged_query_results, GraphColorHit, QueryResult};
+    use utils::test_dir::TestDir;
+
+    fn hit(graph: &str, color: u32, count: u64) -> GraphColorHit {
+        GraphColorHit {
+            graph: graph.to_string(),
+            color,
+            count,
+        }
+    }
+
+    #[test]
+    fn results_from_two_graphs_are_merged_by_query() {
+        // Both graphs use the color 0, for different samples
+        let first_graph = vec![
+            QueryResult {
+                query_index: 2,
+                matches: vec![(1, 4)],
+            },
+            QueryResult {
+                query_index: 0,
+                matches: vec![(1, 3), (0, 10)],
+            },
+        ];
+        let second_graph = vec![
+            QueryResult {
+                query_index: 0,
+                matches: vec![(0, 7)],
+            },
+            QueryResult {
+                query_index: 1,
+                matches: vec![(2, 5)],
+            },
+            QueryResult {
+                query_index: 3,
+                matches: vec![],
+            },
+        ];
+
+        let merged = merge_query_results([
+            ("first".to_string(), first_graph),
+            ("second".to_string(), second_graph),
+        ]);
+
+        let hits: Vec<_> = merged
+            .iter()
+            .map(|result| (result.query_index, result.hits.clone()))
+            .collect();
+
+        assert_eq!(
+            hits,
+            vec![
+                (
+                    0,
+                    vec![hit("first", 0, 10), hit("first", 1, 3), hit("second", 0, 7)]
+                ),
+                (1, vec![hit("second", 2, 5)]),
+                (2, vec![hit("first", 1, 4)]),
+            ]
+        );
+
+        let temp_dir = TestDir::new("results_from_two_graphs_are_merged_by_query");
+        let output_file = temp_dir.path("merged-query-results.jsonl");
+        write_merged_query_results(&merged, &output_file);
+        let output = std::fs::read_to_string(&output_file).unwrap();
+
+        assert_eq!(
+            output,
+            "{\"query_index\":0, \"matches\":{\"first\":{\"0\": 10,\"1\": 3},\"second\":{\"0\": 7}}}\n\
+             {\"query_index\":1, \"matches\":{\"second\":{\"2\": 5}}}\n\
+             {\"query_index\":2, \"matches\":{\"first\":{\"1\": 4}}}\n"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::positions_query::query_sequence_intervals;
+use crate::unitigs_index::{parse_unitig_id, UnitigsIndex};
+use io::concurrent::structured_sequences::fasta::FastaWriter;
+use io::concurrent::structured_sequences::StructuredSequenceBackend;
+use io::sequences_reader::SequencesReader;
+use io::unitig_links::{parse_link_field, LinkStrand};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+
+fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|b| match *b {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            b'T' => b'A',
+            b'a' => b't',
+            b'c' => b'g',
+            b'g' => b'c',
+            b't' => b'a',
+            other => other,
+        })
+        .collect()
+}
+
+fn flip_strand(strand: LinkStrand, flip: bool) -> LinkStrand {
+    match (strand, flip) {
+        (LinkStrand::Forward, true) => LinkStrand::Reverse,
+        (LinkStrand::Reverse, true) => LinkStrand::Forward,
+        _ => strand,
+    }
+}
+
+pub(crate) fn create_graph_writer(path: &Path) -> FastaWriter<(), ()> {
+    match path.extension() {
+        Some(ext) => match ext.to_string_lossy().to_string().as_str() {
+            "lz4" => FastaWriter::new_compressed_lz4(path, 2),
+            "gz" => FastaWriter::new_compressed_gzip(path, 2),
+            _ => FastaWriter::new_plain(path),
+        },
+        None => FastaWriter::new_plain(path),
+    }
+}
+
+// Finds the unitigs that match more reference bases on their reverse strand than on the forward one
+fn find_reversed_unitigs(index: &mut UnitigsIndex, reference_file: &Path) -> HashSet<u64> {
+    // Forward matched kmers minus the reverse matched ones, for each unitig
+    let mut strand_votes: HashMap<u64, i64> = HashMap::new();
+
+    SequencesReader::new().process_file_extended(
+        reference_file,
+        |seq| {
+            for interval in query_sequence_intervals(index, seq.seq) {
+                let matched_kmers = (interval.query_end - interval.query_start) as i64;
+                *strand_votes.entry(interval.unitig_id).or_insert(0) += if interval.forward {
+                    matched_kmers
+                } else {
+                    -matched_kmers
+                };
+            }
+        },
+        None,
+        false,
+        false,
+    );
+
+    strand_votes
+        .into_iter()
+        .filter(|(_, votes)| *votes < 0)
+        .map(|(unitig_id, _)| unitig_id)
+        .collect()
+}
+
+/// Writes the graph with each unitig oriented as the reference strand where its kmers are found,
+/// reverse complementing the unitigs found on the reverse strand of the reference. The unitigs not
+/// found in the reference keep their original orientation.
+/// The links and the colors runs of the reversed unitigs are updated to the new orientation,
+/// as well as the links pointing to them.
+/// Returns the number of reversed unitigs
+pub fn orient_unitigs_to_reference(
+    index_file: impl AsRef<Path>,
+    graph_input: impl AsRef<Path>,
+    reference_file: impl AsRef<Path>,
+    output_file: impl AsRef<Path>,
+) -> usize {
+    let mut index = UnitigsIndex::open(index_file).unwrap_or_else(|err| panic!("{}", err));
+    let reversed_unitigs = find_reversed_unitigs(&mut index, reference_file.as_ref());
+
+    let mut writer = create_graph_writer(output_file.as_ref());
+    let mut buffer = vec![];
+    let mut sequence_index = 0;
+
+    SequencesReader::new().process_file_extended(
+        graph_input.as_ref(),
+        |seq| {
+            let unitig_id = parse_unitig_id(seq.ident_data).unwrap_or(sequence_index);
+            sequence_index += 1;
+            let is_reversed = reversed_unitigs.contains(&unitig_id);
+
+            let mut fields = seq.ident_data.split(|c| *c == b' ');
+            buffer.extend_from_slice(fields.next().unwrap());
+
+            let mut color_runs = vec![];
+            for field in fields {
+                if let Some(link) = parse_link_field(unitig_id, field) {
+                    let other_reversed = reversed_unitigs.contains(&link.to_id);
+                    write!(
+                        buffer,
+                        " L:{}:{}:{}",
+                        flip_strand(link.from_strand, is_reversed).sign(),
+                        link.to_id,
+                        flip_strand(link.to_strand, other_reversed).sign()
+                    )
+                    .unwrap();
+                    continue;
+                } else if is_reversed && field.starts_with(b"C:") {
+                    // The colors runs follow the kmers order, so they are written reversed
+                    color_runs.push(field);
+                    continue;
+                }
+
+                buffer.extend_from_slice(b" ");
+                buffer.extend_from_slice(field);
+            }
+
+            for run in color_runs.iter().rev() {
+                buffer.extend_from_slice(b" ");
+                buffer.extend_from_slice(run);
+            }
+
+            buffer.extend_from_slice(b"\n");
+            if is_reversed {
+                buffer.extend_from_slice(&reverse_complement(seq.seq));
+            } else {
+                buffer.extend_from_slice(seq.seq);
+            }
+            buffer.extend_from_slice(b"\n");
+            writer.flush_temp_buffer(&mut buffer);
+        },
+        None,
+        true,
+        false,
+    );
+
+    reversed_unitigs.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{orient_unitigs_to_reference, reverse_complement};
+    use crate::unitigs_index::build_unitigs_index;
+    use std::io::{BufRead, BufReader, Write};
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn unitig_on_reverse_strand_is_reversed() {
+        const K: usize = 15;
+        let unitigs: [&[u8]; 3] = [
+            b"ACGTTGCAAGGCTTACCGATTAGCCATGACCTAGG",
+            b"TTAGGCATCGGATCCAGTAGCTTGACAACGTGCATGCCA",
+            b"GATCGTACCTGAATGCGTTCAGGACTTCGAAGTCCTA",
+        ];
+
+        let temp_dir = TestDir::new("unitig_on_reverse_strand_is_reversed");
+        let graph_file = temp_dir.path("reference-orientation.fa");
+        let index_file = temp_dir.path("reference-orientation.kmers.idx");
+        let reference_file = temp_dir.path("reference-orientation-reference.fa");
+        let output_file = temp_dir.path("reference-orientation-output.fa");
+
+        {
+            let mut graph = std::fs::File::create(&graph_file).unwrap();
+            writeln!(graph, ">0 LN:i:35 C:0:10 C:1:11 L:+:1:+").unwrap();
+            graph.write_all(unitigs[0]).unwrap();
+            writeln!(graph).unwrap();
+            writeln!(graph, ">1 LN:i:39 C:0:10 C:1:15 L:-:0:-").unwrap();
+            graph.write_all(unitigs[1]).unwrap();
+            writeln!(graph).unwrap();
+            writeln!(graph, ">2 LN:i:37 C:1:23").unwrap();
+            graph.write_all(unitigs[2]).unwrap();
+            writeln!(graph).unwrap();
+
+            // The first unitig appears on the forward strand and the second one on the reverse strand,
+            // the third one is not in the reference
+            let mut reference = std::fs::File::create(&reference_file).unwrap();
+            writeln!(reference, ">reference").unwrap();
+            reference.write_all(unitigs[0]).unwrap();
+            reference.write_all(b"NNNN").unwrap();
+            reference
+                .write_all(&reverse_complement(unitigs[1]))
+                .unwrap();
+            writeln!(reference).unwrap();
+        }
+
+        build_unitigs_index(&graph_file, &index_file, K);
+        assert_eq!(
+            orient_unitigs_to_reference(&index_file, &graph_file, &reference_file, &output_file),
+            1
+        );
+
+        let lines: Vec<String> = BufReader::new(std::fs::File::open(&output_file).unwrap())
+            .lines()
+            .map(|l| l.unwrap())
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                ">0 LN:i:35 C:0:10 C:1:11 L:+:1:-".to_string(),
+                String::from_utf8(unitigs[0].to_vec()).unwrap(),
+                ">1 LN:i:39 L:+:0:- C:1:15 C:0:10".to_string(),
+                String::from_utf8(reverse_complement(unitigs[1])).unwrap(),
+                ">2 LN:i:37 C:1:23".to_string(),
+                String::from_utf8(unitigs[2].to_vec()).unwrap(),
+            ]
+        );
+    }
+}
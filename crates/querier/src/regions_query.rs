@@ -0,0 +1,293 @@
+use config::ColorIndexType;
+use io::sequences_reader::SequencesReader;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// An interval of a reference sequence, with 0-based start and exclusive end
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryRegion {
+    pub feature_id: String,
+    pub sequence_name: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The matches of the query made from a region, as (color index, matched kmers count) pairs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionQueryResult {
+    pub region: QueryRegion,
+    pub matches: Vec<(ColorIndexType, u64)>,
+}
+
+fn is_gff_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("gff" | "gff3" | "gtf")
+    )
+}
+
+fn parse_position(value: &str, line_number: usize) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid position '{}' at line {}", value, line_number))
+}
+
+// Feature id from the gff3 ID or Name attributes, or from the gtf gene_id one
+fn parse_gff_feature_id(attributes: &str) -> Option<String> {
+    let attributes: Vec<_> = attributes
+        .split(';')
+        .map(|attribute| attribute.trim())
+        .collect();
+
+    for key in ["ID", "Name"] {
+        for attribute in &attributes {
+            if let Some(value) = attribute
+                .strip_prefix(key)
+                .and_then(|value| value.strip_prefix('='))
+            {
+                return Some(value.to_string());
+            }
+        }
+    }
+
+    attributes.iter().find_map(|attribute| {
+        attribute
+            .strip_prefix("gene_id ")
+            .map(|value| value.trim_matches('"').to_string())
+    })
+}
+
+/// Reads the regions of a BED file, or of a GFF/GTF file if it has a .gff, .gff3 or .gtf extension.
+/// The feature ids are taken from the BED name column or from the GFF ID/Name/gene_id attributes,
+/// falling back to <sequence>:<start>-<end> (1-based, inclusive) if missing
+pub fn read_query_regions(regions_file: impl AsRef<Path>) -> Result<Vec<QueryRegion>, String> {
+    let regions_file = regions_file.as_ref();
+    let is_gff = is_gff_file(regions_file);
+
+    let reader = BufReader::new(File::open(regions_file).map_err(|err| {
+        format!(
+            "Cannot open regions file {}: {}",
+            regions_file.display(),
+            err
+        )
+    })?);
+
+    let mut regions = vec![];
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.map_err(|err| format!("Cannot read line {}: {}", line_number, err))?;
+
+        if line.trim().is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+
+        let (sequence_name, start, end, feature_id) = if is_gff {
+            let fields: Vec<_> = line.split('\t').collect();
+            if fields.len() < 9 {
+                return Err(format!("Expected 9 gff columns at line {}", line_number));
+            }
+            // GFF positions are 1-based and inclusive
+            let start = parse_position(fields[3], line_number)?;
+            if start == 0 {
+                return Err(format!("Invalid position '0' at line {}", line_number));
+            }
+            (
+                fields[0],
+                start - 1,
+                parse_position(fields[4], line_number)?,
+                parse_gff_feature_id(fields[8]),
+            )
+        } else {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            if fields.len() < 3 {
+                return Err(format!(
+                    "Expected at least 3 bed columns at line {}",
+                    line_number
+                ));
+            }
+            (
+                fields[0],
+                parse_position(fields[1], line_number)?,
+                parse_position(fields[2], line_number)?,
+                fields.get(3).map(|name| name.to_string()),
+            )
+        };
+
+        if start >= end {
+            return Err(format!("Empty region at line {}", line_number));
+        }
+
+        regions.push(QueryRegion {
+            feature_id: feature_id
+                .unwrap_or_else(|| format!("{}:{}-{}", sequence_name, start + 1, end)),
+            sequence_name: sequence_name.to_string(),
+            start,
+            end,
+        });
+    }
+
+    Ok(regions)
+}
+
+/// Extracts the sequences of the regions from the reference, writing them to a fasta file
+/// in the same order, with the feature ids as headers. The query index of each region
+/// is its position in the regions list
+pub fn write_regions_queries(
+    reference_file: impl AsRef<Path>,
+    regions: &[QueryRegion],
+    output_file: impl AsRef<Path>,
+) -> Result<(), String> {
+    let mut reference_sequences: HashMap<&str, Vec<u8>> = regions
+        .iter()
+        .map(|region| (region.sequence_name.as_str(), vec![]))
+        .collect();
+
+    SequencesReader::new().process_file_extended(
+        reference_file.as_ref(),
+        |seq| {
+            let name = seq
+                .ident_data
+                .strip_prefix(b">")
+                .unwrap_or(seq.ident_data)
+                .split(|c| c.is_ascii_whitespace())
+                .next()
+                .unwrap_or(&[]);
+            if let Some(sequence) = std::str::from_utf8(name)
+                .ok()
+                .and_then(|name| reference_sequences.get_mut(name))
+            {
+                sequence.extend_from_slice(seq.seq);
+            }
+        },
+        None,
+        true,
+        false,
+    );
+
+    let mut writer = BufWriter::new(File::create(output_file.as_ref()).map_err(|err| {
+        format!(
+            "Cannot create regions queries file {}: {}",
+            output_file.as_ref().display(),
+            err
+        )
+    })?);
+
+    for region in regions {
+        let sequence = &reference_sequences[region.sequence_name.as_str()];
+        if sequence.is_empty() {
+            return Err(format!(
+                "Sequence {} of feature {} not found in the reference",
+                region.sequence_name, region.feature_id
+            ));
+        }
+        if region.end > sequence.len() {
+            return Err(format!(
+                "Feature {} ends at {}, after the end of sequence {} ({} bases)",
+                region.feature_id,
+                region.end,
+                region.sequence_name,
+                sequence.len()
+            ));
+        }
+
+        writeln!(writer, ">{}", region.feature_id).unwrap();
+        writer
+            .write_all(&sequence[region.start..region.end])
+            .unwrap();
+        writeln!(writer).unwrap();
+    }
+
+    writer.flush().unwrap();
+    Ok(())
+}
+
+/// Attributes the results of the regions queries to their features, in the regions order.
+/// The regions without matches are reported with no matches
+pub fn attribute_regions_results(
+    regions: &[QueryRegion],
+    results: &HashMap<u64, Vec<(ColorIndexType, u64)>>,
+) -> Vec<RegionQueryResult> {
+    regions
+        .iter()
+        .enumerate()
+        .map(|(query_index, region)| RegionQueryResult {
+            region: region.clone(),
+            matches: results
+                .get(&(query_index as u64))
+                .cloned()
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_query_regions, write_regions_queries, QueryRegion};
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn bed_and_gff_regions_are_extracted() {
+        let temp_dir = TestDir::new("bed_and_gff_regions_are_extracted");
+        let reference_file = temp_dir.path("regions-query-reference.fa");
+        let bed_file = temp_dir.path("regions-query.bed");
+        let gff_file = temp_dir.path("regions-query.gff3");
+        let queries_file = temp_dir.path("regions-query-queries.fa");
+
+        {
+            let mut reference = std::fs::File::create(&reference_file).unwrap();
+            writeln!(
+                reference,
+                ">chr1 first chromosome\nACGTACGTTTGCA\n>chr2\nGGGCCCAAATTT"
+            )
+            .unwrap();
+
+            let mut bed = std::fs::File::create(&bed_file).unwrap();
+            writeln!(bed, "track name=test\nchr1\t2\t8\tgeneA\nchr2\t3\t9").unwrap();
+
+            let mut gff = std::fs::File::create(&gff_file).unwrap();
+            writeln!(
+                gff,
+                "##gff-version 3\nchr1\ttest\tgene\t3\t8\t.\t+\t.\tID=geneA;Name=A\n\
+                 chr2\ttest\tgene\t4\t9\t.\t+\t.\tName=geneB"
+            )
+            .unwrap();
+        }
+
+        let expected_regions = vec![
+            QueryRegion {
+                feature_id: "geneA".to_string(),
+                sequence_name: "chr1".to_string(),
+                start: 2,
+                end: 8,
+            },
+            QueryRegion {
+                feature_id: "chr2:4-9".to_string(),
+                sequence_name: "chr2".to_string(),
+                start: 3,
+                end: 9,
+            },
+        ];
+
+        let bed_regions = read_query_regions(&bed_file).unwrap();
+        assert_eq!(bed_regions, expected_regions);
+
+        let gff_regions = read_query_regions(&gff_file).unwrap();
+        assert_eq!(gff_regions[0], expected_regions[0]);
+        assert_eq!(gff_regions[1].feature_id, "geneB");
+        assert_eq!(gff_regions[1].start..gff_regions[1].end, 3..9);
+
+        write_regions_queries(&reference_file, &bed_regions, &queries_file).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&queries_file).unwrap(),
+            ">geneA\nGTACGT\n>chr2:4-9\nCCCAAA\n"
+        );
+    }
+}
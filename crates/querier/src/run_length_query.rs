@@ -0,0 +1,106 @@
+use crate::unitigs_index::UnitigsIndex;
+use io::sequences_reader::SequencesReader;
+use std::path::{Path, PathBuf};
+
+/// Counts the matched kmers that belong to a run of at least min_run_length consecutive matched kmers,
+/// given if each kmer of a query matched, in query order. The isolated matches, that are usually noise,
+/// are not counted, while the runs of true homology are counted in full
+pub fn count_run_matched_kmers(
+    matched: impl IntoIterator<Item = bool>,
+    min_run_length: usize,
+) -> u64 {
+    let mut matched_kmers = 0;
+    let mut current_run = 0;
+
+    for is_matched in matched.into_iter().chain(std::iter::once(false)) {
+        if is_matched {
+            current_run += 1;
+        } else {
+            if current_run >= min_run_length {
+                matched_kmers += current_run as u64;
+            }
+            current_run = 0;
+        }
+    }
+
+    matched_kmers
+}
+
+/// Reports, for each query, the number of its kmers found in the graph that belong to a run of at least
+/// min_run_length consecutive matching kmers in query coordinates. The output is written in csv format
+/// with the same columns of the uncolored queries, with one line per query
+pub fn query_min_run_length(
+    index_file: impl AsRef<Path>,
+    query_input: impl AsRef<Path>,
+    output_file_prefix: impl AsRef<Path>,
+    min_run_length: usize,
+) -> PathBuf {
+    let mut index = UnitigsIndex::open(index_file).unwrap_or_else(|err| panic!("{}", err));
+    let k = index.kmer_length();
+
+    let output_file = output_file_prefix.as_ref().with_extension("runs.csv");
+    let mut writer = csv::Writer::from_path(&output_file).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create output file {}: {}",
+            output_file.display(),
+            err
+        )
+    });
+    writer
+        .write_record([
+            "query_index",
+            "matched_kmers",
+            "query_kmers",
+            "match_percentage",
+        ])
+        .unwrap();
+
+    let mut query_index = 0;
+    SequencesReader::new().process_file_extended(
+        query_input,
+        |seq| {
+            let query_kmers = (seq.seq.len() + 1).saturating_sub(k);
+            let matched_kmers = count_run_matched_kmers(
+                seq.seq
+                    .windows(k)
+                    .map(|kmer| !kmer.contains(&b'N') && index.lookup(kmer).is_some()),
+                min_run_length,
+            );
+
+            writer
+                .write_record(&[
+                    query_index.to_string(),
+                    matched_kmers.to_string(),
+                    query_kmers.to_string(),
+                    format!("{:.2}", (matched_kmers as f64 / query_kmers as f64)),
+                ])
+                .unwrap();
+            query_index += 1;
+        },
+        None,
+        false,
+        false,
+    );
+
+    writer.flush().unwrap();
+    output_file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::count_run_matched_kmers;
+
+    #[test]
+    fn isolated_matches_are_not_counted() {
+        let scattered = [true, false, false, true, false, true, true, false, true];
+        assert_eq!(count_run_matched_kmers(scattered, 5), 0);
+        assert_eq!(count_run_matched_kmers(scattered, 1), 5);
+
+        // A solid block of 6 matches, and a trailing run of 5 reaching the end of the query
+        let solid = [
+            false, true, true, true, true, true, true, false, true, true, true, true, true,
+        ];
+        assert_eq!(count_run_matched_kmers(solid, 5), 11);
+        assert_eq!(count_run_matched_kmers(solid, 6), 6);
+    }
+}
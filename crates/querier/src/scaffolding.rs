@@ -0,0 +1,454 @@
+use crate::positions_query::query_sequence_intervals;
+use crate::unitigs_index::{parse_unitig_id, UnitigsIndex};
+use io::sequences_reader::SequencesReader;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// Unitigs joined with an estimated overlap are still separated by a gap of this size
+const MIN_SCAFFOLD_GAP: i64 = 1;
+
+fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|b| match *b {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            b'T' => b'A',
+            b'a' => b't',
+            b'c' => b'g',
+            b'g' => b'c',
+            b't' => b'a',
+            other => other,
+        })
+        .collect()
+}
+
+/// Placement of an oriented unitig on the fragment of a read pair
+#[derive(Copy, Clone, Debug)]
+struct MatePlacement {
+    unitig_id: u64,
+    /// True if the forward strand of the unitig is on the fragment strand
+    forward: bool,
+    /// Position of the first base of the oriented unitig, relative to the start of the read
+    offset: i64,
+}
+
+// Places a read on the unitig where its longest alignment interval is
+fn place_read(
+    index: &mut UnitigsIndex,
+    read: &[u8],
+    unitigs_lengths: &HashMap<u64, usize>,
+) -> Option<MatePlacement> {
+    let interval = query_sequence_intervals(index, read)
+        .into_iter()
+        .max_by_key(|interval| interval.query_end - interval.query_start)?;
+
+    let unitig_length = *unitigs_lengths.get(&interval.unitig_id)? as i64;
+
+    // The first query base of a reverse match is on the last matched unitig base
+    let oriented_start = if interval.forward {
+        interval.unitig_start as i64
+    } else {
+        unitig_length - interval.unitig_end as i64
+    };
+
+    Some(MatePlacement {
+        unitig_id: interval.unitig_id,
+        forward: interval.forward,
+        offset: interval.query_start as i64 - oriented_start,
+    })
+}
+
+/// The result of the scaffolding of a graph
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScaffoldingResult {
+    /// The insert size used to place the pairs, as given or estimated
+    pub insert_size: usize,
+    pub scaffolds_count: usize,
+    /// Number of gaps between unitigs in the scaffolds
+    pub joins_count: usize,
+}
+
+#[derive(Default)]
+struct PairsLink {
+    pairs_count: usize,
+    gaps_sum: i64,
+}
+
+// An end of a unitig, with true for the end of its forward strand
+type UnitigEnd = (u64, bool);
+
+fn find_root(parents: &mut HashMap<u64, u64>, unitig_id: u64) -> u64 {
+    let mut root = unitig_id;
+    while let Some(&parent) = parents.get(&root) {
+        if parent == root {
+            break;
+        }
+        root = parent;
+    }
+    parents.insert(unitig_id, root);
+    root
+}
+
+/// Orders and orients the unitigs of a graph into scaffolds, using the read pairs spanning
+/// different unitigs. The pairs are given as two fasta/fastq files with the mates in the same order,
+/// from a forward-reverse library: the first mate is on the fragment strand and the second one
+/// on the opposite one. The mates are placed on the unitigs with the kmers index of the graph.
+/// If the insert size is not given, it is estimated as the median insert of the pairs
+/// with both mates on the same unitig.
+/// Two unitigs are joined only if linked by at least min_pair_links pairs with the same orientation,
+/// starting from the links with the most pairs, and each unitig end can be joined only once.
+/// The joined unitigs are separated by a number of N equal to the mean gap estimated from the pairs
+/// (at least 1). Each scaffold is written with the list of its oriented unitigs in the header,
+/// e.g. >0 unitigs:3+,7-, and the unitigs not joined to others are written as single scaffolds
+pub fn scaffold_unitigs(
+    index_file: impl AsRef<Path>,
+    graph_input: impl AsRef<Path>,
+    reads_1: impl AsRef<Path>,
+    reads_2: impl AsRef<Path>,
+    output_file: impl AsRef<Path>,
+    min_pair_links: usize,
+    insert_size: Option<usize>,
+) -> Result<ScaffoldingResult, String> {
+    let mut index = UnitigsIndex::open(index_file)?;
+
+    let mut unitigs = vec![];
+    let mut sequence_index = 0;
+    SequencesReader::new().process_file_extended(
+        graph_input.as_ref(),
+        |seq| {
+            let unitig_id = parse_unitig_id(seq.ident_data).unwrap_or(sequence_index);
+            sequence_index += 1;
+            unitigs.push((unitig_id, seq.seq.to_vec()));
+        },
+        None,
+        true,
+        false,
+    );
+    let unitigs_lengths: HashMap<u64, usize> = unitigs
+        .iter()
+        .map(|(unitig_id, sequence)| (*unitig_id, sequence.len()))
+        .collect();
+
+    let mut first_mates = vec![];
+    SequencesReader::new().process_file_extended(
+        reads_1.as_ref(),
+        |seq| {
+            first_mates.push(place_read(&mut index, seq.seq, &unitigs_lengths));
+        },
+        None,
+        false,
+        false,
+    );
+
+    // The second mates are reverse complemented to be on the fragment strand
+    let mut pairs = Vec::with_capacity(first_mates.len());
+    let mut mate_index = 0;
+    SequencesReader::new().process_file_extended(
+        reads_2.as_ref(),
+        |seq| {
+            let first_mate = first_mates.get(mate_index).copied().flatten();
+            mate_index += 1;
+
+            if let Some(first_mate) = first_mate {
+                let second_read = reverse_complement(seq.seq);
+                if let Some(second_mate) = place_read(&mut index, &second_read, &unitigs_lengths) {
+                    pairs.push((first_mate, second_mate, second_read.len() as i64));
+                }
+            }
+        },
+        None,
+        false,
+        false,
+    );
+
+    if mate_index != first_mates.len() {
+        return Err(format!(
+            "The paired reads files have a different number of reads ({} and {})",
+            first_mates.len(),
+            mate_index
+        ));
+    }
+
+    // The fragment is placed with the first mate at its start and the second one at its end
+    let insert_size = match insert_size {
+        Some(insert_size) => insert_size as i64,
+        None => {
+            let mut inserts: Vec<_> = pairs
+                .iter()
+                .filter(|(first, second, _)| {
+                    first.unitig_id == second.unitig_id && first.forward == second.forward
+                })
+                .map(|(first, second, second_length)| second_length + first.offset - second.offset)
+                .filter(|insert| *insert > 0)
+                .collect();
+
+            if inserts.is_empty() {
+                return Err(
+                    "Cannot estimate the insert size, as no pair has both mates on the same unitig"
+                        .to_string(),
+                );
+            }
+            inserts.sort_unstable();
+            inserts[inserts.len() / 2]
+        }
+    };
+
+    // Links from the exit end of the first unitig to the entry end of the second one,
+    // stored with the smaller unitig id first
+    let mut links: HashMap<(UnitigEnd, UnitigEnd), PairsLink> = HashMap::new();
+
+    for (first, second, second_length) in &pairs {
+        if first.unitig_id == second.unitig_id {
+            continue;
+        }
+
+        let first_start = first.offset;
+        let second_start = insert_size - second_length + second.offset;
+
+        let (left, left_start, right, right_start) = if first_start <= second_start {
+            (first, first_start, second, second_start)
+        } else {
+            (second, second_start, first, first_start)
+        };
+        let gap = right_start - (left_start + unitigs_lengths[&left.unitig_id] as i64);
+
+        let left_exit = (left.unitig_id, left.forward);
+        let right_entry = (right.unitig_id, !right.forward);
+
+        let key = if left_exit.0 < right_entry.0 {
+            (left_exit, right_entry)
+        } else {
+            (right_entry, left_exit)
+        };
+
+        let link = links.entry(key).or_default();
+        link.pairs_count += 1;
+        link.gaps_sum += gap;
+    }
+
+    let mut links: Vec<_> = links
+        .into_iter()
+        .filter(|(_, link)| link.pairs_count >= min_pair_links)
+        .collect();
+    links.sort_unstable_by_key(|(ends, link)| (std::cmp::Reverse(link.pairs_count), *ends));
+
+    let mut joins: HashMap<UnitigEnd, (UnitigEnd, i64)> = HashMap::new();
+    let mut parents = HashMap::new();
+
+    for ((first_end, second_end), link) in links {
+        if joins.contains_key(&first_end) || joins.contains_key(&second_end) {
+            continue;
+        }
+
+        // Joining two unitigs already in the same scaffold would create a cycle
+        let first_root = find_root(&mut parents, first_end.0);
+        let second_root = find_root(&mut parents, second_end.0);
+        if first_root == second_root {
+            continue;
+        }
+        parents.insert(first_root, second_root);
+
+        let gap = link.gaps_sum / link.pairs_count as i64;
+        joins.insert(first_end, (second_end, gap));
+        joins.insert(second_end, (first_end, gap));
+    }
+
+    let unitigs_indices: HashMap<u64, usize> = unitigs
+        .iter()
+        .enumerate()
+        .map(|(index, (unitig_id, _))| (*unitig_id, index))
+        .collect();
+
+    let mut writer = BufWriter::new(File::create(output_file.as_ref()).map_err(|err| {
+        format!(
+            "Cannot create scaffolds file {}: {}",
+            output_file.as_ref().display(),
+            err
+        )
+    })?);
+
+    let mut visited = vec![false; unitigs.len()];
+    let mut scaffolds_count = 0;
+
+    for (unitig_id, _) in &unitigs {
+        if visited[unitigs_indices[unitig_id]] {
+            continue;
+        }
+
+        // Finds the free end of the scaffold, starting from the first end of the unitig
+        let mut free_end = (*unitig_id, false);
+        while let Some(((joined_id, joined_side), _)) = joins.get(&free_end) {
+            free_end = (*joined_id, !joined_side);
+        }
+
+        // The scaffold starts from its free end, so the first unitig is forward if it is its start
+        let (mut current_id, mut forward) = (free_end.0, !free_end.1);
+        let mut scaffold_unitigs = vec![];
+        let mut scaffold_sequence = vec![];
+
+        loop {
+            visited[unitigs_indices[&current_id]] = true;
+            let sequence = &unitigs[unitigs_indices[&current_id]].1;
+            if forward {
+                scaffold_sequence.extend_from_slice(sequence);
+            } else {
+                scaffold_sequence.extend(reverse_complement(sequence));
+            }
+            scaffold_unitigs.push(format!("{}{}", current_id, if forward { '+' } else { '-' }));
+
+            let Some(((next_id, next_side), gap)) = joins.get(&(current_id, forward)) else {
+                break;
+            };
+
+            scaffold_sequence
+                .extend(std::iter::repeat(b'N').take((*gap).max(MIN_SCAFFOLD_GAP) as usize));
+            current_id = *next_id;
+            // Entering from the start of the forward strand
+            forward = !next_side;
+        }
+
+        writeln!(
+            writer,
+            ">{} unitigs:{}",
+            scaffolds_count,
+            scaffold_unitigs.join(",")
+        )
+        .unwrap();
+        writer.write_all(&scaffold_sequence).unwrap();
+        writeln!(writer).unwrap();
+        scaffolds_count += 1;
+    }
+
+    writer.flush().unwrap();
+
+    Ok(ScaffoldingResult {
+        insert_size: insert_size as usize,
+        scaffolds_count,
+        joins_count: joins.len() / 2,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reverse_complement, scaffold_unitigs, ScaffoldingResult};
+    use crate::unitigs_index::build_unitigs_index;
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    const K: usize = 21;
+
+    fn random_bases(state: &mut u64, count: usize) -> Vec<u8> {
+        (0..count)
+            .map(|_| {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                b"ACGT"[(*state >> 32) as usize % 4]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn linked_unitigs_are_scaffolded_with_the_pairs_gap() {
+        const UNITIG_LENGTH: usize = 400;
+        const GAP: usize = 50;
+        const INSERT_SIZE: usize = 300;
+        const READ_LENGTH: usize = 50;
+
+        let temp_dir = TestDir::new("linked_unitigs_are_scaffolded_with_the_pairs_gap");
+        let graph_file = temp_dir.path("scaffolding-graph.fa");
+        let index_file = temp_dir.path("scaffolding-graph.kmers.idx");
+        let reads_1_file = temp_dir.path("scaffolding-reads_1.fa");
+        let reads_2_file = temp_dir.path("scaffolding-reads_2.fa");
+        let scaffolds_file = temp_dir.path("scaffolding-scaffolds.fa");
+
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        let first = random_bases(&mut state, UNITIG_LENGTH);
+        let second = random_bases(&mut state, UNITIG_LENGTH);
+        let unlinked = random_bases(&mut state, UNITIG_LENGTH);
+
+        // The genome has the two unitigs separated by a gap not in the graph
+        let mut genome = first.clone();
+        genome.extend(random_bases(&mut state, GAP));
+        genome.extend_from_slice(&second);
+
+        {
+            // The second unitig is stored as reverse complement
+            let mut graph = std::fs::File::create(&graph_file).unwrap();
+            for (unitig_id, sequence) in [&first, &reverse_complement(&second), &unlinked]
+                .iter()
+                .enumerate()
+            {
+                writeln!(
+                    graph,
+                    ">{}\n{}",
+                    unitig_id,
+                    std::str::from_utf8(sequence).unwrap()
+                )
+                .unwrap();
+            }
+
+            let mut reads_1 = std::fs::File::create(&reads_1_file).unwrap();
+            let mut reads_2 = std::fs::File::create(&reads_2_file).unwrap();
+            let mut write_pair = |first_mate: &[u8], second_mate: &[u8]| {
+                writeln!(reads_1, ">r\n{}", std::str::from_utf8(first_mate).unwrap()).unwrap();
+                writeln!(reads_2, ">r\n{}", std::str::from_utf8(second_mate).unwrap()).unwrap();
+            };
+
+            // Fragments within the first unitig, to estimate the insert size,
+            // and spanning the gap between the two unitigs
+            for fragment_start in (0..100).step_by(10).chain(300..350) {
+                let fragment = &genome[fragment_start..fragment_start + INSERT_SIZE];
+                write_pair(
+                    &fragment[..READ_LENGTH],
+                    &reverse_complement(&fragment[INSERT_SIZE - READ_LENGTH..]),
+                );
+            }
+
+            // A single pair linking the first unitig to the unlinked one
+            write_pair(
+                &first[..READ_LENGTH],
+                &reverse_complement(&unlinked[..READ_LENGTH]),
+            );
+        }
+
+        build_unitigs_index(&graph_file, &index_file, K);
+
+        let result = scaffold_unitigs(
+            &index_file,
+            &graph_file,
+            &reads_1_file,
+            &reads_2_file,
+            &scaffolds_file,
+            3,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            result,
+            ScaffoldingResult {
+                insert_size: INSERT_SIZE,
+                scaffolds_count: 2,
+                joins_count: 1,
+            }
+        );
+
+        let mut expected_scaffold = first.clone();
+        expected_scaffold.extend(std::iter::repeat(b'N').take(GAP));
+        expected_scaffold.extend_from_slice(&second);
+
+        assert_eq!(
+            std::fs::read_to_string(&scaffolds_file).unwrap(),
+            format!(
+                ">0 unitigs:0+,1-\n{}\n>1 unitigs:2+\n{}\n",
+                std::str::from_utf8(&expected_scaffold).unwrap(),
+                std::str::from_utf8(&unlinked).unwrap()
+            )
+        );
+    }
+}
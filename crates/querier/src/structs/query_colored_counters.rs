@@ -27,6 +27,7 @@ impl ColorsRange {
 pub struct QueryColorDesc {
     pub query_index: u64,
     pub count: u64,
+    pub reverse_count: u64,
 }
 
 pub struct QueryColoredCounters<'a> {
@@ -63,6 +64,7 @@ impl BucketItemSerializer for QueryColoredCountersSerializer {
         for query in element.queries.iter() {
             encode_varint(|b| bucket.extend_from_slice(b), query.query_index);
             encode_varint(|b| bucket.extend_from_slice(b), query.count);
+            encode_varint(|b| bucket.extend_from_slice(b), query.reverse_count);
         }
 
         assert_eq!(element.colors.len() % 2, 0);
@@ -82,7 +84,12 @@ impl BucketItemSerializer for QueryColoredCountersSerializer {
         for _ in 0..queries_count {
             let query_index = decode_varint(|| stream.read_u8().ok())?;
             let count = decode_varint(|| stream.read_u8().ok())?;
-            read_buffer.0.push(QueryColorDesc { query_index, count });
+            let reverse_count = decode_varint(|| stream.read_u8().ok())?;
+            read_buffer.0.push(QueryColorDesc {
+                query_index,
+                count,
+                reverse_count,
+            });
         }
 
         ColorIndexSerializer::deserialize_colors(stream, &mut read_buffer.1)?;
@@ -0,0 +1,188 @@
+use crate::color_unitigs::parse_color_subset_kmers;
+use crate::unitigs_index::parse_unitig_id;
+use colors::colors_manager::ColorMapReader;
+use colors::storage::deserializer::ColorsDeserializer;
+use colors::DefaultColorsSerializer;
+use config::ColorIndexType;
+use io::sequences_reader::SequencesReader;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Obtains the standard unique kmers report file path from a graph file path
+pub fn get_unique_kmers_file(graph_file: impl AsRef<Path>) -> PathBuf {
+    graph_file.as_ref().with_extension("unique_kmers.tsv")
+}
+
+// The maximum number of temporary files, each holding the stretches of a range of colors
+const MAX_STRETCHES_FILES: usize = 64;
+
+/// Writes the kmers found in exactly one input color of a graph, as the private markers of each color.
+/// The graph is streamed once, decoding the colors subset of each run of kmers of the unitigs from the
+/// colormap only the first time it is seen, and the consecutive kmers with a single color are merged
+/// in stretches. The stretches are written to temporary files in temp_dir, each for a range of colors,
+/// that are then sorted one at a time. The report is a tsv file grouped by color, with a line for each
+/// stretch with the color name, the unitig id, the offset of the first kmer in the unitig, the number
+/// of kmers and the sequence of the stretch. The kmers with only a sentinel color are not reported.
+/// Fails if the shared kmers of the graph were assigned only to their first color, as their colors
+/// subsets do not tell if they are unique. Returns the number of unique kmers of each input color
+pub fn write_unique_kmers_report(
+    graph_input: impl AsRef<Path>,
+    output_file: impl AsRef<Path>,
+    temp_dir: impl AsRef<Path>,
+) -> Result<Vec<u64>, String> {
+    let colormap_file = graph_input.as_ref().with_extension("colors.dat");
+    let mut colors_deserializer =
+        ColorsDeserializer::<DefaultColorsSerializer>::new(colormap_file, true);
+
+    if colors_deserializer.sentinels().first_color {
+        return Err(
+            "The shared kmers of the graph were assigned only to their first color, rebuild it assigning them to all their colors to report the unique kmers"
+                .to_string(),
+        );
+    }
+
+    let colors_count = colors_deserializer.input_colors_count();
+    let files_count = colors_count.clamp(1, MAX_STRETCHES_FILES);
+    let colors_per_file = colors_count.div_ceil(files_count).max(1);
+
+    let stretches_files: Vec<_> = (0..files_count)
+        .map(|index| {
+            temp_dir
+                .as_ref()
+                .join(format!("unique-kmers-stretches-{}.tsv", index))
+        })
+        .collect();
+    let mut stretches_writers: Vec<_> = stretches_files
+        .iter()
+        .map(|file| {
+            BufWriter::new(File::create(file).unwrap_or_else(|err| {
+                panic!(
+                    "Cannot create unique kmers temp file {}: {}",
+                    file.display(),
+                    err
+                )
+            }))
+        })
+        .collect();
+
+    // The only input color of each subset, None for the subsets with multiple or sentinel colors
+    let mut subsets_color: HashMap<ColorIndexType, Option<ColorIndexType>> = HashMap::new();
+    let mut colors = vec![];
+    let mut unitig_index = 0;
+
+    SequencesReader::new().process_file_extended(
+        graph_input.as_ref(),
+        |seq| {
+            let unitig_id = parse_unitig_id(seq.ident_data).unwrap_or(unitig_index);
+            unitig_index += 1;
+
+            let runs: Vec<_> = seq
+                .ident_data
+                .split(|c| *c == b' ')
+                .filter_map(parse_color_subset_kmers)
+                .collect();
+            let kmers_count: u64 = runs.iter().map(|(_, count)| count).sum();
+            let Some(k) = (seq.seq.len() + 1).checked_sub(kmers_count as usize) else {
+                return;
+            };
+
+            // The stretches are written with the color index, replaced by its name in the report
+            let mut push_stretch = |color: ColorIndexType, offset: usize, kmers_count: usize| {
+                let writer = &mut stretches_writers[color as usize / colors_per_file];
+                writeln!(
+                    writer,
+                    "{}\t{}\t{}\t{}\t{}",
+                    color,
+                    unitig_id,
+                    offset,
+                    kmers_count,
+                    std::str::from_utf8(&seq.seq[offset..offset + kmers_count + k - 1]).unwrap()
+                )
+                .unwrap();
+            };
+
+            // The color, offset and kmers count of the current stretch
+            let mut current: Option<(ColorIndexType, usize, usize)> = None;
+            let mut offset = 0;
+            for (subset, count) in runs {
+                let color = *subsets_color.entry(subset).or_insert_with(|| {
+                    colors.clear();
+                    colors_deserializer.get_color_mappings(subset, &mut colors);
+                    colors.sort_unstable();
+                    colors.dedup();
+                    match colors.as_slice() {
+                        [color] if (*color as usize) < colors_count => Some(*color),
+                        _ => None,
+                    }
+                });
+
+                current = match (current, color) {
+                    (Some((current_color, start, kmers)), Some(color))
+                        if current_color == color =>
+                    {
+                        Some((color, start, kmers + count as usize))
+                    }
+                    (previous, color) => {
+                        if let Some((previous_color, start, kmers)) = previous {
+                            push_stretch(previous_color, start, kmers);
+                        }
+                        color.map(|color| (color, offset, count as usize))
+                    }
+                };
+                offset += count as usize;
+            }
+            if let Some((color, start, kmers)) = current {
+                push_stretch(color, start, kmers);
+            }
+        },
+        None,
+        true,
+        false,
+    );
+
+    for mut writer in stretches_writers {
+        writer.flush().unwrap();
+    }
+
+    let mut writer = BufWriter::new(File::create(output_file.as_ref()).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create unique kmers file {}: {}",
+            output_file.as_ref().display(),
+            err
+        )
+    }));
+    writeln!(writer, "#color\tunitig\toffset\tkmers\tsequence").unwrap();
+
+    let mut unique_kmers = vec![0; colors_count];
+    for stretches_file in stretches_files {
+        let stretches = std::fs::read_to_string(&stretches_file).unwrap();
+        let _ = std::fs::remove_file(&stretches_file);
+
+        let mut stretches: Vec<_> = stretches
+            .lines()
+            .map(|line| {
+                let (color, stretch) = line.split_once('\t').unwrap();
+                (color.parse::<ColorIndexType>().unwrap(), stretch)
+            })
+            .collect();
+        // Stable, keeping the stretches of each color in the graph order
+        stretches.sort_by_key(|(color, _)| *color);
+
+        for (color, stretch) in stretches {
+            writeln!(
+                writer,
+                "{}\t{}",
+                colors_deserializer.get_color_name(color, false),
+                stretch
+            )
+            .unwrap();
+            let kmers_count: u64 = stretch.split('\t').nth(2).unwrap().parse().unwrap();
+            unique_kmers[color as usize] += kmers_count;
+        }
+    }
+
+    writer.flush().unwrap();
+    Ok(unique_kmers)
+}
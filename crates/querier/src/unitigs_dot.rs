@@ -0,0 +1,116 @@
+use io::sequences_reader::SequencesReader;
+use io::unitig_links::{iter_links, parse_header_links};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Above this number of unitigs the DOT files are too big to be rendered
+pub const DEFAULT_DOT_MAX_NODES: usize = 10000;
+
+/// Obtains the standard DOT file path from a graph file path
+pub fn get_unitigs_dot_file(graph_file: impl AsRef<Path>) -> PathBuf {
+    graph_file.as_ref().with_extension("dot")
+}
+
+/// Writes a small compacted graph in the GraphViz DOT format, for teaching and debugging. Each unitig is
+/// a node labeled with its id and length, and each link in the graph headers, if any, is an edge labeled
+/// with the strands of its unitigs, as in `0 -> 1 [label="+/-"]`. Graphs with more than max_nodes unitigs
+/// are refused, removing the partial file, as their DOT files cannot be rendered.
+/// Returns the number of written nodes
+pub fn write_unitigs_dot(
+    graph_input: impl AsRef<Path>,
+    output_file: impl AsRef<Path>,
+    max_nodes: usize,
+) -> Result<usize, String> {
+    let output_file = output_file.as_ref();
+    let mut writer =
+        BufWriter::new(File::create(output_file).unwrap_or_else(|err| {
+            panic!("Cannot create dot file {}: {}", output_file.display(), err)
+        }));
+    writeln!(writer, "digraph unitigs {{").unwrap();
+    writeln!(writer, "    node [shape=box];").unwrap();
+
+    let mut nodes_count = 0;
+    SequencesReader::new().process_file_extended(
+        graph_input.as_ref(),
+        |seq| {
+            nodes_count += 1;
+            if nodes_count > max_nodes {
+                return;
+            }
+            let (id, _) = parse_header_links(seq.ident_data, nodes_count as u64 - 1);
+            writeln!(
+                writer,
+                "    {} [label=\"{} ({} bp)\"];",
+                id,
+                id,
+                seq.seq.len()
+            )
+            .unwrap();
+        },
+        None,
+        true,
+        false,
+    );
+
+    if nodes_count > max_nodes {
+        drop(writer);
+        let _ = std::fs::remove_file(output_file);
+        return Err(format!(
+            "the graph has {} unitigs, more than the maximum of {} for the DOT output",
+            nodes_count, max_nodes
+        ));
+    }
+
+    // Each link is listed by both its unitigs, keep it only once
+    for link in iter_links(graph_input.as_ref()).filter(|link| link.from_id <= link.to_id) {
+        writeln!(
+            writer,
+            "    {} -> {} [label=\"{}/{}\"];",
+            link.from_id,
+            link.to_id,
+            link.from_strand.sign(),
+            link.to_strand.sign()
+        )
+        .unwrap();
+    }
+
+    writeln!(writer, "}}").unwrap();
+    writer.flush().unwrap();
+    Ok(nodes_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_unitigs_dot;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn tiny_graph_nodes_and_edges() {
+        let temp_dir = TestDir::new("tiny_graph_nodes_and_edges");
+        let graph_file = temp_dir.path("unitigs-dot.fa");
+        let dot_file = temp_dir.path("unitigs-dot.dot");
+
+        std::fs::write(
+            &graph_file,
+            ">0 LN:i:8 L:+:1:+ L:-:2:+\nAAGTTGAC\n\
+             >1 LN:i:9 L:-:0:-\nTGACAGGTC\n\
+             >2 LN:i:6 L:-:0:+\nGGCCAT\n",
+        )
+        .unwrap();
+
+        assert_eq!(write_unitigs_dot(&graph_file, &dot_file, 3), Ok(3));
+        let dot = std::fs::read_to_string(&dot_file).unwrap();
+        let lines: Vec<_> = dot.lines().collect();
+        assert_eq!(lines.first(), Some(&"digraph unitigs {"));
+        assert_eq!(lines.last(), Some(&"}"));
+        assert!(lines.contains(&"    0 [label=\"0 (8 bp)\"];"));
+        assert!(lines.contains(&"    2 [label=\"2 (6 bp)\"];"));
+        assert!(lines.contains(&"    0 -> 1 [label=\"+/+\"];"));
+        assert!(lines.contains(&"    0 -> 2 [label=\"-/+\"];"));
+        assert_eq!(lines.iter().filter(|line| line.contains("->")).count(), 2);
+
+        assert!(write_unitigs_dot(&graph_file, &dot_file, 2).is_err());
+        assert!(!dot_file.exists());
+    }
+}
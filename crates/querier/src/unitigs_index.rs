@@ -0,0 +1,545 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use hashes::cn_nthash::CanonicalNtHashIteratorFactory;
+use hashes::{ExtendableHashTraitType, HashFunction, HashFunctionFactory};
+use io::sequences_reader::SequencesReader;
+use rayon::slice::ParallelSliceMut;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::UNIX_EPOCH;
+
+const INDEX_MAGIC: [u8; 16] = *b"GGCAT_KMER_INDEX";
+const INDEX_VERSION: u64 = 2;
+
+// magic + version + k + graph size + graph modification time + entries count
+const HEADER_SIZE: u64 = 16 + 8 * 5;
+// hash + unitig id + offset + strand
+const ENTRY_SIZE: u64 = 8 + 8 + 4 + 1;
+
+/// 512MB for the entries sorted in memory while building the index, the entries exceeding it
+/// are sorted in runs written next to the index file and then merged
+pub const DEFAULT_UNITIGS_INDEX_SORT_MEMORY: usize = 512 * 1024 * 1024;
+
+// The runs merged at once, the runs exceeding it are first merged into larger runs
+const MAX_MERGED_RUNS: usize = 32;
+
+/// Position of a kmer inside the graph
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UnitigPosition {
+    pub unitig_id: u64,
+    /// Offset of the first base of the kmer from the start of the unitig
+    pub offset: u32,
+    /// True if the kmer appears as-is on the forward strand of the unitig,
+    /// false if its reverse complement does
+    pub forward: bool,
+}
+
+#[derive(Copy, Clone)]
+struct IndexEntry {
+    hash: u64,
+    unitig_id: u64,
+    offset: u32,
+    forward: bool,
+}
+
+impl IndexEntry {
+    // The order of the index entries, sorted by hash and then by position for a deterministic file
+    fn key(&self) -> (u64, u64, u32, bool) {
+        (self.hash, self.unitig_id, self.offset, self.forward)
+    }
+
+    fn write(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writer.write_u64::<LittleEndian>(self.hash)?;
+        writer.write_u64::<LittleEndian>(self.unitig_id)?;
+        writer.write_u32::<LittleEndian>(self.offset)?;
+        writer.write_u8(self.forward as u8)
+    }
+
+    fn read(mut reader: impl Read) -> std::io::Result<Self> {
+        Ok(Self {
+            hash: reader.read_u64::<LittleEndian>()?,
+            unitig_id: reader.read_u64::<LittleEndian>()?,
+            offset: reader.read_u32::<LittleEndian>()?,
+            forward: reader.read_u8()? != 0,
+        })
+    }
+}
+
+/// Obtains the standard kmers index file path from a graph file path
+pub fn get_unitigs_index_file(graph_file: impl AsRef<Path>) -> PathBuf {
+    graph_file.as_ref().with_extension("kmers.idx")
+}
+
+// The size and the modification time in nanoseconds of the graph file, stored in the index header
+// to detect an index built from a previous version of the graph
+fn graph_file_stamp(graph_file: &Path) -> (u64, u64) {
+    let metadata = std::fs::metadata(graph_file).ok();
+    let size = metadata
+        .as_ref()
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    let modified = metadata
+        .and_then(|metadata| metadata.modified().ok())
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or(0);
+    (size, modified)
+}
+
+pub(crate) fn parse_unitig_id(ident: &[u8]) -> Option<u64> {
+    let ident = ident.strip_prefix(b">").unwrap_or(ident);
+    let id_end = ident
+        .iter()
+        .position(|c| c.is_ascii_whitespace())
+        .unwrap_or(ident.len());
+    std::str::from_utf8(&ident[..id_end]).ok()?.parse().ok()
+}
+
+// Sorts the index entries in runs of bounded size written next to the index file
+struct IndexRunsSorter {
+    index_file: PathBuf,
+    buffer: Vec<IndexEntry>,
+    max_buffer_entries: usize,
+    runs: Vec<PathBuf>,
+    entries_count: u64,
+}
+
+impl IndexRunsSorter {
+    fn new(index_file: &Path, max_memory: usize) -> Self {
+        Self {
+            index_file: index_file.to_path_buf(),
+            buffer: vec![],
+            max_buffer_entries: (max_memory / std::mem::size_of::<IndexEntry>()).max(1),
+            runs: vec![],
+            entries_count: 0,
+        }
+    }
+
+    fn push(&mut self, entry: IndexEntry) {
+        self.buffer.push(entry);
+        self.entries_count += 1;
+        if self.buffer.len() >= self.max_buffer_entries {
+            self.spill();
+        }
+    }
+
+    fn create_run_writer(&mut self) -> (PathBuf, BufWriter<File>) {
+        static RUN_INDEX: AtomicUsize = AtomicUsize::new(0);
+        let run_file = self.index_file.with_extension(format!(
+            "idx.run{}",
+            RUN_INDEX.fetch_add(1, Ordering::Relaxed)
+        ));
+        let writer = BufWriter::new(File::create(&run_file).unwrap_or_else(|err| {
+            panic!(
+                "Cannot create kmers index run {}: {}",
+                run_file.display(),
+                err
+            )
+        }));
+        (run_file, writer)
+    }
+
+    fn spill(&mut self) {
+        self.buffer.par_sort_unstable_by_key(|entry| entry.key());
+
+        let (run_file, mut writer) = self.create_run_writer();
+        for entry in &self.buffer {
+            entry.write(&mut writer).unwrap();
+        }
+        writer.flush().unwrap();
+
+        self.runs.push(run_file);
+        self.buffer.clear();
+    }
+
+    // Writes the sorted entries, directly from memory if they were never spilled
+    fn write_sorted(mut self, mut writer: impl Write) {
+        if self.runs.is_empty() {
+            self.buffer.par_sort_unstable_by_key(|entry| entry.key());
+            for entry in &self.buffer {
+                entry.write(&mut writer).unwrap();
+            }
+            return;
+        }
+
+        if !self.buffer.is_empty() {
+            self.spill();
+        }
+        self.buffer = vec![];
+
+        // Merges the runs in passes of at most MAX_MERGED_RUNS, to bound the open runs
+        let mut runs = std::mem::take(&mut self.runs);
+        while runs.len() > MAX_MERGED_RUNS {
+            let merged: Vec<_> = runs.drain(..MAX_MERGED_RUNS).collect();
+            let (run_file, mut run_writer) = self.create_run_writer();
+            merge_index_runs(&merged, &mut run_writer);
+            run_writer.flush().unwrap();
+            runs.push(run_file);
+        }
+        merge_index_runs(&runs, writer);
+    }
+}
+
+// Merges the sorted runs, removing them once read
+fn merge_index_runs(runs: &[PathBuf], mut writer: impl Write) {
+    let mut readers: Vec<_> = runs
+        .iter()
+        .map(|run| BufReader::new(File::open(run).unwrap()))
+        .collect();
+
+    let mut heap = BinaryHeap::new();
+    let mut current = vec![];
+    for (run, reader) in readers.iter_mut().enumerate() {
+        let entry = IndexEntry::read(reader).ok();
+        if let Some(entry) = &entry {
+            heap.push(Reverse((entry.key(), run)));
+        }
+        current.push(entry);
+    }
+
+    while let Some(Reverse((_, run))) = heap.pop() {
+        let entry = current[run].take().unwrap();
+        if let Ok(next) = IndexEntry::read(&mut readers[run]) {
+            heap.push(Reverse((next.key(), run)));
+            current[run] = Some(next);
+        }
+        entry.write(&mut writer).unwrap();
+    }
+
+    drop(readers);
+    for run in runs {
+        let _ = std::fs::remove_file(run);
+    }
+}
+
+/// Builds an index mapping each canonical kmer hash of the graph to its position
+/// in the unitigs, sorted by hash to allow binary search lookups directly from disk.
+/// The size and the modification time of the graph are stored in the index, to check with
+/// UnitigsIndex::matches_graph that the index is still up to date with the graph.
+/// The unitig ids are taken from the sequence headers, falling back to the sequence
+/// ordinal if an header does not start with a number.
+/// The entries are sorted in memory up to DEFAULT_UNITIGS_INDEX_SORT_MEMORY, larger graphs
+/// are sorted in runs written next to the index file
+pub fn build_unitigs_index(graph_input: impl AsRef<Path>, output_file: impl AsRef<Path>, k: usize) {
+    build_unitigs_index_with_memory(
+        graph_input,
+        output_file,
+        k,
+        DEFAULT_UNITIGS_INDEX_SORT_MEMORY,
+    )
+}
+
+fn build_unitigs_index_with_memory(
+    graph_input: impl AsRef<Path>,
+    output_file: impl AsRef<Path>,
+    k: usize,
+    max_sort_memory: usize,
+) {
+    CanonicalNtHashIteratorFactory::initialize(k);
+
+    // Taken before reading the graph, so that a graph modified while indexing is detected as changed
+    let (graph_size, graph_modified) = graph_file_stamp(graph_input.as_ref());
+
+    let mut sorter = IndexRunsSorter::new(output_file.as_ref(), max_sort_memory);
+    let mut sequence_index = 0;
+
+    SequencesReader::new().process_file_extended(
+        graph_input.as_ref(),
+        |seq| {
+            let unitig_id = parse_unitig_id(seq.ident_data).unwrap_or(sequence_index);
+            sequence_index += 1;
+
+            if seq.seq.len() < k {
+                return;
+            }
+
+            for (offset, hash) in CanonicalNtHashIteratorFactory::new(seq.seq, k).iter_enumerate() {
+                sorter.push(IndexEntry {
+                    hash: hash.to_unextendable(),
+                    unitig_id,
+                    offset: offset as u32,
+                    forward: hash.is_forward() || hash.is_rc_symmetric(),
+                });
+            }
+        },
+        None,
+        true,
+        false,
+    );
+
+    let mut writer = BufWriter::new(File::create(output_file.as_ref()).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create kmers index file {}: {}",
+            output_file.as_ref().display(),
+            err
+        )
+    }));
+
+    writer.write_all(&INDEX_MAGIC).unwrap();
+    writer.write_u64::<LittleEndian>(INDEX_VERSION).unwrap();
+    writer.write_u64::<LittleEndian>(k as u64).unwrap();
+    writer.write_u64::<LittleEndian>(graph_size).unwrap();
+    writer.write_u64::<LittleEndian>(graph_modified).unwrap();
+    writer
+        .write_u64::<LittleEndian>(sorter.entries_count)
+        .unwrap();
+
+    sorter.write_sorted(&mut writer);
+    writer.flush().unwrap();
+}
+
+/// Kmers index reader, performing the lookups with a binary search on the index file
+/// without loading it in memory
+pub struct UnitigsIndex {
+    file: File,
+    k: usize,
+    graph_size: u64,
+    graph_modified: u64,
+    entries_count: u64,
+}
+
+impl UnitigsIndex {
+    pub fn open(index_file: impl AsRef<Path>) -> Result<Self, String> {
+        let mut file = File::open(index_file.as_ref()).map_err(|err| {
+            format!(
+                "Cannot open kmers index file {}: {}",
+                index_file.as_ref().display(),
+                err
+            )
+        })?;
+
+        let mut magic = [0; 16];
+        file.read_exact(&mut magic).map_err(|e| e.to_string())?;
+        if magic != INDEX_MAGIC {
+            return Err("Invalid kmers index file".to_string());
+        }
+
+        let version = file.read_u64::<LittleEndian>().map_err(|e| e.to_string())?;
+        if version != INDEX_VERSION {
+            return Err(format!(
+                "Unsupported kmers index version {}, please rebuild the index",
+                version
+            ));
+        }
+
+        let k = file.read_u64::<LittleEndian>().map_err(|e| e.to_string())? as usize;
+        let graph_size = file.read_u64::<LittleEndian>().map_err(|e| e.to_string())?;
+        let graph_modified = file.read_u64::<LittleEndian>().map_err(|e| e.to_string())?;
+        let entries_count = file.read_u64::<LittleEndian>().map_err(|e| e.to_string())?;
+        CanonicalNtHashIteratorFactory::initialize(k);
+
+        Ok(Self {
+            file,
+            k,
+            graph_size,
+            graph_modified,
+            entries_count,
+        })
+    }
+
+    pub fn kmer_length(&self) -> usize {
+        self.k
+    }
+
+    pub fn kmers_count(&self) -> u64 {
+        self.entries_count
+    }
+
+    /// True if the graph file has the same size and modification time it had when the index was built
+    pub fn matches_graph(&self, graph_file: impl AsRef<Path>) -> bool {
+        graph_file_stamp(graph_file.as_ref()) == (self.graph_size, self.graph_modified)
+    }
+
+    fn read_entry(&mut self, index: u64) -> IndexEntry {
+        self.file
+            .seek(SeekFrom::Start(HEADER_SIZE + index * ENTRY_SIZE))
+            .unwrap();
+        IndexEntry::read(&mut self.file).unwrap()
+    }
+
+    /// Finds the position of the kmer at the start of the given sequence (in plain ACGT format),
+    /// with the kmers length k read from the index header. Returns None if the sequence is shorter than k.
+    /// As only the hashes are stored, a kmer not in the graph can be reported
+    /// if its hash collides with one of the graph kmers.
+    pub fn lookup(&mut self, sequence: &[u8]) -> Option<UnitigPosition> {
+        if sequence.len() < self.k {
+            return None;
+        }
+
+        let hash = CanonicalNtHashIteratorFactory::new(&sequence[..self.k], self.k)
+            .iter()
+            .next()
+            .unwrap();
+        let query_forward = hash.is_forward() || hash.is_rc_symmetric();
+        let hash = hash.to_unextendable();
+
+        let mut start = 0;
+        let mut end = self.entries_count;
+
+        while start < end {
+            let middle = start + (end - start) / 2;
+            let entry = self.read_entry(middle);
+
+            if entry.hash < hash {
+                start = middle + 1;
+            } else if entry.hash > hash {
+                end = middle;
+            } else {
+                return Some(UnitigPosition {
+                    unitig_id: entry.unitig_id,
+                    offset: entry.offset,
+                    forward: entry.forward == query_forward,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        build_unitigs_index, build_unitigs_index_with_memory, IndexEntry, UnitigPosition,
+        UnitigsIndex, ENTRY_SIZE, HEADER_SIZE, MAX_MERGED_RUNS,
+    };
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+        seq.iter()
+            .rev()
+            .map(|b| match *b {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                b'T' => b'A',
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unitigs_index_lookup() {
+        const K: usize = 15;
+        let unitigs: [&[u8]; 2] = [
+            b"ACGTTGCAAGGCTTACCGATTAGCCATGACCTAGG",
+            b"TTAGGCATCGGATCCAGTAGCTTGACAACGTGCATGCCA",
+        ];
+
+        let temp_dir = TestDir::new("unitigs_index_lookup");
+        let graph_file = temp_dir.path("unitigs-index.fa");
+        let index_file = temp_dir.path("unitigs-index.kmers.idx");
+
+        {
+            let mut graph = std::fs::File::create(&graph_file).unwrap();
+            for (id, unitig) in unitigs.iter().enumerate() {
+                writeln!(graph, ">{} LN:i:{}", id + 10, unitig.len()).unwrap();
+                graph.write_all(unitig).unwrap();
+                writeln!(graph).unwrap();
+            }
+        }
+
+        build_unitigs_index(&graph_file, &index_file, K);
+        let mut index = UnitigsIndex::open(&index_file).unwrap();
+
+        assert_eq!(index.kmer_length(), K);
+        assert_eq!(
+            index.kmers_count() as usize,
+            unitigs.iter().map(|u| u.len() - K + 1).sum::<usize>()
+        );
+
+        let kmer = &unitigs[1][7..7 + K];
+        assert_eq!(
+            index.lookup(kmer),
+            Some(UnitigPosition {
+                unitig_id: 11,
+                offset: 7,
+                forward: true
+            })
+        );
+        assert_eq!(
+            index.lookup(&reverse_complement(kmer)),
+            Some(UnitigPosition {
+                unitig_id: 11,
+                offset: 7,
+                forward: false
+            })
+        );
+        assert_eq!(index.lookup(b"AAAAAAAAAAAAAAA"), None);
+
+        // Only the first kmer of a longer sequence is looked up, with the k of the index
+        assert_eq!(index.lookup(&unitigs[1][7..]), index.lookup(kmer));
+        assert_eq!(index.lookup(&kmer[..K - 1]), None);
+
+        // The index no longer matches the graph once it is rewritten
+        assert!(index.matches_graph(&graph_file));
+        std::fs::write(&graph_file, b">0 LN:i:15\nACGTTGCAAGGCTTA\n").unwrap();
+        assert!(!index.matches_graph(&graph_file));
+    }
+
+    #[test]
+    fn unitigs_index_sorted_in_runs_is_identical() {
+        const K: usize = 15;
+
+        let temp_dir = TestDir::new("unitigs_index_sorted_in_runs_is_identical");
+        let graph_file = temp_dir.path("unitigs-index-runs.fa");
+        let memory_index = temp_dir.path("unitigs-index-runs.memory.kmers.idx");
+        let runs_index = temp_dir.path("unitigs-index-runs.runs.kmers.idx");
+
+        // Pseudo random unitigs, with more kmers than the entries of the merged runs
+        let mut state = 0x9e3779b97f4a7c15u64;
+        let mut kmers_count = 0;
+        {
+            let mut graph = std::fs::File::create(&graph_file).unwrap();
+            for id in 0..40 {
+                let unitig: Vec<u8> = (0..100)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        b"ACGT"[(state >> 33) as usize % 4]
+                    })
+                    .collect();
+                kmers_count += unitig.len() - K + 1;
+                writeln!(graph, ">{} LN:i:{}", id, unitig.len()).unwrap();
+                graph.write_all(&unitig).unwrap();
+                writeln!(graph).unwrap();
+            }
+        }
+
+        build_unitigs_index(&graph_file, &memory_index, K);
+        // Runs of 16 entries, merged in multiple passes
+        assert!(kmers_count / 16 > MAX_MERGED_RUNS);
+        build_unitigs_index_with_memory(
+            &graph_file,
+            &runs_index,
+            K,
+            16 * std::mem::size_of::<IndexEntry>(),
+        );
+
+        let memory_data = std::fs::read(&memory_index).unwrap();
+        assert_eq!(
+            memory_data.len() as u64,
+            HEADER_SIZE + kmers_count as u64 * ENTRY_SIZE
+        );
+        assert!(memory_data == std::fs::read(&runs_index).unwrap());
+
+        // The runs are removed once merged
+        let runs_left = std::fs::read_dir(runs_index.parent().unwrap())
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("unitigs-index-runs.runs.kmers.idx.run")
+            })
+            .count();
+        assert_eq!(runs_left, 0);
+    }
+}
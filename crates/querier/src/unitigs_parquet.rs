@@ -0,0 +1,238 @@
+use crate::color_unitigs::{parse_color_subset, DecodedSubsets};
+use arrow_array::builder::{Float64Builder, ListBuilder, UInt32Builder, UInt64Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use colors::storage::deserializer::ColorsDeserializer;
+use colors::DefaultColorsSerializer;
+use io::sequences_reader::SequencesReader;
+use io::unitig_links::parse_header_links;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Unitigs buffered in each record batch, and in each row group of the parquet file
+const UNITIGS_BATCH_ROWS: usize = 65536;
+
+/// Obtains the standard unitigs table file path from a graph file path
+pub fn get_unitigs_parquet_file(graph_file: impl AsRef<Path>) -> PathBuf {
+    graph_file.as_ref().with_extension("unitigs.parquet")
+}
+
+/// The schema of the unitigs table, with the colors column only for colored graphs
+pub fn unitigs_table_schema(colored: bool) -> SchemaRef {
+    let mut fields = vec![
+        Field::new("id", DataType::UInt64, false),
+        Field::new("length", DataType::UInt64, false),
+        // Only in graphs built with the kmers counters
+        Field::new("coverage", DataType::Float64, true),
+        Field::new("gc_content", DataType::Float64, false),
+    ];
+    if colored {
+        fields.push(Field::new(
+            "colors",
+            DataType::List(Arc::new(Field::new("item", DataType::UInt32, true))),
+            false,
+        ));
+    }
+    Arc::new(Schema::new(fields))
+}
+
+// Coverage format: km:f:<average kmers count>
+fn parse_coverage(field: &[u8]) -> Option<f64> {
+    std::str::from_utf8(field.strip_prefix(b"km:f:")?)
+        .ok()?
+        .parse()
+        .ok()
+}
+
+fn gc_content(sequence: &[u8]) -> f64 {
+    let gc_count = sequence
+        .iter()
+        .filter(|b| matches!(b.to_ascii_uppercase(), b'G' | b'C'))
+        .count();
+    gc_count as f64 / sequence.len().max(1) as f64
+}
+
+struct UnitigsColumns {
+    ids: UInt64Builder,
+    lengths: UInt64Builder,
+    coverages: Float64Builder,
+    gc_contents: Float64Builder,
+    colors: Option<ListBuilder<UInt32Builder>>,
+    rows: usize,
+}
+
+impl UnitigsColumns {
+    fn new(colored: bool) -> Self {
+        Self {
+            ids: UInt64Builder::new(),
+            lengths: UInt64Builder::new(),
+            coverages: Float64Builder::new(),
+            gc_contents: Float64Builder::new(),
+            colors: colored.then(|| ListBuilder::new(UInt32Builder::new())),
+            rows: 0,
+        }
+    }
+
+    fn write_batch(&mut self, schema: &SchemaRef, writer: &mut ArrowWriter<File>) {
+        if self.rows == 0 {
+            return;
+        }
+
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(self.ids.finish()),
+            Arc::new(self.lengths.finish()),
+            Arc::new(self.coverages.finish()),
+            Arc::new(self.gc_contents.finish()),
+        ];
+        if let Some(colors) = &mut self.colors {
+            columns.push(Arc::new(colors.finish()));
+        }
+
+        let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+        writer
+            .write(&batch)
+            .unwrap_or_else(|err| panic!("Cannot write unitigs table batch: {}", err));
+        self.rows = 0;
+    }
+}
+
+/// Writes a row for each unitig of a graph to a parquet file, with its id, length, coverage (null if the
+/// graph was built without the kmers counters), GC content and, with colored, the sorted list of its colors,
+/// decoded from the colormap next to the graph. The graph is streamed once, writing the rows in batches,
+/// so that the table can be loaded directly in a DataFrame (e.g. with pandas or polars).
+/// Returns the number of written rows
+pub fn write_unitigs_parquet(
+    graph_input: impl AsRef<Path>,
+    output_file: impl AsRef<Path>,
+    colored: bool,
+) -> u64 {
+    let mut colors_deserializer = colored.then(|| {
+        ColorsDeserializer::<DefaultColorsSerializer>::new(
+            graph_input.as_ref().with_extension("colors.dat"),
+            true,
+        )
+    });
+
+    let schema = unitigs_table_schema(colored);
+    let file = File::create(output_file.as_ref()).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create unitigs table {}: {}",
+            output_file.as_ref().display(),
+            err
+        )
+    });
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_max_row_group_size(UNITIGS_BATCH_ROWS)
+        .build();
+    let mut writer = ArrowWriter::try_new(file, schema.clone(), Some(properties))
+        .unwrap_or_else(|err| panic!("Cannot create unitigs table writer: {}", err));
+
+    let mut columns = UnitigsColumns::new(colored);
+    let mut decoded_subsets = DecodedSubsets::new();
+    let mut unitig_colors = vec![];
+    let mut sequence_index = 0;
+    let mut rows_count = 0;
+
+    SequencesReader::new().process_file_extended(
+        graph_input.as_ref(),
+        |seq| {
+            let (unitig_id, _) = parse_header_links(seq.ident_data, sequence_index);
+            sequence_index += 1;
+
+            let fields = seq.ident_data.split(|c| *c == b' ');
+            columns.ids.append_value(unitig_id);
+            columns.lengths.append_value(seq.seq.len() as u64);
+            columns
+                .coverages
+                .append_option(fields.clone().find_map(parse_coverage));
+            columns.gc_contents.append_value(gc_content(seq.seq));
+
+            if let (Some(colors_column), Some(colors_deserializer)) =
+                (&mut columns.colors, &mut colors_deserializer)
+            {
+                unitig_colors.clear();
+                for subset in fields.filter_map(parse_color_subset) {
+                    unitig_colors
+                        .extend_from_slice(decoded_subsets.get(colors_deserializer, subset));
+                }
+                unitig_colors.sort_unstable();
+                unitig_colors.dedup();
+
+                colors_column.values().append_slice(&unitig_colors);
+                colors_column.append(true);
+            }
+
+            columns.rows += 1;
+            rows_count += 1;
+            if columns.rows == UNITIGS_BATCH_ROWS {
+                columns.write_batch(&schema, &mut writer);
+            }
+        },
+        None,
+        true,
+        false,
+    );
+
+    columns.write_batch(&schema, &mut writer);
+    writer
+        .close()
+        .unwrap_or_else(|err| panic!("Cannot finalize unitigs table: {}", err));
+
+    rows_count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_unitigs_parquet;
+    use arrow_array::{Array, Float64Array, UInt64Array};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::fs::File;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn unitigs_table_can_be_read_back() {
+        let temp_dir = TestDir::new("unitigs_table_can_be_read_back");
+        let graph_file = temp_dir.path("unitigs-parquet.fa");
+        let table_file = temp_dir.path("unitigs-parquet.unitigs.parquet");
+
+        std::fs::write(
+            &graph_file,
+            ">0 LN:i:8 KC:i:12 km:f:3.0 L:+:1:+\nAAGTTGAC\n\
+             >1 LN:i:9 KC:i:10 km:f:2.0 L:-:0:-\nTGACAGGTC\n\
+             >2 LN:i:6\nGGCCAT\n",
+        )
+        .unwrap();
+
+        assert_eq!(write_unitigs_parquet(&graph_file, &table_file, false), 3);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&table_file).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<_> = reader.map(|batch| batch.unwrap()).collect();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
+
+        let batch = &batches[0];
+        assert!(batch.column_by_name("colors").is_none());
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+
+        let lengths = column("length");
+        let lengths = lengths.as_any().downcast_ref::<UInt64Array>().unwrap();
+        assert_eq!(lengths.values().to_vec(), vec![8, 9, 6]);
+
+        let coverages = column("coverage");
+        let coverages = coverages.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(coverages.value(0), 3.0);
+        assert!(coverages.is_null(2));
+
+        let gc_contents = column("gc_content");
+        let gc_contents = gc_contents.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(gc_contents.value(0), 3.0 / 8.0);
+        assert_eq!(gc_contents.value(2), 4.0 / 6.0);
+    }
+}
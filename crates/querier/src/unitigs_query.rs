@@ -0,0 +1,119 @@
+use crate::unitigs_index::{parse_unitig_id, UnitigsIndex};
+use io::sequences_reader::SequencesReader;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Finds the ids of the distinct unitigs containing at least one kmer of the query, sorted by id
+pub fn query_matched_unitigs(index: &mut UnitigsIndex, query: &[u8]) -> Vec<u64> {
+    let k = index.kmer_length();
+    if query.len() < k {
+        return vec![];
+    }
+
+    let matched: BTreeSet<_> = query
+        .windows(k)
+        .filter(|kmer| !kmer.contains(&b'N'))
+        .filter_map(|kmer| index.lookup(kmer))
+        .map(|position| position.unitig_id)
+        .collect();
+    matched.into_iter().collect()
+}
+
+/// Loads the sequences of the given unitigs, streaming the graph once and keeping only the requested ones.
+/// The unitig ids are taken from the headers as in the kmers index
+pub fn load_unitig_sequences(
+    graph_input: impl AsRef<Path>,
+    unitig_ids: &HashSet<u64>,
+) -> HashMap<u64, Vec<u8>> {
+    let mut sequences = HashMap::with_capacity(unitig_ids.len());
+    let mut sequence_index = 0;
+
+    SequencesReader::new().process_file_extended(
+        graph_input.as_ref(),
+        |seq| {
+            let unitig_id = parse_unitig_id(seq.ident_data).unwrap_or(sequence_index);
+            sequence_index += 1;
+            if unitig_ids.contains(&unitig_id) {
+                sequences.insert(unitig_id, seq.seq.to_vec());
+            }
+        },
+        None,
+        true,
+        false,
+    );
+
+    sequences
+}
+
+/// Reports, for each query, the ids of the unitigs matched by its kmers and optionally their sequences,
+/// for the local re-alignment of the queries. The output is written in jsonl format, with one line per query.
+/// The sequences are loaded after all the queries with a single pass on the graph, keeping in memory
+/// only the matched unitigs
+pub fn query_unitigs(
+    index_file: impl AsRef<Path>,
+    graph_input: impl AsRef<Path>,
+    query_input: impl AsRef<Path>,
+    output_file_prefix: impl AsRef<Path>,
+    with_sequences: bool,
+) -> PathBuf {
+    let mut index = UnitigsIndex::open(index_file).unwrap_or_else(|err| panic!("{}", err));
+
+    let mut queries_unitigs = vec![];
+    SequencesReader::new().process_file_extended(
+        query_input,
+        |seq| {
+            queries_unitigs.push(query_matched_unitigs(&mut index, seq.seq));
+        },
+        None,
+        false,
+        false,
+    );
+
+    let sequences = if with_sequences {
+        let unitig_ids: HashSet<_> = queries_unitigs.iter().flatten().copied().collect();
+        load_unitig_sequences(graph_input, &unitig_ids)
+    } else {
+        HashMap::new()
+    };
+
+    let output_file = output_file_prefix.as_ref().with_extension("unitigs.jsonl");
+    let mut writer = BufWriter::new(File::create(&output_file).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create output file {}: {}",
+            output_file.display(),
+            err
+        )
+    }));
+
+    for (query_index, unitigs) in queries_unitigs.iter().enumerate() {
+        write!(writer, "{{\"query_index\":{}, \"unitigs\":[", query_index).unwrap();
+        for (i, unitig_id) in unitigs.iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",").unwrap();
+            }
+            write!(writer, "{}", unitig_id).unwrap();
+        }
+        write!(writer, "]").unwrap();
+
+        if with_sequences {
+            write!(writer, ", \"sequences\":[").unwrap();
+            for (i, unitig_id) in unitigs.iter().enumerate() {
+                if i > 0 {
+                    write!(writer, ",").unwrap();
+                }
+                let sequence = sequences
+                    .get(unitig_id)
+                    .map(|s| s.as_slice())
+                    .unwrap_or(&[]);
+                write!(writer, "\"{}\"", std::str::from_utf8(sequence).unwrap()).unwrap();
+            }
+            write!(writer, "]").unwrap();
+        }
+        writeln!(writer, "}}").unwrap();
+    }
+
+    writer.flush().unwrap();
+    output_file
+}
@@ -0,0 +1,52 @@
+use crate::unitigs_index::parse_unitig_id;
+use io::sequences_reader::SequencesReader;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Copies to output_file only the unitigs of the graph with the given ids, keeping their headers
+/// unchanged so that their colors still refer to the colormap of the original graph.
+/// The unitig ids are taken from the sequence headers, falling back to the sequence
+/// ordinal if an header does not start with a number.
+/// Returns the number of copied unitigs
+pub fn write_unitigs_subset(
+    graph_input: impl AsRef<Path>,
+    unitig_ids: &[u64],
+    output_file: impl AsRef<Path>,
+) -> usize {
+    let unitig_ids: HashSet<_> = unitig_ids.iter().copied().collect();
+
+    let mut writer = BufWriter::new(File::create(output_file.as_ref()).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create unitigs subset file {}: {}",
+            output_file.as_ref().display(),
+            err
+        )
+    }));
+
+    let mut sequence_index = 0;
+    let mut written_count = 0;
+
+    SequencesReader::new().process_file_extended(
+        graph_input.as_ref(),
+        |seq| {
+            let unitig_id = parse_unitig_id(seq.ident_data).unwrap_or(sequence_index);
+            sequence_index += 1;
+
+            if unitig_ids.contains(&unitig_id) {
+                writer.write_all(seq.ident_data).unwrap();
+                writer.write_all(b"\n").unwrap();
+                writer.write_all(seq.seq).unwrap();
+                writer.write_all(b"\n").unwrap();
+                written_count += 1;
+            }
+        },
+        None,
+        true,
+        false,
+    );
+
+    writer.flush().unwrap();
+    written_count
+}
@@ -1,6 +1,7 @@
 use config::{READ_FLAG_INCL_BEGIN, READ_FLAG_INCL_END};
 use std::cell::Cell;
 use std::mem::size_of;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 const FLAGS_COUNT: usize = 2;
 const FLAGS_SHIFT: usize = size_of::<usize>() * 8 - FLAGS_COUNT;
@@ -8,6 +9,24 @@ const USED_MARKER: usize = 1 << (FLAGS_SHIFT - 1);
 const COUNTER_MASK: usize = (1 << (FLAGS_SHIFT - 1)) - 1;
 
 pub const COUNTER_BITS: usize = FLAGS_SHIFT - 1;
+pub const MAX_COUNTER_VALUE: usize = COUNTER_MASK;
+
+static KMER_COUNTERS_SATURATION: AtomicUsize = AtomicUsize::new(MAX_COUNTER_VALUE);
+
+/// Sets the value at which the kmers counters saturate, instead of wrapping around.
+/// None uses all the counter bits, values higher than the maximum counter value are clamped to it
+pub fn set_kmer_counters_saturation(saturation: Option<usize>) {
+    KMER_COUNTERS_SATURATION.store(
+        saturation
+            .unwrap_or(MAX_COUNTER_VALUE)
+            .clamp(1, MAX_COUNTER_VALUE),
+        Ordering::Relaxed,
+    );
+}
+
+pub fn get_kmer_counters_saturation() -> usize {
+    KMER_COUNTERS_SATURATION.load(Ordering::Relaxed)
+}
 
 pub struct MapEntry<CHI> {
     count_flags: Cell<usize>,
@@ -24,9 +43,25 @@ impl<CHI> MapEntry<CHI> {
         }
     }
 
+    /// Increments the counter, saturating at max_count without touching the flags bits.
+    /// Returns true if the counter was already saturated
     #[inline(always)]
-    pub fn incr(&mut self) {
-        self.count_flags.set(self.count_flags.get() + 1);
+    pub fn incr(&mut self, max_count: usize) -> bool {
+        let saturated = self.get_counter() >= max_count;
+        if !saturated {
+            self.count_flags.set(self.count_flags.get() + 1);
+        }
+        saturated
+    }
+
+    /// Increments the counter by amount, saturating at max_count without touching the flags bits.
+    /// Returns the number of increments dropped by the saturation
+    #[inline(always)]
+    pub fn incr_by(&mut self, amount: usize, max_count: usize) -> usize {
+        let counter = self.get_counter();
+        let added = amount.min(max_count.saturating_sub(counter));
+        self.count_flags.set(self.count_flags.get() + added);
+        amount - added
     }
 
     #[inline(always)]
@@ -68,3 +103,50 @@ impl<CHI> MapEntry<CHI> {
             >> ((self.get_flags() == (READ_FLAG_INCL_BEGIN | READ_FLAG_INCL_END)) as u8)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{MapEntry, MAX_COUNTER_VALUE};
+    use config::{READ_FLAG_INCL_BEGIN, READ_FLAG_INCL_END};
+
+    #[test]
+    fn counter_saturates_instead_of_wrapping() {
+        let mut entry = MapEntry::new(());
+        entry.update_flags(READ_FLAG_INCL_BEGIN);
+
+        let saturated_count = (0..u16::MAX as usize + 10)
+            .filter(|_| entry.incr(u16::MAX as usize))
+            .count();
+
+        assert_eq!(entry.get_counter(), u16::MAX as usize);
+        assert_eq!(saturated_count, 10);
+        assert_eq!(entry.get_flags(), READ_FLAG_INCL_BEGIN);
+        assert!(!entry.is_used());
+
+        // Near the full counter width the increments must not carry into the used marker or the flags
+        entry.set_counter_after_check(MAX_COUNTER_VALUE - 1);
+        assert!(!entry.incr(MAX_COUNTER_VALUE));
+        assert!(entry.incr(MAX_COUNTER_VALUE));
+        assert_eq!(entry.get_counter(), MAX_COUNTER_VALUE);
+        assert_eq!(entry.get_flags(), READ_FLAG_INCL_BEGIN);
+        assert!(!entry.is_used());
+
+        entry.update_flags(READ_FLAG_INCL_END);
+        assert_eq!(entry.get_kmer_multiplicity(), MAX_COUNTER_VALUE >> 1);
+    }
+
+    #[test]
+    fn counter_increments_by_multiplicity_up_to_the_saturation() {
+        let mut entry = MapEntry::new(());
+        entry.update_flags(READ_FLAG_INCL_END);
+
+        assert_eq!(entry.incr_by(40, 100), 0);
+        assert_eq!(entry.get_counter(), 40);
+        assert_eq!(entry.incr_by(70, 100), 10);
+        assert_eq!(entry.get_counter(), 100);
+        assert_eq!(entry.incr_by(5, 100), 5);
+        assert_eq!(entry.get_counter(), 100);
+        assert_eq!(entry.get_flags(), READ_FLAG_INCL_END);
+        assert!(!entry.is_used());
+    }
+}
@@ -6,21 +6,16 @@ use io::{
 use serde::{Deserialize, Serialize};
 use std::io::{Read, Write};
 
-#[derive(Copy, Clone, Serialize, Deserialize, Debug)]
+/// The counts of each kmer of a partial unitig, in order
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct UnitigsCounters {
-    pub first: u64,
-    pub sum: u64,
-    pub last: u64,
+    pub counts: Vec<u64>,
 }
 
 impl UnitigsCounters {
     #[inline]
     pub fn new() -> Self {
-        Self {
-            first: 0,
-            sum: 0,
-            last: 0,
-        }
+        Self { counts: vec![] }
     }
 }
 
@@ -28,20 +23,23 @@ impl HasEmptyExtraBuffer for UnitigsCounters {}
 
 impl SequenceExtraData for UnitigsCounters {
     fn decode_extended(_: &mut Self::TempBuffer, reader: &mut impl Read) -> Option<Self> {
-        let first = decode_varint(|| reader.read_u8().ok())?;
-        let sum = decode_varint(|| reader.read_u8().ok())?;
-        let last = decode_varint(|| reader.read_u8().ok())?;
-        Some(Self { first, sum, last })
+        let counts_len = decode_varint(|| reader.read_u8().ok())?;
+        let mut counts = Vec::with_capacity(counts_len as usize);
+        for _ in 0..counts_len {
+            counts.push(decode_varint(|| reader.read_u8().ok())?);
+        }
+        Some(Self { counts })
     }
 
     fn encode_extended(&self, _: &Self::TempBuffer, writer: &mut impl Write) {
-        encode_varint(|b| writer.write(b).ok(), self.first).unwrap();
-        encode_varint(|b| writer.write(b).ok(), self.sum).unwrap();
-        encode_varint(|b| writer.write(b).ok(), self.last).unwrap();
+        encode_varint(|b| writer.write(b).ok(), self.counts.len() as u64).unwrap();
+        for count in self.counts.iter() {
+            encode_varint(|b| writer.write(b).ok(), *count).unwrap();
+        }
     }
 
     #[inline(always)]
     fn max_size(&self) -> usize {
-        3 * VARINT_MAX_SIZE
+        (self.counts.len() + 1) * VARINT_MAX_SIZE
     }
 }
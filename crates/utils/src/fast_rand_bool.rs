@@ -1,10 +1,11 @@
-use rand::rngs::ThreadRng;
-use rand::{thread_rng, RngCore};
+use crate::random_seed::{seeded_rng, RandomComponent};
+use rand::rngs::StdRng;
+use rand::RngCore;
 
 // Increasing PROB_ITERS decreases the probability that a true value happens,
 // by combining with and multiple random values
 pub struct FastRandBool<const PROB_ITERS: usize> {
-    random: ThreadRng,
+    random: StdRng,
     randidx: usize,
     randval: u64,
 }
@@ -19,8 +20,8 @@ impl<const PROB_ITERS: usize> FastRandBool<PROB_ITERS> {
         val
     }
 
-    pub fn new() -> Self {
-        let random = thread_rng();
+    pub fn new(seed: Option<u64>, component: RandomComponent, index: u64) -> Self {
+        let random = seeded_rng(seed, component, index);
         Self {
             random,
             randidx: 0,
@@ -0,0 +1,90 @@
+/// HyperLogLog cardinality estimator over 64 bit hashes
+#[derive(Clone)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+    precision: u32,
+    seed: u64,
+}
+
+impl HyperLogLog {
+    pub const DEFAULT_PRECISION: u32 = 14;
+
+    /// Sketches with different seeds hash the values differently and cannot be merged
+    pub fn new(precision: u32, seed: u64) -> Self {
+        assert!((4..=18).contains(&precision));
+        Self {
+            registers: vec![0; 1 << precision],
+            precision,
+            seed,
+        }
+    }
+
+    // Finalizer of splitmix64, as the input hashes are not guaranteed to be uniform in all the bits
+    #[inline(always)]
+    fn mix(mut hash: u64) -> u64 {
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+        hash ^ (hash >> 31)
+    }
+
+    #[inline(always)]
+    pub fn add_hash(&mut self, hash: u64) {
+        let hash = Self::mix(hash ^ self.seed);
+        let index = (hash >> (64 - self.precision)) as usize;
+        let rank = ((hash << self.precision) | (1 << (self.precision - 1))).leading_zeros() + 1;
+        let register = &mut self.registers[index];
+        *register = (*register).max(rank as u8);
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        assert_eq!(self.precision, other.precision);
+        assert_eq!(self.seed, other.seed);
+        for (register, other) in self.registers.iter_mut().zip(other.registers.iter()) {
+            *register = (*register).max(*other);
+        }
+    }
+
+    pub fn estimate(&self) -> u64 {
+        let registers_count = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / registers_count);
+
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|r| 2.0f64.powi(-(*r as i32)))
+            .sum();
+        let estimate = alpha * registers_count * registers_count / sum;
+
+        let zero_registers = self.registers.iter().filter(|r| **r == 0).count();
+
+        // Small range correction with linear counting
+        if estimate <= 2.5 * registers_count && zero_registers > 0 {
+            (registers_count * (registers_count / zero_registers as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HyperLogLog;
+    use crate::random_seed::{derive_seed, RandomComponent};
+
+    fn sketch(seed: u64) -> Vec<u8> {
+        let mut hll = HyperLogLog::new(
+            10,
+            derive_seed(Some(seed), RandomComponent::CardinalitySketch, 0),
+        );
+        for value in 0..100000u64 {
+            hll.add_hash(value.wrapping_mul(0x2545F4914F6CDD1D));
+        }
+        hll.registers
+    }
+
+    #[test]
+    fn hyperloglog_seed_reproducibility() {
+        assert_eq!(sketch(42), sketch(42));
+        assert_ne!(sketch(42), sketch(43));
+    }
+}
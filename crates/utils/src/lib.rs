@@ -1,8 +1,12 @@
 #[macro_use]
 pub mod debug_functions;
 pub mod fast_rand_bool;
+pub mod hyperloglog;
 pub mod owned_drop;
+pub mod phase_log;
+pub mod random_seed;
 pub mod resource_counter;
+pub mod test_dir;
 pub mod vec_slice;
 
 use std::cmp::max;
@@ -0,0 +1,619 @@
+use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
+use parking_lot::Mutex;
+use std::cell::Cell;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::time::{Duration, Instant};
+
+/// Format of the phases and warnings logs
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable logs on stdout
+    Human = 0,
+    /// One json object per line on stderr, for each phase event and warning
+    Json = 1,
+}
+
+static LOG_FORMAT: AtomicU8 = AtomicU8::new(LogFormat::Human as u8);
+
+pub fn set_log_format(format: LogFormat) {
+    LOG_FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+pub fn get_log_format() -> LogFormat {
+    match LOG_FORMAT.load(Ordering::Relaxed) {
+        0 => LogFormat::Human,
+        _ => LogFormat::Json,
+    }
+}
+
+struct CurrentPhase {
+    name: String,
+    start: Instant,
+    cpu_time_start: Option<Duration>,
+}
+
+struct PhasesLogState {
+    run_start: Option<Instant>,
+    current_phase: Option<CurrentPhase>,
+}
+
+static PHASES_LOG_STATE: Mutex<PhasesLogState> = Mutex::new(PhasesLogState {
+    run_start: None,
+    current_phase: None,
+});
+
+/// The wall and the CPU (user + system) time of a completed phase
+#[derive(Clone, Debug)]
+pub struct PhaseUtilization {
+    pub phase: String,
+    pub wall_time: Duration,
+    pub cpu_time: Duration,
+}
+
+impl PhaseUtilization {
+    /// The effective parallelism of the phase, as the ratio between its CPU and wall time.
+    /// A value much lower than the threads count points to an I/O or serialization bottleneck
+    pub fn utilization(&self) -> f64 {
+        self.cpu_time.as_secs_f64() / self.wall_time.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+static PHASES_UTILIZATION: Mutex<Vec<PhaseUtilization>> = Mutex::new(Vec::new());
+
+static VERBOSE_PHASES_LOG: AtomicBool = AtomicBool::new(false);
+
+/// Also print the CPU utilization of each phase at its end with the human readable format,
+/// off by default. The json phase events always have it
+pub fn set_verbose_phases_log(enabled: bool) {
+    VERBOSE_PHASES_LOG.store(enabled, Ordering::Relaxed);
+}
+
+/// The CPU time (user + system) used until now by all the threads of the process,
+/// available only on Unix
+#[cfg(unix)]
+fn process_cpu_time() -> Option<Duration> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let usage = unsafe { usage.assume_init() };
+    let time = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    Some(time(usage.ru_utime) + time(usage.ru_stime))
+}
+
+#[cfg(not(unix))]
+fn process_cpu_time() -> Option<Duration> {
+    None
+}
+
+/// The CPU utilization of the phases completed since the start of the current run.
+/// Empty if the process CPU time is not available on this platform
+pub fn get_phases_utilization() -> Vec<PhaseUtilization> {
+    PHASES_UTILIZATION.lock().clone()
+}
+
+/// A step within a phase timed by the sub-phase timers
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SubPhase {
+    /// Rolling the hashes over the sequences to find their minimizers
+    Hashing = 0,
+    /// Dispatching the sequences to the thread buffers of their buckets
+    BucketWriting = 1,
+    /// Flushing the thread buffers to the buckets files
+    Flushing = 2,
+}
+
+impl SubPhase {
+    pub const ALL: [SubPhase; 3] = [
+        SubPhase::Hashing,
+        SubPhase::BucketWriting,
+        SubPhase::Flushing,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SubPhase::Hashing => "hashing",
+            SubPhase::BucketWriting => "bucket_writing",
+            SubPhase::Flushing => "flushing",
+        }
+    }
+
+    fn json_field(&self) -> &'static str {
+        match self {
+            SubPhase::Hashing => "hashing_ms",
+            SubPhase::BucketWriting => "bucket_writing_ms",
+            SubPhase::Flushing => "flushing_ms",
+        }
+    }
+}
+
+// The time spent in each sub-phase by all the threads since the start of the current phase
+static SUBPHASES_NANOS: [AtomicU64; 3] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+
+thread_local! {
+    // The innermost running sub-phase of the thread, with the time since it is accounted to it
+    static CURRENT_SUBPHASE: Cell<Option<(SubPhase, Instant)>> = const { Cell::new(None) };
+    // The time accounted to each sub-phase by the thread, published when its outermost timer ends
+    static THREAD_SUBPHASES_NANOS: [Cell<u64>; 3] = const { [Cell::new(0), Cell::new(0), Cell::new(0)] };
+}
+
+fn account_subphase_time(sub_phase: SubPhase, since: Instant, now: Instant) {
+    THREAD_SUBPHASES_NANOS.with(|nanos| {
+        let nanos = &nanos[sub_phase as usize];
+        nanos.set(nanos.get() + now.duration_since(since).as_nanos() as u64);
+    });
+}
+
+/// Accounts the time until it is dropped to a sub-phase, if the sub-phase timing is enabled for the run,
+/// as it reads the clock in the inner loops of the pipeline.
+/// The timers can be nested, the time of an inner timer is accounted only to its own sub-phase
+/// and not to the enclosing ones, so the sub-phases times of a thread never overlap
+pub struct SubPhaseTimer {
+    enabled: bool,
+    outer: Option<SubPhase>,
+}
+
+impl SubPhaseTimer {
+    #[inline(always)]
+    pub fn start(sub_phase: SubPhase, enabled: bool) -> Self {
+        if !enabled {
+            return Self {
+                enabled: false,
+                outer: None,
+            };
+        }
+
+        let now = Instant::now();
+        let outer = CURRENT_SUBPHASE.with(|current| {
+            let outer = current.replace(Some((sub_phase, now)));
+            outer.map(|(outer, since)| {
+                account_subphase_time(outer, since, now);
+                outer
+            })
+        });
+        Self {
+            enabled: true,
+            outer,
+        }
+    }
+}
+
+impl Drop for SubPhaseTimer {
+    fn drop(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        let now = Instant::now();
+        CURRENT_SUBPHASE.with(|current| {
+            if let Some((sub_phase, since)) = current.replace(self.outer.map(|outer| (outer, now)))
+            {
+                account_subphase_time(sub_phase, since, now);
+            }
+        });
+
+        if self.outer.is_none() {
+            THREAD_SUBPHASES_NANOS.with(|nanos| {
+                for (total, nanos) in SUBPHASES_NANOS.iter().zip(nanos.iter()) {
+                    total.fetch_add(nanos.replace(0), Ordering::Relaxed);
+                }
+            });
+        }
+    }
+}
+
+/// The time spent in each sub-phase of a completed phase, summed over all the threads
+#[derive(Clone, Debug)]
+pub struct PhaseSubTimes {
+    pub phase: String,
+    pub wall_time: Duration,
+    pub sub_phases: Vec<(SubPhase, Duration)>,
+}
+
+impl PhaseSubTimes {
+    /// The total time of the timed sub-phases
+    pub fn total(&self) -> Duration {
+        self.sub_phases.iter().map(|(_, time)| *time).sum()
+    }
+}
+
+static PHASES_SUB_TIMES: Mutex<Vec<PhaseSubTimes>> = Mutex::new(Vec::new());
+
+fn take_subphases_times() -> Vec<(SubPhase, Duration)> {
+    SubPhase::ALL
+        .iter()
+        .map(|sub_phase| {
+            let nanos = SUBPHASES_NANOS[*sub_phase as usize].swap(0, Ordering::Relaxed);
+            (*sub_phase, Duration::from_nanos(nanos))
+        })
+        .collect()
+}
+
+/// The sub-phases times of the phases completed since the start of the current run
+/// that have at least one timed sub-phase. Empty if the sub-phase timing is not enabled
+pub fn get_phases_sub_times() -> Vec<PhaseSubTimes> {
+    PHASES_SUB_TIMES.lock().clone()
+}
+
+// Destination of the json events, stderr if not set
+static JSON_LOG_OUTPUT: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+fn json_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+fn emit_json_event(state: &mut PhasesLogState, event: &str, fields: &[(&str, String)]) {
+    let now = Instant::now();
+    let run_start = *state.run_start.get_or_insert(now);
+
+    let mut line = format!("{{\"event\":{}", json_string(event));
+    for (name, value) in fields {
+        line.push_str(&format!(",{}:{}", json_string(name), value));
+    }
+    line.push_str(&format!(
+        ",\"elapsed_ms\":{}}}\n",
+        now.duration_since(run_start).as_millis()
+    ));
+
+    match JSON_LOG_OUTPUT.lock().as_mut() {
+        Some(output) => output.write_all(line.as_bytes()),
+        None => std::io::stderr().write_all(line.as_bytes()),
+    }
+    .unwrap();
+}
+
+fn end_current_phase(state: &mut PhasesLogState) {
+    if let Some(phase) = state.current_phase.take() {
+        let wall_time = phase.start.elapsed();
+        let utilization =
+            phase
+                .cpu_time_start
+                .zip(process_cpu_time())
+                .map(|(cpu_start, cpu_end)| PhaseUtilization {
+                    phase: phase.name.clone(),
+                    wall_time,
+                    cpu_time: cpu_end.saturating_sub(cpu_start),
+                });
+
+        let sub_times = Some(PhaseSubTimes {
+            phase: phase.name.clone(),
+            wall_time,
+            sub_phases: take_subphases_times(),
+        })
+        .filter(|sub_times| !sub_times.total().is_zero());
+
+        match get_log_format() {
+            LogFormat::Human => {
+                if let Some(utilization) = utilization
+                    .as_ref()
+                    .filter(|_| VERBOSE_PHASES_LOG.load(Ordering::Relaxed))
+                {
+                    println!(
+                        "Phase {} CPU utilization: {:.2}x ({:.2}s CPU / {:.2}s wall)",
+                        utilization.phase,
+                        utilization.utilization(),
+                        utilization.cpu_time.as_secs_f64(),
+                        utilization.wall_time.as_secs_f64()
+                    );
+                }
+                if let Some(sub_times) = &sub_times {
+                    println!(
+                        "Phase {} sub-phases time (summed over the threads): {}",
+                        sub_times.phase,
+                        sub_times
+                            .sub_phases
+                            .iter()
+                            .map(|(sub_phase, time)| format!(
+                                "{} {:.2}s",
+                                sub_phase.name(),
+                                time.as_secs_f64()
+                            ))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
+            }
+            LogFormat::Json => {
+                let mut fields = vec![
+                    ("phase", json_string(&phase.name)),
+                    ("phase_elapsed_ms", wall_time.as_millis().to_string()),
+                ];
+                if let Some(utilization) = &utilization {
+                    fields.push(("cpu_ms", utilization.cpu_time.as_millis().to_string()));
+                    fields.push((
+                        "cpu_utilization",
+                        format!("{:.2}", utilization.utilization()),
+                    ));
+                }
+                if let Some(sub_times) = &sub_times {
+                    for (sub_phase, time) in &sub_times.sub_phases {
+                        fields.push((sub_phase.json_field(), time.as_millis().to_string()));
+                    }
+                }
+                emit_json_event(state, "phase_end", &fields);
+            }
+        }
+
+        if let Some(utilization) = utilization {
+            PHASES_UTILIZATION.lock().push(utilization);
+        }
+        if let Some(sub_times) = sub_times {
+            PHASES_SUB_TIMES.lock().push(sub_times);
+        }
+    }
+}
+
+/// Starts the phases timing of a new run, replaces PHASES_TIMES_MONITOR.init()
+pub fn init_phases() {
+    PHASES_TIMES_MONITOR.write().init();
+
+    let mut state = PHASES_LOG_STATE.lock();
+    state.run_start = Some(Instant::now());
+    state.current_phase = None;
+    PHASES_UTILIZATION.lock().clear();
+    PHASES_SUB_TIMES.lock().clear();
+    take_subphases_times();
+    if get_log_format() == LogFormat::Json {
+        emit_json_event(&mut state, "run_start", &[]);
+    }
+}
+
+/// Starts a new phase, ending the current one, replaces PHASES_TIMES_MONITOR.start_phase()
+pub fn start_phase(name: String) {
+    let mut state = PHASES_LOG_STATE.lock();
+    end_current_phase(&mut state);
+    if get_log_format() == LogFormat::Json {
+        emit_json_event(&mut state, "phase_start", &[("phase", json_string(&name))]);
+    }
+    state.current_phase = Some(CurrentPhase {
+        name: name.clone(),
+        start: Instant::now(),
+        cpu_time_start: process_cpu_time(),
+    });
+    drop(state);
+
+    PHASES_TIMES_MONITOR.write().start_phase(name);
+}
+
+/// Ends the current phase and prints the final stats of the run,
+/// replaces PHASES_TIMES_MONITOR.print_stats()
+pub fn print_phases_stats(message: String) {
+    let mut state = PHASES_LOG_STATE.lock();
+    end_current_phase(&mut state);
+    if get_log_format() == LogFormat::Json {
+        emit_json_event(&mut state, "run_end", &[("message", json_string(&message))]);
+    }
+    drop(state);
+
+    PHASES_TIMES_MONITOR.write().print_stats(message);
+}
+
+/// Reports an informational message, printing it as is with the human readable format
+pub fn log_info(message: String) {
+    match get_log_format() {
+        LogFormat::Human => println!("{}", message),
+        LogFormat::Json => emit_json_event(
+            &mut PHASES_LOG_STATE.lock(),
+            "info",
+            &[("message", json_string(&message))],
+        ),
+    }
+}
+
+/// Reports a warning, printing it as is with the human readable format
+pub fn log_warning(message: String) {
+    match get_log_format() {
+        LogFormat::Human => println!("{}", message),
+        LogFormat::Json => emit_json_event(
+            &mut PHASES_LOG_STATE.lock(),
+            "warning",
+            &[("message", json_string(&message))],
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_phases_sub_times, get_phases_utilization, init_phases, json_string, log_warning,
+        print_phases_stats, process_cpu_time, set_log_format, start_phase, LogFormat, SubPhase,
+        SubPhaseTimer, JSON_LOG_OUTPUT,
+    };
+    use parking_lot::Mutex;
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    // The phases state is global, so the tests using it cannot run in parallel
+    static PHASES_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    #[derive(Clone)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_strings_escape_the_control_characters() {
+        let message = "tab\tnew line\nbell\u{7}escape\u{1b}unit separator\u{1f}\"quoted\"\\";
+        let escaped = json_string(message);
+
+        assert!(escaped.chars().all(|c| c as u32 >= 0x20));
+        assert_eq!(
+            serde_json::from_str::<String>(&escaped).unwrap(),
+            message.to_string()
+        );
+    }
+
+    #[test]
+    fn json_phase_events() {
+        let _lock = PHASES_TEST_LOCK.lock();
+        let buffer = SharedBuffer(Arc::new(Mutex::new(vec![])));
+        *JSON_LOG_OUTPUT.lock() = Some(Box::new(buffer.clone()));
+        set_log_format(LogFormat::Json);
+
+        init_phases();
+        start_phase("phase: first".to_string());
+        log_warning("WARNING: \"quoted\" message".to_string());
+        start_phase("phase: second".to_string());
+        print_phases_stats("Completed.".to_string());
+
+        set_log_format(LogFormat::Human);
+        *JSON_LOG_OUTPUT.lock() = None;
+
+        let output = String::from_utf8(buffer.0.lock().clone()).unwrap();
+        let events: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        let summary: Vec<_> = events
+            .iter()
+            .map(|e| {
+                (
+                    e["event"].as_str().unwrap(),
+                    e["phase"].as_str().or(e["message"].as_str()).unwrap_or(""),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                ("run_start", ""),
+                ("phase_start", "phase: first"),
+                ("warning", "WARNING: \"quoted\" message"),
+                ("phase_end", "phase: first"),
+                ("phase_start", "phase: second"),
+                ("phase_end", "phase: second"),
+                ("run_end", "Completed."),
+            ]
+        );
+
+        let elapsed: Vec<_> = events
+            .iter()
+            .map(|e| e["elapsed_ms"].as_u64().unwrap())
+            .collect();
+        assert!(elapsed.windows(2).all(|w| w[0] <= w[1]));
+        assert!(events
+            .iter()
+            .filter(|e| e["event"] == "phase_end")
+            .all(|e| e["phase_elapsed_ms"].is_u64()));
+    }
+
+    // Set in the child process running only the CPU utilization test
+    const CPU_UTILIZATION_TEST_ENV: &str = "GGCAT_PHASE_LOG_CPU_UTILIZATION_TEST";
+
+    #[test]
+    fn cpu_bound_phase_has_higher_utilization() {
+        let _lock = PHASES_TEST_LOCK.lock();
+        if process_cpu_time().is_none() {
+            return;
+        }
+
+        // The CPU time is process wide, so the phases are timed in a child process running only
+        // this test, without the CPU used by the other tests running in parallel
+        if std::env::var_os(CPU_UTILIZATION_TEST_ENV).is_none() {
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args([
+                    "phase_log::tests::cpu_bound_phase_has_higher_utilization",
+                    "--exact",
+                    "--test-threads=1",
+                ])
+                .env(CPU_UTILIZATION_TEST_ENV, "1")
+                .status()
+                .unwrap();
+            assert!(status.success());
+            return;
+        }
+
+        const PHASE_DURATION: Duration = Duration::from_millis(300);
+
+        init_phases();
+        start_phase("phase: cpu bound".to_string());
+        std::thread::scope(|scope| {
+            for _ in 0..2 {
+                scope.spawn(|| {
+                    let start = Instant::now();
+                    let mut value = 0u64;
+                    while start.elapsed() < PHASE_DURATION {
+                        value = std::hint::black_box(value.wrapping_mul(31).wrapping_add(7));
+                    }
+                });
+            }
+        });
+        // Waiting for I/O does not use the CPU, as sleeping
+        start_phase("phase: io bound".to_string());
+        std::thread::sleep(PHASE_DURATION);
+        print_phases_stats("Completed.".to_string());
+
+        let utilization = get_phases_utilization();
+        assert_eq!(
+            utilization
+                .iter()
+                .map(|u| u.phase.as_str())
+                .collect::<Vec<_>>(),
+            vec!["phase: cpu bound", "phase: io bound"]
+        );
+        assert!(utilization[0].utilization() > 0.8, "{:?}", utilization[0]);
+        assert!(
+            utilization[1].utilization() < utilization[0].utilization() / 2.0,
+            "{:?}",
+            utilization
+        );
+    }
+
+    #[test]
+    fn sub_phases_times_sum_to_the_phase_time() {
+        let _lock = PHASES_TEST_LOCK.lock();
+        const STEP_DURATION: Duration = Duration::from_millis(50);
+
+        init_phases();
+        start_phase("phase: sub-phases".to_string());
+        for _ in 0..4 {
+            let _hashing = SubPhaseTimer::start(SubPhase::Hashing, true);
+            std::thread::sleep(STEP_DURATION);
+            {
+                let _bucket_writing = SubPhaseTimer::start(SubPhase::BucketWriting, true);
+                std::thread::sleep(STEP_DURATION);
+            }
+        }
+        {
+            let _flushing = SubPhaseTimer::start(SubPhase::Flushing, true);
+            std::thread::sleep(STEP_DURATION);
+        }
+        print_phases_stats("Completed.".to_string());
+
+        let sub_times = get_phases_sub_times();
+        assert_eq!(sub_times.len(), 1);
+        let sub_times = &sub_times[0];
+        assert_eq!(sub_times.phase, "phase: sub-phases");
+
+        // The nested timers are not accounted to the enclosing one
+        let times: Vec<_> = sub_times.sub_phases.iter().map(|(_, time)| *time).collect();
+        assert!(
+            times[0] >= STEP_DURATION * 4 && times[0] < STEP_DURATION * 6,
+            "{:?}",
+            times
+        );
+        assert!(times[1] >= STEP_DURATION * 4, "{:?}", times);
+        assert!(times[2] >= STEP_DURATION, "{:?}", times);
+
+        assert!(sub_times.total() <= sub_times.wall_time, "{:?}", sub_times);
+        assert!(
+            sub_times.total().as_secs_f64() >= sub_times.wall_time.as_secs_f64() * 0.95,
+            "{:?}",
+            sub_times
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use rand::rngs::StdRng;
+use rand::{thread_rng, RngCore, SeedableRng};
+
+/// The randomized components, each one gets an independent stream of seeds
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RandomComponent {
+    HashesSorting = 1,
+    LinksCompaction = 2,
+    ColorsHashing = 3,
+    MinimizerOrdering = 4,
+    CardinalitySketch = 5,
+}
+
+#[inline(always)]
+fn splitmix64(mut value: u64) -> u64 {
+    value = value.wrapping_add(0x9e3779b97f4a7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+/// Derives the seed for the given component and sub-stream index (for example the bucket index) from the
+/// seed of the run, so that the values do not depend on the order the work is scheduled on the threads.
+/// With no seed (the default) each run uses different random values
+pub fn derive_seed(seed: Option<u64>, component: RandomComponent, index: u64) -> u64 {
+    match seed {
+        Some(seed) => splitmix64(seed ^ splitmix64(((component as u64) << 48) ^ index)),
+        None => thread_rng().next_u64(),
+    }
+}
+
+pub fn seeded_rng(seed: Option<u64>, component: RandomComponent, index: u64) -> StdRng {
+    StdRng::seed_from_u64(derive_seed(seed, component, index))
+}
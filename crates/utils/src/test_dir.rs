@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_TEST_DIR_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The directory of the files of a single test, unique to the test, the process and the call,
+/// so that the tests running in parallel or in other processes never share their files.
+/// It is removed when the test succeeds and kept for inspection when it fails
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    pub fn new(test_name: &str) -> Self {
+        let dir = std::env::temp_dir().join("ggcat-tests").join(format!(
+            "{}-{}-{}",
+            test_name,
+            std::process::id(),
+            NEXT_TEST_DIR_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+}